@@ -153,6 +153,8 @@ export const cancelBulkImport = (clientId: string | number) =>
   api.post(`/jbovlaste/bulk-import/cancel/${clientId}`)
 export const deleteBulkDefinitions = (clientId: string | number) =>
   api.post(`/jbovlaste/bulk-import/delete/${clientId}`)
+export const revertBulkImport = (clientId: string | number) =>
+  api.post(`/jbovlaste/bulk-import/revert/${clientId}`)
 
 export const getThread = (params?: Record<string, unknown>) => api.get('/mail/thread', { params })
 
@@ -0,0 +1,36 @@
+import { computed, toValue } from 'vue'
+import type { MaybeRefOrGetter } from 'vue'
+import { useHead } from '@vueuse/head'
+import { getApiBaseUrl } from '@/api'
+
+/**
+ * Advertises the Atom and RSS feeds of a changes listing to feed readers.
+ * `feedPath` is the API path without extension, e.g. `/jbovlaste/changes`; nothing is added
+ * while it is empty.
+ */
+export function useFeedLinks(
+  feedPath: MaybeRefOrGetter<string | null | undefined>,
+  title: MaybeRefOrGetter<string>
+) {
+  const links = computed(() => {
+    const path = toValue(feedPath)
+    if (!path) return []
+    const href = `${getApiBaseUrl()}${path}`
+    return [
+      {
+        rel: 'alternate',
+        type: 'application/atom+xml',
+        title: toValue(title),
+        href: `${href}.atom`,
+      },
+      {
+        rel: 'alternate',
+        type: 'application/rss+xml',
+        title: toValue(title),
+        href: `${href}.rss`,
+      },
+    ]
+  })
+
+  useHead({ link: links })
+}
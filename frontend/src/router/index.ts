@@ -59,6 +59,7 @@ const baseRoutes: Array<RouteRecordRaw> = [
     component: () => import('../pages/ThreadView.vue'),
     props: (route) => ({
       subject: decodeURIComponent(route.params.subject as string),
+      threadId: Number(route.query.thread_id) || undefined,
       searchTerm: route.query.highlight,
     }),
   },
@@ -0,0 +1,26 @@
+-- Personal FSRS weights fitted from flashcard_review_history.
+-- Global per-user weights live next to optimal_retention in user_settings;
+-- per-collection weights (for learners with enough reviews in one collection)
+-- go into user_collection_fsrs_parameters and take precedence when present.
+
+ALTER TABLE user_settings
+    ADD COLUMN IF NOT EXISTS fsrs_parameters REAL[],
+    ADD COLUMN IF NOT EXISTS fsrs_log_loss_before DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS fsrs_log_loss_after DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS fsrs_review_count INTEGER,
+    ADD COLUMN IF NOT EXISTS fsrs_optimized_at TIMESTAMP WITH TIME ZONE;
+
+CREATE TABLE IF NOT EXISTS user_collection_fsrs_parameters (
+    user_id INTEGER NOT NULL REFERENCES users(userid) ON DELETE CASCADE,
+    collection_id INTEGER NOT NULL REFERENCES collections(collection_id) ON DELETE CASCADE,
+    parameters REAL[],
+    log_loss_before DOUBLE PRECISION NOT NULL,
+    log_loss_after DOUBLE PRECISION NOT NULL,
+    review_count INTEGER NOT NULL,
+    optimized_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, collection_id)
+);
+
+-- The optimizer replays each user's history in order.
+CREATE INDEX IF NOT EXISTS idx_flashcard_review_history_user_time
+ON flashcard_review_history (user_id, review_time);
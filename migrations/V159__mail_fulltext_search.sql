@@ -0,0 +1,43 @@
+-- Full-text search over the mail archive.
+-- The decoded text of every text/* MIME part (HTML with tags stripped) is indexed together
+-- with the subject, stemmed with the english configuration. Subject terms weigh more (A)
+-- than body terms (B) for ts_rank_cd.
+
+CREATE OR REPLACE FUNCTION mail_parts_text(parts JSONB)
+RETURNS TEXT
+LANGUAGE SQL
+IMMUTABLE
+PARALLEL SAFE
+AS $$
+    SELECT COALESCE(
+        string_agg(
+            CASE
+                WHEN part->>'mime_type' = 'text/html'
+                    THEN regexp_replace(part->>'content', '<[^>]*>', ' ', 'g')
+                ELSE part->>'content'
+            END,
+            E'\n'
+        ),
+        ''
+    )
+    FROM jsonb_array_elements(
+        CASE WHEN jsonb_typeof(parts) = 'array' THEN parts ELSE '[]'::jsonb END
+    ) AS part
+    WHERE part->>'mime_type' LIKE 'text/%'
+$$;
+
+-- tsvector input is capped at 1MB; a few archived messages carry huge inline logs.
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS search_vector TSVECTOR
+    GENERATED ALWAYS AS (
+        setweight(to_tsvector('english'::regconfig, COALESCE(subject, '')), 'A')
+        || setweight(
+            to_tsvector('english'::regconfig, LEFT(mail_parts_text(parts_json), 500000)),
+            'B'
+        )
+    ) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_messages_search_vector ON messages USING GIN (search_vector);
+
+-- before:/after: filters and date ordering.
+CREATE INDEX IF NOT EXISTS idx_messages_sent_at ON messages (sent_at);
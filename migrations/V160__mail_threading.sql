@@ -0,0 +1,18 @@
+-- Reply trees for the mail archive (JWZ threading over Message-ID / In-Reply-To / References).
+-- in_reply_to and reference_ids hold message ids without angle brackets. parent_id is the
+-- nearest archived ancestor; thread_id is the root message of the tree. Both are recomputed by
+-- the importer after new mail arrives; cleaned_subject grouping remains the fallback for rows
+-- that have not been threaded yet.
+
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS in_reply_to TEXT,
+    ADD COLUMN IF NOT EXISTS reference_ids TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN IF NOT EXISTS thread_headers_parsed BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS parent_id INTEGER REFERENCES messages(id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS thread_id INTEGER REFERENCES messages(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_messages_parent_id ON messages (parent_id);
+CREATE INDEX IF NOT EXISTS idx_messages_thread_id ON messages (thread_id);
+-- Backfill of reply headers for messages imported before threading existed.
+CREATE INDEX IF NOT EXISTS idx_messages_thread_headers_pending
+ON messages (id) WHERE NOT thread_headers_parsed;
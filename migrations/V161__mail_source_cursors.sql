@@ -0,0 +1,10 @@
+-- Progress of each mail ingestion source (Maildir, mbox files, IMAP mailboxes).
+-- `cursor` is opaque to the database; each source defines its own format.
+CREATE TABLE IF NOT EXISTS mail_source_cursors (
+    source TEXT PRIMARY KEY,
+    cursor TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Sources other than the Maildir skip messages whose Message-ID is already archived.
+CREATE INDEX IF NOT EXISTS idx_messages_message_id ON messages (message_id);
@@ -0,0 +1,39 @@
+-- Incremental nightly dictionary exports.
+--
+-- Each cached export records a fingerprint of the data it was built from; the nightly run only
+-- rebuilds exports whose language changed since. `checked_at` is when the content was last
+-- confirmed current (the cache TTL runs from it), `created_at` stays the build time.
+ALTER TABLE cached_dictionary_exports
+ADD COLUMN IF NOT EXISTS fingerprint TEXT;
+
+ALTER TABLE cached_dictionary_exports
+ADD COLUMN IF NOT EXISTS checked_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+UPDATE cached_dictionary_exports SET checked_at = created_at;
+
+CREATE INDEX IF NOT EXISTS idx_cached_exports_checked_at
+ON cached_dictionary_exports (checked_at);
+
+-- Exports waiting for (or in) the background export worker. A job is removed once its
+-- export is cached; failed jobs stay with their error until they are queued again.
+CREATE TABLE IF NOT EXISTS dictionary_export_jobs (
+    language_tag TEXT NOT NULL,
+    source_language_tag TEXT NOT NULL,
+    format TEXT NOT NULL,
+    positive_scores_only BOOLEAN NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'running', 'failed')),
+    queued_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    error TEXT,
+    PRIMARY KEY (language_tag, source_language_tag, format, positive_scores_only)
+);
+
+CREATE INDEX IF NOT EXISTS idx_dictionary_export_jobs_queue
+ON dictionary_export_jobs (status, queued_at);
+
+-- Fingerprint lookups: latest definition version and vote totals per language.
+CREATE INDEX IF NOT EXISTS idx_definition_versions_langid_version
+ON definition_versions (langid, version_id);
+
+CREATE INDEX IF NOT EXISTS idx_definitionvotes_langid
+ON definitionvotes (langid);
@@ -0,0 +1,34 @@
+-- What each bulk import changed, so that it can be reverted.
+--
+-- `created` rows point at definitions the import added; `updated` rows at definitions of the
+-- importing user that the import rewrote, with the version they had before
+-- (`previous_version_id`). `import_version_id` is the version the import wrote: a definition
+-- with a later version has been edited since and is not reverted.
+CREATE TABLE IF NOT EXISTS bulk_import_changes (
+    id SERIAL PRIMARY KEY,
+    client_id TEXT NOT NULL,
+    definition_id INTEGER NOT NULL,
+    action TEXT NOT NULL CHECK (action IN ('created', 'updated')),
+    user_id INTEGER NOT NULL REFERENCES users(userid),
+    previous_version_id INTEGER,
+    import_version_id INTEGER,
+    imported_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_bulk_import_changes_client_id
+ON bulk_import_changes (client_id);
+
+CREATE INDEX IF NOT EXISTS idx_bulk_import_changes_definition_id
+ON bulk_import_changes (definition_id);
+
+-- Earlier imports only created definitions, tagged through their metadata.
+INSERT INTO bulk_import_changes (client_id, definition_id, action, user_id, import_version_id, imported_at)
+SELECT
+    d.metadata->>'client_id',
+    d.definitionid,
+    'created',
+    d.userid,
+    (SELECT MIN(v.version_id) FROM definition_versions v WHERE v.definition_id = d.definitionid),
+    COALESCE((d.metadata->>'import_time')::timestamptz, d.created_at, NOW())
+FROM definitions d
+WHERE d.metadata->>'bulk_import' = 'true' AND d.metadata->>'client_id' IS NOT NULL;
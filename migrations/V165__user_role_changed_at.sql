@@ -0,0 +1,8 @@
+-- When a user's role or blocked status last changed. Access tokens carry the role they were
+-- issued with, so every instance overrides it for users changed within the lifetime of a token
+-- (see `auth::permissions::PermissionCache`).
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role_changed_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_users_role_changed_at
+ON users (role_changed_at)
+WHERE role_changed_at IS NOT NULL;
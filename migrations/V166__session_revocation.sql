@@ -0,0 +1,4 @@
+-- Sessions revoked by their user (or by a password change or block) before they ended.
+-- Tokens of revoked sessions are also listed in Redis so that `auth::validator` rejects them
+-- without a database round trip; this column is the durable record used on token refresh.
+ALTER TABLE user_sessions ADD COLUMN IF NOT EXISTS revoked_at TIMESTAMPTZ;
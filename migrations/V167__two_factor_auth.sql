@@ -0,0 +1,40 @@
+-- Optional second factors: TOTP (RFC 6238), one-time recovery codes and WebAuthn passkeys.
+CREATE TABLE IF NOT EXISTS user_totp (
+    user_id INTEGER PRIMARY KEY REFERENCES users(userid) ON DELETE CASCADE,
+    -- Base32, as shown to the user when enrolling
+    secret TEXT NOT NULL,
+    -- NULL until the user confirms a code from their authenticator app
+    enabled_at TIMESTAMPTZ,
+    -- Time step of the last accepted code, so that a code cannot be used twice
+    last_used_step BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS user_recovery_codes (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(userid) ON DELETE CASCADE,
+    -- SHA-256 of the normalized code
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_recovery_codes_unused
+ON user_recovery_codes (user_id)
+WHERE used_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS user_passkeys (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(userid) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    credential_id TEXT NOT NULL UNIQUE,
+    -- Serialized `webauthn_rs::prelude::Passkey`, including its signature counter
+    passkey JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_passkeys_user_id ON user_passkeys (user_id);
+
+-- Opaque WebAuthn user handle, so authenticators never see the numeric user id
+ALTER TABLE users ADD COLUMN IF NOT EXISTS webauthn_user_id UUID NOT NULL DEFAULT gen_random_uuid();
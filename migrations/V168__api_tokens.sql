@@ -0,0 +1,19 @@
+-- Personal API tokens for bots and scripts, accepted by `auth::validator` next to JWTs.
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(userid) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    -- SHA-256 of the token; the token itself is only shown once, when created
+    token_hash TEXT NOT NULL UNIQUE,
+    -- First characters of the token, so users can tell their tokens apart
+    token_prefix TEXT NOT NULL,
+    -- `read` and/or permission names (see `permissions.name`)
+    scopes TEXT[] NOT NULL,
+    rate_limit_per_hour INTEGER NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_tokens_user ON api_tokens (user_id) WHERE revoked_at IS NULL;
@@ -0,0 +1,85 @@
+-- Reports of abusive or vandalised content, worked through by moderators in a queue.
+-- Any user can report a comment, definition, collection or wiki page (`wiki_articles.id`).
+CREATE TABLE IF NOT EXISTS content_reports (
+    id SERIAL PRIMARY KEY,
+    reporter_id INTEGER NOT NULL REFERENCES users(userid) ON DELETE CASCADE,
+    target_type TEXT NOT NULL CHECK (target_type IN ('comment', 'definition', 'collection', 'wiki_page')),
+    target_id INTEGER NOT NULL,
+    reason TEXT NOT NULL CHECK (reason IN ('spam', 'abuse', 'vandalism', 'copyright', 'other')),
+    details TEXT,
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'claimed', 'resolved', 'dismissed')),
+    claimed_by INTEGER REFERENCES users(userid) ON DELETE SET NULL,
+    claimed_at TIMESTAMPTZ,
+    resolved_by INTEGER REFERENCES users(userid) ON DELETE SET NULL,
+    resolved_at TIMESTAMPTZ,
+    -- What the moderator did about it; `resolution_ref` points at the result
+    -- (hidden comment, new definition version, blocked user)
+    resolution_action TEXT CHECK (resolution_action IN ('none', 'hide_comment', 'revert_version', 'block_user')),
+    resolution_ref JSONB,
+    resolution_note TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- A user has at most one pending report per target
+CREATE UNIQUE INDEX IF NOT EXISTS idx_content_reports_pending_unique
+ON content_reports (reporter_id, target_type, target_id)
+WHERE status IN ('open', 'claimed');
+
+CREATE INDEX IF NOT EXISTS idx_content_reports_queue ON content_reports (status, created_at);
+CREATE INDEX IF NOT EXISTS idx_content_reports_target ON content_reports (target_type, target_id);
+
+-- Append-only trail of everything that happened to a report
+CREATE TABLE IF NOT EXISTS content_report_events (
+    id BIGSERIAL PRIMARY KEY,
+    report_id INTEGER NOT NULL REFERENCES content_reports(id),
+    actor_id INTEGER REFERENCES users(userid) ON DELETE SET NULL,
+    -- reported, claimed, resolved or dismissed
+    event TEXT NOT NULL,
+    note TEXT,
+    data JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_content_report_events_report ON content_report_events (report_id, id);
+
+-- Comments hidden by a moderator keep their row, so replies and reports still resolve,
+-- but their subject and content are blanked in listings.
+ALTER TABLE comments
+ADD COLUMN IF NOT EXISTS hidden_at TIMESTAMPTZ,
+ADD COLUMN IF NOT EXISTS hidden_by INTEGER REFERENCES users(userid) ON DELETE SET NULL;
+
+CREATE OR REPLACE VIEW convenientcomments AS
+SELECT
+    c.commentid,
+    c.threadid,
+    c.parentid,
+    c.userid,
+    u.username,
+    u.realname,
+    c.time,
+    CASE WHEN c.hidden_at IS NULL THEN c.subject END AS subject,
+    CASE WHEN c.hidden_at IS NULL THEN c.content
+         ELSE jsonb_build_array(jsonb_build_object('type', 'text', 'data', '[hidden by a moderator]'))
+    END AS content,
+    c.commentnum,
+    cc.total_reactions,
+    cc.total_replies,
+    t.valsiid,
+    t.definitionid,
+    t.definition_link_id,
+    t.collection_id
+FROM
+    comments c
+    JOIN users u ON c.userid = u.userid
+    JOIN threads t ON c.threadid = t.threadid
+    LEFT JOIN comment_counters cc ON c.commentid = cc.comment_id;
+
+INSERT INTO permissions (name, description) VALUES
+('moderate_reports', 'Can work the content report queue')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role, permission_id)
+SELECT r.role, p.id
+FROM permissions p, (VALUES ('admin'), ('moderator')) AS r(role)
+WHERE p.name = 'moderate_reports'
+ON CONFLICT (role, permission_id) DO NOTHING;
@@ -0,0 +1,178 @@
+-- Deleting a definition, comment or collection now only marks it. The row stays in place, hidden
+-- from search and listings, so that the owner or a moderator can restore it together with the
+-- flashcards, progress and collection items that reference it. The purge job removes marked rows
+-- for good once they are older than TRASH_RETENTION_DAYS.
+ALTER TABLE definitions
+    ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS deleted_by INTEGER REFERENCES users(userid) ON DELETE SET NULL;
+
+ALTER TABLE comments
+    ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS deleted_by INTEGER REFERENCES users(userid) ON DELETE SET NULL;
+
+ALTER TABLE collections
+    ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS deleted_by INTEGER REFERENCES users(userid) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_definitions_deleted_at ON definitions (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_comments_deleted_at ON comments (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_collections_deleted_at ON collections (deleted_at) WHERE deleted_at IS NOT NULL;
+
+CREATE OR REPLACE VIEW convenientdefinitions AS
+SELECT nd.definitionid,
+    l.realname AS langrealname,
+    l.tag,
+    l.langid,
+    v.valsiid,
+    v.word,
+    nd.definition,
+    nd.notes,
+    u.username,
+    u.userid,
+    nd."time",
+    nd.definitionnum,
+    COALESCE(nd.rafsi, v.rafsi) AS rafsi,
+    nd.selmaho,
+    nd.jargon
+FROM public.definitions nd
+JOIN public.languages l ON nd.langid = l.langid
+JOIN public.valsi v ON nd.valsiid = v.valsiid
+JOIN public.users u ON nd.userid = u.userid
+WHERE nd.deleted_at IS NULL;
+
+CREATE OR REPLACE VIEW convenientcomments AS
+SELECT
+    c.commentid,
+    c.threadid,
+    c.parentid,
+    c.userid,
+    u.username,
+    u.realname,
+    c.time,
+    CASE WHEN c.hidden_at IS NULL THEN c.subject END AS subject,
+    CASE WHEN c.hidden_at IS NULL THEN c.content
+         ELSE jsonb_build_array(jsonb_build_object('type', 'text', 'data', '[hidden by a moderator]'))
+    END AS content,
+    c.commentnum,
+    cc.total_reactions,
+    cc.total_replies,
+    t.valsiid,
+    t.definitionid,
+    t.definition_link_id,
+    t.collection_id
+FROM
+    comments c
+    JOIN users u ON c.userid = u.userid
+    JOIN threads t ON c.threadid = t.threadid
+    LEFT JOIN comment_counters cc ON c.commentid = cc.comment_id
+WHERE c.deleted_at IS NULL;
+
+-- Thread stats only count live comments; marking or restoring a comment is an UPDATE and
+-- recomputes them.
+CREATE OR REPLACE FUNCTION update_thread_stats() RETURNS TRIGGER AS $$
+BEGIN
+    -- Update stats for both old and new thread IDs (for comment moves)
+    IF TG_OP = 'DELETE' OR TG_OP = 'UPDATE' THEN
+        UPDATE threads SET
+            last_comment_id = (SELECT commentid FROM comments WHERE threadid = OLD.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            last_comment_user_id = (SELECT userid FROM comments WHERE threadid = OLD.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            last_comment_time = (SELECT time FROM comments WHERE threadid = OLD.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            last_comment_subject = (SELECT subject FROM comments WHERE threadid = OLD.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            last_comment_content = (SELECT content FROM comments WHERE threadid = OLD.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            total_comments = (SELECT COUNT(*) FROM comments WHERE threadid = OLD.threadid AND deleted_at IS NULL),
+            first_comment_subject = (SELECT subject FROM comments WHERE threadid = OLD.threadid AND deleted_at IS NULL ORDER BY time ASC, commentid ASC LIMIT 1),
+            first_comment_content = (SELECT content FROM comments WHERE threadid = OLD.threadid AND deleted_at IS NULL ORDER BY time ASC, commentid ASC LIMIT 1)
+        WHERE threadid = OLD.threadid;
+    END IF;
+
+    IF TG_OP = 'INSERT' OR TG_OP = 'UPDATE' THEN
+        UPDATE threads SET
+            last_comment_id = (SELECT commentid FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            last_comment_user_id = (SELECT userid FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            last_comment_time = (SELECT time FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            last_comment_subject = (SELECT subject FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            last_comment_content = (SELECT content FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL ORDER BY time DESC, commentid DESC LIMIT 1),
+            total_comments = (SELECT COUNT(*) FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL),
+            first_comment_subject = (SELECT subject FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL ORDER BY time ASC, commentid ASC LIMIT 1),
+            first_comment_content = (SELECT content FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL ORDER BY time ASC, commentid ASC LIMIT 1),
+            creator_user_id = (SELECT userid FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL ORDER BY time ASC, commentid ASC LIMIT 1),
+            creator_username = (SELECT username FROM users WHERE userid = (SELECT userid FROM comments WHERE threadid = NEW.threadid AND deleted_at IS NULL ORDER BY time ASC, commentid ASC LIMIT 1))
+        WHERE threadid = NEW.threadid;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- A marked comment no longer counts as a reply; purging it later must not count it twice.
+CREATE OR REPLACE FUNCTION update_comment_reply_count()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        IF NEW.parentid IS NOT NULL THEN
+            UPDATE comment_activity_counters
+            SET total_replies = total_replies + 1
+            WHERE comment_id = NEW.parentid;
+        END IF;
+    ELSIF TG_OP = 'DELETE' THEN
+        IF OLD.parentid IS NOT NULL AND OLD.deleted_at IS NULL THEN
+            UPDATE comment_activity_counters
+            SET total_replies = total_replies - 1
+            WHERE comment_id = OLD.parentid;
+        END IF;
+    ELSIF TG_OP = 'UPDATE' AND NEW.parentid IS NOT NULL
+          AND (OLD.deleted_at IS NULL) <> (NEW.deleted_at IS NULL) THEN
+        UPDATE comment_activity_counters
+        SET total_replies = total_replies + CASE WHEN NEW.deleted_at IS NULL THEN 1 ELSE -1 END
+        WHERE comment_id = NEW.parentid;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS maintain_comment_reply_count ON comments;
+CREATE TRIGGER maintain_comment_reply_count
+    AFTER INSERT OR DELETE OR UPDATE OF deleted_at ON comments
+    FOR EACH ROW
+    EXECUTE FUNCTION update_comment_reply_count();
+
+-- Dictionary exports leave trashed definitions out.
+CREATE OR REPLACE FUNCTION export_best_definitions(p_langid integer, p_positive_only boolean)
+RETURNS TABLE(valsiid integer, definitionid integer, score bigint) AS $$
+WITH definition_scores AS (
+    SELECT d.definitionid, d.valsiid, d.langid,
+           COALESCE(SUM(dv.value), 0)::bigint AS score
+    FROM definitions d
+    LEFT JOIN definitionvotes dv ON dv.definitionid = d.definitionid
+    WHERE d.langid = p_langid AND d.deleted_at IS NULL
+    GROUP BY d.definitionid, d.valsiid, d.langid
+),
+filtered AS (
+    SELECT ds.valsiid, ds.definitionid, ds.score
+    FROM definition_scores ds
+    WHERE ds.score > 0 OR p_positive_only = false
+),
+best_positive AS (
+    SELECT DISTINCT ON (f.valsiid)
+        f.valsiid,
+        f.definitionid,
+        f.score
+    FROM filtered f
+    WHERE p_positive_only = true
+    ORDER BY f.valsiid, f.score DESC, f.definitionid ASC
+)
+SELECT b.valsiid, b.definitionid, b.score FROM best_positive b
+UNION ALL
+SELECT f.valsiid, f.definitionid, f.score FROM filtered f
+WHERE p_positive_only = false;
+$$ LANGUAGE sql STABLE;
+
+INSERT INTO permissions (name, description) VALUES
+('manage_trash', 'Can see and restore content deleted by other users')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role, permission_id)
+SELECT r.role, p.id
+FROM permissions p, (VALUES ('admin'), ('moderator')) AS r(role)
+WHERE p.name = 'manage_trash'
+ON CONFLICT (role, permission_id) DO NOTHING;
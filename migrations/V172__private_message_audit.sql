@@ -0,0 +1,68 @@
+-- Edits and deletions of private messages keep the previous (encrypted) content so that the
+-- sender and thread admins can see what changed.
+CREATE TABLE IF NOT EXISTS private_message_audit (
+    audit_id BIGSERIAL PRIMARY KEY,
+    message_id BIGINT NOT NULL REFERENCES private_messages(message_id) ON DELETE CASCADE,
+    actor_id INTEGER REFERENCES users(userid) ON DELETE SET NULL,
+    action VARCHAR(20) NOT NULL CHECK (action IN ('edit', 'delete')),
+    previous_content TEXT NOT NULL,
+    previous_nonce BYTEA NOT NULL,
+    previous_signature BYTEA,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_private_message_audit_message ON private_message_audit(message_id, created_at);
+
+CREATE OR REPLACE FUNCTION private_message_preview(p_content TEXT)
+RETURNS TEXT AS $$
+BEGIN
+    RETURN LEFT(convert_from(decode(p_content, 'base64'), 'UTF8'), 100);
+EXCEPTION WHEN OTHERS THEN
+    RETURN LEFT(p_content, 100);
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+-- Editing the latest message refreshes the thread preview; deleting it falls back to the
+-- previous live message.
+CREATE OR REPLACE FUNCTION update_thread_message_stats()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' AND NEW.is_deleted = FALSE THEN
+        UPDATE message_threads
+        SET message_count = message_count + 1,
+            last_message_at = NEW.created_at,
+            last_message_preview = private_message_preview(NEW.encrypted_content),
+            updated_at = CURRENT_TIMESTAMP
+        WHERE thread_id = NEW.thread_id;
+
+        UPDATE thread_participants
+        SET unread_count = unread_count + 1
+        WHERE thread_id = NEW.thread_id
+        AND user_id != NEW.sender_id
+        AND is_active = TRUE;
+    ELSIF TG_OP = 'UPDATE' AND OLD.is_deleted = FALSE AND NEW.is_deleted = TRUE THEN
+        UPDATE message_threads
+        SET message_count = GREATEST(message_count - 1, 0),
+            last_message_at = (
+                SELECT MAX(m.created_at) FROM private_messages m
+                WHERE m.thread_id = NEW.thread_id AND m.is_deleted = FALSE),
+            last_message_preview = (
+                SELECT private_message_preview(m.encrypted_content) FROM private_messages m
+                WHERE m.thread_id = NEW.thread_id AND m.is_deleted = FALSE
+                ORDER BY m.created_at DESC, m.message_id DESC
+                LIMIT 1)
+        WHERE thread_id = NEW.thread_id;
+    ELSIF TG_OP = 'UPDATE' AND NEW.is_deleted = FALSE
+          AND NEW.encrypted_content IS DISTINCT FROM OLD.encrypted_content THEN
+        UPDATE message_threads
+        SET last_message_preview = private_message_preview(NEW.encrypted_content)
+        WHERE thread_id = NEW.thread_id
+        AND NOT EXISTS (
+            SELECT 1 FROM private_messages m
+            WHERE m.thread_id = NEW.thread_id AND m.is_deleted = FALSE
+            AND (m.created_at, m.message_id) > (NEW.created_at, NEW.message_id)
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,83 @@
+-- Opt-in end-to-end encrypted threads. The server only stores public keys, thread keys wrapped
+-- by clients for each participant, and ciphertext.
+ALTER TABLE message_threads
+    ADD COLUMN IF NOT EXISTS is_encrypted BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS key_version INTEGER NOT NULL DEFAULT 0;
+
+-- Version of the thread key the message was encrypted with (encrypted threads only)
+ALTER TABLE private_messages ADD COLUMN IF NOT EXISTS key_version INTEGER;
+
+ALTER TABLE message_encryption_keys
+    ADD COLUMN IF NOT EXISTS wrapped_by INTEGER REFERENCES users(userid) ON DELETE SET NULL;
+
+CREATE TABLE IF NOT EXISTS user_identity_keys (
+    user_id INTEGER PRIMARY KEY REFERENCES users(userid) ON DELETE CASCADE,
+    identity_key BYTEA NOT NULL,
+    key_algorithm VARCHAR(50) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Prekeys signed with the user's identity key; the newest one is handed out
+CREATE TABLE IF NOT EXISTS user_signed_prekeys (
+    user_id INTEGER NOT NULL REFERENCES users(userid) ON DELETE CASCADE,
+    prekey_id INTEGER NOT NULL,
+    public_key BYTEA NOT NULL,
+    signature BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, prekey_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_signed_prekeys_latest ON user_signed_prekeys(user_id, created_at DESC);
+
+-- Ciphertext of encrypted threads says nothing readable, so they get no preview
+CREATE OR REPLACE FUNCTION thread_message_preview(p_thread_id BIGINT, p_content TEXT)
+RETURNS TEXT AS $$
+    SELECT CASE WHEN mt.is_encrypted THEN NULL ELSE private_message_preview(p_content) END
+    FROM message_threads mt
+    WHERE mt.thread_id = p_thread_id;
+$$ LANGUAGE sql STABLE;
+
+CREATE OR REPLACE FUNCTION update_thread_message_stats()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' AND NEW.is_deleted = FALSE THEN
+        UPDATE message_threads
+        SET message_count = message_count + 1,
+            last_message_at = NEW.created_at,
+            last_message_preview = thread_message_preview(NEW.thread_id, NEW.encrypted_content),
+            updated_at = CURRENT_TIMESTAMP
+        WHERE thread_id = NEW.thread_id;
+
+        UPDATE thread_participants
+        SET unread_count = unread_count + 1
+        WHERE thread_id = NEW.thread_id
+        AND user_id != NEW.sender_id
+        AND is_active = TRUE;
+    ELSIF TG_OP = 'UPDATE' AND OLD.is_deleted = FALSE AND NEW.is_deleted = TRUE THEN
+        UPDATE message_threads
+        SET message_count = GREATEST(message_count - 1, 0),
+            last_message_at = (
+                SELECT MAX(m.created_at) FROM private_messages m
+                WHERE m.thread_id = NEW.thread_id AND m.is_deleted = FALSE),
+            last_message_preview = (
+                SELECT thread_message_preview(m.thread_id, m.encrypted_content)
+                FROM private_messages m
+                WHERE m.thread_id = NEW.thread_id AND m.is_deleted = FALSE
+                ORDER BY m.created_at DESC, m.message_id DESC
+                LIMIT 1)
+        WHERE thread_id = NEW.thread_id;
+    ELSIF TG_OP = 'UPDATE' AND NEW.is_deleted = FALSE
+          AND NEW.encrypted_content IS DISTINCT FROM OLD.encrypted_content THEN
+        UPDATE message_threads
+        SET last_message_preview = thread_message_preview(NEW.thread_id, NEW.encrypted_content)
+        WHERE thread_id = NEW.thread_id
+        AND NOT EXISTS (
+            SELECT 1 FROM private_messages m
+            WHERE m.thread_id = NEW.thread_id AND m.is_deleted = FALSE
+            AND (m.created_at, m.message_id) > (NEW.created_at, NEW.message_id)
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,23 @@
+-- Full-text search over private messages. Content of threads that are not end-to-end encrypted
+-- is stored base64 encoded by the web client; it is decoded before indexing. Messages of
+-- encrypted threads carry a key_version and are never indexed.
+
+CREATE OR REPLACE FUNCTION private_message_text(p_content TEXT)
+RETURNS TEXT AS $$
+BEGIN
+    RETURN convert_from(decode(p_content, 'base64'), 'UTF8');
+EXCEPTION WHEN OTHERS THEN
+    RETURN p_content;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE;
+
+-- tsvector input is capped at 1MB
+ALTER TABLE private_messages
+    ADD COLUMN IF NOT EXISTS search_vector TSVECTOR
+    GENERATED ALWAYS AS (
+        CASE WHEN key_version IS NULL
+            THEN to_tsvector('simple'::regconfig, LEFT(private_message_text(encrypted_content), 500000))
+        END
+    ) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_private_messages_search_vector ON private_messages USING GIN (search_vector);
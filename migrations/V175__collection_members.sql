@@ -0,0 +1,41 @@
+-- Collections can be co-maintained. The owner invites users by username as editors (may change
+-- items, flashcards, levels and media) or viewers (may read a private collection).
+CREATE TABLE IF NOT EXISTS collection_members (
+    collection_id INTEGER NOT NULL REFERENCES collections(collection_id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(userid) ON DELETE CASCADE,
+    role VARCHAR(10) NOT NULL CHECK (role IN ('editor', 'viewer')),
+    invited_by INTEGER REFERENCES users(userid) ON DELETE SET NULL,
+    invited_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- NULL while the invitation is pending
+    accepted_at TIMESTAMPTZ,
+    PRIMARY KEY (collection_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_collection_members_user ON collection_members(user_id);
+
+-- The user's role in a collection that is not in the trash: 'owner', 'editor', 'viewer' or NULL.
+-- Pending invitations grant nothing.
+CREATE OR REPLACE FUNCTION collection_role(p_collection_id INTEGER, p_user_id INTEGER)
+RETURNS VARCHAR AS $$
+    SELECT CASE WHEN c.user_id = p_user_id THEN 'owner' ELSE m.role END
+    FROM collections c
+    LEFT JOIN collection_members m
+        ON m.collection_id = c.collection_id
+        AND m.user_id = p_user_id
+        AND m.accepted_at IS NOT NULL
+    WHERE c.collection_id = p_collection_id AND c.deleted_at IS NULL;
+$$ LANGUAGE sql STABLE;
+
+-- Who changed which item and how. item_id has no foreign key so that removals stay listed.
+CREATE TABLE IF NOT EXISTS collection_item_history (
+    history_id BIGSERIAL PRIMARY KEY,
+    collection_id INTEGER NOT NULL REFERENCES collections(collection_id) ON DELETE CASCADE,
+    item_id INTEGER NOT NULL,
+    user_id INTEGER REFERENCES users(userid) ON DELETE SET NULL,
+    action VARCHAR(20) NOT NULL
+        CHECK (action IN ('add', 'update', 'reorder', 'notes', 'media', 'remove')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_collection_item_history_collection ON collection_item_history(collection_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_collection_item_history_item ON collection_item_history(item_id, created_at DESC);
@@ -0,0 +1,5 @@
+-- Lookups for threading new mail against the existing reply trees: messages not threaded yet,
+-- and replies archived before the message they answer.
+CREATE INDEX IF NOT EXISTS idx_messages_unthreaded ON messages (id) WHERE thread_id IS NULL;
+CREATE INDEX IF NOT EXISTS idx_messages_in_reply_to ON messages (in_reply_to);
+CREATE INDEX IF NOT EXISTS idx_messages_reference_ids ON messages USING GIN (reference_ids);
@@ -0,0 +1,7 @@
+-- A running export job belongs to its worker until locked_until; the worker renews the lease
+-- while it builds. Jobs whose lease ran out (the worker's process died) are queued again.
+ALTER TABLE dictionary_export_jobs
+ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_dictionary_export_jobs_running_lease
+ON dictionary_export_jobs (locked_until) WHERE status = 'running';
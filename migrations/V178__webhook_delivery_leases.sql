@@ -0,0 +1,7 @@
+-- A running delivery belongs to the worker that claimed it until locked_until. Deliveries whose
+-- lease ran out (the worker's process died) are sent again.
+ALTER TABLE webhook_deliveries
+ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_running_lease
+ON webhook_deliveries (locked_until) WHERE status = 'running';
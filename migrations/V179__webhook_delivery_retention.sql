@@ -0,0 +1,4 @@
+-- Delivered and failed deliveries are purged nightly once they are older than
+-- WEBHOOK_DELIVERY_RETENTION_DAYS.
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_finished
+ON webhook_deliveries (created_at) WHERE status IN ('delivered', 'failed');
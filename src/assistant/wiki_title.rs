@@ -173,7 +173,7 @@ async fn load_entry_context(
                 "SELECT d.definition, v.word
                  FROM definitions d
                  JOIN valsi v ON v.valsiid = d.valsiid
-                 WHERE d.definitionid = $1",
+                 WHERE d.definitionid = $1 AND d.deleted_at IS NULL",
                 &[&definition_id],
             )
             .await
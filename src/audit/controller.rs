@@ -0,0 +1,105 @@
+use actix_web::{get, http::header, web, HttpResponse};
+use chrono::Utc;
+use deadpool_postgres::Pool;
+
+use super::{dto::*, service};
+use crate::{auth::permissions::PermissionCache, auth::Claims, AppError};
+
+async fn require_audit_access(
+    claims: &Claims,
+    perm_cache: &PermissionCache,
+) -> Result<(), AppError> {
+    if perm_cache
+        .claims_have_permission(claims, "view_audit_log")
+        .await
+    {
+        Ok(())
+    } else {
+        Err(AppError::Auth(
+            "The audit log needs the view_audit_log permission".to_string(),
+        ))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit-log",
+    tag = "audit",
+    params(AuditLogQuery),
+    responses(
+        (status = 200, description = "Audit log entries, newest first", body = AuditLogResponse),
+        (status = 400, description = "Unknown action or target type"),
+        (status = 403, description = "Missing the view_audit_log permission")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Admin audit log",
+    description = "Lists privileged actions (role changes, blocks, reverts, definition deletions, report resolutions) \
+                  with the actor, target, state before and after, IP address and session. Filter by actor, action, \
+                  target and time range."
+)]
+#[get("")]
+pub async fn list_entries(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    query: web::Query<AuditLogQuery>,
+) -> Result<HttpResponse, AppError> {
+    require_audit_access(&claims, &perm_cache).await?;
+    let log = service::list_entries(&pool, &query).await?;
+    Ok(HttpResponse::Ok().json(log))
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit-log/export",
+    tag = "audit",
+    params(AuditLogQuery, AuditExportQuery),
+    responses(
+        (status = 200, description = "Matching entries as a CSV download, or JSON with format=json", content_type = "text/csv"),
+        (status = 400, description = "Unknown format, action or target type"),
+        (status = 403, description = "Missing the view_audit_log permission")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Export the admin audit log",
+    description = "Downloads the entries matching the same filters as the list, newest first, up to 50000 rows. \
+                  In CSV the before and after states are JSON strings."
+)]
+#[get("/export")]
+pub async fn export_entries(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    query: web::Query<AuditLogQuery>,
+    export: web::Query<AuditExportQuery>,
+) -> Result<HttpResponse, AppError> {
+    require_audit_access(&claims, &perm_cache).await?;
+    let format = export.format.as_deref().unwrap_or("csv");
+    if !matches!(format, "csv" | "json") {
+        return Err(AppError::Validation(format!(
+            "Unknown export format: {}",
+            format
+        )));
+    }
+
+    let entries = service::export_entries(&pool, &query).await?;
+    let (content_type, body) = if format == "json" {
+        ("application/json", serde_json::to_vec(&entries)?)
+    } else {
+        (
+            "text/csv; charset=utf-8",
+            service::entries_to_csv(&entries)?,
+        )
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .content_type(content_type)
+        .append_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"audit-log-{}.{}\"",
+                Utc::now().format("%Y%m%d-%H%M%S"),
+                format
+            ),
+        ))
+        .body(body))
+}
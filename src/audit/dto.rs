@@ -0,0 +1,50 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AuditLogQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    pub actor_id: Option<i32>,
+    /// e.g. assign_role, block_user, revert_version, delete_definition
+    pub action: Option<String>,
+    /// user, role, definition, bulk_import or report
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    /// Only entries at or after this time (RFC 3339)
+    pub from: Option<DateTime<Utc>>,
+    /// Only entries before this time (RFC 3339)
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AuditExportQuery {
+    /// csv (default) or json
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub actor_id: Option<i32>,
+    pub actor_username: Option<String>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub session_id: Option<Uuid>,
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditLogResponse {
+    pub entries: Vec<AuditLogEntry>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+}
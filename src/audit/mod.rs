@@ -0,0 +1,18 @@
+pub mod controller;
+pub mod dto;
+pub mod models;
+pub mod service;
+
+use actix_web::web;
+use actix_web_httpauth::middleware::HttpAuthentication;
+
+pub use models::{AuditAction, AuditContext};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/audit-log")
+            .wrap(HttpAuthentication::bearer(crate::auth::validator))
+            .service(controller::list_entries)
+            .service(controller::export_entries),
+    );
+}
@@ -0,0 +1,104 @@
+use actix_web::{dev::Payload, Error as ActixError, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use uuid::Uuid;
+
+use crate::auth::Claims;
+
+/// Who performs a privileged action and from where. Extracting it authenticates the request
+/// like [`Claims`] does.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub actor_id: i32,
+    pub actor_username: String,
+    pub ip_address: Option<String>,
+    pub session_id: Option<Uuid>,
+}
+
+impl AuditContext {
+    pub fn new(claims: &Claims, req: &HttpRequest) -> Self {
+        AuditContext {
+            actor_id: claims.sub,
+            actor_username: claims.username.clone(),
+            ip_address: req
+                .connection_info()
+                .realip_remote_addr()
+                .map(str::to_string),
+            session_id: claims.sid,
+        }
+    }
+}
+
+impl FromRequest for AuditContext {
+    type Error = ActixError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        ready(
+            Claims::from_request(req, payload)
+                .into_inner()
+                .map(|claims| AuditContext::new(&claims, req)),
+        )
+    }
+}
+
+/// Privileged actions recorded in `admin_audit_log`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    AssignRole,
+    BlockUser,
+    UnblockUser,
+    CreateRole,
+    UpdateRole,
+    DeleteRole,
+    RevertVersion,
+    DeleteDefinition,
+    DeleteBulkDefinitions,
+    RevertBulkImport,
+    ResolveReport,
+    DismissReport,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 12] = [
+        AuditAction::AssignRole,
+        AuditAction::BlockUser,
+        AuditAction::UnblockUser,
+        AuditAction::CreateRole,
+        AuditAction::UpdateRole,
+        AuditAction::DeleteRole,
+        AuditAction::RevertVersion,
+        AuditAction::DeleteDefinition,
+        AuditAction::DeleteBulkDefinitions,
+        AuditAction::RevertBulkImport,
+        AuditAction::ResolveReport,
+        AuditAction::DismissReport,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::AssignRole => "assign_role",
+            AuditAction::BlockUser => "block_user",
+            AuditAction::UnblockUser => "unblock_user",
+            AuditAction::CreateRole => "create_role",
+            AuditAction::UpdateRole => "update_role",
+            AuditAction::DeleteRole => "delete_role",
+            AuditAction::RevertVersion => "revert_version",
+            AuditAction::DeleteDefinition => "delete_definition",
+            AuditAction::DeleteBulkDefinitions => "delete_bulk_definitions",
+            AuditAction::RevertBulkImport => "revert_bulk_import",
+            AuditAction::ResolveReport => "resolve_report",
+            AuditAction::DismissReport => "dismiss_report",
+        }
+    }
+
+    /// Kind of object `target_id` refers to.
+    pub fn target_type(&self) -> &'static str {
+        match self {
+            AuditAction::AssignRole | AuditAction::BlockUser | AuditAction::UnblockUser => "user",
+            AuditAction::CreateRole | AuditAction::UpdateRole | AuditAction::DeleteRole => "role",
+            AuditAction::RevertVersion | AuditAction::DeleteDefinition => "definition",
+            AuditAction::DeleteBulkDefinitions | AuditAction::RevertBulkImport => "bulk_import",
+            AuditAction::ResolveReport | AuditAction::DismissReport => "report",
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use deadpool_postgres::{GenericClient, Pool};
+use serde_json::Value;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Row;
+
+use super::dto::{AuditLogEntry, AuditLogQuery, AuditLogResponse};
+use super::models::{AuditAction, AuditContext};
+use crate::error::{AppError, AppResult};
+
+const TARGET_TYPES: [&str; 5] = ["user", "role", "definition", "bulk_import", "report"];
+const MAX_EXPORT_ROWS: i64 = 50_000;
+const ENTRY_COLUMNS: &str = "id, actor_id, actor_username, action, target_type, target_id, \
+                             before, after, ip_address, session_id, created_at";
+const ENTRY_FILTER: &str = "WHERE ($1::int IS NULL OR actor_id = $1)
+      AND ($2::text IS NULL OR action = $2)
+      AND ($3::text IS NULL OR target_type = $3)
+      AND ($4::text IS NULL OR target_id = $4)
+      AND ($5::timestamptz IS NULL OR created_at >= $5)
+      AND ($6::timestamptz IS NULL OR created_at < $6)";
+
+/// Appends an entry to `admin_audit_log`. Call it in the transaction that makes the change, so
+/// that the log holds exactly the committed actions.
+pub async fn record(
+    client: &impl GenericClient,
+    ctx: &AuditContext,
+    action: AuditAction,
+    target_id: &str,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute(
+            "INSERT INTO admin_audit_log
+                 (actor_id, actor_username, action, target_type, target_id, before, after,
+                  ip_address, session_id)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            &[
+                &ctx.actor_id,
+                &ctx.actor_username,
+                &action.as_str(),
+                &action.target_type(),
+                &target_id,
+                &before,
+                &after,
+                &ctx.ip_address,
+                &ctx.session_id,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Role and block state of a user, for before/after snapshots.
+pub async fn user_snapshot(
+    client: &impl GenericClient,
+    user_id: i32,
+) -> Result<Option<Value>, tokio_postgres::Error> {
+    Ok(client
+        .query_opt(
+            "SELECT jsonb_build_object(
+                 'username', username, 'role', role::text, 'disabled', disabled
+             ) FROM users WHERE userid = $1",
+            &[&user_id],
+        )
+        .await?
+        .map(|row| row.get(0)))
+}
+
+/// Permissions of a role and how many users hold it.
+pub async fn role_snapshot(
+    client: &impl GenericClient,
+    role: &str,
+) -> Result<Value, tokio_postgres::Error> {
+    client
+        .query_one(
+            "SELECT jsonb_build_object(
+                 'permissions', COALESCE((
+                     SELECT jsonb_agg(p.name ORDER BY p.name)
+                     FROM role_permissions rp JOIN permissions p ON p.id = rp.permission_id
+                     WHERE LOWER(rp.role::text) = LOWER($1::text)), '[]'::jsonb),
+                 'users', (SELECT COUNT(*) FROM users WHERE LOWER(role::text) = LOWER($1::text))
+             )",
+            &[&role],
+        )
+        .await
+        .map(|row| row.get(0))
+}
+
+/// The user-visible fields of a definition.
+pub async fn definition_snapshot(
+    client: &impl GenericClient,
+    definition_id: i32,
+) -> Result<Option<Value>, tokio_postgres::Error> {
+    Ok(client
+        .query_opt(
+            "SELECT jsonb_build_object(
+                 'definition_id', d.definitionid, 'valsi_id', d.valsiid, 'word', v.word,
+                 'langid', d.langid, 'definition', d.definition, 'notes', d.notes,
+                 'selmaho', d.selmaho, 'jargon', d.jargon, 'rafsi', d.rafsi,
+                 'etymology', d.etymology, 'user_id', d.userid, 'owner_only', d.owner_only,
+                 'metadata', d.metadata
+             )
+             FROM definitions d JOIN valsi v ON v.valsiid = d.valsiid
+             WHERE d.definitionid = $1",
+            &[&definition_id],
+        )
+        .await?
+        .map(|row| row.get(0)))
+}
+
+fn entry_from_row(row: &Row) -> AuditLogEntry {
+    AuditLogEntry {
+        id: row.get("id"),
+        actor_id: row.get("actor_id"),
+        actor_username: row.get("actor_username"),
+        action: row.get("action"),
+        target_type: row.get("target_type"),
+        target_id: row.get("target_id"),
+        before: row.get("before"),
+        after: row.get("after"),
+        ip_address: row.get("ip_address"),
+        session_id: row.get("session_id"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn validate_query(query: &AuditLogQuery) -> AppResult<()> {
+    if let Some(action) = query.action.as_deref() {
+        if !AuditAction::ALL.iter().any(|a| a.as_str() == action) {
+            return Err(AppError::Validation(format!(
+                "Unknown audit action: {}",
+                action
+            )));
+        }
+    }
+    if let Some(target_type) = query.target_type.as_deref() {
+        if !TARGET_TYPES.contains(&target_type) {
+            return Err(AppError::Validation(format!(
+                "Unknown target type: {}",
+                target_type
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn filter_params(query: &AuditLogQuery) -> [&(dyn ToSql + Sync); 6] {
+    [
+        &query.actor_id,
+        &query.action,
+        &query.target_type,
+        &query.target_id,
+        &query.from,
+        &query.to,
+    ]
+}
+
+pub async fn list_entries(pool: &Pool, query: &AuditLogQuery) -> AppResult<AuditLogResponse> {
+    validate_query(query)?;
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 200);
+    let offset = (page - 1) * per_page;
+
+    let client = pool.get().await?;
+    let mut params = filter_params(query).to_vec();
+    params.push(&per_page);
+    params.push(&offset);
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {} FROM admin_audit_log {} ORDER BY id DESC LIMIT $7 OFFSET $8",
+                ENTRY_COLUMNS, ENTRY_FILTER
+            ),
+            &params,
+        )
+        .await?;
+    let total: i64 = client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM admin_audit_log {}", ENTRY_FILTER),
+            &filter_params(query),
+        )
+        .await?
+        .get(0);
+
+    Ok(AuditLogResponse {
+        entries: rows.iter().map(entry_from_row).collect(),
+        total,
+        page,
+        per_page,
+    })
+}
+
+/// All entries matching the filters, newest first, up to [`MAX_EXPORT_ROWS`].
+pub async fn export_entries(pool: &Pool, query: &AuditLogQuery) -> AppResult<Vec<AuditLogEntry>> {
+    validate_query(query)?;
+    let client = pool.get().await?;
+    let mut params = filter_params(query).to_vec();
+    params.push(&MAX_EXPORT_ROWS);
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {} FROM admin_audit_log {} ORDER BY id DESC LIMIT $7",
+                ENTRY_COLUMNS, ENTRY_FILTER
+            ),
+            &params,
+        )
+        .await?;
+    Ok(rows.iter().map(entry_from_row).collect())
+}
+
+/// One row per entry; `before` and `after` are written as JSON.
+pub fn entries_to_csv(entries: &[AuditLogEntry]) -> AppResult<Vec<u8>> {
+    let csv_error = |e: csv::Error| AppError::Internal(format!("Failed to write CSV: {}", e));
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "id",
+            "created_at",
+            "actor_id",
+            "actor_username",
+            "action",
+            "target_type",
+            "target_id",
+            "before",
+            "after",
+            "ip_address",
+            "session_id",
+        ])
+        .map_err(csv_error)?;
+    let json = |value: &Option<Value>| value.as_ref().map(Value::to_string).unwrap_or_default();
+    for entry in entries {
+        writer
+            .write_record([
+                entry.id.to_string(),
+                entry.created_at.to_rfc3339(),
+                entry.actor_id.map(|id| id.to_string()).unwrap_or_default(),
+                entry.actor_username.clone().unwrap_or_default(),
+                entry.action.clone(),
+                entry.target_type.clone(),
+                entry.target_id.clone().unwrap_or_default(),
+                json(&entry.before),
+                json(&entry.after),
+                entry.ip_address.clone().unwrap_or_default(),
+                entry
+                    .session_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_default(),
+            ])
+            .map_err(csv_error)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| AppError::Internal(format!("Failed to write CSV: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    #[test]
+    fn csv_export_quotes_json_columns() {
+        let entry = AuditLogEntry {
+            id: 7,
+            actor_id: Some(1),
+            actor_username: Some("admin".to_string()),
+            action: AuditAction::AssignRole.as_str().to_string(),
+            target_type: AuditAction::AssignRole.target_type().to_string(),
+            target_id: Some("42".to_string()),
+            before: Some(json!({ "role": "user" })),
+            after: Some(json!({ "role": "editor" })),
+            ip_address: None,
+            session_id: None,
+            created_at: Utc::now(),
+        };
+        let csv =
+            String::from_utf8(entries_to_csv(&[entry]).unwrap_or_default()).unwrap_or_default();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("id,created_at,actor_id,actor_username,action,target_type,target_id,before,after,ip_address,session_id")
+        );
+        let row = lines.next().unwrap_or_default();
+        assert!(row.starts_with("7,"));
+        assert!(row.contains(",admin,assign_role,user,42,\"{\"\"role\"\":\"\"user\"\"}\",\"{\"\"role\"\":\"\"editor\"\"}\",,"));
+    }
+}
@@ -0,0 +1,494 @@
+//! Personal API tokens for bots and scripts.
+//!
+//! A token acts for the user who created it, limited to its scopes: `read` for safe requests,
+//! and for everything else the scope [`WRITE_ROUTES`] names for the route: a permission name
+//! from [`PermissionCache`], or one of [`RESOURCE_SCOPES`] for the user's own data.
+//! [`super::validator`] accepts tokens next to JWTs; they are told apart by [`TOKEN_PREFIX`].
+//! Only a SHA-256 hash of the secret is stored. Account settings (`/auth`, sessions, payments)
+//! are out of reach of tokens. Tokens cannot confirm a second factor, so a destructive scope
+//! (such as `bulk_import`) is only granted with a recent one when the owner has a factor; the
+//! token then acts without step-up.
+
+use actix_web::http::Method;
+use chrono::{Duration, Utc};
+use data_encoding::BASE32_NOPAD;
+use deadpool_postgres::Pool;
+use rand::{rng, RngExt};
+use sha2::{Digest, Sha256};
+use tokio_postgres::Row;
+
+use super::dto::{
+    ApiTokenResponse, ApiTokensResponse, CreateApiTokenRequest, CreatedApiTokenResponse,
+};
+use super::models::{Claims, UserRole};
+use super::permissions::PermissionCache;
+use super::two_factor;
+use crate::middleware::cache::RedisCache;
+use crate::{AppError, AppResult};
+
+pub const TOKEN_PREFIX: &str = "lsk_";
+/// Scope for GET and other safe requests.
+pub const READ_SCOPE: &str = "read";
+const SECRET_BYTES: usize = 20;
+/// Characters of the token kept in clear, so users can tell their tokens apart.
+const DISPLAY_PREFIX_LEN: usize = 12;
+const MAX_NAME_LEN: usize = 100;
+const MAX_ACTIVE_TOKENS: i64 = 25;
+const DEFAULT_EXPIRY_DAYS: i64 = 90;
+const MAX_EXPIRY_DAYS: i64 = 365;
+const DEFAULT_RATE_LIMIT_PER_HOUR: i32 = 1000;
+const MAX_RATE_LIMIT_PER_HOUR: i32 = 10_000;
+const RATE_LIMIT_WINDOW_SECS: i64 = 60 * 60;
+/// `last_used_at` is written at most this often per token.
+const LAST_USED_RESOLUTION_SECS: i32 = 60;
+const ACCOUNT_PATHS: [&str; 3] = ["/auth/", "/api/sessions", "/payments"];
+/// Scopes for changing a user's own data, which no role permission covers.
+pub const RESOURCE_SCOPES: [&str; 7] = [
+    "assistant",
+    "collections",
+    "flashcards",
+    "reports",
+    "subscriptions",
+    "trash",
+    "webhooks",
+];
+/// The scope each unsafe request needs, by path: `*` stands for one segment and a trailing `**`
+/// for any rest of the path. The first match applies; unsafe requests to paths not listed here
+/// are refused, so new routes stay out of reach of tokens until they are added.
+const WRITE_ROUTES: [(&str, &str); 24] = [
+    ("/jbovlaste/valsi/*/wiki/rename", "edit_definition"),
+    ("/jbovlaste/valsi", "create_definition"),
+    ("/jbovlaste/valsi/*", "edit_definition"),
+    ("/jbovlaste/vote", "vote_definition"),
+    // Looks votes up in bulk
+    ("/jbovlaste/votes", READ_SCOPE),
+    ("/jbovlaste/bulk-import/**", "bulk_import"),
+    ("/jbovlaste/definition_image/**", "edit_definition"),
+    ("/jbovlaste/definition/*", "delete_definition"),
+    ("/jbovlaste/definitions/link/**", "edit_definition"),
+    // Parsers and validators that change nothing
+    ("/language/**", READ_SCOPE),
+    ("/comments", "create_comment"),
+    ("/comments/bookmark", "create_comment"),
+    ("/comments/reactions", "create_comment"),
+    ("/comments/opinions/**", "create_comment"),
+    ("/comments/*", "delete_comment"),
+    ("/versions/*/revert", "revert_entry_version"),
+    ("/reports", "reports"),
+    ("/reports/*/**", "moderate_reports"),
+    ("/collections/**", "collections"),
+    ("/flashcards/**", "flashcards"),
+    ("/subscriptions/**", "subscriptions"),
+    ("/trash/**", "trash"),
+    ("/webhooks/**", "webhooks"),
+    ("/assistant/**", "assistant"),
+];
+
+/// A valid token and the user it acts for.
+pub struct AuthenticatedToken {
+    pub claims: Claims,
+    id: i32,
+    rate_limit_per_hour: i32,
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn generate_token() -> String {
+    let bytes: [u8; SECRET_BYTES] = rng().random();
+    format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        BASE32_NOPAD.encode(&bytes).to_lowercase()
+    )
+}
+
+fn token_response(row: &Row) -> ApiTokenResponse {
+    ApiTokenResponse {
+        id: row.get("id"),
+        name: row.get("name"),
+        token_prefix: row.get("token_prefix"),
+        scopes: row.get("scopes"),
+        rate_limit_per_hour: row.get("rate_limit_per_hour"),
+        expires_at: row.get("expires_at"),
+        last_used_at: row.get("last_used_at"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn route_matches(pattern: &str, path: &str) -> bool {
+    let mut segments = path.trim_end_matches('/').split('/');
+    for part in pattern.split('/') {
+        if part == "**" {
+            return true;
+        }
+        match segments.next() {
+            Some(segment) if part == "*" || part == segment => {}
+            _ => return false,
+        }
+    }
+    segments.next().is_none()
+}
+
+/// The scope an unsafe request to `path` needs, if tokens may make it at all.
+fn write_scope(path: &str) -> Option<&'static str> {
+    WRITE_ROUTES
+        .iter()
+        .find(|(pattern, _)| route_matches(pattern, path))
+        .map(|(_, scope)| *scope)
+}
+
+/// Whether a token with `scopes` may make this request at all. Permission-guarded routes
+/// additionally check the scopes through the authorities of the request.
+pub fn check_request(scopes: &[String], method: &Method, path: &str) -> Result<(), &'static str> {
+    if ACCOUNT_PATHS.iter().any(|prefix| path.starts_with(prefix)) {
+        return Err("API tokens cannot be used for account settings");
+    }
+    let required = if method.is_safe() {
+        READ_SCOPE
+    } else {
+        write_scope(path).ok_or("API tokens cannot be used for this action")?
+    };
+    if !scopes.iter().any(|scope| scope == required) {
+        return Err(if required == READ_SCOPE {
+            "API token lacks the read scope"
+        } else {
+            "API token lacks the scope for this action"
+        });
+    }
+    Ok(())
+}
+
+/// Looks up an unexpired, unrevoked token of an active user and notes its use.
+pub async fn authenticate(pool: &Pool, token: &str) -> AppResult<Option<AuthenticatedToken>> {
+    let client = pool.get().await?;
+    let Some(row) = client
+        .query_opt(
+            "SELECT t.id, t.scopes, t.rate_limit_per_hour, t.expires_at,
+                    u.userid, u.username, u.email, u.created_at, u.role::text AS role,
+                    u.email_confirmed, u.disabled
+             FROM api_tokens t
+             JOIN users u ON u.userid = t.user_id
+             WHERE t.token_hash = $1 AND t.revoked_at IS NULL AND t.expires_at > NOW()",
+            &[&hash_token(token)],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let id: i32 = row.get("id");
+    client
+        .execute(
+            "UPDATE api_tokens SET last_used_at = NOW()
+             WHERE id = $1
+               AND (last_used_at IS NULL
+                    OR last_used_at < NOW() - make_interval(secs => $2))",
+            &[&id, &f64::from(LAST_USED_RESOLUTION_SECS)],
+        )
+        .await?;
+
+    let disabled: bool = row.get("disabled");
+    let role: String = if disabled {
+        UserRole::Blocked.to_string()
+    } else {
+        row.get("role")
+    };
+    let expires_at: chrono::DateTime<Utc> = row.get("expires_at");
+    let created_at: chrono::DateTime<Utc> = row.get("created_at");
+    let scopes: Vec<String> = row.get("scopes");
+    Ok(Some(AuthenticatedToken {
+        claims: Claims {
+            sub: row.get("userid"),
+            exp: expires_at.timestamp(),
+            username: row.get("username"),
+            email: row.get("email"),
+            created_at: created_at.timestamp(),
+            role,
+            email_confirmed: row.get("email_confirmed"),
+            authorities: scopes.clone(),
+            sid: None,
+            mfa_at: None,
+            scopes: Some(scopes),
+        },
+        id,
+        rate_limit_per_hour: row.get("rate_limit_per_hour"),
+    }))
+}
+
+/// Counts a request against the token's hourly limit. Returns `false` if it is over the limit.
+pub async fn record_request(
+    redis_cache: &RedisCache,
+    token: &AuthenticatedToken,
+) -> Result<bool, redis::RedisError> {
+    let mut conn = redis_cache
+        .client
+        .get_multiplexed_async_connection()
+        .await?;
+    let key = format!("api_token_requests:{}", token.id);
+    // INCR and EXPIRE NX run as one MULTI/EXEC, so concurrent requests cannot all see the same
+    // count, and a dropped connection cannot leave a counter without an expiry that would lock
+    // the token out for good. NX keeps later requests from extending the window.
+    let (count,): (i64,) = redis::pipe()
+        .atomic()
+        .incr(&key, 1)
+        .cmd("EXPIRE")
+        .arg(&key)
+        .arg(RATE_LIMIT_WINDOW_SECS)
+        .arg("NX")
+        .ignore()
+        .query_async(&mut conn)
+        .await?;
+    Ok(count <= i64::from(token.rate_limit_per_hour))
+}
+
+/// `read`, the resource scopes and the permissions of the user's role: a token never grants
+/// more than its owner has.
+async fn available_scopes(perm_cache: &PermissionCache, claims: &Claims) -> Vec<String> {
+    let mut scopes = vec![READ_SCOPE.to_string()];
+    scopes.extend(RESOURCE_SCOPES.iter().map(|scope| scope.to_string()));
+    let mut permissions: Vec<String> = perm_cache
+        .get_permissions_for_role(claims.role.clone())
+        .await
+        .into_iter()
+        .map(|permission| permission.name)
+        .collect();
+    permissions.sort();
+    permissions.dedup();
+    scopes.extend(permissions);
+    scopes
+}
+
+fn ensure_session(claims: &Claims) -> AppResult<()> {
+    if claims.is_api_token() {
+        Err(AppError::Auth(
+            "API tokens cannot manage API tokens".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub async fn list_tokens(
+    pool: &Pool,
+    perm_cache: &PermissionCache,
+    claims: &Claims,
+) -> AppResult<ApiTokensResponse> {
+    ensure_session(claims)?;
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            "SELECT id, name, token_prefix, scopes, rate_limit_per_hour, expires_at,
+                    last_used_at, created_at
+             FROM api_tokens
+             WHERE user_id = $1 AND revoked_at IS NULL
+             ORDER BY created_at DESC",
+            &[&claims.sub],
+        )
+        .await?;
+    Ok(ApiTokensResponse {
+        tokens: rows.iter().map(token_response).collect(),
+        available_scopes: available_scopes(perm_cache, claims).await,
+    })
+}
+
+pub async fn create_token(
+    pool: &Pool,
+    perm_cache: &PermissionCache,
+    claims: &Claims,
+    request: &CreateApiTokenRequest,
+) -> AppResult<CreatedApiTokenResponse> {
+    ensure_session(claims)?;
+    let name = request.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::BadRequest(format!(
+            "Token name must be 1 to {} characters",
+            MAX_NAME_LEN
+        )));
+    }
+    let available = available_scopes(perm_cache, claims).await;
+    let mut scopes: Vec<String> = request
+        .scopes
+        .iter()
+        .map(|s| s.trim().to_string())
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(AppError::BadRequest(
+            "A token needs at least one scope".to_string(),
+        ));
+    }
+    if let Some(scope) = scopes.iter().find(|scope| !available.contains(scope)) {
+        return Err(AppError::BadRequest(format!(
+            "Scope not available to your role: {}",
+            scope
+        )));
+    }
+    if scopes
+        .iter()
+        .any(|scope| two_factor::is_destructive_permission(scope))
+    {
+        two_factor::require_step_up(pool, perm_cache, claims).await?;
+    }
+    let expires_in_days = request.expires_in_days.unwrap_or(DEFAULT_EXPIRY_DAYS);
+    if !(1..=MAX_EXPIRY_DAYS).contains(&expires_in_days) {
+        return Err(AppError::BadRequest(format!(
+            "Tokens expire after 1 to {} days",
+            MAX_EXPIRY_DAYS
+        )));
+    }
+    let rate_limit_per_hour = request
+        .rate_limit_per_hour
+        .unwrap_or(DEFAULT_RATE_LIMIT_PER_HOUR);
+    if !(1..=MAX_RATE_LIMIT_PER_HOUR).contains(&rate_limit_per_hour) {
+        return Err(AppError::BadRequest(format!(
+            "Rate limit must be 1 to {} requests per hour",
+            MAX_RATE_LIMIT_PER_HOUR
+        )));
+    }
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    // Serializes token creation per user so the limit holds
+    transaction
+        .execute(
+            "SELECT 1 FROM users WHERE userid = $1 FOR UPDATE",
+            &[&claims.sub],
+        )
+        .await?;
+    let active: i64 = transaction
+        .query_one(
+            "SELECT COUNT(*) FROM api_tokens
+             WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()",
+            &[&claims.sub],
+        )
+        .await?
+        .get(0);
+    if active >= MAX_ACTIVE_TOKENS {
+        return Err(AppError::BadRequest(format!(
+            "At most {} active tokens are allowed",
+            MAX_ACTIVE_TOKENS
+        )));
+    }
+
+    let secret = generate_token();
+    let token_prefix: String = secret.chars().take(DISPLAY_PREFIX_LEN).collect();
+    let expires_at = Utc::now() + Duration::days(expires_in_days);
+    let row = transaction
+        .query_one(
+            "INSERT INTO api_tokens
+                 (user_id, name, token_hash, token_prefix, scopes, rate_limit_per_hour, expires_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             RETURNING id, name, token_prefix, scopes, rate_limit_per_hour, expires_at,
+                       last_used_at, created_at",
+            &[
+                &claims.sub,
+                &name,
+                &hash_token(&secret),
+                &token_prefix,
+                &scopes,
+                &rate_limit_per_hour,
+                &expires_at,
+            ],
+        )
+        .await?;
+    transaction.commit().await?;
+
+    Ok(CreatedApiTokenResponse {
+        token: token_response(&row),
+        secret,
+    })
+}
+
+pub async fn revoke_token(pool: &Pool, claims: &Claims, token_id: i32) -> AppResult<()> {
+    ensure_session(claims)?;
+    let client = pool.get().await?;
+    let revoked = client
+        .execute(
+            "UPDATE api_tokens SET revoked_at = NOW()
+             WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+            &[&token_id, &claims.sub],
+        )
+        .await?;
+    if revoked == 0 {
+        return Err(AppError::NotFound("API token not found".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scopes(scopes: &[&str]) -> Vec<String> {
+        scopes.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn generated_tokens_are_recognised() {
+        let token = generate_token();
+        assert!(is_api_token(&token));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + 32);
+        assert_ne!(token, generate_token());
+        assert!(!is_api_token("eyJhbGciOiJIUzI1NiJ9.e30.sig"));
+        assert_eq!(hash_token(&token).len(), 64);
+    }
+
+    #[test]
+    fn scopes_gate_methods_and_paths() {
+        let read_only = scopes(&["read"]);
+        let editor = scopes(&["edit_definition"]);
+        let both = scopes(&["edit_definition", "read"]);
+
+        assert!(check_request(&read_only, &Method::GET, "/jbovlaste/words").is_ok());
+        assert!(check_request(&read_only, &Method::POST, "/comments").is_err());
+        assert!(check_request(&editor, &Method::GET, "/jbovlaste/words").is_err());
+        assert!(check_request(&editor, &Method::PUT, "/jbovlaste/valsi/1").is_ok());
+        assert!(check_request(&both, &Method::GET, "/auth/profile").is_err());
+        assert!(check_request(&both, &Method::DELETE, "/api/sessions/3").is_err());
+    }
+
+    #[test]
+    fn unsafe_requests_need_the_scope_of_their_route() {
+        let editor = scopes(&["edit_definition"]);
+        let collections = scopes(&["collections"]);
+        let read_only = scopes(&["read"]);
+
+        assert!(check_request(&editor, &Method::POST, "/collections/3/items").is_err());
+        assert!(check_request(&editor, &Method::DELETE, "/webhooks/2").is_err());
+        assert!(check_request(&editor, &Method::POST, "/trash/comment/9/restore").is_err());
+        assert!(check_request(&editor, &Method::POST, "/jbovlaste/valsi").is_err());
+        assert!(check_request(&editor, &Method::POST, "/jbovlaste/valsi/4/wiki/rename").is_ok());
+        assert!(check_request(&collections, &Method::POST, "/collections/3/items").is_ok());
+        assert!(check_request(&collections, &Method::PUT, "/collections/3").is_ok());
+        assert!(check_request(&collections, &Method::POST, "/flashcards/3").is_err());
+        assert!(check_request(&collections, &Method::POST, "/reports/5/resolve").is_err());
+        assert!(check_request(&read_only, &Method::POST, "/language/parse_lojban").is_ok());
+        // Routes nobody mapped are refused whatever the scopes
+        assert!(check_request(&editor, &Method::POST, "/users/profile-image").is_err());
+        assert!(check_request(&collections, &Method::POST, "/messaging/threads").is_err());
+    }
+
+    #[test]
+    fn route_patterns_match_whole_segments() {
+        assert!(route_matches("/jbovlaste/valsi", "/jbovlaste/valsi"));
+        assert!(!route_matches("/jbovlaste/valsi", "/jbovlaste/valsi/1"));
+        assert!(route_matches("/jbovlaste/valsi/*", "/jbovlaste/valsi/1"));
+        assert!(!route_matches("/jbovlaste/vote", "/jbovlaste/votes"));
+        assert!(route_matches("/collections/**", "/collections"));
+        assert!(route_matches(
+            "/collections/**",
+            "/collections/1/items/2/notes"
+        ));
+        assert!(!route_matches("/collections/**", "/collections-export"));
+    }
+}
@@ -1,7 +1,13 @@
+use crate::audit::AuditContext;
+use crate::auth::api_tokens;
+use crate::auth::models::LoginOutcome;
 use crate::auth::permissions::PermissionCache;
+use crate::auth::two_factor;
 use crate::sessions;
 use crate::AppError;
-use actix_web::{delete, get, http::Error, post, put, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{
+    delete, get, http::Error, post, put, web, HttpRequest, HttpResponse, Responder, ResponseError,
+};
 use actix_web_grants::protect;
 use deadpool_postgres::Pool;
 use serde_json::json;
@@ -12,6 +18,7 @@ use crate::{
         email_confirmation, service, Claims, CompletePasswordChangeRequest,
         EmailConfirmationRequest, FollowRequest, RefreshTokenRequest, ResendConfirmationRequest,
     },
+    middleware::cache::RedisCache,
     middleware::limiter::{EmailConfirmationLimiter, LoginLimiter, PasswordResetLimiter},
 };
 
@@ -104,10 +111,11 @@ pub async fn create_role(
     perm_cache: web::Data<PermissionCache>,
     request: web::Json<CreateRoleRequest>,
     claims: Claims,
+    audit: AuditContext,
 ) -> impl Responder {
-    match service::create_role(&pool, &request, &claims.role.to_string()).await {
+    match service::create_role(&pool, &audit, &request, &claims.role.to_string()).await {
         Ok(role) => {
-            if let Err(e) = perm_cache.load_permissions().await {
+            if let Err(e) = perm_cache.invalidate().await {
                 return HttpResponse::InternalServerError().json(json!({
                     "error": format!("Failed to reload permissions: {}", e)
                 }));
@@ -144,10 +152,19 @@ pub async fn update_role(
     role_name: web::Path<String>,
     request: web::Json<UpdateRoleRequest>,
     claims: Claims,
+    audit: AuditContext,
 ) -> impl Responder {
-    match service::update_role(&pool, &role_name, &request, &claims.role.to_string()).await {
+    match service::update_role(
+        &pool,
+        &audit,
+        &role_name,
+        &request,
+        &claims.role.to_string(),
+    )
+    .await
+    {
         Ok(role) => {
-            if let Err(e) = perm_cache.load_permissions().await {
+            if let Err(e) = perm_cache.invalidate().await {
                 return HttpResponse::InternalServerError().json(json!({
                     "error": format!("Failed to reload permissions: {}", e)
                 }));
@@ -181,10 +198,11 @@ pub async fn delete_role(
     pool: web::Data<Pool>,
     perm_cache: web::Data<PermissionCache>,
     role_name: web::Path<String>,
+    audit: AuditContext,
 ) -> impl Responder {
-    match service::delete_role(&pool, &role_name).await {
+    match service::delete_role(&pool, &audit, &role_name).await {
         Ok(_) => {
-            if let Err(e) = perm_cache.load_permissions().await {
+            if let Err(e) = perm_cache.invalidate().await {
                 return HttpResponse::InternalServerError().json(json!({
                     "success": false,
                     "error": format!("Failed to reload permissions: {}", e)
@@ -220,19 +238,30 @@ pub async fn delete_role(
 #[post("/block-user")]
 pub async fn block_user(
     pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    redis_cache: web::Data<RedisCache>,
     claims: Claims,
+    audit: AuditContext,
     request: web::Json<BlockUserRequest>,
 ) -> impl Responder {
-    match service::block_user(&pool, claims.sub, request.user_id, request.block).await {
-        Ok(_) => HttpResponse::Ok().json(BlockUserResponse {
-            success: true,
-            message: if request.block {
-                "User blocked successfully"
-            } else {
-                "User unblocked successfully"
+    if let Err(e) = two_factor::require_step_up(&pool, &perm_cache, &claims).await {
+        return e.error_response();
+    }
+    match service::block_user(&pool, &redis_cache, &audit, request.user_id, request.block).await {
+        Ok(_) => {
+            if let Err(e) = perm_cache.invalidate().await {
+                log::error!("Failed to reload permissions after blocking a user: {}", e);
             }
-            .to_string(),
-        }),
+            HttpResponse::Ok().json(BlockUserResponse {
+                success: true,
+                message: if request.block {
+                    "User blocked successfully"
+                } else {
+                    "User unblocked successfully"
+                }
+                .to_string(),
+            })
+        }
         Err(e) => {
             let error_msg = e.to_string();
             if error_msg.contains("Insufficient permissions") {
@@ -273,14 +302,24 @@ pub async fn block_user(
 #[post("/assign-role")]
 pub async fn assign_role(
     pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
     claims: Claims,
+    audit: AuditContext,
     request: web::Json<AssignRoleRequest>,
 ) -> impl Responder {
-    match service::assign_role(&pool, claims.sub, request.user_id, request.role.clone()).await {
-        Ok(_) => HttpResponse::Ok().json(AssignRoleResponse {
-            success: true,
-            message: "Role assigned successfully".to_string(),
-        }),
+    if let Err(e) = two_factor::require_step_up(&pool, &perm_cache, &claims).await {
+        return e.error_response();
+    }
+    match service::assign_role(&pool, &audit, request.user_id, request.role.clone()).await {
+        Ok(_) => {
+            if let Err(e) = perm_cache.invalidate().await {
+                log::error!("Failed to reload permissions after assigning a role: {}", e);
+            }
+            HttpResponse::Ok().json(AssignRoleResponse {
+                success: true,
+                message: "Role assigned successfully".to_string(),
+            })
+        }
         Err(e) => {
             let error_msg = e.to_string();
             if error_msg.contains("Insufficient permissions") {
@@ -386,17 +425,19 @@ pub async fn confirm_email(
     path = "/auth/login",
     request_body = LoginRequest,
     responses(
-        (status = 200, description = "Login successful", body = AuthResponse),
+        (status = 200, description = "Login successful, or a second factor is required", body = AuthResponse),
         (status = 401, description = "Invalid credentials", body = String),
         (status = 429, description = "Too many login attempts", body = String),
         (status = 500, description = "Internal server error")
     ),
     summary = "Authenticate user",
-    description = "Authenticate a user with their credentials and receive access and refresh tokens. Rate limited by IP and by failed attempts per identifier."
+    description = "Authenticate a user with their credentials and receive access and refresh tokens. Rate limited by IP and by failed attempts per identifier. \
+                  If the account has a second factor, the response is a `SecondFactorChallenge` instead; finish with `POST /auth/login/2fa`."
 )]
 #[post("/login")]
 pub async fn login(
     pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
     login_limiter: web::Data<LoginLimiter>,
     user_data: web::Json<LoginRequest>,
     req: actix_web::HttpRequest,
@@ -431,11 +472,12 @@ pub async fn login(
             .json("Too many failed attempts for this account. Please try again later."));
     }
 
-    match service::login(&pool, &user_data, ip_address, user_agent).await {
-        Ok(token_pair) => Ok(HttpResponse::Ok().json(json!({
+    match service::login(&pool, &redis_cache, &user_data, ip_address, user_agent).await {
+        Ok(LoginOutcome::Tokens(token_pair)) => Ok(HttpResponse::Ok().json(json!({
             "access_token": token_pair.access_token,
             "refresh_token": token_pair.refresh_token
         }))),
+        Ok(LoginOutcome::SecondFactorRequired(challenge)) => Ok(HttpResponse::Ok().json(challenge)),
         Err(_) => {
             if login_limiter
                 .record_failed_login(&user_data.username_or_email)
@@ -781,15 +823,19 @@ pub async fn logout(pool: web::Data<Pool>, claims: Claims) -> Result<HttpRespons
     ),
     security(("bearer_auth" = [])),
     summary = "Complete password change",
-    description = "Complete the password change process by verifying the code sent via email and setting the new password"
+    description = "Complete the password change process by verifying the code sent via email and setting the new password. Every other session of the user is revoked."
 )]
 #[post("/change-password/complete")]
 pub async fn complete_password_change(
     pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
     claims: Claims,
     request: web::Json<CompletePasswordChangeRequest>,
 ) -> impl Responder {
-    match service::complete_password_change(&pool, claims.sub, &request).await {
+    // Whoever else knew the old password is signed out in the same transaction
+    match service::complete_password_change(&pool, &redis_cache, claims.sub, claims.sid, &request)
+        .await
+    {
         Ok(response) => HttpResponse::Ok().json(response),
         Err(e) => {
             if e.to_string().contains("Invalid verification") {
@@ -805,6 +851,419 @@ pub async fn complete_password_change(
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/login/2fa",
+    tag = "auth",
+    request_body = SecondFactorLoginRequest,
+    responses(
+        (status = 200, description = "Login completed", body = inline(serde_json::Value), example = json!({"access_token": "access_token", "refresh_token": "refresh_token"})),
+        (status = 400, description = "Not exactly one of code, recovery_code or passkey"),
+        (status = 401, description = "Invalid second factor, or the login expired"),
+        (status = 429, description = "Too many attempts")
+    ),
+    summary = "Finish a two-factor login",
+    description = "Confirms the `mfa_token` returned by password or OAuth login with a TOTP code, an unused recovery code, \
+                  or a passkey assertion for the options from `POST /auth/login/2fa/passkey/options`. \
+                  Failures count towards the same per-account limit as wrong passwords."
+)]
+#[post("/login/2fa")]
+pub async fn login_second_factor(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    login_limiter: web::Data<LoginLimiter>,
+    request: web::Json<SecondFactorLoginRequest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let (ip_address, user_agent) = client_meta(&req);
+    if !login_limiter
+        .check_and_record_attempt(&ip_address)
+        .await
+        .unwrap_or(false)
+    {
+        return Ok(HttpResponse::TooManyRequests()
+            .json("Too many login attempts from this IP. Please try again later."));
+    }
+
+    let pending = two_factor::pending_login(&redis_cache, &request.mfa_token).await?;
+    if login_limiter
+        .is_identifier_over_failure_limit(&pending.identifier)
+        .await
+        .unwrap_or(false)
+    {
+        return Ok(HttpResponse::TooManyRequests()
+            .json("Too many failed attempts for this account. Please try again later."));
+    }
+
+    match two_factor::complete_login(
+        &pool,
+        &redis_cache,
+        &pending,
+        &request,
+        ip_address,
+        user_agent,
+    )
+    .await
+    {
+        Ok(token_pair) => Ok(HttpResponse::Ok().json(json!({
+            "access_token": token_pair.access_token,
+            "refresh_token": token_pair.refresh_token
+        }))),
+        Err(e) => {
+            if login_limiter
+                .record_failed_login(&pending.identifier)
+                .await
+                .is_err()
+            {
+                log::warn!("Failed to record login failure for rate limiting");
+            }
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/login/2fa/passkey/options",
+    tag = "auth",
+    request_body = PasskeyLoginOptionsRequest,
+    responses(
+        (status = 200, description = "WebAuthn request options for navigator.credentials.get", body = inline(serde_json::Value)),
+        (status = 400, description = "The account has no passkeys"),
+        (status = 401, description = "The login expired")
+    ),
+    summary = "Passkey options for a two-factor login"
+)]
+#[post("/login/2fa/passkey/options")]
+pub async fn login_passkey_options(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    request: web::Json<PasskeyLoginOptionsRequest>,
+) -> Result<HttpResponse, AppError> {
+    let options =
+        two_factor::passkey_login_options(&pool, &redis_cache, &request.mfa_token).await?;
+    Ok(HttpResponse::Ok().json(options))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/2fa",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Second factors of the current user", body = TwoFactorStatusResponse),
+        (status = 401, description = "Unauthorized")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Two-factor status",
+    description = "Lists the user's second factors. `step_up_required` tells whether the user's role needs a second factor \
+                  confirmed in the last 15 minutes for destructive actions such as assigning roles, blocking users, \
+                  reverting versions or deleting bulk imports."
+)]
+#[get("/2fa")]
+pub async fn two_factor_status(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+) -> Result<HttpResponse, AppError> {
+    let status = two_factor::status(&pool, &perm_cache, &claims).await?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/totp",
+    tag = "auth",
+    responses(
+        (status = 200, description = "New TOTP secret, enabled once confirmed", body = TotpSetupResponse),
+        (status = 400, description = "TOTP is already enabled"),
+        (status = 403, description = "The account has a second factor that was not confirmed recently")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Start TOTP setup"
+)]
+#[post("/2fa/totp")]
+pub async fn begin_totp_setup(
+    pool: web::Data<Pool>,
+    claims: Claims,
+) -> Result<HttpResponse, AppError> {
+    let setup = two_factor::begin_totp_setup(&pool, &claims).await?;
+    Ok(HttpResponse::Ok().json(setup))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/totp/confirm",
+    tag = "auth",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "TOTP enabled; recovery codes are only shown here", body = RecoveryCodesResponse),
+        (status = 400, description = "No TOTP setup in progress"),
+        (status = 401, description = "Invalid code")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Confirm TOTP setup",
+    description = "Enables TOTP with a code from the authenticator app and replaces the user's recovery codes."
+)]
+#[post("/2fa/totp/confirm")]
+pub async fn confirm_totp_setup(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    request: web::Json<TotpCodeRequest>,
+) -> Result<HttpResponse, AppError> {
+    let codes = two_factor::confirm_totp_setup(&pool, &claims, &request.code).await?;
+    Ok(HttpResponse::Ok().json(codes))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/2fa/totp",
+    tag = "auth",
+    responses(
+        (status = 204, description = "TOTP disabled"),
+        (status = 403, description = "No second factor confirmed recently"),
+        (status = 404, description = "TOTP is not enabled")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Disable TOTP"
+)]
+#[delete("/2fa/totp")]
+pub async fn disable_totp(pool: web::Data<Pool>, claims: Claims) -> Result<HttpResponse, AppError> {
+    two_factor::disable_totp(&pool, &claims).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/recovery-codes",
+    tag = "auth",
+    responses(
+        (status = 200, description = "New recovery codes; the previous ones stop working", body = RecoveryCodesResponse),
+        (status = 400, description = "No second factor set up"),
+        (status = 403, description = "No second factor confirmed recently")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Regenerate recovery codes"
+)]
+#[post("/2fa/recovery-codes")]
+pub async fn regenerate_recovery_codes(
+    pool: web::Data<Pool>,
+    claims: Claims,
+) -> Result<HttpResponse, AppError> {
+    let codes = two_factor::regenerate_recovery_codes(&pool, &claims).await?;
+    Ok(HttpResponse::Ok().json(codes))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/passkeys/options",
+    tag = "auth",
+    responses(
+        (status = 200, description = "WebAuthn creation options for navigator.credentials.create", body = inline(serde_json::Value)),
+        (status = 403, description = "The account has a second factor that was not confirmed recently")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Passkey registration options"
+)]
+#[post("/2fa/passkeys/options")]
+pub async fn passkey_registration_options(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    claims: Claims,
+) -> Result<HttpResponse, AppError> {
+    let options = two_factor::passkey_registration_options(&pool, &redis_cache, &claims).await?;
+    Ok(HttpResponse::Ok().json(options))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/passkeys",
+    tag = "auth",
+    request_body = PasskeyRegistrationRequest,
+    responses(
+        (status = 201, description = "Passkey registered", body = PasskeyRegisteredResponse),
+        (status = 400, description = "The registration request expired"),
+        (status = 401, description = "The attestation was rejected")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Register a passkey",
+    description = "Stores a passkey for the options from `POST /auth/2fa/passkeys/options`. \
+                  Recovery codes are returned when this is the user's first second factor."
+)]
+#[post("/2fa/passkeys")]
+pub async fn register_passkey(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    claims: Claims,
+    request: web::Json<PasskeyRegistrationRequest>,
+) -> Result<HttpResponse, AppError> {
+    let registered = two_factor::register_passkey(&pool, &redis_cache, &claims, &request).await?;
+    Ok(HttpResponse::Created().json(registered))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/2fa/passkeys/{id}",
+    tag = "auth",
+    params(("id" = i32, Path, description = "Passkey ID")),
+    responses(
+        (status = 204, description = "Passkey removed"),
+        (status = 403, description = "No second factor confirmed recently"),
+        (status = 404, description = "Passkey not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Remove a passkey"
+)]
+#[delete("/2fa/passkeys/{id}")]
+pub async fn delete_passkey(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    two_factor::delete_passkey(&pool, &claims, id.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/step-up/passkey/options",
+    tag = "auth",
+    responses(
+        (status = 200, description = "WebAuthn request options for navigator.credentials.get", body = inline(serde_json::Value)),
+        (status = 400, description = "The account has no passkeys")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Passkey options for step-up"
+)]
+#[post("/2fa/step-up/passkey/options")]
+pub async fn step_up_passkey_options(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    claims: Claims,
+) -> Result<HttpResponse, AppError> {
+    let options = two_factor::passkey_step_up_options(&pool, &redis_cache, claims.sub).await?;
+    Ok(HttpResponse::Ok().json(options))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/step-up",
+    tag = "auth",
+    request_body = SecondFactorProof,
+    responses(
+        (status = 200, description = "Tokens for the current session recording the second factor", body = inline(serde_json::Value), example = json!({"access_token": "access_token", "refresh_token": "refresh_token"})),
+        (status = 401, description = "Invalid second factor"),
+        (status = 429, description = "Too many failed attempts")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Confirm a second factor",
+    description = "Confirms a second factor without logging in again and returns a new token pair for the same session. \
+                  Destructive actions are allowed for 15 minutes afterwards."
+)]
+#[post("/2fa/step-up")]
+pub async fn step_up(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    login_limiter: web::Data<LoginLimiter>,
+    claims: Claims,
+    proof: web::Json<SecondFactorProof>,
+) -> Result<HttpResponse, AppError> {
+    if login_limiter
+        .is_identifier_over_failure_limit(&claims.username)
+        .await
+        .unwrap_or(false)
+    {
+        return Ok(HttpResponse::TooManyRequests()
+            .json("Too many failed attempts for this account. Please try again later."));
+    }
+
+    match two_factor::step_up(&pool, &redis_cache, &claims, &proof).await {
+        Ok(token_pair) => Ok(HttpResponse::Ok().json(json!({
+            "access_token": token_pair.access_token,
+            "refresh_token": token_pair.refresh_token
+        }))),
+        Err(e) => {
+            if login_limiter
+                .record_failed_login(&claims.username)
+                .await
+                .is_err()
+            {
+                log::warn!("Failed to record step-up failure for rate limiting");
+            }
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/tokens",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Personal API tokens of the current user", body = ApiTokensResponse),
+        (status = 401, description = "Unauthorized")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "List personal API tokens",
+    description = "Lists the user's unrevoked API tokens (without their secrets) and the scopes new tokens may have."
+)]
+#[get("/tokens")]
+pub async fn list_api_tokens(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+) -> Result<HttpResponse, AppError> {
+    let tokens = api_tokens::list_tokens(&pool, &perm_cache, &claims).await?;
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/tokens",
+    tag = "auth",
+    request_body = CreateApiTokenRequest,
+    responses(
+        (status = 201, description = "Token created; the secret is not shown again", body = CreatedApiTokenResponse),
+        (status = 400, description = "Invalid name, scopes, expiry or rate limit, or too many tokens")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Create a personal API token",
+    description = "Creates a long-lived token for bots and scripts. It acts as the user, limited to its scopes: \
+                  `read` allows GET requests, permission names allow the actions they guard. Tokens are accepted \
+                  wherever an access token is, except for account settings."
+)]
+#[post("/tokens")]
+pub async fn create_api_token(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    request: web::Json<CreateApiTokenRequest>,
+) -> Result<HttpResponse, AppError> {
+    let token = api_tokens::create_token(&pool, &perm_cache, &claims, &request).await?;
+    Ok(HttpResponse::Created().json(token))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/tokens/{id}",
+    tag = "auth",
+    params(("id" = i32, Path, description = "API token ID")),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 404, description = "Token not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Revoke a personal API token"
+)]
+#[delete("/tokens/{id}")]
+pub async fn revoke_api_token(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    api_tokens::revoke_token(&pool, &claims, id.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
 fn client_meta(req: &actix_web::HttpRequest) -> (String, String) {
     let ip_address = req
         .connection_info()
@@ -857,14 +1316,11 @@ fn oauth_error_response(err: AppError) -> HttpResponse {
         (status = 200, description = "Configured social login providers", body = OAuthProvidersResponse)
     ),
     summary = "List configured OAuth providers",
-    description = "Returns provider ids (github, google, …) that have complete server environment configuration. The frontend should only render buttons for these providers."
+    description = "Returns provider ids (github, google, and OpenID Connect issuers from OIDC_PROVIDERS) that have complete server environment configuration. The frontend should only render buttons for these providers."
 )]
 #[get("/oauth/providers")]
 pub async fn list_oauth_providers() -> impl Responder {
-    let providers: Vec<String> = crate::auth::oauth::configured_providers()
-        .into_iter()
-        .map(str::to_string)
-        .collect();
+    let providers = crate::auth::oauth::configured_providers();
     HttpResponse::Ok().json(OAuthProvidersResponse { providers })
 }
 
@@ -873,7 +1329,7 @@ pub async fn list_oauth_providers() -> impl Responder {
     path = "/auth/oauth/{provider}/authorize",
     tag = "auth",
     params(
-        ("provider" = String, Path, description = "OAuth provider id (github or google)"),
+        ("provider" = String, Path, description = "OAuth provider id (github, google or a configured OIDC provider)"),
         ("return_to" = Option<String>, Query, description = "Optional in-app path to return to after login")
     ),
     responses(
@@ -889,7 +1345,7 @@ pub async fn oauth_authorize(
     path: web::Path<String>,
     query: web::Query<OAuthAuthorizeQuery>,
 ) -> impl Responder {
-    match crate::auth::oauth::authorize_url(&path, query.return_to.as_deref()) {
+    match crate::auth::oauth::authorize_url(&path, query.return_to.as_deref()).await {
         Ok(result) => HttpResponse::Ok().json(OAuthAuthorizeResponse {
             authorize_url: result.authorize_url,
         }),
@@ -902,7 +1358,7 @@ pub async fn oauth_authorize(
     path = "/auth/oauth/{provider}",
     tag = "auth",
     params(
-        ("provider" = String, Path, description = "OAuth provider id (github or google)")
+        ("provider" = String, Path, description = "OAuth provider id (github, google or a configured OIDC provider)")
     ),
     request_body = OAuthCompleteRequest,
     responses(
@@ -911,11 +1367,12 @@ pub async fn oauth_authorize(
         (status = 503, description = "Provider is not configured")
     ),
     summary = "Complete OAuth sign-in",
-    description = "Exchanges the authorization code, links or creates the local account, and returns the same access/refresh token pair as password login."
+    description = "Exchanges the authorization code, links or creates the local account, and returns the same access/refresh token pair as password login. Accounts with a second factor get `two_factor` instead of tokens; finish with `POST /auth/login/2fa`."
 )]
 #[post("/oauth/{provider}")]
 pub async fn oauth_complete(
     pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
     path: web::Path<String>,
     body: web::Json<OAuthCompleteRequest>,
     request: actix_web::HttpRequest,
@@ -923,6 +1380,7 @@ pub async fn oauth_complete(
     let (ip_address, user_agent) = client_meta(&request);
     match crate::auth::oauth::complete_oauth(
         &pool,
+        &redis_cache,
         &path,
         &body.code,
         &body.state,
@@ -936,7 +1394,116 @@ pub async fn oauth_complete(
             refresh_token: result.refresh_token,
             username: result.username,
             return_to: result.return_to,
+            two_factor: result.two_factor,
+        }),
+        Err(e) => oauth_error_response(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/oauth/identities",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Identities linked to the current user", body = OAuthIdentitiesResponse),
+        (status = 401, description = "Unauthorized")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "List linked OAuth identities"
+)]
+#[get("/oauth/identities")]
+pub async fn list_oauth_identities(pool: web::Data<Pool>, claims: Claims) -> impl Responder {
+    match crate::auth::oauth::list_identities(&pool, claims.sub).await {
+        Ok(identities) => HttpResponse::Ok().json(OAuthIdentitiesResponse { identities }),
+        Err(e) => oauth_error_response(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/oauth/{provider}/link",
+    tag = "auth",
+    params(
+        ("provider" = String, Path, description = "OAuth provider id (github, google or a configured OIDC provider)"),
+        ("return_to" = Option<String>, Query, description = "Optional in-app path to return to after linking")
+    ),
+    responses(
+        (status = 200, description = "Authorization URL for the identity provider", body = OAuthAuthorizeResponse),
+        (status = 404, description = "Unknown provider"),
+        (status = 503, description = "Provider is not configured")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Start linking an OAuth identity",
+    description = "Like `GET /auth/oauth/{provider}/authorize`, but the state is bound to the current user. \
+                  Finish with `POST /auth/oauth/{provider}/link` instead of the sign-in endpoint."
+)]
+#[get("/oauth/{provider}/link")]
+pub async fn oauth_link_authorize(
+    path: web::Path<String>,
+    query: web::Query<OAuthAuthorizeQuery>,
+    claims: Claims,
+) -> impl Responder {
+    match crate::auth::oauth::link_authorize_url(&path, claims.sub, query.return_to.as_deref())
+        .await
+    {
+        Ok(result) => HttpResponse::Ok().json(OAuthAuthorizeResponse {
+            authorize_url: result.authorize_url,
         }),
         Err(e) => oauth_error_response(e),
     }
 }
+
+#[utoipa::path(
+    post,
+    path = "/auth/oauth/{provider}/link",
+    tag = "auth",
+    params(
+        ("provider" = String, Path, description = "OAuth provider id (github, google or a configured OIDC provider)")
+    ),
+    request_body = OAuthCompleteRequest,
+    responses(
+        (status = 200, description = "Identity linked to the current user", body = OAuthIdentityResponse),
+        (status = 400, description = "Invalid code or state, or the identity belongs to another account"),
+        (status = 503, description = "Provider is not configured")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Link an OAuth identity"
+)]
+#[post("/oauth/{provider}/link")]
+pub async fn oauth_link(
+    pool: web::Data<Pool>,
+    path: web::Path<String>,
+    body: web::Json<OAuthCompleteRequest>,
+    claims: Claims,
+) -> impl Responder {
+    match crate::auth::oauth::link_identity(&pool, claims.sub, &path, &body.code, &body.state).await
+    {
+        Ok(identity) => HttpResponse::Ok().json(identity),
+        Err(e) => oauth_error_response(e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/oauth/identities/{id}",
+    tag = "auth",
+    params(("id" = i32, Path, description = "Linked identity ID")),
+    responses(
+        (status = 204, description = "Identity unlinked"),
+        (status = 400, description = "It is the last way to sign in to an account without a password"),
+        (status = 404, description = "Identity not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Unlink an OAuth identity"
+)]
+#[delete("/oauth/identities/{id}")]
+pub async fn oauth_unlink(
+    pool: web::Data<Pool>,
+    id: web::Path<i32>,
+    claims: Claims,
+) -> impl Responder {
+    match crate::auth::oauth::unlink_identity(&pool, claims.sub, id.into_inner()).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => oauth_error_response(e),
+    }
+}
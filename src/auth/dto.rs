@@ -1,5 +1,7 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use webauthn_rs::prelude::{PublicKeyCredential, RegisterPublicKeyCredential};
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct SignupRequest {
@@ -196,8 +198,151 @@ pub struct OAuthCompleteRequest {
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct OAuthCompleteResponse {
-    pub access_token: String,
-    pub refresh_token: String,
+    /// Absent when `two_factor` is set
+    pub access_token: Option<String>,
+    pub refresh_token: Option<String>,
     pub username: String,
     pub return_to: Option<String>,
+    /// Set when the account has a second factor; finish with `POST /auth/login/2fa`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub two_factor: Option<SecondFactorChallenge>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthIdentityResponse {
+    pub id: i32,
+    pub provider: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthIdentitiesResponse {
+    pub identities: Vec<OAuthIdentityResponse>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    /// `read`, resource scopes such as `collections` and/or permission names of your role, see
+    /// `available_scopes`
+    pub scopes: Vec<String>,
+    /// Defaults to 90, at most 365
+    pub expires_in_days: Option<i64>,
+    /// Defaults to 1000
+    pub rate_limit_per_hour: Option<i32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiTokenResponse {
+    pub id: i32,
+    pub name: String,
+    /// First characters of the token, to tell tokens apart
+    pub token_prefix: String,
+    pub scopes: Vec<String>,
+    pub rate_limit_per_hour: i32,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedApiTokenResponse {
+    #[serde(flatten)]
+    pub token: ApiTokenResponse,
+    /// Shown only once; send it as `Authorization: Bearer <secret>`
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiTokensResponse {
+    pub tokens: Vec<ApiTokenResponse>,
+    /// Scopes new tokens may have
+    pub available_scopes: Vec<String>,
+}
+
+/// Returned by login instead of tokens when the account has a second factor.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SecondFactorChallenge {
+    pub two_factor_required: bool,
+    /// Identifies the half-finished login in `POST /auth/login/2fa`
+    pub mfa_token: String,
+    /// Any of `totp`, `passkey` and `recovery_code`
+    pub methods: Vec<String>,
+    /// Seconds until `mfa_token` expires
+    pub expires_in: u64,
+}
+
+/// Proof of a second factor. Exactly one of the fields is expected.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SecondFactorProof {
+    /// Six-digit code from an authenticator app
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+    /// Assertion for the options returned by the matching `passkey/options` endpoint
+    #[schema(value_type = Option<Object>)]
+    pub passkey: Option<PublicKeyCredential>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SecondFactorLoginRequest {
+    pub mfa_token: String,
+    #[serde(flatten)]
+    pub proof: SecondFactorProof,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PasskeyLoginOptionsRequest {
+    pub mfa_token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TwoFactorStatusResponse {
+    pub totp_enabled: bool,
+    pub passkeys: Vec<PasskeyResponse>,
+    pub recovery_codes_remaining: i64,
+    /// Whether destructive actions need a recent second factor: the role holds a destructive
+    /// permission and the user has a second factor
+    pub step_up_required: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TotpSetupResponse {
+    /// Base32 secret for manual entry
+    pub secret: String,
+    /// `otpauth://` URI to show as a QR code
+    pub otpauth_url: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RecoveryCodesResponse {
+    /// Shown once; each code can be used a single time instead of a second factor
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PasskeyResponse {
+    pub id: i32,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PasskeyRegistrationRequest {
+    pub name: Option<String>,
+    /// Attestation for the options returned by `POST /auth/2fa/passkeys/options`
+    #[schema(value_type = Object)]
+    pub credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PasskeyRegisteredResponse {
+    pub passkey: PasskeyResponse,
+    /// Set when this is the user's first second factor
+    pub recovery_codes: Option<Vec<String>>,
 }
@@ -8,7 +8,7 @@ pub async fn extract_authorities(req: &ServiceRequest) -> Result<HashSet<String>
 
     // Get claims from request extensions
     // Extract claims data before await
-    let role = if let Some(claims) = req.extensions().get::<Claims>() {
+    let (role, scopes) = if let Some(claims) = req.extensions().get::<Claims>() {
         debug!("Processing claims for user: {}", claims.username);
 
         // Add email confirmation status
@@ -16,18 +16,24 @@ pub async fn extract_authorities(req: &ServiceRequest) -> Result<HashSet<String>
             authorities.insert("UNCONFIRMED".to_string());
         }
 
-        Some(claims.role.clone())
+        (Some(claims.role.clone()), claims.scopes.clone())
     } else {
         debug!("No claims found in request");
-        None
+        (None, None)
     };
 
     // Add permissions based on role if we have one
     if let Some(role) = role {
         if let Some(perm_cache) = req.app_data::<web::Data<PermissionCache>>() {
             let permissions = perm_cache.get_permissions_for_role(role).await;
+            // A personal API token only carries the permissions it was scoped to
             for permission in permissions {
-                authorities.insert(permission.name);
+                if scopes
+                    .as_ref()
+                    .is_none_or(|scopes| scopes.contains(&permission.name))
+                {
+                    authorities.insert(permission.name);
+                }
             }
         } else {
             debug!("Permission cache not found in app data");
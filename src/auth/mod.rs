@@ -1,3 +1,4 @@
+pub mod api_tokens;
 pub mod controller;
 pub mod dto;
 mod email_confirmation;
@@ -5,15 +6,18 @@ pub mod error;
 pub mod extractor;
 pub mod models;
 pub mod oauth;
+mod oidc;
+pub mod passkeys;
 pub mod permissions;
 pub mod service;
+pub mod two_factor;
 
 use actix_web::web;
 use actix_web_grants::GrantsMiddleware;
 use actix_web_httpauth::middleware::HttpAuthentication;
 pub use dto::*;
 use extractor::extract_authorities;
-pub use models::{decode_token, Claims, User};
+pub use models::{Claims, User};
 pub use service::*;
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
@@ -21,6 +25,8 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             // Public routes (no auth required)
             .service(controller::signup)
             .service(controller::login)
+            .service(controller::login_second_factor)
+            .service(controller::login_passkey_options)
             .service(controller::logout)
             .service(controller::refresh_token)
             .service(controller::restore_password)
@@ -46,7 +52,24 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
                     .service(controller::create_role)
                     .service(controller::update_role)
                     .service(controller::delete_role)
-                    .service(controller::get_permissions),
+                    .service(controller::get_permissions)
+                    .service(controller::list_api_tokens)
+                    .service(controller::create_api_token)
+                    .service(controller::revoke_api_token)
+                    .service(controller::list_oauth_identities)
+                    .service(controller::oauth_link_authorize)
+                    .service(controller::oauth_link)
+                    .service(controller::oauth_unlink)
+                    .service(controller::two_factor_status)
+                    .service(controller::begin_totp_setup)
+                    .service(controller::confirm_totp_setup)
+                    .service(controller::disable_totp)
+                    .service(controller::regenerate_recovery_codes)
+                    .service(controller::passkey_registration_options)
+                    .service(controller::register_passkey)
+                    .service(controller::delete_passkey)
+                    .service(controller::step_up_passkey_options)
+                    .service(controller::step_up),
             ),
     );
 }
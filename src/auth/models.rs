@@ -1,11 +1,13 @@
 use actix_web::{dev::Payload, Error as ActixError, FromRequest, HttpRequest};
 use chrono::{DateTime, Utc};
-use futures::future::{ready, Ready};
+use futures::future::{ready, LocalBoxFuture};
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
 use std::{env, error::Error, str::FromStr};
 use utoipa::ToSchema;
 
+use super::dto::SecondFactorChallenge;
+use super::service::authenticate_session_token;
 use crate::AppError;
 
 #[derive(Debug)]
@@ -14,6 +16,13 @@ pub struct TokenPair {
     pub refresh_token: String,
 }
 
+/// Result of checking a password: tokens, or a second factor still to confirm.
+#[derive(Debug)]
+pub enum LoginOutcome {
+    Tokens(TokenPair),
+    SecondFactorRequired(SecondFactorChallenge),
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
 pub enum UserRole {
     Admin,
@@ -87,6 +96,29 @@ pub struct Claims {
     pub email_confirmed: bool,
     pub authorities: Vec<String>,
     pub sid: Option<uuid::Uuid>,
+    /// Unix time the user last confirmed a second factor, carried over on refresh
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mfa_at: Option<i64>,
+    /// Set when the request was made with a personal API token: the scopes it is limited to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+}
+
+impl Claims {
+    pub fn is_blocked(&self) -> bool {
+        self.role.to_lowercase() == UserRole::Blocked.to_string()
+    }
+
+    pub fn is_api_token(&self) -> bool {
+        self.scopes.is_some()
+    }
+
+    /// Whether the token's scopes allow a permission. The role must still hold it.
+    pub fn allows(&self, permission_name: &str) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.iter().any(|scope| scope == permission_name))
+    }
 }
 
 pub fn decode_token(token: &str) -> Result<Claims, AppError> {
@@ -102,25 +134,32 @@ pub fn decode_token(token: &str) -> Result<Claims, AppError> {
 
 impl FromRequest for Claims {
     type Error = ActixError;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        // Set by `auth::validator`, which also accepts personal API tokens
+        if let Some(claims) = req.extensions().get::<Claims>() {
+            return Box::pin(ready(Ok(claims.clone())));
+        }
+
         // Extract token from Authorization header
         let auth_header = req.headers().get("Authorization");
         let token = auth_header
             .and_then(|h| h.to_str().ok())
-            .and_then(|h| h.strip_prefix("Bearer "));
-
-        if let Some(token) = token {
-            match decode_token(token) {
-                Ok(claims) => ready(Ok(claims)),
-                Err(_) => ready(Err(actix_web::error::ErrorUnauthorized("Invalid token"))),
-            }
-        } else {
-            ready(Err(actix_web::error::ErrorUnauthorized(
-                "No authorization token found",
-            )))
-        }
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .map(str::to_string);
+
+        let req = req.clone();
+        Box::pin(async move {
+            let Some(token) = token else {
+                return Err(actix_web::error::ErrorUnauthorized(
+                    "No authorization token found",
+                ));
+            };
+            authenticate_session_token(&req, &token)
+                .await
+                .map_err(actix_web::error::ErrorUnauthorized)
+        })
     }
 }
 
@@ -1,23 +1,34 @@
-//! Shared OAuth social-login pipeline (GitHub, Google).
+//! Shared OAuth social-login pipeline (GitHub, Google and OpenID Connect issuers).
 //!
-//! Adding a provider: extend [`OAuthProvider`], env `{PREFIX}_CLIENT_ID/_SECRET/_REDIRECT_URL`,
-//! and [`fetch_profile`]. Account linking and JWT issuance stay in this module.
+//! Adding a built-in provider: extend [`OAuthProvider`], env
+//! `{PREFIX}_CLIENT_ID/_SECRET/_REDIRECT_URL`, and [`fetch_profile`]. Any OIDC issuer can be added
+//! through configuration instead (see [`super::oidc`]). Account linking and JWT issuance stay in
+//! this module.
 
 use chrono::{Duration, Utc};
 use deadpool_postgres::Pool;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use oauth2::basic::{
+    BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
+    BasicTokenType,
+};
 use oauth2::{
-    basic::BasicClient, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken,
-    EndpointNotSet, EndpointSet, RedirectUrl, Scope, TokenResponse, TokenUrl,
+    AuthUrl, AuthorizationCode, Client, ClientId, ClientSecret, CsrfToken, EndpointNotSet,
+    EndpointSet, ExtraTokenFields, RedirectUrl, Scope, StandardRevocableToken,
+    StandardTokenResponse, TokenResponse, TokenUrl,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use std::env;
 use uuid::Uuid;
 
+use crate::auth::dto::{OAuthIdentityResponse, SecondFactorChallenge};
 use crate::auth::models::UserRole;
+use crate::auth::oidc;
 use crate::auth::service::{create_token_pair, hash_password, sanitize_html};
+use crate::auth::two_factor;
 use crate::auth::User;
+use crate::middleware::cache::RedisCache;
 use crate::sessions;
 use crate::{AppError, AppResult};
 
@@ -41,55 +52,48 @@ pub struct OAuthAccount {
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OAuthProvider {
     Github,
     Google,
+    /// An issuer listed in `OIDC_PROVIDERS`, by its id
+    Oidc(String),
 }
 
 impl OAuthProvider {
-    const ALL: [OAuthProvider; 2] = [OAuthProvider::Github, OAuthProvider::Google];
+    const BUILTIN: [OAuthProvider; 2] = [OAuthProvider::Github, OAuthProvider::Google];
+
+    fn all() -> Vec<Self> {
+        Self::BUILTIN
+            .into_iter()
+            .chain(oidc::provider_ids().into_iter().map(Self::Oidc))
+            .collect()
+    }
 
     pub fn parse(s: &str) -> Option<Self> {
         match s {
             "github" => Some(Self::Github),
             "google" => Some(Self::Google),
-            _ => None,
+            _ => oidc::provider_ids()
+                .into_iter()
+                .find(|id| id == s)
+                .map(Self::Oidc),
         }
     }
 
-    pub fn as_str(self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             Self::Github => "github",
             Self::Google => "google",
+            Self::Oidc(id) => id,
         }
     }
 
-    fn env_prefix(self) -> &'static str {
-        match self {
-            Self::Github => "GITHUB",
-            Self::Google => "GOOGLE",
-        }
-    }
-
-    fn auth_url(self) -> &'static str {
-        match self {
-            Self::Github => "https://github.com/login/oauth/authorize",
-            Self::Google => "https://accounts.google.com/o/oauth2/v2/auth",
-        }
-    }
-
-    fn token_url(self) -> &'static str {
+    fn env_prefix(&self) -> String {
         match self {
-            Self::Github => "https://github.com/login/oauth/access_token",
-            Self::Google => "https://oauth2.googleapis.com/token",
-        }
-    }
-
-    fn scopes(self) -> &'static [&'static str] {
-        match self {
-            Self::Github => &["read:user", "user:email"],
-            Self::Google => &["openid", "email", "profile"],
+            Self::Github => "GITHUB".to_string(),
+            Self::Google => "GOOGLE".to_string(),
+            Self::Oidc(id) => oidc::env_prefix(id),
         }
     }
 }
@@ -100,16 +104,38 @@ struct ProviderConfig {
     redirect_url: String,
 }
 
+/// Where to send the user and which scopes to ask for; discovered for OIDC issuers.
+struct ProviderEndpoints {
+    auth_url: String,
+    token_url: String,
+    scopes: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct OAuthStateClaims {
     purpose: String,
+    /// Also sent to OIDC issuers, which echo it in the `id_token`
     nonce: String,
     provider: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     return_to: Option<String>,
+    /// Set when an existing account is linking an identity rather than signing in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link_user_id: Option<i32>,
     exp: i64,
 }
 
+/// OIDC issuers return an `id_token` next to the access token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IdTokenFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id_token: Option<String>,
+}
+
+impl ExtraTokenFields for IdTokenFields {}
+
+type OAuthTokenResponse = StandardTokenResponse<IdTokenFields, BasicTokenType>;
+
 #[derive(Debug)]
 struct OAuthProfile {
     provider_id: String,
@@ -123,20 +149,24 @@ pub struct OAuthAuthorizeResult {
 }
 
 pub struct OAuthCompleteResult {
-    pub access_token: String,
-    pub refresh_token: String,
+    pub access_token: Option<String>,
+    pub refresh_token: Option<String>,
     pub username: String,
     pub return_to: Option<String>,
+    pub two_factor: Option<SecondFactorChallenge>,
 }
 
-fn env_nonempty(key: &str) -> Option<String> {
+pub(crate) fn env_nonempty(key: &str) -> Option<String> {
     env::var(key)
         .ok()
         .map(|s| s.trim().to_string())
         .filter(|s| !s.is_empty())
 }
 
-fn load_config(provider: OAuthProvider) -> AppResult<ProviderConfig> {
+fn load_config(provider: &OAuthProvider) -> AppResult<ProviderConfig> {
+    if let OAuthProvider::Oidc(id) = provider {
+        oidc::settings(id)?;
+    }
     let prefix = provider.env_prefix();
     let client_id_key = format!("{prefix}_CLIENT_ID");
     let client_secret_key = format!("{prefix}_CLIENT_SECRET");
@@ -228,27 +258,74 @@ fn oauth_http_client() -> AppResult<reqwest::Client> {
         .map_err(|e| AppError::Internal(format!("Failed to build OAuth HTTP client: {e}")))
 }
 
-type ConfiguredOAuthClient =
-    BasicClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointSet>;
+type ConfiguredOAuthClient = Client<
+    BasicErrorResponse,
+    OAuthTokenResponse,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointSet,
+>;
+
+async fn provider_endpoints(
+    provider: &OAuthProvider,
+    http: &reqwest::Client,
+) -> AppResult<ProviderEndpoints> {
+    let (auth_url, token_url, scopes): (&str, &str, &[&str]) = match provider {
+        OAuthProvider::Github => (
+            "https://github.com/login/oauth/authorize",
+            "https://github.com/login/oauth/access_token",
+            &["read:user", "user:email"],
+        ),
+        OAuthProvider::Google => (
+            "https://accounts.google.com/o/oauth2/v2/auth",
+            "https://oauth2.googleapis.com/token",
+            &["openid", "email", "profile"],
+        ),
+        OAuthProvider::Oidc(id) => {
+            let settings = oidc::settings(id)?;
+            let discovery = oidc::discover(http, &settings).await?;
+            return Ok(ProviderEndpoints {
+                auth_url: discovery.authorization_endpoint.clone(),
+                token_url: discovery.token_endpoint.clone(),
+                scopes: settings.scopes,
+            });
+        }
+    };
+    Ok(ProviderEndpoints {
+        auth_url: auth_url.to_string(),
+        token_url: token_url.to_string(),
+        scopes: scopes.iter().map(|s| (*s).to_string()).collect(),
+    })
+}
 
 fn build_oauth_client(
-    provider: OAuthProvider,
+    endpoints: &ProviderEndpoints,
     config: &ProviderConfig,
 ) -> AppResult<ConfiguredOAuthClient> {
-    let auth_url = AuthUrl::new(provider.auth_url().to_string())
+    let auth_url = AuthUrl::new(endpoints.auth_url.clone())
         .map_err(|e| AppError::Internal(format!("Invalid auth URL: {e}")))?;
-    let token_url = TokenUrl::new(provider.token_url().to_string())
+    let token_url = TokenUrl::new(endpoints.token_url.clone())
         .map_err(|e| AppError::Internal(format!("Invalid token URL: {e}")))?;
     let redirect = RedirectUrl::new(config.redirect_url.clone())
         .map_err(|e| AppError::Internal(format!("Invalid redirect URL: {e}")))?;
-    Ok(BasicClient::new(ClientId::new(config.client_id.clone()))
+    Ok(Client::new(ClientId::new(config.client_id.clone()))
         .set_client_secret(ClientSecret::new(config.client_secret.clone()))
         .set_auth_uri(auth_url)
         .set_token_uri(token_url)
         .set_redirect_uri(redirect))
 }
 
-fn sign_state(provider: OAuthProvider, return_to: Option<String>) -> AppResult<String> {
+fn sign_state(
+    provider: &OAuthProvider,
+    nonce: &str,
+    return_to: Option<String>,
+    link_user_id: Option<i32>,
+) -> AppResult<String> {
     let secret = jwt_secret()?;
     let exp = Utc::now()
         .checked_add_signed(Duration::minutes(OAUTH_STATE_TTL_MINUTES))
@@ -256,9 +333,10 @@ fn sign_state(provider: OAuthProvider, return_to: Option<String>) -> AppResult<S
         .timestamp();
     let claims = OAuthStateClaims {
         purpose: OAUTH_STATE_PURPOSE.to_string(),
-        nonce: Uuid::new_v4().to_string(),
+        nonce: nonce.to_string(),
         provider: provider.as_str().to_string(),
         return_to,
+        link_user_id,
         exp,
     };
     encode(
@@ -269,7 +347,7 @@ fn sign_state(provider: OAuthProvider, return_to: Option<String>) -> AppResult<S
     .map_err(|e| AppError::Internal(format!("Failed to sign OAuth state: {e}")))
 }
 
-fn verify_state(state: &str, provider: OAuthProvider) -> AppResult<OAuthStateClaims> {
+fn verify_state(state: &str, provider: &OAuthProvider) -> AppResult<OAuthStateClaims> {
     let secret = jwt_secret()?;
     let mut validation = Validation::new(Algorithm::HS256);
     validation.set_required_spec_claims(&["exp"]);
@@ -291,28 +369,39 @@ fn verify_state(state: &str, provider: OAuthProvider) -> AppResult<OAuthStateCla
     Ok(claims)
 }
 
-pub fn configured_providers() -> Vec<&'static str> {
-    OAuthProvider::ALL
+pub fn configured_providers() -> Vec<String> {
+    OAuthProvider::all()
         .iter()
-        .filter(|p| load_config(**p).is_ok())
-        .map(|p| p.as_str())
+        .filter(|p| load_config(p).is_ok())
+        .map(|p| p.as_str().to_string())
         .collect()
 }
 
-pub fn authorize_url(
+async fn start_authorization(
     provider_name: &str,
     return_to: Option<&str>,
+    link_user_id: Option<i32>,
 ) -> AppResult<OAuthAuthorizeResult> {
     let provider = parse_provider(provider_name)?;
-    let config = load_config(provider)?;
-    let client = build_oauth_client(provider, &config)?;
-    let state = sign_state(provider, sanitize_return_to(return_to))?;
+    let config = load_config(&provider)?;
+    let http = oauth_http_client()?;
+    let endpoints = provider_endpoints(&provider, &http).await?;
+    let client = build_oauth_client(&endpoints, &config)?;
+    let nonce = Uuid::new_v4().to_string();
+    let state = sign_state(
+        &provider,
+        &nonce,
+        sanitize_return_to(return_to),
+        link_user_id,
+    )?;
     let mut request = client.authorize_url(|| CsrfToken::new(state));
-    for scope in provider.scopes() {
-        request = request.add_scope(Scope::new((*scope).to_string()));
+    for scope in &endpoints.scopes {
+        request = request.add_scope(Scope::new(scope.clone()));
     }
-    if provider == OAuthProvider::Google {
-        request = request.add_extra_param("prompt", "select_account");
+    match provider {
+        OAuthProvider::Google => request = request.add_extra_param("prompt", "select_account"),
+        OAuthProvider::Oidc(_) => request = request.add_extra_param("nonce", nonce),
+        OAuthProvider::Github => {}
     }
     let (url, _) = request.url();
     Ok(OAuthAuthorizeResult {
@@ -320,18 +409,53 @@ pub fn authorize_url(
     })
 }
 
+pub async fn authorize_url(
+    provider_name: &str,
+    return_to: Option<&str>,
+) -> AppResult<OAuthAuthorizeResult> {
+    start_authorization(provider_name, return_to, None).await
+}
+
+/// Like [`authorize_url`], for adding the identity to the signed-in account.
+pub async fn link_authorize_url(
+    provider_name: &str,
+    user_id: i32,
+    return_to: Option<&str>,
+) -> AppResult<OAuthAuthorizeResult> {
+    start_authorization(provider_name, return_to, Some(user_id)).await
+}
+
 fn json_true(value: &Value) -> bool {
     value.as_bool().unwrap_or(false) || value.as_str() == Some("true")
 }
 
+/// Exchanges the authorization code and fetches the user's profile from the provider.
 async fn fetch_profile(
-    provider: OAuthProvider,
-    access_token: &str,
-    http: &reqwest::Client,
+    provider: &OAuthProvider,
+    config: &ProviderConfig,
+    code: &str,
+    nonce: &str,
 ) -> AppResult<OAuthProfile> {
+    let http = oauth_http_client()?;
+    let endpoints = provider_endpoints(provider, &http).await?;
+    let client = build_oauth_client(&endpoints, config)?;
+    let token_result = client
+        .exchange_code(AuthorizationCode::new(code.to_string()))
+        .request_async(&http)
+        .await
+        .map_err(|e| AppError::Auth(format!("Failed to exchange code for token: {e}")))?;
+    let access_token = token_result.access_token().secret();
     match provider {
-        OAuthProvider::Github => fetch_github_profile(access_token, http).await,
-        OAuthProvider::Google => fetch_google_profile(access_token, http).await,
+        OAuthProvider::Github => fetch_github_profile(access_token, &http).await,
+        OAuthProvider::Google => fetch_google_profile(access_token, &http).await,
+        OAuthProvider::Oidc(id) => {
+            let id_token = token_result
+                .extra_fields()
+                .id_token
+                .as_deref()
+                .ok_or_else(|| AppError::Auth("Provider returned no id_token".to_string()))?;
+            fetch_oidc_profile(id, config, id_token, access_token, nonce, &http).await
+        }
     }
 }
 
@@ -464,7 +588,65 @@ async fn fetch_google_profile(
     })
 }
 
-fn fallback_email(provider: OAuthProvider, provider_id: &str) -> String {
+async fn fetch_oidc_profile(
+    id: &str,
+    config: &ProviderConfig,
+    id_token: &str,
+    access_token: &str,
+    nonce: &str,
+    http: &reqwest::Client,
+) -> AppResult<OAuthProfile> {
+    let settings = oidc::settings(id)?;
+    let mut claims =
+        oidc::verify_id_token(http, &settings, &config.client_id, id_token, nonce).await?;
+    // Some issuers (GitLab, Keycloak with default mappers off) only expose these via userinfo
+    if oidc::claim(&claims, &settings.username_claim).is_none()
+        || oidc::claim(&claims, &settings.email_claim).is_none()
+    {
+        let discovery = oidc::discover(http, &settings).await?;
+        if let Some(userinfo) = oidc::userinfo(http, &discovery, access_token).await? {
+            if userinfo.get("sub") != claims.get("sub") {
+                return Err(AppError::Auth("OIDC userinfo subject mismatch".to_string()));
+            }
+            for (key, value) in userinfo {
+                claims.entry(key).or_insert(value);
+            }
+        }
+    }
+    oidc_profile(&settings, &claims)
+}
+
+fn oidc_profile(
+    settings: &oidc::OidcSettings,
+    claims: &Map<String, Value>,
+) -> AppResult<OAuthProfile> {
+    let provider_id = claims
+        .get("sub")
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| AppError::Auth("Missing OIDC subject".to_string()))?
+        .to_string();
+    let claimed_email = oidc::claim(claims, &settings.email_claim)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty());
+    // Only a verified address from a trusted issuer may sign in to an existing account
+    let email = claimed_email
+        .filter(|_| settings.trust_email && claims.get("email_verified").is_some_and(json_true))
+        .map(str::to_string);
+    let username = oidc::claim(claims, &settings.username_claim)
+        .and_then(Value::as_str)
+        .or_else(|| claimed_email.and_then(|e| e.split('@').next()))
+        .or_else(|| claims.get("name").and_then(Value::as_str))
+        .unwrap_or("user");
+    Ok(OAuthProfile {
+        provider_id,
+        email_verified: email.is_some(),
+        email,
+        suggested_username: sanitize_username(username),
+    })
+}
+
+fn fallback_email(provider: &OAuthProvider, provider_id: &str) -> String {
     format!(
         "{}+{}@users.noreply.lensisku.invalid",
         provider.as_str(),
@@ -507,10 +689,26 @@ fn reject_if_blocked(user: &User) -> AppResult<()> {
 
 async fn issue_tokens(
     pool: &Pool,
+    redis_cache: &RedisCache,
     user: &User,
     ip_address: String,
     user_agent: String,
 ) -> AppResult<OAuthCompleteResult> {
+    // The provider stands in for the password; a second factor is still required
+    let methods = two_factor::enabled_methods(pool, user.userid).await?;
+    if !methods.is_empty() {
+        let challenge =
+            two_factor::start_pending_login(redis_cache, user.userid, &user.username, methods)
+                .await?;
+        return Ok(OAuthCompleteResult {
+            access_token: None,
+            refresh_token: None,
+            username: user.username.clone(),
+            return_to: None,
+            two_factor: Some(challenge),
+        });
+    }
+
     let mut jwt_session_id = None;
     match sessions::service::start_session(pool, user.userid, ip_address, user_agent).await {
         Ok(session) => jwt_session_id = Some(session.session_uuid),
@@ -520,17 +718,19 @@ async fn issue_tokens(
             e
         ),
     }
-    let pair = create_token_pair(pool, user, jwt_session_id).await?;
+    let pair = create_token_pair(pool, user, jwt_session_id, None).await?;
     Ok(OAuthCompleteResult {
-        access_token: pair.access_token,
-        refresh_token: pair.refresh_token,
+        access_token: Some(pair.access_token),
+        refresh_token: Some(pair.refresh_token),
         username: user.username.clone(),
         return_to: None,
+        two_factor: None,
     })
 }
 
 pub async fn complete_oauth(
     pool: &Pool,
+    redis_cache: &RedisCache,
     provider_name: &str,
     code: &str,
     state: &str,
@@ -543,19 +743,15 @@ pub async fn complete_oauth(
         ));
     }
     let provider = parse_provider(provider_name)?;
-    let config = load_config(provider)?;
-    let claims = verify_state(state, provider)?;
+    let config = load_config(&provider)?;
+    let claims = verify_state(state, &provider)?;
+    if claims.link_user_id.is_some() {
+        return Err(AppError::BadRequest(
+            "OAuth state was issued for linking an account".to_string(),
+        ));
+    }
     let return_to = sanitize_return_to(claims.return_to.as_deref());
-
-    let client = build_oauth_client(provider, &config)?;
-    let http = oauth_http_client()?;
-    let token_result = client
-        .exchange_code(AuthorizationCode::new(code.to_string()))
-        .request_async(&http)
-        .await
-        .map_err(|e| AppError::Auth(format!("Failed to exchange code for token: {e}")))?;
-    let access_token = token_result.access_token().secret().to_string();
-    let profile = fetch_profile(provider, &access_token, &http).await?;
+    let profile = fetch_profile(&provider, &config, code, &claims.nonce).await?;
 
     let mut db = pool
         .get()
@@ -599,14 +795,14 @@ pub async fn complete_oauth(
             .map_err(|e| AppError::Database(e.to_string()))?;
         if let Some(row) = existing_email {
             let user =
-                link_oauth_to_user(&transaction, User::from(row), provider, &profile).await?;
+                link_oauth_to_user(&transaction, User::from(row), &provider, &profile).await?;
             transaction
                 .commit()
                 .await
                 .map_err(|e| AppError::Database(e.to_string()))?;
             user
         } else {
-            let user = insert_oauth_user(&transaction, provider, &profile).await?;
+            let user = insert_oauth_user(&transaction, &provider, &profile).await?;
             transaction
                 .commit()
                 .await
@@ -614,7 +810,7 @@ pub async fn complete_oauth(
             user
         }
     } else {
-        let user = insert_oauth_user(&transaction, provider, &profile).await?;
+        let user = insert_oauth_user(&transaction, &provider, &profile).await?;
         transaction
             .commit()
             .await
@@ -622,7 +818,7 @@ pub async fn complete_oauth(
         user
     };
 
-    let mut result = issue_tokens(pool, &user, ip_address, user_agent).await?;
+    let mut result = issue_tokens(pool, redis_cache, &user, ip_address, user_agent).await?;
     result.return_to = return_to;
     Ok(result)
 }
@@ -630,7 +826,7 @@ pub async fn complete_oauth(
 async fn link_oauth_to_user(
     transaction: &deadpool_postgres::Transaction<'_>,
     mut user: User,
-    provider: OAuthProvider,
+    provider: &OAuthProvider,
     profile: &OAuthProfile,
 ) -> AppResult<User> {
     reject_if_blocked(&user)?;
@@ -663,7 +859,7 @@ async fn link_oauth_to_user(
 
 async fn insert_oauth_user(
     transaction: &deadpool_postgres::Transaction<'_>,
-    provider: OAuthProvider,
+    provider: &OAuthProvider,
     profile: &OAuthProfile,
 ) -> AppResult<User> {
     let username = unique_username(transaction, &profile.suggested_username).await?;
@@ -728,6 +924,123 @@ async fn insert_oauth_user(
     })
 }
 
+fn identity_response(row: &tokio_postgres::Row) -> OAuthIdentityResponse {
+    OAuthIdentityResponse {
+        id: row.get("id"),
+        provider: row.get("provider"),
+        created_at: row.get("created_at"),
+    }
+}
+
+pub async fn list_identities(pool: &Pool, user_id: i32) -> AppResult<Vec<OAuthIdentityResponse>> {
+    let db = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let rows = db
+        .query(
+            "SELECT id, provider, created_at FROM oauth_accounts
+             WHERE user_id = $1 ORDER BY created_at, id",
+            &[&user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    Ok(rows.iter().map(identity_response).collect())
+}
+
+/// Completes a flow started with [`link_authorize_url`]. Linking an identity that is already
+/// linked to this account is a no-op.
+pub async fn link_identity(
+    pool: &Pool,
+    user_id: i32,
+    provider_name: &str,
+    code: &str,
+    state: &str,
+) -> AppResult<OAuthIdentityResponse> {
+    if code.trim().is_empty() || state.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "Missing OAuth code or state".to_string(),
+        ));
+    }
+    let provider = parse_provider(provider_name)?;
+    let config = load_config(&provider)?;
+    let claims = verify_state(state, &provider)?;
+    if claims.link_user_id != Some(user_id) {
+        return Err(AppError::BadRequest(
+            "OAuth state was not issued for linking this account".to_string(),
+        ));
+    }
+    let profile = fetch_profile(&provider, &config, code, &claims.nonce).await?;
+
+    let db = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let row = db
+        .query_opt(
+            "INSERT INTO oauth_accounts (user_id, provider, provider_id) VALUES ($1, $2, $3)
+             ON CONFLICT (provider, provider_id) DO UPDATE SET updated_at = NOW()
+             WHERE oauth_accounts.user_id = EXCLUDED.user_id
+             RETURNING id, provider, created_at",
+            &[&user_id, &provider.as_str(), &profile.provider_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    row.as_ref().map(identity_response).ok_or_else(|| {
+        AppError::BadRequest("This identity is linked to another account".to_string())
+    })
+}
+
+pub async fn unlink_identity(pool: &Pool, user_id: i32, identity_id: i32) -> AppResult<()> {
+    let mut db = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let transaction = db
+        .transaction()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    // Locks the account so concurrent unlinks cannot remove every way to sign in
+    let oauth_signup: Option<bool> = transaction
+        .query_opt(
+            "SELECT oauth_signup FROM users WHERE userid = $1 FOR UPDATE",
+            &[&user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?
+        .get("oauth_signup");
+    let deleted = transaction
+        .execute(
+            "DELETE FROM oauth_accounts WHERE id = $1 AND user_id = $2",
+            &[&identity_id, &user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    if deleted == 0 {
+        return Err(AppError::NotFound("Linked identity not found".to_string()));
+    }
+    let remaining: i64 = transaction
+        .query_one(
+            "SELECT COUNT(*) FROM oauth_accounts WHERE user_id = $1",
+            &[&user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .get(0);
+    // Accounts created through a provider have a random password until one is set
+    if remaining == 0 && oauth_signup.unwrap_or(false) {
+        return Err(AppError::BadRequest(
+            "Set a password before removing your last linked identity".to_string(),
+        ));
+    }
+    transaction
+        .commit()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -739,6 +1052,43 @@ mod tests {
         assert_eq!(OAuthProvider::parse("discord"), None);
     }
 
+    fn oidc_settings(trust_email: bool) -> oidc::OidcSettings {
+        oidc::OidcSettings {
+            issuer: "https://sso.example".to_string(),
+            scopes: vec!["openid".to_string()],
+            username_claim: "preferred_username".to_string(),
+            email_claim: "email".to_string(),
+            trust_email,
+        }
+    }
+
+    #[test]
+    fn oidc_claim_mapping() {
+        let claims = serde_json::json!({
+            "sub": "abc",
+            "email": "gerku@example.org",
+            "email_verified": true
+        });
+        let claims = claims.as_object().cloned().unwrap_or_default();
+
+        let profile = oidc_profile(&oidc_settings(true), &claims).ok();
+        assert_eq!(
+            profile.as_ref().and_then(|p| p.email.as_deref()),
+            Some("gerku@example.org")
+        );
+        assert_eq!(
+            profile.as_ref().map(|p| p.suggested_username.as_str()),
+            Some("gerku")
+        );
+
+        // An untrusted issuer's address is never used to match accounts
+        let profile = oidc_profile(&oidc_settings(false), &claims).ok();
+        assert_eq!(profile.as_ref().map(|p| p.email.is_none()), Some(true));
+        assert_eq!(profile.as_ref().map(|p| p.email_verified), Some(false));
+
+        assert!(oidc_profile(&oidc_settings(true), &Map::new()).is_err());
+    }
+
     #[test]
     fn return_to_must_be_relative() {
         assert_eq!(
@@ -0,0 +1,307 @@
+//! Configuration-driven OpenID Connect providers for [`super::oauth`].
+//!
+//! `OIDC_PROVIDERS` lists provider ids (`keycloak,gitlab`). Each id reads env
+//! `OIDC_{ID}_ISSUER/_CLIENT_ID/_CLIENT_SECRET/_REDIRECT_URL` and optionally `_SCOPES`
+//! (default `openid email profile`), `_USERNAME_CLAIM` (default `preferred_username`),
+//! `_EMAIL_CLAIM` (default `email`) and `_TRUST_EMAIL`. Claims may be dotted paths into nested
+//! objects. Endpoints and signing keys come from the issuer's discovery document.
+
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use super::oauth::env_nonempty;
+use crate::{AppError, AppResult};
+
+const DISCOVERY_TTL: Duration = Duration::from_secs(60 * 60);
+const BUILTIN_PROVIDERS: [&str; 2] = ["github", "google"];
+const DEFAULT_SCOPES: &str = "openid email profile";
+
+pub(crate) struct OidcSettings {
+    pub issuer: String,
+    pub scopes: Vec<String>,
+    pub username_claim: String,
+    pub email_claim: String,
+    /// Whether `email_verified` from this issuer is good enough to sign in to an existing
+    /// account with the same email. Off by default: a self-hosted IdP can claim any address.
+    pub trust_email: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    pub userinfo_endpoint: Option<String>,
+}
+
+struct CachedIssuer {
+    discovery: Arc<Discovery>,
+    jwks: Arc<JwkSet>,
+    fetched_at: Instant,
+}
+
+/// Discovery documents and key sets by issuer.
+static ISSUERS: LazyLock<Mutex<HashMap<String, CachedIssuer>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn valid_provider_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 32
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        && !BUILTIN_PROVIDERS.contains(&id)
+}
+
+/// Provider ids listed in `OIDC_PROVIDERS`, whether or not they are fully configured.
+pub(crate) fn provider_ids() -> Vec<String> {
+    let Some(raw) = env_nonempty("OIDC_PROVIDERS") else {
+        return Vec::new();
+    };
+    let mut ids = Vec::new();
+    for id in raw.split(',').map(|id| id.trim().to_lowercase()) {
+        if !valid_provider_id(&id) {
+            if !id.is_empty() {
+                log::warn!("Ignoring invalid OIDC provider id: {id}");
+            }
+            continue;
+        }
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+pub(crate) fn env_prefix(id: &str) -> String {
+    format!("OIDC_{}", id.to_uppercase().replace('-', "_"))
+}
+
+pub(crate) fn settings(id: &str) -> AppResult<OidcSettings> {
+    let prefix = env_prefix(id);
+    let issuer = env_nonempty(&format!("{prefix}_ISSUER"))
+        .ok_or_else(|| AppError::Config(vec![format!("{id} is not configured")]))?;
+    let scopes =
+        env_nonempty(&format!("{prefix}_SCOPES")).unwrap_or_else(|| DEFAULT_SCOPES.to_string());
+    let mut scopes: Vec<String> = scopes
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect();
+    if !scopes.iter().any(|s| s == "openid") {
+        scopes.insert(0, "openid".to_string());
+    }
+    Ok(OidcSettings {
+        issuer: issuer.trim_end_matches('/').to_string(),
+        scopes,
+        username_claim: env_nonempty(&format!("{prefix}_USERNAME_CLAIM"))
+            .unwrap_or_else(|| "preferred_username".to_string()),
+        email_claim: env_nonempty(&format!("{prefix}_EMAIL_CLAIM"))
+            .unwrap_or_else(|| "email".to_string()),
+        trust_email: env_nonempty(&format!("{prefix}_TRUST_EMAIL"))
+            .is_some_and(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes")),
+    })
+}
+
+/// Looks up a claim by dotted path, e.g. `profile.nickname`.
+pub(crate) fn claim<'a>(claims: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut segments = path.split('.');
+    let mut value = claims.get(segments.next()?)?;
+    for segment in segments {
+        value = value.as_object()?.get(segment)?;
+    }
+    Some(value)
+}
+
+async fn fetch_json<T: DeserializeOwned>(
+    http: &reqwest::Client,
+    url: &str,
+    what: &str,
+) -> AppResult<T> {
+    let resp = http
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .await
+        .map_err(|e| AppError::Auth(format!("Failed to fetch OIDC {what}: {e}")))?;
+    if !resp.status().is_success() {
+        return Err(AppError::Auth(format!(
+            "Failed to fetch OIDC {what}: {}",
+            resp.status()
+        )));
+    }
+    resp.json()
+        .await
+        .map_err(|e| AppError::Auth(format!("Failed to parse OIDC {what}: {e}")))
+}
+
+/// `refresh` bypasses the cache, for when the issuer has rotated its keys.
+async fn load_issuer(
+    http: &reqwest::Client,
+    settings: &OidcSettings,
+    refresh: bool,
+) -> AppResult<(Arc<Discovery>, Arc<JwkSet>)> {
+    if !refresh {
+        let cache = ISSUERS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(cached) = cache.get(&settings.issuer) {
+            if cached.fetched_at.elapsed() < DISCOVERY_TTL {
+                return Ok((cached.discovery.clone(), cached.jwks.clone()));
+            }
+        }
+    }
+
+    let discovery: Discovery = fetch_json(
+        http,
+        &format!("{}/.well-known/openid-configuration", settings.issuer),
+        "discovery document",
+    )
+    .await?;
+    if discovery.issuer.trim_end_matches('/') != settings.issuer {
+        return Err(AppError::Config(vec![format!(
+            "OIDC issuer mismatch: configured {}, discovered {}",
+            settings.issuer, discovery.issuer
+        )]));
+    }
+    let jwks: JwkSet = fetch_json(http, &discovery.jwks_uri, "key set").await?;
+
+    let (discovery, jwks) = (Arc::new(discovery), Arc::new(jwks));
+    ISSUERS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(
+            settings.issuer.clone(),
+            CachedIssuer {
+                discovery: discovery.clone(),
+                jwks: jwks.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+    Ok((discovery, jwks))
+}
+
+pub(crate) async fn discover(
+    http: &reqwest::Client,
+    settings: &OidcSettings,
+) -> AppResult<Arc<Discovery>> {
+    Ok(load_issuer(http, settings, false).await?.0)
+}
+
+fn find_key(jwks: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid).cloned(),
+        None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+        None => None,
+    }
+}
+
+/// Validates the signature, issuer, audience, expiry and nonce of an `id_token` and returns its
+/// claims.
+pub(crate) async fn verify_id_token(
+    http: &reqwest::Client,
+    settings: &OidcSettings,
+    client_id: &str,
+    id_token: &str,
+    nonce: &str,
+) -> AppResult<Map<String, Value>> {
+    let header =
+        decode_header(id_token).map_err(|e| AppError::Auth(format!("Invalid id_token: {e}")))?;
+    // Symmetric algorithms would let anyone holding the client secret mint tokens
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err(AppError::Auth(
+            "Unsupported id_token signing algorithm".to_string(),
+        ));
+    }
+
+    let (discovery, jwks) = load_issuer(http, settings, false).await?;
+    let jwk = match find_key(&jwks, header.kid.as_deref()) {
+        Some(jwk) => jwk,
+        None => {
+            let (_, jwks) = load_issuer(http, settings, true).await?;
+            find_key(&jwks, header.kid.as_deref())
+                .ok_or_else(|| AppError::Auth("Unknown id_token signing key".to_string()))?
+        }
+    };
+    let key = DecodingKey::from_jwk(&jwk)
+        .map_err(|e| AppError::Auth(format!("Unusable id_token signing key: {e}")))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&discovery.issuer]);
+    validation.set_audience(&[client_id]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    let claims = decode::<Map<String, Value>>(id_token, &key, &validation)
+        .map_err(|e| AppError::Auth(format!("Invalid id_token: {e}")))?
+        .claims;
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err(AppError::Auth("id_token nonce mismatch".to_string()));
+    }
+    Ok(claims)
+}
+
+/// Claims from the userinfo endpoint, for issuers that keep them out of the `id_token`.
+pub(crate) async fn userinfo(
+    http: &reqwest::Client,
+    discovery: &Discovery,
+    access_token: &str,
+) -> AppResult<Option<Map<String, Value>>> {
+    let Some(url) = discovery.userinfo_endpoint.as_deref() else {
+        return Ok(None);
+    };
+    let resp = http
+        .get(url)
+        .header(
+            reqwest::header::AUTHORIZATION,
+            format!("Bearer {access_token}"),
+        )
+        .send()
+        .await
+        .map_err(|e| AppError::Auth(format!("Failed to get OIDC userinfo: {e}")))?;
+    if !resp.status().is_success() {
+        return Err(AppError::Auth(format!(
+            "Failed to get OIDC userinfo: {}",
+            resp.status()
+        )));
+    }
+    resp.json()
+        .await
+        .map(Some)
+        .map_err(|e| AppError::Auth(format!("Failed to parse OIDC userinfo: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn provider_ids_exclude_builtins() {
+        assert!(valid_provider_id("keycloak"));
+        assert!(valid_provider_id("self-hosted_2"));
+        assert!(!valid_provider_id("github"));
+        assert!(!valid_provider_id("Key Cloak"));
+        assert!(!valid_provider_id(""));
+        assert_eq!(env_prefix("self-hosted"), "OIDC_SELF_HOSTED");
+    }
+
+    #[test]
+    fn dotted_claims() {
+        let claims = json!({
+            "sub": "42",
+            "profile": { "nickname": "la gerku" }
+        });
+        let claims = claims.as_object().cloned().unwrap_or_default();
+        assert_eq!(claim(&claims, "sub"), Some(&json!("42")));
+        assert_eq!(claim(&claims, "profile.nickname"), Some(&json!("la gerku")));
+        assert_eq!(claim(&claims, "profile.missing"), None);
+        assert_eq!(claim(&claims, "sub.nested"), None);
+    }
+}
@@ -0,0 +1,261 @@
+//! WebAuthn passkeys as a second factor (see [`super::two_factor`]).
+//!
+//! The relying party is the frontend: `WEBAUTHN_RP_ORIGIN` (default `FRONTEND_URL`) and
+//! `WEBAUTHN_RP_ID` (default: the host of the origin). The state of a ceremony is kept in Redis
+//! between handing out its options and receiving the authenticator's response.
+
+use deadpool_postgres::Pool;
+use redis::AsyncCommands;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, Passkey, PasskeyAuthentication, PasskeyRegistration,
+    PublicKeyCredential, RequestChallengeResponse, Url, WebauthnError,
+};
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+use super::dto::{PasskeyRegistrationRequest, PasskeyResponse};
+use super::oauth::env_nonempty;
+use crate::middleware::cache::RedisCache;
+use crate::{AppError, AppResult};
+
+const RP_NAME: &str = "Lensisku";
+const CEREMONY_TTL_SECS: u64 = 5 * 60;
+const DEFAULT_NAME: &str = "Passkey";
+const MAX_NAME_LEN: usize = 100;
+
+fn webauthn() -> AppResult<Webauthn> {
+    let origin = env_nonempty("WEBAUTHN_RP_ORIGIN")
+        .or_else(|| env_nonempty("FRONTEND_URL"))
+        .ok_or_else(|| {
+            AppError::Config(vec![
+                "WEBAUTHN_RP_ORIGIN or FRONTEND_URL must be set for passkeys".to_string(),
+            ])
+        })?;
+    let origin = Url::parse(&origin)
+        .map_err(|e| AppError::Config(vec![format!("Invalid WebAuthn origin: {}", e)]))?;
+    let rp_id = match env_nonempty("WEBAUTHN_RP_ID") {
+        Some(rp_id) => rp_id,
+        None => origin
+            .host_str()
+            .map(str::to_string)
+            .ok_or_else(|| AppError::Config(vec!["WebAuthn origin has no host".to_string()]))?,
+    };
+    WebauthnBuilder::new(&rp_id, &origin)
+        .and_then(|builder| builder.rp_name(RP_NAME).build())
+        .map_err(|e| AppError::Config(vec![format!("Invalid WebAuthn configuration: {}", e)]))
+}
+
+fn rejected(e: WebauthnError) -> AppError {
+    AppError::Unauthorized(format!("Passkey verification failed: {}", e))
+}
+
+fn registration_key(user_id: i32) -> String {
+    format!("webauthn:register:{}", user_id)
+}
+
+fn authentication_key(state: &str) -> String {
+    format!("webauthn:authenticate:{}", state)
+}
+
+async fn store_state<T: Serialize>(
+    redis_cache: &RedisCache,
+    key: &str,
+    state: &T,
+) -> AppResult<()> {
+    let state = serde_json::to_string(state)?;
+    let mut conn = redis_cache
+        .client
+        .get_multiplexed_async_connection()
+        .await?;
+    let _: () = conn.set_ex(key, state, CEREMONY_TTL_SECS).await?;
+    Ok(())
+}
+
+/// Ceremony state can be used once, so a response cannot be replayed.
+async fn take_state<T: DeserializeOwned>(redis_cache: &RedisCache, key: &str) -> AppResult<T> {
+    let mut conn = redis_cache
+        .client
+        .get_multiplexed_async_connection()
+        .await?;
+    let state: Option<String> = conn.get_del(key).await?;
+    let state = state.ok_or_else(|| {
+        AppError::BadRequest("Passkey request expired, please try again".to_string())
+    })?;
+    Ok(serde_json::from_str(&state)?)
+}
+
+/// Credential ID as base64url, the way `webauthn-rs` serializes it.
+fn credential_id(passkey: &Passkey) -> AppResult<String> {
+    Ok(match serde_json::to_value(passkey.cred_id())? {
+        serde_json::Value::String(id) => id,
+        other => other.to_string(),
+    })
+}
+
+fn passkey_response(row: &Row) -> PasskeyResponse {
+    PasskeyResponse {
+        id: row.get("id"),
+        name: row.get("name"),
+        created_at: row.get("created_at"),
+        last_used_at: row.get("last_used_at"),
+    }
+}
+
+async fn user_passkeys(pool: &Pool, user_id: i32) -> AppResult<Vec<(i32, Passkey)>> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            "SELECT id, passkey FROM user_passkeys WHERE user_id = $1",
+            &[&user_id],
+        )
+        .await?;
+    rows.iter()
+        .map(|row| Ok((row.get("id"), serde_json::from_value(row.get("passkey"))?)))
+        .collect()
+}
+
+pub async fn list_passkeys(pool: &Pool, user_id: i32) -> AppResult<Vec<PasskeyResponse>> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            "SELECT id, name, created_at, last_used_at FROM user_passkeys
+             WHERE user_id = $1 ORDER BY created_at",
+            &[&user_id],
+        )
+        .await?;
+    Ok(rows.iter().map(passkey_response).collect())
+}
+
+pub async fn start_registration(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    user_id: i32,
+    username: &str,
+) -> AppResult<CreationChallengeResponse> {
+    let webauthn = webauthn()?;
+    let user_handle: Uuid = pool
+        .get()
+        .await?
+        .query_one(
+            "SELECT webauthn_user_id FROM users WHERE userid = $1",
+            &[&user_id],
+        )
+        .await?
+        .get("webauthn_user_id");
+    // The same authenticator cannot be registered twice
+    let existing = user_passkeys(pool, user_id)
+        .await?
+        .iter()
+        .map(|(_, passkey)| passkey.cred_id().clone())
+        .collect();
+
+    let (options, state) = webauthn
+        .start_passkey_registration(user_handle, username, username, Some(existing))
+        .map_err(|e| AppError::Internal(format!("Failed to start passkey registration: {}", e)))?;
+    store_state(redis_cache, &registration_key(user_id), &state).await?;
+    Ok(options)
+}
+
+pub async fn finish_registration(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    user_id: i32,
+    request: &PasskeyRegistrationRequest,
+) -> AppResult<PasskeyResponse> {
+    let webauthn = webauthn()?;
+    let state: PasskeyRegistration = take_state(redis_cache, &registration_key(user_id)).await?;
+    let passkey = webauthn
+        .finish_passkey_registration(&request.credential, &state)
+        .map_err(rejected)?;
+    let name: String = request
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or(DEFAULT_NAME)
+        .chars()
+        .take(MAX_NAME_LEN)
+        .collect();
+
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            "INSERT INTO user_passkeys (user_id, name, credential_id, passkey)
+             VALUES ($1, $2, $3, $4)
+             RETURNING id, name, created_at, last_used_at",
+            &[
+                &user_id,
+                &name,
+                &credential_id(&passkey)?,
+                &serde_json::to_value(&passkey)?,
+            ],
+        )
+        .await?;
+    Ok(passkey_response(&row))
+}
+
+/// Options for asserting one of the user's passkeys. `state` names the ceremony, so that a
+/// login and a step-up do not overwrite each other.
+pub async fn start_authentication(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    user_id: i32,
+    state: &str,
+) -> AppResult<RequestChallengeResponse> {
+    let passkeys: Vec<Passkey> = user_passkeys(pool, user_id)
+        .await?
+        .into_iter()
+        .map(|(_, passkey)| passkey)
+        .collect();
+    if passkeys.is_empty() {
+        return Err(AppError::BadRequest("No passkeys registered".to_string()));
+    }
+    let (options, authentication) = webauthn()?
+        .start_passkey_authentication(&passkeys)
+        .map_err(|e| AppError::Internal(format!("Failed to start passkey login: {}", e)))?;
+    store_state(redis_cache, &authentication_key(state), &authentication).await?;
+    Ok(options)
+}
+
+pub async fn finish_authentication(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    user_id: i32,
+    state: &str,
+    credential: &PublicKeyCredential,
+) -> AppResult<()> {
+    let authentication: PasskeyAuthentication =
+        take_state(redis_cache, &authentication_key(state)).await?;
+    let result = webauthn()?
+        .finish_passkey_authentication(credential, &authentication)
+        .map_err(rejected)?;
+    let (id, mut passkey) = user_passkeys(pool, user_id)
+        .await?
+        .into_iter()
+        .find(|(_, passkey)| passkey.cred_id() == result.cred_id())
+        .ok_or_else(|| AppError::Unauthorized("Unknown passkey".to_string()))?;
+
+    // Keeps the signature counter current, which is how cloned authenticators are noticed
+    passkey.update_credential(&result);
+    pool.get()
+        .await?
+        .execute(
+            "UPDATE user_passkeys SET passkey = $2, last_used_at = NOW() WHERE id = $1",
+            &[&id, &serde_json::to_value(&passkey)?],
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_passkey(pool: &Pool, user_id: i32, passkey_id: i32) -> AppResult<bool> {
+    let client = pool.get().await?;
+    let deleted = client
+        .execute(
+            "DELETE FROM user_passkeys WHERE id = $1 AND user_id = $2",
+            &[&passkey_id, &user_id],
+        )
+        .await?;
+    Ok(deleted > 0)
+}
@@ -1,23 +1,38 @@
 use deadpool_postgres::Pool;
+use futures::StreamExt;
+use log::{error, info};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio::time::{sleep, Duration};
 
-use super::models::Permission;
+use super::models::{Claims, Permission, UserRole};
+
+/// Redis channel on which instances announce role and permission changes.
+const INVALIDATION_CHANNEL: &str = "lensisku:permissions:invalidate";
+/// Access tokens are valid for 24 hours; role changes older than that are in every live token.
+const ROLE_CHANGE_WINDOW_HOURS: i32 = 24;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
 
 pub struct PermissionCache {
     pool: Pool,
+    redis: redis::Client,
     cache: RwLock<HashMap<String, Vec<Permission>>>,
+    /// Current role of users whose role changed, or who were blocked, after live tokens were
+    /// issued. Read synchronously by the `Claims` extractor.
+    user_roles: std::sync::RwLock<HashMap<i32, String>>,
 }
 
 impl PermissionCache {
     // ELI5: We're making a special box (Arc) to hold our permission rules that can be safely shared
     // across different parts of our web server. Like a rulebook that many teachers can look at
     // at the same time to check if students are allowed to do something.
-    pub fn new(pool: Pool) -> Arc<Self> {
+    pub fn new(pool: Pool, redis: redis::Client) -> Arc<Self> {
         Arc::new(Self {
             pool,
+            redis,
             cache: RwLock::new(HashMap::new()),
+            user_roles: std::sync::RwLock::new(HashMap::new()),
         })
     }
 
@@ -47,12 +62,87 @@ impl PermissionCache {
                 .push(permission);
         }
 
+        // Blocked accounts count as the blocked role whichever way they were blocked
+        let user_roles: HashMap<i32, String> = client
+            .query(
+                "SELECT userid,
+                        CASE WHEN disabled THEN $1 ELSE LOWER(role::text) END AS role
+                 FROM users
+                 WHERE role_changed_at > NOW() - make_interval(hours => $2)",
+                &[&UserRole::Blocked.to_string(), &ROLE_CHANGE_WINDOW_HOURS],
+            )
+            .await?
+            .iter()
+            .map(|row| (row.get("userid"), row.get("role")))
+            .collect();
+
         let mut write_cache = self.cache.write().await;
         *write_cache = cache;
+        drop(write_cache);
+
+        let mut write_roles = self
+            .user_roles
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *write_roles = user_roles;
 
         Ok(())
     }
 
+    /// Reloads this instance and tells the others to reload. Call after changing roles, their
+    /// permissions or the role of a user.
+    pub async fn invalidate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.load_permissions().await?;
+        let mut conn = self.redis.get_multiplexed_async_connection().await?;
+        let _: i64 =
+            redis::AsyncCommands::publish(&mut conn, INVALIDATION_CHANNEL, "reload").await?;
+        Ok(())
+    }
+
+    /// Reloads whenever another instance announces a change, for as long as the server runs.
+    /// Changes announced while the subscription is down are picked up by the reload done after
+    /// reconnecting.
+    pub fn listen_for_invalidations(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                match self.redis.get_async_pubsub().await {
+                    Ok(mut pubsub) => match pubsub.subscribe(INVALIDATION_CHANNEL).await {
+                        Ok(()) => {
+                            info!(
+                                "Listening for permission changes on {}",
+                                INVALIDATION_CHANNEL
+                            );
+                            if let Err(e) = self.load_permissions().await {
+                                error!("Failed to reload permissions: {}", e);
+                            }
+                            let mut messages = pubsub.on_message();
+                            while messages.next().await.is_some() {
+                                if let Err(e) = self.load_permissions().await {
+                                    error!("Failed to reload permissions: {}", e);
+                                }
+                            }
+                            error!("Permission invalidation subscription closed");
+                        }
+                        Err(e) => error!("Failed to subscribe to permission changes: {}", e),
+                    },
+                    Err(e) => error!("Failed to connect for permission changes: {}", e),
+                }
+                sleep(RECONNECT_DELAY).await;
+            }
+        });
+    }
+
+    /// Replaces the role a token was issued with by the user's current role, if it changed since.
+    pub fn apply_role_change(&self, claims: &mut Claims) {
+        let user_roles = self
+            .user_roles
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(role) = user_roles.get(&claims.sub) {
+            claims.role = role.clone();
+        }
+    }
+
     pub async fn has_permission(&self, role: String, permission_name: &str) -> bool {
         let cache = self.cache.read().await;
         // Normalize role to lowercase for case-insensitive lookup
@@ -63,6 +153,15 @@ impl PermissionCache {
             .unwrap_or(false)
     }
 
+    /// Like [`Self::has_permission`] for the role in `claims`, also honouring the scopes of a
+    /// personal API token.
+    pub async fn claims_have_permission(&self, claims: &Claims, permission_name: &str) -> bool {
+        claims.allows(permission_name)
+            && self
+                .has_permission(claims.role.clone(), permission_name)
+                .await
+    }
+
     pub async fn get_permissions_for_role(&self, role: String) -> Vec<Permission> {
         let cache = self.cache.read().await;
         // Normalize role to lowercase for case-insensitive lookup
@@ -73,3 +172,124 @@ impl PermissionCache {
             .unwrap_or_default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deadpool_postgres::{Config, Runtime};
+    use std::env;
+
+    fn test_pool() -> Pool {
+        dotenvy::dotenv().ok();
+        let mut cfg = Config::new();
+        cfg.host = Some(env::var("DB_HOST").unwrap_or_else(|_| "localhost".into()));
+        cfg.port = Some(
+            env::var("DB_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(5432),
+        );
+        cfg.user = Some(env::var("DB_USER").expect("DB_USER"));
+        cfg.password = Some(env::var("DB_PASSWORD").expect("DB_PASSWORD"));
+        cfg.dbname = Some(env::var("DB_NAME").expect("DB_NAME"));
+        cfg.create_pool(Some(Runtime::Tokio1), tokio_postgres::NoTls)
+            .unwrap()
+    }
+
+    /// Neither the pool nor the Redis client connects until used.
+    fn unconnected_cache() -> Arc<PermissionCache> {
+        let mut cfg = Config::new();
+        cfg.dbname = Some("unused".to_string());
+        let pool = cfg
+            .create_pool(Some(Runtime::Tokio1), tokio_postgres::NoTls)
+            .unwrap();
+        PermissionCache::new(pool, redis::Client::open("redis://127.0.0.1/").unwrap())
+    }
+
+    fn claims(sub: i32, role: &str) -> Claims {
+        Claims {
+            sub,
+            exp: 0,
+            username: "la gerku".to_string(),
+            email: "gerku@example.org".to_string(),
+            created_at: 0,
+            role: role.to_string(),
+            email_confirmed: true,
+            authorities: Vec::new(),
+            sid: None,
+            mfa_at: None,
+            scopes: None,
+        }
+    }
+
+    #[test]
+    fn role_changes_replace_the_role_in_live_tokens() {
+        let cache = unconnected_cache();
+        cache
+            .user_roles
+            .write()
+            .unwrap()
+            .insert(1, UserRole::Blocked.to_string());
+
+        let mut changed = claims(1, "admin");
+        cache.apply_role_change(&mut changed);
+        assert!(changed.is_blocked());
+
+        let mut unchanged = claims(2, "editor");
+        cache.apply_role_change(&mut unchanged);
+        assert_eq!(unchanged.role, "editor");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn reload_picks_up_recent_role_changes_and_blocks() {
+        let pool = test_pool();
+        let client = pool.get().await.unwrap();
+        let mut user_ids = Vec::new();
+        for (label, role, disabled, changed_hours_ago) in [
+            ("promoted", "editor", false, 1),
+            ("blocked", "user", true, 1),
+            ("long-ago", "editor", false, ROLE_CHANGE_WINDOW_HOURS + 1),
+        ] {
+            let username = format!("{}-{}", label, std::process::id());
+            let email = format!("{}@example.org", username);
+            let user_id: i32 = client
+                .query_one(
+                    "INSERT INTO users (username, email, password, created_at, role, email_confirmed,
+                                        votesize, disabled, role_changed_at)
+                     VALUES ($1, $2, 'x', NOW(), $3, true, 1.0, $4,
+                             NOW() - make_interval(hours => $5))
+                     RETURNING userid",
+                    &[&username, &email, &role, &disabled, &changed_hours_ago],
+                )
+                .await
+                .unwrap()
+                .get("userid");
+            user_ids.push(user_id);
+        }
+
+        let cache = PermissionCache::new(
+            pool.clone(),
+            redis::Client::open("redis://127.0.0.1/").unwrap(),
+        );
+        let loaded = cache.load_permissions().await;
+        // New users get a balance row from a trigger
+        for cleanup in [
+            "DELETE FROM user_balances WHERE user_id = ANY($1)",
+            "DELETE FROM users WHERE userid = ANY($1)",
+        ] {
+            client.execute(cleanup, &[&user_ids]).await.unwrap();
+        }
+        loaded.unwrap();
+
+        let roles: Vec<String> = user_ids
+            .iter()
+            .map(|user_id| {
+                let mut token = claims(*user_id, "user");
+                cache.apply_role_change(&mut token);
+                token.role
+            })
+            .collect();
+        assert_eq!(roles, ["editor", "blocked", "user"]);
+    }
+}
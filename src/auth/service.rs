@@ -10,7 +10,10 @@ use rand::distr::Alphanumeric;
 use std::env;
 use tokio::task;
 
-use actix_web::{dev::ServiceRequest, error::ErrorUnauthorized, HttpMessage};
+use actix_web::error::{
+    ErrorForbidden, ErrorInternalServerError, ErrorTooManyRequests, ErrorUnauthorized,
+};
+use actix_web::{dev::ServiceRequest, HttpMessage, HttpRequest};
 use actix_web::{web, Error};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
@@ -22,16 +25,21 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 use super::error::EmailError;
+use crate::audit::{self, AuditAction, AuditContext};
 use crate::auth::models::UserRole;
 use crate::auth::{AuthResponse, RoleWithPermissions, SignupRequest};
+use crate::middleware::cache::RedisCache;
 use crate::middleware::limiter::{EmailConfirmationLimiter, PasswordResetLimiter};
 use crate::notifications::service::EmailNotification;
 use crate::notifications::EmailService;
 use crate::sessions;
 use crate::{AppError, AppResult};
 
+use super::api_tokens;
 use super::error::PasswordHashError;
-use super::models::TokenPair;
+use super::models::{decode_token, LoginOutcome, TokenPair};
+use super::permissions::PermissionCache;
+use super::two_factor;
 use super::{
     Claims, CompletePasswordChangeRequest, CompletePasswordChangeResponse, CreateRoleRequest,
     FollowResponse, InitiatePasswordChangeRequest, InitiatePasswordChangeResponse, LoginRequest,
@@ -163,6 +171,7 @@ pub async fn get_roles_with_permissions(
 
 pub async fn create_role(
     pool: &Pool,
+    ctx: &AuditContext,
     request: &CreateRoleRequest,
     actor_role: &str,
 ) -> AppResult<RoleResponse> {
@@ -224,6 +233,17 @@ pub async fn create_role(
             .await?;
     }
 
+    let after = audit::service::role_snapshot(&transaction, &request.name).await?;
+    audit::service::record(
+        &transaction,
+        ctx,
+        AuditAction::CreateRole,
+        &request.name,
+        None,
+        Some(after),
+    )
+    .await?;
+
     transaction.commit().await?;
 
     Ok(RoleResponse {
@@ -234,6 +254,7 @@ pub async fn create_role(
 
 pub async fn update_role(
     pool: &Pool,
+    ctx: &AuditContext,
     role_name: &str,
     request: &UpdateRoleRequest,
     actor_role: &str,
@@ -271,6 +292,8 @@ pub async fn update_role(
         }
     }
 
+    let before = audit::service::role_snapshot(&transaction, role_name).await?;
+
     // Clear existing permissions (case-insensitive)
     transaction
         .execute(
@@ -290,6 +313,17 @@ pub async fn update_role(
             .await?;
     }
 
+    let after = audit::service::role_snapshot(&transaction, role_name).await?;
+    audit::service::record(
+        &transaction,
+        ctx,
+        AuditAction::UpdateRole,
+        role_name,
+        Some(before),
+        Some(after),
+    )
+    .await?;
+
     transaction.commit().await?;
 
     Ok(RoleResponse {
@@ -298,7 +332,7 @@ pub async fn update_role(
     })
 }
 
-pub async fn delete_role(pool: &Pool, role_name: &str) -> AppResult<()> {
+pub async fn delete_role(pool: &Pool, ctx: &AuditContext, role_name: &str) -> AppResult<()> {
     let protected_roles = ["admin", "editor", "unconfirmed", "blocked"];
     if protected_roles
         .iter()
@@ -315,11 +349,13 @@ pub async fn delete_role(pool: &Pool, role_name: &str) -> AppResult<()> {
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
     let transaction = client.transaction().await?;
+    let before = audit::service::role_snapshot(&transaction, role_name).await?;
 
     // Convert users to default 'user' role (case-insensitive)
     transaction
         .execute(
-            "UPDATE users SET role = 'user' WHERE LOWER(role::text) = LOWER($1::text)",
+            "UPDATE users SET role = 'user', role_changed_at = NOW()
+             WHERE LOWER(role::text) = LOWER($1::text)",
             &[&role_name],
         )
         .await?;
@@ -332,13 +368,24 @@ pub async fn delete_role(pool: &Pool, role_name: &str) -> AppResult<()> {
         )
         .await?;
 
+    audit::service::record(
+        &transaction,
+        ctx,
+        AuditAction::DeleteRole,
+        role_name,
+        Some(before),
+        None,
+    )
+    .await?;
+
     transaction.commit().await?;
     Ok(())
 }
 
 pub async fn block_user(
     pool: &Pool,
-    actor_id: i32,
+    redis_cache: &RedisCache,
+    ctx: &AuditContext,
     target_user_id: i32,
     block: bool,
 ) -> AppResult<()> {
@@ -348,6 +395,30 @@ pub async fn block_user(
         .map_err(|e| AppError::Database(e.to_string()))?;
     let transaction = client.transaction().await?;
 
+    block_user_with_transaction(&transaction, redis_cache, ctx, target_user_id, block).await?;
+
+    log::debug!("Attempting to commit transaction");
+    match transaction.commit().await {
+        Ok(_) => log::debug!("Transaction committed successfully"),
+        Err(e) => {
+            error!("Failed to commit transaction: {}", e); // Keep log
+            return Err(AppError::Database(e.to_string())); // Return AppError
+        }
+    }
+    Ok(())
+}
+
+/// Blocks or unblocks the user. Blocking also signs the user out of every session before the
+/// caller commits, so a block never lands while the user's tokens keep working.
+pub async fn block_user_with_transaction(
+    transaction: &deadpool_postgres::Transaction<'_>,
+    redis_cache: &RedisCache,
+    ctx: &AuditContext,
+    target_user_id: i32,
+    block: bool,
+) -> AppResult<()> {
+    let actor_id = ctx.actor_id;
+
     // Check if actor has block_users permission (case-insensitive role comparison)
     let has_block_users: bool = transaction
         .query_one(
@@ -390,35 +461,58 @@ pub async fn block_user(
         ));
     }
 
+    let before = audit::service::user_snapshot(transaction, target_user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
     // Update user's disabled status
     transaction
         .execute(
             "UPDATE users 
              SET disabled = $1,
                  disabled_at = CASE WHEN $1 THEN NOW() ELSE NULL END,
-                 disabled_by = CASE WHEN $1 THEN $2 ELSE NULL::integer END
+                 disabled_by = CASE WHEN $1 THEN $2 ELSE NULL::integer END,
+                 role_changed_at = NOW()
              WHERE userid = $3",
             &[&block, &actor_id, &target_user_id],
         )
         .await?;
 
-    log::debug!("Attempting to commit transaction");
-    match transaction.commit().await {
-        Ok(_) => log::debug!("Transaction committed successfully"),
-        Err(e) => {
-            error!("Failed to commit transaction: {}", e); // Keep log
-            return Err(AppError::Database(e.to_string())); // Return AppError
-        }
+    let after = audit::service::user_snapshot(transaction, target_user_id).await?;
+    audit::service::record(
+        transaction,
+        ctx,
+        if block {
+            AuditAction::BlockUser
+        } else {
+            AuditAction::UnblockUser
+        },
+        &target_user_id.to_string(),
+        Some(before),
+        after,
+    )
+    .await?;
+
+    if block {
+        sessions::service::revoke_other_sessions_with_client(
+            transaction,
+            redis_cache,
+            target_user_id,
+            None,
+        )
+        .await?;
     }
+
     Ok(())
 }
 
 pub async fn assign_role(
     pool: &Pool,
-    assigner_id: i32,
+    ctx: &AuditContext,
     target_user_id: i32,
     new_role: String,
 ) -> AppResult<()> {
+    let assigner_id = ctx.actor_id;
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
@@ -482,6 +576,10 @@ pub async fn assign_role(
         }
     }
 
+    let before = audit::service::user_snapshot(&transaction, target_user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
     // Update user's role and disable status
     transaction
         .execute(
@@ -489,12 +587,24 @@ pub async fn assign_role(
              SET role = $1,
                  disabled = CASE WHEN LOWER($1::text) = 'blocked' THEN true ELSE false END,
                  disabled_at = CASE WHEN LOWER($1::text) = 'blocked' THEN NOW() ELSE NULL END,
-                 disabled_by = CASE WHEN LOWER($1::text) = 'blocked' THEN $3 ELSE NULL::integer END
+                 disabled_by = CASE WHEN LOWER($1::text) = 'blocked' THEN $3 ELSE NULL::integer END,
+                 role_changed_at = NOW()
              WHERE userid = $2",
             &[&new_role, &target_user_id, &assigner_id],
         )
         .await?;
 
+    let after = audit::service::user_snapshot(&transaction, target_user_id).await?;
+    audit::service::record(
+        &transaction,
+        ctx,
+        AuditAction::AssignRole,
+        &target_user_id.to_string(),
+        Some(before),
+        after,
+    )
+    .await?;
+
     transaction.commit().await?;
     Ok(())
 }
@@ -523,6 +633,8 @@ pub fn generate_token(user: &User) -> AppResult<String> {
         email_confirmed: user.email_confirmed,
         authorities: Vec::new(), // Will be populated in create_token_pair
         sid: None,               // Will be populated by specific token generation functions
+        mfa_at: None,
+        scopes: None,
     };
 
     let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
@@ -535,27 +647,89 @@ pub fn generate_token(user: &User) -> AppResult<String> {
     .map_err(|e| AppError::Auth(format!("Token encoding error: {}", e)))
 }
 
+/// Checks a session token the way every authenticated request does: signature and expiry,
+/// role changes made since it was issued, blocked accounts and revoked sessions. Used by
+/// [`validator`], the `Claims` extractor and the WebSocket handshake.
+pub async fn authenticate_session_token(
+    req: &HttpRequest,
+    token: &str,
+) -> Result<Claims, &'static str> {
+    let mut claims = decode_token(token).map_err(|_| "Invalid token")?;
+    if let Some(perm_cache) = req.app_data::<web::Data<PermissionCache>>() {
+        perm_cache.apply_role_change(&mut claims);
+    }
+    if claims.is_blocked() {
+        return Err("Account is blocked");
+    }
+    // Fail open if Redis is unavailable: refresh still checks revocation in the database
+    let redis_cache = req.app_data::<web::Data<RedisCache>>();
+    if let (Some(session_uuid), Some(redis_cache)) = (claims.sid, redis_cache) {
+        match sessions::service::is_session_revoked(redis_cache, session_uuid).await {
+            Ok(true) => return Err("Session revoked"),
+            Ok(false) => {}
+            Err(e) => error!(
+                "Failed to check revocation of session {}: {}",
+                session_uuid, e
+            ),
+        }
+    }
+    Ok(claims)
+}
+
 pub async fn validator(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
     let token = credentials.token();
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-
-    let validation = Validation::new(Algorithm::HS256);
-    match decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(token_data) => {
-            req.extensions_mut().insert(token_data.claims);
+    if api_tokens::is_api_token(token) {
+        return validate_api_token(req, token).await;
+    }
+    match authenticate_session_token(req.request(), token).await {
+        Ok(claims) => {
+            req.extensions_mut().insert(claims);
             Ok(req)
         }
-        Err(_) => Err((ErrorUnauthorized("Invalid token"), req)),
+        Err(message) => Err((ErrorUnauthorized(message), req)),
     }
 }
 
+async fn validate_api_token(
+    req: ServiceRequest,
+    token: &str,
+) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let Some(pool) = req.app_data::<web::Data<Pool>>().cloned() else {
+        return Err((
+            ErrorInternalServerError("Database pool not configured"),
+            req,
+        ));
+    };
+    let token = match api_tokens::authenticate(&pool, token).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return Err((ErrorUnauthorized("Invalid token"), req)),
+        Err(e) => {
+            error!("Failed to check API token: {}", e);
+            return Err((e.into(), req));
+        }
+    };
+    if token.claims.is_blocked() {
+        return Err((ErrorUnauthorized("Account is blocked"), req));
+    }
+    let scopes = token.claims.scopes.as_deref().unwrap_or_default();
+    if let Err(message) = api_tokens::check_request(scopes, req.method(), req.path()) {
+        return Err((ErrorForbidden(message), req));
+    }
+    // Fails open like session revocation: the token itself was checked in the database
+    if let Some(redis_cache) = req.app_data::<web::Data<RedisCache>>() {
+        match api_tokens::record_request(redis_cache, &token).await {
+            Ok(true) => {}
+            Ok(false) => return Err((ErrorTooManyRequests("API token rate limit exceeded"), req)),
+            Err(e) => error!("Failed to rate limit API token: {}", e),
+        }
+    }
+    req.extensions_mut().insert(token.claims);
+    Ok(req)
+}
+
 pub async fn signup(
     pool: &Pool,
     user_data: &SignupRequest,
@@ -756,10 +930,11 @@ pub async fn resend_confirmation(
 
 pub async fn login(
     pool: &Pool,
+    redis_cache: &RedisCache,
     user_data: &LoginRequest,
     ip_address: String,
     user_agent: String,
-) -> AppResult<TokenPair> {
+) -> AppResult<LoginOutcome> {
     let mut client = pool
         .get()
         .await
@@ -769,7 +944,7 @@ pub async fn login(
     let user_query_result = transaction
         .query_opt(
             "SELECT userid, username, email, password, created_at, followers,
-                    role, email_confirmed
+                    role, email_confirmed, disabled
              FROM users
              WHERE username = $1 OR email = $1",
             &[&user_data.username_or_email],
@@ -778,10 +953,11 @@ pub async fn login(
 
     match user_query_result {
         Some(row) => {
+            let disabled: bool = row.get("disabled");
             // Then create the User struct. If User::from doesn't use 'user_uuid', it's fine.
             let user = User::from(row);
 
-            if user.role.to_lowercase() == UserRole::Blocked.to_string() {
+            if disabled || user.role.to_lowercase() == UserRole::Blocked.to_string() {
                 Err(AppError::Auth("Account is blocked".to_string()))
             } else if verify_password(&user_data.password, &user.password).unwrap_or(false) {
                 let mut user_for_token = user.clone();
@@ -798,6 +974,19 @@ pub async fn login(
                     user_for_token.password = new_hash;
                 }
 
+                let methods = two_factor::enabled_methods(pool, user.userid).await?;
+                if !methods.is_empty() {
+                    transaction.commit().await?;
+                    let challenge = two_factor::start_pending_login(
+                        redis_cache,
+                        user.userid,
+                        &user_data.username_or_email,
+                        methods,
+                    )
+                    .await?;
+                    return Ok(LoginOutcome::SecondFactorRequired(challenge));
+                }
+
                 // Start user session before committing and creating token pair
                 let mut jwt_session_id: Option<Uuid> = None;
                 match sessions::service::start_session(pool, user.userid, ip_address, user_agent)
@@ -825,7 +1014,9 @@ pub async fn login(
                 }
 
                 transaction.commit().await?;
-                create_token_pair(pool, &user_for_token, jwt_session_id).await
+                create_token_pair(pool, &user_for_token, jwt_session_id, None)
+                    .await
+                    .map(LoginOutcome::Tokens)
             } else {
                 Err(AppError::Auth("Invalid credentials".to_string()))
             }
@@ -1130,7 +1321,7 @@ pub async fn restore_password(
 
     transaction
         .execute(
-            "UPDATE users SET password = $1 WHERE email = $2",
+            "UPDATE users SET password = $1, oauth_signup = false WHERE email = $2",
             &[&password_hash, &email],
         )
         .await?;
@@ -1153,10 +1344,12 @@ pub async fn restore_password(
     }))
 }
 
+/// `mfa_at` is when the user last confirmed a second factor in this session, if ever.
 pub async fn create_token_pair(
     pool: &Pool,
     user: &User,
     session_id: Option<Uuid>,
+    mfa_at: Option<i64>,
 ) -> AppResult<TokenPair> {
     // Get permissions for the user's role (map potential errors)
     // Use case-insensitive role comparison
@@ -1176,8 +1369,8 @@ pub async fn create_token_pair(
         .map(|row| row.get::<_, String>("name"))
         .collect();
 
-    let access_token = generate_access_token(user, &authorities, session_id)?;
-    let refresh_token = generate_refresh_token(user, session_id)?;
+    let access_token = generate_access_token(user, &authorities, session_id, mfa_at)?;
+    let refresh_token = generate_refresh_token(user, session_id, mfa_at)?;
 
     Ok(TokenPair {
         access_token,
@@ -1189,6 +1382,7 @@ pub fn generate_access_token(
     user: &User,
     authorities: &[String],
     session_id: Option<Uuid>,
+    mfa_at: Option<i64>,
 ) -> AppResult<String> {
     let expiration = Utc::now()
         .checked_add_signed(Duration::hours(24))
@@ -1205,6 +1399,8 @@ pub fn generate_access_token(
         email_confirmed: user.email_confirmed,
         authorities: authorities.to_vec(),
         sid: session_id,
+        mfa_at,
+        scopes: None,
     };
 
     let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
@@ -1217,7 +1413,11 @@ pub fn generate_access_token(
     .map_err(|e| AppError::Auth(format!("Token encoding error: {}", e)))
 }
 
-pub fn generate_refresh_token(user: &User, session_id: Option<Uuid>) -> AppResult<String> {
+pub fn generate_refresh_token(
+    user: &User,
+    session_id: Option<Uuid>,
+    mfa_at: Option<i64>,
+) -> AppResult<String> {
     let expiration = Utc::now()
         .checked_add_signed(Duration::days(30))
         .ok_or_else(|| {
@@ -1235,6 +1435,8 @@ pub fn generate_refresh_token(user: &User, session_id: Option<Uuid>) -> AppResul
         email_confirmed: user.email_confirmed,
         authorities: Vec::new(), // Not needed for refresh token but required by struct
         sid: session_id,
+        mfa_at,
+        scopes: None,
     };
 
     let secret = env::var("REFRESH_TOKEN_SECRET").expect("REFRESH_TOKEN_SECRET must be set");
@@ -1266,6 +1468,12 @@ pub async fn refresh_tokens(
 
     let claims = token_data.claims;
 
+    if let Some(session_uuid) = claims.sid {
+        if sessions::service::was_session_revoked(pool, session_uuid).await? {
+            return Err(AppError::Unauthorized("Session revoked".to_string()));
+        }
+    }
+
     // Attempt to update session activity
     if let Some(session_uuid) = claims.sid {
         match sessions::service::get_session_id_from_uuid(pool, session_uuid).await {
@@ -1321,14 +1529,18 @@ pub async fn refresh_tokens(
     let row = client
         .query_one(
             // Ensure the query for User struct is correct and doesn't conflict with 'id as user_uuid' if that alias was specific to login
-            "SELECT userid, username, email, password, created_at, followers, role, email_confirmed FROM users WHERE userid = $1",
+            "SELECT userid, username, email, password, created_at, followers, role, email_confirmed, disabled FROM users WHERE userid = $1",
             &[&claims.sub],
         )
         .await?;
+    let disabled: bool = row.get("disabled");
     let user = User::from(row);
+    if disabled || user.role.to_lowercase() == UserRole::Blocked.to_string() {
+        return Err(AppError::Auth("Account is blocked".to_string()));
+    }
 
     // Generate new token pair, passing the original session_id from the refresh token
-    create_token_pair(pool, &user, claims.sid).await
+    create_token_pair(pool, &user, claims.sid, claims.mfa_at).await
 }
 
 pub async fn set_following(
@@ -1473,9 +1685,13 @@ pub async fn initiate_password_change(
     })
 }
 
+/// Changes the password and signs out every other session of the user, keeping
+/// `current_session`, in one transaction.
 pub async fn complete_password_change(
     pool: &Pool,
+    redis_cache: &RedisCache,
     user_id: i32,
+    current_session: Option<Uuid>,
     request: &CompletePasswordChangeRequest,
 ) -> AppResult<CompletePasswordChangeResponse> {
     let mut client = pool
@@ -1519,7 +1735,7 @@ pub async fn complete_password_change(
     // Update password
     transaction
         .execute(
-            "UPDATE users SET password = $1 WHERE userid = $2",
+            "UPDATE users SET password = $1, oauth_signup = false WHERE userid = $2",
             &[&password_hash, &user_id],
         )
         .await?;
@@ -1534,6 +1750,14 @@ pub async fn complete_password_change(
         )
         .await?;
 
+    sessions::service::revoke_other_sessions_with_client(
+        &transaction,
+        redis_cache,
+        user_id,
+        current_session,
+    )
+    .await?;
+
     transaction.commit().await?;
 
     Ok(CompletePasswordChangeResponse {
@@ -0,0 +1,735 @@
+//! Optional second factors: TOTP codes (RFC 6238), one-time recovery codes and passkeys
+//! (see [`super::passkeys`]).
+//!
+//! When an account has a second factor, password and OAuth logins stop at a
+//! [`SecondFactorChallenge`]: the half-finished login is kept in Redis under a random
+//! `mfa_token` and [`complete_login`] issues the tokens once a factor is confirmed. Tokens issued
+//! after a second factor carry its time in `mfa_at`. Users with a second factor whose role holds
+//! one of [`DESTRUCTIVE_PERMISSIONS`] need it to be recent (see [`require_step_up`]), which
+//! [`step_up`] renews without logging in again. Accounts without a factor have nothing to
+//! confirm. API tokens cannot step up: granting a token a destructive scope needs the recent
+//! factor instead, when the token is created.
+
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use deadpool_postgres::{Pool, Transaction};
+use hmac::{Hmac, KeyInit, Mac};
+use rand::distr::Alphanumeric;
+use rand::{rng, RngExt};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use webauthn_rs::prelude::{CreationChallengeResponse, RequestChallengeResponse};
+
+use super::dto::{
+    PasskeyRegisteredResponse, PasskeyRegistrationRequest, RecoveryCodesResponse,
+    SecondFactorChallenge, SecondFactorLoginRequest, SecondFactorProof, TotpSetupResponse,
+    TwoFactorStatusResponse,
+};
+use super::models::{Claims, TokenPair, User, UserRole};
+use super::passkeys;
+use super::permissions::PermissionCache;
+use super::service::create_token_pair;
+use crate::middleware::cache::RedisCache;
+use crate::sessions;
+use crate::{AppError, AppResult};
+
+/// Permissions that need a second factor confirmed within [`STEP_UP_WINDOW_SECS`].
+const DESTRUCTIVE_PERMISSIONS: &[&str] = &[
+    "manage_roles",
+    "block_users",
+    "revert_entry_version",
+    "bulk_import",
+];
+const STEP_UP_WINDOW_SECS: i64 = 15 * 60;
+/// Message of the 403 returned when an action needs a recent second factor.
+pub const STEP_UP_REQUIRED: &str = "Two-factor confirmation required";
+const PENDING_LOGIN_TTL_SECS: u64 = 5 * 60;
+const MFA_TOKEN_LEN: usize = 40;
+
+const TOTP_ISSUER: &str = "Lensisku";
+const TOTP_STEP_SECS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+/// Codes of the previous and next time step are accepted too, to allow for clock drift.
+const TOTP_SKEW_STEPS: i64 = 1;
+const TOTP_SECRET_BYTES: usize = 20;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// A login whose password (or OAuth provider) was accepted, waiting for a second factor.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub user_id: i32,
+    /// What the user logged in with, for `LoginLimiter`
+    pub identifier: String,
+}
+
+/// HOTP value (RFC 4226) of `counter`.
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = <Hmac<Sha1> as KeyInit>::new_from_slice(secret)
+        .unwrap_or_else(|_| unreachable!("HMAC accepts keys of any length"));
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+/// Time step whose TOTP code is `code`, among those around `unix_time`.
+fn matching_step(secret: &[u8], code: &str, unix_time: i64) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let current = unix_time.div_euclid(TOTP_STEP_SECS);
+    (current - TOTP_SKEW_STEPS..=current + TOTP_SKEW_STEPS)
+        .find(|step| hotp(secret, *step as u64) == code)
+}
+
+fn generate_totp_secret() -> String {
+    let bytes: [u8; TOTP_SECRET_BYTES] = rng().random();
+    BASE32_NOPAD.encode(&bytes)
+}
+
+fn decode_totp_secret(secret: &str) -> AppResult<Vec<u8>> {
+    BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .map_err(|e| AppError::Internal(format!("Invalid TOTP secret: {}", e)))
+}
+
+fn otpauth_url(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&digits={digits}&period={period}",
+        issuer = TOTP_ISSUER,
+        user = urlencoding::encode(username),
+        secret = secret,
+        digits = TOTP_DIGITS,
+        period = TOTP_STEP_SECS,
+    )
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = rng()
+                .sample_iter(&Alphanumeric)
+                .take(RECOVERY_CODE_LEN)
+                .map(|b| (b as char).to_ascii_lowercase())
+                .collect();
+            let (first, second) = code.split_at(RECOVERY_CODE_LEN / 2);
+            format!("{}-{}", first, second)
+        })
+        .collect()
+}
+
+/// Codes are compared ignoring case, dashes and spaces.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    Sha256::digest(normalized.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn invalid_code() -> AppError {
+    AppError::Unauthorized("Invalid two-factor code".to_string())
+}
+
+fn login_expired() -> AppError {
+    AppError::Unauthorized("Login expired, please sign in again".to_string())
+}
+
+fn pending_login_key(mfa_token: &str) -> String {
+    format!("two_factor:login:{}", mfa_token)
+}
+
+fn passkey_login_state(mfa_token: &str) -> String {
+    format!("login:{}", mfa_token)
+}
+
+fn passkey_step_up_state(user_id: i32) -> String {
+    format!("step_up:{}", user_id)
+}
+
+/// Second factors the user can log in with; empty if two-factor authentication is off.
+pub async fn enabled_methods(pool: &Pool, user_id: i32) -> AppResult<Vec<String>> {
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            "SELECT
+                EXISTS (SELECT 1 FROM user_totp WHERE user_id = $1 AND enabled_at IS NOT NULL) AS totp,
+                EXISTS (SELECT 1 FROM user_passkeys WHERE user_id = $1) AS passkey,
+                EXISTS (SELECT 1 FROM user_recovery_codes WHERE user_id = $1 AND used_at IS NULL)
+                    AS recovery_code",
+            &[&user_id],
+        )
+        .await?;
+    let totp: bool = row.get("totp");
+    let passkey: bool = row.get("passkey");
+    if !totp && !passkey {
+        return Ok(Vec::new());
+    }
+    Ok(["totp", "passkey", "recovery_code"]
+        .into_iter()
+        .filter(|method| row.get::<_, bool>(*method))
+        .map(String::from)
+        .collect())
+}
+
+async fn load_active_user(pool: &Pool, user_id: i32) -> AppResult<User> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            "SELECT userid, username, email, password, created_at, followers, role, email_confirmed, disabled
+             FROM users WHERE userid = $1",
+            &[&user_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    let disabled: bool = row.get("disabled");
+    let user = User::from(row);
+    if disabled || user.role.to_lowercase() == UserRole::Blocked.to_string() {
+        return Err(AppError::Auth("Account is blocked".to_string()));
+    }
+    Ok(user)
+}
+
+/// Parks a login whose first factor was accepted until a second factor confirms it.
+pub async fn start_pending_login(
+    redis_cache: &RedisCache,
+    user_id: i32,
+    identifier: &str,
+    methods: Vec<String>,
+) -> AppResult<SecondFactorChallenge> {
+    let mfa_token: String = rng()
+        .sample_iter(&Alphanumeric)
+        .take(MFA_TOKEN_LEN)
+        .map(char::from)
+        .collect();
+    let pending = serde_json::to_string(&PendingLogin {
+        user_id,
+        identifier: identifier.to_string(),
+    })?;
+    let mut conn = redis_cache
+        .client
+        .get_multiplexed_async_connection()
+        .await?;
+    let _: () = conn
+        .set_ex(
+            pending_login_key(&mfa_token),
+            pending,
+            PENDING_LOGIN_TTL_SECS,
+        )
+        .await?;
+    Ok(SecondFactorChallenge {
+        two_factor_required: true,
+        mfa_token,
+        methods,
+        expires_in: PENDING_LOGIN_TTL_SECS,
+    })
+}
+
+pub async fn pending_login(redis_cache: &RedisCache, mfa_token: &str) -> AppResult<PendingLogin> {
+    let mut conn = redis_cache
+        .client
+        .get_multiplexed_async_connection()
+        .await?;
+    let pending: Option<String> = conn.get(pending_login_key(mfa_token)).await?;
+    let pending = pending.ok_or_else(login_expired)?;
+    Ok(serde_json::from_str(&pending)?)
+}
+
+/// Options for confirming a pending login with a passkey.
+pub async fn passkey_login_options(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    mfa_token: &str,
+) -> AppResult<RequestChallengeResponse> {
+    let pending = pending_login(redis_cache, mfa_token).await?;
+    passkeys::start_authentication(
+        pool,
+        redis_cache,
+        pending.user_id,
+        &passkey_login_state(mfa_token),
+    )
+    .await
+}
+
+/// Confirms a pending login with a second factor and starts its session.
+pub async fn complete_login(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    pending: &PendingLogin,
+    request: &SecondFactorLoginRequest,
+    ip_address: String,
+    user_agent: String,
+) -> AppResult<TokenPair> {
+    let user = load_active_user(pool, pending.user_id).await?;
+    verify_second_factor(
+        pool,
+        redis_cache,
+        user.userid,
+        &request.proof,
+        &passkey_login_state(&request.mfa_token),
+    )
+    .await?;
+
+    // Only the request that removes the pending login gets tokens
+    let mut conn = redis_cache
+        .client
+        .get_multiplexed_async_connection()
+        .await?;
+    let removed: i64 = conn.del(pending_login_key(&request.mfa_token)).await?;
+    if removed == 0 {
+        return Err(login_expired());
+    }
+
+    let mut jwt_session_id = None;
+    match sessions::service::start_session(pool, user.userid, ip_address, user_agent).await {
+        Ok(session) => jwt_session_id = Some(session.session_uuid),
+        Err(e) => log::error!(
+            "Failed to start user session for user_id {}: {}",
+            user.userid,
+            e
+        ),
+    }
+    create_token_pair(pool, &user, jwt_session_id, Some(Utc::now().timestamp())).await
+}
+
+async fn verify_second_factor(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    user_id: i32,
+    proof: &SecondFactorProof,
+    passkey_state: &str,
+) -> AppResult<()> {
+    match (&proof.code, &proof.recovery_code, &proof.passkey) {
+        (Some(code), None, None) => verify_totp(pool, user_id, code).await,
+        (None, Some(code), None) => use_recovery_code(pool, user_id, code).await,
+        (None, None, Some(credential)) => {
+            passkeys::finish_authentication(pool, redis_cache, user_id, passkey_state, credential)
+                .await
+        }
+        _ => Err(AppError::BadRequest(
+            "Provide exactly one of code, recovery_code or passkey".to_string(),
+        )),
+    }
+}
+
+async fn verify_totp(pool: &Pool, user_id: i32, code: &str) -> AppResult<()> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            "SELECT secret FROM user_totp WHERE user_id = $1 AND enabled_at IS NOT NULL",
+            &[&user_id],
+        )
+        .await?
+        .ok_or_else(invalid_code)?;
+    let secret = decode_totp_secret(row.get("secret"))?;
+    let step = matching_step(&secret, code, Utc::now().timestamp()).ok_or_else(invalid_code)?;
+
+    // Each time step is accepted once, so an observed code cannot be replayed
+    let updated = client
+        .execute(
+            "UPDATE user_totp SET last_used_step = $2 WHERE user_id = $1 AND last_used_step < $2",
+            &[&user_id, &step],
+        )
+        .await?;
+    if updated == 0 {
+        return Err(invalid_code());
+    }
+    Ok(())
+}
+
+async fn use_recovery_code(pool: &Pool, user_id: i32, code: &str) -> AppResult<()> {
+    let client = pool.get().await?;
+    let updated = client
+        .execute(
+            "UPDATE user_recovery_codes SET used_at = NOW()
+             WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+            &[&user_id, &hash_recovery_code(code)],
+        )
+        .await?;
+    if updated == 0 {
+        return Err(invalid_code());
+    }
+    Ok(())
+}
+
+pub fn has_recent_second_factor(claims: &Claims) -> bool {
+    claims
+        .mfa_at
+        .is_some_and(|mfa_at| Utc::now().timestamp() - mfa_at <= STEP_UP_WINDOW_SECS)
+}
+
+pub fn is_destructive_permission(permission: &str) -> bool {
+    DESTRUCTIVE_PERMISSIONS.contains(&permission)
+}
+
+async fn holds_destructive_permission(perm_cache: &PermissionCache, claims: &Claims) -> bool {
+    for permission in DESTRUCTIVE_PERMISSIONS {
+        if perm_cache.claims_have_permission(claims, permission).await {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether the request has to confirm a second factor before a destructive action.
+fn step_up_needed(claims: &Claims, destructive_role: bool, has_factor: bool) -> bool {
+    destructive_role && has_factor && !claims.is_api_token() && !has_recent_second_factor(claims)
+}
+
+/// Rejects the request if the user has a second factor, their role holds a destructive
+/// permission and they have not confirmed the factor recently. Call before performing a
+/// destructive action.
+pub async fn require_step_up(
+    pool: &Pool,
+    perm_cache: &PermissionCache,
+    claims: &Claims,
+) -> AppResult<()> {
+    if claims.is_api_token()
+        || has_recent_second_factor(claims)
+        || !holds_destructive_permission(perm_cache, claims).await
+    {
+        return Ok(());
+    }
+    let has_factor = !enabled_methods(pool, claims.sub).await?.is_empty();
+    if step_up_needed(claims, true, has_factor) {
+        Err(AppError::Auth(STEP_UP_REQUIRED.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Options for confirming a passkey during step-up.
+pub async fn passkey_step_up_options(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    user_id: i32,
+) -> AppResult<RequestChallengeResponse> {
+    passkeys::start_authentication(pool, redis_cache, user_id, &passkey_step_up_state(user_id))
+        .await
+}
+
+/// Confirms a second factor for the current session and issues tokens recording it.
+pub async fn step_up(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    claims: &Claims,
+    proof: &SecondFactorProof,
+) -> AppResult<TokenPair> {
+    let user = load_active_user(pool, claims.sub).await?;
+    verify_second_factor(
+        pool,
+        redis_cache,
+        user.userid,
+        proof,
+        &passkey_step_up_state(user.userid),
+    )
+    .await?;
+    create_token_pair(pool, &user, claims.sid, Some(Utc::now().timestamp())).await
+}
+
+/// Adding or removing factors of an account that has one needs a recent second factor, so that
+/// a stolen session cannot take the account over.
+async fn ensure_can_change_factors(pool: &Pool, claims: &Claims) -> AppResult<()> {
+    if has_recent_second_factor(claims) || enabled_methods(pool, claims.sub).await?.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Auth(STEP_UP_REQUIRED.to_string()))
+    }
+}
+
+fn ensure_recent_second_factor(claims: &Claims) -> AppResult<()> {
+    if has_recent_second_factor(claims) {
+        Ok(())
+    } else {
+        Err(AppError::Auth(STEP_UP_REQUIRED.to_string()))
+    }
+}
+
+async fn replace_recovery_codes(
+    transaction: &Transaction<'_>,
+    user_id: i32,
+) -> AppResult<Vec<String>> {
+    let codes = generate_recovery_codes();
+    let hashes: Vec<String> = codes.iter().map(|code| hash_recovery_code(code)).collect();
+    transaction
+        .execute(
+            "DELETE FROM user_recovery_codes WHERE user_id = $1",
+            &[&user_id],
+        )
+        .await?;
+    transaction
+        .execute(
+            "INSERT INTO user_recovery_codes (user_id, code_hash)
+             SELECT $1, UNNEST($2::text[])",
+            &[&user_id, &hashes],
+        )
+        .await?;
+    Ok(codes)
+}
+
+/// Recovery codes only stand in for a factor; drop them once the last factor is removed.
+async fn drop_recovery_codes_without_factor(pool: &Pool, user_id: i32) -> AppResult<()> {
+    let client = pool.get().await?;
+    client
+        .execute(
+            "DELETE FROM user_recovery_codes
+             WHERE user_id = $1
+               AND NOT EXISTS (SELECT 1 FROM user_totp WHERE user_id = $1 AND enabled_at IS NOT NULL)
+               AND NOT EXISTS (SELECT 1 FROM user_passkeys WHERE user_id = $1)",
+            &[&user_id],
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn status(
+    pool: &Pool,
+    perm_cache: &PermissionCache,
+    claims: &Claims,
+) -> AppResult<TwoFactorStatusResponse> {
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            "SELECT
+                EXISTS (SELECT 1 FROM user_totp WHERE user_id = $1 AND enabled_at IS NOT NULL)
+                    AS totp_enabled,
+                (SELECT COUNT(*) FROM user_recovery_codes WHERE user_id = $1 AND used_at IS NULL)
+                    AS recovery_codes_remaining",
+            &[&claims.sub],
+        )
+        .await?;
+    Ok(TwoFactorStatusResponse {
+        totp_enabled: row.get("totp_enabled"),
+        passkeys: passkeys::list_passkeys(pool, claims.sub).await?,
+        recovery_codes_remaining: row.get("recovery_codes_remaining"),
+        step_up_required: holds_destructive_permission(perm_cache, claims).await
+            && !enabled_methods(pool, claims.sub).await?.is_empty(),
+    })
+}
+
+/// Creates a new TOTP secret. It is only used once confirmed with [`confirm_totp_setup`].
+pub async fn begin_totp_setup(pool: &Pool, claims: &Claims) -> AppResult<TotpSetupResponse> {
+    ensure_can_change_factors(pool, claims).await?;
+    let secret = generate_totp_secret();
+    let client = pool.get().await?;
+    let updated = client
+        .execute(
+            "INSERT INTO user_totp (user_id, secret) VALUES ($1, $2)
+             ON CONFLICT (user_id) DO UPDATE SET secret = EXCLUDED.secret, created_at = NOW()
+             WHERE user_totp.enabled_at IS NULL",
+            &[&claims.sub, &secret],
+        )
+        .await?;
+    if updated == 0 {
+        return Err(AppError::BadRequest("TOTP is already enabled".to_string()));
+    }
+    Ok(TotpSetupResponse {
+        otpauth_url: otpauth_url(&secret, &claims.username),
+        secret,
+    })
+}
+
+/// Enables TOTP once the user proves their app generates codes, and issues a new set of
+/// recovery codes.
+pub async fn confirm_totp_setup(
+    pool: &Pool,
+    claims: &Claims,
+    code: &str,
+) -> AppResult<RecoveryCodesResponse> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let row = transaction
+        .query_opt(
+            "SELECT secret FROM user_totp WHERE user_id = $1 AND enabled_at IS NULL FOR UPDATE",
+            &[&claims.sub],
+        )
+        .await?
+        .ok_or_else(|| AppError::BadRequest("Start TOTP setup first".to_string()))?;
+    let secret = decode_totp_secret(row.get("secret"))?;
+    let step = matching_step(&secret, code, Utc::now().timestamp()).ok_or_else(invalid_code)?;
+    transaction
+        .execute(
+            "UPDATE user_totp SET enabled_at = NOW(), last_used_step = $2 WHERE user_id = $1",
+            &[&claims.sub, &step],
+        )
+        .await?;
+    let recovery_codes = replace_recovery_codes(&transaction, claims.sub).await?;
+    transaction.commit().await?;
+    Ok(RecoveryCodesResponse { recovery_codes })
+}
+
+pub async fn disable_totp(pool: &Pool, claims: &Claims) -> AppResult<()> {
+    ensure_recent_second_factor(claims)?;
+    let client = pool.get().await?;
+    let deleted = client
+        .execute("DELETE FROM user_totp WHERE user_id = $1", &[&claims.sub])
+        .await?;
+    if deleted == 0 {
+        return Err(AppError::NotFound("TOTP is not enabled".to_string()));
+    }
+    drop_recovery_codes_without_factor(pool, claims.sub).await
+}
+
+pub async fn regenerate_recovery_codes(
+    pool: &Pool,
+    claims: &Claims,
+) -> AppResult<RecoveryCodesResponse> {
+    ensure_recent_second_factor(claims)?;
+    if enabled_methods(pool, claims.sub).await?.is_empty() {
+        return Err(AppError::BadRequest(
+            "Set up a second factor first".to_string(),
+        ));
+    }
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let recovery_codes = replace_recovery_codes(&transaction, claims.sub).await?;
+    transaction.commit().await?;
+    Ok(RecoveryCodesResponse { recovery_codes })
+}
+
+pub async fn passkey_registration_options(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    claims: &Claims,
+) -> AppResult<CreationChallengeResponse> {
+    ensure_can_change_factors(pool, claims).await?;
+    passkeys::start_registration(pool, redis_cache, claims.sub, &claims.username).await
+}
+
+/// Stores a passkey. Recovery codes are issued along with the user's first second factor.
+pub async fn register_passkey(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    claims: &Claims,
+    request: &PasskeyRegistrationRequest,
+) -> AppResult<PasskeyRegisteredResponse> {
+    let first_factor = enabled_methods(pool, claims.sub).await?.is_empty();
+    let passkey = passkeys::finish_registration(pool, redis_cache, claims.sub, request).await?;
+    let recovery_codes = if first_factor {
+        let mut client = pool.get().await?;
+        let transaction = client.transaction().await?;
+        let codes = replace_recovery_codes(&transaction, claims.sub).await?;
+        transaction.commit().await?;
+        Some(codes)
+    } else {
+        None
+    };
+    Ok(PasskeyRegisteredResponse {
+        passkey,
+        recovery_codes,
+    })
+}
+
+pub async fn delete_passkey(pool: &Pool, claims: &Claims, passkey_id: i32) -> AppResult<()> {
+    ensure_recent_second_factor(claims)?;
+    if !passkeys::delete_passkey(pool, claims.sub, passkey_id).await? {
+        return Err(AppError::NotFound("Passkey not found".to_string()));
+    }
+    drop_recovery_codes_without_factor(pool, claims.sub).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B, SHA-1, truncated to six digits
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn totp_matches_rfc_6238_vectors() {
+        for (time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ] {
+            assert_eq!(
+                matching_step(RFC_SECRET, code, time),
+                Some(time / TOTP_STEP_SECS)
+            );
+        }
+    }
+
+    #[test]
+    fn totp_allows_one_step_of_drift() {
+        assert_eq!(matching_step(RFC_SECRET, "287 082", 59 + 30), Some(1));
+        assert_eq!(matching_step(RFC_SECRET, "287082", 59 + 60), None);
+        assert_eq!(matching_step(RFC_SECRET, "28708", 59), None);
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_and_separators() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        let code = &codes[0];
+        assert_eq!(
+            hash_recovery_code(code),
+            hash_recovery_code(&code.to_uppercase().replace('-', " "))
+        );
+        assert_ne!(hash_recovery_code(code), hash_recovery_code(&codes[1]));
+    }
+
+    fn claims(mfa_at: Option<i64>, scopes: Option<Vec<String>>) -> Claims {
+        Claims {
+            sub: 1,
+            exp: 0,
+            username: "la gerku".to_string(),
+            email: "gerku@example.org".to_string(),
+            created_at: 0,
+            role: "admin".to_string(),
+            email_confirmed: true,
+            authorities: Vec::new(),
+            sid: None,
+            mfa_at,
+            scopes,
+        }
+    }
+
+    #[test]
+    fn step_up_is_needed_only_with_an_enrolled_factor() {
+        let session = claims(None, None);
+        assert!(step_up_needed(&session, true, true));
+        assert!(!step_up_needed(&session, true, false));
+        assert!(!step_up_needed(&session, false, true));
+
+        let confirmed = claims(Some(Utc::now().timestamp() - 60), None);
+        assert!(!step_up_needed(&confirmed, true, true));
+        let expired = claims(
+            Some(Utc::now().timestamp() - STEP_UP_WINDOW_SECS - 60),
+            None,
+        );
+        assert!(step_up_needed(&expired, true, true));
+    }
+
+    #[test]
+    fn api_tokens_skip_step_up() {
+        let token = claims(None, Some(vec!["bulk_import".to_string()]));
+        assert!(!step_up_needed(&token, true, true));
+        assert!(is_destructive_permission("bulk_import"));
+        assert!(!is_destructive_permission("read"));
+    }
+
+    #[test]
+    fn otpauth_url_escapes_username() {
+        assert_eq!(
+            otpauth_url("ABC", "la gerku"),
+            "otpauth://totp/Lensisku:la%20gerku?secret=ABC&issuer=Lensisku&digits=6&period=30"
+        );
+    }
+}
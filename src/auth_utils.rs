@@ -1,30 +1,29 @@
-use crate::{AppError, AppResult};
+use crate::{collections::models::CollectionRole, AppError, AppResult};
 use deadpool_postgres::{Pool, Transaction};
 
 /// Verifies that the user (or anonymous) may read collection data.
 /// Anonymous (user_id None): only public collections.
-/// Logged-in: public collections or collection owner.
+/// Logged-in: public collections, or collections they own or are a member of.
 pub async fn verify_collection_read_access(
     transaction: &Transaction<'_>,
     collection_id: i32,
     user_id: Option<i32>,
 ) -> AppResult<()> {
     let row = transaction
-        .query_one(
-            "SELECT user_id, is_public FROM collections WHERE collection_id = $1",
-            &[&collection_id],
+        .query_opt(
+            "SELECT is_public, collection_role(collection_id, $2) AS role FROM collections
+             WHERE collection_id = $1 AND deleted_at IS NULL",
+            &[&collection_id, &user_id],
         )
         .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    let owner_id: i32 = row.get("user_id");
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Collection not found".to_string()))?;
     let is_public: bool = row.get("is_public");
-    match user_id {
-        None if !is_public => Err(AppError::Auth("Access denied".to_string())),
-        Some(uid) if !is_public && owner_id != uid => {
-            Err(AppError::Auth("Access denied".to_string()))
-        }
-        _ => Ok(()),
+    let role: Option<CollectionRole> = row.get("role");
+    if !is_public && role.is_none() {
+        return Err(AppError::Auth("Access denied".to_string()));
     }
+    Ok(())
 }
 
 /// Pool-based convenience wrapper around [`verify_collection_read_access`] for
@@ -58,11 +57,12 @@ pub async fn verify_collection_ownership(
     user_id: i32,
 ) -> AppResult<()> {
     let owner_id: i32 = transaction
-        .query_one(
-            "SELECT user_id FROM collections WHERE collection_id = $1",
+        .query_opt(
+            "SELECT user_id FROM collections WHERE collection_id = $1 AND deleted_at IS NULL",
             &[&collection_id],
         )
         .await?
+        .ok_or_else(|| AppError::NotFound("Collection not found".to_string()))?
         .get("user_id");
 
     if owner_id != user_id {
@@ -73,26 +73,245 @@ pub async fn verify_collection_ownership(
     Ok(())
 }
 
-pub async fn verify_flashcard_ownership(
+/// Verifies that the user may change the collection's items, flashcards, levels and media,
+/// i.e. owns it or is one of its editors.
+pub async fn verify_collection_edit_access(
+    transaction: &Transaction<'_>,
+    collection_id: i32,
+    user_id: i32,
+) -> AppResult<CollectionRole> {
+    let role: Option<CollectionRole> = transaction
+        .query_opt(
+            "SELECT collection_role(collection_id, $2) AS role FROM collections
+             WHERE collection_id = $1 AND deleted_at IS NULL",
+            &[&collection_id, &user_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound("Collection not found".to_string()))?
+        .get("role");
+
+    match role {
+        Some(role) if role.can_edit() => Ok(role),
+        _ => Err(AppError::Auth(
+            "Access Denied: User cannot edit this collection".to_string(),
+        )),
+    }
+}
+
+pub async fn verify_flashcard_edit_access(
     transaction: &Transaction<'_>,
     flashcard_id: i32,
     user_id: i32,
 ) -> AppResult<()> {
-    let owner_id: i32 = transaction
+    let role: Option<CollectionRole> = transaction
         .query_one(
-            "SELECT c.user_id
+            "SELECT collection_role(c.collection_id, $2) AS role
              FROM flashcards f
              JOIN collections c ON f.collection_id = c.collection_id
-             WHERE f.id = $1",
-            &[&flashcard_id],
+             WHERE f.id = $1 AND c.deleted_at IS NULL",
+            &[&flashcard_id, &user_id],
         )
         .await?
-        .get("user_id");
+        .get("role");
 
-    if owner_id != user_id {
+    if !role.is_some_and(CollectionRole::can_edit) {
         return Err(AppError::Auth(
-            "Access Denied: Flashcard does not belong to user".to_string(),
+            "Access Denied: User cannot edit this flashcard".to_string(),
         ));
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deadpool_postgres::{Config, Runtime};
+    use std::env;
+    use uuid::Uuid;
+
+    fn test_pool() -> Pool {
+        dotenvy::dotenv().ok();
+        let mut cfg = Config::new();
+        cfg.host = Some(env::var("DB_HOST").unwrap_or_else(|_| "localhost".into()));
+        cfg.port = Some(
+            env::var("DB_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(5432),
+        );
+        cfg.user = Some(env::var("DB_USER").expect("DB_USER"));
+        cfg.password = Some(env::var("DB_PASSWORD").expect("DB_PASSWORD"));
+        cfg.dbname = Some(env::var("DB_NAME").expect("DB_NAME"));
+        cfg.create_pool(Some(Runtime::Tokio1), tokio_postgres::NoTls)
+            .unwrap()
+    }
+
+    /// A private collection with one flashcard, its owner, an editor, a viewer, a user whose
+    /// invitation is still pending and a stranger.
+    struct Fixture {
+        collection_id: i32,
+        flashcard_id: i32,
+        owner: i32,
+        editor: i32,
+        viewer: i32,
+        invited: i32,
+        stranger: i32,
+    }
+
+    async fn create_user(transaction: &Transaction<'_>, label: &str) -> i32 {
+        let username = format!("{}-{}", label, &Uuid::new_v4().simple().to_string()[..8]);
+        let email = format!("{}@example.org", username);
+        transaction
+            .query_one(
+                "INSERT INTO users (username, email, password, created_at, role, email_confirmed, votesize)
+                 VALUES ($1, $2, 'x', NOW(), 'user', true, 1.0)
+                 RETURNING userid",
+                &[&username, &email],
+            )
+            .await
+            .unwrap()
+            .get("userid")
+    }
+
+    async fn fixture(transaction: &Transaction<'_>) -> Fixture {
+        let owner = create_user(transaction, "owner").await;
+        let editor = create_user(transaction, "editor").await;
+        let viewer = create_user(transaction, "viewer").await;
+        let invited = create_user(transaction, "invited").await;
+        let stranger = create_user(transaction, "stranger").await;
+
+        let collection_id: i32 = transaction
+            .query_one(
+                "INSERT INTO collections (user_id, name, description, is_public)
+                 VALUES ($1, 'access test', NULL, false)
+                 RETURNING collection_id",
+                &[&owner],
+            )
+            .await
+            .unwrap()
+            .get("collection_id");
+        transaction
+            .execute(
+                "INSERT INTO collection_members (collection_id, user_id, role, invited_by, accepted_at)
+                 VALUES ($1, $2, 'editor', $5, NOW()), ($1, $3, 'viewer', $5, NOW()),
+                        ($1, $4, 'editor', $5, NULL)",
+                &[&collection_id, &editor, &viewer, &invited, &owner],
+            )
+            .await
+            .unwrap();
+        let item_id: i32 = transaction
+            .query_one(
+                "INSERT INTO collection_items (collection_id, free_content_front, free_content_back)
+                 VALUES ($1, 'front', 'back')
+                 RETURNING item_id",
+                &[&collection_id],
+            )
+            .await
+            .unwrap()
+            .get("item_id");
+        let flashcard_id: i32 = transaction
+            .query_one(
+                "INSERT INTO flashcards (collection_id, position, item_id)
+                 VALUES ($1, 0, $2)
+                 RETURNING id",
+                &[&collection_id, &item_id],
+            )
+            .await
+            .unwrap()
+            .get("id");
+
+        Fixture {
+            collection_id,
+            flashcard_id,
+            owner,
+            editor,
+            viewer,
+            invited,
+            stranger,
+        }
+    }
+
+    #[test]
+    fn only_owners_and_editors_can_edit() {
+        assert!(CollectionRole::Owner.can_edit());
+        assert!(CollectionRole::Editor.can_edit());
+        assert!(!CollectionRole::Viewer.can_edit());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn collection_role_counts_only_accepted_members() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        let f = fixture(&transaction).await;
+
+        for (user_id, expected) in [
+            (f.owner, Some(CollectionRole::Owner)),
+            (f.editor, Some(CollectionRole::Editor)),
+            (f.viewer, Some(CollectionRole::Viewer)),
+            (f.invited, None),
+            (f.stranger, None),
+        ] {
+            let role: Option<CollectionRole> = transaction
+                .query_one(
+                    "SELECT collection_role($1, $2) AS role",
+                    &[&f.collection_id, &user_id],
+                )
+                .await
+                .unwrap()
+                .get("role");
+            assert_eq!(role, expected, "user {}", user_id);
+        }
+        // Dropping the transaction rolls the fixture back
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn collection_edit_access_needs_owner_or_editor() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        let f = fixture(&transaction).await;
+
+        assert_eq!(
+            verify_collection_edit_access(&transaction, f.collection_id, f.owner)
+                .await
+                .unwrap(),
+            CollectionRole::Owner
+        );
+        assert_eq!(
+            verify_collection_edit_access(&transaction, f.collection_id, f.editor)
+                .await
+                .unwrap(),
+            CollectionRole::Editor
+        );
+        for user_id in [f.viewer, f.invited, f.stranger] {
+            assert!(matches!(
+                verify_collection_edit_access(&transaction, f.collection_id, user_id).await,
+                Err(AppError::Auth(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn flashcard_edit_access_needs_owner_or_editor() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        let f = fixture(&transaction).await;
+
+        for user_id in [f.owner, f.editor] {
+            verify_flashcard_edit_access(&transaction, f.flashcard_id, user_id)
+                .await
+                .unwrap();
+        }
+        for user_id in [f.viewer, f.invited, f.stranger] {
+            assert!(matches!(
+                verify_flashcard_edit_access(&transaction, f.flashcard_id, user_id).await,
+                Err(AppError::Auth(_))
+            ));
+        }
+    }
+}
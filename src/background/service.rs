@@ -3,19 +3,20 @@ use crate::{
     comments::service as comments_service,
     db,
     error::{AppError, AppResult},
-    export::service::export_all_dictionaries,
-    mailarchive::{check_for_new_emails, import_maildir},
+    export::{jobs::spawn_export_workers, service::export_all_dictionaries},
+    mailarchive::{
+        import_maildir,
+        sources::{self, maildir::MaildirSource},
+    },
     notifications::run_email_notifications,
+    webhooks::spawn_webhook_deliveries,
 };
 use chrono::Local;
 use deadpool_postgres::Pool;
 use log::{error, info};
 use pgvector::Vector;
 use std::{sync::Arc, time::Duration};
-use tokio::{
-    sync::Mutex,
-    time::{self, sleep},
-};
+use tokio::time::{self, sleep};
 
 /// Types where definition notes are known to skew embeddings (e.g. boilerplate "experimental" text).
 /// When we have no glosswords, we use only definition and exclude notes for these types.
@@ -55,7 +56,8 @@ async fn calculate_missing_embeddings(pool: &Pool) -> AppResult<()> {
              FROM definitions d
              JOIN valsi v ON d.valsiid = v.valsiid
              JOIN valsitypes vt ON v.typeid = vt.typeid
-             WHERE d.embedding IS NULL AND d.definition != '' AND d.langid != 1",
+             WHERE d.embedding IS NULL AND d.definition != '' AND d.langid != 1
+               AND d.deleted_at IS NULL",
             &[],
         )
         .await
@@ -176,9 +178,8 @@ pub async fn spawn_background_tasks(
     maildir_path: String,
 ) {
     let pool_clone = pool.clone();
-    let maildir_path_clone = maildir_path.clone();
     tokio::spawn(async move {
-        if let Err(e) = import_maildir(&pool_clone, &maildir_path_clone).await {
+        if let Err(e) = import_maildir(&pool_clone, &maildir_path).await {
             error!("Failed to import emails from Maildir: {:?}", e);
         }
 
@@ -196,6 +197,9 @@ pub async fn spawn_background_tasks(
                 error!("Failed to get message count: {}", e);
             }
         }
+
+        // Then poll the Maildir for new deliveries.
+        sources::run_source(pool_clone, Box::new(MaildirSource::new(maildir_path))).await;
     });
 
     // Embedding calculation task (in-process via fastembed — no external service needed)
@@ -211,18 +215,10 @@ pub async fn spawn_background_tasks(
         }
     });
 
-    // Check for new emails periodically
-    let pool_clone = pool.clone();
-    let maildir_path_clone = maildir_path.clone();
-    tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(24 * 60 * 60)); // 1 day
-        loop {
-            interval.tick().await;
-            if let Err(e) = check_for_new_emails(&pool_clone, &maildir_path_clone).await {
-                error!("Failed to check for new emails: {}", e);
-            }
-        }
-    });
+    // Keep the mail archive up to date from mbox files and IMAP (see `mailarchive::sources`).
+    for source in sources::configured_sources() {
+        tokio::spawn(sources::run_source(pool.clone(), source));
+    }
 
     // Probe OpenRouter models and cache two working ids in Redis for the assistant (fast path).
     // Cached order is provider-priority aware; within the same priority class, faster final
@@ -318,17 +314,60 @@ pub async fn spawn_background_tasks(
         }
     });
 
+    // Fit personal FSRS weights from review history once a day (first run one hour after
+    // startup so it doesn't compete with the initial maildir import and exports).
+    let fsrs_pool = pool.clone();
+    tokio::spawn(async move {
+        sleep(Duration::from_secs(60 * 60)).await;
+        let mut interval = time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = crate::flashcards::run_fsrs_optimization(&fsrs_pool).await {
+                error!("Failed to optimize FSRS parameters: {}", e);
+            }
+        }
+    });
+
+    // Purge items that have been in the trash longer than TRASH_RETENTION_DAYS, once a day
+    let trash_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = crate::trash::service::purge_expired(&trash_pool).await {
+                error!("Failed to purge the trash: {}", e);
+            }
+        }
+    });
+
+    // Drop delivered and failed webhook deliveries older than WEBHOOK_DELIVERY_RETENTION_DAYS,
+    // once a day
+    let webhook_log_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = crate::webhooks::purge_finished_deliveries(&webhook_log_pool).await {
+                error!("Failed to purge old webhook deliveries: {}", e);
+            }
+        }
+    });
+
     // Spawn email notification processor
     let email_pool = pool.clone();
     tokio::spawn(async move {
         run_email_notifications(email_pool).await;
     });
 
+    // Send queued webhook deliveries, retrying failed ones with backoff
+    spawn_webhook_deliveries(pool.clone());
+
     // Generate missing valsi sounds (Lojban, Kitten TTS Nano 0.8 / Bruno) every 5 minutes
     valsi_tts::spawn_valsi_sound_generation(pool.clone());
 
-    // Cache dictionary exports. Skipped when DISABLE_DICTIONARY_EXPORT=1/true/yes
-    // because it runs xelatex at startup and can freeze low-resource dev containers.
+    // Cache dictionary exports. Only languages that changed are rebuilt, by a worker limited to
+    // DICTIONARY_EXPORT_CONCURRENCY builds at a time. Skipped entirely when
+    // DISABLE_DICTIONARY_EXPORT=1/true/yes.
     if std::env::var("DISABLE_DICTIONARY_EXPORT")
         .ok()
         .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
@@ -336,17 +375,15 @@ pub async fn spawn_background_tasks(
     {
         info!("DISABLE_DICTIONARY_EXPORT set; skipping dictionary export background task");
     } else {
-        let export_lock = Arc::new(Mutex::new(()));
+        spawn_export_workers(pool.clone());
         let pool_clone = pool.clone();
 
         tokio::spawn(async move {
             loop {
-                // Acquire lock before starting export (run once at startup, then after each midnight)
-                let _lock = export_lock.lock().await;
+                // Queue changed exports once at startup, then after each midnight
                 if let Err(e) = export_all_dictionaries(&pool_clone).await {
-                    error!("Failed to export dictionaries: {}", e);
+                    error!("Failed to queue dictionary exports: {}", e);
                 }
-                drop(_lock);
 
                 let now = Local::now();
                 let next_midnight = match (now + chrono::Duration::days(1))
@@ -0,0 +1,1474 @@
+//! Anki `.apkg` export and import for collections.
+//!
+//! A package is a ZIP holding a legacy (schema 11) `collection.anki2` SQLite database, a `media`
+//! JSON map and numbered media files. Collection items become notes of a "Lensisku" note type
+//! (fields Front, Back, Notes, Sound) with one card per studied side: ord 0 is the `direct`
+//! side, ord 1 the `reverse` side. Levels become subdecks of the collection deck. When the
+//! exporting user has studied the collection, their progress and review history are written as
+//! card scheduling, FSRS memory state (`cards.data`) and `revlog` rows; import does the reverse
+//! so moving between the two tools keeps scheduling.
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+use fsrs::{FSRSItem, FSRSReview, FSRS};
+use log::warn;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusqlite::{params, Connection};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use super::dto::{AnkiImportResponse, CollectionOwner, CollectionResponse};
+use super::service::{
+    decode_data_url, get_or_insert_collection_image_id, invalidate_public_collections_cache,
+    parse_export_direction, sanitize_html,
+};
+use crate::{
+    flashcards::models::{FlashcardDirection, FlashcardStatus},
+    middleware::cache::RedisCache,
+    utils::{remove_html_tags, MAX_ITEM_IMAGE_BYTES},
+    AppError, AppResult,
+};
+
+/// Max `.apkg` upload (compressed).
+pub const MAX_APKG_BYTES: usize = 100 * 1024 * 1024;
+/// Budget for all uncompressed entries (database + media), to reject zip bombs.
+const MAX_APKG_UNCOMPRESSED_TOTAL: u64 = 512 * 1024 * 1024;
+const MAX_ITEM_SOUND_BYTES: usize = 5 * 1024 * 1024;
+
+/// Offsets keep generated Anki ids in the millisecond-timestamp range Anki itself uses, so they
+/// are stable across exports of the same collection and don't collide with small ids.
+const NOTE_ID_BASE: i64 = 1_000_000_000_000;
+const DECK_ID_BASE: i64 = 1_100_000_000_000;
+const MODEL_ID: i64 = 1_200_000_000_001;
+const FIELD_SEPARATOR: char = '\x1f';
+const DEFAULT_FACTOR: i64 = 2500;
+
+const ANKI_SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null,
+    tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+/// One `revlog` row. `ivl`/`last_ivl` are days (negative values are learning steps in seconds).
+#[derive(Debug, Clone)]
+pub(crate) struct ApkgReview {
+    pub id: i64,
+    pub ease: i64,
+    pub ivl: i64,
+    pub last_ivl: i64,
+    pub review_type: i64,
+    /// FSRS (stability, difficulty) after this review; filled in on import.
+    pub memory_after: Option<(f32, f32)>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ApkgCard {
+    pub id: i64,
+    pub ord: i64,
+    pub card_type: i64,
+    pub queue: i64,
+    pub due: i64,
+    pub ivl: i64,
+    pub reps: i64,
+    pub lapses: i64,
+    /// FSRS (stability, difficulty), stored by Anki in `cards.data` as `{"s": .., "d": ..}`.
+    pub memory: Option<(f32, f32)>,
+    pub revlog: Vec<ApkgReview>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ApkgNote {
+    pub id: i64,
+    pub guid: String,
+    pub fields: Vec<String>,
+    pub tags: Vec<String>,
+    /// Index into [`ApkgPackage::subdecks`]; `None` places the note in the root deck.
+    pub subdeck: Option<usize>,
+    pub cards: Vec<ApkgCard>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ApkgMedia {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ApkgPackage {
+    pub deck_name: String,
+    pub deck_description: String,
+    /// `col.crt`: review card `due` values are day numbers counted from this timestamp.
+    pub created_at_secs: i64,
+    pub subdecks: Vec<String>,
+    pub notes: Vec<ApkgNote>,
+    pub media: Vec<ApkgMedia>,
+}
+
+fn deck_id(index: usize) -> i64 {
+    DECK_ID_BASE + index as i64
+}
+
+/// First 8 hex digits of SHA-1 over the tag-stripped sort field, as Anki computes `notes.csum`.
+fn field_checksum(field: &str) -> i64 {
+    let digest = openssl::sha::sha1(remove_html_tags(field).trim().as_bytes());
+    i64::from(u32::from_be_bytes([
+        digest[0], digest[1], digest[2], digest[3],
+    ]))
+}
+
+fn anki_models_json(now_secs: i64) -> serde_json::Value {
+    let field = |name: &str, ord: i64| {
+        json!({
+            "name": name, "ord": ord, "sticky": false, "rtl": false,
+            "font": "Arial", "size": 20, "media": []
+        })
+    };
+    json!({
+        MODEL_ID.to_string(): {
+            "id": MODEL_ID,
+            "name": "Lensisku",
+            "type": 0,
+            "mod": now_secs,
+            "usn": -1,
+            "sortf": 0,
+            "did": deck_id(0),
+            "tmpls": [
+                {
+                    "name": "Direct", "ord": 0,
+                    "qfmt": "{{Front}}{{Sound}}",
+                    "afmt": "{{FrontSide}}<hr id=answer>{{Back}}<div class=notes>{{Notes}}</div>",
+                    "bqfmt": "", "bafmt": "", "did": null
+                },
+                {
+                    "name": "Reverse", "ord": 1,
+                    "qfmt": "{{Back}}",
+                    "afmt": "{{FrontSide}}<hr id=answer>{{Front}}{{Sound}}<div class=notes>{{Notes}}</div>",
+                    "bqfmt": "", "bafmt": "", "did": null
+                }
+            ],
+            "flds": [field("Front", 0), field("Back", 1), field("Notes", 2), field("Sound", 3)],
+            "css": ".card { font-family: arial; font-size: 20px; text-align: center; }\n.notes { font-size: 14px; color: #666; margin-top: 1em; }",
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "latexsvg": false,
+            "req": [[0, "any", [0]], [1, "any", [1]]],
+            "tags": [],
+            "vers": []
+        }
+    })
+}
+
+fn anki_deck_json(id: i64, name: &str, description: &str, now_secs: i64) -> serde_json::Value {
+    json!({
+        "id": id, "name": name, "desc": description, "mod": now_secs, "usn": -1,
+        "lrnToday": [0, 0], "revToday": [0, 0], "newToday": [0, 0], "timeToday": [0, 0],
+        "collapsed": false, "browserCollapsed": false, "dyn": 0, "conf": 1,
+        "extendNew": 0, "extendRev": 0
+    })
+}
+
+fn anki_dconf_json() -> serde_json::Value {
+    json!({
+        "1": {
+            "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60, "autoplay": true,
+            "timer": 0, "replayq": true, "dyn": false,
+            "new": { "bury": false, "delays": [1.0, 10.0], "initialFactor": DEFAULT_FACTOR,
+                     "ints": [1, 4, 0], "order": 1, "perDay": 20 },
+            "lapse": { "delays": [10.0], "leechAction": 1, "leechFails": 8, "minInt": 1,
+                       "mult": 0.0 },
+            "rev": { "bury": false, "ease4": 1.3, "ivlFct": 1.0, "maxIvl": 36500,
+                     "perDay": 200, "hardFactor": 1.2 }
+        }
+    })
+}
+
+/// Writes a package to `.apkg` bytes.
+pub(crate) fn write_apkg(package: &ApkgPackage) -> Result<Vec<u8>, String> {
+    let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let db_path = dir.path().join("collection.anki2");
+    let now = Utc::now();
+    let now_secs = now.timestamp();
+    let now_ms = now.timestamp_millis();
+
+    {
+        let conn = Connection::open(&db_path).map_err(|e| e.to_string())?;
+        conn.execute_batch(ANKI_SCHEMA).map_err(|e| e.to_string())?;
+
+        let mut decks = serde_json::Map::new();
+        decks.insert("1".to_string(), anki_deck_json(1, "Default", "", now_secs));
+        decks.insert(
+            deck_id(0).to_string(),
+            anki_deck_json(
+                deck_id(0),
+                &package.deck_name,
+                &package.deck_description,
+                now_secs,
+            ),
+        );
+        for (i, subdeck) in package.subdecks.iter().enumerate() {
+            let name = format!("{}::{}", package.deck_name, subdeck);
+            decks.insert(
+                deck_id(i + 1).to_string(),
+                anki_deck_json(deck_id(i + 1), &name, "", now_secs),
+            );
+        }
+        let conf = json!({
+            "nextPos": package.notes.len() + 1, "estTimes": true, "activeDecks": [deck_id(0)],
+            "sortType": "noteFld", "timeLim": 0, "sortBackwards": false, "addToCur": true,
+            "curDeck": deck_id(0), "newSpread": 0, "dueCounts": true, "curModel": MODEL_ID,
+            "collapseTime": 1200
+        });
+
+        conn.execute(
+            "INSERT INTO col VALUES (1, ?1, ?2, ?3, 11, 0, 0, 0, ?4, ?5, ?6, ?7, '{}')",
+            params![
+                package.created_at_secs,
+                now_ms,
+                now_ms,
+                conf.to_string(),
+                anki_models_json(now_secs).to_string(),
+                serde_json::Value::Object(decks).to_string(),
+                anki_dconf_json().to_string(),
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+
+        for note in &package.notes {
+            let sort_field = note.fields.first().cloned().unwrap_or_default();
+            let tags = if note.tags.is_empty() {
+                String::new()
+            } else {
+                format!(" {} ", note.tags.join(" "))
+            };
+            conn.execute(
+                "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+                params![
+                    note.id,
+                    note.guid,
+                    MODEL_ID,
+                    now_secs,
+                    tags,
+                    note.fields.join(&FIELD_SEPARATOR.to_string()),
+                    remove_html_tags(&sort_field),
+                    field_checksum(&sort_field),
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+
+            let did = deck_id(note.subdeck.map(|i| i + 1).unwrap_or(0));
+            for card in &note.cards {
+                let data = card
+                    .memory
+                    .map(|(s, d)| json!({ "s": s, "d": d }).to_string())
+                    .unwrap_or_default();
+                conn.execute(
+                    "INSERT INTO cards VALUES
+                     (?1, ?2, ?3, ?4, ?5, -1, ?6, ?7, ?8, ?9, ?10, ?11, ?12, 0, 0, 0, 0, ?13)",
+                    params![
+                        card.id,
+                        note.id,
+                        did,
+                        card.ord,
+                        now_secs,
+                        card.card_type,
+                        card.queue,
+                        card.due,
+                        card.ivl,
+                        if card.card_type == 0 {
+                            0
+                        } else {
+                            DEFAULT_FACTOR
+                        },
+                        card.reps,
+                        card.lapses,
+                        data,
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+
+                for review in &card.revlog {
+                    conn.execute(
+                        "INSERT OR IGNORE INTO revlog VALUES (?1, ?2, -1, ?3, ?4, ?5, ?6, 0, ?7)",
+                        params![
+                            review.id,
+                            card.id,
+                            review.ease,
+                            review.ivl,
+                            review.last_ivl,
+                            DEFAULT_FACTOR,
+                            review.review_type,
+                        ],
+                    )
+                    .map_err(|e| e.to_string())?;
+                }
+            }
+        }
+    }
+
+    let db_bytes = std::fs::read(&db_path).map_err(|e| e.to_string())?;
+
+    let mut zip_buffer = Vec::new();
+    {
+        let mut zip = ZipWriter::new(Cursor::new(&mut zip_buffer));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("collection.anki2", options)
+            .map_err(|e| e.to_string())?;
+        zip.write_all(&db_bytes).map_err(|e| e.to_string())?;
+
+        let mut media_map = serde_json::Map::new();
+        for (i, media) in package.media.iter().enumerate() {
+            media_map.insert(
+                i.to_string(),
+                serde_json::Value::String(media.filename.clone()),
+            );
+            zip.start_file(i.to_string(), options)
+                .map_err(|e| e.to_string())?;
+            zip.write_all(&media.data).map_err(|e| e.to_string())?;
+        }
+        zip.start_file("media", options)
+            .map_err(|e| e.to_string())?;
+        zip.write_all(serde_json::Value::Object(media_map).to_string().as_bytes())
+            .map_err(|e| e.to_string())?;
+        zip.finish().map_err(|e| e.to_string())?;
+    }
+    Ok(zip_buffer)
+}
+
+fn read_zip_entry(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+    total_uncompressed: &mut u64,
+) -> Result<Option<Vec<u8>>, String> {
+    let mut file = match archive.by_name(name) {
+        Ok(f) => f,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(format!("Invalid .apkg archive: {}", e)),
+    };
+    *total_uncompressed = total_uncompressed.saturating_add(file.size());
+    if *total_uncompressed > MAX_APKG_UNCOMPRESSED_TOTAL {
+        return Err("Anki package exceeds the uncompressed size budget".to_string());
+    }
+    let mut data = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read {} from .apkg: {}", name, e))?;
+    Ok(Some(data))
+}
+
+/// Reads `.apkg` bytes. Only the legacy `collection.anki2` / `collection.anki21` databases are
+/// supported; packages that contain only `collection.anki21b` must be re-exported from Anki with
+/// "Support older Anki versions" enabled.
+pub(crate) fn read_apkg(bytes: &[u8]) -> Result<ApkgPackage, String> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(bytes)).map_err(|e| format!("Invalid .apkg archive: {}", e))?;
+    let mut total_uncompressed = 0u64;
+
+    let db_bytes = match read_zip_entry(&mut archive, "collection.anki21", &mut total_uncompressed)?
+    {
+        Some(b) => b,
+        None => match read_zip_entry(&mut archive, "collection.anki2", &mut total_uncompressed)? {
+            Some(b) => b,
+            None => {
+                return Err(
+                    "Unsupported .apkg: re-export the deck from Anki with \"Support older Anki versions\" enabled"
+                        .to_string(),
+                )
+            }
+        },
+    };
+
+    let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let db_path = dir.path().join("collection.anki2");
+    std::fs::write(&db_path, &db_bytes).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&db_path).map_err(|e| e.to_string())?;
+
+    let (created_at_secs, decks_json): (i64, String) = conn
+        .query_row("SELECT crt, decks FROM col LIMIT 1", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .map_err(|e| format!("Invalid Anki collection: {}", e))?;
+    let decks: serde_json::Value =
+        serde_json::from_str(&decks_json).map_err(|e| format!("Invalid deck list: {}", e))?;
+    let deck_names: HashMap<i64, String> = decks
+        .as_object()
+        .map(|obj| {
+            obj.values()
+                .filter_map(|d| Some((d["id"].as_i64()?, d["name"].as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut revlogs: HashMap<i64, Vec<ApkgReview>> = HashMap::new();
+    {
+        let mut stmt = conn
+            .prepare("SELECT id, cid, ease, ivl, lastIvl, type FROM revlog ORDER BY id")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(1)?,
+                    ApkgReview {
+                        id: row.get(0)?,
+                        ease: row.get(2)?,
+                        ivl: row.get(3)?,
+                        last_ivl: row.get(4)?,
+                        review_type: row.get(5)?,
+                        memory_after: None,
+                    },
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (cid, review) = row.map_err(|e| e.to_string())?;
+            revlogs.entry(cid).or_default().push(review);
+        }
+    }
+
+    // (note id) -> (deck id of its first card, cards)
+    let mut cards_by_note: BTreeMap<i64, (i64, Vec<ApkgCard>)> = BTreeMap::new();
+    {
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, nid, did, ord, type, queue, due, ivl, reps, lapses, data
+                 FROM cards ORDER BY nid, ord",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                let data: String = row.get(10)?;
+                let memory = serde_json::from_str::<serde_json::Value>(&data)
+                    .ok()
+                    .and_then(|v| Some((v["s"].as_f64()? as f32, v["d"].as_f64()? as f32)));
+                Ok((
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    ApkgCard {
+                        id: row.get(0)?,
+                        ord: row.get(3)?,
+                        card_type: row.get(4)?,
+                        queue: row.get(5)?,
+                        due: row.get(6)?,
+                        ivl: row.get(7)?,
+                        reps: row.get(8)?,
+                        lapses: row.get(9)?,
+                        memory,
+                        revlog: Vec::new(),
+                    },
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (nid, did, mut card) = row.map_err(|e| e.to_string())?;
+            card.revlog = revlogs.remove(&card.id).unwrap_or_default();
+            cards_by_note
+                .entry(nid)
+                .or_insert_with(|| (did, Vec::new()))
+                .1
+                .push(card);
+        }
+    }
+
+    // The most used top-level deck becomes the collection; its subdecks become levels.
+    let mut top_level_counts: HashMap<String, usize> = HashMap::new();
+    for (did, _) in cards_by_note.values() {
+        if let Some(name) = deck_names.get(did) {
+            let top = name.split("::").next().unwrap_or(name).to_string();
+            *top_level_counts.entry(top).or_default() += 1;
+        }
+    }
+    let deck_name = top_level_counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(name, _)| name)
+        .unwrap_or_else(|| "Anki import".to_string());
+    let root_prefix = format!("{}::", deck_name);
+
+    let mut subdecks: Vec<String> = Vec::new();
+    let mut notes = Vec::new();
+    {
+        let mut stmt = conn
+            .prepare("SELECT id, guid, tags, flds FROM notes ORDER BY id")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (id, guid, tags, flds) = row.map_err(|e| e.to_string())?;
+            let Some((did, cards)) = cards_by_note.remove(&id) else {
+                continue;
+            };
+            let subdeck = deck_names.get(&did).and_then(|name| {
+                let sub = match name.strip_prefix(&root_prefix) {
+                    Some(sub) => sub.to_string(),
+                    None if *name == deck_name => return None,
+                    None => name.clone(),
+                };
+                Some(match subdecks.iter().position(|s| *s == sub) {
+                    Some(i) => i,
+                    None => {
+                        subdecks.push(sub);
+                        subdecks.len() - 1
+                    }
+                })
+            });
+            notes.push(ApkgNote {
+                id,
+                guid,
+                fields: flds.split(FIELD_SEPARATOR).map(str::to_string).collect(),
+                tags: tags.split_whitespace().map(str::to_string).collect(),
+                subdeck,
+                cards,
+            });
+        }
+    }
+
+    let media_map: HashMap<String, String> =
+        match read_zip_entry(&mut archive, "media", &mut total_uncompressed)? {
+            Some(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            None => HashMap::new(),
+        };
+    let mut media = Vec::with_capacity(media_map.len());
+    for (index, filename) in media_map {
+        if let Some(data) = read_zip_entry(&mut archive, &index, &mut total_uncompressed)? {
+            media.push(ApkgMedia { filename, data });
+        }
+    }
+
+    Ok(ApkgPackage {
+        deck_name,
+        deck_description: String::new(),
+        created_at_secs,
+        subdecks,
+        notes,
+        media,
+    })
+}
+
+/// Replays each card's revlog through FSRS to fill in the memory state after every review and,
+/// when Anki did not store one, the card's current memory state.
+fn compute_memory_states(package: &mut ApkgPackage) -> Result<(), String> {
+    let fsrs = FSRS::new(&[]).map_err(|e| e.to_string())?;
+    for card in package.notes.iter_mut().flat_map(|n| n.cards.iter_mut()) {
+        let mut reviews: Vec<FSRSReview> = Vec::new();
+        let mut last_review_ms: Option<i64> = None;
+        for review in card.revlog.iter_mut() {
+            if !(1..=4).contains(&review.ease) {
+                continue;
+            }
+            let delta_t = last_review_ms
+                .map(|last| ((review.id - last).max(0) / 86_400_000) as u32)
+                .unwrap_or(0);
+            last_review_ms = Some(review.id);
+            reviews.push(FSRSReview {
+                rating: review.ease as u32,
+                delta_t,
+            });
+            review.memory_after = fsrs
+                .memory_state(
+                    FSRSItem {
+                        reviews: reviews.clone(),
+                    },
+                    None,
+                )
+                .ok()
+                .map(|m| (m.stability, m.difficulty));
+        }
+        if card.memory.is_none() {
+            card.memory = card.revlog.iter().rev().find_map(|r| r.memory_after);
+        }
+    }
+    Ok(())
+}
+
+fn card_side(ord: i64) -> &'static str {
+    if ord == 0 {
+        "direct"
+    } else {
+        "reverse"
+    }
+}
+
+fn direction_for_sides(has_direct: bool, has_reverse: bool) -> FlashcardDirection {
+    match (has_direct, has_reverse) {
+        (true, true) => FlashcardDirection::Both,
+        (false, true) => FlashcardDirection::Reverse,
+        _ => FlashcardDirection::Direct,
+    }
+}
+
+fn sides_for_direction(direction: &FlashcardDirection) -> &'static [i64] {
+    match direction {
+        FlashcardDirection::Both
+        | FlashcardDirection::FillInBoth
+        | FlashcardDirection::QuizBoth
+        | FlashcardDirection::QuizImageBoth => &[0, 1],
+        FlashcardDirection::Reverse
+        | FlashcardDirection::FillInReverse
+        | FlashcardDirection::QuizReverse
+        | FlashcardDirection::QuizImageReverse => &[1],
+        FlashcardDirection::Direct
+        | FlashcardDirection::FillIn
+        | FlashcardDirection::JustInformation
+        | FlashcardDirection::QuizDirect
+        | FlashcardDirection::QuizImageDirect => &[0],
+    }
+}
+
+fn media_extension(mime_type: &str) -> &'static str {
+    match mime_type.split(';').next().unwrap_or("").trim() {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/ogg" => "ogg",
+        "audio/webm" => "webm",
+        _ => "bin",
+    }
+}
+
+fn mime_from_filename(filename: &str) -> Option<&'static str> {
+    let ext = filename.rsplit('.').next()?.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "mp3" => "audio/mpeg",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "webm" => "audio/webm",
+        _ => return None,
+    })
+}
+
+/// Pulls `<img src="...">` and `[sound:...]` references out of an Anki field, returning the
+/// remaining HTML and the referenced media filenames.
+#[allow(clippy::expect_used)] // fixed patterns
+pub(crate) fn extract_field_media(field: &str) -> (String, Vec<String>, Vec<String>) {
+    static IMG_RE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r#"(?i)<img[^>]*\bsrc\s*=\s*["']?([^"'>\s]+)["']?[^>]*>"#).expect("valid regex")
+    });
+    static SOUND_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"\[sound:([^\]]+)\]").expect("valid regex"));
+
+    let images: Vec<String> = IMG_RE
+        .captures_iter(field)
+        .filter_map(|c| c.get(1).map(|m| m.as_str().to_string()))
+        .collect();
+    let sounds: Vec<String> = SOUND_RE
+        .captures_iter(field)
+        .filter_map(|c| c.get(1).map(|m| m.as_str().to_string()))
+        .collect();
+    let without_images = IMG_RE.replace_all(field, "");
+    let text = SOUND_RE.replace_all(&without_images, "");
+    let text = text.trim().trim_end_matches("<br>").trim().to_string();
+    (text, images, sounds)
+}
+
+fn days_between(from_secs: i64, to: DateTime<Utc>) -> i64 {
+    (to.timestamp() - from_secs).div_euclid(86_400)
+}
+
+struct ProgressRow {
+    status: FlashcardStatus,
+    stability: f64,
+    difficulty: f64,
+    interval: i32,
+    review_count: i32,
+    next_review_at: Option<DateTime<Utc>>,
+}
+
+struct HistoryRow {
+    review_time: DateTime<Utc>,
+    rating: i32,
+    elapsed_days: i32,
+    scheduled_days: i32,
+}
+
+/// Builds an `.apkg` for a collection. When `user_id` has studied the collection, their
+/// progress and review history are included as card scheduling and revlog.
+pub async fn export_collection_apkg(
+    pool: &Pool,
+    collection_id: i32,
+    user_id: Option<i32>,
+) -> AppResult<ApkgExport> {
+    let export = super::service::export_collection_full(pool, collection_id, user_id).await?;
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    let sound_rows = client
+        .query(
+            "SELECT s.item_id, s.sound_data, s.mime_type
+             FROM collection_item_sounds s
+             JOIN collection_items ci ON ci.item_id = s.item_id
+             WHERE ci.collection_id = $1",
+            &[&collection_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let mut sounds: HashMap<i32, (Vec<u8>, String)> = sound_rows
+        .into_iter()
+        .map(|row| {
+            (
+                row.get("item_id"),
+                (row.get("sound_data"), row.get("mime_type")),
+            )
+        })
+        .collect();
+
+    // Progress and review history of the exporting user, keyed by (item_id, card_side).
+    let mut progress: HashMap<(i32, String), ProgressRow> = HashMap::new();
+    let mut history: HashMap<(i32, String), Vec<HistoryRow>> = HashMap::new();
+    let mut earliest_secs = client
+        .query_one(
+            "SELECT created_at FROM collections WHERE collection_id = $1",
+            &[&collection_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .get::<_, DateTime<Utc>>("created_at")
+        .timestamp();
+
+    if let Some(uid) = user_id {
+        let rows = client
+            .query(
+                "SELECT f.item_id, p.card_side, p.status, p.stability, p.difficulty, p.interval,
+                        p.review_count, p.next_review_at
+                 FROM user_flashcard_progress p
+                 JOIN flashcards f ON f.id = p.flashcard_id
+                 WHERE p.user_id = $1 AND f.collection_id = $2 AND NOT p.archived",
+                &[&uid, &collection_id],
+            )
+            .await
+            .map_err(|e| AppError::Database(e.to_string()))?;
+        for row in rows {
+            progress.insert(
+                (row.get("item_id"), row.get("card_side")),
+                ProgressRow {
+                    status: row.get("status"),
+                    stability: row.get("stability"),
+                    difficulty: row.get("difficulty"),
+                    interval: row.get("interval"),
+                    review_count: row.get("review_count"),
+                    next_review_at: row.get("next_review_at"),
+                },
+            );
+        }
+
+        let rows = client
+            .query(
+                "SELECT f.item_id, h.card_side, h.review_time, h.rating, h.elapsed_days,
+                        h.scheduled_days
+                 FROM flashcard_review_history h
+                 JOIN flashcards f ON f.id = h.flashcard_id
+                 WHERE h.user_id = $1 AND f.collection_id = $2
+                 ORDER BY h.review_time, h.id",
+                &[&uid, &collection_id],
+            )
+            .await
+            .map_err(|e| AppError::Database(e.to_string()))?;
+        for row in rows {
+            let review_time: DateTime<Utc> = row.get("review_time");
+            earliest_secs = earliest_secs.min(review_time.timestamp());
+            history
+                .entry((row.get("item_id"), row.get("card_side")))
+                .or_default()
+                .push(HistoryRow {
+                    review_time,
+                    rating: row.get("rating"),
+                    elapsed_days: row.get("elapsed_days"),
+                    scheduled_days: row.get("scheduled_days"),
+                });
+        }
+    }
+    drop(client);
+
+    let created_at_secs = earliest_secs - earliest_secs.rem_euclid(86_400);
+    let mut item_subdeck: HashMap<usize, usize> = HashMap::new();
+    for (level_idx, level) in export.levels.iter().enumerate() {
+        for &item_idx in &level.item_positions {
+            item_subdeck.entry(item_idx).or_insert(level_idx);
+        }
+    }
+
+    let mut media: Vec<ApkgMedia> = Vec::new();
+    let mut notes: Vec<ApkgNote> = Vec::with_capacity(export.items.len());
+    let mut used_revlog_ids: HashSet<i64> = HashSet::new();
+
+    for (idx, item) in export.items.iter().enumerate() {
+        let mut front = item
+            .word
+            .clone()
+            .or_else(|| item.free_content_front.clone())
+            .unwrap_or_default();
+        let mut back = item
+            .definition
+            .clone()
+            .or_else(|| item.free_content_back.clone())
+            .unwrap_or_default();
+        let notes_field = [item.collection_note.clone(), item.definition_notes.clone()]
+            .into_iter()
+            .flatten()
+            .filter(|n| !n.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("<br>");
+
+        for (side, url, field) in [
+            ("front", &item.front_image_url, &mut front),
+            ("back", &item.back_image_url, &mut back),
+        ] {
+            if let Some(url) = url {
+                match decode_data_url(url) {
+                    Ok((mime_type, data)) => {
+                        let filename = format!(
+                            "lensisku-{}-{}.{}",
+                            item.item_id,
+                            side,
+                            media_extension(&mime_type)
+                        );
+                        field.push_str(&format!("<br><img src=\"{}\">", filename));
+                        media.push(ApkgMedia { filename, data });
+                    }
+                    Err(e) => warn!("Skipping {} image of item {}: {}", side, item.item_id, e),
+                }
+            }
+        }
+
+        let sound_field = match sounds.remove(&item.item_id) {
+            Some((data, mime_type)) => {
+                let filename = format!(
+                    "lensisku-{}-sound.{}",
+                    item.item_id,
+                    media_extension(&mime_type)
+                );
+                let field = format!("[sound:{}]", filename);
+                media.push(ApkgMedia { filename, data });
+                field
+            }
+            None => String::new(),
+        };
+
+        // Items without a flashcard still get a card so the deck is studyable in Anki.
+        let direction = match item.direction {
+            Some(_) => parse_export_direction(item.direction.as_ref()),
+            None => FlashcardDirection::Direct,
+        };
+
+        let note_id = NOTE_ID_BASE + i64::from(item.item_id);
+        let mut cards = Vec::new();
+        for &ord in sides_for_direction(&direction) {
+            let key = (item.item_id, card_side(ord).to_string());
+            let revlog: Vec<ApkgReview> = history
+                .remove(&key)
+                .unwrap_or_default()
+                .into_iter()
+                .enumerate()
+                .map(|(i, h)| {
+                    // revlog ids are millisecond timestamps and must be unique.
+                    let mut id = h.review_time.timestamp_millis();
+                    while !used_revlog_ids.insert(id) {
+                        id += 1;
+                    }
+                    ApkgReview {
+                        id,
+                        ease: i64::from(h.rating),
+                        ivl: i64::from(h.scheduled_days),
+                        last_ivl: i64::from(h.elapsed_days),
+                        review_type: if i == 0 {
+                            0
+                        } else if h.rating == 1 {
+                            2
+                        } else {
+                            1
+                        },
+                        memory_after: None,
+                    }
+                })
+                .collect();
+            let lapses = revlog.iter().skip(1).filter(|r| r.ease == 1).count() as i64;
+
+            let card = match progress.remove(&key) {
+                Some(p) if p.status != FlashcardStatus::New => {
+                    let due_day =
+                        days_between(created_at_secs, p.next_review_at.unwrap_or_else(Utc::now));
+                    ApkgCard {
+                        id: note_id * 2 + ord,
+                        ord,
+                        card_type: 2,
+                        queue: 2,
+                        due: due_day,
+                        ivl: i64::from(p.interval.max(1)),
+                        reps: i64::from(p.review_count),
+                        lapses,
+                        memory: (p.stability > 0.0)
+                            .then_some((p.stability as f32, p.difficulty as f32)),
+                        revlog,
+                    }
+                }
+                _ => ApkgCard {
+                    id: note_id * 2 + ord,
+                    ord,
+                    card_type: 0,
+                    queue: 0,
+                    due: idx as i64 + 1,
+                    ivl: 0,
+                    reps: 0,
+                    lapses: 0,
+                    memory: None,
+                    revlog,
+                },
+            };
+            cards.push(card);
+        }
+
+        let mut tags = vec!["lensisku".to_string()];
+        if let Some(word_type) = &item.word_type {
+            tags.push(word_type.replace(char::is_whitespace, "_"));
+        }
+
+        notes.push(ApkgNote {
+            id: note_id,
+            guid: format!("lensisku-{}", item.item_id),
+            fields: vec![front, back, notes_field, sound_field],
+            tags,
+            subdeck: item_subdeck.get(&idx).copied(),
+            cards,
+        });
+    }
+
+    let package = ApkgPackage {
+        deck_name: export.collection.name.replace("::", ":"),
+        deck_description: export.collection.description.clone().unwrap_or_default(),
+        created_at_secs,
+        subdecks: export
+            .levels
+            .iter()
+            .map(|l| l.name.replace("::", ":"))
+            .collect(),
+        notes,
+        media,
+    };
+
+    let content = tokio::task::spawn_blocking(move || write_apkg(&package))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(|e| AppError::Internal(format!("Failed to build Anki package: {}", e)))?;
+
+    Ok(ApkgExport {
+        content,
+        collection_name: export.collection.name,
+    })
+}
+
+pub struct ApkgExport {
+    pub content: Vec<u8>,
+    pub collection_name: String,
+}
+
+/// Imports an `.apkg` as a new collection owned by `user_id`, with flashcards, levels (from
+/// subdecks) and the deck's scheduling and revlog as the user's flashcard progress.
+pub async fn import_apkg(
+    pool: &Pool,
+    redis: &RedisCache,
+    user_id: i32,
+    bytes: Vec<u8>,
+) -> AppResult<AnkiImportResponse> {
+    if bytes.len() > MAX_APKG_BYTES {
+        return Err(AppError::BadRequest(format!(
+            "Anki package exceeds {} MiB",
+            MAX_APKG_BYTES / (1024 * 1024)
+        )));
+    }
+
+    let package = tokio::task::spawn_blocking(move || -> Result<ApkgPackage, String> {
+        let mut package = read_apkg(&bytes)?;
+        compute_memory_states(&mut package)?;
+        Ok(package)
+    })
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?
+    .map_err(AppError::BadRequest)?;
+
+    let media: HashMap<&str, &[u8]> = package
+        .media
+        .iter()
+        .map(|m| (m.filename.as_str(), m.data.as_slice()))
+        .collect();
+
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let transaction = client
+        .transaction()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    let name = sanitize_html(&package.deck_name);
+    let row = transaction
+        .query_one(
+            "INSERT INTO collections (user_id, name, description, is_public)
+             VALUES ($1, $2, NULL, false)
+             RETURNING collection_id, created_at, updated_at",
+            &[&user_id, &name],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let collection_id: i32 = row.get("collection_id");
+
+    let mut level_ids: Vec<i32> = Vec::with_capacity(package.subdecks.len());
+    for (position, subdeck) in package.subdecks.iter().enumerate() {
+        let level_id: i32 = transaction
+            .query_one(
+                "INSERT INTO flashcard_levels (collection_id, name, min_cards, min_success_rate, position)
+                 VALUES ($1, $2, 5, 0.8, $3)
+                 RETURNING level_id",
+                &[&collection_id, &sanitize_html(subdeck), &(position as i32)],
+            )
+            .await
+            .map_err(|e| AppError::Database(e.to_string()))?
+            .get("level_id");
+        level_ids.push(level_id);
+    }
+
+    let crt = package.created_at_secs;
+    let now = Utc::now();
+    let mut imported_count = 0i32;
+    let mut skipped_count = 0i32;
+    let mut cards_with_progress = 0i32;
+    let mut reviews_imported = 0i32;
+    let mut warnings: Vec<String> = Vec::new();
+    let mut level_positions: HashMap<i32, i32> = HashMap::new();
+
+    for (pos, note) in package.notes.iter().enumerate() {
+        let (front, front_images, front_sounds) =
+            extract_field_media(note.fields.first().map(String::as_str).unwrap_or(""));
+        let (back, back_images, back_sounds) =
+            extract_field_media(note.fields.get(1).map(String::as_str).unwrap_or(""));
+        let mut extra_sounds = Vec::new();
+        let extra: Vec<String> = note
+            .fields
+            .iter()
+            .skip(2)
+            .map(|f| {
+                let (text, _, sounds) = extract_field_media(f);
+                extra_sounds.extend(sounds);
+                text
+            })
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        if front.is_empty() && back.is_empty() && front_images.is_empty() && back_images.is_empty()
+        {
+            warnings.push(format!("Note {}: empty front and back, skipped", note.guid));
+            skipped_count += 1;
+            continue;
+        }
+
+        let front = sanitize_html(&front);
+        let back = sanitize_html(&back);
+        let item_notes = (!extra.is_empty()).then(|| sanitize_html(&extra.join("<br>")));
+        let canonical_form = crate::utils::canonical::get_canonical_form(&front);
+
+        let item_id: i32 = transaction
+            .query_one(
+                "INSERT INTO collection_items (collection_id, free_content_front, free_content_back, notes, position, canonical_form)
+                 VALUES ($1, $2, $3, $4, $5, $6) RETURNING item_id",
+                &[
+                    &collection_id,
+                    &front,
+                    &back,
+                    &item_notes,
+                    &(pos as i32),
+                    &canonical_form,
+                ],
+            )
+            .await
+            .map_err(|e| AppError::Database(e.to_string()))?
+            .get("item_id");
+
+        for (side, images) in [("front", &front_images), ("back", &back_images)] {
+            let Some(filename) = images.first() else {
+                continue;
+            };
+            let (Some(data), Some(mime_type)) =
+                (media.get(filename.as_str()), mime_from_filename(filename))
+            else {
+                warnings.push(format!(
+                    "Note {}: {} image {} missing or unsupported",
+                    note.guid, side, filename
+                ));
+                continue;
+            };
+            if data.len() > MAX_ITEM_IMAGE_BYTES {
+                warnings.push(format!(
+                    "Note {}: {} image exceeds {}MB, skipped",
+                    note.guid,
+                    side,
+                    MAX_ITEM_IMAGE_BYTES / (1024 * 1024)
+                ));
+                continue;
+            }
+            let image_id = get_or_insert_collection_image_id(&transaction, data, mime_type).await?;
+            transaction
+                .execute(
+                    "INSERT INTO collection_item_images (item_id, collection_image_id, side) VALUES ($1, $2, $3)",
+                    &[&item_id, &image_id, &side],
+                )
+                .await
+                .map_err(|e| AppError::Database(e.to_string()))?;
+        }
+
+        if let Some(filename) = front_sounds
+            .iter()
+            .chain(back_sounds.iter())
+            .chain(extra_sounds.iter())
+            .next()
+        {
+            match (media.get(filename.as_str()), mime_from_filename(filename)) {
+                (Some(data), Some(mime_type))
+                    if mime_type.starts_with("audio/") && data.len() <= MAX_ITEM_SOUND_BYTES =>
+                {
+                    transaction
+                        .execute(
+                            "INSERT INTO collection_item_sounds (item_id, sound_data, mime_type)
+                             VALUES ($1, $2, $3)",
+                            &[&item_id, data, &mime_type],
+                        )
+                        .await
+                        .map_err(|e| AppError::Database(e.to_string()))?;
+                }
+                _ => warnings.push(format!(
+                    "Note {}: sound {} missing, unsupported or too large",
+                    note.guid, filename
+                )),
+            }
+        }
+
+        let has_direct = note.cards.iter().any(|c| c.ord == 0);
+        let has_reverse = note.cards.iter().any(|c| c.ord != 0);
+        let direction = direction_for_sides(has_direct, has_reverse);
+        let flashcard_id: i32 = transaction
+            .query_one(
+                "INSERT INTO flashcards (collection_id, position, item_id, direction)
+                 VALUES ($1, $2, $3, $4)
+                 RETURNING id",
+                &[&collection_id, &(pos as i32), &item_id, &direction],
+            )
+            .await
+            .map_err(|e| AppError::Database(e.to_string()))?
+            .get("id");
+
+        if let Some(level_id) = note.subdeck.and_then(|i| level_ids.get(i)) {
+            let position = level_positions.entry(*level_id).or_insert(0);
+            transaction
+                .execute(
+                    "INSERT INTO flashcard_level_items (level_id, flashcard_id, position)
+                     VALUES ($1, $2, $3)
+                     ON CONFLICT (level_id, flashcard_id) DO NOTHING",
+                    &[level_id, &flashcard_id, &*position],
+                )
+                .await
+                .map_err(|e| AppError::Database(e.to_string()))?;
+            *position += 1;
+        }
+
+        let mut seen_sides: Vec<&str> = Vec::new();
+        for card in &note.cards {
+            let side = card_side(card.ord);
+            if seen_sides.contains(&side) {
+                continue;
+            }
+            seen_sides.push(side);
+
+            // Anki card types: 0 new, 1 learning, 2 review, 3 relearning. Learning cards in
+            // queue 1 store `due` as a Unix timestamp, everything else as a day number.
+            let status = match card.card_type {
+                0 => FlashcardStatus::New,
+                1 | 3 => FlashcardStatus::Learning,
+                _ => FlashcardStatus::Review,
+            };
+            let next_review_at = match (card.card_type, card.queue) {
+                (0, _) => now,
+                (_, 1) => DateTime::from_timestamp(card.due, 0).unwrap_or(now),
+                _ => DateTime::from_timestamp(crt + card.due * 86_400, 0).unwrap_or(now),
+            };
+            let (stability, difficulty) = card
+                .memory
+                .map(|(s, d)| (f64::from(s), f64::from(d)))
+                .unwrap_or(if status == FlashcardStatus::New {
+                    (0.0, 0.0)
+                } else {
+                    (card.ivl.max(1) as f64, 5.0)
+                });
+            let last_reviewed_at = card
+                .revlog
+                .last()
+                .and_then(|r| DateTime::from_timestamp_millis(r.id));
+
+            transaction
+                .execute(
+                    "INSERT INTO user_flashcard_progress
+                     (user_id, flashcard_id, card_side, stability, difficulty, interval,
+                      next_review_at, last_reviewed_at, review_count, status)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                     ON CONFLICT (user_id, flashcard_id, card_side) WHERE NOT archived DO NOTHING",
+                    &[
+                        &user_id,
+                        &flashcard_id,
+                        &side,
+                        &stability,
+                        &difficulty,
+                        &(card.ivl.max(0) as i32),
+                        &next_review_at,
+                        &last_reviewed_at,
+                        &(card.reps as i32),
+                        &status,
+                    ],
+                )
+                .await
+                .map_err(|e| AppError::Database(e.to_string()))?;
+            if status != FlashcardStatus::New {
+                cards_with_progress += 1;
+            }
+
+            let mut last_review_ms: Option<i64> = None;
+            for review in &card.revlog {
+                if !(1..=4).contains(&review.ease) {
+                    continue;
+                }
+                let Some(review_time) = DateTime::from_timestamp_millis(review.id) else {
+                    continue;
+                };
+                let elapsed_days = last_review_ms
+                    .map(|last| ((review.id - last).max(0) / 86_400_000) as i32)
+                    .unwrap_or(0);
+                last_review_ms = Some(review.id);
+                let state = match review.memory_after {
+                    Some((s, d)) => json!({ "stability": s, "difficulty": d }),
+                    None => json!({}),
+                };
+                transaction
+                    .execute(
+                        "INSERT INTO flashcard_review_history
+                         (user_id, flashcard_id, card_side, rating, elapsed_days, scheduled_days,
+                          state, review_time)
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                        &[
+                            &user_id,
+                            &flashcard_id,
+                            &side,
+                            &(review.ease as i32),
+                            &elapsed_days,
+                            &(review.ivl.max(0) as i32),
+                            &state,
+                            &review_time,
+                        ],
+                    )
+                    .await
+                    .map_err(|e| AppError::Database(e.to_string()))?;
+                reviews_imported += 1;
+            }
+        }
+
+        imported_count += 1;
+    }
+
+    let username: String = transaction
+        .query_one("SELECT username FROM users WHERE userid = $1", &[&user_id])
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .get("username");
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    invalidate_public_collections_cache(redis).await;
+
+    Ok(AnkiImportResponse {
+        collection: CollectionResponse {
+            collection_id,
+            name,
+            description: None,
+            is_public: false,
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+            item_count: imported_count as i64,
+            has_flashcards: imported_count > 0,
+            has_cover_image: false,
+            has_collection_image: false,
+            comment_count: 0,
+            owner: CollectionOwner { user_id, username },
+        },
+        imported_count,
+        skipped_count,
+        levels_created: level_ids.len() as i32,
+        cards_with_progress,
+        reviews_imported,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_package() -> ApkgPackage {
+        ApkgPackage {
+            deck_name: "gismu".to_string(),
+            deck_description: String::new(),
+            created_at_secs: 1_700_000_000 - 1_700_000_000 % 86_400,
+            subdecks: vec!["Level 1".to_string()],
+            notes: vec![ApkgNote {
+                id: NOTE_ID_BASE + 7,
+                guid: "lensisku-7".to_string(),
+                fields: vec![
+                    "klama<br><img src=\"k.png\">".to_string(),
+                    "x1 comes/goes".to_string(),
+                    String::new(),
+                    "[sound:k.ogg]".to_string(),
+                ],
+                tags: vec!["lensisku".to_string()],
+                subdeck: Some(0),
+                cards: vec![
+                    ApkgCard {
+                        id: (NOTE_ID_BASE + 7) * 2,
+                        ord: 0,
+                        card_type: 2,
+                        queue: 2,
+                        due: 10,
+                        ivl: 4,
+                        reps: 2,
+                        lapses: 0,
+                        memory: Some((4.5, 5.25)),
+                        revlog: vec![
+                            ApkgReview {
+                                id: 1_700_000_000_000,
+                                ease: 3,
+                                ivl: 1,
+                                last_ivl: 0,
+                                review_type: 0,
+                                memory_after: None,
+                            },
+                            ApkgReview {
+                                id: 1_700_172_800_000,
+                                ease: 3,
+                                ivl: 4,
+                                last_ivl: 2,
+                                review_type: 1,
+                                memory_after: None,
+                            },
+                        ],
+                    },
+                    ApkgCard {
+                        id: (NOTE_ID_BASE + 7) * 2 + 1,
+                        ord: 1,
+                        card_type: 0,
+                        queue: 0,
+                        due: 1,
+                        ivl: 0,
+                        reps: 0,
+                        lapses: 0,
+                        memory: None,
+                        revlog: Vec::new(),
+                    },
+                ],
+            }],
+            media: vec![
+                ApkgMedia {
+                    filename: "k.png".to_string(),
+                    data: vec![0x89, b'P', b'N', b'G'],
+                },
+                ApkgMedia {
+                    filename: "k.ogg".to_string(),
+                    data: b"OggS".to_vec(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn apkg_round_trip_keeps_notes_cards_revlog_and_media() {
+        let bytes = write_apkg(&sample_package()).expect("write");
+        let package = read_apkg(&bytes).expect("read");
+
+        assert_eq!(package.deck_name, "gismu");
+        assert_eq!(package.subdecks, vec!["Level 1".to_string()]);
+        assert_eq!(package.notes.len(), 1);
+        let note = &package.notes[0];
+        assert_eq!(note.guid, "lensisku-7");
+        assert_eq!(note.subdeck, Some(0));
+        assert_eq!(note.fields[1], "x1 comes/goes");
+        assert_eq!(note.cards.len(), 2);
+        assert_eq!(note.cards[0].memory, Some((4.5, 5.25)));
+        assert_eq!(note.cards[0].revlog.len(), 2);
+        assert_eq!(note.cards[0].due, 10);
+        assert_eq!(package.media.len(), 2);
+    }
+
+    #[test]
+    fn field_media_is_extracted() {
+        let (text, images, sounds) =
+            extract_field_media("klama<br><img src=\"k.png\"> [sound:k.ogg]");
+        assert_eq!(text, "klama");
+        assert_eq!(images, vec!["k.png".to_string()]);
+        assert_eq!(sounds, vec!["k.ogg".to_string()]);
+    }
+
+    #[test]
+    fn card_ords_map_to_directions() {
+        assert_eq!(direction_for_sides(true, true), FlashcardDirection::Both);
+        assert_eq!(
+            direction_for_sides(false, true),
+            FlashcardDirection::Reverse
+        );
+        assert_eq!(direction_for_sides(true, false), FlashcardDirection::Direct);
+        assert_eq!(sides_for_direction(&FlashcardDirection::QuizBoth), &[0, 1]);
+        assert_eq!(
+            sides_for_direction(&FlashcardDirection::FillInReverse),
+            &[1]
+        );
+    }
+}
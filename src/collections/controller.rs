@@ -63,9 +63,9 @@ pub async fn create_collection(
     ),
     security(("bearer_auth" = [])),
     summary = "List user collections",
-    description = "Retrieves all collections owned by the authenticated user. Includes basic collection \
-                  information such as name, description, and item count, as well as creation and last \
-                  update timestamps."
+    description = "Retrieves all collections owned by the authenticated user, and with `include_shared=true` \
+                  also those they edit or view as a member. Includes basic collection information such as \
+                  name, description, and item count, as well as creation and last update timestamps."
 )]
 #[get("")]
 pub async fn list_collections(
@@ -196,7 +196,7 @@ pub async fn get_collection_flashcards(
         (status = 500, description = "Internal server error")
     ),
     summary = "Get collection cover image",
-    description = "Returns the collection logo image. Public collections are readable without auth; private collections require the owner."
+    description = "Returns the collection logo image. Public collections are readable without auth; private collections require the owner or a member."
 )]
 #[get("/{id}/image")]
 pub async fn get_collection_image(
@@ -320,9 +320,9 @@ pub async fn update_collection(
     ),
     security(("bearer_auth" = [])),
     summary = "Delete collection",
-    description = "Permanently deletes a collection and all its items. This action can only be performed \
-                  by the collection owner and cannot be undone. All associated items and notes will also \
-                  be removed."
+    description = "Moves a collection to the trash. Its items and flashcard progress are kept, hidden, until \
+                  the owner restores it or it is purged after TRASH_RETENTION_DAYS. This action can only be \
+                  performed by the collection owner."
 )]
 #[delete("/{id}")]
 pub async fn delete_collection(
@@ -449,7 +449,7 @@ pub async fn delete_collection_image(
     summary = "Add item to collection",
     description = "Adds a new dictionary definition to a collection. Each item consists of a reference to \
                   a dictionary definition and optional user notes. Items can be added to both public and \
-                  private collections, but only by the collection owner."
+                  private collections, but only by the collection owner and editors."
 )]
 #[post("/{id}/items")]
 pub async fn upsert_item(
@@ -461,6 +461,7 @@ pub async fn upsert_item(
 ) -> impl Responder {
     match service::upsert_item(&pool, &redis_cache, id.into_inner(), claims.sub, &req).await {
         Ok(item_response) => HttpResponse::Ok().json(item_response),
+        Err(AppError::Auth(_)) => HttpResponse::Forbidden().finish(),
         Err(e) => {
             log::error!("Failed to upsert item: {:?}", e);
             match e.to_string().as_str() {
@@ -707,6 +708,7 @@ pub async fn update_item_position(
             "item_id": path.1,
             "position": req.position
         })),
+        Err(AppError::Auth(_)) => HttpResponse::Forbidden().finish(),
         Err(e) => match e.to_string().as_str() {
             "Collection not found" => HttpResponse::NotFound().finish(),
             "Item not found" => HttpResponse::NotFound().finish(),
@@ -751,6 +753,7 @@ pub async fn remove_item(
             "collection_id": collection_id,
             "item_id": item_id
         })),
+        Err(AppError::Auth(_)) => HttpResponse::Forbidden().finish(),
         Err(e) => {
             let message = e.to_string();
             match message.as_str() {
@@ -1344,7 +1347,7 @@ pub async fn list_collection_items(
     ),
     security(("bearer_auth" = [])),
     summary = "Update item notes",
-    description = "Updates the notes associated with a collection item. Only the collection owner can modify notes."
+    description = "Updates the notes associated with a collection item. Only the collection owner and editors can modify notes."
 )]
 #[put("/{id}/items/{item_id}/notes")]
 pub async fn update_item_notes(
@@ -1356,6 +1359,7 @@ pub async fn update_item_notes(
 ) -> impl Responder {
     match service::update_item_notes(&pool, &redis_cache, path.0, path.1, claims.sub, &req).await {
         Ok(item) => HttpResponse::Ok().json(item),
+        Err(AppError::Auth(_)) => HttpResponse::Forbidden().finish(),
         Err(e) => match e.to_string().as_str() {
             "Collection not found" => HttpResponse::NotFound().finish(),
             "Item not found" => HttpResponse::NotFound().finish(),
@@ -1627,6 +1631,36 @@ pub async fn import_full(
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/collections/import/apkg",
+    tag = "collections",
+    request_body(content = Vec<u8>, description = "Anki .apkg file (application/octet-stream)"),
+    responses(
+        (status = 200, description = "Deck imported as a new collection", body = AnkiImportResponse),
+        (status = 400, description = "Invalid or unsupported .apkg"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Import Anki deck",
+    description = "Creates a private collection from an Anki .apkg package. Notes become items (images and [sound:] references are attached as item media), subdecks become levels, and the deck's card scheduling and review log become the caller's flashcard progress. Packages containing only collection.anki21b must be exported from Anki with \"Support older Anki versions\" enabled."
+)]
+#[post("/import/apkg")]
+pub async fn import_apkg(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    claims: Claims,
+    body: web::Bytes,
+) -> impl Responder {
+    match super::anki::import_apkg(&pool, &redis_cache, claims.sub, body.to_vec()).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(AppError::BadRequest(msg)) => HttpResponse::BadRequest().json(json!({ "error": msg })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to import: {}", e)
+        })),
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/collections/{collection_id}/search",
@@ -1664,3 +1698,196 @@ pub async fn search_collection_items(
         })),
     }
 }
+
+#[utoipa::path(
+    get,
+    path = "/collections/{id}/members",
+    tag = "collections",
+    params(("id" = i32, Path, description = "Collection ID")),
+    responses(
+        (status = 200, description = "Owner, editors and viewers", body = CollectionMemberListResponse),
+        (status = 403, description = "Not a member of the collection"),
+        (status = 404, description = "Collection not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "List collection members",
+    description = "Lists the owner, editors and viewers of a collection along with the caller's own role. \
+                  Pending invitations are only listed for the owner."
+)]
+#[get("/{id}/members")]
+pub async fn list_collection_members(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let members = service::list_collection_members(&pool, path.into_inner(), claims.sub).await?;
+    Ok(HttpResponse::Ok().json(members))
+}
+
+#[utoipa::path(
+    post,
+    path = "/collections/{id}/members",
+    tag = "collections",
+    params(("id" = i32, Path, description = "Collection ID")),
+    request_body = InviteCollectionMemberRequest,
+    responses(
+        (status = 200, description = "Invitation created", body = CollectionMemberResponse),
+        (status = 400, description = "Invalid role, or the user is already invited"),
+        (status = 403, description = "Not the collection owner"),
+        (status = 404, description = "Collection or user not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Invite a collection member",
+    description = "Invites a user by username as an editor, who may change items, flashcards, levels and media, \
+                  or as a viewer, who may read the collection while it is private. The role takes effect once \
+                  the user accepts the invitation."
+)]
+#[post("/{id}/members")]
+pub async fn invite_collection_member(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    path: web::Path<i32>,
+    req: web::Json<InviteCollectionMemberRequest>,
+) -> Result<HttpResponse, AppError> {
+    let member =
+        service::invite_collection_member(&pool, path.into_inner(), claims.sub, &req).await?;
+    Ok(HttpResponse::Ok().json(member))
+}
+
+#[utoipa::path(
+    put,
+    path = "/collections/{id}/members/{user_id}",
+    tag = "collections",
+    params(
+        ("id" = i32, Path, description = "Collection ID"),
+        ("user_id" = i32, Path, description = "Member's user ID")
+    ),
+    request_body = UpdateCollectionMemberRequest,
+    responses(
+        (status = 200, description = "Role changed", body = CollectionMemberResponse),
+        (status = 400, description = "Invalid role"),
+        (status = 403, description = "Not the collection owner"),
+        (status = 404, description = "Collection or member not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Change a member's role",
+    description = "Switches a member or pending invitation between editor and viewer."
+)]
+#[put("/{id}/members/{user_id}")]
+pub async fn update_collection_member(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    path: web::Path<(i32, i32)>,
+    req: web::Json<UpdateCollectionMemberRequest>,
+) -> Result<HttpResponse, AppError> {
+    let (collection_id, user_id) = path.into_inner();
+    let member =
+        service::update_collection_member_role(&pool, collection_id, claims.sub, user_id, &req)
+            .await?;
+    Ok(HttpResponse::Ok().json(member))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/collections/{id}/members/{user_id}",
+    tag = "collections",
+    params(
+        ("id" = i32, Path, description = "Collection ID"),
+        ("user_id" = i32, Path, description = "Member's user ID")
+    ),
+    responses(
+        (status = 204, description = "Member removed"),
+        (status = 403, description = "Not the collection owner"),
+        (status = 404, description = "Collection or member not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Remove a collection member",
+    description = "The owner removes a member or withdraws an invitation. Members pass their own user ID to \
+                  leave the collection or decline an invitation."
+)]
+#[delete("/{id}/members/{user_id}")]
+pub async fn remove_collection_member(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, AppError> {
+    let (collection_id, user_id) = path.into_inner();
+    service::remove_collection_member(&pool, collection_id, claims.sub, user_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/collections/invitations",
+    tag = "collections",
+    responses(
+        (status = 200, description = "Pending invitations, newest first", body = Vec<CollectionInvitationResponse>)
+    ),
+    security(("bearer_auth" = [])),
+    summary = "List collection invitations",
+    description = "Lists the caller's pending invitations to collaborate on collections."
+)]
+#[get("")]
+pub async fn list_collection_invitations(
+    pool: web::Data<Pool>,
+    claims: Claims,
+) -> Result<HttpResponse, AppError> {
+    let invitations = service::list_collection_invitations(&pool, claims.sub).await?;
+    Ok(HttpResponse::Ok().json(invitations))
+}
+
+#[utoipa::path(
+    post,
+    path = "/collections/{id}/invitation/accept",
+    tag = "collections",
+    params(("id" = i32, Path, description = "Collection ID")),
+    responses(
+        (status = 200, description = "Invitation accepted", body = CollectionMemberResponse),
+        (status = 404, description = "No pending invitation to this collection")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Accept a collection invitation",
+    description = "Joins the collection with the role the owner invited the caller as."
+)]
+#[post("/{id}/invitation/accept")]
+pub async fn accept_collection_invitation(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let member =
+        service::accept_collection_invitation(&pool, path.into_inner(), claims.sub).await?;
+    Ok(HttpResponse::Ok().json(member))
+}
+
+#[utoipa::path(
+    get,
+    path = "/collections/{id}/history",
+    tag = "collections",
+    params(
+        ("id" = i32, Path, description = "Collection ID"),
+        ("item_id" = Option<i32>, Query, description = "Only changes to this item"),
+        ("page" = Option<i64>, Query, description = "Page number (1-based)"),
+        ("per_page" = Option<i64>, Query, description = "Entries per page (max 100)")
+    ),
+    responses(
+        (status = 200, description = "Item changes, newest first", body = CollectionItemHistoryResponse),
+        (status = 403, description = "Not a member of the collection"),
+        (status = 404, description = "Collection not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Collection item history",
+    description = "Lists who added, changed, reordered, annotated, gave media to or removed each item of the \
+                  collection. Visible to the owner and members."
+)]
+#[get("/{id}/history")]
+pub async fn get_collection_item_history(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    path: web::Path<i32>,
+    query: web::Query<CollectionItemHistoryQuery>,
+) -> Result<HttpResponse, AppError> {
+    let history =
+        service::get_collection_item_history(&pool, path.into_inner(), claims.sub, &query).await?;
+    Ok(HttpResponse::Ok().json(history))
+}
@@ -1,4 +1,4 @@
-use super::models::{ImageData, SoundData};
+use super::models::{CollectionRole, ImageData, SoundData};
 use crate::export::models::CollectionExportItem;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -22,6 +22,8 @@ pub struct ListCollectionsQuery {
     pub has_flashcards_only: Option<bool>,
     /// When true, only return collections that have at least one flashcard level.
     pub has_levels_only: Option<bool>,
+    /// When true, also return collections the user has joined as an editor or viewer.
+    pub include_shared: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -422,6 +424,21 @@ pub struct ImportFullResponse {
     pub warnings: Vec<String>,
 }
 
+/// Result of importing an Anki `.apkg` as a new collection.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AnkiImportResponse {
+    pub collection: CollectionResponse,
+    pub imported_count: i32,
+    pub skipped_count: i32,
+    /// Levels created from the deck's subdecks.
+    pub levels_created: i32,
+    /// Cards that had been studied in Anki and now carry scheduling for the importing user.
+    pub cards_with_progress: i32,
+    /// Anki revlog entries copied into the user's review history.
+    pub reviews_imported: i32,
+    pub warnings: Vec<String>,
+}
+
 /// One row for bulk edit: items with no dictionary definition and non-empty custom front and back text.
 #[derive(Debug, Serialize, ToSchema)]
 pub struct CustomTextBulkItemRow {
@@ -526,6 +543,80 @@ pub struct MediaBulkImportResponse {
     pub warnings: Vec<String>,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct InviteCollectionMemberRequest {
+    pub username: String,
+    /// `editor` or `viewer`.
+    pub role: CollectionRole,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateCollectionMemberRequest {
+    /// `editor` or `viewer`.
+    pub role: CollectionRole,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CollectionMemberResponse {
+    pub user_id: i32,
+    pub username: String,
+    pub role: CollectionRole,
+    pub invited_by: Option<CollectionOwner>,
+    #[schema(value_type = String, format = DateTime)]
+    pub invited_at: DateTime<Utc>,
+    /// Unset while the invitation is pending.
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub accepted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CollectionMemberListResponse {
+    /// Role of the requesting user.
+    pub role: CollectionRole,
+    pub owner: CollectionOwner,
+    /// Editors and viewers, including pending invitations.
+    pub members: Vec<CollectionMemberResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CollectionInvitationResponse {
+    pub collection_id: i32,
+    pub collection_name: String,
+    pub owner: CollectionOwner,
+    pub role: CollectionRole,
+    pub invited_by: Option<CollectionOwner>,
+    #[schema(value_type = String, format = DateTime)]
+    pub invited_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CollectionItemHistoryQuery {
+    /// Only changes to this item.
+    pub item_id: Option<i32>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CollectionItemHistoryEntry {
+    pub history_id: i64,
+    pub item_id: i32,
+    /// `add`, `update`, `reorder`, `notes`, `media` or `remove`.
+    pub action: String,
+    /// Unset when the member's account was deleted.
+    pub user: Option<CollectionOwner>,
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CollectionItemHistoryResponse {
+    pub entries: Vec<CollectionItemHistoryEntry>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::parse_positive_id_list;
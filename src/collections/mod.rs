@@ -1,3 +1,4 @@
+pub mod anki;
 pub mod controller;
 pub mod dto;
 pub mod models;
@@ -11,6 +12,12 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         web::scope("collections")
             .service(controller::list_public_collections)
             .service(controller::list_users_and_collections)
+            // Authenticated, but registered before /{id} so that it is not shadowed by it
+            .service(
+                web::scope("/invitations")
+                    .wrap(HttpAuthentication::bearer(crate::auth::validator))
+                    .service(controller::list_collection_invitations),
+            )
             // More specific item routes first so they are not shadowed by /{id} or /{id}/items
             .service(controller::search_items_in_collections)
             .service(controller::get_item_image)
@@ -42,11 +49,18 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
                     .service(controller::post_kitten_tts)
                     .service(controller::update_item_media)
                     .service(controller::import_json)
+                    .service(controller::list_collection_members)
+                    .service(controller::invite_collection_member)
+                    .service(controller::update_collection_member)
+                    .service(controller::remove_collection_member)
+                    .service(controller::accept_collection_invitation)
+                    .service(controller::get_collection_item_history)
                     .service(
                         web::scope("")
                             .app_data(web::PayloadConfig::new(100 * 1024 * 1024))
                             .service(controller::post_collection_media_bulk_multipart)
-                            .service(controller::post_collection_media_bulk_zip),
+                            .service(controller::post_collection_media_bulk_zip)
+                            .service(controller::import_apkg),
                     )
                     .service(
                         web::scope("")
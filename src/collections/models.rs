@@ -1,5 +1,7 @@
 use chrono::{DateTime, Utc};
+use postgres_types::{FromSql, ToSql};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use utoipa::ToSchema;
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -39,3 +41,72 @@ pub struct SoundData {
     pub data: String, // Base64 encoded audio data
     pub mime_type: String,
 }
+
+/// What a user may do with a collection. Editors change its items, flashcards, levels and media;
+/// viewers may read it while it is private. Only the owner manages members and the collection
+/// itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionRole {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+impl CollectionRole {
+    pub fn can_edit(self) -> bool {
+        matches!(self, CollectionRole::Owner | CollectionRole::Editor)
+    }
+}
+
+impl fmt::Display for CollectionRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollectionRole::Owner => write!(f, "owner"),
+            CollectionRole::Editor => write!(f, "editor"),
+            CollectionRole::Viewer => write!(f, "viewer"),
+        }
+    }
+}
+
+impl FromSql<'_> for CollectionRole {
+    fn accepts(ty: &postgres_types::Type) -> bool {
+        ty.name() == "text" || ty.name() == "varchar"
+    }
+
+    fn from_sql(
+        _ty: &postgres_types::Type,
+        raw: &[u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let value = String::from_utf8(raw.to_vec())?;
+        match value.as_str() {
+            "owner" => Ok(CollectionRole::Owner),
+            "editor" => Ok(CollectionRole::Editor),
+            "viewer" => Ok(CollectionRole::Viewer),
+            _ => Err(format!("Unknown collection role: {}", value).into()),
+        }
+    }
+}
+
+impl ToSql for CollectionRole {
+    fn to_sql(
+        &self,
+        _ty: &postgres_types::Type,
+        out: &mut postgres_types::private::BytesMut,
+    ) -> Result<postgres_types::IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        out.extend_from_slice(self.to_string().as_bytes());
+        Ok(postgres_types::IsNull::No)
+    }
+
+    fn accepts(ty: &postgres_types::Type) -> bool {
+        ty.name() == "text" || ty.name() == "varchar"
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &postgres_types::Type,
+        out: &mut postgres_types::private::BytesMut,
+    ) -> Result<postgres_types::IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.to_sql(ty, out)
+    }
+}
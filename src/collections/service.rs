@@ -1,5 +1,7 @@
 use super::dto::SkippedItemInfo;
 use super::dto::*;
+use super::models::CollectionRole;
+use crate::auth_utils::verify_collection_edit_access;
 use crate::jbovlaste::service::get_valsi_sound_urls_from_db;
 use crate::utils::remove_html_tags;
 use crate::{
@@ -20,7 +22,7 @@ fn mime_base(mime: &str) -> &str {
 }
 
 /// Insert or reuse a row in `collection_images` (content-addressed by SHA-256 of stored bytes).
-async fn get_or_insert_collection_image_id(
+pub(crate) async fn get_or_insert_collection_image_id(
     client: &impl GenericClient,
     image_data: &[u8],
     mime_type: &str,
@@ -178,9 +180,18 @@ pub async fn list_collections(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
+    let base_condition = if query.include_shared.unwrap_or(false) {
+        "(c.user_id = $1 OR EXISTS(
+            SELECT 1 FROM collection_members mb
+            WHERE mb.collection_id = c.collection_id AND mb.user_id = $1
+            AND mb.accepted_at IS NOT NULL))"
+    } else {
+        "c.user_id = $1"
+    };
+
     let mut params: Vec<Box<dyn ToSql + Sync>> = vec![Box::new(user_id)];
     let where_clause = build_collection_where_clause(
-        "c.user_id = $1",
+        base_condition,
         &mut params,
         query.search.as_deref(),
         query.has_flashcards_only.unwrap_or(false),
@@ -277,8 +288,7 @@ pub async fn list_public_collections(
 
 const MAX_PICKER_PER_KIND: i64 = 40;
 const POPULAR_PICKER_PER_KIND: i64 = 10;
-const FILTER_PICKER_POPULAR_CACHE_TTL: std::time::Duration =
-    std::time::Duration::from_secs(3600);
+const FILTER_PICKER_POPULAR_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(3600);
 
 /// Combined picker for Home/Fast Search: public collections, then authors (users).
 ///
@@ -347,7 +357,7 @@ async fn query_popular_users_and_collections(
              LEFT JOIN flashcards f ON f.collection_id = c.collection_id
              LEFT JOIN user_flashcard_progress ufp ON ufp.flashcard_id = f.id
                  AND ufp.last_reviewed_at >= NOW() - INTERVAL '7 days'
-             WHERE c.is_public = true
+             WHERE c.is_public = true AND c.deleted_at IS NULL
              GROUP BY c.collection_id, u.userid, u.username
              ORDER BY COUNT(DISTINCT ufp.user_id) DESC, c.updated_at DESC
              LIMIT $1",
@@ -390,6 +400,7 @@ async fn query_users_and_collections(
              FROM collections c
              JOIN users u ON u.userid = c.user_id
              WHERE c.is_public = true
+               AND c.deleted_at IS NULL
                AND (
                     $1::text IS NULL
                     OR c.name ILIKE $1
@@ -462,7 +473,10 @@ fn build_collection_where_clause(
     has_flashcards_only: bool,
     has_levels_only: bool,
 ) -> String {
-    let mut conditions = vec![base_condition.to_string()];
+    let mut conditions = vec![
+        base_condition.to_string(),
+        "c.deleted_at IS NULL".to_string(),
+    ];
     if has_flashcards_only {
         conditions.push(
             "EXISTS(SELECT 1 FROM flashcards f WHERE f.collection_id = c.collection_id)"
@@ -570,7 +584,7 @@ async fn query_collections(
                     )
                 ) AS has_collection_image,
                 (SELECT COUNT(*) FROM comments cm
-                    JOIN threads t ON cm.threadid = t.threadid
+                    JOIN threads t ON cm.threadid = t.threadid AND cm.deleted_at IS NULL
                     WHERE t.collection_id = c.collection_id) AS comment_count,
                 {search_rank_select}
                 c.updated_at AS _rank_tiebreak
@@ -632,7 +646,11 @@ pub async fn refresh_collection_sort_cache(pool: &Pool, redis: &RedisCache) -> A
         .map_err(|e| AppError::Database(e.to_string()))?;
 
     for sort_key in &["active_week", "active_month", "active_all", "newest"] {
-        let sql = build_collections_query("WHERE c.is_public = true", Some(sort_key), false);
+        let sql = build_collections_query(
+            "WHERE c.is_public = true AND c.deleted_at IS NULL",
+            Some(sort_key),
+            false,
+        );
         let rows = client.query(&sql, &[]).await.map_err(|e| {
             AppError::Database(format!("Cache refresh query failed for {sort_key}: {e}"))
         })?;
@@ -754,7 +772,7 @@ fn build_collections_query(where_clause: &str, sort: Option<&str>, user_owned: b
                     )
                 ) AS has_collection_image,
                 (SELECT COUNT(*) FROM comments cm
-                    JOIN threads t ON cm.threadid = t.threadid
+                    JOIN threads t ON cm.threadid = t.threadid AND cm.deleted_at IS NULL
                     WHERE t.collection_id = c.collection_id) AS comment_count
          FROM collections c
          JOIN users u ON c.user_id = u.userid
@@ -782,7 +800,7 @@ pub async fn get_collection(
 
     // Get collection details
     let collection_row = client
-    .query_one(
+    .query_opt(
         "SELECT c.*, u.userid, u.username, 
         (SELECT COUNT(*) FROM collection_items ci WHERE ci.collection_id = c.collection_id) as item_count,
         EXISTS(SELECT 1 FROM flashcards f WHERE f.collection_id = c.collection_id) as has_flashcards,
@@ -796,20 +814,23 @@ pub async fn get_collection(
             )
         ) as has_collection_image,
         (SELECT COUNT(*) FROM comments cm
-            JOIN threads t ON cm.threadid = t.threadid
-            WHERE t.collection_id = c.collection_id) as comment_count
+            JOIN threads t ON cm.threadid = t.threadid AND cm.deleted_at IS NULL
+            WHERE t.collection_id = c.collection_id) as comment_count,
+        collection_role(c.collection_id, $2) as role
         FROM collections c
         JOIN users u ON c.user_id = u.userid
-             WHERE c.collection_id = $1",
-            &[&collection_id],
+             WHERE c.collection_id = $1 AND c.deleted_at IS NULL",
+            &[&collection_id, &user_id],
         )
-        .await.map_err(|e| AppError::Database(e.to_string()))?;
+        .await.map_err(|e| AppError::Database(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Collection not found".to_string()))?;
 
     let is_public: bool = collection_row.get("is_public");
     let owner_id: i32 = collection_row.get("user_id");
+    let role: Option<CollectionRole> = collection_row.get("role");
 
     // Check access
-    if !is_public && Some(owner_id) != user_id {
+    if !is_public && role.is_none() {
         return Err(AppError::Unauthorized("Access denied".to_string()));
     }
 
@@ -853,7 +874,7 @@ pub async fn update_collection(
     // Check ownership
     let owner_id: i32 = transaction
         .query_one(
-            "SELECT user_id FROM collections WHERE collection_id = $1",
+            "SELECT user_id FROM collections WHERE collection_id = $1 AND deleted_at IS NULL",
             &[&collection_id],
         )
         .await
@@ -957,29 +978,28 @@ pub async fn update_collection(
     })
 }
 
+/// Moves a collection to the trash. Its items, flashcards and study progress stay until it is
+/// restored or purged.
 pub async fn delete_collection(
     pool: &Pool,
     redis: &RedisCache,
     collection_id: i32,
     user_id: i32,
 ) -> AppResult<()> {
-    let mut client = pool
+    let client = pool
         .get()
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
-    let transaction = client
-        .transaction()
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
 
     // Check ownership
-    let owner_id: i32 = transaction
-        .query_one(
-            "SELECT user_id FROM collections WHERE collection_id = $1",
+    let owner_id: i32 = client
+        .query_opt(
+            "SELECT user_id FROM collections WHERE collection_id = $1 AND deleted_at IS NULL",
             &[&collection_id],
         )
         .await
         .map_err(|e| AppError::Database(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Collection not found".to_string()))?
         .try_get("user_id")
         .map_err(|e| AppError::Database(e.to_string()))?;
 
@@ -987,6 +1007,57 @@ pub async fn delete_collection(
         return Err(AppError::Unauthorized("Access denied".to_string()));
     }
 
+    client
+        .execute(
+            "UPDATE collections SET deleted_at = NOW(), deleted_by = $2 WHERE collection_id = $1",
+            &[&collection_id, &user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    invalidate_public_collections_cache(redis).await;
+    Ok(())
+}
+
+/// Takes a collection out of the trash, with the items and flashcards it kept.
+pub async fn restore_collection(
+    transaction: &Transaction<'_>,
+    redis: &RedisCache,
+    collection_id: i32,
+) -> AppResult<()> {
+    let restored = transaction
+        .execute(
+            "UPDATE collections SET deleted_at = NULL, deleted_by = NULL
+             WHERE collection_id = $1 AND deleted_at IS NOT NULL",
+            &[&collection_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    if restored == 0 {
+        return Err(AppError::NotFound(
+            "Collection is not in the trash".to_string(),
+        ));
+    }
+
+    invalidate_public_collections_cache(redis).await;
+    Ok(())
+}
+
+/// Removes a trashed collection for good, with its items, flashcards and levels.
+pub async fn purge_collection(transaction: &Transaction<'_>, collection_id: i32) -> AppResult<()> {
+    let trashed = transaction
+        .query_opt(
+            "SELECT 1 FROM collections
+             WHERE collection_id = $1 AND deleted_at IS NOT NULL
+             FOR UPDATE",
+            &[&collection_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    if trashed.is_none() {
+        return Ok(());
+    }
+
     // Delete in dependency order: flashcards reference collection_items, so delete flashcard
     // data first, then levels, then items, then the collection.
 
@@ -1036,12 +1107,6 @@ pub async fn delete_collection(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    transaction
-        .commit()
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    invalidate_public_collections_cache(redis).await;
     Ok(())
 }
 
@@ -1196,7 +1261,7 @@ pub async fn import_json(
 }
 
 // Helper function to parse data URL and decode base64
-fn decode_data_url(url: &str) -> AppResult<(String, Vec<u8>)> {
+pub(crate) fn decode_data_url(url: &str) -> AppResult<(String, Vec<u8>)> {
     if !url.starts_with("data:") {
         return Err(AppError::BadRequest("Invalid data URL format".to_string()));
     }
@@ -1236,12 +1301,13 @@ pub async fn import_collection_from_json(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    // Verify ownership of the target collection
-    verify_collection_ownership(&transaction, target_collection_id, user_id).await?;
+    // Verify the user may edit the target collection
+    verify_collection_edit_access(&transaction, target_collection_id, user_id).await?;
 
     let mut imported_count = 0;
     let mut skipped_count = 0;
     let mut skipped_items = Vec::new();
+    let mut imported_item_ids = Vec::new();
 
     // Get current max position in the target collection
     let mut current_max_position: i32 = transaction
@@ -1362,9 +1428,19 @@ pub async fn import_collection_from_json(
             }
         }
 
+        imported_item_ids.push(new_item_id);
         imported_count += 1;
     }
 
+    record_item_history(
+        &transaction,
+        target_collection_id,
+        &imported_item_ids,
+        user_id,
+        "add",
+    )
+    .await?;
+
     transaction
         .commit()
         .await
@@ -1380,7 +1456,7 @@ pub async fn import_collection_from_json(
 }
 
 /// Parse direction string from export; defaults to Both if missing or invalid.
-fn parse_export_direction(s: Option<&String>) -> FlashcardDirection {
+pub(crate) fn parse_export_direction(s: Option<&String>) -> FlashcardDirection {
     let s = match s {
         Some(x) => x.to_lowercase(),
         None => return FlashcardDirection::Both,
@@ -1931,6 +2007,28 @@ async fn initialize_flashcard_progress(
     Ok(())
 }
 
+/// Attributes changes to collection items to the member who made them.
+async fn record_item_history(
+    transaction: &Transaction<'_>,
+    collection_id: i32,
+    item_ids: &[i32],
+    user_id: i32,
+    action: &str,
+) -> AppResult<()> {
+    if item_ids.is_empty() {
+        return Ok(());
+    }
+    transaction
+        .execute(
+            "INSERT INTO collection_item_history (collection_id, item_id, user_id, action)
+             SELECT $1, item_id, $3, $4 FROM UNNEST($2::int4[]) AS item_id",
+            &[&collection_id, &item_ids, &user_id, &action],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    Ok(())
+}
+
 pub async fn upsert_item(
     pool: &Pool,
     redis: &RedisCache,
@@ -1947,8 +2045,7 @@ pub async fn upsert_item(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    // Check collection ownership
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Validate images if present
     if let Some(img) = &req.front_image {
@@ -2031,6 +2128,11 @@ pub async fn upsert_item(
         }
     }
 
+    let history_action = if existing_item.is_some() {
+        "update"
+    } else {
+        "add"
+    };
     let (item_id, notes, added_at): (i32, Option<String>, DateTime<Utc>) =
         if let Some(row) = existing_item {
             let item_id: i32 = row.get("item_id");
@@ -2584,6 +2686,15 @@ pub async fn upsert_item(
         }
     };
 
+    record_item_history(
+        &transaction,
+        collection_id,
+        &[item_id],
+        user_id,
+        history_action,
+    )
+    .await?;
+
     transaction
         .commit()
         .await
@@ -2648,20 +2759,7 @@ pub async fn update_item_position(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    // Check collection ownership
-    let owner_id: i32 = transaction
-        .query_one(
-            "SELECT user_id FROM collections WHERE collection_id = $1",
-            &[&collection_id],
-        )
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?
-        .try_get("user_id")
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    if owner_id != user_id {
-        return Err(AppError::Unauthorized("Access denied".to_string()));
-    }
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Get current item position
     let current_position: i32 = transaction
@@ -2721,6 +2819,8 @@ pub async fn update_item_position(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
+    record_item_history(&transaction, collection_id, &[item_id], user_id, "reorder").await?;
+
     transaction
         .commit()
         .await
@@ -2746,20 +2846,7 @@ pub async fn remove_item(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    // Check collection ownership
-    let owner_id: i32 = transaction
-        .query_one(
-            "SELECT user_id FROM collections WHERE collection_id = $1",
-            &[&collection_id],
-        )
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?
-        .try_get("user_id")
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    if owner_id != user_id {
-        return Err(AppError::Unauthorized("Access denied".to_string()));
-    }
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // First delete any associated flashcard history and progress
     transaction
@@ -2813,6 +2900,8 @@ pub async fn remove_item(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
+    record_item_history(&transaction, collection_id, &[item_id], user_id, "remove").await?;
+
     transaction
         .commit()
         .await
@@ -2858,7 +2947,7 @@ pub async fn remove_items_bulk(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     let rows = transaction
         .query(
@@ -2919,6 +3008,8 @@ pub async fn remove_items_bulk(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
+    record_item_history(&transaction, collection_id, &unique, user_id, "remove").await?;
+
     transaction
         .commit()
         .await
@@ -2973,7 +3064,7 @@ pub async fn add_items_bulk_by_definition_ids(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Split requested ids into (valid definition ids present in DB) and invalid ones.
     let existing_def_rows = transaction
@@ -3063,7 +3154,7 @@ pub async fn add_items_bulk_by_definition_ids(
 
     // Release the DB connection during CPU-bound Lojban parsing. Holding a transaction
     // open while we spin up tersmu for thousands of words would both pin a pool slot
-    // and risk a statement timeout. We'll re-verify ownership in the write transaction.
+    // and risk a statement timeout. We'll re-verify edit access in the write transaction.
     transaction
         .rollback()
         .await
@@ -3101,10 +3192,10 @@ pub async fn add_items_bulk_by_definition_ids(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    verify_collection_ownership(&write_tx, collection_id, user_id).await?;
+    verify_collection_edit_access(&write_tx, collection_id, user_id).await?;
 
-    let inserted_rows = write_tx
-        .execute(
+    let inserted_item_ids: Vec<i32> = write_tx
+        .query(
             "INSERT INTO collection_items (
                  collection_id, definition_id, notes, position,
                  auto_progress, canonical_form, is_original
@@ -3112,7 +3203,8 @@ pub async fn add_items_bulk_by_definition_ids(
              SELECT $1, x.def_id, $2, x.pos, true, x.canonical, true
                FROM UNNEST($3::int4[], $4::int4[], $5::text[])
                     AS x(def_id, pos, canonical)
-             ON CONFLICT (collection_id, definition_id) DO NOTHING",
+             ON CONFLICT (collection_id, definition_id) DO NOTHING
+             RETURNING item_id",
             &[
                 &collection_id,
                 &sanitized_notes,
@@ -3122,7 +3214,10 @@ pub async fn add_items_bulk_by_definition_ids(
             ],
         )
         .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .iter()
+        .map(|row| row.get("item_id"))
+        .collect();
 
     write_tx
         .execute(
@@ -3132,6 +3227,8 @@ pub async fn add_items_bulk_by_definition_ids(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
+    record_item_history(&write_tx, collection_id, &inserted_item_ids, user_id, "add").await?;
+
     write_tx
         .commit()
         .await
@@ -3139,7 +3236,7 @@ pub async fn add_items_bulk_by_definition_ids(
 
     invalidate_public_collections_cache(redis).await;
 
-    let added = inserted_rows as i32;
+    let added = inserted_item_ids.len() as i32;
     // Rows we *planned* to insert but `ON CONFLICT` skipped — a concurrent add
     // beat us to them, so count them as skipped for an accurate response.
     let raced_skipped = to_insert.len() as i32 - added;
@@ -3172,7 +3269,7 @@ pub async fn clone_collection(
         .query_one(
             "SELECT name, description, is_public, cover_collection_image_id
              FROM collections
-             WHERE collection_id = $1",
+             WHERE collection_id = $1 AND deleted_at IS NULL",
             &[&source_collection_id],
         )
         .await
@@ -3325,7 +3422,7 @@ pub async fn merge_collections(
     for collection_id in &[req.source_collection_id, req.target_collection_id] {
         let owner_id: i32 = transaction
             .query_one(
-                "SELECT user_id FROM collections WHERE collection_id = $1",
+                "SELECT user_id FROM collections WHERE collection_id = $1 AND deleted_at IS NULL",
                 &[collection_id],
             )
             .await
@@ -3451,16 +3548,17 @@ pub async fn list_collection_items(
     // Check collection access
     let collection = transaction
         .query_one(
-            "SELECT user_id, is_public FROM collections WHERE collection_id = $1",
-            &[&collection_id],
+            "SELECT is_public, collection_role(collection_id, $2) AS role
+             FROM collections WHERE collection_id = $1 AND deleted_at IS NULL",
+            &[&collection_id, &user_id],
         )
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
     let is_public: bool = collection.get("is_public");
-    let owner_id: i32 = collection.get("user_id");
+    let role: Option<CollectionRole> = collection.get("role");
 
-    if !is_public && Some(owner_id) != user_id {
+    if !is_public && role.is_none() {
         return Err(AppError::Unauthorized("Access denied".to_string()));
     }
 
@@ -3488,6 +3586,7 @@ pub async fn list_collection_items(
          LEFT JOIN users u ON d.userid = u.userid
          LEFT JOIN flashcards f ON ci.item_id = f.item_id
          WHERE ci.collection_id = $1 
+           AND d.deleted_at IS NULL
            AND ($2::int IS NULL OR ci.item_id = $2)
            AND ($3::boolean IS NULL OR ($3::boolean = true AND f.id IS NULL))
            AND ($4::boolean IS DISTINCT FROM true OR EXISTS (
@@ -3730,6 +3829,7 @@ pub async fn list_collection_items(
          LEFT JOIN users u ON d.userid = u.userid
          LEFT JOIN flashcards f ON ci.item_id = f.item_id
          WHERE ci.collection_id = $1
+           AND d.deleted_at IS NULL
            AND ($2::int IS NULL OR ci.item_id = $2)
            AND ($3::boolean IS NULL OR ($3::boolean = true AND f.id IS NULL))
            AND ($4::boolean IS DISTINCT FROM true OR EXISTS (
@@ -3900,6 +4000,7 @@ pub async fn search_items_in_collections(
             FROM collections c
             WHERE c.collection_id = ANY($1::int[])
               AND c.is_public = true
+              AND c.deleted_at IS NULL
          )";
     const FROM_WHERE_HEAD: &str = "
             FROM accessible c
@@ -4128,6 +4229,7 @@ pub async fn search_items_in_collections_for_export(
             FROM collections c
             WHERE c.collection_id = ANY($1::int[])
               AND c.is_public = true
+              AND c.deleted_at IS NULL
          )";
     const FROM_WHERE_HEAD: &str = "
             FROM accessible c
@@ -4357,20 +4459,7 @@ pub async fn update_item_notes(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    // Check collection ownership
-    let owner_id: i32 = transaction
-        .query_one(
-            "SELECT user_id FROM collections WHERE collection_id = $1",
-            &[&collection_id],
-        )
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?
-        .try_get("user_id")
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    if owner_id != user_id {
-        return Err(AppError::Unauthorized("Access denied".to_string()));
-    }
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Update item notes and auto_progress flag
     let item = transaction
@@ -4428,6 +4517,8 @@ pub async fn update_item_notes(
         .map(|r| r.get(0))
         .unwrap_or(false);
 
+    record_item_history(&transaction, collection_id, &[item_id], user_id, "notes").await?;
+
     transaction
         .commit()
         .await
@@ -4486,19 +4577,19 @@ pub async fn get_item_image(
 
     // Check access rights
     if let Some(uid) = user_id {
-        let owner_id: i32 = client
+        let is_member: bool = client
             .query_one(
-                "SELECT c.user_id FROM collections c 
+                "SELECT collection_role(c.collection_id, $2) IS NOT NULL FROM collections c 
                  JOIN collection_items ci ON c.collection_id = ci.collection_id 
-                 WHERE ci.item_id = $1",
-                &[&item_id],
+                 WHERE ci.item_id = $1 AND c.deleted_at IS NULL",
+                &[&item_id, &uid],
             )
             .await
             .map_err(|e| AppError::Database(e.to_string()))?
             .try_get(0)
             .map_err(|e| AppError::Database(e.to_string()))?;
 
-        if owner_id != uid {
+        if !is_member {
             return Err(AppError::Unauthorized("Access denied".to_string()));
         }
     }
@@ -4529,19 +4620,19 @@ pub async fn get_item_sound(
 
     // Check access rights
     if let Some(uid) = user_id {
-        let owner_id: i32 = client
+        let is_member: bool = client
             .query_one(
-                "SELECT c.user_id FROM collections c 
+                "SELECT collection_role(c.collection_id, $2) IS NOT NULL FROM collections c 
                  JOIN collection_items ci ON c.collection_id = ci.collection_id 
-                 WHERE ci.item_id = $1",
-                &[&item_id],
+                 WHERE ci.item_id = $1 AND c.deleted_at IS NULL",
+                &[&item_id, &uid],
             )
             .await
             .map_err(|e| AppError::Database(e.to_string()))?
             .try_get(0)
             .map_err(|e| AppError::Database(e.to_string()))?;
 
-        if owner_id != uid {
+        if !is_member {
             return Err(AppError::Unauthorized("Access denied".to_string()));
         }
     }
@@ -4575,7 +4666,16 @@ pub async fn update_item_images(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
+
+    transaction
+        .query_opt(
+            "SELECT 1 FROM collection_items WHERE collection_id = $1 AND item_id = $2",
+            &[&collection_id, &item_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Item not found".to_string()))?;
 
     // Update notes if provided
     if let Some(notes) = &req.notes {
@@ -4670,6 +4770,8 @@ pub async fn update_item_images(
             .map_err(|e| AppError::Database(e.to_string()))?;
     }
 
+    record_item_history(&transaction, collection_id, &[item_id], user_id, "media").await?;
+
     transaction
         .commit()
         .await
@@ -4735,16 +4837,16 @@ pub async fn search_items(
                       WHERE cis.item_id = ci.item_id) as has_sound
         FROM collection_items ci
         JOIN accessible_collections ac ON ci.collection_id = ac.collection_id
-        JOIN collections c ON ci.collection_id = c.collection_id
+        JOIN collections c ON ci.collection_id = c.collection_id AND c.deleted_at IS NULL
         LEFT JOIN definitions d ON ci.definition_id = d.definitionid
         LEFT JOIN valsi v ON d.valsiid = v.valsiid
         LEFT JOIN users u ON d.userid = u.userid
-        WHERE v.word ILIKE $1
+        WHERE d.deleted_at IS NULL AND (v.word ILIKE $1
            OR d.definition ILIKE $1
            OR d.notes ILIKE $1
            OR ci.notes ILIKE $1
            OR ci.free_content_front ILIKE $1
-           OR ci.free_content_back ILIKE $1
+           OR ci.free_content_back ILIKE $1)
         ORDER BY c.updated_at DESC, ci.position ASC",
     );
 
@@ -4818,7 +4920,7 @@ pub async fn list_custom_text_bulk_items(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     let rows = transaction
         .query(
@@ -4888,9 +4990,10 @@ pub async fn bulk_update_custom_text_items(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     let mut updated: i32 = 0;
+    let mut updated_item_ids = Vec::with_capacity(req.items.len());
 
     for item in &req.items {
         let sanitized_front = sanitize_html(&item.free_content_front);
@@ -4930,10 +5033,12 @@ pub async fn bulk_update_custom_text_items(
                 item.item_id
             )));
         }
+        updated_item_ids.push(item.item_id);
         updated += 1;
     }
 
     let mut inserted: i32 = 0;
+    let mut inserted_item_ids = Vec::with_capacity(req.new_items.len());
 
     if !req.new_items.is_empty() {
         let max_position: i32 = transaction
@@ -4976,15 +5081,16 @@ pub async fn bulk_update_custom_text_items(
                 .as_ref()
                 .and_then(|front| crate::utils::canonical::get_canonical_form(front.as_str()));
 
-            transaction
-                .execute(
+            let item_id: i32 = transaction
+                .query_one(
                     "INSERT INTO collection_items (
                     collection_id, definition_id,
                     free_content_front, free_content_back,
                     langid, owner_user_id, license, script, is_original,
                     notes, position, auto_progress, canonical_form
                 )
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+                RETURNING item_id",
                     &[
                         &collection_id,
                         &definition_id,
@@ -5002,13 +5108,33 @@ pub async fn bulk_update_custom_text_items(
                     ],
                 )
                 .await
-                .map_err(|e| AppError::Database(e.to_string()))?;
+                .map_err(|e| AppError::Database(e.to_string()))?
+                .get("item_id");
 
+            inserted_item_ids.push(item_id);
             next_position += 1;
             inserted += 1;
         }
     }
 
+    record_item_history(
+        &transaction,
+        collection_id,
+        &updated_item_ids,
+        user_id,
+        "update",
+    )
+    .await?;
+
+    record_item_history(
+        &transaction,
+        collection_id,
+        &inserted_item_ids,
+        user_id,
+        "add",
+    )
+    .await?;
+
     transaction
         .commit()
         .await
@@ -5032,8 +5158,9 @@ pub async fn get_collection_image_bytes(
 
     let row = client
         .query_opt(
-            "SELECT user_id, is_public FROM collections WHERE collection_id = $1",
-            &[&collection_id],
+            "SELECT is_public, collection_role(collection_id, $2) AS role
+             FROM collections WHERE collection_id = $1 AND deleted_at IS NULL",
+            &[&collection_id, &user_id],
         )
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
@@ -5043,9 +5170,9 @@ pub async fn get_collection_image_bytes(
     };
 
     let is_public: bool = row.get("is_public");
-    let owner_id: i32 = row.get("user_id");
+    let role: Option<CollectionRole> = row.get("role");
 
-    if !is_public && Some(owner_id) != user_id {
+    if !is_public && role.is_none() {
         return Err(AppError::Unauthorized("Access denied".to_string()));
     }
 
@@ -5314,7 +5441,7 @@ pub async fn bulk_import_collection_item_media(
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     let mut attached: u32 = 0;
     let mut created_items: u32 = 0;
@@ -5482,6 +5609,10 @@ pub async fn bulk_import_collection_item_media(
                 .await
                 .map_err(|e| AppError::Database(e.to_string()))?;
 
+            let action = if created_new { "add" } else { "media" };
+            record_item_history(&transaction, collection_id, &[target_item_id], user_id, action)
+                .await?;
+
             Ok(created_new)
         }
         .await;
@@ -5524,3 +5655,396 @@ pub async fn bulk_import_collection_item_media(
         warnings,
     })
 }
+
+const COLLECTION_MEMBER_SELECT: &str = "SELECT m.user_id, u.username, m.role, m.invited_by,
+            iu.username AS invited_by_username, m.invited_at, m.accepted_at
+     FROM collection_members m
+     JOIN users u ON u.userid = m.user_id
+     LEFT JOIN users iu ON iu.userid = m.invited_by";
+
+fn collection_member_from_row(row: &tokio_postgres::Row) -> CollectionMemberResponse {
+    CollectionMemberResponse {
+        user_id: row.get("user_id"),
+        username: row.get("username"),
+        role: row.get("role"),
+        invited_by: row
+            .get::<_, Option<i32>>("invited_by")
+            .map(|user_id| CollectionOwner {
+                user_id,
+                username: row.get("invited_by_username"),
+            }),
+        invited_at: row.get("invited_at"),
+        accepted_at: row.get("accepted_at"),
+    }
+}
+
+async fn get_collection_member(
+    client: &impl GenericClient,
+    collection_id: i32,
+    user_id: i32,
+) -> AppResult<CollectionMemberResponse> {
+    let row = client
+        .query_opt(
+            &format!("{COLLECTION_MEMBER_SELECT} WHERE m.collection_id = $1 AND m.user_id = $2"),
+            &[&collection_id, &user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Member not found".to_string()))?;
+    Ok(collection_member_from_row(&row))
+}
+
+fn ensure_member_role(role: CollectionRole) -> AppResult<()> {
+    if role == CollectionRole::Owner {
+        return Err(AppError::BadRequest(
+            "Members can only be editors or viewers".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Editors and viewers of a collection. Only the owner sees pending invitations.
+pub async fn list_collection_members(
+    pool: &Pool,
+    collection_id: i32,
+    user_id: i32,
+) -> AppResult<CollectionMemberListResponse> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    let collection_row = client
+        .query_opt(
+            "SELECT c.user_id, u.username, collection_role(c.collection_id, $2) AS role
+             FROM collections c
+             JOIN users u ON u.userid = c.user_id
+             WHERE c.collection_id = $1 AND c.deleted_at IS NULL",
+            &[&collection_id, &user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Collection not found".to_string()))?;
+
+    let role: CollectionRole = collection_row
+        .get::<_, Option<CollectionRole>>("role")
+        .ok_or_else(|| AppError::Auth("Access denied".to_string()))?;
+
+    let rows = client
+        .query(
+            &format!(
+                "{COLLECTION_MEMBER_SELECT}
+                 WHERE m.collection_id = $1 AND ($2 OR m.accepted_at IS NOT NULL)
+                 ORDER BY m.accepted_at IS NULL, u.username"
+            ),
+            &[&collection_id, &(role == CollectionRole::Owner)],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    Ok(CollectionMemberListResponse {
+        role,
+        owner: CollectionOwner {
+            user_id: collection_row.get("user_id"),
+            username: collection_row.get("username"),
+        },
+        members: rows.iter().map(collection_member_from_row).collect(),
+    })
+}
+
+/// Invites a user by username. The invitation grants nothing until the user accepts it.
+pub async fn invite_collection_member(
+    pool: &Pool,
+    collection_id: i32,
+    user_id: i32,
+    req: &InviteCollectionMemberRequest,
+) -> AppResult<CollectionMemberResponse> {
+    ensure_member_role(req.role)?;
+
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let transaction = client
+        .transaction()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+
+    let invitee_id: i32 = transaction
+        .query_opt(
+            "SELECT userid FROM users WHERE username = $1",
+            &[&req.username.trim()],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?
+        .get("userid");
+
+    if invitee_id == user_id {
+        return Err(AppError::BadRequest(
+            "The owner cannot be invited to their own collection".to_string(),
+        ));
+    }
+
+    let inserted = transaction
+        .execute(
+            "INSERT INTO collection_members (collection_id, user_id, role, invited_by)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (collection_id, user_id) DO NOTHING",
+            &[&collection_id, &invitee_id, &req.role, &user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    if inserted == 0 {
+        return Err(AppError::BadRequest(
+            "User is already a member or has a pending invitation".to_string(),
+        ));
+    }
+
+    let member = get_collection_member(&transaction, collection_id, invitee_id).await?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    Ok(member)
+}
+
+pub async fn update_collection_member_role(
+    pool: &Pool,
+    collection_id: i32,
+    user_id: i32,
+    member_id: i32,
+    req: &UpdateCollectionMemberRequest,
+) -> AppResult<CollectionMemberResponse> {
+    ensure_member_role(req.role)?;
+
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let transaction = client
+        .transaction()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+
+    let updated = transaction
+        .execute(
+            "UPDATE collection_members SET role = $3 WHERE collection_id = $1 AND user_id = $2",
+            &[&collection_id, &member_id, &req.role],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    if updated == 0 {
+        return Err(AppError::NotFound("Member not found".to_string()));
+    }
+
+    let member = get_collection_member(&transaction, collection_id, member_id).await?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    Ok(member)
+}
+
+/// The owner removes a member or withdraws an invitation; members remove themselves to leave
+/// the collection or decline an invitation.
+pub async fn remove_collection_member(
+    pool: &Pool,
+    collection_id: i32,
+    user_id: i32,
+    member_id: i32,
+) -> AppResult<()> {
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let transaction = client
+        .transaction()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    if member_id != user_id {
+        verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    }
+
+    let removed = transaction
+        .execute(
+            "DELETE FROM collection_members WHERE collection_id = $1 AND user_id = $2",
+            &[&collection_id, &member_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    if removed == 0 {
+        return Err(AppError::NotFound("Member not found".to_string()));
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Pending invitations to collections that are not in the trash.
+pub async fn list_collection_invitations(
+    pool: &Pool,
+    user_id: i32,
+) -> AppResult<Vec<CollectionInvitationResponse>> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    let rows = client
+        .query(
+            "SELECT m.collection_id, c.name, c.user_id AS owner_id, ou.username AS owner_username,
+                    m.role, m.invited_by, iu.username AS invited_by_username, m.invited_at
+             FROM collection_members m
+             JOIN collections c ON c.collection_id = m.collection_id
+             JOIN users ou ON ou.userid = c.user_id
+             LEFT JOIN users iu ON iu.userid = m.invited_by
+             WHERE m.user_id = $1 AND m.accepted_at IS NULL AND c.deleted_at IS NULL
+             ORDER BY m.invited_at DESC",
+            &[&user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| CollectionInvitationResponse {
+            collection_id: row.get("collection_id"),
+            collection_name: row.get("name"),
+            owner: CollectionOwner {
+                user_id: row.get("owner_id"),
+                username: row.get("owner_username"),
+            },
+            role: row.get("role"),
+            invited_by: row
+                .get::<_, Option<i32>>("invited_by")
+                .map(|user_id| CollectionOwner {
+                    user_id,
+                    username: row.get("invited_by_username"),
+                }),
+            invited_at: row.get("invited_at"),
+        })
+        .collect())
+}
+
+pub async fn accept_collection_invitation(
+    pool: &Pool,
+    collection_id: i32,
+    user_id: i32,
+) -> AppResult<CollectionMemberResponse> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    let accepted = client
+        .execute(
+            "UPDATE collection_members m SET accepted_at = CURRENT_TIMESTAMP
+             FROM collections c
+             WHERE m.collection_id = $1 AND m.user_id = $2 AND m.accepted_at IS NULL
+             AND c.collection_id = m.collection_id AND c.deleted_at IS NULL",
+            &[&collection_id, &user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    if accepted == 0 {
+        return Err(AppError::NotFound("Invitation not found".to_string()));
+    }
+
+    get_collection_member(&client, collection_id, user_id).await
+}
+
+/// Changes to the collection's items, newest first, with the member who made each of them.
+pub async fn get_collection_item_history(
+    pool: &Pool,
+    collection_id: i32,
+    user_id: i32,
+    query: &CollectionItemHistoryQuery,
+) -> AppResult<CollectionItemHistoryResponse> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    let role: Option<CollectionRole> = client
+        .query_opt(
+            "SELECT collection_role(collection_id, $2) AS role FROM collections
+             WHERE collection_id = $1 AND deleted_at IS NULL",
+            &[&collection_id, &user_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Collection not found".to_string()))?
+        .get("role");
+
+    if role.is_none() {
+        return Err(AppError::Auth("Access denied".to_string()));
+    }
+
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 100);
+    let offset = (page - 1) * per_page;
+
+    let total: i64 = client
+        .query_one(
+            "SELECT COUNT(*) FROM collection_item_history
+             WHERE collection_id = $1 AND ($2::int4 IS NULL OR item_id = $2)",
+            &[&collection_id, &query.item_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?
+        .get(0);
+
+    let rows = client
+        .query(
+            "SELECT h.history_id, h.item_id, h.action, h.user_id, u.username, h.created_at
+             FROM collection_item_history h
+             LEFT JOIN users u ON u.userid = h.user_id
+             WHERE h.collection_id = $1 AND ($2::int4 IS NULL OR h.item_id = $2)
+             ORDER BY h.created_at DESC, h.history_id DESC
+             LIMIT $3 OFFSET $4",
+            &[&collection_id, &query.item_id, &per_page, &offset],
+        )
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    let entries = rows
+        .iter()
+        .map(|row| CollectionItemHistoryEntry {
+            history_id: row.get("history_id"),
+            item_id: row.get("item_id"),
+            action: row.get("action"),
+            user: row
+                .get::<_, Option<i32>>("user_id")
+                .map(|user_id| CollectionOwner {
+                    user_id,
+                    username: row.get("username"),
+                }),
+            created_at: row.get("created_at"),
+        })
+        .collect();
+
+    Ok(CollectionItemHistoryResponse {
+        entries,
+        total,
+        page,
+        per_page,
+    })
+}
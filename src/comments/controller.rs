@@ -803,7 +803,7 @@ pub async fn comments_by_hashtag(
         ("bearer_auth" = [])
     ),
     summary = "Delete a comment",
-    description = "Deletes a comment if user is the author and it has no replies. All associated reactions, opinions and bookmarks will also be deleted."
+    description = "Moves a comment to the trash if user is the author and it has no replies. It can be restored until it is purged with its reactions, opinions and bookmarks after TRASH_RETENTION_DAYS."
 )]
 #[delete("/{comment_id}")]
 pub async fn delete_comment(
@@ -7,6 +7,7 @@ use std::{
 
 use crate::comments::models::Comment;
 use crate::middleware::cache::RedisCache;
+use crate::webhooks::{self, WebhookEvent};
 use chrono::Utc;
 use deadpool_postgres::Pool;
 use tokio_postgres::Row;
@@ -223,7 +224,7 @@ pub async fn add_comment(params: NewCommentParams) -> Result<Comment, Box<dyn st
         // If only parent_id is provided, get thread_id from parent comment
         transaction
             .query_one(
-                "SELECT threadid FROM comments WHERE commentid = $1",
+                "SELECT threadid FROM comments WHERE commentid = $1 AND deleted_at IS NULL",
                 &[&parent_id],
             )
             .await?
@@ -445,13 +446,30 @@ pub async fn add_comment(params: NewCommentParams) -> Result<Comment, Box<dyn st
                         &notif_valsi_id,
                         &format!(
                             "New comment on thread for {}",
-                            valsi_word.unwrap_or_else(|| "a valsi".to_string())
+                            valsi_word.as_deref().unwrap_or("a valsi")
                         ),
                         &url,
                         &params.user_id,
                     ],
                 )
                 .await?;
+            webhooks::service::queue_event(
+                &transaction,
+                WebhookEvent::CommentAdded,
+                Some(notif_valsi_id),
+                params.user_id,
+                serde_json::json!({
+                    "comment_id": comment_id,
+                    "thread_id": thread_id,
+                    "parent_id": params.parent_id,
+                    "definition_id": notif_definition_id,
+                    "word": valsi_word,
+                    "subject": comment.subject,
+                    "content": comment.content,
+                    "url": url,
+                }),
+            )
+            .await?;
         }
     }
 
@@ -763,7 +781,7 @@ pub async fn get_user_comments(
     // Get total count
     let total: i64 = transaction
         .query_one(
-            "SELECT COUNT(*) FROM comments WHERE userid = $1",
+            "SELECT COUNT(*) FROM comments WHERE userid = $1 AND deleted_at IS NULL",
             &[&user_id],
         )
         .await?
@@ -1295,7 +1313,10 @@ pub async fn get_most_bookmarked_comments(
 
     // Get total count first
     let total: i64 = transaction
-        .query_one("SELECT COUNT(*) FROM comments", &[])
+        .query_one(
+            "SELECT COUNT(*) FROM comments WHERE deleted_at IS NULL",
+            &[],
+        )
         .await?
         .get(0);
 
@@ -1397,7 +1418,7 @@ pub async fn get_trending_hashtags(
             SELECT h.tag, COUNT(*) as usage_count, MAX(CURRENT_TIMESTAMP) as last_used
             FROM post_hashtags ph
             JOIN hashtags h ON h.id = ph.hashtag_id
-            JOIN comments c ON c.commentid = ph.post_id
+            JOIN comments c ON c.commentid = ph.post_id AND c.deleted_at IS NULL
             WHERE to_timestamp(c.time) >= NOW() - ($1 || ' hours')::INTERVAL
             GROUP BY h.tag
             ORDER BY usage_count DESC
@@ -1439,7 +1460,7 @@ pub async fn get_comments_by_hashtag(
             "SELECT COUNT(*) FROM comments c
              JOIN post_hashtags ph ON c.commentid = ph.post_id
              JOIN hashtags h ON ph.hashtag_id = h.id
-             WHERE h.tag = $1",
+             WHERE h.tag = $1 AND c.deleted_at IS NULL",
             &[&tag],
         )
         .await?
@@ -1514,6 +1535,8 @@ pub async fn get_comments_by_hashtag(
     })
 }
 
+/// Moves a comment to the trash. Its reactions, opinions and bookmarks stay until it is restored
+/// or purged.
 pub async fn delete_comment(
     pool: &Pool,
     comment_id: i32,
@@ -1525,10 +1548,10 @@ pub async fn delete_comment(
     // Verify comment ownership and check for replies
     let row = transaction
         .query_opt(
-            "SELECT c.userid, cc.total_replies, c.threadid
+            "SELECT c.userid, cc.total_replies, c.parentid
              FROM comments c
              JOIN comment_counters cc ON c.commentid = cc.comment_id
-             WHERE c.commentid = $1",
+             WHERE c.commentid = $1 AND c.deleted_at IS NULL",
             &[&comment_id],
         )
         .await?;
@@ -1540,7 +1563,7 @@ pub async fn delete_comment(
 
     let comment_user_id: i32 = row.get("userid");
     let total_replies: i64 = row.get("total_replies");
-    let thread_id: i32 = row.get("threadid");
+    let parent_id: Option<i32> = row.get("parentid");
 
     if comment_user_id != user_id {
         return Err("Unauthorized: You can only delete your own comments".into());
@@ -1550,51 +1573,116 @@ pub async fn delete_comment(
         return Err("Cannot delete comment with replies".into());
     }
 
-    // Delete related data
+    // Thread stats are recomputed by the comment_stats_trigger
     transaction
         .execute(
-            "DELETE FROM comment_reactions WHERE comment_id = $1",
-            &[&comment_id],
+            "UPDATE comments SET deleted_at = NOW(), deleted_by = $2 WHERE commentid = $1",
+            &[&comment_id, &user_id],
         )
         .await?;
 
+    // Update parent reply count if exists
+    if let Some(parent_id) = parent_id {
+        transaction
+            .execute(
+                "UPDATE comment_counters
+                 SET total_replies = total_replies - 1
+                 WHERE comment_id = $1",
+                &[&parent_id],
+            )
+            .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Takes a comment out of the trash. Fails while the comment it replies to is still in the trash.
+pub async fn restore_comment(
+    transaction: &tokio_postgres::Transaction<'_>,
+    comment_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let row = transaction
+        .query_opt(
+            "SELECT c.parentid, p.deleted_at IS NOT NULL AS parent_deleted
+             FROM comments c
+             LEFT JOIN comments p ON p.commentid = c.parentid
+             WHERE c.commentid = $1 AND c.deleted_at IS NOT NULL
+             FOR UPDATE OF c",
+            &[&comment_id],
+        )
+        .await?
+        .ok_or("Comment is not in the trash")?;
+    let parent_id: Option<i32> = row.get("parentid");
+
+    if row.get::<_, bool>("parent_deleted") {
+        return Err("Restore the comment this one replies to first".into());
+    }
+
     transaction
         .execute(
-            "DELETE FROM comment_opinions WHERE comment_id = $1",
+            "UPDATE comments SET deleted_at = NULL, deleted_by = NULL WHERE commentid = $1",
             &[&comment_id],
         )
         .await?;
 
+    if let Some(parent_id) = parent_id {
+        transaction
+            .execute(
+                "UPDATE comment_counters
+                 SET total_replies = total_replies + 1
+                 WHERE comment_id = $1",
+                &[&parent_id],
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Removes a trashed comment for good, and its thread when no comments are left in it.
+pub async fn purge_comment(
+    transaction: &tokio_postgres::Transaction<'_>,
+    comment_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let thread_id: i32 = match transaction
+        .query_opt(
+            "SELECT threadid FROM comments
+             WHERE commentid = $1 AND deleted_at IS NOT NULL
+             FOR UPDATE",
+            &[&comment_id],
+        )
+        .await?
+    {
+        Some(row) => row.get("threadid"),
+        None => return Ok(()),
+    };
+
+    // Delete related data
     transaction
         .execute(
-            "DELETE FROM valsi_subscriptions WHERE source_comment_id = $1",
+            "DELETE FROM comment_reactions WHERE comment_id = $1",
             &[&comment_id],
         )
         .await?;
 
     transaction
         .execute(
-            "DELETE FROM comment_bookmarks WHERE comment_id = $1",
+            "DELETE FROM comment_opinions WHERE comment_id = $1",
             &[&comment_id],
         )
         .await?;
 
-    // Get parent ID before deleting
-    let parent_id: Option<i32> = transaction
-        .query_one(
-            "SELECT parentid FROM comments WHERE commentid = $1",
+    transaction
+        .execute(
+            "DELETE FROM valsi_subscriptions WHERE source_comment_id = $1",
             &[&comment_id],
         )
-        .await?
-        .get("parentid");
+        .await?;
 
-    // Update thread stats to ensure foreign key consistency
     transaction
         .execute(
-            "UPDATE threads SET
-            last_comment_id = null
-         WHERE threadid = $1",
-            &[&thread_id],
+            "DELETE FROM comment_bookmarks WHERE comment_id = $1",
+            &[&comment_id],
         )
         .await?;
 
@@ -1603,27 +1691,16 @@ pub async fn delete_comment(
         .execute("DELETE FROM comments WHERE commentid = $1", &[&comment_id])
         .await?;
 
-    // Delete the thread if it's empty
+    // Delete the thread if it's empty, including trashed comments
     transaction
         .execute(
-            "DELETE FROM threads WHERE threadid = $1 and total_comments = 0",
+            "DELETE FROM threads
+             WHERE threadid = $1
+               AND NOT EXISTS (SELECT 1 FROM comments WHERE threadid = $1)",
             &[&thread_id],
         )
         .await?;
 
-    // Update parent reply count if exists
-    if let Some(parent_id) = parent_id {
-        transaction
-            .execute(
-                "UPDATE comment_counters
-                 SET total_replies = total_replies - 1
-                 WHERE comment_id = $1",
-                &[&parent_id],
-            )
-            .await?;
-    }
-
-    transaction.commit().await?;
     Ok(())
 }
 
@@ -1732,7 +1809,8 @@ pub(crate) async fn search_comments(
         LEFT JOIN definitions d ON t.definitionid = d.definitionid
         LEFT JOIN comment_activity_counters cc ON c.commentid = cc.comment_id
         LEFT JOIN comment_bookmarks cb ON c.commentid = cb.comment_id AND cb.user_id = $1
-        WHERE (c.subject ILIKE $2 OR c.plain_content ILIKE $2 OR u.username ILIKE $2)";
+        WHERE c.hidden_at IS NULL AND c.deleted_at IS NULL
+          AND (c.subject ILIKE $2 OR c.plain_content ILIKE $2 OR u.username ILIKE $2)";
 
     let mut conditions = Vec::new();
     let mut query_params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> =
@@ -2309,7 +2387,10 @@ pub async fn list_comments(
 
     // Get total count
     let total: i64 = transaction
-        .query_one("SELECT COUNT(*) FROM comments", &[])
+        .query_one(
+            "SELECT COUNT(*) FROM comments WHERE hidden_at IS NULL AND deleted_at IS NULL",
+            &[],
+        )
         .await?
         .get(0);
 
@@ -2342,6 +2423,7 @@ pub async fn list_comments(
         LEFT JOIN definitions d ON t.definitionid = d.definitionid
         LEFT JOIN comment_activity_counters cc ON c.commentid = cc.comment_id
         LEFT JOIN comment_bookmarks cb ON c.commentid = cb.comment_id AND cb.user_id = $1
+        WHERE c.hidden_at IS NULL AND c.deleted_at IS NULL
         ORDER BY c.time {}
         LIMIT $2 OFFSET $3",
         sort_dir
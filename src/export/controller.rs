@@ -14,7 +14,7 @@ use crate::{
     path = "/export/cached",
     tag = "export",
     responses(
-        (status = 200, description = "List of cached exports", body = Vec<CachedExport>),
+        (status = 200, description = "Cached exports, and exports queued or being rebuilt with their progress", body = Vec<CachedExport>),
         (status = 500, description = "Internal server error")
     ),
     security(
@@ -36,7 +36,7 @@ pub async fn list_cached_exports(pool: web::Data<Pool>) -> impl Responder {
     tag = "export",
     params(
         ("language_tag" = String, Path, description = "Language tag"),
-        ("format" = String, Path, description = "Export format (pdf, latex, xml, json, tsv)"),
+        ("format" = String, Path, description = "Export format (pdf, latex, xml, json, tsv, stardict, dictd, kindle)"),
         ("source_lang" = Option<String>, Query, description = "Source language tag (defaults to Lojban / jbo)"),
         ("positive_scores_only" = Option<bool>, Query, description = "Only include positive-scored entries (defaults to true)")
     ),
@@ -92,7 +92,7 @@ pub async fn download_cached_export(
     tag = "export",
     params(
         ("lang" = String, Path, description = "Language tag"),
-        ("format" = Option<String>, Query, description = "Export format (pdf, latex, xml, json)"),
+        ("format" = Option<String>, Query, description = "Export format (pdf, latex, xml, json, tsv, stardict, dictd, kindle). The last three are zip bundles for offline dictionary readers."),
         ("positive_scores_only" = Option<bool>, Query, description = "When true (default), export one best positive-scored definition per word. When false, export every definition including zero/negative scores."),
         ("collection_id" = Option<i32>, Query, description = "Export only definitions from specific collection"),
         ("source_lang" = Option<String>, Query, description = "Language tag of the source/word language (defaults to Lojban)")
@@ -120,9 +120,13 @@ pub async fn export_dictionary(
         "xml" => ExportFormat::Xml,
         "json" => ExportFormat::Json,
         "tsv" => ExportFormat::Tsv,
+        "stardict" => ExportFormat::StarDict,
+        "dictd" | "dict" => ExportFormat::Dictd,
+        "kindle" => ExportFormat::Kindle,
         _ => {
-            return HttpResponse::BadRequest()
-                .body("Invalid format. Supported formats: pdf, latex, xml, json, tsv");
+            return HttpResponse::BadRequest().body(
+                "Invalid format. Supported formats: pdf, latex, xml, json, tsv, stardict, dictd, kindle",
+            );
         }
     };
 
@@ -150,7 +154,7 @@ pub async fn export_dictionary(
     path = "/export/search",
     tag = "export",
     params(
-        ("format" = Option<String>, Query, description = "Export format (pdf, latex, xml, json, tsv)"),
+        ("format" = Option<String>, Query, description = "Export format (pdf, latex, xml, json, tsv; apkg with full_collection)"),
         ("search" = Option<String>, Query, description = "Search term"),
         ("languages" = Option<String>, Query, description = "Comma-separated definition language ids"),
         ("selmaho" = Option<String>, Query, description = "Filter by selma'o"),
@@ -162,7 +166,7 @@ pub async fn export_dictionary(
         ("semantic" = Option<bool>, Query, description = "Use semantic ranking when search text is present"),
         ("collection_ids" = Option<String>, Query, description = "Comma-separated public collection ids; unioned with include-authors unless collection_only is set. Without authors, only collection matches are exported (no unscoped dictionary fallback)"),
         ("collection_only" = Option<bool>, Query, description = "When true with collection_ids, export only filtered collection items (authors filter items; no dictionary union)"),
-        ("full_collection" = Option<bool>, Query, description = "When true with format=json and exactly one collection_id, export full collection backup (metadata, all items with images, levels). Same access as get collection.")
+        ("full_collection" = Option<bool>, Query, description = "When true with format=json and exactly one collection_id, export full collection backup (metadata, all items with images, levels). With format=apkg, export an Anki deck including the caller's scheduling and review history. Same access as get collection.")
     ),
     responses(
         (status = 200, description = "Filtered search exported successfully"),
@@ -200,6 +204,8 @@ pub async fn export_search(
                 || msg.starts_with("Too many matching")
                 || msg.starts_with("No matching")
                 || msg.starts_with("Invalid format")
+                || msg.starts_with("Anki packages")
+                || msg.starts_with("StarDict, dictd and Kindle")
                 || msg.starts_with("Semantic search is disabled")
             {
                 HttpResponse::BadRequest().body(msg)
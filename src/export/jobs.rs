@@ -0,0 +1,311 @@
+//! Background builds of cached dictionary exports.
+//!
+//! [`super::service::export_all_dictionaries`] queues the exports whose language changed in
+//! `dictionary_export_jobs`; the workers started by [`spawn_export_workers`] build them a few at
+//! a time (`DICTIONARY_EXPORT_CONCURRENCY`, default 1) so xelatex cannot starve a small host.
+//! A running job is leased to its worker for [`LEASE`] and renewed while the build runs, so
+//! jobs of a worker that died are queued again without touching builds still in progress on
+//! other replicas.
+//! Queue state is reported by [`super::service::list_cached_exports`].
+
+use deadpool_postgres::Pool;
+use log::{error, info, warn};
+use std::env;
+use std::error::Error;
+use tokio::time::{interval_at, sleep, Duration, Instant};
+
+use super::models::ExportFormat;
+use super::service::{self, CachedExportKey};
+
+/// Bump when export output changes so that every cached export is rebuilt.
+const FINGERPRINT_VERSION: u32 = 1;
+const DEFAULT_CONCURRENCY: usize = 1;
+/// How often idle workers look for queued jobs, and expired leases are looked for.
+const IDLE_POLL: Duration = Duration::from_secs(30);
+/// How long a running job stays reserved for its worker without a renewal.
+const LEASE: Duration = Duration::from_secs(10 * 60);
+const LEASE_RENEWAL: Duration = Duration::from_secs(2 * 60);
+
+struct ExportJob {
+    language_tag: String,
+    source_language_tag: String,
+    format: String,
+    positive_scores_only: bool,
+}
+
+fn export_concurrency() -> usize {
+    env::var("DICTIONARY_EXPORT_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_CONCURRENCY)
+        .clamp(1, 8)
+}
+
+/// Summary of the data an export of `lang` is built from: the latest definition version
+/// (edits, including keywords), the number of definitions (deletions), vote totals and a digest
+/// of the words defined, since edits to a word (its spelling, rafsi or type) are not versioned.
+pub(crate) async fn language_fingerprint(
+    client: &deadpool_postgres::Client,
+    lang: &str,
+    source_language_tag: &str,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let row = client
+        .query_one(
+            "SELECT
+                (SELECT COALESCE(MAX(version_id), 0) FROM definition_versions
+                 WHERE langid = l.langid) AS last_version,
+                (SELECT COUNT(*) FROM definitions WHERE langid = l.langid) AS definitions,
+                (SELECT COALESCE(md5(string_agg(
+                     concat_ws(':', w.valsiid, w.word, w.rafsi, w.typeid, w.source_langid),
+                     ',' ORDER BY w.valsiid)), '')
+                 FROM valsi w
+                 WHERE EXISTS (
+                    SELECT 1 FROM definitions d
+                    WHERE d.valsiid = w.valsiid AND d.langid = l.langid
+                 )) AS words,
+                v.votes, v.vote_total, v.vote_checksum
+             FROM languages l,
+             LATERAL (
+                SELECT COUNT(*) AS votes,
+                       COALESCE(SUM(ROUND(value * 1000)::bigint), 0)::bigint AS vote_total,
+                       COALESCE(SUM(definitionid::bigint * ROUND(value * 1000)::bigint), 0)::bigint
+                           AS vote_checksum
+                FROM definitionvotes
+                WHERE langid = l.langid
+             ) v
+             WHERE l.tag = $1",
+            &[&lang],
+        )
+        .await?;
+    Ok(format!(
+        "{}:{}>{}:v{}:d{}:w{}:n{}:s{}:c{}",
+        FINGERPRINT_VERSION,
+        source_language_tag,
+        lang,
+        row.get::<_, i32>("last_version"),
+        row.get::<_, i64>("definitions"),
+        row.get::<_, String>("words"),
+        row.get::<_, i64>("votes"),
+        row.get::<_, i64>("vote_total"),
+        row.get::<_, i64>("vote_checksum"),
+    ))
+}
+
+/// Queues a build unless one is already queued or running. Returns whether a job was added.
+pub(crate) async fn enqueue(
+    client: &deadpool_postgres::Client,
+    key: &CachedExportKey<'_>,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let added = client
+        .execute(
+            "INSERT INTO dictionary_export_jobs
+                 (language_tag, source_language_tag, format, positive_scores_only)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (language_tag, source_language_tag, format, positive_scores_only)
+             DO UPDATE SET status = 'queued', queued_at = NOW(), started_at = NULL, error = NULL
+             WHERE dictionary_export_jobs.status = 'failed'",
+            &[
+                &key.language_tag,
+                &key.source_language_tag,
+                &key.format.to_string(),
+                &key.positive_scores_only,
+            ],
+        )
+        .await?;
+    Ok(added > 0)
+}
+
+/// Running jobs whose lease ran out belong to a worker that stopped; put them back in the queue.
+async fn requeue_interrupted(pool: &Pool) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    Ok(pool
+        .get()
+        .await?
+        .execute(
+            "UPDATE dictionary_export_jobs
+             SET status = 'queued', started_at = NULL, locked_until = NULL
+             WHERE status = 'running' AND (locked_until IS NULL OR locked_until < NOW())",
+            &[],
+        )
+        .await?)
+}
+
+async fn claim_job(pool: &Pool) -> Result<Option<ExportJob>, Box<dyn Error + Send + Sync>> {
+    let row = pool
+        .get()
+        .await?
+        .query_opt(
+            "UPDATE dictionary_export_jobs j
+             SET status = 'running', started_at = NOW(),
+                 locked_until = NOW() + make_interval(secs => $1)
+             FROM (
+                SELECT language_tag, source_language_tag, format, positive_scores_only
+                FROM dictionary_export_jobs
+                WHERE status = 'queued'
+                ORDER BY queued_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+             ) next
+             WHERE j.language_tag = next.language_tag
+               AND j.source_language_tag = next.source_language_tag
+               AND j.format = next.format
+               AND j.positive_scores_only = next.positive_scores_only
+             RETURNING j.language_tag, j.source_language_tag, j.format, j.positive_scores_only",
+            &[&LEASE.as_secs_f64()],
+        )
+        .await?;
+    Ok(row.map(|row| ExportJob {
+        language_tag: row.get("language_tag"),
+        source_language_tag: row.get("source_language_tag"),
+        format: row.get("format"),
+        positive_scores_only: row.get("positive_scores_only"),
+    }))
+}
+
+/// Extends the lease of a job that is still being built.
+async fn renew_lease(pool: &Pool, job: &ExportJob) -> Result<(), Box<dyn Error + Send + Sync>> {
+    pool.get()
+        .await?
+        .execute(
+            "UPDATE dictionary_export_jobs
+             SET locked_until = NOW() + make_interval(secs => $5)
+             WHERE language_tag = $1 AND source_language_tag = $2
+               AND format = $3 AND positive_scores_only = $4 AND status = 'running'",
+            &[
+                &job.language_tag,
+                &job.source_language_tag,
+                &job.format,
+                &job.positive_scores_only,
+                &LEASE.as_secs_f64(),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Removes a finished job, or marks it failed with the error.
+async fn finish_job(
+    pool: &Pool,
+    job: &ExportJob,
+    failure: Option<String>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let client = pool.get().await?;
+    let key: [&(dyn tokio_postgres::types::ToSql + Sync); 4] = [
+        &job.language_tag,
+        &job.source_language_tag,
+        &job.format,
+        &job.positive_scores_only,
+    ];
+    match failure {
+        None => {
+            client
+                .execute(
+                    "DELETE FROM dictionary_export_jobs
+                     WHERE language_tag = $1 AND source_language_tag = $2
+                       AND format = $3 AND positive_scores_only = $4",
+                    &key,
+                )
+                .await?;
+        }
+        Some(message) => {
+            client
+                .execute(
+                    "UPDATE dictionary_export_jobs
+                     SET status = 'failed', error = $5, locked_until = NULL
+                     WHERE language_tag = $1 AND source_language_tag = $2
+                       AND format = $3 AND positive_scores_only = $4",
+                    &[key[0], key[1], key[2], key[3], &message],
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn run_job(pool: &Pool, job: &ExportJob) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let format = ExportFormat::from_query(Some(&job.format))?;
+    let key = CachedExportKey {
+        language_tag: &job.language_tag,
+        source_language_tag: &job.source_language_tag,
+        format,
+        positive_scores_only: job.positive_scores_only,
+    };
+    service::rebuild_cached_export(pool, &key).await
+}
+
+/// Runs the build, renewing the job's lease every [`LEASE_RENEWAL`] until it finishes.
+async fn run_leased_job(pool: &Pool, job: &ExportJob) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let build = run_job(pool, job);
+    tokio::pin!(build);
+    let mut renewal = interval_at(Instant::now() + LEASE_RENEWAL, LEASE_RENEWAL);
+    loop {
+        tokio::select! {
+            result = &mut build => return result,
+            _ = renewal.tick() => {
+                if let Err(e) = renew_lease(pool, job).await {
+                    warn!(
+                        "Failed to renew the lease of the {} {} export job: {}",
+                        job.language_tag, job.format, e
+                    );
+                }
+            }
+        }
+    }
+}
+
+async fn work(pool: Pool) {
+    loop {
+        let job = match claim_job(&pool).await {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                sleep(IDLE_POLL).await;
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to claim dictionary export job: {}", e);
+                sleep(IDLE_POLL).await;
+                continue;
+            }
+        };
+
+        info!(
+            "Exporting dictionary for language {} in format {}",
+            job.language_tag, job.format
+        );
+        let failure = match run_leased_job(&pool, &job).await {
+            Ok(()) => None,
+            Err(e) => {
+                warn!(
+                    "Failed to export {} dictionary to {}: {}",
+                    job.language_tag, job.format, e
+                );
+                Some(e.to_string())
+            }
+        };
+        if let Err(e) = finish_job(&pool, &job, failure).await {
+            error!(
+                "Failed to record {} {} export job result: {}",
+                job.language_tag, job.format, e
+            );
+        }
+    }
+}
+
+/// Starts the export workers for the lifetime of the process, and requeues jobs whose lease
+/// expired every [`IDLE_POLL`].
+pub fn spawn_export_workers(pool: Pool) {
+    tokio::spawn(async move {
+        let concurrency = export_concurrency();
+        info!("Starting {} dictionary export worker(s)", concurrency);
+        for _ in 0..concurrency {
+            tokio::spawn(work(pool.clone()));
+        }
+        loop {
+            match requeue_interrupted(&pool).await {
+                Ok(0) => {}
+                Ok(n) => info!("Requeued {} interrupted dictionary exports", n),
+                Err(e) => error!("Failed to requeue interrupted dictionary exports: {}", e),
+            }
+            sleep(IDLE_POLL).await;
+        }
+    });
+}
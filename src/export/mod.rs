@@ -1,5 +1,7 @@
 pub mod controller;
+pub mod jobs;
 pub mod models;
+pub mod offline;
 pub mod service;
 
 use actix_web::web;
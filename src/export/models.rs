@@ -11,6 +11,14 @@ pub enum ExportFormat {
     Xml,
     Json,
     Tsv,
+    /// Anki package; only for full collection exports.
+    Apkg,
+    /// StarDict `.ifo/.idx/.syn/.dict.dz`, zipped.
+    StarDict,
+    /// dictd `.index` + `.dict.dz`, zipped.
+    Dictd,
+    /// Kindle dictionary source (OPF + HTML with `idx:entry` markup), zipped.
+    Kindle,
 }
 
 impl std::fmt::Display for ExportFormat {
@@ -21,6 +29,10 @@ impl std::fmt::Display for ExportFormat {
             ExportFormat::Xml => write!(f, "xml"),
             ExportFormat::Json => write!(f, "json"),
             ExportFormat::Tsv => write!(f, "tsv"),
+            ExportFormat::Apkg => write!(f, "apkg"),
+            ExportFormat::StarDict => write!(f, "stardict"),
+            ExportFormat::Dictd => write!(f, "dictd"),
+            ExportFormat::Kindle => write!(f, "kindle"),
         }
     }
 }
@@ -33,6 +45,10 @@ impl ExportFormat {
             ExportFormat::Xml => "application/xml",
             ExportFormat::Json => "application/json",
             ExportFormat::Tsv => "application/zip",
+            ExportFormat::Apkg => "application/octet-stream",
+            ExportFormat::StarDict | ExportFormat::Dictd | ExportFormat::Kindle => {
+                "application/zip"
+            }
         }
     }
 
@@ -43,6 +59,10 @@ impl ExportFormat {
             ExportFormat::Xml => "xml",
             ExportFormat::Json => "json",
             ExportFormat::Tsv => "zip",
+            ExportFormat::Apkg => "apkg",
+            ExportFormat::StarDict => "stardict.zip",
+            ExportFormat::Dictd => "dictd.zip",
+            ExportFormat::Kindle => "kindle.zip",
         }
     }
 
@@ -53,7 +73,13 @@ impl ExportFormat {
             "xml" => Ok(Self::Xml),
             "json" => Ok(Self::Json),
             "tsv" => Ok(Self::Tsv),
-            _ => Err("Invalid format. Supported formats: pdf, latex, xml, json, tsv"),
+            "apkg" => Ok(Self::Apkg),
+            "stardict" => Ok(Self::StarDict),
+            "dictd" | "dict" => Ok(Self::Dictd),
+            "kindle" => Ok(Self::Kindle),
+            _ => Err(
+                "Invalid format. Supported formats: pdf, latex, xml, json, tsv, apkg, stardict, dictd, kindle",
+            ),
         }
     }
 }
@@ -258,6 +284,17 @@ pub struct CachedExport {
     pub format: String,
     pub positive_scores_only: bool,
     pub filename: String,
-    #[schema(value_type = String, format = DateTime)]
-    pub created_at: DateTime<Utc>,
+    /// When the download was built; absent while its first build is pending.
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// `ready`, or the state of a pending (re)build: `queued`, `running` or `failed`.
+    pub status: String,
+    /// 1-based position among queued builds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_position: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
@@ -0,0 +1,640 @@
+//! Dictionary bundles for offline readers: StarDict, dictd and Kindle.
+//!
+//! All three are built from the same [`DictionaryEntry`] rows as the JSON export and shipped as a
+//! zip holding the files the reader expects, so they can go through the export cache like TSV.
+//!
+//! - StarDict: `.ifo`, `.idx`, `.syn` (gloss words pointing at their entries) and `.dict.dz`
+//! - dictd: `.index` and `.dict.dz`, with gloss words as extra headwords
+//! - Kindle: an OPF package with `idx:entry` markup, to be compiled with Kindle Previewer
+
+#![allow(clippy::expect_used)] // compile-time-fixed patterns
+
+use chrono::{DateTime, Utc};
+use flate2::{Compress, Compression, FlushCompress, Status};
+use regex::Regex;
+use std::cmp::Ordering;
+use std::io::{self, Cursor, Write};
+use std::sync::LazyLock;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+use super::models::DictionaryEntry;
+
+/// Uncompressed bytes per dictzip chunk; the value `dictzip` itself uses.
+const DICTZIP_CHUNK_LEN: usize = 58315;
+/// StarDict readers reject longer index words.
+const MAX_STARDICT_WORD_LEN: usize = 255;
+/// Kindle Previewer struggles with very large content files.
+const KINDLE_ENTRIES_PER_FILE: usize = 5000;
+
+static MATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$([^$]+)\$").expect("Invalid math regex pattern"));
+static SUBSCRIPT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"_\{?([0-9]+)\}?").expect("Invalid subscript regex pattern"));
+
+pub struct OfflineDictionary<'a> {
+    /// File name stem inside the bundle, e.g. `dictionary-jbo-en`.
+    pub basename: &'a str,
+    pub title: String,
+    pub source_language_tag: &'a str,
+    pub language_tag: &'a str,
+    pub created_at: DateTime<Utc>,
+}
+
+type BundleResult = Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Renders `$x_{1}$` place notation as `x₁`; readers have no TeX.
+fn plain_math(text: &str) -> String {
+    MATH.replace_all(text, |caps: &regex::Captures| {
+        SUBSCRIPT
+            .replace_all(&caps[1], |sub: &regex::Captures| {
+                sub[1]
+                    .chars()
+                    .filter_map(|d| d.to_digit(10))
+                    .filter_map(|d| char::from_u32(0x2080 + d))
+                    .collect::<String>()
+            })
+            .replace(['{', '}'], "")
+    })
+    .into_owned()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_text(text: &str) -> String {
+    escape_html(&plain_math(text)).replace('\n', "<br>")
+}
+
+fn keyword_list(keywords: &Option<Vec<crate::jbovlaste::KeywordMapping>>) -> Option<String> {
+    let keywords = keywords.as_deref().filter(|k| !k.is_empty())?;
+    Some(
+        keywords
+            .iter()
+            .map(|k| match k.meaning.as_deref().filter(|m| !m.is_empty()) {
+                Some(meaning) => format!("{} ({})", k.word, meaning),
+                None => k.word.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("; "),
+    )
+}
+
+fn grammar_line(entry: &DictionaryEntry) -> String {
+    let mut parts = vec![entry.word_type.clone()];
+    if let Some(rafsi) = entry.rafsi.as_deref().filter(|r| !r.is_empty()) {
+        parts.push(format!("rafsi: {}", rafsi));
+    }
+    if let Some(selmaho) = entry.selmaho.as_deref().filter(|s| !s.is_empty()) {
+        parts.push(format!("selma'o: {}", selmaho));
+    }
+    parts.join("  ")
+}
+
+/// Notes, etymology, collection note and keywords, labelled, in display order.
+fn extra_sections(entry: &DictionaryEntry) -> Vec<(&'static str, String)> {
+    let mut sections = Vec::new();
+    for (label, value) in [
+        ("Notes", entry.notes.clone()),
+        ("Etymology", entry.etymology.clone()),
+        ("Collection note", entry.collection_note.clone()),
+        ("Glosses", keyword_list(&entry.gloss_keywords)),
+        ("Places", keyword_list(&entry.place_keywords)),
+    ] {
+        if let Some(value) = value.filter(|v| !v.trim().is_empty()) {
+            sections.push((label, value));
+        }
+    }
+    sections
+}
+
+/// Article body without the headword (StarDict and Kindle show that themselves).
+fn article_html(entry: &DictionaryEntry) -> String {
+    let mut html = format!(
+        "<i>{}</i><br>{}",
+        escape_html(&grammar_line(entry)),
+        html_text(&entry.definition)
+    );
+    for (label, value) in extra_sections(entry) {
+        html.push_str(&format!(
+            "<br><small>{}: {}</small>",
+            label,
+            html_text(&value)
+        ));
+    }
+    html
+}
+
+/// dictd article: the headword line followed by indented text, as `dictfmt` writes it.
+fn article_text(entry: &DictionaryEntry) -> String {
+    let mut text = format!("{}\n   {}\n\n", entry.word, grammar_line(entry));
+    let mut paragraph = |body: &str| {
+        for line in plain_math(body).lines() {
+            text.push_str("   ");
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text.push('\n');
+    };
+    paragraph(&entry.definition);
+    for (label, value) in extra_sections(entry) {
+        paragraph(&format!("{}: {}", label, value));
+    }
+    text
+}
+
+/// Gloss words of an entry, used as secondary lookup keys.
+fn gloss_words(entry: &DictionaryEntry) -> Vec<&str> {
+    let mut words: Vec<&str> = entry
+        .gloss_keywords
+        .iter()
+        .flatten()
+        .map(|k| k.word.trim())
+        .filter(|w| !w.is_empty() && *w != entry.word)
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+    words
+}
+
+/// dictzip: gzip whose chunks are flushed independently, with the chunk table in the `RA`
+/// extra field so readers can seek without inflating the whole file.
+pub fn dictzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    let chunks: Vec<&[u8]> = if data.is_empty() {
+        vec![data]
+    } else {
+        data.chunks(DICTZIP_CHUNK_LEN).collect()
+    };
+    let xlen = 10 + 2 * chunks.len();
+    if xlen > u16::MAX as usize {
+        return Err(io::Error::other("data too large for dictzip"));
+    }
+
+    let mut compress = Compress::new(Compression::best(), false);
+    let mut body = Vec::with_capacity(data.len() / 3);
+    let mut sizes = Vec::with_capacity(chunks.len());
+    for (i, chunk) in chunks.iter().enumerate() {
+        let flush = if i + 1 == chunks.len() {
+            FlushCompress::Finish
+        } else {
+            FlushCompress::Full
+        };
+        let start = body.len();
+        deflate_chunk(&mut compress, chunk, flush, &mut body)?;
+        sizes.push(body.len() - start);
+    }
+
+    let mut out = Vec::with_capacity(body.len() + xlen + 20);
+    // ID1 ID2, deflate, FEXTRA, no mtime, maximum compression, Unix
+    out.extend_from_slice(&[0x1f, 0x8b, 8, 0x04, 0, 0, 0, 0, 2, 3]);
+    out.extend_from_slice(&(xlen as u16).to_le_bytes());
+    out.extend_from_slice(b"RA");
+    out.extend_from_slice(&((xlen - 4) as u16).to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&(DICTZIP_CHUNK_LEN as u16).to_le_bytes());
+    out.extend_from_slice(&(chunks.len() as u16).to_le_bytes());
+    for size in sizes {
+        let size = u16::try_from(size).map_err(|_| io::Error::other("dictzip chunk too large"))?;
+        out.extend_from_slice(&size.to_le_bytes());
+    }
+    out.extend_from_slice(&body);
+    out.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    Ok(out)
+}
+
+fn deflate_chunk(
+    compress: &mut Compress,
+    mut input: &[u8],
+    flush: FlushCompress,
+    out: &mut Vec<u8>,
+) -> io::Result<()> {
+    loop {
+        out.reserve(input.len() + 1024);
+        let before = compress.total_in();
+        let status = compress
+            .compress_vec(input, out, flush)
+            .map_err(io::Error::other)?;
+        input = &input[(compress.total_in() - before) as usize..];
+        match status {
+            Status::StreamEnd => return Ok(()),
+            // A flush is complete once it leaves output space unused.
+            _ if input.is_empty()
+                && !matches!(flush, FlushCompress::Finish)
+                && out.len() < out.capacity() =>
+            {
+                return Ok(())
+            }
+            _ => {}
+        }
+    }
+}
+
+/// StarDict index order: ASCII case-insensitive, then bytewise.
+fn stardict_cmp(a: &str, b: &str) -> Ordering {
+    let folded = |s: &str| {
+        s.bytes()
+            .map(|b| b.to_ascii_lowercase())
+            .collect::<Vec<_>>()
+    };
+    folded(a).cmp(&folded(b)).then_with(|| a.cmp(b))
+}
+
+fn stardict_word(word: &str) -> Option<&str> {
+    let word = word.trim();
+    (!word.is_empty() && word.len() <= MAX_STARDICT_WORD_LEN && !word.contains('\0'))
+        .then_some(word)
+}
+
+fn zip_bundle(files: &[(String, Vec<u8>)]) -> BundleResult {
+    let mut buffer = Vec::new();
+    {
+        let mut zip = ZipWriter::new(Cursor::new(&mut buffer));
+        for (name, content) in files {
+            // dict.dz is compressed already and must stay seekable once extracted.
+            let method = if name.ends_with(".dz") {
+                zip::CompressionMethod::Stored
+            } else {
+                zip::CompressionMethod::Deflated
+            };
+            zip.start_file(
+                name.as_str(),
+                SimpleFileOptions::default().compression_method(method),
+            )?;
+            zip.write_all(content)?;
+        }
+        zip.finish()?;
+    }
+    Ok(buffer)
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub fn stardict_bundle(info: &OfflineDictionary, entries: &[DictionaryEntry]) -> BundleResult {
+    let mut sorted: Vec<(&str, &DictionaryEntry)> = entries
+        .iter()
+        .filter_map(|e| stardict_word(&e.word).map(|w| (w, e)))
+        .collect();
+    sorted.sort_by(|a, b| stardict_cmp(a.0, b.0));
+
+    let mut dict = Vec::new();
+    let mut idx = Vec::new();
+    let mut synonyms: Vec<(&str, u32)> = Vec::new();
+    for (index, (word, entry)) in sorted.iter().enumerate() {
+        let article = article_html(entry);
+        idx.extend_from_slice(word.as_bytes());
+        idx.push(0);
+        idx.extend_from_slice(&(dict.len() as u32).to_be_bytes());
+        idx.extend_from_slice(&(article.len() as u32).to_be_bytes());
+        dict.extend_from_slice(article.as_bytes());
+        synonyms.extend(
+            gloss_words(entry)
+                .into_iter()
+                .filter_map(stardict_word)
+                .map(|w| (w, index as u32)),
+        );
+    }
+    synonyms.sort_by(|a, b| stardict_cmp(a.0, b.0).then(a.1.cmp(&b.1)));
+    let mut syn = Vec::new();
+    for (word, index) in &synonyms {
+        syn.extend_from_slice(word.as_bytes());
+        syn.push(0);
+        syn.extend_from_slice(&index.to_be_bytes());
+    }
+
+    let ifo = format!(
+        "StarDict's dict ifo file\n\
+         version=2.4.2\n\
+         bookname={}\n\
+         wordcount={}\n\
+         synwordcount={}\n\
+         idxfilesize={}\n\
+         author=jbovlaste\n\
+         description=Exported from jbovlaste ({} to {})\n\
+         date={}\n\
+         sametypesequence=h\n",
+        single_line(&info.title),
+        sorted.len(),
+        synonyms.len(),
+        idx.len(),
+        info.source_language_tag,
+        info.language_tag,
+        info.created_at.format("%Y.%m.%d"),
+    );
+
+    let base = info.basename;
+    zip_bundle(&[
+        (format!("{base}/{base}.ifo"), ifo.into_bytes()),
+        (format!("{base}/{base}.idx"), idx),
+        (format!("{base}/{base}.syn"), syn),
+        (format!("{base}/{base}.dict.dz"), dictzip(&dict)?),
+    ])
+}
+
+/// Offsets and lengths in the dictd `.index` use this alphabet, most significant digit first.
+fn dictd_base64(mut value: u64) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut digits = vec![ALPHABET[(value % 64) as usize] as char];
+    value /= 64;
+    while value > 0 {
+        digits.push(ALPHABET[(value % 64) as usize] as char);
+        value /= 64;
+    }
+    digits.iter().rev().collect()
+}
+
+/// dictd looks headwords up by binary search, ignoring case and punctuation.
+fn dictd_sort_key(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+pub fn dictd_bundle(info: &OfflineDictionary, entries: &[DictionaryEntry]) -> BundleResult {
+    let mut dict = String::new();
+    let mut headwords: Vec<(String, usize, usize)> = Vec::new();
+    let mut push_article = |dict: &mut String, names: Vec<String>, article: String| {
+        let offset = dict.len();
+        dict.push_str(&article);
+        for name in names {
+            headwords.push((name, offset, article.len()));
+        }
+    };
+
+    for (name, article) in [
+        ("00-database-utf8".to_string(), String::new()),
+        (
+            "00-database-short".to_string(),
+            format!("00-database-short\n   {}\n", single_line(&info.title)),
+        ),
+        (
+            "00-database-info".to_string(),
+            format!(
+                "00-database-info\n   Exported from jbovlaste on {}.\n",
+                info.created_at.format("%Y-%m-%d")
+            ),
+        ),
+    ] {
+        push_article(&mut dict, vec![name], article);
+    }
+
+    for entry in entries {
+        let mut names = vec![single_line(&entry.word)];
+        names.extend(gloss_words(entry).into_iter().map(single_line));
+        names.retain(|n| !n.is_empty());
+        if names.is_empty() {
+            continue;
+        }
+        push_article(&mut dict, names, article_text(entry));
+    }
+
+    headwords.sort_by(|a, b| {
+        dictd_sort_key(&a.0)
+            .cmp(&dictd_sort_key(&b.0))
+            .then_with(|| a.0.cmp(&b.0))
+            .then(a.1.cmp(&b.1))
+    });
+    let mut index = String::new();
+    for (name, offset, len) in &headwords {
+        index.push_str(&format!(
+            "{}\t{}\t{}\n",
+            name,
+            dictd_base64(*offset as u64),
+            dictd_base64(*len as u64)
+        ));
+    }
+
+    let base = info.basename;
+    zip_bundle(&[
+        (format!("{base}.index"), index.into_bytes()),
+        (format!("{base}.dict.dz"), dictzip(dict.as_bytes())?),
+    ])
+}
+
+fn kindle_page(entries: &[DictionaryEntry]) -> String {
+    let mut html = String::from(
+        "<html xmlns:mbp=\"https://kindlegen.s3.amazonaws.com/AmazonKindlePublishingGuidelines.pdf\" \
+         xmlns:idx=\"https://kindlegen.s3.amazonaws.com/AmazonKindlePublishingGuidelines.pdf\">\n\
+         <head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=utf-8\"></head>\n\
+         <body>\n<mbp:frameset>\n",
+    );
+    for entry in entries {
+        let word = escape_html(&entry.word);
+        html.push_str(&format!(
+            "<idx:entry name=\"default\" scriptable=\"yes\" spell=\"yes\">\n\
+             <idx:orth value=\"{word}\"><b>{word}</b></idx:orth>\n\
+             <p>{}</p>\n\
+             </idx:entry>\n<hr/>\n",
+            article_html(entry)
+        ));
+    }
+    html.push_str("</mbp:frameset>\n</body>\n</html>\n");
+    html
+}
+
+pub fn kindle_bundle(info: &OfflineDictionary, entries: &[DictionaryEntry]) -> BundleResult {
+    let base = info.basename;
+    let mut files = Vec::new();
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    for (i, page) in entries.chunks(KINDLE_ENTRIES_PER_FILE).enumerate() {
+        let name = format!("{base}-{:03}.html", i + 1);
+        manifest.push_str(&format!(
+            "    <item id=\"content{i}\" href=\"{name}\" media-type=\"application/xhtml+xml\"/>\n"
+        ));
+        spine.push_str(&format!("    <itemref idref=\"content{i}\"/>\n"));
+        files.push((format!("{base}/{name}"), kindle_page(page).into_bytes()));
+    }
+
+    let opf = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <package unique-identifier=\"uid\" version=\"2.0\" xmlns=\"http://www.idpf.org/2007/opf\">\n\
+         <metadata>\n\
+         <dc-metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+         <dc:Identifier id=\"uid\">{base}</dc:Identifier>\n\
+         <dc:Title>{title}</dc:Title>\n\
+         <dc:Language>{source}</dc:Language>\n\
+         <dc:Creator>jbovlaste</dc:Creator>\n\
+         <dc:Date>{date}</dc:Date>\n\
+         </dc-metadata>\n\
+         <x-metadata>\n\
+         <DictionaryInLanguage>{source}</DictionaryInLanguage>\n\
+         <DictionaryOutLanguage>{target}</DictionaryOutLanguage>\n\
+         <DefaultLookupIndex>default</DefaultLookupIndex>\n\
+         </x-metadata>\n\
+         </metadata>\n\
+         <manifest>\n{manifest}</manifest>\n\
+         <spine>\n{spine}</spine>\n\
+         </package>\n",
+        title = escape_html(&single_line(&info.title)),
+        source = escape_html(info.source_language_tag),
+        target = escape_html(info.language_tag),
+        date = info.created_at.format("%Y-%m-%d"),
+    );
+    files.insert(0, (format!("{base}/{base}.opf"), opf.into_bytes()));
+    zip_bundle(&files)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::jbovlaste::KeywordMapping;
+    use flate2::read::GzDecoder;
+    use flate2::{Decompress, FlushDecompress};
+    use std::io::Read;
+
+    fn entry(word: &str, definition: &str, glosses: &[&str]) -> DictionaryEntry {
+        DictionaryEntry {
+            word: word.to_string(),
+            word_type: "gismu".to_string(),
+            rafsi: None,
+            selmaho: None,
+            definition: definition.to_string(),
+            definition_id: None,
+            notes: None,
+            etymology: None,
+            jargon: None,
+            collection_note: None,
+            score: 1.0,
+            gloss_keywords: Some(
+                glosses
+                    .iter()
+                    .map(|g| KeywordMapping {
+                        word: g.to_string(),
+                        meaning: None,
+                    })
+                    .collect(),
+            ),
+            place_keywords: None,
+            user: None,
+        }
+    }
+
+    fn info() -> OfflineDictionary<'static> {
+        OfflineDictionary {
+            basename: "dictionary-jbo-en",
+            title: "jbovlaste: Lojban to English".to_string(),
+            source_language_tag: "jbo",
+            language_tag: "en",
+            created_at: DateTime::from_timestamp(0, 0).unwrap(),
+        }
+    }
+
+    fn unzip(bundle: &[u8], name: &str) -> Vec<u8> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bundle)).unwrap();
+        let mut file = archive.by_name(name).unwrap();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).unwrap();
+        content
+    }
+
+    fn gunzip(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn dictzip_chunks_inflate_on_their_own() {
+        let data: Vec<u8> = (0..DICTZIP_CHUNK_LEN * 2 + 100)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect();
+        let dz = dictzip(&data).unwrap();
+        assert_eq!(gunzip(&dz), data);
+
+        let chunk_count = u16::from_le_bytes([dz[20], dz[21]]) as usize;
+        assert_eq!(chunk_count, 3);
+        let sizes: Vec<usize> = (0..chunk_count)
+            .map(|i| u16::from_le_bytes([dz[22 + 2 * i], dz[23 + 2 * i]]) as usize)
+            .collect();
+        let second_start = 22 + 2 * chunk_count + sizes[0];
+        let mut inflate = Decompress::new(false);
+        let mut second = Vec::with_capacity(DICTZIP_CHUNK_LEN);
+        inflate
+            .decompress_vec(
+                &dz[second_start..second_start + sizes[1]],
+                &mut second,
+                FlushDecompress::Sync,
+            )
+            .unwrap();
+        assert_eq!(second, &data[DICTZIP_CHUNK_LEN..DICTZIP_CHUNK_LEN * 2]);
+    }
+
+    #[test]
+    fn stardict_index_points_into_dict() {
+        let entries = vec![
+            entry("klama", "$x_{1}$ comes to $x_{2}$", &["come"]),
+            entry("broda", "$x_1$ is a predicate", &[]),
+        ];
+        let bundle = stardict_bundle(&info(), &entries).unwrap();
+        let dict = gunzip(&unzip(
+            &bundle,
+            "dictionary-jbo-en/dictionary-jbo-en.dict.dz",
+        ));
+        let idx = unzip(&bundle, "dictionary-jbo-en/dictionary-jbo-en.idx");
+
+        assert!(idx.starts_with(b"broda\0"));
+        let klama = idx.windows(6).position(|w| w == b"klama\0").unwrap() + 6;
+        let offset = u32::from_be_bytes(idx[klama..klama + 4].try_into().unwrap()) as usize;
+        let size = u32::from_be_bytes(idx[klama + 4..klama + 8].try_into().unwrap()) as usize;
+        let article = String::from_utf8(dict[offset..offset + size].to_vec()).unwrap();
+        assert!(article.contains("x₁ comes to x₂"));
+
+        let syn = unzip(&bundle, "dictionary-jbo-en/dictionary-jbo-en.syn");
+        assert_eq!(syn, b"come\0\0\0\0\x01");
+        let ifo =
+            String::from_utf8(unzip(&bundle, "dictionary-jbo-en/dictionary-jbo-en.ifo")).unwrap();
+        assert!(ifo.contains("wordcount=2\n"));
+        assert!(ifo.contains(&format!("idxfilesize={}\n", idx.len())));
+    }
+
+    #[test]
+    fn dictd_index_uses_dictd_base64() {
+        assert_eq!(dictd_base64(0), "A");
+        assert_eq!(dictd_base64(64), "BA");
+        assert_eq!(dictd_base64(4095), "//");
+
+        let entries = vec![entry("ko'a", "pro-sumti", &["it"])];
+        let bundle = dictd_bundle(&info(), &entries).unwrap();
+        let dict = gunzip(&unzip(&bundle, "dictionary-jbo-en.dict.dz"));
+        let index = String::from_utf8(unzip(&bundle, "dictionary-jbo-en.index")).unwrap();
+        let line = index.lines().find(|l| l.starts_with("it\t")).unwrap();
+        let fields: Vec<&str> = line.split('\t').collect();
+        let decode = |s: &str| {
+            s.bytes().fold(0usize, |acc, b| {
+                acc * 64
+                    + b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/"
+                        .iter()
+                        .position(|c| *c == b)
+                        .unwrap()
+            })
+        };
+        let (offset, len) = (decode(fields[1]), decode(fields[2]));
+        assert!(String::from_utf8_lossy(&dict[offset..offset + len]).starts_with("ko'a\n"));
+        assert!(index.contains("00-database-utf8\t"));
+    }
+
+    #[test]
+    fn kindle_entries_are_escaped() {
+        let entries = vec![entry("a<b", "x & y", &[])];
+        let bundle = kindle_bundle(&info(), &entries).unwrap();
+        let page = String::from_utf8(unzip(
+            &bundle,
+            "dictionary-jbo-en/dictionary-jbo-en-001.html",
+        ))
+        .unwrap();
+        assert!(page.contains("<idx:orth value=\"a&lt;b\">"));
+        assert!(page.contains("x &amp; y"));
+        let opf =
+            String::from_utf8(unzip(&bundle, "dictionary-jbo-en/dictionary-jbo-en.opf")).unwrap();
+        assert!(opf.contains("href=\"dictionary-jbo-en-001.html\""));
+        assert!(opf.contains("<DictionaryOutLanguage>en</DictionaryOutLanguage>"));
+    }
+}
@@ -1,5 +1,4 @@
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
-use chrono::DateTime;
 use chrono::Datelike;
 use chrono::Utc;
 use deadpool_postgres::Pool;
@@ -14,6 +13,7 @@ use tokio::time::{timeout, Duration};
 use xml::writer::{EventWriter, XmlEvent};
 use zip::write::{SimpleFileOptions, ZipWriter};
 
+use super::jobs;
 use super::models::CachedExport;
 use super::models::CollectionExportItem;
 use super::models::DictionaryEntry;
@@ -23,6 +23,7 @@ use super::models::SearchExportQuery;
 use super::models::User;
 use super::models::ValsiRow;
 use super::models::{ExportFormat, ExportOptions, SEARCH_EXPORT_ROW_CAP};
+use super::offline;
 use crate::jbovlaste::KeywordMapping;
 use std::collections::HashMap;
 
@@ -400,15 +401,16 @@ pub async fn verify_collection_access(
 ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
     let row = transaction
         .query_one(
-            "SELECT user_id, is_public FROM collections WHERE collection_id = $1",
-            &[&collection_id],
+            "SELECT is_public, collection_role(collection_id, $2) IS NOT NULL AS is_member
+             FROM collections WHERE collection_id = $1 AND deleted_at IS NULL",
+            &[&collection_id, &user_id],
         )
         .await?;
 
     let is_public: bool = row.get("is_public");
-    let owner_id: i32 = row.get("user_id");
+    let is_member: bool = row.get("is_member");
 
-    Ok(is_public || user_id == Some(owner_id))
+    Ok(is_public || is_member)
 }
 
 const DEFAULT_SOURCE_LANGID: i32 = 1;
@@ -517,7 +519,7 @@ pub async fn export_dictionary(
                    AND source_language_tag = $2
                    AND format = $3
                    AND positive_scores_only = $4
-                   AND created_at > NOW() - INTERVAL '4 days'",
+                   AND checked_at > NOW() - INTERVAL '4 days'",
                 &[
                     &lang,
                     &source_language_tag,
@@ -540,6 +542,22 @@ pub async fn export_dictionary(
         transaction.commit().await?;
     }
 
+    // Fingerprint the data before generating, so changes made meanwhile trigger a rebuild.
+    let fingerprint = if use_dictionary_cache {
+        match jobs::language_fingerprint(&pool.get().await?, lang, source_language_tag).await {
+            Ok(fingerprint) => Some(fingerprint),
+            Err(e) => {
+                error!(
+                    "Failed to fingerprint {} export data, not caching it: {}",
+                    lang, e
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // If not in cache, or batch refresh bypassed cache read, generate
     let result = generate_export(
         pool,
@@ -554,45 +572,67 @@ pub async fn export_dictionary(
 
     // Cache the result of on-demand dictionary exports so the next identical
     // request does not pay the xelatex generation cost again.
-    if use_dictionary_cache && collection_id.is_none() {
-        let format_str = format.to_string();
-        match pool.get().await {
-            Ok(c) => {
-                if let Err(e) = c
-                    .execute(
-                        "INSERT INTO cached_dictionary_exports
-                         (language_tag, source_language_tag, format, positive_scores_only, content, content_type, filename)
-                         VALUES ($1, $2, $3, $4, $5, $6, $7)
-                         ON CONFLICT (language_tag, source_language_tag, format, positive_scores_only)
-                         DO UPDATE SET
-                            content = EXCLUDED.content,
-                            content_type = EXCLUDED.content_type,
-                            filename = EXCLUDED.filename,
-                            created_at = CURRENT_TIMESTAMP",
-                        &[
-                            &lang,
-                            &source_language_tag,
-                            &format_str,
-                            &positive_scores_only,
-                            &result.0.as_slice(),
-                            &result.1,
-                            &result.2,
-                        ],
-                    )
-                    .await
-                {
-                    error!("Failed to cache on-demand {} export for {}: {}", format, lang, e);
-                }
-            }
-            Err(e) => {
-                error!("Failed to get DB pool to cache on-demand {} export for {}: {}", format, lang, e);
-            }
+    if let Some(fingerprint) = fingerprint {
+        let key = CachedExportKey {
+            language_tag: lang,
+            source_language_tag,
+            format,
+            positive_scores_only,
+        };
+        if let Err(e) = store_cached_export(pool, &key, &result, &fingerprint).await {
+            error!(
+                "Failed to cache on-demand {} export for {}: {}",
+                format, lang, e
+            );
         }
     }
 
     Ok(result)
 }
 
+/// Identity of a row in `cached_dictionary_exports`.
+pub(crate) struct CachedExportKey<'a> {
+    pub language_tag: &'a str,
+    pub source_language_tag: &'a str,
+    pub format: ExportFormat,
+    pub positive_scores_only: bool,
+}
+
+pub(crate) async fn store_cached_export(
+    pool: &Pool,
+    key: &CachedExportKey<'_>,
+    (content, content_type, filename): &(Vec<u8>, String, String),
+    fingerprint: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    pool.get()
+        .await?
+        .execute(
+            "INSERT INTO cached_dictionary_exports
+             (language_tag, source_language_tag, format, positive_scores_only, content, content_type, filename, fingerprint)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT (language_tag, source_language_tag, format, positive_scores_only)
+             DO UPDATE SET
+                content = EXCLUDED.content,
+                content_type = EXCLUDED.content_type,
+                filename = EXCLUDED.filename,
+                fingerprint = EXCLUDED.fingerprint,
+                created_at = CURRENT_TIMESTAMP,
+                checked_at = CURRENT_TIMESTAMP",
+            &[
+                &key.language_tag,
+                &key.source_language_tag,
+                &key.format.to_string(),
+                &key.positive_scores_only,
+                &content.as_slice(),
+                content_type,
+                filename,
+                &fingerprint,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
 fn zip_tsv_content(
     tsv_content: &str,
     filename: &str,
@@ -621,18 +661,30 @@ fn zip_tsv_files(
     Ok(zip_buffer)
 }
 
-fn build_export_filename(
+fn build_export_basename(
     collection_id: Option<i32>,
     source_language_tag: &str,
     lang: &str,
-    extension: &str,
 ) -> String {
     match collection_id {
-        Some(id) => format!("collection-{}-{}.{}", id, lang, extension),
-        None => format!("dictionary-{}-{}.{}", source_language_tag, lang, extension),
+        Some(id) => format!("collection-{}-{}", id, lang),
+        None => format!("dictionary-{}-{}", source_language_tag, lang),
     }
 }
 
+fn build_export_filename(
+    collection_id: Option<i32>,
+    source_language_tag: &str,
+    lang: &str,
+    extension: &str,
+) -> String {
+    format!(
+        "{}.{}",
+        build_export_basename(collection_id, source_language_tag, lang),
+        extension
+    )
+}
+
 async fn generate_export(
     pool: &Pool,
     lang: &str,
@@ -717,6 +769,23 @@ async fn generate_export(
                 build_export_filename(collection_id, source_language_tag, lang, "tsv");
             zip_tsv_content(&tsv, &tsv_filename)?
         }
+        ExportFormat::Apkg => {
+            return Err(APKG_FULL_COLLECTION_ONLY.into());
+        }
+        ExportFormat::StarDict | ExportFormat::Dictd | ExportFormat::Kindle => {
+            let bundle = generate_offline_dictionary(
+                &mut transaction,
+                format,
+                lang,
+                options,
+                collection_id,
+                source_langid,
+                source_language_tag,
+            )
+            .await?;
+            transaction.commit().await?;
+            bundle
+        }
     };
 
     Ok((content, content_type, filename))
@@ -816,7 +885,7 @@ async fn generate_xml(
             LEFT JOIN valsi v ON d.valsiid = v.valsiid
             LEFT JOIN valsitypes t ON v.typeid = t.typeid
             LEFT JOIN convenientdefinitions c ON c.definitionid = d.definitionid
-            WHERE ci.collection_id = $1
+            WHERE ci.collection_id = $1 AND d.deleted_at IS NULL
             ORDER BY ci.position";
 
         let rows = transaction.query(query, &[&id]).await?;
@@ -868,7 +937,7 @@ async fn generate_xml(
             LEFT JOIN valsi v ON d.valsiid = v.valsiid
             LEFT JOIN valsitypes t ON v.typeid = t.typeid
             LEFT JOIN convenientdefinitions c ON c.definitionid = d.definitionid -- For rafsi/selmaho if needed
-            WHERE ci.collection_id = $1
+            WHERE ci.collection_id = $1 AND d.deleted_at IS NULL
             ORDER BY ci.position";
 
         let rows = transaction.query(query, &[&id]).await?;
@@ -1124,7 +1193,7 @@ async fn generate_latex(
             LEFT JOIN valsi v ON d.valsiid = v.valsiid
             LEFT JOIN valsitypes t ON v.typeid = t.typeid
             LEFT JOIN convenientdefinitions c ON c.definitionid = d.definitionid
-            WHERE ci.collection_id = $1
+            WHERE ci.collection_id = $1 AND d.deleted_at IS NULL
             ORDER BY ci.position";
 
         let rows = transaction.query(query, &[&id]).await?;
@@ -1274,7 +1343,7 @@ async fn generate_collection_latex(
         LEFT JOIN valsi v ON d.valsiid = v.valsiid
         LEFT JOIN valsitypes t ON v.typeid = t.typeid
         LEFT JOIN convenientdefinitions c ON c.definitionid = d.definitionid
-        WHERE ci.collection_id = $1
+        WHERE ci.collection_id = $1 AND d.deleted_at IS NULL
         ORDER BY ci.position";
 
     let rows = transaction.query(query, &[&collection_id]).await?;
@@ -1603,7 +1672,7 @@ async fn generate_tsv(
             LEFT JOIN valsi v ON d.valsiid = v.valsiid
             LEFT JOIN valsitypes t ON v.typeid = t.typeid
             LEFT JOIN convenientdefinitions c ON c.definitionid = d.definitionid
-            WHERE ci.collection_id = $1
+            WHERE ci.collection_id = $1 AND d.deleted_at IS NULL
             ORDER BY ci.position";
 
         let rows = transaction.query(query, &[&id]).await?;
@@ -1643,7 +1712,7 @@ async fn generate_tsv(
             LEFT JOIN valsi v ON d.valsiid = v.valsiid
             LEFT JOIN valsitypes t ON v.typeid = t.typeid
             LEFT JOIN convenientdefinitions c ON c.definitionid = d.definitionid
-            WHERE ci.collection_id = $1
+            WHERE ci.collection_id = $1 AND d.deleted_at IS NULL
             ORDER BY ci.position";
 
         let rows = transaction.query(query, &[&id]).await?;
@@ -1861,7 +1930,7 @@ async fn generate_json(
             LEFT JOIN valsi v ON d.valsiid = v.valsiid
             LEFT JOIN valsitypes t ON v.typeid = t.typeid
             LEFT JOIN convenientdefinitions c ON c.definitionid = d.definitionid
-            WHERE ci.collection_id = $1
+            WHERE ci.collection_id = $1 AND d.deleted_at IS NULL
             ORDER BY ci.position";
 
         let rows = transaction.query(query, &[&id]).await?;
@@ -1887,6 +1956,20 @@ async fn generate_json(
         return Ok(serde_json::to_string_pretty(&entries)?);
     }
 
+    let entries =
+        fetch_dictionary_entries(transaction, lang, options, collection_id, source_langid).await?;
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+/// Best definitions in `lang` with their keywords, as exported to JSON and the offline formats.
+/// With `collection_id`, only definitions in that collection, with the collection notes.
+async fn fetch_dictionary_entries(
+    transaction: &mut Transaction<'_>,
+    lang: &str,
+    options: &ExportOptions,
+    collection_id: Option<i32>,
+    source_langid: i32,
+) -> Result<Vec<DictionaryEntry>, Box<dyn std::error::Error + Send + Sync>> {
     let positive_scores_only = options.positive_scores_only.unwrap_or(true);
 
     let collection_join = collection_id
@@ -1958,39 +2041,116 @@ async fn generate_json(
         })
         .collect();
 
-    Ok(serde_json::to_string_pretty(&entries)?)
+    Ok(entries)
+}
+
+async fn generate_offline_dictionary(
+    transaction: &mut Transaction<'_>,
+    format: ExportFormat,
+    lang: &str,
+    options: &ExportOptions,
+    collection_id: Option<i32>,
+    source_langid: i32,
+    source_language_tag: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let entries =
+        fetch_dictionary_entries(transaction, lang, options, collection_id, source_langid).await?;
+
+    let tags: Vec<&str> = vec![lang, source_language_tag];
+    let names: HashMap<String, String> = transaction
+        .query(
+            "SELECT tag, realname FROM languages WHERE tag = ANY($1)",
+            &[&tags],
+        )
+        .await?
+        .iter()
+        .map(|row| (row.get("tag"), row.get("realname")))
+        .collect();
+    let name = |tag: &str| names.get(tag).cloned().unwrap_or_else(|| tag.to_string());
+    let mut title = format!("jbovlaste: {} → {}", name(source_language_tag), name(lang));
+    if let Some(id) = collection_id {
+        title.push_str(&format!(" (collection {})", id));
+    }
+
+    let basename = build_export_basename(collection_id, source_language_tag, lang);
+    let info = offline::OfflineDictionary {
+        basename: &basename,
+        title,
+        source_language_tag,
+        language_tag: lang,
+        created_at: Utc::now(),
+    };
+    match format {
+        ExportFormat::StarDict => offline::stardict_bundle(&info, &entries),
+        ExportFormat::Dictd => offline::dictd_bundle(&info, &entries),
+        ExportFormat::Kindle => offline::kindle_bundle(&info, &entries),
+        _ => Err(format!("{} is not an offline dictionary format", format).into()),
+    }
 }
 
+/// Cached exports within the TTL, plus exports the background worker has queued, is building or
+/// failed to build. A cached export with a pending rebuild stays downloadable meanwhile.
 pub async fn list_cached_exports(
     pool: &Pool,
 ) -> Result<Vec<CachedExport>, Box<dyn Error + Send + Sync>> {
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    // Only list exports that are still within the cache TTL.
     let rows = transaction
         .query(
-            "SELECT cde.language_tag, cde.source_language_tag,
-                l.realname AS language_realname, cde.format,
-                cde.positive_scores_only, cde.filename, cde.created_at
-         FROM cached_dictionary_exports cde
-         JOIN languages l ON cde.language_tag = l.tag
-         WHERE cde.created_at > NOW() - INTERVAL '4 days'
-         ORDER BY l.realname",
+            "WITH cached AS (
+                SELECT language_tag, source_language_tag, format, positive_scores_only,
+                       filename, created_at
+                FROM cached_dictionary_exports
+                WHERE checked_at > NOW() - INTERVAL '4 days'
+             ),
+             queue AS (
+                SELECT language_tag, source_language_tag, format, positive_scores_only,
+                       status, started_at, error,
+                       CASE WHEN status = 'queued' THEN
+                           ROW_NUMBER() OVER (PARTITION BY status ORDER BY queued_at, language_tag, format)
+                       END AS queue_position
+                FROM dictionary_export_jobs
+             )
+             SELECT language_tag, source_language_tag, format, positive_scores_only,
+                    l.realname AS language_realname, c.filename, c.created_at,
+                    q.status, q.started_at, q.error, q.queue_position
+             FROM cached c
+             FULL OUTER JOIN queue q
+                 USING (language_tag, source_language_tag, format, positive_scores_only)
+             JOIN languages l ON l.tag = language_tag
+             ORDER BY l.realname, format",
             &[],
         )
         .await?;
 
     let exports = rows
         .into_iter()
-        .map(|row| CachedExport {
-            language_tag: row.get("language_tag"),
-            source_language_tag: row.get("source_language_tag"),
-            language_realname: row.get("language_realname"),
-            format: row.get("format"),
-            positive_scores_only: row.get("positive_scores_only"),
-            filename: row.get("filename"),
-            created_at: row.get("created_at"),
+        .map(|row| {
+            let language_tag: String = row.get("language_tag");
+            let source_language_tag: String = row.get("source_language_tag");
+            let format: String = row.get("format");
+            let filename = row.get::<_, Option<String>>("filename").unwrap_or_else(|| {
+                let extension = ExportFormat::from_query(Some(&format))
+                    .map(|f| f.file_extension().to_string())
+                    .unwrap_or_else(|_| format.clone());
+                build_export_filename(None, &source_language_tag, &language_tag, &extension)
+            });
+            CachedExport {
+                language_tag,
+                source_language_tag,
+                language_realname: row.get("language_realname"),
+                format,
+                positive_scores_only: row.get("positive_scores_only"),
+                filename,
+                created_at: row.get("created_at"),
+                status: row
+                    .get::<_, Option<String>>("status")
+                    .unwrap_or_else(|| "ready".to_string()),
+                queue_position: row.get("queue_position"),
+                started_at: row.get("started_at"),
+                error: row.get("error"),
+            }
         })
         .collect();
 
@@ -2016,7 +2176,7 @@ pub async fn get_cached_export(
            AND source_language_tag = $2
            AND format = $3
            AND positive_scores_only = $4
-           AND created_at > NOW() - INTERVAL '4 days'",
+           AND checked_at > NOW() - INTERVAL '4 days'",
             &[
                 &language_tag,
                 &source_language_tag,
@@ -2039,6 +2199,21 @@ pub async fn get_cached_export(
     result
 }
 
+/// Formats the nightly run keeps cached for every language.
+const NIGHTLY_FORMATS: [ExportFormat; 8] = [
+    ExportFormat::Pdf,
+    ExportFormat::LaTeX,
+    ExportFormat::Xml,
+    ExportFormat::Json,
+    ExportFormat::Tsv,
+    ExportFormat::StarDict,
+    ExportFormat::Dictd,
+    ExportFormat::Kindle,
+];
+
+/// Queues a rebuild of each canonical export (Lojban source, positive scores only) whose language
+/// changed since it was cached. Unchanged exports only have their TTL renewed. The builds
+/// themselves run in [`crate::export::jobs::run_export_worker`].
 pub async fn export_all_dictionaries(pool: &Pool) -> Result<(), Box<dyn Error + Send + Sync>> {
     let client = pool.get().await?;
 
@@ -2049,116 +2224,90 @@ pub async fn export_all_dictionaries(pool: &Pool) -> Result<(), Box<dyn Error +
         .map(|row| row.get::<_, String>("tag"))
         .collect::<Vec<_>>();
 
-    // Check existing cached exports (do not hold a transaction across long-running generates).
-    // The background job only pre-warms the canonical Lojban-source, positive-scores-only
-    // variant; other variants are generated on demand via the interactive endpoint.
-    let cached_exports_rows = client
-        .query(
-            "SELECT language_tag, source_language_tag, format, positive_scores_only, MAX(created_at) as last_export
-             FROM cached_dictionary_exports
-             GROUP BY language_tag, source_language_tag, format, positive_scores_only",
-            &[],
-        )
-        .await?;
-
-    let mut cached_exports = std::collections::HashMap::new();
-    for row in cached_exports_rows {
-        let lang_tag: String = row.get("language_tag");
-        let source_tag: String = row.get("source_language_tag");
-        let format: String = row.get("format");
-        let positive_only: bool = row.get("positive_scores_only");
-        let last_export: DateTime<Utc> = row.get("last_export");
-        cached_exports.insert((lang_tag, source_tag, format, positive_only), last_export);
-    }
-
-    let canonical_options = ExportOptions {
-        format: None,
-        positive_scores_only: Some(true),
-        collection_id: None,
-        source_lang: Some(DEFAULT_SOURCE_LANGUAGE_TAG.to_string()),
-    };
-
+    let mut queued = 0;
     for lang in languages {
-        for format in &[
-            ExportFormat::Pdf,
-            ExportFormat::LaTeX,
-            ExportFormat::Xml,
-            ExportFormat::Json,
-            ExportFormat::Tsv,
-        ] {
-            let format_str = format.to_string();
-            let cache_key = (
-                lang.clone(),
-                DEFAULT_SOURCE_LANGUAGE_TAG.to_string(),
-                format_str.clone(),
-                true,
-            );
-            if let Some(last_export_time) = cached_exports.get(&cache_key) {
-                let duration_since_last =
-                    chrono::Utc::now().signed_duration_since(*last_export_time);
-                if duration_since_last < chrono::Duration::days(1) {
-                    info!(
-                        "Skipping {} {} export - last cached at {}",
-                        lang, format, last_export_time
-                    );
-                    continue;
-                }
-            }
-
-            info!(
-                "Exporting dictionary for language {} in format {}",
-                lang, format
-            );
-
-            // Must bypass DB cache read or we would keep re-storing stale blobs (< 4 days old).
-            match export_dictionary(
-                pool,
-                &lang,
-                *format,
-                &canonical_options,
-                None,
-                DEFAULT_SOURCE_LANGID,
-                DEFAULT_SOURCE_LANGUAGE_TAG,
-                false,
+        let fingerprint =
+            jobs::language_fingerprint(&client, &lang, DEFAULT_SOURCE_LANGUAGE_TAG).await?;
+
+        // Renew every export built from this exact data; whatever is left needs a build.
+        let current: Vec<String> = client
+            .query(
+                "UPDATE cached_dictionary_exports
+                 SET checked_at = CURRENT_TIMESTAMP
+                 WHERE language_tag = $1
+                   AND source_language_tag = $2
+                   AND positive_scores_only = true
+                   AND fingerprint = $3
+                 RETURNING format",
+                &[&lang, &DEFAULT_SOURCE_LANGUAGE_TAG, &fingerprint],
             )
-            .await
-            {
-                Ok((content, content_type, filename)) => {
-                    let c = pool.get().await?;
-                    if let Err(e) = c
-                        .execute(
-                            "INSERT INTO cached_dictionary_exports
-                             (language_tag, source_language_tag, format, positive_scores_only, content, content_type, filename)
-                             VALUES ($1, $2, $3, $4, $5, $6, $7)
-                             ON CONFLICT (language_tag, source_language_tag, format, positive_scores_only)
-                             DO UPDATE SET
-                                content = EXCLUDED.content,
-                                content_type = EXCLUDED.content_type,
-                                filename = EXCLUDED.filename,
-                                created_at = CURRENT_TIMESTAMP",
-                            &[
-                                &lang,
-                                &DEFAULT_SOURCE_LANGUAGE_TAG,
-                                &format.to_string(),
-                                &true,
-                                &content,
-                                &content_type,
-                                &filename,
-                            ],
-                        )
-                        .await
-                    {
-                        error!("Failed to cache export for {}: {}", lang, e);
-                    }
-                }
-                Err(e) => error!("Failed to export {} dictionary to {}: {}", lang, format, e),
+            .await?
+            .iter()
+            .map(|row| row.get("format"))
+            .collect();
+
+        for format in NIGHTLY_FORMATS {
+            if current.contains(&format.to_string()) {
+                debug!(
+                    "Skipping {} {} export - unchanged since last build",
+                    lang, format
+                );
+                continue;
+            }
+            let key = CachedExportKey {
+                language_tag: &lang,
+                source_language_tag: DEFAULT_SOURCE_LANGUAGE_TAG,
+                format,
+                positive_scores_only: true,
+            };
+            if jobs::enqueue(&client, &key).await? {
+                queued += 1;
             }
         }
     }
 
+    info!("Queued {} dictionary exports for rebuilding", queued);
     Ok(())
 }
 
+/// Builds one export from scratch and caches it with the fingerprint of the data it read.
+pub(crate) async fn rebuild_cached_export(
+    pool: &Pool,
+    key: &CachedExportKey<'_>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (source_langid, source_language_tag) = {
+        let mut client = pool.get().await?;
+        let mut transaction = client.transaction().await?;
+        let source =
+            resolve_source_language(&mut transaction, Some(key.source_language_tag)).await?;
+        transaction.commit().await?;
+        source
+    };
+    let fingerprint =
+        jobs::language_fingerprint(&pool.get().await?, key.language_tag, &source_language_tag)
+            .await?;
+
+    let options = ExportOptions {
+        format: None,
+        positive_scores_only: Some(key.positive_scores_only),
+        collection_id: None,
+        source_lang: Some(source_language_tag.clone()),
+    };
+    // Must bypass the cache read or we would keep re-storing the stale blob.
+    let result = export_dictionary(
+        pool,
+        key.language_tag,
+        key.format,
+        &options,
+        None,
+        source_langid,
+        &source_language_tag,
+        false,
+    )
+    .await?;
+    store_cached_export(pool, key, &result, &fingerprint).await
+}
+
 fn parse_i32_csv(value: &Option<String>) -> Option<Vec<i32>> {
     let ids: Vec<i32> = value
         .as_deref()
@@ -2500,7 +2649,7 @@ pub async fn export_search_results(
     user_id: Option<i32>,
 ) -> Result<(Vec<u8>, String, String), Box<dyn std::error::Error + Send + Sync>> {
     if query.full_collection.unwrap_or(false) {
-        return export_full_collection_json(pool, query, user_id).await;
+        return export_full_collection(pool, query, user_id).await;
     }
 
     if !super::models::has_search_export_constraint(query) {
@@ -2532,8 +2681,7 @@ pub async fn export_search_results(
     // Skip dictionary for collection-only export, or when collections are set without authors.
     let has_collections = !collection_ids.is_empty();
     let has_authors = usernames.is_some();
-    let skip_dictionary_fallback =
-        collection_only || (has_collections && !has_authors);
+    let skip_dictionary_fallback = collection_only || (has_collections && !has_authors);
     let collection_item_usernames = if collection_only {
         usernames.clone()
     } else {
@@ -2767,12 +2915,22 @@ async fn finalize_search_export(
                 .collect();
             zip_tsv_files(&refs)?
         }
+        ExportFormat::Apkg => return Err(APKG_FULL_COLLECTION_ONLY.into()),
+        ExportFormat::StarDict | ExportFormat::Dictd | ExportFormat::Kindle => {
+            return Err(OFFLINE_DICTIONARY_ONLY.into())
+        }
     };
 
     Ok((content, content_type, filename))
 }
 
-fn collection_export_filename(name: &str) -> String {
+const APKG_FULL_COLLECTION_ONLY: &str =
+    "Anki packages are only available for full collection exports (full_collection=true).";
+
+const OFFLINE_DICTIONARY_ONLY: &str =
+    "StarDict, dictd and Kindle bundles are only available for whole dictionaries (/export/dictionary/{lang}).";
+
+fn collection_export_filename(name: &str, extension: &str) -> String {
     let sanitized: String = name
         .chars()
         .map(|c| {
@@ -2788,17 +2946,17 @@ fn collection_export_filename(name: &str) -> String {
     } else {
         sanitized
     };
-    format!("{base}-export.json")
+    format!("{base}-export.{extension}")
 }
 
-async fn export_full_collection_json(
+async fn export_full_collection(
     pool: &Pool,
     query: &SearchExportQuery,
     user_id: Option<i32>,
 ) -> Result<(Vec<u8>, String, String), Box<dyn std::error::Error + Send + Sync>> {
     let format = ExportFormat::from_query(query.format.as_deref()).map_err(|e| e.to_string())?;
-    if format != ExportFormat::Json {
-        return Err("Full collection export supports JSON format only.".into());
+    if format != ExportFormat::Json && format != ExportFormat::Apkg {
+        return Err("Full collection export supports JSON and apkg formats only.".into());
     }
 
     let collection_ids = query
@@ -2810,15 +2968,22 @@ async fn export_full_collection_json(
         return Err("Full collection export requires exactly one collection id.".into());
     }
 
-    let export = crate::collections::service::export_collection_full(
-        pool,
-        collection_ids[0],
-        user_id,
-    )
-    .await
-    .map_err(|e| e.to_string())?;
+    if format == ExportFormat::Apkg {
+        // Includes the requesting user's scheduling and review history for the collection.
+        let export =
+            crate::collections::anki::export_collection_apkg(pool, collection_ids[0], user_id)
+                .await
+                .map_err(|e| e.to_string())?;
+        let filename = collection_export_filename(&export.collection_name, "apkg");
+        return Ok((export.content, format.content_type().to_string(), filename));
+    }
+
+    let export =
+        crate::collections::service::export_collection_full(pool, collection_ids[0], user_id)
+            .await
+            .map_err(|e| e.to_string())?;
 
-    let filename = collection_export_filename(&export.collection.name);
+    let filename = collection_export_filename(&export.collection.name, "json");
     let content = serde_json::to_vec_pretty(&export)?;
     Ok((
         content,
@@ -2907,6 +3072,29 @@ mod tests {
             format!("search-export.{}", ExportFormat::Tsv.file_extension()),
             "search-export.zip"
         );
+        assert_eq!(
+            ExportFormat::from_query(Some("apkg")).unwrap(),
+            ExportFormat::Apkg
+        );
+        assert_eq!(
+            ExportFormat::from_query(Some("dict")).unwrap(),
+            ExportFormat::Dictd
+        );
+        assert_eq!(
+            build_export_filename(None, "jbo", "en", ExportFormat::StarDict.file_extension()),
+            "dictionary-jbo-en.stardict.zip"
+        );
+    }
+
+    #[test]
+    fn nightly_formats_round_trip_through_job_names() {
+        // The export worker reads formats back from `dictionary_export_jobs.format`.
+        for format in NIGHTLY_FORMATS {
+            assert_eq!(
+                ExportFormat::from_query(Some(&format.to_string())).unwrap(),
+                format
+            );
+        }
     }
 }
 
@@ -8,13 +8,14 @@ use serde_json::json;
 use super::{
     dto::{
         self, AddCardsRequest, CreateFlashcardRequest, CreateLevelRequest, DirectAnswerResponse,
-        FillInAnswerRequest, FlashcardListResponse, FlashcardResponse, ImportFromCollectionRequest,
-        ImportFromCollectionResponse, LevelCardListResponse, LevelCardResponse, LevelListResponse,
-        LevelResponse, MergeProgressRequest, MergeProgressResponse, ReviewRequest, ReviewResponse,
-        StreakResponse, UpdateLevelRequest,
+        FillInAnswerRequest, FlashcardListResponse, FlashcardResponse, FsrsParametersQuery,
+        FsrsParametersResponse, ImportFromCollectionRequest, ImportFromCollectionResponse,
+        LevelCardListResponse, LevelCardResponse, LevelListResponse, LevelResponse,
+        MergeProgressRequest, MergeProgressResponse, ReviewRequest, ReviewResponse, StreakResponse,
+        UpdateLevelRequest,
     },
     models::*,
-    service,
+    optimizer, service,
 };
 use crate::{
     auth::Claims,
@@ -546,6 +547,36 @@ pub async fn get_streak(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/flashcards/fsrs/parameters",
+    tag = "flashcards",
+    params(
+        ("collection_id" = Option<i32>, Query, description = "Show the weights fitted on this collection (falls back to the user's global weights)")
+    ),
+    responses(
+        (status = 200, description = "FSRS weights and fit quality", body = FsrsParametersResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    summary = "Get personal FSRS parameters",
+    description = "Returns the FSRS weights used to schedule the user's reviews together with the log-loss of the default and the fitted weights on the user's review history. Weights are refitted by a background job."
+)]
+#[get("/fsrs/parameters")]
+pub async fn get_fsrs_parameters(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    query: web::Query<FsrsParametersQuery>,
+) -> impl Responder {
+    match optimizer::get_fsrs_parameters(&pool, claims.sub, query.collection_id).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => crate::utils::handle_error(e, "Failed to get FSRS parameters"),
+    }
+}
+
 #[utoipa::path(
     post,
     path = "flashcards/levels/{collection_id}",
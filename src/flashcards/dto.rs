@@ -183,6 +183,28 @@ pub struct StreakResponse {
     pub total_points: i32,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct FsrsParametersQuery {
+    pub collection_id: Option<i32>,
+}
+
+/// FSRS weights used to schedule the user's reviews, with the log-loss of the default
+/// weights and of the fitted weights on the user's own review history.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FsrsParametersResponse {
+    /// Set when the weights were fitted on a single collection.
+    pub collection_id: Option<i32>,
+    /// False when no fit exists yet or the fit did not beat the defaults.
+    pub personalized: bool,
+    pub parameters: Vec<f32>,
+    pub default_parameters: Vec<f32>,
+    pub log_loss_before: Option<f64>,
+    pub log_loss_after: Option<f64>,
+    pub review_count: Option<i32>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub optimized_at: Option<DateTime<Utc>>,
+}
+
 // levels:
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -1,8 +1,10 @@
 pub mod controller;
 pub mod dto;
 pub mod models;
+mod optimizer;
 mod service;
 
+pub use optimizer::run_fsrs_optimization;
 pub use service::list_flashcards_public;
 
 use actix_web::web;
@@ -24,6 +26,7 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .service(controller::update_flashcard_position)
             .service(controller::import_from_collection)
             .service(controller::get_streak)
+            .service(controller::get_fsrs_parameters)
             .service(controller::update_level)
             .service(controller::add_cards)
             .service(controller::create_level)
@@ -0,0 +1,420 @@
+//! Personal FSRS parameter fitting.
+//!
+//! Replays `flashcard_review_history` per user (and per user/collection pair once there is
+//! enough data in a single collection), fits FSRS weights with `fsrs::FSRS::compute_parameters`
+//! and stores them with the log-loss measured before (default weights) and after fitting.
+//! `review_flashcard` then schedules with [`get_scheduling_parameters`].
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{GenericClient, Pool};
+use fsrs::{ComputeParametersInput, FSRSItem, FSRSReview, DEFAULT_PARAMETERS, FSRS};
+use log::{info, warn};
+use std::collections::HashMap;
+
+use super::dto::FsrsParametersResponse;
+
+/// Minimum number of reviews before we try to fit personal weights.
+/// Below this FSRS tends to overfit and the defaults schedule better.
+const MIN_REVIEWS_FOR_OPTIMIZATION: i64 = 400;
+
+/// Refit at most once a week unless the user has doubled their history since.
+const REOPTIMIZE_AFTER_DAYS: i32 = 7;
+
+/// One row of `flashcard_review_history`, reduced to what FSRS needs.
+pub(crate) struct ReviewLogRow {
+    pub flashcard_id: i32,
+    pub card_side: String,
+    pub rating: i32,
+    pub elapsed_days: i32,
+}
+
+/// Groups review logs by card side (in review order) and expands every card history into
+/// the training items FSRS expects: one item per prefix that ends in a review with a
+/// non-zero interval, since only those carry a recall signal.
+pub(crate) fn build_training_items(logs: &[ReviewLogRow]) -> Vec<FSRSItem> {
+    let mut per_card: HashMap<(i32, &str), Vec<FSRSReview>> = HashMap::new();
+    let mut order: Vec<(i32, &str)> = Vec::new();
+    for log in logs {
+        if !(1..=4).contains(&log.rating) {
+            continue;
+        }
+        let key = (log.flashcard_id, log.card_side.as_str());
+        let reviews = per_card.entry(key).or_insert_with(|| {
+            order.push(key);
+            Vec::new()
+        });
+        reviews.push(FSRSReview {
+            rating: log.rating as u32,
+            delta_t: log.elapsed_days.max(0) as u32,
+        });
+    }
+
+    let mut items = Vec::new();
+    for key in order {
+        let Some(reviews) = per_card.get(&key) else {
+            continue;
+        };
+        for end in 2..=reviews.len() {
+            if reviews[end - 1].delta_t == 0 {
+                continue;
+            }
+            items.push(FSRSItem {
+                reviews: reviews[..end].to_vec(),
+            });
+        }
+    }
+    items
+}
+
+/// Returns the weights to schedule with: per-collection if fitted, otherwise the user's
+/// global weights, otherwise an empty slice (which `FSRS::new` treats as the defaults).
+pub async fn get_scheduling_parameters(
+    client: &impl GenericClient,
+    user_id: i32,
+    collection_id: Option<i32>,
+) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    if let Some(collection_id) = collection_id {
+        let params: Option<Vec<f32>> = client
+            .query_opt(
+                "SELECT parameters FROM user_collection_fsrs_parameters
+                 WHERE user_id = $1 AND collection_id = $2",
+                &[&user_id, &collection_id],
+            )
+            .await?
+            .and_then(|row| row.get("parameters"));
+        if let Some(params) = params {
+            return Ok(params);
+        }
+    }
+
+    let params: Option<Vec<f32>> = client
+        .query_opt(
+            "SELECT fsrs_parameters FROM user_settings WHERE user_id = $1",
+            &[&user_id],
+        )
+        .await?
+        .and_then(|row| row.get("fsrs_parameters"));
+
+    Ok(params.unwrap_or_default())
+}
+
+struct FitResult {
+    parameters: Vec<f32>,
+    log_loss_before: f32,
+    log_loss_after: f32,
+}
+
+/// Fits and evaluates weights off the async runtime; training is CPU bound and can take
+/// several seconds for large histories.
+async fn fit_parameters(items: Vec<FSRSItem>) -> Result<FitResult, Box<dyn std::error::Error>> {
+    let result = tokio::task::spawn_blocking(move || -> Result<FitResult, String> {
+        let default_model = FSRS::new(&DEFAULT_PARAMETERS).map_err(|e| e.to_string())?;
+        let before = default_model
+            .evaluate(items.clone(), |_| true)
+            .map_err(|e| e.to_string())?;
+
+        let parameters = default_model
+            .compute_parameters(ComputeParametersInput {
+                train_set: items.clone(),
+                enable_short_term: true,
+                ..Default::default()
+            })
+            .map_err(|e| e.to_string())?;
+
+        let fitted_model = FSRS::new(&parameters).map_err(|e| e.to_string())?;
+        let after = fitted_model
+            .evaluate(items, |_| true)
+            .map_err(|e| e.to_string())?;
+
+        Ok(FitResult {
+            parameters,
+            log_loss_before: before.log_loss,
+            log_loss_after: after.log_loss,
+        })
+    })
+    .await?;
+
+    result.map_err(|e| e.into())
+}
+
+async fn load_review_logs(
+    client: &impl GenericClient,
+    user_id: i32,
+    collection_id: Option<i32>,
+) -> Result<Vec<ReviewLogRow>, Box<dyn std::error::Error>> {
+    let rows = client
+        .query(
+            "SELECT h.flashcard_id, h.card_side, h.rating, h.elapsed_days
+             FROM flashcard_review_history h
+             JOIN flashcards f ON f.id = h.flashcard_id
+             WHERE h.user_id = $1
+               AND ($2::int IS NULL OR f.collection_id = $2)
+             ORDER BY h.review_time, h.id",
+            &[&user_id, &collection_id],
+        )
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ReviewLogRow {
+            flashcard_id: row.get("flashcard_id"),
+            card_side: row.get("card_side"),
+            rating: row.get("rating"),
+            elapsed_days: row.get("elapsed_days"),
+        })
+        .collect())
+}
+
+/// Fits weights for one user (or one user/collection pair) and stores them.
+/// Returns `false` when there is not enough usable history to fit anything, or when the
+/// fitted weights do not beat the defaults on the user's own reviews.
+pub async fn optimize_user_parameters(
+    pool: &Pool,
+    user_id: i32,
+    collection_id: Option<i32>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let client = pool.get().await?;
+    let logs = load_review_logs(&*client, user_id, collection_id).await?;
+    if (logs.len() as i64) < MIN_REVIEWS_FOR_OPTIMIZATION {
+        return Ok(false);
+    }
+    let review_count = logs.len() as i32;
+
+    let items = build_training_items(&logs);
+    if items.is_empty() {
+        return Ok(false);
+    }
+
+    let fit = fit_parameters(items).await?;
+    info!(
+        "FSRS fit for user {} (collection {:?}): log-loss {:.4} -> {:.4} over {} reviews",
+        user_id, collection_id, fit.log_loss_before, fit.log_loss_after, review_count
+    );
+
+    // Keep the defaults when fitting made things worse; still record the evaluation so
+    // the endpoint can explain why personal weights are not in use.
+    let parameters: Option<Vec<f32>> = if fit.log_loss_after <= fit.log_loss_before {
+        Some(fit.parameters)
+    } else {
+        None
+    };
+
+    match collection_id {
+        Some(collection_id) => {
+            client
+                .execute(
+                    "INSERT INTO user_collection_fsrs_parameters
+                     (user_id, collection_id, parameters, log_loss_before, log_loss_after,
+                      review_count, optimized_at)
+                     VALUES ($1, $2, $3, $4, $5, $6, CURRENT_TIMESTAMP)
+                     ON CONFLICT (user_id, collection_id) DO UPDATE SET
+                        parameters = $3,
+                        log_loss_before = $4,
+                        log_loss_after = $5,
+                        review_count = $6,
+                        optimized_at = CURRENT_TIMESTAMP",
+                    &[
+                        &user_id,
+                        &collection_id,
+                        &parameters,
+                        &(fit.log_loss_before as f64),
+                        &(fit.log_loss_after as f64),
+                        &review_count,
+                    ],
+                )
+                .await?;
+        }
+        None => {
+            client
+                .execute(
+                    "INSERT INTO user_settings
+                     (user_id, fsrs_parameters, fsrs_log_loss_before, fsrs_log_loss_after,
+                      fsrs_review_count, fsrs_optimized_at)
+                     VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)
+                     ON CONFLICT (user_id) DO UPDATE SET
+                        fsrs_parameters = $2,
+                        fsrs_log_loss_before = $3,
+                        fsrs_log_loss_after = $4,
+                        fsrs_review_count = $5,
+                        fsrs_optimized_at = CURRENT_TIMESTAMP",
+                    &[
+                        &user_id,
+                        &parameters,
+                        &(fit.log_loss_before as f64),
+                        &(fit.log_loss_after as f64),
+                        &review_count,
+                    ],
+                )
+                .await?;
+        }
+    }
+
+    Ok(parameters.is_some())
+}
+
+/// Background pass: refits every user (and user/collection pair) with enough reviews whose
+/// weights are missing, older than a week, or based on less than half of the current history.
+pub async fn run_fsrs_optimization(pool: &Pool) -> Result<(), Box<dyn std::error::Error>> {
+    let client = pool.get().await?;
+
+    let users = client
+        .query(
+            "SELECT h.user_id
+             FROM flashcard_review_history h
+             LEFT JOIN user_settings s ON s.user_id = h.user_id
+             GROUP BY h.user_id, s.fsrs_optimized_at, s.fsrs_review_count
+             HAVING COUNT(*) >= $1
+                AND (s.fsrs_optimized_at IS NULL
+                     OR s.fsrs_optimized_at < CURRENT_TIMESTAMP - make_interval(days => $2)
+                     OR COUNT(*) >= 2 * COALESCE(s.fsrs_review_count, 0))",
+            &[&MIN_REVIEWS_FOR_OPTIMIZATION, &REOPTIMIZE_AFTER_DAYS],
+        )
+        .await?;
+
+    let pairs = client
+        .query(
+            "SELECT h.user_id, f.collection_id
+             FROM flashcard_review_history h
+             JOIN flashcards f ON f.id = h.flashcard_id
+             LEFT JOIN user_collection_fsrs_parameters p
+                ON p.user_id = h.user_id AND p.collection_id = f.collection_id
+             GROUP BY h.user_id, f.collection_id, p.optimized_at, p.review_count
+             HAVING COUNT(*) >= $1
+                AND (p.optimized_at IS NULL
+                     OR p.optimized_at < CURRENT_TIMESTAMP - make_interval(days => $2)
+                     OR COUNT(*) >= 2 * COALESCE(p.review_count, 0))",
+            &[&MIN_REVIEWS_FOR_OPTIMIZATION, &REOPTIMIZE_AFTER_DAYS],
+        )
+        .await?;
+    drop(client);
+
+    for row in users {
+        let user_id: i32 = row.get("user_id");
+        if let Err(e) = optimize_user_parameters(pool, user_id, None).await {
+            warn!("FSRS optimization failed for user {}: {}", user_id, e);
+        }
+    }
+
+    for row in pairs {
+        let user_id: i32 = row.get("user_id");
+        let collection_id: i32 = row.get("collection_id");
+        if let Err(e) = optimize_user_parameters(pool, user_id, Some(collection_id)).await {
+            warn!(
+                "FSRS optimization failed for user {} collection {}: {}",
+                user_id, collection_id, e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Fitted weights and their evaluation for the endpoint. Falls back to the user's global
+/// fit when no per-collection fit exists.
+pub async fn get_fsrs_parameters(
+    pool: &Pool,
+    user_id: i32,
+    collection_id: Option<i32>,
+) -> Result<FsrsParametersResponse, Box<dyn std::error::Error>> {
+    let client = pool.get().await?;
+
+    if let Some(collection_id) = collection_id {
+        if let Some(row) = client
+            .query_opt(
+                "SELECT parameters, log_loss_before, log_loss_after, review_count, optimized_at
+                 FROM user_collection_fsrs_parameters
+                 WHERE user_id = $1 AND collection_id = $2",
+                &[&user_id, &collection_id],
+            )
+            .await?
+        {
+            let parameters: Option<Vec<f32>> = row.get("parameters");
+            return Ok(FsrsParametersResponse {
+                collection_id: Some(collection_id),
+                personalized: parameters.is_some(),
+                parameters: parameters.unwrap_or_else(|| DEFAULT_PARAMETERS.to_vec()),
+                default_parameters: DEFAULT_PARAMETERS.to_vec(),
+                log_loss_before: row.get("log_loss_before"),
+                log_loss_after: row.get("log_loss_after"),
+                review_count: row.get("review_count"),
+                optimized_at: row.get("optimized_at"),
+            });
+        }
+    }
+
+    let row = client
+        .query_opt(
+            "SELECT fsrs_parameters, fsrs_log_loss_before, fsrs_log_loss_after,
+                    fsrs_review_count, fsrs_optimized_at
+             FROM user_settings WHERE user_id = $1",
+            &[&user_id],
+        )
+        .await?;
+
+    let (parameters, log_loss_before, log_loss_after, review_count, optimized_at) = match row {
+        Some(row) => (
+            row.get::<_, Option<Vec<f32>>>("fsrs_parameters"),
+            row.get::<_, Option<f64>>("fsrs_log_loss_before"),
+            row.get::<_, Option<f64>>("fsrs_log_loss_after"),
+            row.get::<_, Option<i32>>("fsrs_review_count"),
+            row.get::<_, Option<DateTime<Utc>>>("fsrs_optimized_at"),
+        ),
+        None => (None, None, None, None, None),
+    };
+
+    Ok(FsrsParametersResponse {
+        collection_id: None,
+        personalized: parameters.is_some(),
+        parameters: parameters.unwrap_or_else(|| DEFAULT_PARAMETERS.to_vec()),
+        default_parameters: DEFAULT_PARAMETERS.to_vec(),
+        log_loss_before,
+        log_loss_after,
+        review_count,
+        optimized_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_training_items, ReviewLogRow};
+
+    fn log(flashcard_id: i32, side: &str, rating: i32, elapsed_days: i32) -> ReviewLogRow {
+        ReviewLogRow {
+            flashcard_id,
+            card_side: side.to_string(),
+            rating,
+            elapsed_days,
+        }
+    }
+
+    #[test]
+    fn training_items_are_prefixes_ending_in_a_spaced_review() {
+        let logs = vec![
+            log(1, "direct", 3, 0),
+            log(2, "direct", 1, 0),
+            log(1, "direct", 3, 2),
+            log(1, "direct", 1, 0),
+            log(1, "direct", 4, 5),
+        ];
+        let items = build_training_items(&logs);
+        // Card 1 yields prefixes of length 2 and 4 (the same-day relearn step is skipped);
+        // card 2 has a single review and yields nothing.
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].reviews.len(), 2);
+        assert_eq!(items[1].reviews.len(), 4);
+        assert_eq!(items[1].reviews[3].delta_t, 5);
+    }
+
+    #[test]
+    fn sides_are_separate_histories_and_bad_ratings_are_ignored() {
+        let logs = vec![
+            log(1, "direct", 3, 0),
+            log(1, "reverse", 3, 0),
+            log(1, "direct", 0, 1),
+            log(1, "reverse", 2, 3),
+        ];
+        let items = build_training_items(&logs);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].reviews[1].rating, 2);
+    }
+}
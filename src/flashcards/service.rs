@@ -11,8 +11,9 @@ use log::{debug, error, info};
 use std::collections::HashMap;
 
 use crate::auth_utils::{
-    verify_collection_ownership, verify_collection_read_access, verify_flashcard_ownership,
+    verify_collection_edit_access, verify_collection_read_access, verify_flashcard_edit_access,
 };
+use crate::collections::models::CollectionRole;
 
 use super::{
     dto::{
@@ -24,6 +25,7 @@ use super::{
         PrerequisiteLevel, ReviewRequest, ReviewResponse, StreakResponse, UpdateLevelRequest,
     },
     models::*,
+    optimizer::get_scheduling_parameters,
 };
 
 async fn get_flashcard(
@@ -91,7 +93,7 @@ pub async fn create_flashcard(
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Get or create collection item
     let item_id: i32 = match transaction
@@ -513,7 +515,7 @@ pub async fn delete_flashcard(
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    verify_flashcard_ownership(&transaction, flashcard_id, user_id).await?;
+    verify_flashcard_edit_access(&transaction, flashcard_id, user_id).await?;
 
     // Delete dependent records first
     transaction
@@ -554,7 +556,7 @@ pub async fn generate_and_set_quiz_options(
         )
         .await?;
     let collection_id: i32 = collection_id_row.get("collection_id");
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Fetch flashcard and related item details
     let flashcard_details_row = transaction
@@ -712,7 +714,7 @@ pub async fn list_flashcards_public(
              JOIN collection_items ci ON f.item_id = ci.item_id AND ci.collection_id = f.collection_id
              LEFT JOIN definitions d ON ci.definition_id = d.definitionid
              LEFT JOIN valsi v ON d.valsiid = v.valsiid
-             WHERE f.collection_id = $1
+             WHERE f.collection_id = $1 AND d.deleted_at IS NULL
              ORDER BY f.position",
             &[&collection_id],
         )
@@ -803,7 +805,9 @@ pub async fn list_flashcards(
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    let fsrs = FSRS::new(&[])?;
+    let parameters =
+        get_scheduling_parameters(&transaction, user_id, Some(query.collection_id)).await?;
+    let fsrs = FSRS::new(&parameters)?;
 
     // Get base flashcards including free content
     let rows = transaction
@@ -840,6 +844,7 @@ pub async fn list_flashcards(
             ON f.id = rh.flashcard_id
             AND p.card_side = rh.card_side
         WHERE f.collection_id = $2
+        AND d.deleted_at IS NULL
         AND ($3::flashcard_status IS NULL OR p.status = $3)
         AND ($4::boolean IS NULL
              OR ($4::boolean = true AND
@@ -1292,6 +1297,7 @@ async fn get_quiz_options_for_listing(
                  LEFT JOIN definitions d ON ci.definition_id = d.definitionid
                  LEFT JOIN valsi v ON d.valsiid = v.valsiid
                  WHERE f.collection_id = (SELECT collection_id FROM flashcards WHERE id = $1)
+                   AND d.deleted_at IS NULL
                    AND {} IS NOT NULL
                    AND {} != $2 -- Not the correct answer
                    AND ({} = '' OR {} <> ALL($3)) -- Not one of the already selected historical distractors, handle empty string case
@@ -1406,15 +1412,20 @@ pub async fn calculate_optimal_retention(
     let day_cutoff = Utc::now().timestamp() - 86400; // 24 hours ago
     let config = extract_simulator_config(revlogs, day_cutoff, true);
 
-    // Calculate optimal retention
-    let _fsrs = FSRS::new(&[])?;
+    // Calculate optimal retention with the user's fitted weights when we have them
+    let parameters = get_scheduling_parameters(transaction, user_id, None).await?;
+    let parameters = if parameters.is_empty() {
+        DEFAULT_PARAMETERS.to_vec()
+    } else {
+        parameters
+    };
 
     // Use a progress callback that always returns true to avoid interruption
     let progress_callback = |_: ItemProgress| true;
 
     match optimal_retention(
         &config,
-        DEFAULT_PARAMETERS.as_slice(),
+        parameters.as_slice(),
         progress_callback,
         None::<Vec<Card>>,
         None,
@@ -1571,7 +1582,16 @@ pub async fn review_flashcard(
         0
     };
 
-    let fsrs = FSRS::new(&[])?;
+    let collection_id: i32 = transaction
+        .query_one(
+            "SELECT collection_id FROM flashcards WHERE id = $1",
+            &[&req.flashcard_id],
+        )
+        .await?
+        .get("collection_id");
+    // Personal weights fitted by the background optimizer; empty means FSRS defaults.
+    let parameters = get_scheduling_parameters(&transaction, user_id, Some(collection_id)).await?;
+    let fsrs = FSRS::new(&parameters)?;
     //todo: uncomment to use hardcoded desired_retention
     // let desired_retention: f32 = 0.9;
     let desired_retention = get_optimal_retention(&transaction, user_id).await?;
@@ -1659,7 +1679,16 @@ pub async fn review_flashcard(
     };
 
     // Initialize values for new cards
-    let (stability, difficulty) = if current_state.is_none() {
+    let (stability, difficulty) = if current_state.is_none() && parameters.len() >= 6 {
+        // Personal weights: s0 = w[rating - 1], d0 = w[4] - exp(w[5] * (rating - 1)) + 1
+        let rating_index = (req.rating as usize).clamp(1, 4) - 1;
+        let initial_stability = parameters[rating_index] as f64;
+        let initial_difficulty =
+            (parameters[4] as f64 - (parameters[5] as f64 * (req.rating as f64 - 1.0)).exp() + 1.0)
+                .clamp(1.0, 10.0);
+
+        (initial_stability, initial_difficulty)
+    } else if current_state.is_none() {
         // Get initial s0 stability based on first rating
         let initial_stability = match req.rating {
             1 => 0.4,  // DEFAULT_PARAMETERS[0]
@@ -1800,21 +1829,21 @@ async fn verify_study_access(
     flashcard_id: i32,
     user_id: i32,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // 1. Check collection ownership/public status
+    // 1. Check collection membership/public status
     let row = transaction
         .query_one(
-            "SELECT c.user_id, c.is_public
+            "SELECT c.is_public, collection_role(c.collection_id, $2) AS role
              FROM flashcards f
              JOIN collections c ON f.collection_id = c.collection_id
-             WHERE f.id = $1",
-            &[&flashcard_id],
+             WHERE f.id = $1 AND c.deleted_at IS NULL",
+            &[&flashcard_id, &user_id],
         )
         .await?;
 
-    let owner_id: i32 = row.get("user_id");
     let is_public: bool = row.get("is_public");
+    let role: Option<CollectionRole> = row.get("role");
 
-    if !is_public && owner_id != user_id {
+    if !is_public && role.is_none() {
         return Err("access denied".into());
     }
 
@@ -1853,7 +1882,7 @@ pub async fn update_flashcard_position(
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    verify_flashcard_ownership(&transaction, flashcard_id, user_id).await?;
+    verify_flashcard_edit_access(&transaction, flashcard_id, user_id).await?;
 
     // Get collection_id and current position
     let flashcard = transaction
@@ -1865,19 +1894,6 @@ pub async fn update_flashcard_position(
 
     let collection_id = flashcard.get::<_, i32>("collection_id");
 
-    // Verify collection ownership
-    let owner_id: i32 = transaction
-        .query_one(
-            "SELECT user_id FROM collections WHERE collection_id = $1",
-            &[&collection_id],
-        )
-        .await?
-        .get("user_id");
-
-    if owner_id != user_id {
-        return Err("access denied".into());
-    }
-
     // First update all positions to temporary negative values to avoid unique constraint violations
     transaction
         .execute(
@@ -1952,13 +1968,14 @@ pub async fn import_from_collection(
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Check if collection exists and user has access to it
     let _collection = transaction
         .query_opt(
             "SELECT collection_id FROM collections
-             WHERE collection_id = $1 AND (user_id = $2 OR is_public = true)",
+             WHERE collection_id = $1
+             AND (is_public = true OR collection_role(collection_id, $2) IS NOT NULL)",
             &[&collection_id, &user_id],
         )
         .await?
@@ -2481,7 +2498,15 @@ async fn record_review(
         0
     };
 
-    let fsrs = FSRS::new(&[])?;
+    let collection_id: i32 = transaction
+        .query_one(
+            "SELECT collection_id FROM flashcards WHERE id = $1",
+            &[&req.flashcard_id],
+        )
+        .await?
+        .get("collection_id");
+    let parameters = get_scheduling_parameters(transaction, user_id, Some(collection_id)).await?;
+    let fsrs = FSRS::new(&parameters)?;
     let desired_retention = 0.9;
 
     let next_states = match fsrs.next_states(current_state, desired_retention, elapsed_days) {
@@ -2589,7 +2614,11 @@ pub async fn get_next_quiz_for_user(
         SELECT f.id, f.direction
         FROM flashcards f
         JOIN user_flashcard_progress p ON f.id = p.flashcard_id
+        JOIN collections c ON c.collection_id = f.collection_id AND c.deleted_at IS NULL
+        JOIN collection_items ci ON ci.item_id = f.item_id
+        LEFT JOIN definitions d ON d.definitionid = ci.definition_id
         WHERE p.user_id = $1
+        AND d.deleted_at IS NULL
         AND p.next_review_at <= CURRENT_TIMESTAMP
         AND p.card_side = 'direct'  -- Quiz progress tracked on direct side
         AND f.direction IN ('quiz_direct', 'quiz_reverse', 'quiz_both', 'quiz_image_direct', 'quiz_image_reverse', 'quiz_image_both')
@@ -2835,7 +2864,7 @@ pub async fn create_level(
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Get max position if not specified
     let position = match req.position {
@@ -2891,7 +2920,7 @@ pub async fn update_level(
     let transaction = client.transaction().await?;
 
     let collection_id = get_collection_id(&transaction, level_id).await?;
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Update level
     let update_result = transaction
@@ -2954,7 +2983,7 @@ pub async fn add_cards_to_level(
     let transaction = client.transaction().await?;
 
     let collection_id = get_collection_id(&transaction, level_id).await?;
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Get max position first
     let max_position = transaction
@@ -3215,7 +3244,7 @@ async fn get_level_cards(
          LEFT JOIN flashcard_review_history frh
              ON frh.flashcard_id = f.id
              AND frh.user_id = $2
-         WHERE fli.level_id = $1
+         WHERE fli.level_id = $1 AND d.deleted_at IS NULL
          GROUP BY f.id, f.collection_id, ci.item_id, fli.position, v.word, d.definition, v.valsiid, d.definitionid,
                   ci.free_content_front, ci.free_content_back, ci.canonical_form
          ORDER BY fli.position",
@@ -3286,7 +3315,7 @@ pub async fn delete_level(
     let transaction = client.transaction().await?;
 
     let collection_id = get_collection_id(&transaction, level_id).await?;
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     // Check if this level is a prerequisite for any other level
     let dependent_count: i64 = transaction
@@ -3356,22 +3385,21 @@ pub async fn get_level_cards_paginated(
     let client = pool.get().await?;
     let offset = (page - 1) * per_page;
 
-    // Enforce collection read access: anonymous only public, logged-in: public or owner
+    // Enforce collection read access: anonymous only public, logged-in: public, owner or member
     let collection_row = client
         .query_one(
-            "SELECT c.collection_id, c.user_id as owner_id, c.is_public
+            "SELECT c.collection_id, c.is_public,
+                    collection_role(c.collection_id, $2) AS role
              FROM flashcard_levels l
              JOIN collections c ON c.collection_id = l.collection_id
              WHERE l.level_id = $1",
-            &[&level_id],
+            &[&level_id, &user_id],
         )
         .await?;
-    let owner_id: i32 = collection_row.get("owner_id");
     let is_public: bool = collection_row.get("is_public");
-    match user_id {
-        None if !is_public => return Err("Access denied".into()),
-        Some(uid) if !is_public && owner_id != uid => return Err("Access denied".into()),
-        _ => {}
+    let role: Option<CollectionRole> = collection_row.get("role");
+    if !is_public && role.is_none() {
+        return Err("Access denied".into());
     }
 
     // Check if level is unlocked for authenticated users
@@ -3416,7 +3444,7 @@ pub async fn get_level_cards_paginated(
             LEFT JOIN flashcard_review_history frh
                 ON frh.flashcard_id = f.id
                 AND frh.user_id = $1
-            WHERE fli.level_id = $2
+            WHERE fli.level_id = $2 AND d.deleted_at IS NULL
             GROUP BY f.id, f.collection_id, ci.item_id, fli.position, v.word, d.definition, v.valsiid, d.definitionid,
                     ci.free_content_front, ci.free_content_back, ci.canonical_form
             ORDER BY fli.position
@@ -3570,7 +3598,7 @@ pub async fn remove_card_from_level(
     let transaction = client.transaction().await?;
 
     let collection_id = get_collection_id(&transaction, level_id).await?;
-    verify_collection_ownership(&transaction, collection_id, user_id).await?;
+    verify_collection_edit_access(&transaction, collection_id, user_id).await?;
 
     let result = transaction
         .execute(
@@ -1,13 +1,19 @@
 use actix_web::http::header::{ContentDisposition, ContentType, DispositionType};
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder, ResponseError};
 use actix_web_grants::protect;
 use chrono::Utc;
 use deadpool_postgres::Pool;
 use serde_json::json;
 
-use super::dto::ClientIdGroup;
+use super::dto::{BulkRevertReport, ChangesFeedQuery, ClientIdGroup};
+use super::feed::{self, FeedFormat, FeedInfo};
+use super::import;
 use super::{BulkImportRequest, SearchDefinitionsQuery, SemanticGraphQuery, UserVoteResponse};
+use crate::audit::AuditContext;
+use crate::auth::permissions::PermissionCache;
+use crate::auth::two_factor;
 use crate::auth::Claims;
+use crate::error::AppError;
 // Removed unused Permission import
 use crate::jbovlaste::broadcast::Broadcaster;
 use crate::jbovlaste::dto::{ListDefinitionsQuery, NonLojbanDefinitionsQuery};
@@ -17,10 +23,10 @@ use crate::jbovlaste::{
     BulkVoteResponse, DefinitionDetail, DefinitionListResponse, DefinitionTranslation,
     ExportPairsQuery, GetImageDefinitionQuery, ImageUploadRequest, LinkDefinitionsRequest,
     RafsiOverlapHit, RafsiOverlapQuery, RafsiOverlapResponse, RecentChangesQuery,
-    RecentChangesResponse, RenameWikiRequest, RenameWikiResponse, SearchDefinitionsParams,
-    SemanticGraphParams, SemanticGraphResponse, UpdateDefinitionRequest, UpdateDefinitionResponse,
-    ValsiDefinitionsQuery, ValsiDetail, ValsiTypeListResponse, VoteRequest, VoteResponse,
-    WikiByDefinitionResponse,
+    RecentChangesResponse, RecentChangesScope, RenameWikiRequest, RenameWikiResponse,
+    SearchDefinitionsParams, SemanticGraphParams, SemanticGraphResponse, UpdateDefinitionRequest,
+    UpdateDefinitionResponse, ValsiDefinitionsQuery, ValsiDetail, ValsiTypeListResponse,
+    VoteRequest, VoteResponse, WikiByDefinitionResponse,
 };
 use crate::language::{validate_mathjax_fields, MathJaxValidationOptions};
 use crate::middleware::cache::{
@@ -1134,7 +1140,7 @@ pub async fn update_definition(
                     error: Some(msg),
                     warning: None,
                 })
-            } else if msg.contains("Invalid target language") {
+            } else if msg.contains("Invalid target language") || msg.contains("in the trash") {
                 HttpResponse::BadRequest().json(UpdateDefinitionResponse {
                     success: false,
                     error: Some(msg),
@@ -1285,7 +1291,17 @@ pub async fn get_recent_changes(
     let home = query.home.unwrap_or(false);
     let user_id = claims.map(|c| c.sub);
 
-    match service::get_recent_changes(&pool, limit, types, after, home, &redis_cache, user_id).await
+    match service::get_recent_changes(
+        &pool,
+        limit,
+        types,
+        after,
+        home,
+        &RecentChangesScope::All,
+        &redis_cache,
+        user_id,
+    )
+    .await
     {
         Ok(response) => HttpResponse::Ok().json(response),
         Err(e) => {
@@ -1299,6 +1315,187 @@ pub async fn get_recent_changes(
     }
 }
 
+/// Renders one page of `scope` changes as a feed. Feed and entry links point at the frontend
+/// (`FRONTEND_URL`), which serves the API under `/api`.
+#[allow(clippy::too_many_arguments)]
+async fn changes_feed_response(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    req: &HttpRequest,
+    format: &str,
+    query: &ChangesFeedQuery,
+    scope: &RecentChangesScope,
+    title: &str,
+    html_path: &str,
+) -> HttpResponse {
+    let Some(format) = FeedFormat::from_extension(format) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Unknown feed format, expected atom, rss or json"
+        }));
+    };
+
+    let response = match service::get_recent_changes(
+        pool,
+        query.limit,
+        query.types.clone(),
+        query.after.clone(),
+        false,
+        scope,
+        redis_cache,
+        None,
+    )
+    .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            let detail = error_chain(&*e);
+            log::error!("Error retrieving changes for feed: {}", detail);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Error retrieving changes",
+                "detail": detail
+            }));
+        }
+    };
+
+    let site_url = std::env::var("FRONTEND_URL").unwrap_or_else(|_| "https://example.com".into());
+    let feed_url = format!("{}/api{}", site_url, req.path());
+    let self_url = match req.query_string() {
+        "" => feed_url.clone(),
+        query_string => format!("{}?{}", feed_url, query_string),
+    };
+    let next_url = response.next_cursor.as_ref().map(|cursor| {
+        let mut next_url = format!("{}?after={}", feed_url, urlencoding::encode(cursor));
+        if let Some(types) = &query.types {
+            next_url.push_str(&format!("&types={}", urlencoding::encode(types)));
+        }
+        if let Some(limit) = query.limit {
+            next_url.push_str(&format!("&limit={}", limit));
+        }
+        next_url
+    });
+    let html_url = format!("{}{}", site_url, html_path);
+    let info = FeedInfo {
+        title,
+        site_url: &site_url,
+        html_url: &html_url,
+        self_url: &self_url,
+        next_url: next_url.as_deref(),
+    };
+
+    match feed::render_feed(format, &info, &response.changes) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(format.content_type())
+            .body(body),
+        Err(e) => {
+            log::error!("Error rendering changes feed: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Error rendering feed"
+            }))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/jbovlaste/changes.{format}",
+    tag = "jbovlaste",
+    summary = "Recent changes feed",
+    description = "Recent changes as an Atom (`atom`), RSS 2.0 (`rss`) or ActivityStreams \
+                  (`json`) feed, with the same `types` filter as `/jbovlaste/changes`. \
+                  Entry ids are derived from the change cursors and stay the same across \
+                  requests; `after` pages back through older changes.",
+    params(
+        ("format" = String, Path, description = "Feed format: atom, rss or json"),
+        ("limit" = Option<i64>, Query, description = "Number of entries (default 20)"),
+        ("types" = Option<String>, Query, description = "Comma-separated types: comment,definition,valsi,message"),
+        ("after" = Option<String>, Query, description = "Cursor of the page to start after")
+    ),
+    responses(
+        (status = 200, description = "Feed document", content_type = "application/atom+xml"),
+        (status = 404, description = "Unknown feed format"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/changes.{format}")]
+pub async fn get_recent_changes_feed(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    req: HttpRequest,
+    format: web::Path<String>,
+    query: web::Query<ChangesFeedQuery>,
+) -> impl Responder {
+    changes_feed_response(
+        &pool,
+        &redis_cache,
+        &req,
+        &format,
+        &query,
+        &RecentChangesScope::All,
+        "Recent changes",
+        "/recent",
+    )
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/jbovlaste/feeds/{kind}/{key}.{format}",
+    tag = "jbovlaste",
+    summary = "Per-user, per-valsi or per-collection changes feed",
+    description = "Changes made by one user (`user`, key = username), to one valsi and its \
+                  definitions (`valsi`, key = word) or to the definitions in one collection \
+                  (`collection`, key = collection id) as an Atom, RSS or ActivityStreams feed. \
+                  Private collections only have a feed for their owner.",
+    params(
+        ("kind" = String, Path, description = "user, valsi or collection"),
+        ("key" = String, Path, description = "Username, word or collection id"),
+        ("format" = String, Path, description = "Feed format: atom, rss or json"),
+        ("limit" = Option<i64>, Query, description = "Number of entries (default 20)"),
+        ("types" = Option<String>, Query, description = "Comma-separated types: comment,definition,valsi"),
+        ("after" = Option<String>, Query, description = "Cursor of the page to start after")
+    ),
+    responses(
+        (status = 200, description = "Feed document", content_type = "application/atom+xml"),
+        (status = 404, description = "No such user, valsi or collection, or unknown feed format"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/feeds/{kind}/{key}.{format}")]
+pub async fn get_scoped_changes_feed(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+    query: web::Query<ChangesFeedQuery>,
+    claims: Option<Claims>,
+) -> impl Responder {
+    let (kind, key, format) = path.into_inner();
+    match service::resolve_feed_scope(&pool, &kind, &key, claims.map(|c| c.sub)).await {
+        Ok(Some(scoped)) => {
+            changes_feed_response(
+                &pool,
+                &redis_cache,
+                &req,
+                &format,
+                &query,
+                &scoped.scope,
+                &scoped.title,
+                &scoped.html_path,
+            )
+            .await
+        }
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "error": "Feed not found"
+        })),
+        Err(e) => {
+            log::error!("Error resolving {} feed {}: {}", kind, key, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Error retrieving changes"
+            }))
+        }
+    }
+}
+
 /// Build a full error description from an error and its source chain (for debugging).
 fn error_chain(e: &(dyn std::error::Error + 'static)) -> String {
     let mut s = e.to_string();
@@ -1402,9 +1599,15 @@ pub async fn get_bulk_votes(
 #[protect("bulk_import")]
 pub async fn delete_bulk_definitions(
     pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    audit: AuditContext,
     client_id: web::Path<String>,
 ) -> impl Responder {
-    match service::delete_bulk_definitions(&pool, &client_id.into_inner()).await {
+    if let Err(e) = two_factor::require_step_up(&pool, &perm_cache, &claims).await {
+        return e.error_response();
+    }
+    match service::delete_bulk_definitions(&pool, &client_id.into_inner(), &audit).await {
         Ok((deleted, skipped)) => HttpResponse::Ok().json(json!({
             "deleted": deleted,
             "skipped": skipped
@@ -1415,6 +1618,39 @@ pub async fn delete_bulk_definitions(
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/jbovlaste/bulk-import/revert/{client_id}",
+    tag = "jbovlaste",
+    params(
+        ("client_id" = String, Path, description = "Client ID of the bulk import")
+    ),
+    responses(
+        (status = 200, description = "Revert report", body = BulkRevertReport),
+        (status = 403, description = "Forbidden"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = ["ADMIN"])
+    ),
+    summary = "Revert a bulk import",
+    description = "Deletes the definitions a bulk import created and restores the ones it updated to their pre-import version. Definitions that were commented on, voted on by others or edited since the import are left alone and listed in `not_reverted` with the reason."
+)]
+#[post("/bulk-import/revert/{client_id}")]
+#[protect("bulk_import")]
+pub async fn revert_bulk_import(
+    pool: web::Data<Pool>,
+    audit: AuditContext,
+    client_id: web::Path<String>,
+) -> impl Responder {
+    match service::revert_bulk_import(&pool, &client_id.into_inner(), &audit).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to revert import: {}", e)
+        })),
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/jbovlaste/bulk-import/active",
@@ -1443,15 +1679,15 @@ pub async fn list_active_imports(broadcaster: web::Data<Broadcaster>) -> impl Re
     request_body = BulkImportRequest,
     responses(
         (status = 200, description = "SSE stream of import progress", content_type = "text/event-stream"),
-        (status = 400, description = "Invalid CSV format"),
+        (status = 400, description = "Invalid import file"),
         (status = 403, description = "Admin access required"),
         (status = 500, description = "Internal server error")
     ),
     security(
         ("bearer_auth" = ["ADMIN"])
     ),
-    summary = "Bulk import gismu definitions with progress updates",
-    description = "Admin endpoint for bulk importing gismu definitions from CSV with real-time progress updates via SSE. CSV format: gismu,definition,notes,glosswords"
+    summary = "Bulk import definitions with progress updates",
+    description = "Admin endpoint for bulk importing definitions with real-time progress updates via SSE. `format` selects the file format: `csv` (word,definition,notes,glosswords), `tsv` with a header row (word, definition, notes, etymology, selmaho, jargon, rafsi, glossword_N[_meaning], placekeyword_N[_meaning]), or the `json` and `xml` dictionary exports, so an export from one instance can be imported into another. `source_langid` sets the language of the words (Lojban by default). Each row creates a definition, updates the importing user's own definition of the word, or is skipped as a duplicate of an identical definition. With `dry_run` every row is reported with the action it would take and nothing is written."
 )]
 #[post("/bulk-import")]
 #[protect("bulk_import")]
@@ -1462,7 +1698,11 @@ pub async fn bulk_import_definitions(
     redis_cache: web::Data<RedisCache>,
     broadcaster: web::Data<Broadcaster>,
     request: web::Json<BulkImportRequest>,
-) -> impl Responder {
+) -> Result<impl Responder, AppError> {
+    // An unreadable file is rejected before any stream is opened
+    let rows = import::parse_rows(request.format, &request.csv)
+        .map_err(|e| AppError::BadRequest(format!("Invalid import file: {}", e)))?;
+
     // Get client_id, SSE stream, and cancellation receiver from the broadcaster
     let (client_id, sse, cancel_rx) = broadcaster.new_client().await;
     let client_id_clone = client_id.clone(); // Clone client_id for the spawned task
@@ -1488,10 +1728,12 @@ pub async fn bulk_import_definitions(
     // Spawn the import task
     actix_web::rt::spawn(async move {
         let params = BulkImportParams {
-            csv_data: &request.csv,
+            rows,
             lang_id: request.lang_id,
+            source_langid: request.source_langid,
             client_id: client_id_clone.clone(), // Use the cloned client_id
             import_time: Utc::now(),
+            dry_run: request.dry_run,
         };
 
         let result = service::bulk_import_definitions(
@@ -1507,16 +1749,31 @@ pub async fn bulk_import_definitions(
 
         // Send final status based on the result from the service
         match result {
-            Ok((success_count, error_count)) => {
+            Ok(summary) => {
+                let success_count = summary.succeeded();
+                let error_count = summary.failed;
                 let total_processed = success_count + error_count; // Total attempted/processed
+                let message = if summary.dry_run {
+                    format!(
+                        "Dry run finished. Would create: {}, update: {}, skip as duplicate: {}, fail: {}",
+                        summary.created, summary.updated, summary.duplicates, summary.failed
+                    )
+                } else {
+                    format!(
+                        "Import finished. Created: {}, Updated: {}, Duplicates: {}, Errors: {}",
+                        summary.created, summary.updated, summary.duplicates, summary.failed
+                    )
+                };
                 let final_payload = json!({
                     "type": "complete",
                     "success": error_count == 0, // Success if no errors
                     "client_id": &client_id_clone,
+                    "dry_run": summary.dry_run,
                     "success_count": success_count,
                     "error_count": error_count,
                     "total_processed": total_processed,
-                    "message": format!("Import finished. Success: {}, Errors: {}", success_count, error_count)
+                    "summary": summary,
+                    "message": message
                 });
                 if let Ok(json_str) = serde_json::to_string(&final_payload) {
                     log::info!(
@@ -1575,7 +1832,7 @@ pub async fn bulk_import_definitions(
         broadcaster.remove_client(&client_id_clone).await;
     });
 
-    sse
+    Ok(sse)
 }
 
 #[utoipa::path(
@@ -1709,15 +1966,17 @@ pub async fn get_sitemap(
         ("bearer_auth" = ["ADMIN"])
     ),
     summary = "Delete definition",
-    description = "Deletes a definition if it has no comments. Only administrators can delete definitions."
+    description = "Moves a definition to the trash if it has no comments. It stays restorable, together with \
+                  the flashcards and collection items that use it, until it is purged after TRASH_RETENTION_DAYS. \
+                  Only administrators can delete definitions."
 )]
 #[delete("/definition/{id}")]
 pub async fn delete_definition(
     pool: web::Data<Pool>,
     id: web::Path<i32>,
-    claims: Claims,
+    audit: AuditContext,
 ) -> impl Responder {
-    match service::delete_definition(&pool, id.into_inner(), claims.sub).await {
+    match service::delete_definition(&pool, id.into_inner(), &audit).await {
         Ok(result) => {
             if !result.definition_deleted {
                 if result.has_remaining_definitions {
@@ -1,3 +1,4 @@
+use super::import::ParsedRow;
 use super::{models::KeywordMapping, DefinitionDetail, RecentChange};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -362,21 +363,141 @@ pub struct RecentChangesQuery {
     pub home: Option<bool>,
 }
 
+/// Which changes a recent changes listing covers. Scoped listings back the per-user, per-valsi
+/// and per-collection feeds and leave out mailing list messages.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum RecentChangesScope {
+    #[default]
+    All,
+    /// Changes made by the user with this username
+    User(String),
+    /// Changes to the valsi with this id and its definitions
+    Valsi(i32),
+    /// Changes to the definitions in the collection with this id
+    Collection(i32),
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangesFeedQuery {
+    /// Number of entries (default 20)
+    pub limit: Option<i64>,
+    /// Comma-separated types: comment,definition,valsi,message
+    pub types: Option<String>,
+    /// Cursor of the page to start after
+    pub after: Option<String>,
+}
+
+/// File format of a bulk import, see [`super::import`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkImportFormat {
+    /// Columns: word,definition,notes,glosswords
+    #[default]
+    Csv,
+    /// Tab-separated with a header row, as in search result exports
+    Tsv,
+    /// Entry array of a JSON dictionary export
+    Json,
+    /// XML dictionary export
+    Xml,
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct BulkImportRequest {
-    /// CSV content with columns: gismu,definition,notes,glosswords
+    /// File content in the given format (CSV columns: word,definition,notes,glosswords)
     #[schema(format = "binary")]
     pub csv: String,
+    #[serde(default)]
+    pub format: BulkImportFormat,
     /// Target language ID for all definitions
     pub lang_id: i32,
+    /// Language the words are in (defaults to Lojban)
+    pub source_langid: Option<i32>,
+    /// Report what each row would do without writing anything
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 #[derive(Debug)]
-pub struct BulkImportParams<'a> {
-    pub csv_data: &'a str,
+pub struct BulkImportParams {
+    /// The uploaded file, already split by [`super::import::parse_rows`]
+    pub rows: Vec<ParsedRow>,
     pub lang_id: i32,
+    pub source_langid: Option<i32>,
     pub client_id: String,
     pub import_time: DateTime<Utc>,
+    pub dry_run: bool,
+}
+
+/// What a bulk import does (or, in a dry run, would do) with one row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkImportAction {
+    /// New definition
+    Create,
+    /// Rewrites the importing user's own definition of the word
+    Update,
+    /// The word already has a definition with the same text; the row is skipped
+    Duplicate,
+    /// The row cannot be imported
+    Fail,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct BulkImportSummary {
+    pub dry_run: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub duplicates: usize,
+    pub failed: usize,
+}
+
+impl BulkImportSummary {
+    pub fn record(&mut self, action: BulkImportAction) {
+        match action {
+            BulkImportAction::Create => self.created += 1,
+            BulkImportAction::Update => self.updated += 1,
+            BulkImportAction::Duplicate => self.duplicates += 1,
+            BulkImportAction::Fail => self.failed += 1,
+        }
+    }
+
+    /// Rows that were (or would be) handled without error, duplicates included.
+    pub fn succeeded(&self) -> usize {
+        self.created + self.updated + self.duplicates
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkRevertSkipReason {
+    /// Somebody commented on the definition after the import
+    Comments,
+    /// Somebody other than the importer voted on the definition after the import
+    Votes,
+    /// The definition was edited after the import
+    Edited,
+    /// The definition no longer exists
+    Missing,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkRevertEntry {
+    pub definition_id: i32,
+    pub word: Option<String>,
+    /// `created` or `updated`
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<BulkRevertSkipReason>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkRevertReport {
+    pub client_id: String,
+    /// Created definitions that were deleted and updated ones restored to their previous version
+    pub reverted: Vec<BulkRevertEntry>,
+    /// Definitions left as they are, with the reason
+    pub not_reverted: Vec<BulkRevertEntry>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -474,3 +595,31 @@ pub struct SemanticGraphResponse {
     pub nodes: Vec<SemanticGraphNode>,
     pub edges: Vec<SemanticGraphEdge>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BulkImportAction, BulkImportSummary};
+
+    #[test]
+    fn bulk_import_summary_counts_duplicates_as_handled() {
+        let mut summary = BulkImportSummary::default();
+        for action in [
+            BulkImportAction::Create,
+            BulkImportAction::Create,
+            BulkImportAction::Update,
+            BulkImportAction::Duplicate,
+            BulkImportAction::Fail,
+        ] {
+            summary.record(action);
+        }
+        assert_eq!(summary.created, 2);
+        assert_eq!(summary.updated, 1);
+        assert_eq!(summary.duplicates, 1);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.succeeded(), 4);
+        assert_eq!(
+            serde_json::to_value(BulkImportAction::Duplicate).ok(),
+            Some(serde_json::json!("duplicate"))
+        );
+    }
+}
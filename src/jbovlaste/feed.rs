@@ -0,0 +1,514 @@
+//! Atom, RSS and ActivityStreams renderings of recent changes.
+//!
+//! Entry ids are tag URIs built from each change's keyset cursor. The cursor of a comment,
+//! definition version, valsi or message never changes, so feed readers recognise entries they
+//! have already seen across refreshes and pages.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::{json, Value};
+use std::io::Cursor;
+use xml::writer::{EventWriter, XmlEvent};
+
+use super::RecentChange;
+
+const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const ACTIVITYSTREAMS_CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+/// Date part of entry tag URIs; must never change once feeds are published.
+const TAG_DATE: &str = "2025";
+const SUMMARY_MAX_CHARS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+    ActivityStreams,
+}
+
+impl FeedFormat {
+    /// Format for a feed URL extension: `atom`, `rss` or `json` (ActivityStreams).
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "atom" => Some(FeedFormat::Atom),
+            "rss" => Some(FeedFormat::Rss),
+            "json" => Some(FeedFormat::ActivityStreams),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::ActivityStreams => "application/activity+json; charset=utf-8",
+        }
+    }
+}
+
+/// Feed-level details of a rendered page of changes.
+pub struct FeedInfo<'a> {
+    pub title: &'a str,
+    /// Frontend origin; entry links and ids are built from it
+    pub site_url: &'a str,
+    /// Frontend page showing the same changes
+    pub html_url: &'a str,
+    /// URL of this feed page
+    pub self_url: &'a str,
+    /// URL of the next (older) page, if any
+    pub next_url: Option<&'a str>,
+}
+
+struct FeedEntry {
+    id: String,
+    title: String,
+    link: String,
+    author: String,
+    /// Profile page; mailing list senders have none
+    author_url: Option<String>,
+    time: DateTime<Utc>,
+    summary: Option<String>,
+    /// Edits an existing definition rather than adding something new
+    is_update: bool,
+}
+
+/// Host part of `site_url`, the authority of entry tag URIs.
+fn tag_authority(site_url: &str) -> &str {
+    let without_scheme = site_url
+        .split_once("://")
+        .map_or(site_url, |(_, rest)| rest);
+    let host = without_scheme.split('/').next().unwrap_or(without_scheme);
+    host.split(':').next().unwrap_or(host)
+}
+
+fn truncate(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    if text.chars().count() <= SUMMARY_MAX_CHARS {
+        return Some(text);
+    }
+    let mut truncated: String = text.chars().take(SUMMARY_MAX_CHARS).collect();
+    truncated.push('…');
+    Some(truncated)
+}
+
+/// Text of a comment body (a list of `{type, data}` parts) or a plain string content.
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter(|part| part.get("type").and_then(Value::as_str) != Some("image"))
+            .filter_map(|part| part.get("data").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn valsi_link(site_url: &str, word: &str) -> String {
+    format!(
+        "{}/valsi/{}",
+        site_url,
+        urlencoding::encode(&word.replace(' ', "_"))
+    )
+}
+
+fn feed_entry(site_url: &str, change: &RecentChange) -> FeedEntry {
+    let time = Utc
+        .timestamp_opt(i64::from(change.time), 0)
+        .single()
+        .unwrap_or_default();
+    let profile = format!(
+        "{}/user/{}",
+        site_url,
+        urlencoding::encode(&change.username)
+    );
+    let (title, link, summary, author_url) = match change.change_type.as_str() {
+        "comment" => {
+            let about = change.valsi_word.as_deref().unwrap_or(&change.word);
+            (
+                format!("{} commented on {}", change.username, about),
+                format!(
+                    "{}/comments?thread_id={}&scroll_to={}&valsi_id={}&definition_id={}",
+                    site_url,
+                    change.thread_id.unwrap_or(0),
+                    change.comment_id.unwrap_or(0),
+                    change.valsi_id.unwrap_or(0),
+                    change.definition_id.unwrap_or(0)
+                ),
+                content_text(&change.content),
+                Some(profile),
+            )
+        }
+        "message" => (
+            change.word.clone(),
+            format!("{}/message/{}", site_url, change.comment_id.unwrap_or(0)),
+            content_text(&change.content),
+            None,
+        ),
+        "valsi" => (
+            format!("{} added {}", change.username, change.word),
+            valsi_link(site_url, &change.word),
+            String::new(),
+            Some(profile),
+        ),
+        _ => {
+            let language = change
+                .language_name
+                .as_deref()
+                .map(|name| format!(" ({})", name))
+                .unwrap_or_default();
+            let message = content_text(&change.content);
+            let definition = change
+                .diff
+                .as_ref()
+                .map(|diff| diff.new_content.definition.as_str())
+                .unwrap_or_default();
+            (
+                format!(
+                    "{} edited a definition of {}{}",
+                    change.username, change.word, language
+                ),
+                format!(
+                    "{}?highlight_definition_id={}",
+                    valsi_link(site_url, &change.word),
+                    change.definition_id.unwrap_or(0)
+                ),
+                [message.as_str(), definition]
+                    .iter()
+                    .filter(|text| !text.trim().is_empty())
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(" — "),
+                Some(profile),
+            )
+        }
+    };
+    let id = match &change.cursor {
+        Some(cursor) => format!(
+            "tag:{},{}:change:{}",
+            tag_authority(site_url),
+            TAG_DATE,
+            cursor
+        ),
+        None => link.clone(),
+    };
+    FeedEntry {
+        id,
+        title,
+        link,
+        author: change.username.clone(),
+        author_url,
+        time,
+        summary: truncate(&summary),
+        is_update: change.change_type == "definition"
+            && change
+                .diff
+                .as_ref()
+                .is_some_and(|diff| !diff.old_content.definition.is_empty()),
+    }
+}
+
+/// Renders a page of changes, newest first, in `format`.
+pub fn render_feed(
+    format: FeedFormat,
+    info: &FeedInfo<'_>,
+    changes: &[RecentChange],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let entries: Vec<FeedEntry> = changes
+        .iter()
+        .map(|change| feed_entry(info.site_url, change))
+        .collect();
+    match format {
+        FeedFormat::Atom => write_atom(info, &entries),
+        FeedFormat::Rss => write_rss(info, &entries),
+        FeedFormat::ActivityStreams => Ok(serde_json::to_string_pretty(&activity_page(
+            info, &entries,
+        ))?),
+    }
+}
+
+type FeedWriter = EventWriter<Cursor<Vec<u8>>>;
+
+fn new_writer() -> Result<FeedWriter, xml::writer::Error> {
+    let mut writer = EventWriter::new(Cursor::new(Vec::new()));
+    writer.write(XmlEvent::StartDocument {
+        version: xml::common::XmlVersion::Version10,
+        encoding: Some("UTF-8"),
+        standalone: None,
+    })?;
+    Ok(writer)
+}
+
+fn finish_writer(writer: FeedWriter) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(String::from_utf8(writer.into_inner().into_inner())?)
+}
+
+fn write_text(writer: &mut FeedWriter, name: &str, text: &str) -> Result<(), xml::writer::Error> {
+    writer.write(XmlEvent::start_element(name))?;
+    writer.write(XmlEvent::Characters(text))?;
+    writer.write(XmlEvent::end_element())
+}
+
+fn write_link(
+    writer: &mut FeedWriter,
+    rel: &str,
+    href: &str,
+    media_type: Option<&str>,
+) -> Result<(), xml::writer::Error> {
+    let element = XmlEvent::start_element("link")
+        .attr("rel", rel)
+        .attr("href", href);
+    match media_type {
+        Some(media_type) => writer.write(element.attr("type", media_type))?,
+        None => writer.write(element)?,
+    }
+    writer.write(XmlEvent::end_element())
+}
+
+fn write_atom(
+    info: &FeedInfo<'_>,
+    entries: &[FeedEntry],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut writer = new_writer()?;
+    let updated = entries
+        .first()
+        .map_or_else(Utc::now, |entry| entry.time)
+        .to_rfc3339();
+
+    writer.write(XmlEvent::start_element("feed").default_ns(ATOM_NS))?;
+    write_text(&mut writer, "id", info.self_url)?;
+    write_text(&mut writer, "title", info.title)?;
+    write_text(&mut writer, "updated", &updated)?;
+    write_link(
+        &mut writer,
+        "self",
+        info.self_url,
+        Some("application/atom+xml"),
+    )?;
+    write_link(&mut writer, "alternate", info.html_url, Some("text/html"))?;
+    if let Some(next_url) = info.next_url {
+        write_link(&mut writer, "next", next_url, Some("application/atom+xml"))?;
+    }
+
+    for entry in entries {
+        let time = entry.time.to_rfc3339();
+        writer.write(XmlEvent::start_element("entry"))?;
+        write_text(&mut writer, "id", &entry.id)?;
+        write_text(&mut writer, "title", &entry.title)?;
+        write_link(&mut writer, "alternate", &entry.link, Some("text/html"))?;
+        write_text(&mut writer, "published", &time)?;
+        write_text(&mut writer, "updated", &time)?;
+        writer.write(XmlEvent::start_element("author"))?;
+        write_text(&mut writer, "name", &entry.author)?;
+        if let Some(author_url) = &entry.author_url {
+            write_text(&mut writer, "uri", author_url)?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        if let Some(summary) = &entry.summary {
+            writer.write(XmlEvent::start_element("summary").attr("type", "text"))?;
+            writer.write(XmlEvent::Characters(summary))?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        writer.write(XmlEvent::end_element())?;
+    }
+
+    writer.write(XmlEvent::end_element())?;
+    finish_writer(writer)
+}
+
+fn write_rss(
+    info: &FeedInfo<'_>,
+    entries: &[FeedEntry],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut writer = new_writer()?;
+    let build_date = entries
+        .first()
+        .map_or_else(Utc::now, |entry| entry.time)
+        .to_rfc2822();
+
+    writer.write(
+        XmlEvent::start_element("rss")
+            .attr("version", "2.0")
+            .ns("atom", ATOM_NS)
+            .ns("dc", DC_NS),
+    )?;
+    writer.write(XmlEvent::start_element("channel"))?;
+    write_text(&mut writer, "title", info.title)?;
+    write_text(&mut writer, "link", info.html_url)?;
+    write_text(&mut writer, "description", info.title)?;
+    write_text(&mut writer, "lastBuildDate", &build_date)?;
+    writer.write(
+        XmlEvent::start_element("atom:link")
+            .attr("rel", "self")
+            .attr("href", info.self_url)
+            .attr("type", "application/rss+xml"),
+    )?;
+    writer.write(XmlEvent::end_element())?;
+    if let Some(next_url) = info.next_url {
+        writer.write(
+            XmlEvent::start_element("atom:link")
+                .attr("rel", "next")
+                .attr("href", next_url)
+                .attr("type", "application/rss+xml"),
+        )?;
+        writer.write(XmlEvent::end_element())?;
+    }
+
+    for entry in entries {
+        writer.write(XmlEvent::start_element("item"))?;
+        write_text(&mut writer, "title", &entry.title)?;
+        write_text(&mut writer, "link", &entry.link)?;
+        writer.write(XmlEvent::start_element("guid").attr("isPermaLink", "false"))?;
+        writer.write(XmlEvent::Characters(&entry.id))?;
+        writer.write(XmlEvent::end_element())?;
+        write_text(&mut writer, "pubDate", &entry.time.to_rfc2822())?;
+        write_text(&mut writer, "dc:creator", &entry.author)?;
+        if let Some(summary) = &entry.summary {
+            write_text(&mut writer, "description", summary)?;
+        }
+        writer.write(XmlEvent::end_element())?;
+    }
+
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    finish_writer(writer)
+}
+
+/// An ActivityStreams 2.0 `OrderedCollectionPage` of `Create`/`Update` activities, for bots
+/// that consume ActivityPub-style JSON.
+fn activity_page(info: &FeedInfo<'_>, entries: &[FeedEntry]) -> Value {
+    let items: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            let mut actor = json!({ "type": "Person", "name": entry.author });
+            if let Some(author_url) = &entry.author_url {
+                actor["id"] = json!(author_url);
+                actor["url"] = json!(author_url);
+            }
+            let mut object = json!({
+                "type": "Note",
+                "name": entry.title,
+                "url": entry.link,
+            });
+            if let Some(summary) = &entry.summary {
+                object["content"] = json!(summary);
+            }
+            json!({
+                "id": entry.id,
+                "type": if entry.is_update { "Update" } else { "Create" },
+                "summary": entry.title,
+                "actor": actor,
+                "published": entry.time.to_rfc3339(),
+                "object": object,
+            })
+        })
+        .collect();
+
+    let mut page = json!({
+        "@context": ACTIVITYSTREAMS_CONTEXT,
+        "id": info.self_url,
+        "type": "OrderedCollectionPage",
+        "name": info.title,
+        "url": info.html_url,
+        "orderedItems": items,
+    });
+    if let Some(next_url) = info.next_url {
+        page["next"] = json!(next_url);
+    }
+    page
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn change(change_type: &str, cursor: &str) -> RecentChange {
+        RecentChange {
+            change_type: change_type.to_string(),
+            word: "bajra".to_string(),
+            content: json!([{ "type": "text", "data": "coi <rodo> & co" }]),
+            valsi_id: Some(7),
+            lang_id: Some(2),
+            natlang_word_id: None,
+            comment_id: Some(11),
+            thread_id: Some(3),
+            definition_id: Some(5),
+            username: "la.djan.".to_string(),
+            time: 1_700_000_000,
+            language_name: Some("English".to_string()),
+            language_english_name: None,
+            language_lojban_name: None,
+            diff: None,
+            comment_num: None,
+            valsi_word: Some("bajra".to_string()),
+            parent_id: None,
+            reactions: None,
+            is_bookmarked: None,
+            cursor: Some(cursor.to_string()),
+        }
+    }
+
+    fn info() -> FeedInfo<'static> {
+        FeedInfo {
+            title: "Recent changes",
+            site_url: "https://lensisku.example.org",
+            html_url: "https://lensisku.example.org/recent",
+            self_url: "https://lensisku.example.org/api/jbovlaste/changes.atom",
+            next_url: Some("https://lensisku.example.org/api/jbovlaste/changes.atom?after=abc"),
+        }
+    }
+
+    #[test]
+    fn entry_ids_come_from_the_cursor() {
+        let entry = feed_entry(
+            "https://lensisku.example.org:8080/",
+            &change("comment", "eyJ0Ijox"),
+        );
+        assert_eq!(entry.id, "tag:lensisku.example.org,2025:change:eyJ0Ijox");
+        assert_eq!(entry.title, "la.djan. commented on bajra");
+        assert_eq!(entry.summary.as_deref(), Some("coi <rodo> & co"));
+    }
+
+    #[test]
+    fn atom_and_rss_are_escaped() {
+        let changes = [change("comment", "b25l"), change("valsi", "dHdv")];
+        let atom = render_feed(FeedFormat::Atom, &info(), &changes).unwrap();
+        assert!(atom.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+        assert!(atom.contains("<id>tag:lensisku.example.org,2025:change:b25l</id>"));
+        assert!(atom.contains("<summary type=\"text\">coi &lt;rodo&gt; &amp; co</summary>"));
+        assert!(atom.contains("rel=\"next\""));
+        assert_eq!(atom.matches("<entry>").count(), 2);
+
+        let rss = render_feed(FeedFormat::Rss, &info(), &changes).unwrap();
+        assert!(rss.contains(
+            "<guid isPermaLink=\"false\">tag:lensisku.example.org,2025:change:dHdv</guid>"
+        ));
+        assert!(rss.contains("<dc:creator>la.djan.</dc:creator>"));
+        assert!(rss.contains("<link>https://lensisku.example.org/valsi/bajra</link>"));
+    }
+
+    #[test]
+    fn activity_streams_page_lists_activities() {
+        let page: Value = serde_json::from_str(
+            &render_feed(
+                FeedFormat::ActivityStreams,
+                &info(),
+                &[change("message", "bWVz")],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(page["type"], "OrderedCollectionPage");
+        let item = &page["orderedItems"][0];
+        assert_eq!(item["type"], "Create");
+        assert_eq!(
+            item["object"]["url"],
+            "https://lensisku.example.org/message/11"
+        );
+        assert!(item["actor"].get("url").is_none());
+    }
+}
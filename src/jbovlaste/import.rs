@@ -0,0 +1,487 @@
+//! Bulk import file formats.
+//!
+//! Besides the original four-column CSV (`word,definition,notes,glosswords`), bulk imports read
+//! what the dictionary export writes, so that an export from one instance can be loaded into
+//! another:
+//!
+//! - JSON: the entry array of `export::service::generate_json`
+//! - XML: the `<dictionary><entries><entry>` document of `export::service::generate_xml`
+//! - TSV: a header row naming the columns (`word`, `definition`, `notes`, `etymology`,
+//!   `selmaho`, `jargon`, `rafsi`), with keywords in `glossword_N` / `placekeyword_N` columns,
+//!   each optionally followed by a `_meaning` column, as in search result exports.
+//!   Unknown columns such as `type` or `score` are ignored.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use xml::reader::{EventReader, XmlEvent};
+
+use super::{BulkImportFormat, KeywordMapping};
+
+/// One definition to import. `None` keywords mean the file has no keyword data for the row,
+/// so an update keeps the existing keywords.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct BulkImportRow {
+    pub word: String,
+    pub definition: String,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub etymology: Option<String>,
+    #[serde(default)]
+    pub selmaho: Option<String>,
+    #[serde(default)]
+    pub jargon: Option<String>,
+    #[serde(default)]
+    pub rafsi: Option<String>,
+    #[serde(default)]
+    pub gloss_keywords: Option<Vec<KeywordMapping>>,
+    #[serde(default)]
+    pub place_keywords: Option<Vec<KeywordMapping>>,
+}
+
+/// A row that could not be read. `word` is set when the row got far enough to have one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    pub word: Option<String>,
+    pub message: String,
+}
+
+impl RowError {
+    fn new(word: Option<&str>, message: impl Into<String>) -> Self {
+        RowError {
+            word: word.map(str::to_string),
+            message: message.into(),
+        }
+    }
+}
+
+pub type ParsedRow = Result<BulkImportRow, RowError>;
+
+/// Splits an import file into rows. Errors in single rows are returned in place so that the
+/// import can report them and go on; an unreadable file as a whole is an `Err`.
+pub fn parse_rows(format: BulkImportFormat, data: &str) -> Result<Vec<ParsedRow>, String> {
+    match format {
+        BulkImportFormat::Csv => Ok(parse_csv(data)),
+        BulkImportFormat::Tsv => parse_tsv(data),
+        BulkImportFormat::Json => parse_json(data),
+        BulkImportFormat::Xml => parse_xml(data),
+    }
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// Rejects rows without a word or definition and drops empty optional fields.
+fn finish_row(mut row: BulkImportRow) -> ParsedRow {
+    row.word = row.word.trim().to_string();
+    if row.word.is_empty() {
+        return Err(RowError::new(None, "Missing word"));
+    }
+    if row.definition.trim().is_empty() {
+        return Err(RowError::new(Some(&row.word), "Missing definition"));
+    }
+    for field in [
+        &mut row.notes,
+        &mut row.etymology,
+        &mut row.selmaho,
+        &mut row.jargon,
+        &mut row.rafsi,
+    ] {
+        *field = non_empty(field.as_deref());
+    }
+    for keywords in [&mut row.gloss_keywords, &mut row.place_keywords]
+        .into_iter()
+        .flatten()
+    {
+        for keyword in keywords.iter_mut() {
+            keyword.word = keyword.word.trim().to_string();
+            keyword.meaning = non_empty(keyword.meaning.as_deref());
+        }
+    }
+    if let Some(gloss) = row.gloss_keywords.as_mut() {
+        gloss.retain(|keyword| !keyword.word.is_empty());
+    }
+    if let Some(places) = &row.place_keywords {
+        if let Some(gap) = places.iter().position(|keyword| keyword.word.is_empty()) {
+            return Err(RowError::new(
+                Some(&row.word),
+                format!("Place keyword {} is empty", gap + 1),
+            ));
+        }
+    }
+    Ok(row)
+}
+
+/// `word1;meaning1,word2` as used in the glosswords column of the CSV format.
+fn parse_glosswords(value: &str) -> Vec<KeywordMapping> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let parts: Vec<&str> = pair.splitn(2, ';').collect();
+            if parts.is_empty() || parts[0].trim().is_empty() {
+                None
+            } else {
+                Some(KeywordMapping {
+                    word: parts[0].trim().to_string(),
+                    meaning: parts.get(1).map(|s| s.trim().to_string()),
+                })
+            }
+        })
+        .collect()
+}
+
+fn parse_csv(data: &str) -> Vec<ParsedRow> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(data.as_bytes());
+
+    rdr.deserialize::<(String, String, Option<String>, Option<String>)>()
+        .map(|record| {
+            let (word, definition, notes, glosswords) =
+                record.map_err(|e| RowError::new(None, format!("CSV parsing error: {}", e)))?;
+            finish_row(BulkImportRow {
+                word,
+                definition,
+                notes,
+                gloss_keywords: Some(
+                    glosswords
+                        .as_deref()
+                        .map(parse_glosswords)
+                        .unwrap_or_default(),
+                ),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Column indexes of the numbered keyword columns, by keyword number.
+#[derive(Default)]
+struct KeywordColumns(BTreeMap<usize, (Option<usize>, Option<usize>)>);
+
+impl KeywordColumns {
+    fn add(&mut self, suffix: &str, column: usize) -> bool {
+        let (number, is_meaning) = match suffix.strip_suffix("_meaning") {
+            Some(number) => (number, true),
+            None => (suffix, false),
+        };
+        let Ok(number) = number.parse::<usize>() else {
+            return false;
+        };
+        let entry = self.0.entry(number).or_default();
+        if is_meaning {
+            entry.1 = Some(column);
+        } else {
+            entry.0 = Some(column);
+        }
+        true
+    }
+
+    /// Keywords in number order; `None` if the file has no such columns. Empty keyword cells
+    /// stay in place (as empty words) so that gaps in place numbering are noticed.
+    fn read(&self, record: &csv::StringRecord) -> Option<Vec<KeywordMapping>> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let cell = |column: Option<usize>| non_empty(column.and_then(|i| record.get(i)));
+        let mut keywords: Vec<KeywordMapping> = self
+            .0
+            .values()
+            .map(|&(word, meaning)| KeywordMapping {
+                word: cell(word).unwrap_or_default(),
+                meaning: cell(meaning),
+            })
+            .collect();
+        while keywords
+            .last()
+            .is_some_and(|keyword| keyword.word.is_empty())
+        {
+            keywords.pop();
+        }
+        Some(keywords)
+    }
+}
+
+fn parse_tsv(data: &str) -> Result<Vec<ParsedRow>, String> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .quoting(false)
+        .flexible(true)
+        .has_headers(true)
+        .from_reader(data.as_bytes());
+
+    let headers = rdr
+        .headers()
+        .map_err(|e| format!("Invalid TSV header: {}", e))?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim() == name);
+    let (Some(word_column), Some(definition_column)) = (column("word"), column("definition"))
+    else {
+        return Err("TSV files need a header row with `word` and `definition` columns".into());
+    };
+    let field_columns = [
+        ("notes", column("notes")),
+        ("etymology", column("etymology")),
+        ("selmaho", column("selmaho")),
+        ("jargon", column("jargon")),
+        ("rafsi", column("rafsi")),
+    ];
+
+    let mut gloss_columns = KeywordColumns::default();
+    let mut place_columns = KeywordColumns::default();
+    for (i, header) in headers.iter().enumerate() {
+        let header = header.trim();
+        if let Some(suffix) = header.strip_prefix("glossword_") {
+            gloss_columns.add(suffix, i);
+        } else if let Some(suffix) = header.strip_prefix("placekeyword_") {
+            place_columns.add(suffix, i);
+        }
+    }
+
+    Ok(rdr
+        .records()
+        .map(|record| {
+            let record =
+                record.map_err(|e| RowError::new(None, format!("TSV parsing error: {}", e)))?;
+            let field = |name: &str| {
+                field_columns
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .and_then(|(_, column)| *column)
+                    .and_then(|i| record.get(i))
+                    .map(str::to_string)
+            };
+            finish_row(BulkImportRow {
+                word: record.get(word_column).unwrap_or_default().to_string(),
+                definition: record
+                    .get(definition_column)
+                    .unwrap_or_default()
+                    .to_string(),
+                notes: field("notes"),
+                etymology: field("etymology"),
+                selmaho: field("selmaho"),
+                jargon: field("jargon"),
+                rafsi: field("rafsi"),
+                gloss_keywords: gloss_columns.read(&record),
+                place_keywords: place_columns.read(&record),
+            })
+        })
+        .collect())
+}
+
+fn parse_json(data: &str) -> Result<Vec<ParsedRow>, String> {
+    let entries: Vec<serde_json::Value> = serde_json::from_str(data)
+        .map_err(|e| format!("Expected a JSON array of dictionary entries: {}", e))?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            let word = entry
+                .get("word")
+                .and_then(|w| w.as_str())
+                .map(str::to_string);
+            serde_json::from_value::<BulkImportRow>(entry)
+                .map_err(|e| RowError::new(word.as_deref(), format!("Invalid entry: {}", e)))
+                .and_then(finish_row)
+        })
+        .collect())
+}
+
+fn parse_xml(data: &str) -> Result<Vec<ParsedRow>, String> {
+    let mut rows = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+    let mut entry: Option<BulkImportRow> = None;
+    let mut keyword = KeywordMapping {
+        word: String::new(),
+        meaning: None,
+    };
+
+    for event in EventReader::new(data.as_bytes()) {
+        match event.map_err(|e| format!("Invalid XML: {}", e))? {
+            XmlEvent::StartElement { name, .. } => {
+                let name = name.local_name;
+                match (name.as_str(), entry.as_mut()) {
+                    ("entry", None) => entry = Some(BulkImportRow::default()),
+                    ("gloss_keywords", Some(row)) => {
+                        row.gloss_keywords.get_or_insert_with(Vec::new);
+                    }
+                    ("place_keywords", Some(row)) => {
+                        row.place_keywords.get_or_insert_with(Vec::new);
+                    }
+                    ("keyword", Some(_)) => {
+                        keyword = KeywordMapping {
+                            word: String::new(),
+                            meaning: None,
+                        };
+                    }
+                    _ => {}
+                }
+                path.push(name);
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                let Some(row) = entry.as_mut() else {
+                    continue;
+                };
+                let names: Vec<&str> = path.iter().map(String::as_str).collect();
+                match names.as_slice() {
+                    [.., "keyword", "word"] => keyword.word.push_str(&text),
+                    [.., "keyword", "meaning"] => keyword
+                        .meaning
+                        .get_or_insert_with(String::new)
+                        .push_str(&text),
+                    [.., "entry", field] => {
+                        let target = match *field {
+                            "word" => &mut row.word,
+                            "definition" => &mut row.definition,
+                            "notes" => row.notes.get_or_insert_with(String::new),
+                            "etymology" => row.etymology.get_or_insert_with(String::new),
+                            "selmaho" => row.selmaho.get_or_insert_with(String::new),
+                            "jargon" => row.jargon.get_or_insert_with(String::new),
+                            "rafsi" => row.rafsi.get_or_insert_with(String::new),
+                            _ => continue,
+                        };
+                        target.push_str(&text);
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                let name = path.pop().unwrap_or_default();
+                let parent = path.last().map(String::as_str);
+                match (name.as_str(), entry.as_mut()) {
+                    ("keyword", Some(row)) => {
+                        let list = match parent {
+                            Some("gloss_keywords") => row.gloss_keywords.as_mut(),
+                            Some("place_keywords") => row.place_keywords.as_mut(),
+                            _ => None,
+                        };
+                        if let Some(list) = list {
+                            list.push(keyword.clone());
+                        }
+                    }
+                    ("entry", Some(_)) => {
+                        if let Some(row) = entry.take() {
+                            rows.push(finish_row(row));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn keyword(word: &str, meaning: Option<&str>) -> KeywordMapping {
+        KeywordMapping {
+            word: word.to_string(),
+            meaning: meaning.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn csv_keeps_the_original_columns() {
+        let rows = parse_rows(
+            BulkImportFormat::Csv,
+            "word,definition,notes,glosswords\nbajra,$x_1$ runs,,\"jog;slow run,sprint\"\n",
+        )
+        .unwrap();
+        let row = rows[0].as_ref().unwrap();
+        assert_eq!(row.word, "bajra");
+        assert_eq!(row.notes, None);
+        assert_eq!(
+            row.gloss_keywords,
+            Some(vec![
+                keyword("jog", Some("slow run")),
+                keyword("sprint", None)
+            ])
+        );
+        assert_eq!(row.place_keywords, None);
+    }
+
+    #[test]
+    fn tsv_reads_search_export_columns() {
+        let data =
+            "word\ttype\trafsi\tselmaho\tdefinition\tnotes\tjargon\tcollection_note\tscore\t\
+                    glossword_1\tglossword_1_meaning\tplacekeyword_1\tplacekeyword_1_meaning\t\
+                    placekeyword_2\tplacekeyword_2_meaning\n\
+                    bajra\tgismu\tbaj\t\t$x_1$ runs on $x_2$\t\t\t\t3\trun\t\trunner\t\tsurface\t\n\
+                    klama\tgismu\tkla\t\t$x_1$ goes\t\t\t\t5\tgo\t\t\t\tdestination\t\n";
+        let rows = parse_rows(BulkImportFormat::Tsv, data).unwrap();
+        let bajra = rows[0].as_ref().unwrap();
+        assert_eq!(bajra.rafsi.as_deref(), Some("baj"));
+        assert_eq!(bajra.selmaho, None);
+        assert_eq!(bajra.gloss_keywords, Some(vec![keyword("run", None)]));
+        assert_eq!(
+            bajra.place_keywords,
+            Some(vec![keyword("runner", None), keyword("surface", None)])
+        );
+        let klama = rows[1].as_ref().unwrap_err();
+        assert_eq!(klama.word.as_deref(), Some("klama"));
+        assert!(klama.message.contains("Place keyword 1"));
+
+        assert!(parse_rows(BulkImportFormat::Tsv, "gismu\tdefinition\n").is_err());
+    }
+
+    #[test]
+    fn json_reads_export_entries() {
+        let data = r#"[
+            {"word": "bajra", "word_type": "gismu", "rafsi": "baj", "definition": "$x_1$ runs",
+             "etymology": "", "score": 3.0,
+             "gloss_keywords": [{"word": "run", "meaning": null}],
+             "place_keywords": [{"word": "runner", "meaning": "agent"}],
+             "user": {"username": "officialdata", "realname": null}},
+            {"word": "klama"}
+        ]"#;
+        let rows = parse_rows(BulkImportFormat::Json, data).unwrap();
+        let bajra = rows[0].as_ref().unwrap();
+        assert_eq!(bajra.etymology, None);
+        assert_eq!(
+            bajra.place_keywords,
+            Some(vec![keyword("runner", Some("agent"))])
+        );
+        assert_eq!(rows[1].as_ref().unwrap_err().word.as_deref(), Some("klama"));
+        assert!(parse_rows(BulkImportFormat::Json, "{}").is_err());
+    }
+
+    #[test]
+    fn xml_reads_export_documents() {
+        let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+            <dictionary><metadata><language>English</language></metadata><entries>\
+            <entry><word>bajra</word><type>gismu</type><rafsi>baj</rafsi>\
+            <definition>$x_1$ runs &amp; jumps</definition><score>3</score>\
+            <gloss_keywords><keyword><word>run</word></keyword></gloss_keywords>\
+            <place_keywords><keyword><word>runner</word><meaning>agent</meaning></keyword>\
+            <keyword><word>surface</word></keyword></place_keywords></entry>\
+            <entry><word>klama</word></entry>\
+            </entries></dictionary>";
+        let rows = parse_rows(BulkImportFormat::Xml, data).unwrap();
+        assert_eq!(rows.len(), 2);
+        let bajra = rows[0].as_ref().unwrap();
+        assert_eq!(bajra.word, "bajra");
+        assert_eq!(bajra.definition, "$x_1$ runs & jumps");
+        assert_eq!(bajra.rafsi.as_deref(), Some("baj"));
+        assert_eq!(bajra.gloss_keywords, Some(vec![keyword("run", None)]));
+        assert_eq!(
+            bajra.place_keywords,
+            Some(vec![
+                keyword("runner", Some("agent")),
+                keyword("surface", None)
+            ])
+        );
+        assert_eq!(bajra.notes, None);
+        assert!(rows[1].is_err());
+        assert!(parse_rows(BulkImportFormat::Xml, "<dictionary><entries>").is_err());
+    }
+}
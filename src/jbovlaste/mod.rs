@@ -1,6 +1,8 @@
 pub mod broadcast;
 pub mod controller;
 pub mod dto;
+pub mod feed;
+pub mod import;
 pub mod models;
 pub mod service;
 
@@ -33,6 +35,8 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .service(controller::list_non_lojban_definitions)
             .service(controller::get_definition_image)
             .service(controller::get_recent_changes)
+            .service(controller::get_recent_changes_feed)
+            .service(controller::get_scoped_changes_feed)
             .service(controller::list_valsi_types)
             // Public routes must be registered before the authenticated `scope("")`.
             // An empty-prefix scope matches every leftover path and otherwise 404s these GETs.
@@ -48,6 +52,7 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
                     .service(controller::cancel_bulk_import)
                     .service(controller::list_active_imports)
                     .service(controller::delete_bulk_definitions)
+                    .service(controller::revert_bulk_import)
                     .service(controller::update_definition)
                     .service(controller::rename_wiki_page)
                     .service(controller::delete_definition)
@@ -226,6 +226,9 @@ pub struct RecentChange {
     pub reactions: Option<Vec<ReactionResponse>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_bookmarked: Option<bool>,
+    /// Keyset cursor of this change; feed entry ids are derived from it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -13,11 +13,12 @@ use log::debug;
 use super::broadcast::Broadcaster;
 use super::dto::ClientIdGroup;
 use super::{
-    AddDefinitionRequest, BulkImportParams, DefinitionListResponse, DefinitionResponse, Example,
+    AddDefinitionRequest, BulkImportAction, BulkImportParams, BulkImportSummary, BulkRevertEntry,
+    BulkRevertReport, BulkRevertSkipReason, DefinitionListResponse, DefinitionResponse, Example,
     GetImageDefinitionQuery, ImageData, KeywordMapping, ListDefinitionsQuery,
-    NonLojbanDefinitionsQuery, RecentChange, RecentChangesResponse, RenameWikiRequest,
-    RenameWikiResponse, SearchDefinitionsParams, SemanticGraphEdge, SemanticGraphNode,
-    SemanticGraphResponse, UpdateDefinitionRequest, ValsiDetail, ValsiType,
+    NonLojbanDefinitionsQuery, RecentChange, RecentChangesResponse, RecentChangesScope,
+    RenameWikiRequest, RenameWikiResponse, SearchDefinitionsParams, SemanticGraphEdge,
+    SemanticGraphNode, SemanticGraphResponse, UpdateDefinitionRequest, ValsiDetail, ValsiType,
     WikiByDefinitionResponse,
 };
 use crate::jbovlaste::models::{
@@ -75,6 +76,7 @@ fn push_author_sql_numbered(
     }
 }
 
+use crate::audit::{self, AuditAction, AuditContext};
 use crate::auth::Claims;
 use crate::comments::dto::ReactionResponse;
 use crate::language::{
@@ -84,9 +86,10 @@ use crate::middleware::cache::RedisCache;
 use crate::subscriptions::models::SubscriptionTrigger;
 use crate::versions::service::{
     get_diff, get_version_with_transaction, next_definitionnum_for_language,
-    retarget_definition_votes,
+    restore_version_content, retarget_definition_votes,
 };
 use crate::versions::{Change, ChangeType, VersionContent, VersionDiff};
+use crate::webhooks::{self, WebhookEvent};
 use base64::engine::general_purpose::STANDARD as BASE64;
 use base64::Engine;
 use camxes_rs::camxes::peg::parsing::ParseResult;
@@ -242,6 +245,7 @@ pub async fn semantic_search(
             JOIN languages l ON d.langid = l.langid
             LEFT JOIN vote_scores dv ON dv.definitionid = d.definitionid
             WHERE d.langid != 1 
+              AND d.deleted_at IS NULL
               AND (d.langid = ANY($2) OR $2 IS NULL) 
               AND d.definition != ''
               {embedding_match_sql}
@@ -315,11 +319,12 @@ pub async fn semantic_search(
             LEFT JOIN LATERAL (
                 SELECT COUNT(c.commentid) as comment_count
                 FROM threads t
-                LEFT JOIN comments c ON c.threadid = t.threadid
+                LEFT JOIN comments c ON c.threadid = t.threadid AND c.deleted_at IS NULL
                 WHERE (t.valsiid = v.valsiid OR t.definitionid = d.definitionid)
             ) cc ON true
             LEFT JOIN definition_images_flag di ON di.definition_id = d.definitionid
             WHERE d.langid != 1 
+              AND d.deleted_at IS NULL
               AND (d.langid = ANY($2) OR $2 IS NULL) 
               AND d.definition != ''
               {embedding_match_sql}
@@ -442,6 +447,7 @@ pub async fn embedding_for_definition(
             FROM definitions
             WHERE definitionid = $1
               AND embedding IS NOT NULL
+              AND deleted_at IS NULL
               AND langid != 1
             "#,
             &[&definition_id],
@@ -498,6 +504,7 @@ pub async fn semantic_graph_valsi_embedding(
             LEFT JOIN vote_scores dv ON dv.definitionid = d.definitionid
             WHERE d.langid != 1
               AND d.embedding IS NOT NULL
+              AND d.deleted_at IS NULL
               AND d.definition != ''
               AND (v.word = $1 OR lower(v.word) = lower($1))
             ORDER BY
@@ -665,6 +672,7 @@ pub async fn semantic_graph(
               AND (d.langid = ANY($2) OR $2 IS NULL)
               AND d.definition != ''
               AND d.embedding IS NOT NULL
+              AND d.deleted_at IS NULL
             {additional_conditions}
             ORDER BY exact_match_rank ASC, d.embedding <=> $1::vector ASC
             LIMIT 2000
@@ -835,6 +843,7 @@ pub async fn semantic_graph_preview(
               AND (d.langid = ANY($1) OR $1 IS NULL)
               AND d.definition != ''
               AND d.embedding IS NOT NULL
+              AND d.deleted_at IS NULL
             {additional_conditions}
               AND COALESCE(dv.score, 0)::bigint >= ${min_vote_param}
         ),
@@ -1128,11 +1137,12 @@ pub async fn search_definitions(
             LEFT JOIN LATERAL (
                 SELECT COUNT(c.commentid) as comment_count
                 FROM threads t
-                LEFT JOIN comments c ON c.threadid = t.threadid
+                LEFT JOIN comments c ON c.threadid = t.threadid AND c.deleted_at IS NULL
                 WHERE (t.valsiid = d.valsiid OR t.definitionid = d.definitionid)
             ) cc ON true
             LEFT JOIN definition_images_flag di ON di.definition_id = d.definitionid
             WHERE (d.cached_search_text ILIKE $2 OR d.cached_valsiword ILIKE $8)
+                  AND d.deleted_at IS NULL
                   AND (d.langid = ANY($4) OR $4 IS NULL)
                   {additional_conditions}
         ),
@@ -1195,6 +1205,7 @@ pub async fn search_definitions(
             LEFT JOIN vote_scores dv ON dv.definitionid = d.definitionid
             LEFT JOIN definition_images_flag di ON di.definition_id = d.definitionid
             WHERE (d.cached_search_text ILIKE $2 OR d.cached_valsiword ILIKE $8)
+                  AND d.deleted_at IS NULL
                   AND (d.langid = ANY($4) OR $4 IS NULL)
                   {additional_conditions}
         ),
@@ -1378,6 +1389,7 @@ pub async fn search_definitions(
             END as rank
         FROM definitions d
         WHERE (d.cached_search_text ILIKE $2 OR d.cached_valsiword ILIKE $6)
+              AND d.deleted_at IS NULL
               AND (d.langid = ANY($4) OR $4 IS NULL)
               {}
     )
@@ -1519,6 +1531,7 @@ pub async fn fast_search_definitions(
             END as rank
         FROM definitions d
         WHERE (d.cached_search_text ILIKE $2::text OR d.cached_valsiword ILIKE $7::text)
+        AND d.deleted_at IS NULL
         AND (d.langid = ANY($4::int4[]) OR $4::int4[] IS NULL)
         AND d.cached_source_langid = $5::int4
         {additional_conditions}
@@ -1588,7 +1601,8 @@ pub async fn fast_search_definitions(
     // Parameters: $1=like_pattern, $2=languages_slice, $3=source_langid_value, $4=lojban_like_pattern
     let base_conditions = r#"(d.cached_search_text ILIKE $1::text OR d.cached_valsiword ILIKE $4::text)
                   AND (d.langid = ANY($2) OR $2 IS NULL)
-                  AND d.cached_source_langid = $3"#;
+                  AND d.cached_source_langid = $3
+                  AND d.deleted_at IS NULL"#;
 
     // Build dynamic conditions with correct parameter numbering
     let mut conditions = vec![];
@@ -2023,7 +2037,7 @@ pub async fn get_entry_details(
              v.cached_decomposition,
              (SELECT COUNT(c.commentid)
               FROM threads t
-              LEFT JOIN comments c ON t.threadid = c.threadid
+              LEFT JOIN comments c ON t.threadid = c.threadid AND c.deleted_at IS NULL
               WHERE t.valsiid = v.valsiid AND t.definitionid = 0) as comment_count
              FROM valsi v
              JOIN valsitypes vt ON v.typeid = vt.typeid
@@ -2100,7 +2114,7 @@ pub async fn get_wiki_by_word(
                 SELECT 1 FROM definition_images di
                 JOIN definitions d ON di.definition_id = d.definitionid
                 JOIN valsi v ON d.valsiid = v.valsiid
-                WHERE v.word = $1 AND v.typeid = 16
+                WHERE v.word = $1 AND v.typeid = 16 AND d.deleted_at IS NULL
             ) as has_image
         )
         SELECT d.*, v.word as valsiword, v.valsiid as valsiid, v.source_langid, v.typeid as valsi_typeid,
@@ -2108,7 +2122,7 @@ pub async fn get_wiki_by_word(
                 vt.descriptor as type_name,
                 i.has_image,
                 (SELECT COUNT(*) FROM threads t
-                      LEFT JOIN comments c ON t.threadid = c.threadid
+                      LEFT JOIN comments c ON t.threadid = c.threadid AND c.deleted_at IS NULL
                       WHERE t.valsiid = v.valsiid AND t.definitionid = d.definitionid) as comment_count,
                 CASE
                     WHEN $2::int IS NOT NULL THEN can_edit_definition(d.definitionid, $2)
@@ -2126,7 +2140,7 @@ pub async fn get_wiki_by_word(
          JOIN languages l ON d.langid = l.langid
          CROSS JOIN image_check i
          JOIN users u ON d.userid = u.userid
-         WHERE v.word = $1 AND v.typeid = 16
+         WHERE v.word = $1 AND v.typeid = 16 AND d.deleted_at IS NULL
          ORDER BY d.definitionid
          LIMIT 1",
         &[&word, &user_id],
@@ -2438,6 +2452,19 @@ async fn upsert_wiki_in_transaction(
             ],
         )
         .await?;
+    webhooks::service::queue_event(
+        &transaction,
+        WebhookEvent::DefinitionUpdated,
+        Some(valsi_id),
+        claims.sub,
+        json!({
+            "definition_id": definition_id,
+            "word": valsi_word,
+            "lang_id": request.lang_id,
+            "url": url,
+        }),
+    )
+    .await?;
 
     if let Err(e) = redis_cache.invalidate_definition_search_caches().await {
         log::error!(
@@ -2466,7 +2493,7 @@ pub async fn get_wiki_by_definition_id(
             "SELECT d.definitionid, d.metadata, v.word, v.valsiid
              FROM definitions d
              JOIN valsi v ON d.valsiid = v.valsiid
-             WHERE d.definitionid = $1 AND v.typeid = 16",
+             WHERE d.definitionid = $1 AND v.typeid = 16 AND d.deleted_at IS NULL",
             &[&definition_id],
         )
         .await?;
@@ -2725,6 +2752,19 @@ pub async fn rename_wiki_page(
             ],
         )
         .await;
+    webhooks::service::queue_event(
+        &transaction,
+        WebhookEvent::DefinitionUpdated,
+        Some(valsi_id),
+        claims.sub,
+        json!({
+            "definition_id": definition_id,
+            "word": new_word,
+            "previous_word": old_word,
+            "url": url,
+        }),
+    )
+    .await?;
 
     if let Err(e) = redis_cache.invalidate_definition_search_caches().await {
         log::error!(
@@ -3245,6 +3285,21 @@ async fn add_definition_in_transaction(
                 &[&valsi_id, &message, &url, &claims.sub],
             )
             .await?;
+        webhooks::service::queue_event(
+            &transaction,
+            WebhookEvent::DefinitionCreated,
+            Some(valsi_id),
+            claims.sub,
+            json!({
+                "definition_id": definition_id,
+                "word": valsi_word,
+                "lang_id": request.lang_id,
+                "definition": sanitized_definition,
+                "notes": sanitized_notes,
+                "url": url,
+            }),
+        )
+        .await?;
     }
 
     if let Err(e) = redis_cache.invalidate_definition_search_caches().await {
@@ -3274,8 +3329,8 @@ pub async fn get_definition(
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    // Get main definition details
-    let row = transaction.query_one(
+    // Get main definition details; trashed definitions read as missing
+    let Some(row) = transaction.query_opt(
         "
         WITH image_check AS (
             SELECT EXISTS (
@@ -3288,7 +3343,7 @@ pub async fn get_definition(
                 vt.descriptor as type_name,
                 i.has_image,
                 (SELECT COUNT(*) FROM threads t
-                      LEFT JOIN comments c ON t.threadid = c.threadid
+                      LEFT JOIN comments c ON t.threadid = c.threadid AND c.deleted_at IS NULL
                       WHERE t.valsiid = v.valsiid AND t.definitionid = d.definitionid) as comment_count,
                 CASE
                     WHEN $2::int IS NOT NULL THEN can_edit_definition(d.definitionid, $2)
@@ -3307,9 +3362,12 @@ pub async fn get_definition(
          CROSS JOIN image_check i
          JOIN users u ON d.userid = u.userid
          WHERE d.definitionid = $1
+         AND d.deleted_at IS NULL
          AND v.source_langid = 1",
         &[&definition_id, &user_id],
-    ).await?;
+    ).await? else {
+        return Ok(None);
+    };
 
     let source_langid: i32 = row.get("source_langid");
     let valsi_typeid: i16 = row.get("valsi_typeid");
@@ -3459,6 +3517,17 @@ pub async fn update_definition(
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
+    let in_trash: bool = transaction
+        .query_one(
+            "SELECT EXISTS(SELECT 1 FROM definitions WHERE definitionid = $1 AND deleted_at IS NOT NULL)",
+            &[&definition_id],
+        )
+        .await?
+        .get(0);
+    if in_trash {
+        return Err("Definition is in the trash; restore it first".into());
+    }
+
     // Check if we need to create initial version
     let existing_versions: i64 = transaction
         .query_one(
@@ -3906,6 +3975,21 @@ pub async fn update_definition(
             ],
         )
         .await?;
+    webhooks::service::queue_event(
+        &transaction,
+        WebhookEvent::DefinitionUpdated,
+        Some(valsi_id),
+        user_id,
+        json!({
+            "definition_id": definition_id,
+            "word": valsi_word,
+            "lang_id": request.lang_id,
+            "definition": sanitized_definition,
+            "notes": sanitized_notes,
+            "url": url,
+        }),
+    )
+    .await?;
 
     if let Err(e) = redis_cache.invalidate_definition_search_caches().await {
         log::error!(
@@ -3942,7 +4026,7 @@ pub async fn list_definitions(
     let per_page = query.per_page.unwrap_or(20);
     let offset = (page - 1) * per_page;
 
-    let mut conditions: Vec<String> = vec!["TRUE".to_string()];
+    let mut conditions: Vec<String> = vec!["d.deleted_at IS NULL".to_string()];
     let mut params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = Vec::new();
     let mut param_count = 2; // Start param count at 2 since $1 is current_user_id
 
@@ -4142,7 +4226,10 @@ pub async fn list_non_lojban_definitions(
     let offset = (page - 1) * per_page;
 
     let mut query_params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = Vec::new();
-    let mut conditions = vec!["v.source_langid != 1".to_string()]; // Base condition: not Lojban
+    let mut conditions = vec![
+        "v.source_langid != 1".to_string(), // Base condition: not Lojban
+        "d.deleted_at IS NULL".to_string(),
+    ];
 
     // Add source_langid filter if provided
     if let Some(id) = &query.source_langid {
@@ -4319,15 +4406,18 @@ pub async fn update_vote(
     let transaction = client.transaction().await?;
 
     // Get valsi info for the definition
-    let valsi_info = transaction
-        .query_one(
+    let Some(valsi_info) = transaction
+        .query_opt(
             "SELECT d.valsiid as valsiid, langid, v.word
              FROM definitions d
              JOIN valsi v ON d.valsiid = v.valsiid
-             WHERE definitionid = $1",
+             WHERE definitionid = $1 AND d.deleted_at IS NULL",
             &[&definition_id],
         )
-        .await?;
+        .await?
+    else {
+        return Ok((false, "Definition not found".to_string(), None, None));
+    };
 
     let valsi_id: i32 = valsi_info.get("valsiid");
     let lang_id: i32 = valsi_info.get("langid");
@@ -4400,6 +4490,21 @@ pub async fn update_vote(
         raw.clamp(i32::MIN as i64, i32::MAX as i64) as i32
     };
 
+    webhooks::service::queue_event(
+        &transaction,
+        WebhookEvent::VoteChanged,
+        Some(valsi_id),
+        user_id,
+        json!({
+            "definition_id": definition_id,
+            "word": word,
+            "lang_id": lang_id,
+            "vote": should_insert.then_some(if downvote { -1 } else { 1 }),
+            "score": score,
+        }),
+    )
+    .await?;
+
     if let Err(e) = redis_cache.invalidate_definition_search_caches().await {
         log::error!(
             "Failed to invalidate definition search caches after vote: {}",
@@ -4464,6 +4569,7 @@ pub async fn get_definitions_by_entry(
                 WHEN $1 ~ '^\\d+$' THEN v.valsiid = $1::int AND v.source_langid = 1
                 ELSE v.word = $2 AND v.source_langid = 1
             END
+            AND d.deleted_at IS NULL
         )
         SELECT r.*
         FROM definition_ranks r
@@ -4536,7 +4642,7 @@ pub async fn get_definitions_by_entry(
         .query(
             "SELECT t.definitionid, COUNT(*) as count
              FROM threads t
-             LEFT JOIN comments c ON t.threadid = c.threadid
+             LEFT JOIN comments c ON t.threadid = c.threadid AND c.deleted_at IS NULL
              WHERE t.valsiid = $1 AND t.definitionid = ANY($2)
              GROUP BY t.definitionid",
             &[&valsi_id, &def_ids],
@@ -4713,12 +4819,42 @@ fn decode_recent_changes_cursor(after: &str) -> Option<(i32, i32, i64)> {
     Some((c.t, c.s, c.i))
 }
 
+/// Extra condition restricting one branch of [`get_recent_changes`] to `scope`, or `None` when
+/// the branch has no changes in that scope. A user scope binds the username as `$1`.
+fn recent_changes_scope_filter(scope: &RecentChangesScope, change_type: &str) -> Option<String> {
+    match (scope, change_type) {
+        (RecentChangesScope::All, _) => Some(String::new()),
+        (_, "message") => None,
+        (RecentChangesScope::User(_), _) => Some(" AND u.username = $1".to_string()),
+        (RecentChangesScope::Valsi(id), "comment") => Some(format!(" AND t.valsiid = {}", id)),
+        (RecentChangesScope::Valsi(id), "definition") => Some(format!(" AND d.valsiid = {}", id)),
+        (RecentChangesScope::Valsi(id), "valsi") => Some(format!(" AND v.valsiid = {}", id)),
+        (RecentChangesScope::Collection(id), "comment") => Some(format!(
+            " AND t.definitionid IN (SELECT definition_id FROM collection_items WHERE collection_id = {})",
+            id
+        )),
+        (RecentChangesScope::Collection(id), "definition") => Some(format!(
+            " AND d.definitionid IN (SELECT definition_id FROM collection_items WHERE collection_id = {})",
+            id
+        )),
+        (RecentChangesScope::Collection(id), "valsi") => Some(format!(
+            " AND v.valsiid IN (SELECT d.valsiid FROM collection_items ci
+                JOIN definitions d ON d.definitionid = ci.definition_id
+                WHERE ci.collection_id = {})",
+            id
+        )),
+        _ => None,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn get_recent_changes(
     pool: &Pool,
     limit: Option<i64>,
     types: Option<String>,
     after: Option<String>,
     home: bool,
+    scope: &RecentChangesScope,
     redis_cache: &RedisCache,
     user_id: Option<i32>,
 ) -> Result<RecentChangesResponse, Box<dyn std::error::Error>> {
@@ -4726,18 +4862,19 @@ pub async fn get_recent_changes(
 
     let cache_key = match user_id {
         None => format!(
-            "recent_changes:limit:{:?}:after:{:?}:types:{:?}:home:{}",
-            limit, after, types, home
+            "recent_changes:limit:{:?}:after:{:?}:types:{:?}:home:{}:scope:{:?}",
+            limit, after, types, home, scope
         ),
         Some(uid) => format!(
-            "recent_changes:limit:{:?}:after:{:?}:types:{:?}:home:{}:user:{}",
-            limit, after, types, home, uid
+            "recent_changes:limit:{:?}:after:{:?}:types:{:?}:home:{}:scope:{:?}:user:{}",
+            limit, after, types, home, scope, uid
         ),
     };
 
     let cache_ttl = StdDuration::from_secs(300);
     let types_cloned = types.clone();
     let after_cloned = after.clone();
+    let scope = scope.clone();
 
     let response = redis_cache
         .get_or_set(
@@ -4757,6 +4894,7 @@ pub async fn get_recent_changes(
                 if home {
                     requested_types.retain(|t| *t != "valsi");
                 }
+                requested_types.retain(|t| recent_changes_scope_filter(&scope, t).is_some());
 
                 let limit_val = limit.unwrap_or(20).clamp(1, 100);
                 let cursor_condition = after_cloned.as_ref().and_then(|a| {
@@ -4777,6 +4915,9 @@ pub async fn get_recent_changes(
                             format!("ORDER BY c.time DESC, type_sort_order ASC, c.commentid DESC LIMIT {}", limit_val),
                         ),
                     };
+                    let where_extra = recent_changes_scope_filter(&scope, "comment")
+                        .unwrap_or_default()
+                        + &where_extra;
                     queries.push(format!(
                         "(SELECT
                 'comment' AS change_type,
@@ -4807,7 +4948,7 @@ pub async fn get_recent_changes(
             JOIN users u ON c.userid = u.userid
             JOIN definitions d ON d.definitionid = t.definitionid
             LEFT JOIN languages l ON d.langid = l.langid
-            WHERE u.username != 'officialdata' {}
+            WHERE u.username != 'officialdata' AND c.deleted_at IS NULL {}
             {})",
                         where_extra, order_limit
                     ));
@@ -4828,6 +4969,9 @@ pub async fn get_recent_changes(
                             format!("ORDER BY dv.created_at DESC, type_sort_order ASC, dv.version_id DESC LIMIT {}", limit_val),
                         ),
                     };
+                    let where_extra = recent_changes_scope_filter(&scope, "definition")
+                        .unwrap_or_default()
+                        + &where_extra;
                     queries.push(format!(
                         "(SELECT
                 'definition' AS change_type,
@@ -4861,7 +5005,7 @@ pub async fn get_recent_changes(
             JOIN valsi v ON d.valsiid = v.valsiid
             JOIN users u ON dv.user_id = u.userid
             LEFT JOIN languages l ON dv.langid = l.langid
-            WHERE u.username != 'officialdata' AND v.source_langid = 1 {}
+            WHERE u.username != 'officialdata' AND v.source_langid = 1 AND d.deleted_at IS NULL {}
             {})",
                         where_extra, order_limit
                     ));
@@ -4879,6 +5023,9 @@ pub async fn get_recent_changes(
                             format!("ORDER BY v.time DESC, type_sort_order ASC, v.valsiid DESC LIMIT {}", limit_val),
                         ),
                     };
+                    let where_extra = recent_changes_scope_filter(&scope, "valsi")
+                        .unwrap_or_default()
+                        + &where_extra;
                     queries.push(format!(
                         "(SELECT
                 'valsi' AS change_type,
@@ -4926,6 +5073,9 @@ pub async fn get_recent_changes(
                             format!("ORDER BY m.sent_at DESC, type_sort_order ASC, m.id DESC LIMIT {}", limit_val),
                         ),
                     };
+                    let where_extra = recent_changes_scope_filter(&scope, "message")
+                        .unwrap_or_default()
+                        + &where_extra;
                     queries.push(format!(
                         "(SELECT
                 'message' AS change_type,
@@ -4978,7 +5128,12 @@ pub async fn get_recent_changes(
                     limit_val
                 );
 
-                let base_changes = transaction.query(&final_query, &[]).await?;
+                let base_changes = match &scope {
+                    RecentChangesScope::User(username) => {
+                        transaction.query(&final_query, &[username]).await?
+                    }
+                    _ => transaction.query(&final_query, &[]).await?,
+                };
                 let mut changes = Vec::new();
                 let mut last_cursor: Option<(i32, i32, i64)> = None;
 
@@ -5047,6 +5202,11 @@ pub async fn get_recent_changes(
                             .filter(|&id| id != 0),
                         reactions: None,
                         is_bookmarked: None,
+                        cursor: Some(encode_recent_changes_cursor(
+                            time,
+                            type_sort_order,
+                            cursor_id,
+                        )),
                     };
 
                     // Add diff for definition changes
@@ -5166,6 +5326,79 @@ pub async fn get_recent_changes(
     Ok(response)
 }
 
+/// A recent changes feed narrowed to one user, valsi or collection.
+pub struct ScopedFeed {
+    pub scope: RecentChangesScope,
+    pub title: String,
+    /// Frontend path of the page showing the same changes
+    pub html_path: String,
+}
+
+/// Resolves the subject of `/feeds/{kind}/{key}`. `None` if there is no such user, valsi or
+/// collection, or the collection is private and `user_id` is neither its owner nor a member.
+pub async fn resolve_feed_scope(
+    pool: &Pool,
+    kind: &str,
+    key: &str,
+    user_id: Option<i32>,
+) -> Result<Option<ScopedFeed>, Box<dyn std::error::Error>> {
+    let client = pool.get().await?;
+    let feed = match kind {
+        "user" => client
+            .query_opt("SELECT username FROM users WHERE username = $1", &[&key])
+            .await?
+            .map(|row| {
+                let username: String = row.get("username");
+                ScopedFeed {
+                    title: format!("Changes by {}", username),
+                    html_path: format!("/user/{}", username),
+                    scope: RecentChangesScope::User(username),
+                }
+            }),
+        "valsi" => client
+            .query_opt(
+                // Frontend links write spaces in words as underscores.
+                "SELECT valsiid, word FROM valsi
+                 WHERE word = $1 OR word = replace($1, '_', ' ')
+                 ORDER BY source_langid = 1 DESC, word = $1 DESC
+                 LIMIT 1",
+                &[&key],
+            )
+            .await?
+            .map(|row| {
+                let word: String = row.get("word");
+                ScopedFeed {
+                    title: format!("Changes to {}", word),
+                    html_path: format!("/valsi/{}", word.replace(' ', "_")),
+                    scope: RecentChangesScope::Valsi(row.get("valsiid")),
+                }
+            }),
+        "collection" => {
+            let Ok(collection_id) = key.parse::<i32>() else {
+                return Ok(None);
+            };
+            client
+                .query_opt(
+                    "SELECT name, is_public, collection_role(collection_id, $2) IS NOT NULL AS is_member
+                     FROM collections WHERE collection_id = $1",
+                    &[&collection_id, &user_id],
+                )
+                .await?
+                .filter(|row| {
+                    row.get::<_, Option<bool>>("is_public").unwrap_or(true)
+                        || row.get::<_, Option<bool>>("is_member").unwrap_or(false)
+                })
+                .map(|row| ScopedFeed {
+                    title: format!("Changes in {}", row.get::<_, String>("name")),
+                    html_path: format!("/collections/{}", collection_id),
+                    scope: RecentChangesScope::Collection(collection_id),
+                })
+        }
+        _ => None,
+    };
+    Ok(feed)
+}
+
 pub async fn get_user_vote(
     pool: &Pool,
     user_id: i32,
@@ -5296,29 +5529,248 @@ pub async fn list_valsi_types(pool: &Pool) -> Result<Vec<ValsiType>, Box<dyn std
     Ok(types)
 }
 
+/// What a bulk import row resolves to against the definitions already in the dictionary.
+enum BulkRowPlan {
+    Create,
+    Update(i32),
+    Duplicate(i32),
+}
+
+impl BulkRowPlan {
+    fn action(&self) -> BulkImportAction {
+        match self {
+            BulkRowPlan::Create => BulkImportAction::Create,
+            BulkRowPlan::Update(_) => BulkImportAction::Update,
+            BulkRowPlan::Duplicate(_) => BulkImportAction::Duplicate,
+        }
+    }
+
+    fn definition_id(&self) -> Option<i32> {
+        match self {
+            BulkRowPlan::Create => None,
+            BulkRowPlan::Update(id) | BulkRowPlan::Duplicate(id) => Some(*id),
+        }
+    }
+}
+
+/// Decides what importing `request` does: a definition of the word with the same text makes the
+/// row a duplicate, a definition by the importing user is updated, anything else is created.
+/// Runs the same validation as `add_definition` so that dry runs report failing rows too.
+/// Nothing is written.
+async fn plan_bulk_import_row(
+    pool: &Pool,
+    claims: &Claims,
+    parsers: &Arc<HashMap<i32, Peg>>,
+    request: &AddDefinitionRequest,
+) -> Result<BulkRowPlan, Box<dyn std::error::Error>> {
+    let source_langid = request.source_langid.unwrap_or(1);
+    let sanitized_definition = sanitize_html(&request.definition);
+    let combined_text = format!(
+        "{} {} {}",
+        sanitized_definition,
+        request
+            .notes
+            .as_deref()
+            .map(sanitize_html)
+            .unwrap_or_default(),
+        request
+            .etymology
+            .as_deref()
+            .map(sanitize_html)
+            .unwrap_or_default()
+    );
+    let mut client = pool.get().await?;
+    // Read-only; dropped without committing.
+    let transaction = client.transaction().await?;
+    let word = match source_langid {
+        1 | 58 => {
+            let options = MathJaxValidationOptions { use_tectonic: true };
+            validate_mathjax(&combined_text, &options)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
+            analyze_word(parsers, &request.word, source_langid, &transaction)
+                .await?
+                .text
+        }
+        _ => sanitize_html(&request.word),
+    };
+
+    let existing = transaction
+        .query(
+            "SELECT d.definitionid, d.definition, d.userid
+             FROM definitions d
+             JOIN valsi v ON v.valsiid = d.valsiid
+             WHERE v.word = $1 AND v.source_langid = $2 AND d.langid = $3
+             ORDER BY d.definitionid",
+            &[&word, &source_langid, &request.lang_id],
+        )
+        .await?;
+
+    if let Some(row) = existing
+        .iter()
+        .find(|row| row.get::<_, String>("definition") == sanitized_definition)
+    {
+        return Ok(BulkRowPlan::Duplicate(row.get("definitionid")));
+    }
+    Ok(existing
+        .iter()
+        .find(|row| row.get::<_, i32>("userid") == claims.sub)
+        .map_or(BulkRowPlan::Create, |row| {
+            BulkRowPlan::Update(row.get("definitionid"))
+        }))
+}
+
+/// Update request for a bulk import row that rewrites an existing definition. Fields the import
+/// file leaves out keep their current values.
+async fn bulk_update_request(
+    pool: &Pool,
+    definition_id: i32,
+    request: &AddDefinitionRequest,
+    client_id: &str,
+) -> Result<UpdateDefinitionRequest, Box<dyn std::error::Error>> {
+    let client = pool.get().await?;
+    let current = client
+        .query_one(
+            "SELECT etymology, selmaho, jargon, rafsi, owner_only
+             FROM definitions WHERE definitionid = $1",
+            &[&definition_id],
+        )
+        .await?;
+    let place_keywords = match &request.place_keywords {
+        Some(place_keywords) => place_keywords.clone(),
+        None => client
+            .query(
+                "SELECT n.word, n.meaning
+                 FROM keywordmapping k
+                 JOIN natlangwords n ON k.natlangwordid = n.wordid
+                 WHERE k.definitionid = $1 AND k.place > 0
+                 ORDER BY k.place",
+                &[&definition_id],
+            )
+            .await?
+            .iter()
+            .map(|row| KeywordMapping {
+                word: row.get("word"),
+                meaning: row.get("meaning"),
+            })
+            .collect(),
+    };
+
+    Ok(UpdateDefinitionRequest {
+        lang_id: request.lang_id,
+        definition: request.definition.clone(),
+        notes: request.notes.clone(),
+        etymology: request.etymology.clone().or(current.get("etymology")),
+        gloss_keywords: request.gloss_keywords.clone(),
+        place_keywords: Some(place_keywords),
+        selmaho: request.selmaho.clone().or(current.get("selmaho")),
+        jargon: request.jargon.clone().or(current.get("jargon")),
+        rafsi: request.rafsi.clone().or(current.get("rafsi")),
+        owner_only: Some(current.get("owner_only")),
+        image: None,
+        remove_image: None,
+        is_wiki: None,
+        commit_message: Some(format!("Bulk import {}", client_id)),
+        expected_time: None,
+    })
+}
+
+/// Remembers what an import did to a definition, with the version it wrote and the one before,
+/// for [`revert_bulk_import`].
+async fn record_bulk_import_change(
+    pool: &Pool,
+    params: &BulkImportParams,
+    definition_id: i32,
+    action: BulkImportAction,
+    user_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let action = match action {
+        BulkImportAction::Update => "updated",
+        _ => "created",
+    };
+    pool.get()
+        .await?
+        .execute(
+            "INSERT INTO bulk_import_changes
+                 (client_id, definition_id, action, user_id,
+                  previous_version_id, import_version_id, imported_at)
+             SELECT $1, $2, $3, $4, v.ids[2], v.ids[1], $5
+             FROM (
+                SELECT ARRAY(
+                    SELECT version_id FROM definition_versions
+                    WHERE definition_id = $2
+                    ORDER BY version_id DESC
+                    LIMIT 2
+                ) AS ids
+             ) v",
+            &[
+                &params.client_id,
+                &definition_id,
+                &action,
+                &user_id,
+                &params.import_time,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Plans one row and, unless this is a dry run, applies it.
+async fn import_bulk_row(
+    pool: &Pool,
+    claims: &Claims,
+    parsers: &Arc<HashMap<i32, Peg>>,
+    params: &BulkImportParams,
+    request: &AddDefinitionRequest,
+    redis_cache: &RedisCache,
+) -> Result<(BulkImportAction, Option<i32>), Box<dyn std::error::Error>> {
+    let plan = plan_bulk_import_row(pool, claims, parsers, request).await?;
+    let action = plan.action();
+    if params.dry_run {
+        return Ok((action, plan.definition_id()));
+    }
+
+    let definition_id = match plan {
+        BulkRowPlan::Duplicate(id) => return Ok((action, Some(id))),
+        BulkRowPlan::Create => {
+            add_definition(pool, claims, parsers.clone(), request, redis_cache, false)
+                .await?
+                .1
+        }
+        BulkRowPlan::Update(id) => {
+            let update = bulk_update_request(pool, id, request, &params.client_id).await?;
+            update_definition(pool, id, claims.sub, &update, redis_cache).await?;
+            id
+        }
+    };
+
+    if let Err(e) = record_bulk_import_change(pool, params, definition_id, action, claims.sub).await
+    {
+        log::error!(
+            "Failed to record bulk import change for definition {}: {}",
+            definition_id,
+            e
+        );
+    }
+    Ok((action, Some(definition_id)))
+}
+
 pub async fn bulk_import_definitions(
     pool: &Pool,
     claims: &Claims,
     parsers: Arc<HashMap<i32, Peg>>, // Accept the map
-    params: BulkImportParams<'_>,
+    mut params: BulkImportParams,
     broadcaster: &Broadcaster,
     redis_cache: &RedisCache,
     mut cancel_rx: mpsc::Receiver<bool>,
-) -> Result<(usize, usize), Box<dyn std::error::Error>> {
-    let mut rdr = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(params.csv_data.as_bytes());
-
-    let mut success_count = 0;
-    let mut error_count = 0;
-
-    // Calculate total records without consuming the reader fully if possible
-    // Note: This might still read the whole file depending on the CSV structure.
-    // A more robust way might involve reading line-by-line twice or passing total count.
-    let mut temp_rdr = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(params.csv_data.as_bytes());
-    let total_records = temp_rdr.records().count();
+) -> Result<BulkImportSummary, Box<dyn std::error::Error>> {
+    let rows = std::mem::take(&mut params.rows);
+    let total_records = rows.len();
+
+    let mut summary = BulkImportSummary {
+        dry_run: params.dry_run,
+        ..Default::default()
+    };
 
     // Send initial progress
     let _ = broadcaster
@@ -5326,7 +5778,8 @@ pub async fn bulk_import_definitions(
             &params.client_id,
             &serde_json::to_string(&json!({
                 "type": "start",
-                "total": total_records
+                "total": total_records,
+                "dry_run": params.dry_run
             }))
             .unwrap_or_else(|e| {
                 log::error!("Failed to serialize start event: {}", e);
@@ -5335,30 +5788,36 @@ pub async fn bulk_import_definitions(
         )
         .await;
 
-    for (idx, result) in rdr.deserialize().enumerate() {
+    for (idx, result) in rows.into_iter().enumerate() {
         // Check for cancellation before processing each record
         if let Ok(true) = cancel_rx.try_recv() {
             log::info!("Cancellation received for job {}", params.client_id);
             return Err("Import cancelled by user".into());
         }
 
-        let record: (String, String, Option<String>, Option<String>) = match result {
-            Ok(rec) => rec,
+        let row = match result {
+            Ok(row) => row,
             Err(e) => {
-                log::error!("CSV parsing error at line {}: {}", idx + 1, e);
-                error_count += 1;
+                log::error!(
+                    "Bulk import parsing error at row {}: {}",
+                    idx + 1,
+                    e.message
+                );
+                summary.record(BulkImportAction::Fail);
                 let _ = broadcaster
                     .broadcast(
                         &params.client_id,
                         &serde_json::to_string(&json!({
                             "type": "progress",
                             "success": false,
-                            "word": "N/A",
-                            "error": format!("CSV parsing error: {}", e),
+                            "action": BulkImportAction::Fail,
+                            "dry_run": params.dry_run,
+                            "word": e.word.as_deref().unwrap_or("N/A"),
+                            "error": e.message,
                             "current": idx + 1,
                             "total": total_records,
-                            "success_count": success_count,
-                            "error_count": error_count
+                            "success_count": summary.succeeded(),
+                            "error_count": summary.failed
                         }))
                         .unwrap_or_else(|e| {
                             log::error!("Failed to serialize progress error event: {}", e);
@@ -5370,38 +5829,18 @@ pub async fn bulk_import_definitions(
             }
         };
 
-        let (gismu, definition, notes, glosswords) = record;
-
-        // Parse glosswords into KeywordMapping vec
-        let gloss_keywords = glosswords
-            .map(|gs| {
-                gs.split(',')
-                    .filter_map(|pair| {
-                        let parts: Vec<&str> = pair.splitn(2, ';').collect();
-                        if parts.is_empty() || parts[0].trim().is_empty() {
-                            None
-                        } else {
-                            Some(KeywordMapping {
-                                word: parts[0].trim().to_string(),
-                                meaning: parts.get(1).map(|s| s.trim().to_string()),
-                            })
-                        }
-                    })
-                    .collect()
-            })
-            .unwrap_or_default();
-
+        let gismu = row.word.clone();
         let request = AddDefinitionRequest {
-            source_langid: None, // Assuming Lojban source for bulk import
-            word: gismu.clone(),
-            definition,
-            notes,
-            etymology: None,
+            source_langid: params.source_langid,
+            word: row.word,
+            definition: row.definition,
+            notes: row.notes,
+            etymology: row.etymology,
             lang_id: params.lang_id,
-            selmaho: None, // Selmaho might need to be derived or provided
-            jargon: None,
-            gloss_keywords: Some(gloss_keywords),
-            place_keywords: None,
+            selmaho: row.selmaho,
+            jargon: row.jargon,
+            gloss_keywords: row.gloss_keywords,
+            place_keywords: row.place_keywords,
             owner_only: Some(false),
             image: None,
             metadata: Some(serde_json::json!({
@@ -5409,68 +5848,71 @@ pub async fn bulk_import_definitions(
                 "client_id": params.client_id,
                 "import_time": params.import_time,
             })),
-            rafsi: None,
+            rafsi: row.rafsi,
             is_wiki: None,
             commit_message: None,
             expected_time: None,
         };
 
-        // Pass the parser map to add_definition
-        match add_definition(pool, claims, parsers.clone(), &request, redis_cache, false).await {
-            Ok(_) => {
-                success_count += 1;
-                let _ = broadcaster
-                    .broadcast(
-                        &params.client_id,
-                        &serde_json::to_string(&json!({
-                            "type": "progress",
-                            "success": true,
-                            "word": gismu.clone(),
-                            "current": idx + 1,
-                            "total": total_records,
-                            "success_count": success_count,
-                            "error_count": error_count
-                        }))
-                        .unwrap_or_else(|e| {
-                            log::error!("Failed to serialize progress success event: {}", e);
-                            "{}".to_string()
-                        }),
-                    )
-                    .await;
+        let outcome = import_bulk_row(pool, claims, &parsers, &params, &request, redis_cache).await;
+        let event = match outcome {
+            Ok((action, definition_id)) => {
+                summary.record(action);
+                json!({
+                    "type": "progress",
+                    "success": true,
+                    "action": action,
+                    "definition_id": definition_id,
+                    "dry_run": params.dry_run,
+                    "word": gismu,
+                    "current": idx + 1,
+                    "total": total_records,
+                    "success_count": summary.succeeded(),
+                    "error_count": summary.failed
+                })
             }
             Err(e) => {
                 log::error!("Failed to import definition for '{}': {}", gismu, e);
-                error_count += 1;
-                let _ = broadcaster
-                    .broadcast(
-                        &params.client_id,
-                        &serde_json::to_string(&json!({
-                            "type": "progress",
-                            "success": false,
-                            "word": gismu,
-                            "error": e.to_string(),
-                            "current": idx + 1,
-                            "total": total_records,
-                            "success_count": success_count,
-                            "error_count": error_count
-                        }))
-                        .unwrap_or_else(|e| {
-                            log::error!("Failed to serialize progress error event: {}", e);
-                            "{}".to_string()
-                        }),
-                    )
-                    .await;
+                summary.record(BulkImportAction::Fail);
+                json!({
+                    "type": "progress",
+                    "success": false,
+                    "action": BulkImportAction::Fail,
+                    "dry_run": params.dry_run,
+                    "word": gismu,
+                    "error": e.to_string(),
+                    "current": idx + 1,
+                    "total": total_records,
+                    "success_count": summary.succeeded(),
+                    "error_count": summary.failed
+                })
             }
-        }
+        };
+        let _ = broadcaster
+            .broadcast(
+                &params.client_id,
+                &serde_json::to_string(&event).unwrap_or_else(|e| {
+                    log::error!("Failed to serialize progress event: {}", e);
+                    "{}".to_string()
+                }),
+            )
+            .await;
     }
 
     log::info!(
-        "Bulk import finished for client {}. Success: {}, Errors: {}",
+        "Bulk import {} for client {}. Created: {}, Updated: {}, Duplicates: {}, Errors: {}",
+        if params.dry_run {
+            "dry run finished"
+        } else {
+            "finished"
+        },
         params.client_id,
-        success_count,
-        error_count
+        summary.created,
+        summary.updated,
+        summary.duplicates,
+        summary.failed
     );
-    Ok((success_count, error_count))
+    Ok(summary)
 }
 
 #[derive(Debug)]
@@ -5480,25 +5922,28 @@ pub struct DeleteDefinitionResult {
     pub has_remaining_definitions: bool,
 }
 
+/// Moves a definition to the trash. It disappears from search and listings, but keeps its
+/// votes, keywords, flashcards and collection items until it is restored or purged.
 pub async fn delete_definition(
     pool: &Pool,
     definition_id: i32,
-    user_id: i32,
+    ctx: &AuditContext,
 ) -> Result<DeleteDefinitionResult, Box<dyn std::error::Error>> {
+    let user_id = ctx.actor_id;
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    // Get valsi_id before deletion
-    let valsi_id: Option<i32> = transaction
+    // Get valsi_id and author before deletion
+    let row = transaction
         .query_opt(
-            "SELECT valsiid FROM definitions WHERE definitionid = $1",
+            "SELECT valsiid, userid FROM definitions
+             WHERE definitionid = $1 AND deleted_at IS NULL",
             &[&definition_id],
         )
-        .await?
-        .map(|row| row.get::<_, i32>("valsiid"));
+        .await?;
 
-    let valsi_id = match valsi_id {
-        Some(id) => id,
+    let (valsi_id, author_id): (i32, i32) = match row {
+        Some(row) => (row.get("valsiid"), row.get("userid")),
         None => {
             return Ok(DeleteDefinitionResult {
                 definition_deleted: false,
@@ -5509,16 +5954,7 @@ pub async fn delete_definition(
     };
 
     // Check if user is the author
-    let is_author = transaction
-        .query_one(
-            "SELECT userid FROM definitions WHERE definitionid = $1",
-            &[&definition_id],
-        )
-        .await?
-        .get::<_, i32>("userid")
-        == user_id;
-
-    if !is_author {
+    if author_id != user_id {
         return Err("Only the author can delete their definition".into());
     }
 
@@ -5543,49 +5979,132 @@ pub async fn delete_definition(
         });
     }
 
-    // Delete related records first
-    transaction
-        .execute(
-            "DELETE FROM keywordmapping WHERE definitionid = $1",
-            &[&definition_id],
-        )
-        .await?;
+    let before = audit::service::definition_snapshot(&transaction, definition_id).await?;
 
     transaction
         .execute(
-            "DELETE FROM definitionvotes WHERE definitionid = $1",
-            &[&definition_id],
+            "UPDATE definitions SET deleted_at = NOW(), deleted_by = $2
+             WHERE definitionid = $1",
+            &[&definition_id, &user_id],
         )
         .await?;
 
-    transaction
-        .execute(
-            "DELETE FROM natlangwordvotes WHERE definitionid = $1",
-            &[&definition_id],
+    let valsi_word: String = transaction
+        .query_one("SELECT word FROM valsi WHERE valsiid = $1", &[&valsi_id])
+        .await?
+        .get("word");
+    webhooks::service::queue_event(
+        &transaction,
+        WebhookEvent::DefinitionDeleted,
+        Some(valsi_id),
+        user_id,
+        json!({ "definition_id": definition_id, "word": valsi_word }),
+    )
+    .await?;
+
+    // Check if there are remaining definitions for this valsi
+    let remaining_definitions_count: i64 = transaction
+        .query_one(
+            "SELECT COUNT(*) FROM definitions WHERE valsiid = $1 AND deleted_at IS NULL",
+            &[&valsi_id],
         )
-        .await?;
+        .await?
+        .get(0);
 
-    transaction
-        .execute(
-            "DELETE FROM definition_images WHERE definition_id = $1",
-            &[&definition_id],
+    let has_remaining_definitions = remaining_definitions_count > 0;
+
+    // Without other definitions or discussions the word goes away with this definition; it is
+    // removed for good when the definition is purged.
+    let valsi_deleted =
+        !has_remaining_definitions && !valsi_has_discussions(&transaction, valsi_id).await?;
+
+    audit::service::record(
+        &transaction,
+        ctx,
+        AuditAction::DeleteDefinition,
+        &definition_id.to_string(),
+        before,
+        Some(json!({ "valsi_deleted": valsi_deleted })),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(DeleteDefinitionResult {
+        definition_deleted: true,
+        valsi_deleted,
+        has_remaining_definitions,
+    })
+}
+
+/// Whether any thread about the valsi has comments.
+async fn valsi_has_discussions(
+    transaction: &Transaction<'_>,
+    valsi_id: i32,
+) -> Result<bool, tokio_postgres::Error> {
+    Ok(transaction
+        .query_one(
+            "SELECT EXISTS(
+                SELECT 1 FROM threads t
+                WHERE t.valsiid = $1
+                AND EXISTS(SELECT 1 FROM comments c WHERE c.threadid = t.threadid)
+            )",
+            &[&valsi_id],
         )
-        .await?;
+        .await?
+        .get(0))
+}
 
-    transaction
-        .execute(
-            "DELETE FROM definition_versions WHERE definition_id = $1",
+/// Takes a definition out of the trash. Its flashcards and collection items were kept, so they
+/// show up again with it.
+pub async fn restore_definition(
+    transaction: &Transaction<'_>,
+    definition_id: i32,
+    user_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let row = transaction
+        .query_opt(
+            "UPDATE definitions SET deleted_at = NULL, deleted_by = NULL
+             WHERE definitionid = $1 AND deleted_at IS NOT NULL
+             RETURNING valsiid",
             &[&definition_id],
         )
-        .await?;
+        .await?
+        .ok_or("Definition is not in the trash")?;
+    let valsi_id: i32 = row.get("valsiid");
 
-    // Delete user notifications referencing this definition
-    transaction
-        .execute(
-            "DELETE FROM user_notifications WHERE definition_id = $1",
+    let valsi_word: String = transaction
+        .query_one("SELECT word FROM valsi WHERE valsiid = $1", &[&valsi_id])
+        .await?
+        .get("word");
+    webhooks::service::queue_event(
+        transaction,
+        WebhookEvent::DefinitionCreated,
+        Some(valsi_id),
+        user_id,
+        json!({ "definition_id": definition_id, "word": valsi_word, "restored": true }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Removes a trashed definition for good, and its valsi when nothing else uses it.
+pub async fn purge_definition(
+    transaction: &Transaction<'_>,
+    definition_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let valsi_id: i32 = match transaction
+        .query_opt(
+            "SELECT valsiid FROM definitions
+             WHERE definitionid = $1 AND deleted_at IS NOT NULL
+             FOR UPDATE",
             &[&definition_id],
         )
-        .await?;
+        .await?
+    {
+        Some(row) => row.get("valsiid"),
+        None => return Ok(()),
+    };
 
     // Delete related subscriptions
     transaction
@@ -5595,51 +6114,18 @@ pub async fn delete_definition(
         )
         .await?;
 
-    // Delete the definition itself
-    let deleted = transaction
-        .execute(
-            "DELETE FROM definitions WHERE definitionid = $1",
-            &[&definition_id],
-        )
-        .await?;
-
-    if deleted == 0 {
-        transaction.commit().await?;
-        return Ok(DeleteDefinitionResult {
-            definition_deleted: false,
-            valsi_deleted: false,
-            has_remaining_definitions: false,
-        });
-    }
-
-    // Check if there are remaining definitions for this valsi
-    let remaining_definitions_count: i64 = transaction
-        .query_one(
-            "SELECT COUNT(*) FROM definitions WHERE valsiid = $1",
-            &[&valsi_id],
-        )
-        .await?
-        .get(0);
-
-    let has_remaining_definitions = remaining_definitions_count > 0;
+    delete_definition_records(transaction, definition_id).await?;
 
-    // Check if there are any discussions (threads with comments) for this valsi
-    let has_valsi_discussions: bool = transaction
+    // If no definitions and no discussions are left, delete the valsi
+    let has_definitions: bool = transaction
         .query_one(
-            "SELECT EXISTS(
-                SELECT 1 FROM threads t
-                WHERE t.valsiid = $1
-                AND EXISTS(SELECT 1 FROM comments c WHERE c.threadid = t.threadid)
-            )",
+            "SELECT EXISTS(SELECT 1 FROM definitions WHERE valsiid = $1)",
             &[&valsi_id],
         )
         .await?
         .get(0);
 
-    let mut valsi_deleted = false;
-
-    // If no remaining definitions and no discussions, delete the valsi
-    if !has_remaining_definitions && !has_valsi_discussions {
+    if !has_definitions && !valsi_has_discussions(transaction, valsi_id).await? {
         // Delete valsi subscriptions first
         transaction
             .execute(
@@ -5648,21 +6134,12 @@ pub async fn delete_definition(
             )
             .await?;
 
-        // Delete the valsi itself
-        let valsi_deleted_count = transaction
+        transaction
             .execute("DELETE FROM valsi WHERE valsiid = $1", &[&valsi_id])
             .await?;
-
-        valsi_deleted = valsi_deleted_count > 0;
     }
 
-    transaction.commit().await?;
-
-    Ok(DeleteDefinitionResult {
-        definition_deleted: true,
-        valsi_deleted,
-        has_remaining_definitions,
-    })
+    Ok(())
 }
 
 pub async fn add_definition_image(
@@ -5728,9 +6205,104 @@ pub async fn add_definition_image(
     Ok(image_id)
 }
 
+/// Deletes a definition together with everything that references it.
+async fn delete_definition_records(
+    transaction: &Transaction<'_>,
+    def_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Collection items showing the definition, with their flashcards and review history
+    transaction
+        .execute(
+            "DELETE FROM flashcard_review_history
+             WHERE flashcard_id IN (
+                 SELECT f.id FROM flashcards f
+                 JOIN collection_items ci ON ci.item_id = f.item_id
+                 WHERE ci.definition_id = $1
+             )",
+            &[&def_id],
+        )
+        .await?;
+
+    transaction
+        .execute(
+            "DELETE FROM flashcards
+             WHERE item_id IN (SELECT item_id FROM collection_items WHERE definition_id = $1)",
+            &[&def_id],
+        )
+        .await?;
+
+    transaction
+        .execute(
+            "DELETE FROM collection_items WHERE definition_id = $1",
+            &[&def_id],
+        )
+        .await?;
+
+    transaction
+        .execute(
+            "DELETE FROM keywordmapping WHERE definitionid = $1",
+            &[&def_id],
+        )
+        .await?;
+
+    transaction
+        .execute(
+            "DELETE FROM definitionvotes WHERE definitionid = $1",
+            &[&def_id],
+        )
+        .await?;
+
+    transaction
+        .execute(
+            "DELETE FROM natlangwordvotes WHERE definitionid = $1",
+            &[&def_id],
+        )
+        .await?;
+
+    transaction
+        .execute(
+            "DELETE FROM definition_images WHERE definition_id = $1",
+            &[&def_id],
+        )
+        .await?;
+
+    transaction
+        .execute(
+            "DELETE FROM definition_versions WHERE definition_id = $1",
+            &[&def_id],
+        )
+        .await?;
+
+    // Delete user notifications referencing this definition
+    transaction
+        .execute(
+            "DELETE FROM user_notifications WHERE definition_id = $1",
+            &[&def_id],
+        )
+        .await?;
+
+    // Delete the definition itself
+    transaction
+        .execute(
+            "DELETE FROM definitions WHERE definitionid = $1",
+            &[&def_id],
+        )
+        .await?;
+
+    transaction
+        .execute(
+            "DELETE FROM bulk_import_changes WHERE definition_id = $1",
+            &[&def_id],
+        )
+        .await?;
+
+    Ok(())
+}
+
 pub async fn delete_bulk_definitions(
     pool: &Pool,
     client_id: &str,
+    ctx: &AuditContext,
 ) -> Result<(Vec<i32>, Vec<i32>), Box<dyn std::error::Error>> {
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
@@ -5746,6 +6318,7 @@ pub async fn delete_bulk_definitions(
 
     let mut deleted = Vec::new();
     let mut skipped = Vec::new();
+    let mut snapshots = Vec::new();
 
     for row in definitions {
         let def_id: i32 = row.get("definitionid");
@@ -5768,63 +6341,161 @@ pub async fn delete_bulk_definitions(
             continue;
         }
 
-        // Delete related records
-        transaction
-            .execute(
-                "DELETE FROM keywordmapping WHERE definitionid = $1",
-                &[&def_id],
-            )
-            .await?;
+        snapshots.extend(audit::service::definition_snapshot(&transaction, def_id).await?);
+        delete_definition_records(&transaction, def_id).await?;
+        deleted.push(def_id);
+    }
 
-        transaction
-            .execute(
-                "DELETE FROM definitionvotes WHERE definitionid = $1",
-                &[&def_id],
-            )
-            .await?;
+    audit::service::record(
+        &transaction,
+        ctx,
+        AuditAction::DeleteBulkDefinitions,
+        client_id,
+        Some(json!({ "definitions": snapshots })),
+        Some(json!({ "deleted": deleted, "skipped": skipped })),
+    )
+    .await?;
 
-        transaction
-            .execute(
-                "DELETE FROM natlangwordvotes WHERE definitionid = $1",
-                &[&def_id],
-            )
-            .await?;
+    transaction.commit().await?;
+    Ok((deleted, skipped))
+}
 
-        transaction
-            .execute(
-                "DELETE FROM definition_images WHERE definition_id = $1",
-                &[&def_id],
-            )
-            .await?;
+/// Undoes a bulk import: definitions it created are deleted and definitions it updated get their
+/// pre-import version back, with the versions the import wrote removed. Definitions that were
+/// commented on, voted on by someone else or edited since the import are left as they are and
+/// reported in `not_reverted`.
+pub async fn revert_bulk_import(
+    pool: &Pool,
+    client_id: &str,
+    ctx: &AuditContext,
+) -> Result<BulkRevertReport, Box<dyn std::error::Error>> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
 
-        transaction
-            .execute(
-                "DELETE FROM definition_versions WHERE definition_id = $1",
-                &[&def_id],
-            )
-            .await?;
+    // Newest first, so that a row that updated a definition created earlier in the same
+    // import is undone before the creation.
+    let changes = transaction
+        .query(
+            "SELECT id, definition_id, action, user_id, previous_version_id,
+                    import_version_id, imported_at
+             FROM bulk_import_changes
+             WHERE client_id = $1
+             ORDER BY id DESC",
+            &[&client_id],
+        )
+        .await?;
 
-        // Delete user notifications referencing this definition
-        transaction
-            .execute(
-                "DELETE FROM user_notifications WHERE definition_id = $1",
-                &[&def_id],
-            )
-            .await?;
+    let mut report = BulkRevertReport {
+        client_id: client_id.to_string(),
+        reverted: Vec::new(),
+        not_reverted: Vec::new(),
+    };
 
-        // Delete the definition itself
-        transaction
-            .execute(
-                "DELETE FROM definitions WHERE definitionid = $1",
-                &[&def_id],
+    for change in changes {
+        let change_id: i32 = change.get("id");
+        let definition_id: i32 = change.get("definition_id");
+        let action: String = change.get("action");
+        let user_id: i32 = change.get("user_id");
+        let previous_version_id: Option<i32> = change.get("previous_version_id");
+        let import_version_id: Option<i32> = change.get("import_version_id");
+        let imported_at: DateTime<Utc> = change.get("imported_at");
+
+        let state = transaction
+            .query_one(
+                "SELECT
+                    (SELECT v.word FROM definitions d
+                     JOIN valsi v ON v.valsiid = d.valsiid
+                     WHERE d.definitionid = $1) AS word,
+                    EXISTS(
+                        SELECT 1 FROM threads t
+                        JOIN comments c ON t.threadid = c.threadid
+                        WHERE t.definitionid = $1 AND c.time >= $3
+                    ) AS has_comments,
+                    EXISTS(
+                        SELECT 1 FROM definitionvotes
+                        WHERE definitionid = $1 AND userid <> $2 AND time >= $3
+                    ) AS has_votes,
+                    EXISTS(
+                        SELECT 1 FROM definition_versions
+                        WHERE definition_id = $1 AND version_id > $4
+                    ) AS edited",
+                &[
+                    &definition_id,
+                    &user_id,
+                    &(imported_at.timestamp() as i32),
+                    &import_version_id,
+                ],
             )
             .await?;
 
-        deleted.push(def_id);
+        let word: Option<String> = state.get("word");
+        let reason = if word.is_none() {
+            Some(BulkRevertSkipReason::Missing)
+        } else if state.get("has_comments") {
+            Some(BulkRevertSkipReason::Comments)
+        } else if state.get("has_votes") {
+            Some(BulkRevertSkipReason::Votes)
+        } else if state.get("edited") {
+            Some(BulkRevertSkipReason::Edited)
+        } else {
+            None
+        };
+        if reason.is_some() {
+            report.not_reverted.push(BulkRevertEntry {
+                definition_id,
+                word,
+                action,
+                reason,
+            });
+            continue;
+        }
+
+        if action == "updated" {
+            let previous_version_id = previous_version_id.ok_or_else(|| {
+                format!(
+                    "No pre-import version recorded for definition {}",
+                    definition_id
+                )
+            })?;
+            let previous = get_version_with_transaction(&transaction, previous_version_id).await?;
+            restore_version_content(&transaction, &previous).await?;
+            transaction
+                .execute(
+                    "DELETE FROM definition_versions
+                     WHERE definition_id = $1 AND version_id > $2",
+                    &[&definition_id, &previous_version_id],
+                )
+                .await?;
+            transaction
+                .execute(
+                    "DELETE FROM bulk_import_changes WHERE id = $1",
+                    &[&change_id],
+                )
+                .await?;
+        } else {
+            delete_definition_records(&transaction, definition_id).await?;
+        }
+
+        report.reverted.push(BulkRevertEntry {
+            definition_id,
+            word,
+            action,
+            reason: None,
+        });
     }
 
+    audit::service::record(
+        &transaction,
+        ctx,
+        AuditAction::RevertBulkImport,
+        client_id,
+        None,
+        Some(serde_json::to_value(&report)?),
+    )
+    .await?;
+
     transaction.commit().await?;
-    Ok((deleted, skipped))
+    Ok(report)
 }
 
 pub async fn get_definition_image(
@@ -641,7 +641,7 @@ async fn fetch_experimental_rafsi_data(
             "SELECT v.word, string_agg(d.rafsi, ' ' ORDER BY d.definitionid) as rafsi
              FROM valsi v
              JOIN definitions d ON d.valsiid = v.valsiid
-             WHERE v.typeid = $1 AND d.rafsi IS NOT NULL
+             WHERE v.typeid = $1 AND d.rafsi IS NOT NULL AND d.deleted_at IS NULL
              GROUP BY v.word",
             &[&type_id],
         )
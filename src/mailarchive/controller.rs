@@ -1,10 +1,16 @@
-use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web::{get, http::header, post, web, HttpResponse, Responder};
 // use actix_web_grants::protect;
+use bytes::Bytes;
 use deadpool_postgres::Pool;
 use serde_json::json;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 
-use super::{service, Message, SpamVoteResponse, ThreadQuery, ThreadResponse};
+use super::{
+    query::{parse_day, parse_search_query},
+    service, MailExportQuery, Message, SpamVoteResponse, ThreadQuery, ThreadResponse,
+};
 use crate::auth::Claims;
+use crate::middleware::limiter::MailExportLimiter;
 
 #[utoipa::path(
     post,
@@ -79,7 +85,8 @@ pub async fn get_message(
     tag = "mail",
     path = "/mail/thread",
     params(
-        ("subject" = String, Query, description = "Thread subject"),
+        ("thread_id" = Option<i32>, Query, description = "Id of any message in the thread"),
+        ("subject" = Option<String>, Query, description = "Thread subject, used when thread_id is not given"),
         ("page" = Option<i64>, Query, description = "Page number"),
         ("per_page" = Option<i64>, Query, description = "Items per page"),
         ("sort_by" = Option<String>, Query, description = "Field to sort by (date)"),
@@ -88,17 +95,159 @@ pub async fn get_message(
     ),
     responses(
         (status = 200, description = "Thread messages", body = ThreadResponse),
+        (status = 400, description = "Neither thread_id nor subject given"),
         (status = 500, description = "Internal server error")
     ),
     summary = "Show message thread",
-    description = "Retrieve all messages in a thread based on the subject. The subject is normalized by \
-                  removing common prefixes (Re:, [tags], etc) to group related messages. Results are \
-                  paginated and can be sorted chronologically. Message content can be optionally included.",
+    description = "Retrieve all messages in a thread. With thread_id, the reply tree built from \
+                  Message-ID/In-Reply-To/References is returned and each message carries its parent_id. \
+                  With only a subject, the subject is normalized by removing common prefixes (Re:, [tags], etc) \
+                  to group related messages. Results are paginated and can be sorted chronologically. \
+                  Message content can be optionally included.",
 )]
 #[get("/thread")]
 pub async fn show_thread(pool: web::Data<Pool>, query: web::Query<ThreadQuery>) -> impl Responder {
+    if query.thread_id.is_none() && query.subject.is_none() {
+        return HttpResponse::BadRequest().body("Either thread_id or subject is required");
+    }
     match service::show_thread(&pool, query.into_inner()).await {
         Ok(response) => HttpResponse::Ok().json(response),
         Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
     }
 }
+
+#[utoipa::path(
+    get,
+    tag = "mail",
+    path = "/mail/export",
+    params(
+        ("query" = Option<String>, Query, description = "Search query, same syntax as mail search (optional)"),
+        ("from" = Option<String>, Query, description = "Sender substring"),
+        ("after" = Option<String>, Query, description = "Sent on or after this day (YYYY-MM-DD)"),
+        ("before" = Option<String>, Query, description = "Sent before this day (YYYY-MM-DD)"),
+        ("after_id" = Option<i32>, Query, description = "Continue after this message (X-Next-After-Id of the previous export)"),
+        ("limit" = Option<i64>, Query, description = "Messages in this file, at most and by default 5000")
+    ),
+    responses(
+        (status = 200, description = "mbox file (mboxrd), streamed", content_type = "application/mbox",
+         headers(("X-Next-After-Id" = i32, description = "Set when more messages match: the after_id of the next export"))),
+        (status = 400, description = "Invalid date"),
+        (status = 401, description = "Unauthorized"),
+        (status = 429, description = "Too many exports")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    summary = "Export messages as mbox",
+    description = "Authenticated users only; rate limited per user. Streams the matching messages, \
+                  oldest first, as an mboxrd file. Messages are rebuilt from the stored headers and MIME \
+                  parts, attachments included. One file holds at most `limit` messages; when more match, \
+                  the X-Next-After-Id header gives the after_id for the next file.",
+)]
+#[get("/export")]
+pub async fn export_mbox(
+    claims: Claims,
+    pool: web::Data<Pool>,
+    limiter: web::Data<MailExportLimiter>,
+    query: web::Query<MailExportQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let mut filter = parse_search_query(query.query.as_deref().unwrap_or_default());
+    if let Some(from) = query.from.filter(|f| !f.trim().is_empty()) {
+        filter.from = Some(from.trim().to_string());
+    }
+    for (value, target) in [
+        (&query.after, &mut filter.after),
+        (&query.before, &mut filter.before),
+    ] {
+        if let Some(value) = value {
+            match parse_day(value.trim()) {
+                Some(day) => *target = Some(day),
+                None => {
+                    return HttpResponse::BadRequest()
+                        .body(format!("Invalid date '{}', expected YYYY-MM-DD", value));
+                }
+            }
+        }
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(service::MAX_EXPORT_MESSAGES)
+        .clamp(1, service::MAX_EXPORT_MESSAGES);
+
+    match limiter.check_and_record(claims.sub).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", limiter.retry_after_secs().to_string()))
+                .json(json!({ "error": "Rate limit exceeded" }));
+        }
+        Err(e) => {
+            log::error!("Mail export rate limit Redis error: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Rate limit check failed"
+            }));
+        }
+    }
+
+    let next_after_id = match service::next_mbox_export(&pool, &filter, query.after_id, limit).await
+    {
+        Ok(next) => next,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+        }
+    };
+
+    let chunks = ReceiverStream::new(service::export_mbox(
+        pool.get_ref().clone(),
+        filter,
+        query.after_id,
+        limit,
+    ))
+    .map(|chunk| chunk.map(Bytes::from).map_err(std::io::Error::other));
+    let mut response = HttpResponse::Ok();
+    if let Some(id) = next_after_id {
+        response.insert_header(("X-Next-After-Id", id.to_string()));
+    }
+    response
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .content_type("application/mbox")
+        .append_header((
+            "Content-Disposition",
+            "attachment; filename=\"lojban-mail.mbox\"",
+        ))
+        .streaming(chunks)
+}
+
+#[utoipa::path(
+    get,
+    tag = "mail",
+    path = "/mail/export/thread/{id}",
+    params(
+        ("id" = i32, Path, description = "Id of any message in the thread")
+    ),
+    responses(
+        (status = 200, description = "Zip of .eml files", content_type = "application/zip"),
+        (status = 404, description = "Message not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    summary = "Export a thread as EML files",
+    description = "Zip archive with one .eml file per message of the thread, in chronological order, \
+                  rebuilt from the stored headers and MIME parts with attachments.",
+)]
+#[get("/export/thread/{id}")]
+pub async fn export_thread(pool: web::Data<Pool>, id: web::Path<i32>) -> impl Responder {
+    match service::export_thread_eml_zip(&pool, id.into_inner()).await {
+        Ok(Some((root_id, content))) => HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .content_type("application/zip")
+            .append_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"mail-thread-{}.zip\"", root_id),
+            ))
+            .body(content),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
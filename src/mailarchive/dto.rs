@@ -5,9 +5,11 @@ use super::models::Message;
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct SearchQuery {
+    /// Words, `"phrases"`, `-excluded` terms and `from:`/`before:`/`after:` operators.
     pub query: String,
     pub page: Option<i64>,
     pub per_page: Option<i64>,
+    /// `rank` (default, `ts_rank_cd`), `date`, `sent_at` or `subject`.
     pub sort_by: Option<String>,
     pub sort_order: Option<String>,
     pub include_content: Option<bool>,
@@ -24,7 +26,10 @@ pub struct SearchResponse {
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct ThreadQuery {
-    pub subject: String,
+    /// Any message of the thread; its reply tree is returned.
+    pub thread_id: Option<i32>,
+    /// Group by cleaned subject instead; used when `thread_id` is not given.
+    pub subject: Option<String>,
     pub page: Option<i64>,
     pub per_page: Option<i64>,
     pub sort_by: Option<String>,
@@ -39,6 +44,24 @@ pub struct ThreadResponse {
     pub page: i64,
     pub per_page: i64,
     pub clean_subject: String,
+    /// Root message id when the thread was looked up by `thread_id`.
+    pub thread_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MailExportQuery {
+    /// Same syntax as search; empty exports everything.
+    pub query: Option<String>,
+    /// Case-insensitive substring of the From header.
+    pub from: Option<String>,
+    /// `YYYY-MM-DD` (or `YYYY-MM`, `YYYY`), inclusive.
+    pub after: Option<String>,
+    /// `YYYY-MM-DD` (or `YYYY-MM`, `YYYY`), exclusive.
+    pub before: Option<String>,
+    /// Continue after this message; the value of the previous export's `X-Next-After-Id`.
+    pub after_id: Option<i32>,
+    /// Messages in this file, at most (and by default) [`super::service::MAX_EXPORT_MESSAGES`].
+    pub limit: Option<i64>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -49,9 +72,12 @@ pub struct SpamVoteResponse {
     pub user_voted: bool,
 }
 
-/// Summary of a mail thread (all messages with same cleaned_subject) for listing.
+/// Summary of a mail thread (one reply tree) for listing.
 #[derive(Debug)]
 pub struct MailThreadSummary {
+    /// Root message id.
+    pub thread_id: i32,
+    /// Cleaned subject of the root message.
     pub cleaned_subject: String,
     pub subject: Option<String>,
     pub from_address: Option<String>,
@@ -0,0 +1,440 @@
+//! Rebuilds RFC 5322 messages from the stored headers and `parts_json`, for mbox and EML export.
+//!
+//! The original files are not needed: text parts (stored decoded as UTF-8) are re-encoded as
+//! quoted-printable, binary parts (stored as base64) are written back as base64 attachments with
+//! their filename and Content-ID. Multipart containers in `parts_json` are skipped and the tree is
+//! rebuilt as `multipart/mixed`, with `multipart/alternative` for a plain + HTML body.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+
+use super::threading::parse_message_ids;
+
+const LINE_LIMIT: usize = 76;
+
+pub struct StoredMessage {
+    pub id: i32,
+    pub message_id: Option<String>,
+    pub sent_at: DateTime<Utc>,
+    pub subject: Option<String>,
+    pub from_address: Option<String>,
+    pub to_address: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    pub parts: Option<serde_json::Value>,
+}
+
+struct Part {
+    mime_type: String,
+    content: String,
+    is_base64: bool,
+    filename: String,
+    content_id: Option<String>,
+}
+
+impl Part {
+    fn is_body_text(&self) -> bool {
+        self.filename.is_empty()
+            && self.content_id.is_none()
+            && (self.mime_type == "text/plain" || self.mime_type == "text/html")
+    }
+}
+
+fn leaf_parts(parts: Option<&serde_json::Value>) -> Vec<Part> {
+    let Some(parts) = parts.and_then(|p| p.as_array()) else {
+        return Vec::new();
+    };
+    parts
+        .iter()
+        .filter_map(|part| {
+            let mime_type = part.get("mime_type")?.as_str()?.to_lowercase();
+            if mime_type.starts_with("multipart/") {
+                return None;
+            }
+            Some(Part {
+                content: part.get("content")?.as_str()?.to_string(),
+                is_base64: part
+                    .get("is_base64")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(!mime_type.starts_with("text/")),
+                filename: part
+                    .get("filename")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                content_id: part
+                    .get("content_id")
+                    .and_then(|v| v.as_str())
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string),
+                mime_type,
+            })
+        })
+        .collect()
+}
+
+/// Header values come from the database; never let them start a new header line.
+fn single_line(value: &str) -> String {
+    value
+        .split(['\r', '\n'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// RFC 2047 encoded words for non-ASCII header text.
+fn encode_words(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in value.chars() {
+        if chunk.len() + c.len_utf8() > 45 {
+            words.push(format!("=?UTF-8?B?{}?=", STANDARD.encode(&chunk)));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    if !chunk.is_empty() {
+        words.push(format!("=?UTF-8?B?{}?=", STANDARD.encode(&chunk)));
+    }
+    words.join("\r\n ")
+}
+
+/// `Name <addr>` with only the display name encoded, so the address stays parseable.
+fn encode_address(value: &str) -> String {
+    match value.rfind('<') {
+        Some(i) if !value.is_ascii() && value[i..].is_ascii() => {
+            let name = value[..i].trim().trim_matches('"');
+            format!("{} {}", encode_words(name), &value[i..])
+        }
+        _ => encode_words(value),
+    }
+}
+
+/// Bare address for the mbox `From ` line.
+fn envelope_sender(from: Option<&str>) -> String {
+    let from = from.unwrap_or_default();
+    let address = match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from
+            .split_whitespace()
+            .find(|w| w.contains('@'))
+            .unwrap_or_default(),
+    };
+    if address.is_empty() || address.contains(char::is_whitespace) {
+        "MAILER-DAEMON".to_string()
+    } else {
+        address.to_string()
+    }
+}
+
+/// `name="value"`, or the RFC 2231 `name*=UTF-8''...` form for non-ASCII values.
+fn parameter(name: &str, value: &str) -> String {
+    if value.is_ascii() {
+        format!(
+            "{}=\"{}\"",
+            name,
+            single_line(value)
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+        )
+    } else {
+        let encoded: String = value
+            .bytes()
+            .map(|b| {
+                if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+                    (b as char).to_string()
+                } else {
+                    format!("%{:02X}", b)
+                }
+            })
+            .collect();
+        format!("{}*=UTF-8''{}", name, encoded)
+    }
+}
+
+fn quoted_printable(text: &str) -> String {
+    let mut out = String::new();
+    let mut lines = text.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let bytes = line.as_bytes();
+        let mut width = 0;
+        for (i, &b) in bytes.iter().enumerate() {
+            let last = i + 1 == bytes.len();
+            let token = match b {
+                b'=' => "=3D".to_string(),
+                b' ' | b'\t' if last => format!("={:02X}", b),
+                b' ' | b'\t' | 33..=126 => (b as char).to_string(),
+                _ => format!("={:02X}", b),
+            };
+            if width + token.len() > LINE_LIMIT - 1 {
+                out.push_str("=\r\n");
+                width = 0;
+            }
+            width += token.len();
+            out.push_str(&token);
+        }
+        if lines.peek().is_some() {
+            out.push_str("\r\n");
+        }
+    }
+    out
+}
+
+fn wrap_base64(encoded: &str) -> String {
+    let clean: String = encoded.chars().filter(|c| !c.is_whitespace()).collect();
+    clean
+        .as_bytes()
+        .chunks(LINE_LIMIT)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+fn render_part(part: &Part) -> String {
+    let mut headers = Vec::new();
+    let body = if part.is_base64 {
+        let mut content_type = part.mime_type.clone();
+        if !part.filename.is_empty() {
+            content_type.push_str(&format!("; {}", parameter("name", &part.filename)));
+        }
+        headers.push(format!("Content-Type: {}", content_type));
+        headers.push("Content-Transfer-Encoding: base64".to_string());
+        wrap_base64(&part.content)
+    } else {
+        headers.push(format!(
+            "Content-Type: {}; charset=\"utf-8\"",
+            part.mime_type
+        ));
+        headers.push("Content-Transfer-Encoding: quoted-printable".to_string());
+        quoted_printable(&part.content)
+    };
+    if !part.filename.is_empty() {
+        headers.push(format!(
+            "Content-Disposition: attachment; {}",
+            parameter("filename", &part.filename)
+        ));
+    } else if part.content_id.is_some() {
+        headers.push("Content-Disposition: inline".to_string());
+    }
+    if let Some(cid) = &part.content_id {
+        headers.push(format!("Content-ID: <{}>", single_line(cid)));
+    }
+    format!("{}\r\n\r\n{}", headers.join("\r\n"), body)
+}
+
+/// Boundaries contain `=_`, which neither base64 nor quoted-printable output can.
+fn multipart(subtype: &str, boundary: &str, entities: &[String]) -> String {
+    let mut out = format!(
+        "Content-Type: multipart/{}; boundary=\"{}\"\r\n\r\n",
+        subtype, boundary
+    );
+    for entity in entities {
+        out.push_str(&format!("--{}\r\n{}\r\n", boundary, entity));
+    }
+    out.push_str(&format!("--{}--", boundary));
+    out
+}
+
+fn render_body(message: &StoredMessage) -> String {
+    let parts = leaf_parts(message.parts.as_ref());
+    let (texts, attachments): (Vec<&Part>, Vec<&Part>) =
+        parts.iter().partition(|p| p.is_body_text());
+
+    let mut entities = Vec::new();
+    let plain: Vec<&&Part> = texts
+        .iter()
+        .filter(|p| p.mime_type == "text/plain")
+        .collect();
+    let html: Vec<&&Part> = texts
+        .iter()
+        .filter(|p| p.mime_type == "text/html")
+        .collect();
+    if plain.len() == 1 && html.len() == 1 {
+        entities.push(multipart(
+            "alternative",
+            &format!("=_lensisku_alt_{}", message.id),
+            &[render_part(plain[0]), render_part(html[0])],
+        ));
+    } else {
+        entities.extend(texts.iter().map(|p| render_part(p)));
+    }
+    entities.extend(attachments.iter().map(|p| render_part(p)));
+
+    match entities.len() {
+        0 => render_part(&Part {
+            mime_type: "text/plain".to_string(),
+            content: String::new(),
+            is_base64: false,
+            filename: String::new(),
+            content_id: None,
+        }),
+        1 => entities.remove(0),
+        _ => multipart(
+            "mixed",
+            &format!("=_lensisku_mixed_{}", message.id),
+            &entities,
+        ),
+    }
+}
+
+fn message_id_header(value: &str) -> Option<String> {
+    parse_message_ids(value)
+        .into_iter()
+        .next()
+        .map(|id| format!("<{}>", single_line(&id)))
+}
+
+/// The message as an RFC 5322 document with CRLF line endings.
+pub fn build_eml(message: &StoredMessage) -> Vec<u8> {
+    let mut headers = vec![format!("Date: {}", message.sent_at.to_rfc2822())];
+    if let Some(from) = message.from_address.as_deref().filter(|v| !v.is_empty()) {
+        headers.push(format!("From: {}", encode_address(&single_line(from))));
+    }
+    if let Some(to) = message.to_address.as_deref().filter(|v| !v.is_empty()) {
+        headers.push(format!("To: {}", encode_address(&single_line(to))));
+    }
+    if let Some(subject) = message.subject.as_deref() {
+        headers.push(format!("Subject: {}", encode_words(&single_line(subject))));
+    }
+    if let Some(id) = message.message_id.as_deref().and_then(message_id_header) {
+        headers.push(format!("Message-ID: {}", id));
+    }
+    if let Some(id) = message.in_reply_to.as_deref().and_then(message_id_header) {
+        headers.push(format!("In-Reply-To: {}", id));
+    }
+    let references: Vec<String> = message
+        .references
+        .iter()
+        .filter_map(|r| message_id_header(r))
+        .collect();
+    if !references.is_empty() {
+        headers.push(format!("References: {}", references.join("\r\n ")));
+    }
+    headers.push("MIME-Version: 1.0".to_string());
+
+    format!("{}\r\n{}\r\n", headers.join("\r\n"), render_body(message)).into_bytes()
+}
+
+/// Appends one message in mboxrd format (LF line endings, `>`-escaped `From ` lines).
+pub fn append_mbox(out: &mut Vec<u8>, message: &StoredMessage, eml: &[u8]) {
+    out.extend_from_slice(
+        format!(
+            "From {} {}\n",
+            envelope_sender(message.from_address.as_deref()),
+            message.sent_at.format("%a %b %e %H:%M:%S %Y")
+        )
+        .as_bytes(),
+    );
+    for line in eml.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let quoted = line.iter().take_while(|b| **b == b'>').count();
+        if line[quoted..].starts_with(b"From ") {
+            out.push(b'>');
+        }
+        out.extend_from_slice(line);
+        out.push(b'\n');
+    }
+}
+
+/// Zip entry name for one message of an exported thread.
+pub fn eml_filename(index: usize, message: &StoredMessage) -> String {
+    format!("{:03}-{}.eml", index + 1, message.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mailarchive::sources::mbox::MboxReader;
+    use chrono::TimeZone;
+    use mailparse::MailHeaderMap;
+    use std::io::Cursor;
+
+    fn stored(id: i32) -> StoredMessage {
+        StoredMessage {
+            id,
+            message_id: Some("<a@lojban.org>".to_string()),
+            sent_at: Utc.with_ymd_and_hms(2003, 5, 1, 10, 0, 0).unwrap(),
+            subject: Some("Re: klama – ko'a".to_string()),
+            from_address: Some("Djan Kovan <cowan@example.org>".to_string()),
+            to_address: Some("lojban@lojban.org".to_string()),
+            in_reply_to: Some("parent@lojban.org".to_string()),
+            references: vec![
+                "root@lojban.org".to_string(),
+                "parent@lojban.org".to_string(),
+            ],
+            parts: Some(serde_json::json!([
+                {"mime_type": "multipart/mixed", "content": "xx", "is_base64": true, "filename": ""},
+                {"mime_type": "text/plain", "content": "coi\nFrom the list = ok", "is_base64": false, "filename": ""},
+                {"mime_type": "image/png", "content": STANDARD.encode([0u8, 1, 2, 255]), "is_base64": true,
+                 "filename": "pa.png", "content_id": null}
+            ])),
+        }
+    }
+
+    #[test]
+    fn rebuilt_message_keeps_headers_and_attachments() {
+        let eml = build_eml(&stored(7));
+        let parsed = mailparse::parse_mail(&eml).unwrap();
+        assert_eq!(
+            parsed.headers.get_first_value("Subject").as_deref(),
+            Some("Re: klama – ko'a")
+        );
+        assert_eq!(
+            parse_message_ids(&parsed.headers.get_first_value("References").unwrap()),
+            vec!["root@lojban.org", "parent@lojban.org"]
+        );
+        assert_eq!(parsed.ctype.mimetype, "multipart/mixed");
+        assert_eq!(parsed.subparts.len(), 2);
+        assert_eq!(
+            parsed.subparts[0].get_body().unwrap(),
+            "coi\r\nFrom the list = ok"
+        );
+        assert_eq!(
+            parsed.subparts[1].get_body_raw().unwrap(),
+            vec![0u8, 1, 2, 255]
+        );
+        assert_eq!(
+            parsed.subparts[1]
+                .get_content_disposition()
+                .params
+                .get("filename")
+                .map(String::as_str),
+            Some("pa.png")
+        );
+    }
+
+    #[test]
+    fn quoted_printable_escapes_and_wraps() {
+        assert_eq!(quoted_printable("a = b \r\nc\t"), "a =3D b=20\r\nc=09");
+        let long = quoted_printable(&"x".repeat(100));
+        assert!(long.split("\r\n").all(|line| line.len() <= LINE_LIMIT));
+    }
+
+    #[test]
+    fn mbox_export_reads_back() {
+        let mut mbox = Vec::new();
+        for id in [1, 2] {
+            let message = stored(id);
+            append_mbox(&mut mbox, &message, &build_eml(&message));
+        }
+        assert!(mbox.starts_with(b"From cowan@example.org Thu May  1 10:00:00 2003\n"));
+
+        let mut reader = MboxReader::new(Cursor::new(mbox), 0);
+        let mut count = 0;
+        while let Some((_, raw)) = reader.next_message().unwrap() {
+            let parsed = mailparse::parse_mail(&raw).unwrap();
+            assert_eq!(
+                parsed.subparts[0].get_body().unwrap(),
+                "coi\nFrom the list = ok"
+            );
+            count += 1;
+        }
+        assert_eq!(count, 2);
+    }
+}
@@ -1,18 +1,24 @@
 pub mod controller;
 pub mod dto;
+pub mod mime;
 pub mod models;
+pub mod query;
 pub mod service;
+pub mod sources;
+pub mod threading;
 
 use actix_web::web;
 pub use dto::*;
 pub use models::Message;
-pub use service::{check_for_new_emails, import_maildir};
+pub use service::import_maildir;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("mail")
             .service(controller::get_message)
             .service(controller::show_thread)
+            .service(controller::export_mbox)
+            .service(controller::export_thread)
             .service(controller::vote_spam_message),
     );
 }
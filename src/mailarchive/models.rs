@@ -17,6 +17,13 @@ pub struct Message {
     pub file_path: Option<String>,
     pub spam_vote_count: i64,
     pub current_user_voted_spam: Option<bool>,
+    /// Message this one replies to, when it is in the archive.
+    pub parent_id: Option<i32>,
+    /// Root message of the reply tree.
+    pub thread_id: Option<i32>,
+    /// Search hit excerpt, HTML-escaped with matched terms wrapped in `<mark>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
 }
 
 impl From<Row> for Message {
@@ -38,6 +45,9 @@ impl From<Row> for Message {
             file_path,
             spam_vote_count: row.try_get("spam_vote_count").unwrap_or(0),
             current_user_voted_spam: row.try_get("current_user_voted_spam").ok(),
+            parent_id: row.try_get("parent_id").unwrap_or_default(),
+            thread_id: row.try_get("thread_id").unwrap_or_default(),
+            snippet: None,
         }
     }
 }
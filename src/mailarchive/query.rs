@@ -0,0 +1,143 @@
+//! Search syntax for the mail archive.
+//!
+//! Free text, `"quoted phrases"`, `-excluded` words/phrases and `OR` are passed through to
+//! Postgres `websearch_to_tsquery`. The `from:`, `before:` and `after:` operators are pulled
+//! out here and applied as plain SQL filters:
+//!
+//! - `from:alice` / `from:"Alice Smith"` — case-insensitive substring of the From header
+//! - `before:2004-06-01` — sent before that day (UTC); `YYYY-MM` and `YYYY` are also accepted
+//! - `after:2004` — sent on or after that day (UTC)
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// Marks around highlighted terms in `ts_headline` output. Private-use code points never appear
+/// in archived mail, so the snippet can be HTML-escaped before they are turned into `<mark>`.
+pub const HIGHLIGHT_START: &str = "\u{E000}";
+pub const HIGHLIGHT_STOP: &str = "\u{E001}";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MailSearchFilter {
+    /// Remaining text for `websearch_to_tsquery`; empty when only operators were given.
+    pub text: String,
+    pub from: Option<String>,
+    pub before: Option<DateTime<Utc>>,
+    pub after: Option<DateTime<Utc>>,
+}
+
+impl MailSearchFilter {
+    /// `ILIKE` pattern for the `from:` operator.
+    pub fn from_pattern(&self) -> Option<String> {
+        self.from.as_ref().map(|f| {
+            let escaped = f
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        })
+    }
+}
+
+/// Splits on whitespace, keeping double-quoted runs (including any `-` or `key:` prefix) intact.
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+pub fn parse_day(value: &str) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{}-01", value), "%Y-%m-%d"))
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{}-01-01", value), "%Y-%m-%d"))
+        .ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+/// Parses a search box query. Operators with unusable values are kept as ordinary search text.
+pub fn parse_search_query(input: &str) -> MailSearchFilter {
+    let mut filter = MailSearchFilter::default();
+    let mut text_tokens: Vec<String> = Vec::new();
+
+    for token in tokenize(input) {
+        let Some((key, value)) = token.split_once(':') else {
+            text_tokens.push(token);
+            continue;
+        };
+        let value = value.trim_matches('"').trim();
+        match key.to_ascii_lowercase().as_str() {
+            "from" if !value.is_empty() => filter.from = Some(value.to_string()),
+            "before" => match parse_day(value) {
+                Some(day) => filter.before = Some(day),
+                None => text_tokens.push(token),
+            },
+            "after" => match parse_day(value) {
+                Some(day) => filter.after = Some(day),
+                None => text_tokens.push(token),
+            },
+            _ => text_tokens.push(token),
+        }
+    }
+
+    filter.text = text_tokens.join(" ");
+    filter
+}
+
+/// HTML-escapes a `ts_headline` snippet and turns the highlight markers into `<mark>` tags.
+pub fn render_snippet(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace(HIGHLIGHT_START, "<mark>")
+        .replace(HIGHLIGHT_STOP, "</mark>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_are_extracted_and_text_kept() {
+        let f = parse_search_query(
+            r#"gismu "place structure" -lujvo from:"John Cowan" after:1998 before:2001-06-01"#,
+        );
+        assert_eq!(f.text, r#"gismu "place structure" -lujvo"#);
+        assert_eq!(f.from.as_deref(), Some("John Cowan"));
+        assert_eq!(f.after, parse_day("1998-01-01"));
+        assert_eq!(f.before, parse_day("2001-06-01"));
+        assert_eq!(f.from_pattern().as_deref(), Some("%John Cowan%"));
+    }
+
+    #[test]
+    fn invalid_operator_values_stay_in_text() {
+        let f = parse_search_query("before:someday http://lojban.org");
+        assert_eq!(f.before, None);
+        assert_eq!(f.text, "before:someday http://lojban.org");
+    }
+
+    #[test]
+    fn snippets_are_escaped_before_marking() {
+        let raw = format!("a <b> {}klama{} & co", HIGHLIGHT_START, HIGHLIGHT_STOP);
+        assert_eq!(
+            render_snippet(&raw),
+            "a &lt;b&gt; <mark>klama</mark> &amp; co"
+        );
+    }
+}
@@ -1,19 +1,31 @@
 #![allow(clippy::expect_used)]
 
 use crate::mailarchive::{
-    dto::MailThreadSummary, Message, SearchQuery, SearchResponse, ThreadQuery, ThreadResponse,
+    dto::MailThreadSummary,
+    mime::{append_mbox, build_eml, eml_filename, StoredMessage},
+    query::{
+        parse_search_query, render_snippet, MailSearchFilter, HIGHLIGHT_START, HIGHLIGHT_STOP,
+    },
+    threading::{
+        build_threads, parse_message_ids, ThreadInput, ThreadPlacement,
+        SUBJECT_FALLBACK_WINDOW_DAYS,
+    },
+    Message, SearchQuery, SearchResponse, ThreadQuery, ThreadResponse,
 };
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine as _;
 use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
 use deadpool_postgres::Pool;
 use encoding_rs::{GB18030, KOI8_R, WINDOWS_1252};
-use mailparse::{parse_mail, MailHeaderMap};
-use std::collections::HashSet;
+use mailparse::{parse_mail, MailHeader, MailHeaderMap};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Cursor, Write};
 use std::path::Path;
-use std::{fs, path::PathBuf};
-use tokio_postgres::Client;
+use tokio::sync::mpsc;
+use tokio_postgres::{Client, Row};
 use walkdir::WalkDir;
+use zip::write::{SimpleFileOptions, ZipWriter};
 
 use log::{error, info, warn};
 use regex::Regex;
@@ -30,15 +42,30 @@ static DAY_OF_WEEK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
 
 const BATCH_SIZE: usize = 1000;
 const BATCH_DELAY: Duration = Duration::from_millis(100);
-
-fn escape_like<S: AsRef<str>>(s: S) -> String {
-    s.as_ref()
-        .replace("\\", "\\\\")
-        .replace("%", "\\%")
-        .replace("_", "\\_")
-        .replace("'", "''")
-}
-
+/// Messages rendered per chunk of a streamed mbox export.
+const EXPORT_PAGE_SIZE: i64 = 200;
+/// Messages in one mbox export at most; the rest is exported by following `after_id`.
+pub const MAX_EXPORT_MESSAGES: i64 = 5000;
+/// Messages matching an export filter: $1 to $4 are the search text, `from:` pattern, `before`
+/// and `after`, and $5 the id of the last message already exported, if any. Keyset pagination
+/// on (sent_at, id), which is also the export order.
+const EXPORT_FILTER: &str = "WITH q AS (SELECT websearch_to_tsquery('english', $1::text) AS query)
+         SELECT {columns}
+         FROM messages m, q
+         WHERE (numnode(q.query) = 0 OR m.search_vector @@ q.query)
+           AND ($2::text IS NULL OR m.from_address ILIKE $2)
+           AND ($3::timestamptz IS NULL OR m.sent_at < $3)
+           AND ($4::timestamptz IS NULL OR m.sent_at >= $4)
+           AND ($5::int IS NULL
+                OR (m.sent_at, m.id) > (SELECT p.sent_at, p.id FROM messages p WHERE p.id = $5))
+         ORDER BY m.sent_at, m.id";
+const EXPORT_COLUMNS: &str =
+    "m.id, m.message_id, m.sent_at, m.subject, m.from_address, m.to_address,
+     m.in_reply_to, m.reference_ids, m.parts_json";
+
+/// Full-text search over subject and decoded body text (`messages.search_vector`), with the
+/// operators described in [`super::query`]. Results carry a highlighted `snippet` when the query
+/// has search terms.
 pub async fn search_messages(
     pool: &Pool,
     query: SearchQuery,
@@ -50,43 +77,22 @@ pub async fn search_messages(
     let per_page = query.per_page.unwrap_or(10);
     let offset = (page - 1) * per_page;
     let group_by_thread = query.group_by_thread.unwrap_or(false);
-    // Split query into words and remove empty ones
-    let words: Vec<String> = query
-        .query
-        .split_whitespace()
-        .map(|w| w.to_string())
-        .collect();
 
-    let exact_query = format!("%{}%", escape_like(&query.query));
-    let word_conditions_sql_parts: Vec<String> = words
-        .iter()
-        .map(|word| {
-            let escaped_word = escape_like(word);
-            format!(
-                "(m.subject ILIKE '%{}%' OR m.content ILIKE '%{}%')",
-                escaped_word, escaped_word
-            )
-        })
-        .collect();
-
-    let main_where_conditions_sql = if word_conditions_sql_parts.is_empty() {
-        "TRUE".to_string()
-    } else {
-        word_conditions_sql_parts.join(" AND ")
-    };
-
-    let rank_word_conditions_sql = if word_conditions_sql_parts.is_empty() {
-        "FALSE".to_string() // Ensures this part of rank is 0 if no specific words
-    } else {
-        word_conditions_sql_parts.join(" AND ")
-    };
+    let filter = parse_search_query(&query.query);
+    let from_pattern = filter.from_pattern();
+    let headline_options = format!(
+        "StartSel=\"{}\", StopSel=\"{}\", MaxFragments=2, MaxWords=35, MinWords=15, FragmentDelimiter=\" … \"",
+        HIGHLIGHT_START, HIGHLIGHT_STOP
+    );
 
     // Validate sort_by against allowed fields for the outer query
-    let outer_sort_column_name = match query.sort_by.as_deref() {
-        Some("date") => "date", // Refers to date/sent_at of the representative message
-        Some("subject") => "subject", // Refers to cleaned_subject of the representative message
-        Some("sent_at") => "sent_at", // Refers to sent_at of the representative message
-        _ => "rank",            // Default to rank of the representative message
+    let sort_column_name = match query.sort_by.as_deref() {
+        Some("date") => "date",
+        // Grouped results show the cleaned subject, so sort by that.
+        Some("subject") if group_by_thread => "cleaned_subject",
+        Some("subject") => "subject",
+        Some("sent_at") => "sent_at",
+        _ => "rank",
     };
 
     let sort_order = match query.sort_order.as_deref() {
@@ -95,92 +101,82 @@ pub async fn search_messages(
     };
     let include_content = query.include_content.unwrap_or(true);
     let content_select = if include_content {
-        "parts_json"
+        "p.parts_json"
     } else {
         "NULL::jsonb as parts_json"
     };
 
-    let query_string;
-    let count_query_string;
-
-    if group_by_thread {
-        query_string = format!(
-            "WITH thread_representatives AS (
-                SELECT DISTINCT ON (m.cleaned_subject)
-                       m.id, m.message_id, m.date, m.cleaned_subject, m.from_address, m.to_address, m.parts_json, m.sent_at,
-                       (SELECT COUNT(*) FROM message_spam_votes msv WHERE msv.message_id = m.id) as spam_vote_count,
-                       (CASE
-                          WHEN m.subject ILIKE $1 THEN 3
-                          WHEN m.content ILIKE $1 THEN 2
-                          WHEN {} THEN 1
-                          ELSE 0
-                        END) as rank
-                FROM messages m
-                WHERE {}
-                ORDER BY m.cleaned_subject,
-                         (CASE WHEN m.subject ILIKE $1 THEN 3 WHEN m.content ILIKE $1 THEN 2 WHEN {} THEN 1 ELSE 0 END) DESC,
-                         m.sent_at DESC NULLS LAST, m.date DESC NULLS LAST
-            )
-            SELECT id, message_id, date, cleaned_subject as subject, from_address, to_address, 
-                   CASE WHEN {} THEN parts_json ELSE NULL::jsonb END as parts_json,
-                   spam_vote_count, rank, sent_at
-            FROM thread_representatives
-            ORDER BY {} {}, date {}
-            LIMIT $2 OFFSET $3",
-            rank_word_conditions_sql, // for rank in CTE
-            main_where_conditions_sql,  // for WHERE in CTE
-            rank_word_conditions_sql, // for ORDER BY in CTE (rank part)
-            if include_content { "TRUE" } else { "FALSE" }, // for parts_json selection
-            outer_sort_column_name,
-            sort_order,
-            sort_order
-        );
-        count_query_string = format!(
-            "SELECT COUNT(*) FROM (
-                SELECT DISTINCT ON (m.cleaned_subject) 1
-                FROM messages m
-                WHERE {}
-            ) AS distinct_threads",
-            main_where_conditions_sql
-        );
+    // $1 search text, $2 from: pattern, $3 before:, $4 after:
+    let matches_cte = "q AS (SELECT websearch_to_tsquery('english', $1::text) AS query),
+        matches AS (
+            SELECT m.id, m.message_id, m.date, m.subject, m.cleaned_subject, m.from_address,
+                   m.to_address, m.parts_json, m.sent_at, m.parent_id, m.thread_id,
+                   CASE WHEN numnode(q.query) = 0 THEN 0
+                        ELSE ts_rank_cd(m.search_vector, q.query, 32) END AS rank
+            FROM messages m, q
+            WHERE (numnode(q.query) = 0 OR m.search_vector @@ q.query)
+              AND ($2::text IS NULL OR m.from_address ILIKE $2)
+              AND ($3::timestamptz IS NULL OR m.sent_at < $3)
+              AND ($4::timestamptz IS NULL OR m.sent_at >= $4)
+        )";
+
+    // One representative per thread: its best-ranked, then most recent, message.
+    let (candidates, subject_select) = if group_by_thread {
+        (
+            "(SELECT DISTINCT ON (COALESCE(thread_id, id)) * FROM matches
+              ORDER BY COALESCE(thread_id, id), rank DESC, sent_at DESC NULLS LAST, date DESC NULLS LAST)",
+            "p.cleaned_subject as subject",
+        )
     } else {
-        query_string = format!(
-            "SELECT m.id, m.message_id, m.date, m.subject, m.cleaned_subject, m.from_address, m.to_address, {}, m.sent_at,
-             (SELECT COUNT(*) FROM message_spam_votes msv WHERE msv.message_id = m.id) as spam_vote_count,
-             (CASE
-                WHEN m.subject ILIKE $1 THEN 3
-                WHEN m.content ILIKE $1 THEN 2
-                WHEN {} THEN 1
-                ELSE 0
-              END) as rank
-             FROM messages m
-             WHERE {}
-             ORDER BY {} {}, m.date {}
-             LIMIT $2 OFFSET $3",
-            content_select,
-            rank_word_conditions_sql,
-            main_where_conditions_sql,
-            outer_sort_column_name, // Here m. prefix might be needed if not aliasing in CTE
-            sort_order,
-            sort_order
-        );
-        count_query_string = format!(
-            "SELECT COUNT(*) FROM messages m WHERE {}",
-            main_where_conditions_sql
-        );
-    }
+        ("matches", "p.subject")
+    };
+
+    // Headlines are only computed for the rows on the requested page.
+    let query_string = format!(
+        "WITH {matches_cte},
+        page AS (
+            SELECT * FROM {candidates} c
+            ORDER BY {sort_column_name} {sort_order}, date {sort_order}
+            LIMIT $5 OFFSET $6
+        )
+        SELECT p.id, p.message_id, p.date, {subject_select}, p.cleaned_subject, p.from_address,
+               p.to_address, {content_select}, p.sent_at, p.rank, p.parent_id, p.thread_id,
+               (SELECT COUNT(*) FROM message_spam_votes msv WHERE msv.message_id = p.id) as spam_vote_count,
+               CASE WHEN numnode(q.query) = 0 THEN NULL
+                    ELSE ts_headline('english', LEFT(mail_parts_text(p.parts_json), 500000), q.query, $7)
+               END AS snippet
+        FROM page p, q
+        ORDER BY p.{sort_column_name} {sort_order}, p.date {sort_order}"
+    );
+    let count_query_string = format!("WITH {matches_cte} SELECT COUNT(*) FROM {candidates} c");
 
     let messages = transaction
-        .query(&query_string, &[&exact_query, &per_page, &offset])
+        .query(
+            &query_string,
+            &[
+                &filter.text,
+                &from_pattern,
+                &filter.before,
+                &filter.after,
+                &per_page,
+                &offset,
+                &headline_options,
+            ],
+        )
         .await?
         .into_iter()
-        .map(Message::from)
+        .map(|row| {
+            let snippet: Option<String> = row.try_get("snippet").ok().flatten();
+            let mut message = Message::from(row);
+            message.snippet = snippet.map(|s| render_snippet(&s));
+            message
+        })
         .collect::<Vec<_>>();
 
     let total: i64 = transaction
         .query_one(
             &count_query_string,
-            &[] as &[&(dyn tokio_postgres::types::ToSql + Sync)],
+            &[&filter.text, &from_pattern, &filter.before, &filter.after],
         )
         .await?
         .get(0);
@@ -217,6 +213,9 @@ pub async fn get_message(
     Ok(message)
 }
 
+/// Messages of one thread. With `thread_id` the reply tree containing that message is returned
+/// (each message carries its `parent_id`); with only `subject` messages are grouped by cleaned
+/// subject as before threading existed.
 pub async fn show_thread(
     pool: &Pool,
     query: ThreadQuery,
@@ -238,32 +237,70 @@ pub async fn show_thread(
         Some("sent_at") => "m.sent_at",
         _ => "m.date", // Default to "m.date" (which implies m.sent_at or m.date from DB)
     };
-    // Remove common prefixes and tags from the subject and escape special characters
-    let clean_subject = remove_prefixes(&query.subject);
-
-    let query_string = if include_content {
-        format!(
-            "SELECT m.id, m.message_id, m.date, m.subject, m.from_address, m.to_address, m.parts_json,
-             (SELECT COUNT(*) FROM message_spam_votes msv WHERE msv.message_id = m.id) as spam_vote_count
-             FROM messages m
-             WHERE m.cleaned_subject = $1
-             ORDER BY {} {}, date {}
-             LIMIT $2 OFFSET $3",
-            sort_column, sort_order, sort_order
-        )
+
+    let root = match query.thread_id {
+        Some(id) => transaction
+            .query_opt(
+                "SELECT COALESCE(thread_id, id) AS root_id, cleaned_subject FROM messages WHERE id = $1",
+                &[&id],
+            )
+            .await?
+            .map(|row| {
+                (
+                    row.get::<_, i32>("root_id"),
+                    row.get::<_, Option<String>>("cleaned_subject"),
+                )
+            }),
+        None => None,
+    };
+
+    // Roots have thread_id = id; rows not threaded yet only match themselves.
+    let (where_clause, clean_subject, thread_id) = match (root, query.thread_id) {
+        (Some((root_id, cleaned_subject)), _) => (
+            "(m.thread_id = $1 OR m.id = $1)",
+            cleaned_subject.unwrap_or_default(),
+            Some(root_id),
+        ),
+        (None, Some(_)) => {
+            transaction.commit().await?;
+            return Ok(ThreadResponse {
+                messages: Vec::new(),
+                total: 0,
+                page,
+                per_page,
+                clean_subject: String::new(),
+                thread_id: None,
+            });
+        }
+        // Remove common prefixes and tags from the subject and escape special characters
+        (None, None) => (
+            "m.cleaned_subject = $1",
+            remove_prefixes(query.subject.as_deref().unwrap_or_default()),
+            None,
+        ),
+    };
+    let key: &(dyn tokio_postgres::types::ToSql + Sync) = match &thread_id {
+        Some(root_id) => root_id,
+        None => &clean_subject,
+    };
+
+    let content_select = if include_content {
+        "m.parts_json"
     } else {
-        format!(
-            "SELECT m.id, m.message_id, m.date, m.subject, m.from_address, m.to_address, NULL::jsonb as parts_json,
-             (SELECT COUNT(*) FROM message_spam_votes msv WHERE msv.message_id = m.id) as spam_vote_count
-             FROM messages m
-             WHERE m.cleaned_subject = $1
-             ORDER BY {} {}, date {}
-             LIMIT $2 OFFSET $3",
-            sort_column, sort_order, sort_order
-        )
+        "NULL::jsonb as parts_json"
     };
+    let query_string = format!(
+        "SELECT m.id, m.message_id, m.date, m.subject, m.cleaned_subject, m.from_address, m.to_address,
+         {}, m.parent_id, m.thread_id,
+         (SELECT COUNT(*) FROM message_spam_votes msv WHERE msv.message_id = m.id) as spam_vote_count
+         FROM messages m
+         WHERE {}
+         ORDER BY {} {}, date {}
+         LIMIT $2 OFFSET $3",
+        content_select, where_clause, sort_column, sort_order, sort_order
+    );
     let messages = transaction
-        .query(&query_string, &[&clean_subject, &per_page, &offset])
+        .query(&query_string, &[key, &per_page, &offset])
         .await?
         .into_iter()
         .map(Message::from)
@@ -271,8 +308,8 @@ pub async fn show_thread(
 
     let total: i64 = transaction
         .query_one(
-            "SELECT COUNT(*) FROM messages WHERE cleaned_subject = $1",
-            &[&clean_subject],
+            &format!("SELECT COUNT(*) FROM messages m WHERE {}", where_clause),
+            &[key],
         )
         .await?
         .get(0);
@@ -286,10 +323,12 @@ pub async fn show_thread(
         page,
         per_page,
         clean_subject,
+        thread_id,
     })
 }
 
-/// List mail threads (grouped by cleaned_subject) for the waves unified list.
+/// List mail threads (reply trees, see [`super::threading`]) for the waves unified list.
+/// Messages not threaded yet are listed on their own until the next rebuild.
 pub async fn list_mail_threads(
     pool: &Pool,
     page: i64,
@@ -307,15 +346,15 @@ pub async fn list_mail_threads(
     };
 
     let order_expr = match sort_by {
-        "replies" | "comments" => "c.cnt",
+        "replies" | "comments" => "t.cnt",
         // Mail threads have no reaction counts; fall back to last activity.
-        "reactions" => "l.sent_at",
-        _ => "l.sent_at",
+        "reactions" => "t.last_sent_at",
+        _ => "t.last_sent_at",
     };
 
     let total: i64 = transaction
         .query_one(
-            "SELECT COUNT(DISTINCT cleaned_subject) FROM messages WHERE cleaned_subject IS NOT NULL AND cleaned_subject != ''",
+            "SELECT COUNT(*) FROM messages WHERE thread_id IS NULL OR thread_id = id",
             &[],
         )
         .await?
@@ -325,23 +364,23 @@ pub async fn list_mail_threads(
         .query(
             &format!(
                 r#"
-                WITH latest AS (
-                    SELECT DISTINCT ON (cleaned_subject)
-                        id, cleaned_subject, subject, from_address, sent_at,
-                        LEFT(content, 300) as content_preview
+                WITH threads AS (
+                    SELECT COALESCE(thread_id, id) AS tid, COUNT(*) AS cnt, MAX(sent_at) AS last_sent_at
                     FROM messages
-                    WHERE cleaned_subject IS NOT NULL AND cleaned_subject != ''
-                    ORDER BY cleaned_subject, sent_at DESC NULLS LAST
+                    GROUP BY COALESCE(thread_id, id)
                 ),
-                counts AS (
-                    SELECT cleaned_subject, COUNT(*) as cnt
+                latest AS (
+                    SELECT DISTINCT ON (COALESCE(thread_id, id))
+                        COALESCE(thread_id, id) AS tid, from_address,
+                        LEFT(content, 300) as content_preview
                     FROM messages
-                    WHERE cleaned_subject IS NOT NULL AND cleaned_subject != ''
-                    GROUP BY cleaned_subject
+                    ORDER BY COALESCE(thread_id, id), sent_at DESC NULLS LAST
                 )
-                SELECT l.cleaned_subject, l.subject, l.from_address, l.sent_at, c.cnt as message_count, l.content_preview
-                FROM latest l
-                JOIN counts c ON c.cleaned_subject = l.cleaned_subject
+                SELECT t.tid AS thread_id, r.cleaned_subject, r.subject, l.from_address,
+                       t.last_sent_at AS sent_at, t.cnt as message_count, l.content_preview
+                FROM threads t
+                JOIN messages r ON r.id = t.tid
+                JOIN latest l ON l.tid = t.tid
                 ORDER BY {} {} NULLS LAST
                 LIMIT $1 OFFSET $2
                 "#,
@@ -354,7 +393,10 @@ pub async fn list_mail_threads(
     let items: Vec<MailThreadSummary> = rows
         .iter()
         .map(|row| MailThreadSummary {
-            cleaned_subject: row.get("cleaned_subject"),
+            thread_id: row.get("thread_id"),
+            cleaned_subject: row
+                .get::<_, Option<String>>("cleaned_subject")
+                .unwrap_or_default(),
             subject: row.get("subject"),
             from_address: row.get("from_address"),
             last_sent_at: row.get("sent_at"),
@@ -367,6 +409,364 @@ pub async fn list_mail_threads(
     Ok((items, total))
 }
 
+/// `In-Reply-To` (first id) and `References` of a message, without angle brackets.
+fn reply_headers(headers: &[MailHeader]) -> (Option<String>, Vec<String>) {
+    let in_reply_to = headers
+        .get_first_value("In-Reply-To")
+        .and_then(|v| parse_message_ids(&v).into_iter().next());
+    let references = parse_message_ids(&headers.get_all_values("References").join(" "));
+    (in_reply_to, references)
+}
+
+/// Reads reply headers from the stored files of messages imported before threading existed.
+async fn backfill_thread_headers(
+    pool: &Pool,
+    maildir_path: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let client = pool.get().await?;
+    let mut updated = 0;
+    loop {
+        let rows = client
+            .query(
+                "SELECT id, file_path FROM messages WHERE NOT thread_headers_parsed ORDER BY id LIMIT $1",
+                &[&(BATCH_SIZE as i64)],
+            )
+            .await?;
+        if rows.is_empty() {
+            break;
+        }
+        for row in rows {
+            let id: i32 = row.get("id");
+            let file_path: Option<String> = row.get("file_path");
+            // Unreadable files are marked parsed without references so they are not retried.
+            let (in_reply_to, references) = file_path
+                .and_then(|p| fs::read(Path::new(maildir_path).join(p)).ok())
+                .and_then(|raw| {
+                    mailparse::parse_headers(&raw)
+                        .ok()
+                        .map(|(h, _)| reply_headers(&h))
+                })
+                .unwrap_or_default();
+            client
+                .execute(
+                    "UPDATE messages SET in_reply_to = $2, reference_ids = $3, thread_headers_parsed = TRUE
+                     WHERE id = $1",
+                    &[&id, &in_reply_to, &references],
+                )
+                .await?;
+            updated += 1;
+        }
+        sleep(BATCH_DELAY).await;
+    }
+    Ok(updated)
+}
+
+const THREAD_COLUMNS: &str =
+    "id, message_id, in_reply_to, reference_ids, subject, cleaned_subject, sent_at, parent_id, thread_id";
+
+/// Threads `rows` (selected with [`THREAD_COLUMNS`]) among themselves and stores the placements
+/// that changed. Returns the number of rows updated.
+async fn store_thread_placements(
+    client: &Client,
+    rows: &[Row],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut current: HashMap<i32, (Option<i32>, Option<i32>)> = HashMap::with_capacity(rows.len());
+    let inputs: Vec<ThreadInput> = rows
+        .iter()
+        .map(|row| {
+            let id: i32 = row.get("id");
+            current.insert(id, (row.get("parent_id"), row.get("thread_id")));
+            ThreadInput {
+                id,
+                message_id: row
+                    .get::<_, Option<String>>("message_id")
+                    .and_then(|v| parse_message_ids(&v).into_iter().next()),
+                in_reply_to: row.get("in_reply_to"),
+                references: row.get("reference_ids"),
+                subject: row.get("subject"),
+                cleaned_subject: row.get("cleaned_subject"),
+                sent_at: row.get("sent_at"),
+            }
+        })
+        .collect();
+
+    let changed: Vec<ThreadPlacement> = build_threads(&inputs)
+        .into_iter()
+        .filter(|p| current.get(&p.id) != Some(&(p.parent_id, Some(p.thread_id))))
+        .collect();
+
+    for chunk in changed.chunks(BATCH_SIZE) {
+        let ids: Vec<i32> = chunk.iter().map(|p| p.id).collect();
+        let parent_ids: Vec<Option<i32>> = chunk.iter().map(|p| p.parent_id).collect();
+        let thread_ids: Vec<i32> = chunk.iter().map(|p| p.thread_id).collect();
+        client
+            .execute(
+                "UPDATE messages m SET parent_id = u.parent_id, thread_id = u.thread_id
+                 FROM UNNEST($1::int[], $2::int[], $3::int[]) AS u(id, parent_id, thread_id)
+                 WHERE m.id = u.id",
+                &[&ids, &parent_ids, &thread_ids],
+            )
+            .await?;
+    }
+    Ok(changed.len())
+}
+
+/// Recomputes `parent_id` / `thread_id` for the whole archive and stores the rows that changed.
+/// Run after the Maildir import at startup; new mail in between is threaded by
+/// [`thread_new_messages`].
+pub(crate) async fn rebuild_threads(pool: &Pool) -> Result<usize, Box<dyn std::error::Error>> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(&format!("SELECT {} FROM messages", THREAD_COLUMNS), &[])
+        .await?;
+    store_thread_placements(&client, &rows).await
+}
+
+/// Threads messages that have no `thread_id` yet against the existing reply trees. Only the
+/// trees they can join are loaded: those of the messages they reference or share a Message-ID
+/// with, those of archived replies to them (mail does not always arrive in order) and, for
+/// messages without reply headers, recent trees with the same cleaned subject.
+pub(crate) async fn thread_new_messages(pool: &Pool) -> Result<usize, Box<dyn std::error::Error>> {
+    let client = pool.get().await?;
+    let new_rows = client
+        .query(
+            &format!(
+                "SELECT {} FROM messages WHERE thread_id IS NULL",
+                THREAD_COLUMNS
+            ),
+            &[],
+        )
+        .await?;
+    if new_rows.is_empty() {
+        return Ok(0);
+    }
+
+    let mut own_ids: Vec<String> = Vec::new();
+    // Message-ID values as stored, with and without angle brackets
+    let mut linked_ids: Vec<String> = Vec::new();
+    let mut subjects: Vec<String> = Vec::new();
+    let mut earliest: Option<DateTime<Utc>> = None;
+    for row in &new_rows {
+        let own = row
+            .get::<_, Option<String>>("message_id")
+            .and_then(|v| parse_message_ids(&v).into_iter().next());
+        let in_reply_to: Option<String> = row.get("in_reply_to");
+        let references: Vec<String> = row.get("reference_ids");
+        if in_reply_to.is_none() && references.is_empty() {
+            if let Some(subject) = row.get::<_, Option<String>>("cleaned_subject") {
+                subjects.push(subject);
+            }
+            let sent_at: DateTime<Utc> = row.get("sent_at");
+            earliest = Some(earliest.map_or(sent_at, |e| e.min(sent_at)));
+        }
+        for id in own
+            .iter()
+            .chain(in_reply_to.iter())
+            .chain(references.iter())
+        {
+            linked_ids.push(format!("<{}>", id));
+            linked_ids.push(id.clone());
+        }
+        own_ids.extend(own);
+    }
+    let subjects_since =
+        earliest.unwrap_or_else(Utc::now) - chrono::Duration::days(SUBJECT_FALLBACK_WINDOW_DAYS);
+
+    let rows = client
+        .query(
+            &format!(
+                "WITH seeds AS (
+                    SELECT id, thread_id FROM messages
+                    WHERE thread_id IS NULL
+                       OR message_id = ANY($1)
+                       OR in_reply_to = ANY($2)
+                       OR reference_ids && $2
+                       OR (cleaned_subject = ANY($3) AND sent_at >= $4)
+                 )
+                 SELECT {} FROM messages
+                 WHERE id IN (SELECT id FROM seeds)
+                    OR thread_id IN (SELECT thread_id FROM seeds)",
+                THREAD_COLUMNS
+            ),
+            &[&linked_ids, &own_ids, &subjects, &subjects_since],
+        )
+        .await?;
+    store_thread_placements(&client, &rows).await
+}
+
+/// Brings reply trees up to date after new mail has been imported.
+async fn update_threads(pool: &Pool, maildir_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let backfilled = backfill_thread_headers(pool, maildir_path).await?;
+    let rethreaded = rebuild_threads(pool).await?;
+    info!(
+        "Mail threading: {} messages backfilled, {} messages re-threaded",
+        backfilled, rethreaded
+    );
+    Ok(())
+}
+
+fn stored_message(row: &Row) -> StoredMessage {
+    StoredMessage {
+        id: row.get("id"),
+        message_id: row.get("message_id"),
+        sent_at: row.get("sent_at"),
+        subject: row.get("subject"),
+        from_address: row.get("from_address"),
+        to_address: row.get("to_address"),
+        in_reply_to: row.get("in_reply_to"),
+        references: row.get("reference_ids"),
+        parts: row.get("parts_json"),
+    }
+}
+
+/// Id of the last message of an export of `limit` messages after `after_id`, if more messages
+/// match `filter`: the `after_id` of the next export.
+pub async fn next_mbox_export(
+    pool: &Pool,
+    filter: &MailSearchFilter,
+    after_id: Option<i32>,
+    limit: i64,
+) -> Result<Option<i32>, Box<dyn std::error::Error + Send + Sync>> {
+    let client = pool.get().await?;
+    let query = format!(
+        "{} OFFSET $6 LIMIT 2",
+        EXPORT_FILTER.replace("{columns}", "m.id")
+    );
+    let rows = client
+        .query(
+            &query,
+            &[
+                &filter.text,
+                &filter.from_pattern(),
+                &filter.before,
+                &filter.after,
+                &after_id,
+                &(limit - 1),
+            ],
+        )
+        .await?;
+    Ok(if rows.len() == 2 {
+        Some(rows[0].get("id"))
+    } else {
+        None
+    })
+}
+
+/// Streams up to `limit` messages matching `filter`, after `after_id` and oldest first, as an
+/// mboxrd file. Each item is the rendering of up to [`EXPORT_PAGE_SIZE`] messages; an `Err`
+/// item ends a failed export.
+pub fn export_mbox(
+    pool: Pool,
+    filter: MailSearchFilter,
+    after_id: Option<i32>,
+    limit: i64,
+) -> mpsc::Receiver<Result<Vec<u8>, String>> {
+    let (tx, rx) = mpsc::channel(4);
+    tokio::spawn(async move {
+        if let Err(e) = write_mbox_pages(&pool, &filter, after_id, limit, &tx).await {
+            error!("Mail archive mbox export failed: {}", e);
+            let _ = tx.send(Err(e.to_string())).await;
+        }
+    });
+    rx
+}
+
+async fn write_mbox_pages(
+    pool: &Pool,
+    filter: &MailSearchFilter,
+    after_id: Option<i32>,
+    limit: i64,
+    tx: &mpsc::Sender<Result<Vec<u8>, String>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = pool.get().await?;
+    let from_pattern = filter.from_pattern();
+    let query = format!(
+        "{} LIMIT $6",
+        EXPORT_FILTER.replace("{columns}", EXPORT_COLUMNS)
+    );
+    let mut last = after_id;
+    let mut remaining = limit;
+    while remaining > 0 {
+        let page_size = remaining.min(EXPORT_PAGE_SIZE);
+        let rows = client
+            .query(
+                &query,
+                &[
+                    &filter.text,
+                    &from_pattern,
+                    &filter.before,
+                    &filter.after,
+                    &last,
+                    &page_size,
+                ],
+            )
+            .await?;
+        let Some(last_row) = rows.last() else {
+            return Ok(());
+        };
+        last = Some(last_row.get("id"));
+        remaining -= rows.len() as i64;
+
+        let mut chunk = Vec::new();
+        for row in &rows {
+            let message = stored_message(row);
+            append_mbox(&mut chunk, &message, &build_eml(&message));
+        }
+        if tx.send(Ok(chunk)).await.is_err() {
+            // The client went away.
+            return Ok(());
+        }
+        if (rows.len() as i64) < page_size {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Zip of one `.eml` file per message in the thread containing `message_id`, plus the thread
+/// root id. `None` when the message does not exist.
+pub async fn export_thread_eml_zip(
+    pool: &Pool,
+    message_id: i32,
+) -> Result<Option<(i32, Vec<u8>)>, Box<dyn std::error::Error>> {
+    let client = pool.get().await?;
+    let Some(root) = client
+        .query_opt(
+            "SELECT COALESCE(thread_id, id) AS root_id FROM messages WHERE id = $1",
+            &[&message_id],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+    let root_id: i32 = root.get("root_id");
+
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {EXPORT_COLUMNS} FROM messages m
+                 WHERE m.thread_id = $1 OR m.id = $1
+                 ORDER BY m.sent_at, m.id"
+            ),
+            &[&root_id],
+        )
+        .await?;
+
+    let mut buffer = Vec::new();
+    {
+        let mut zip = ZipWriter::new(Cursor::new(&mut buffer));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (index, row) in rows.iter().enumerate() {
+            let message = stored_message(row);
+            zip.start_file(eml_filename(index, &message), options)?;
+            zip.write_all(&build_eml(&message))?;
+        }
+        zip.finish()?;
+    }
+    Ok(Some((root_id, buffer)))
+}
+
 fn remove_prefixes(subject: &str) -> String {
     let mut clean_subject = subject.to_string();
     let mut modified = true;
@@ -477,6 +877,8 @@ pub async fn import_maildir(
         sleep(BATCH_DELAY).await;
     }
 
+    update_threads(pool, maildir_path).await?;
+
     Ok(())
 }
 
@@ -484,11 +886,26 @@ async fn process_email(
     client: &Client,
     file_path: &Path,
     maildir_path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<bool, Box<dyn std::error::Error>> {
     let content = fs::read(file_path)?;
+    let relative_path = file_path
+        .strip_prefix(maildir_path)?
+        .to_str()
+        .unwrap_or_default();
+    store_email(client, &content, relative_path, false).await
+}
 
+/// Parses a raw message and stores it under `location` (the Maildir-relative path, or a
+/// source-specific key for [`super::sources`]). With `dedup_by_message_id` nothing is stored when
+/// a message with the same Message-ID is already archived. Returns whether a row was inserted.
+pub(crate) async fn store_email(
+    client: &Client,
+    content: &[u8],
+    location: &str,
+    dedup_by_message_id: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
     // Try to parse the email with raw content first to get headers
-    let content_str = &String::from_utf8_lossy(&content)
+    let content_str = &String::from_utf8_lossy(content)
         .lines()
         .collect::<Vec<_>>()
         .join("\r\n")
@@ -538,7 +955,7 @@ async fn process_email(
     let parsed_mail = parse_mail(mail_content.as_bytes())?;
 
     // Process all parts of the email
-    let parts = collect_parts(&parsed_mail, file_path.to_str().unwrap_or_default());
+    let parts = collect_parts(&parsed_mail, location);
 
     let message_id = parsed_mail
         .headers
@@ -579,7 +996,7 @@ async fn process_email(
         .unwrap_or_else(|_| {
             warn!(
                 "Failed to parse date: '{}' and received date '{:#?}'. Using current time. File: {:#?}",
-                fixed_date, received_date, file_path
+                fixed_date, received_date, location
             );
 
             Utc::now()
@@ -598,10 +1015,7 @@ async fn process_email(
         .get_first_value("To")
         .unwrap_or_default();
 
-    let relative_path = file_path
-        .strip_prefix(maildir_path)?
-        .to_str()
-        .unwrap_or_default();
+    let (in_reply_to, references) = reply_headers(&parsed_mail.headers);
 
     // Extract plain text content from text/plain parts
     let mut plain_text_content_parts = Vec::new();
@@ -618,14 +1032,17 @@ async fn process_email(
 
     let parts_json_value = serde_json::json!(parts);
 
-    client.execute(
-        "INSERT INTO messages (message_id, date, subject, from_address, to_address, file_path, parts_json, content)
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+    let inserted = client.execute(
+        "INSERT INTO messages (message_id, date, subject, from_address, to_address, file_path, parts_json, content,
+                               in_reply_to, reference_ids, thread_headers_parsed)
+         SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, TRUE
+         WHERE NOT ($11 AND $1 <> '' AND EXISTS (SELECT 1 FROM messages WHERE message_id = $1))
          ON CONFLICT (file_path) DO NOTHING",
-        &[&message_id, &parsed_date, &subject, &from_address, &to_address, &relative_path, &parts_json_value, &plain_text_content],
+        &[&message_id, &parsed_date, &subject, &from_address, &to_address, &location, &parts_json_value, &plain_text_content,
+          &in_reply_to, &references, &dedup_by_message_id],
     ).await?;
 
-    Ok(())
+    Ok(inserted > 0)
 }
 
 /// Processes all MIME parts of an email and returns structured data
@@ -1008,64 +1425,6 @@ fn fix_timezone_abbreviation(date_str: &str) -> Result<String, Box<dyn std::erro
     Ok(result.trim().to_string())
 }
 
-pub async fn check_for_new_emails(
-    pool: &deadpool_postgres::Pool,
-    maildir_path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client = pool.get().await?;
-    let maildir: &Path = Path::new(maildir_path);
-
-    // Collect all file paths first
-    let file_paths: Vec<(PathBuf, String)> = WalkDir::new(maildir)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .filter_map(|entry| {
-            let full_path = entry.path().to_owned();
-            entry
-                .path()
-                .strip_prefix(maildir)
-                .ok()
-                .and_then(|p| p.to_str())
-                .map(|rel_path| (full_path, rel_path.to_string()))
-        })
-        .collect();
-
-    if file_paths.is_empty() {
-        return Ok(());
-    }
-
-    let mut existing_paths = HashSet::new();
-
-    // Process in batches
-    for chunk in file_paths.chunks(BATCH_SIZE) {
-        let relative_paths: Vec<&str> = chunk.iter().map(|(_, rel)| rel.as_str()).collect();
-
-        let query = "SELECT file_path FROM messages WHERE file_path = ANY($1::text[])";
-
-        // Get existing paths for this batch
-        let batch_existing: Vec<String> = client
-            .query(query, &[&relative_paths])
-            .await?
-            .iter()
-            .map(|row| row.get::<_, String>("file_path"))
-            .collect();
-
-        existing_paths.extend(batch_existing);
-    }
-
-    // Process only new files
-    for (full_path, relative_path) in file_paths {
-        if !existing_paths.contains(&relative_path) {
-            if let Err(e) = process_email(&client, &full_path, maildir_path).await {
-                error!("Error processing new email {}: {}", full_path.display(), e);
-            }
-        }
-    }
-
-    Ok(())
-}
-
 pub async fn vote_spam(
     pool: &Pool,
     message_id: i32,
@@ -0,0 +1,495 @@
+//! IMAP mailbox, kept up to date with IDLE (RFC 2177) when the server supports it and polled
+//! otherwise. The cursor is `UIDVALIDITY:UID` of the last archived message; when the server
+//! resets UIDVALIDITY the mailbox is read again and Message-ID dedup drops what is archived.
+//!
+//! Only the handful of commands needed here are spoken (LOGIN, CAPABILITY, SELECT, UID SEARCH,
+//! UID FETCH, IDLE), over TLS by default.
+
+use async_trait::async_trait;
+use log::{info, warn};
+use std::env;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+use super::{
+    location_key, poll_interval, FetchedMessage, MailSource, RawMessage, SourceBatch, SourceError,
+};
+
+const BATCH_SIZE: usize = 200;
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+/// RFC 2177 asks clients to re-issue IDLE at least every 29 minutes.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+pub struct ImapConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub username: String,
+    pub password: String,
+    pub mailbox: String,
+}
+
+impl ImapConfig {
+    /// `IMAP_HOST` enables the source; `IMAP_PORT`, `IMAP_TLS` (default true), `IMAP_USERNAME`,
+    /// `IMAP_PASSWORD` and `IMAP_MAILBOX` (default INBOX) complete it.
+    pub fn from_env() -> Option<Self> {
+        let host = env::var("IMAP_HOST").ok().filter(|h| !h.is_empty())?;
+        let tls = env::var("IMAP_TLS")
+            .map(|v| !matches!(v.to_lowercase().as_str(), "false" | "0" | "no"))
+            .unwrap_or(true);
+        let port = env::var("IMAP_PORT")
+            .ok()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(if tls { 993 } else { 143 });
+        Some(Self {
+            host,
+            port,
+            tls,
+            username: env::var("IMAP_USERNAME").unwrap_or_default(),
+            password: env::var("IMAP_PASSWORD").unwrap_or_default(),
+            mailbox: env::var("IMAP_MAILBOX").unwrap_or_else(|_| "INBOX".to_string()),
+        })
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// One server response line, with any `{n}` literals read out of it.
+struct ResponseLine {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+impl ResponseLine {
+    fn is_untagged(&self, keyword: &str) -> bool {
+        self.text
+            .strip_prefix("* ")
+            .and_then(|rest| rest.split_whitespace().nth(1))
+            .is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// Number after `key ` in a response, e.g. `UID 42` or `[UIDVALIDITY 7]`.
+fn number_after(text: &str, key: &str) -> Option<u32> {
+    let upper = text.to_ascii_uppercase();
+    let start = upper.find(&format!("{} ", key))? + key.len() + 1;
+    let digits: String = text[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+struct Connection {
+    stream: BufReader<Box<dyn Stream>>,
+    next_tag: u32,
+}
+
+impl Connection {
+    async fn open(config: &ImapConfig) -> Result<Self, SourceError> {
+        let tcp = TcpStream::connect((config.host.as_str(), config.port)).await?;
+        let stream: Box<dyn Stream> = if config.tls {
+            let connector = tokio_native_tls::TlsConnector::from(
+                tokio_native_tls::native_tls::TlsConnector::new()?,
+            );
+            Box::new(connector.connect(&config.host, tcp).await?)
+        } else {
+            Box::new(tcp)
+        };
+        let mut connection = Self {
+            stream: BufReader::new(stream),
+            next_tag: 0,
+        };
+        let greeting = timeout(COMMAND_TIMEOUT, connection.read_line()).await??;
+        if !greeting.text.starts_with("* OK") && !greeting.text.starts_with("* PREAUTH") {
+            return Err(format!("Unexpected IMAP greeting: {}", greeting.text).into());
+        }
+        Ok(connection)
+    }
+
+    async fn read_line(&mut self) -> Result<ResponseLine, SourceError> {
+        let mut text = String::new();
+        let mut literals = Vec::new();
+        loop {
+            let mut chunk = Vec::new();
+            if self.stream.read_until(b'\n', &mut chunk).await? == 0 {
+                return Err("IMAP connection closed".into());
+            }
+            let chunk = String::from_utf8_lossy(&chunk);
+            let chunk = chunk.trim_end_matches(['\r', '\n']);
+            // A line ending in `{n}` is followed by n raw bytes, then the rest of the line.
+            let literal_len = chunk
+                .strip_suffix('}')
+                .and_then(|s| s.rfind('{').map(|i| &s[i + 1..]))
+                .and_then(|n| n.parse::<usize>().ok());
+            text.push_str(chunk);
+            match literal_len {
+                Some(len) => {
+                    let mut literal = vec![0; len];
+                    self.stream.read_exact(&mut literal).await?;
+                    literals.push(literal);
+                }
+                None => return Ok(ResponseLine { text, literals }),
+            }
+        }
+    }
+
+    async fn send(&mut self, line: &str) -> Result<(), SourceError> {
+        let stream = self.stream.get_mut();
+        stream.write_all(line.as_bytes()).await?;
+        stream.write_all(b"\r\n").await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    fn tag(&mut self) -> String {
+        self.next_tag += 1;
+        format!("a{}", self.next_tag)
+    }
+
+    /// Waits for the tagged completion of `tag`, returning the untagged lines before it.
+    async fn complete(&mut self, tag: &str, name: &str) -> Result<Vec<ResponseLine>, SourceError> {
+        let prefix = format!("{} ", tag);
+        let mut untagged = Vec::new();
+        loop {
+            let line = timeout(COMMAND_TIMEOUT, self.read_line()).await??;
+            match line.text.strip_prefix(&prefix) {
+                Some(status) if status.starts_with("OK") => return Ok(untagged),
+                Some(status) => return Err(format!("IMAP {} failed: {}", name, status).into()),
+                None => untagged.push(line),
+            }
+        }
+    }
+
+    async fn command(&mut self, command: &str) -> Result<Vec<ResponseLine>, SourceError> {
+        let tag = self.tag();
+        self.send(&format!("{} {}", tag, command)).await?;
+        // Keep credentials out of error messages.
+        let name = command
+            .split_whitespace()
+            .take(2)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let name = if command.starts_with("LOGIN") {
+            "LOGIN".to_string()
+        } else {
+            name
+        };
+        self.complete(&tag, &name).await
+    }
+
+    /// IDLEs until the server reports new messages or `limit` passes. Returns whether mail arrived.
+    async fn idle(&mut self, limit: Duration) -> Result<bool, SourceError> {
+        let tag = self.tag();
+        self.send(&format!("{} IDLE", tag)).await?;
+        let continuation = timeout(COMMAND_TIMEOUT, self.read_line()).await??;
+        if !continuation.text.starts_with('+') {
+            return Err(format!("IMAP IDLE refused: {}", continuation.text).into());
+        }
+
+        let deadline = Instant::now() + limit;
+        let mut arrived = false;
+        while let Ok(line) = timeout(
+            deadline.saturating_duration_since(Instant::now()),
+            self.read_line(),
+        )
+        .await
+        {
+            if line?.is_untagged("EXISTS") {
+                arrived = true;
+                break;
+            }
+        }
+
+        self.send("DONE").await?;
+        self.complete(&tag, "IDLE").await?;
+        Ok(arrived)
+    }
+}
+
+struct Session {
+    connection: Connection,
+    uid_validity: u32,
+    supports_idle: bool,
+}
+
+pub struct ImapSource {
+    config: ImapConfig,
+    session: Option<Session>,
+}
+
+impl ImapSource {
+    pub fn new(config: ImapConfig) -> Self {
+        Self {
+            config,
+            session: None,
+        }
+    }
+
+    async fn connect(config: &ImapConfig) -> Result<Session, SourceError> {
+        let mut connection = Connection::open(config).await?;
+        connection
+            .command(&format!(
+                "LOGIN {} {}",
+                quote(&config.username),
+                quote(&config.password)
+            ))
+            .await?;
+        let supports_idle = connection
+            .command("CAPABILITY")
+            .await?
+            .iter()
+            .filter(|line| line.text.starts_with("* CAPABILITY"))
+            .any(|line| {
+                line.text
+                    .split_whitespace()
+                    .any(|c| c.eq_ignore_ascii_case("IDLE"))
+            });
+        let uid_validity = connection
+            .command(&format!("SELECT {}", quote(&config.mailbox)))
+            .await?
+            .iter()
+            .find_map(|line| number_after(&line.text, "[UIDVALIDITY"))
+            .ok_or("IMAP server did not report UIDVALIDITY")?;
+        info!(
+            "Connected to IMAP {}:{} ({}), IDLE {}",
+            config.host,
+            config.port,
+            config.mailbox,
+            if supports_idle {
+                "supported"
+            } else {
+                "not supported"
+            }
+        );
+        Ok(Session {
+            connection,
+            uid_validity,
+            supports_idle,
+        })
+    }
+
+    async fn fetch_after(
+        session: &mut Session,
+        container: &str,
+        cursor: Option<&str>,
+    ) -> Result<SourceBatch, SourceError> {
+        let last_uid = cursor
+            .and_then(|c| c.split_once(':'))
+            .filter(|(validity, _)| validity.parse() == Ok(session.uid_validity))
+            .and_then(|(_, uid)| uid.parse::<u32>().ok())
+            .unwrap_or(0);
+
+        // `n:*` always matches the highest UID, even when it is below n.
+        let mut uids: Vec<u32> = session
+            .connection
+            .command(&format!("UID SEARCH UID {}:*", last_uid + 1))
+            .await?
+            .iter()
+            .filter(|line| line.text.starts_with("* SEARCH"))
+            .flat_map(|line| {
+                line.text
+                    .split_whitespace()
+                    .filter_map(|n| n.parse::<u32>().ok())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|uid| *uid > last_uid)
+            .collect();
+        uids.sort_unstable();
+        let more = uids.len() > BATCH_SIZE;
+        uids.truncate(BATCH_SIZE);
+
+        let mut messages = Vec::new();
+        if !uids.is_empty() {
+            let set = uids
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            for line in session
+                .connection
+                .command(&format!("UID FETCH {} (UID BODY.PEEK[])", set))
+                .await?
+            {
+                let (Some(uid), Some(body)) = (
+                    number_after(&line.text, "UID"),
+                    line.literals.into_iter().next(),
+                ) else {
+                    continue;
+                };
+                messages.push(FetchedMessage {
+                    location: location_key(
+                        "imap",
+                        container,
+                        format!("{}:{}", session.uid_validity, uid),
+                    ),
+                    raw: RawMessage::Bytes(body),
+                });
+            }
+        }
+
+        let last = uids.last().copied().unwrap_or(last_uid);
+        Ok(SourceBatch {
+            messages,
+            cursor: Some(format!("{}:{}", session.uid_validity, last)),
+            more,
+        })
+    }
+}
+
+#[async_trait]
+impl MailSource for ImapSource {
+    fn name(&self) -> String {
+        format!("imap:{}/{}", self.config.host, self.config.mailbox)
+    }
+
+    async fn fetch(&mut self, cursor: Option<&str>) -> Result<SourceBatch, SourceError> {
+        let mut session = match self.session.take() {
+            Some(session) => session,
+            None => Self::connect(&self.config).await?,
+        };
+        let container = format!("{}/{}", self.config.host, self.config.mailbox);
+        let batch = Self::fetch_after(&mut session, &container, cursor).await?;
+        // On errors the session is dropped and the next fetch reconnects.
+        self.session = Some(session);
+        Ok(batch)
+    }
+
+    async fn wait_for_mail(&mut self) {
+        match self.session.as_mut() {
+            Some(session) if session.supports_idle => {
+                if let Err(e) = session.connection.idle(IDLE_TIMEOUT).await {
+                    warn!(
+                        "IMAP IDLE on {} failed, reconnecting: {}",
+                        self.config.host, e
+                    );
+                    self.session = None;
+                    sleep(RECONNECT_DELAY).await;
+                }
+            }
+            Some(_) => sleep(poll_interval()).await,
+            None => sleep(RECONNECT_DELAY).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    fn message(uid: u32) -> Vec<u8> {
+        format!(
+            "Message-ID: <{}@example.org>\r\nSubject: coi\r\n\r\nmi'e {}\r\n",
+            uid, uid
+        )
+        .into_bytes()
+    }
+
+    /// Just enough of an IMAP server for one client session. A message with UID 3 is delivered
+    /// while the client IDLEs.
+    async fn stand_in_server(listener: TcpListener, mailbox: Arc<Mutex<Vec<u32>>>) {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut socket = BufReader::new(socket);
+        socket
+            .get_mut()
+            .write_all(b"* OK stand-in ready\r\n")
+            .await
+            .unwrap();
+        let mut line = String::new();
+        while socket.read_line(&mut line).await.unwrap() > 0 {
+            let request = line.trim_end().to_string();
+            line.clear();
+            let (tag, command) = request.split_once(' ').unwrap();
+            let mut reply = Vec::new();
+            if command.starts_with("CAPABILITY") {
+                reply.extend_from_slice(b"* CAPABILITY IMAP4rev1 IDLE\r\n");
+            } else if command.starts_with("SELECT") {
+                let count = mailbox.lock().unwrap().len();
+                reply.extend_from_slice(
+                    format!("* {} EXISTS\r\n* OK [UIDVALIDITY 7] ok\r\n", count).as_bytes(),
+                );
+            } else if let Some(range) = command.strip_prefix("UID SEARCH UID ") {
+                let from: u32 = range.trim_end_matches(":*").parse().unwrap();
+                let uids = mailbox.lock().unwrap().clone();
+                let mut hits: Vec<u32> = uids.iter().copied().filter(|u| *u >= from).collect();
+                if hits.is_empty() {
+                    hits.extend(uids.iter().max());
+                }
+                let hits: Vec<String> = hits.iter().map(u32::to_string).collect();
+                reply.extend_from_slice(format!("* SEARCH {}\r\n", hits.join(" ")).as_bytes());
+            } else if let Some(rest) = command.strip_prefix("UID FETCH ") {
+                let set = rest.split_whitespace().next().unwrap();
+                for (i, uid) in set
+                    .split(',')
+                    .map(|u| u.parse::<u32>().unwrap())
+                    .enumerate()
+                {
+                    let body = message(uid);
+                    reply.extend_from_slice(
+                        format!(
+                            "* {} FETCH (UID {} BODY[] {{{}}}\r\n",
+                            i + 1,
+                            uid,
+                            body.len()
+                        )
+                        .as_bytes(),
+                    );
+                    reply.extend_from_slice(&body);
+                    reply.extend_from_slice(b")\r\n");
+                }
+            } else if command == "IDLE" {
+                socket.get_mut().write_all(b"+ idling\r\n").await.unwrap();
+                mailbox.lock().unwrap().push(3);
+                socket.get_mut().write_all(b"* 3 EXISTS\r\n").await.unwrap();
+                socket.read_line(&mut line).await.unwrap();
+                assert_eq!(line.trim_end(), "DONE");
+                line.clear();
+            }
+            reply.extend_from_slice(format!("{} OK done\r\n", tag).as_bytes());
+            socket.get_mut().write_all(&reply).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_after_cursor_and_wakes_on_idle() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mailbox = Arc::new(Mutex::new(vec![1, 2]));
+        tokio::spawn(stand_in_server(listener, mailbox));
+
+        let mut source = ImapSource::new(ImapConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: false,
+            username: "list".to_string(),
+            password: "se\"cret".to_string(),
+            mailbox: "INBOX".to_string(),
+        });
+
+        let batch = source.fetch(None).await.unwrap();
+        assert_eq!(batch.messages.len(), 2);
+        assert_eq!(batch.cursor.as_deref(), Some("7:2"));
+        assert_eq!(batch.messages[1].location, "imap:127.0.0.1/INBOX#7:2");
+        assert!(matches!(&batch.messages[0].raw, RawMessage::Bytes(raw) if raw == &message(1)));
+
+        let empty = source.fetch(Some("7:2")).await.unwrap();
+        assert!(empty.messages.is_empty());
+        assert_eq!(empty.cursor.as_deref(), Some("7:2"));
+
+        timeout(Duration::from_secs(5), source.wait_for_mail())
+            .await
+            .unwrap();
+        let batch = source.fetch(Some("7:2")).await.unwrap();
+        assert_eq!(batch.messages.len(), 1);
+        assert_eq!(batch.cursor.as_deref(), Some("7:3"));
+    }
+}
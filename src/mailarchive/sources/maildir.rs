@@ -0,0 +1,122 @@
+//! New files in the Maildir. The source remembers which files it has handed out, so a poll
+//! returns every file it has not seen before, whatever its mtime: mail moved from `tmp/` to
+//! `new/` keeps the older mtime it was written with, and several files often share one second.
+//! A batch only counts as seen once its cursor is stored, so a batch that failed to archive is
+//! listed again. The first poll after a restart lists the whole Maildir; files archived already
+//! are skipped by location in [`super::sync_source`].
+
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use walkdir::WalkDir;
+
+use super::{FetchedMessage, MailSource, RawMessage, SourceBatch, SourceError};
+
+const BATCH_SIZE: usize = 1000;
+
+pub struct MaildirSource {
+    root: PathBuf,
+    /// Relative paths of the files handed out in archived batches.
+    seen: HashSet<String>,
+    /// Cursor and files of the last batch, added to `seen` once that cursor comes back.
+    pending: Option<(String, Vec<String>)>,
+}
+
+impl MaildirSource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            seen: HashSet::new(),
+            pending: None,
+        }
+    }
+}
+
+/// Files under `root` that are not in `seen`, oldest first. Forgets files that are gone.
+fn list_new_files(root: &Path, seen: &mut HashSet<String>, cursor: Option<&str>) -> SourceBatch {
+    let mut present: HashSet<String> = HashSet::new();
+    let mut files: Vec<(u64, String, PathBuf)> = Vec::new();
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let Some(relative) = entry
+            .path()
+            .strip_prefix(root)
+            .ok()
+            .and_then(Path::to_str)
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        if !seen.contains(&relative) {
+            let mtime = entry
+                .metadata()
+                .ok()
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |age| age.as_secs());
+            files.push((mtime, relative.clone(), entry.path().to_path_buf()));
+        }
+        present.insert(relative);
+    }
+    seen.retain(|relative| present.contains(relative));
+    files.sort();
+
+    let more = files.len() > BATCH_SIZE;
+    files.truncate(BATCH_SIZE);
+    let cursor = files
+        .last()
+        .map(|(mtime, relative, _)| format!("{}:{}", mtime, relative))
+        .or_else(|| cursor.map(str::to_string));
+    SourceBatch {
+        messages: files
+            .into_iter()
+            .map(|(_, relative, path)| FetchedMessage {
+                location: relative,
+                raw: RawMessage::File(path),
+            })
+            .collect(),
+        cursor,
+        more,
+    }
+}
+
+#[async_trait]
+impl MailSource for MaildirSource {
+    fn name(&self) -> String {
+        "maildir".to_string()
+    }
+
+    /// Maildir rows are keyed by file path, as in the initial import.
+    fn dedup_by_message_id(&self) -> bool {
+        false
+    }
+
+    async fn fetch(&mut self, cursor: Option<&str>) -> Result<SourceBatch, SourceError> {
+        // `sync_source` passes the cursor of the last batch back only once it is archived
+        if let Some((pending_cursor, files)) = self.pending.take() {
+            if cursor == Some(pending_cursor.as_str()) {
+                self.seen.extend(files);
+            }
+        }
+
+        let root = self.root.clone();
+        let cursor = cursor.map(str::to_string);
+        let mut seen = std::mem::take(&mut self.seen);
+        let (batch, seen) = tokio::task::spawn_blocking(move || {
+            let batch = list_new_files(&root, &mut seen, cursor.as_deref());
+            (batch, seen)
+        })
+        .await?;
+        self.seen = seen;
+
+        if let Some(cursor) = &batch.cursor {
+            let files = batch.messages.iter().map(|m| m.location.clone()).collect();
+            self.pending = Some((cursor.clone(), files));
+        }
+        Ok(batch)
+    }
+}
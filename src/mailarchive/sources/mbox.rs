@@ -0,0 +1,230 @@
+//! mbox files (mboxrd / mboxo). The cursor is the byte offset of the next unread message, so an
+//! archive that is appended to is picked up where the last run stopped.
+
+use async_trait::async_trait;
+use log::warn;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use super::{location_key, FetchedMessage, MailSource, RawMessage, SourceBatch, SourceError};
+
+const BATCH_SIZE: usize = 500;
+
+/// Reads messages one by one, starting at the `From ` separator line at its current position.
+pub struct MboxReader<R> {
+    reader: R,
+    /// Offset of the next line to read.
+    position: u64,
+    /// Offset of a separator line already read, which starts the next message.
+    next_start: Option<u64>,
+}
+
+fn is_separator(line: &[u8]) -> bool {
+    line.starts_with(b"From ")
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(|b| *b == b'\n' || *b == b'\r')
+}
+
+impl<R: BufRead> MboxReader<R> {
+    pub fn new(reader: R, position: u64) -> Self {
+        Self {
+            reader,
+            position,
+            next_start: None,
+        }
+    }
+
+    /// Offset where the next message starts (end of input once everything was read).
+    pub fn position(&self) -> u64 {
+        self.next_start.unwrap_or(self.position)
+    }
+
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+        line.clear();
+        let n = self.reader.read_until(b'\n', line)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    /// The next message as `(offset of its separator line, raw message)`.
+    pub fn next_message(&mut self) -> io::Result<Option<(u64, Vec<u8>)>> {
+        let mut line = Vec::new();
+        let start = match self.next_start.take() {
+            Some(start) => start,
+            None => loop {
+                // Skip anything before the first separator.
+                let line_start = self.position;
+                if self.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                if is_separator(&line) {
+                    break line_start;
+                }
+            },
+        };
+
+        let mut message = Vec::new();
+        let mut previous_blank = false;
+        loop {
+            let line_start = self.position;
+            if self.read_line(&mut line)? == 0 {
+                break;
+            }
+            if previous_blank && is_separator(&line) {
+                self.next_start = Some(line_start);
+                break;
+            }
+            previous_blank = is_blank(&line);
+            // mboxrd: ">From " lines were escaped by adding one '>'.
+            let quoted = line.iter().take_while(|b| **b == b'>').count();
+            if quoted > 0 && line[quoted..].starts_with(b"From ") {
+                message.extend_from_slice(&line[1..]);
+            } else {
+                message.extend_from_slice(&line);
+            }
+        }
+
+        // The blank line before the next separator belongs to the separator.
+        if self.next_start.is_some() {
+            if message.ends_with(b"\r\n") {
+                message.truncate(message.len() - 2);
+            } else if message.ends_with(b"\n") {
+                message.truncate(message.len() - 1);
+            }
+        }
+        Ok(Some((start, message)))
+    }
+}
+
+/// Files named in a comma-separated list; directories contribute the files directly inside them.
+pub fn expand_paths(list: &str) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for entry in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let path = PathBuf::from(entry);
+        if path.is_dir() {
+            match std::fs::read_dir(&path) {
+                Ok(dir) => {
+                    let mut files: Vec<PathBuf> = dir
+                        .filter_map(|e| e.ok())
+                        .map(|e| e.path())
+                        .filter(|p| p.is_file())
+                        .collect();
+                    files.sort();
+                    paths.extend(files);
+                }
+                Err(e) => warn!("Cannot list mbox directory {}: {}", path.display(), e),
+            }
+        } else {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+pub struct MboxSource {
+    path: PathBuf,
+}
+
+impl MboxSource {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+fn read_batch(path: &Path, cursor: Option<&str>) -> io::Result<SourceBatch> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut offset: u64 = cursor.and_then(|c| c.parse().ok()).unwrap_or(0);
+    if offset > len {
+        warn!(
+            "{} is shorter than its cursor ({} > {}), reading it again",
+            path.display(),
+            offset,
+            len
+        );
+        offset = 0;
+    }
+    file.seek(SeekFrom::Start(offset))?;
+
+    let container = path.display().to_string();
+    let mut reader = MboxReader::new(BufReader::new(file), offset);
+    let mut messages = Vec::new();
+    while messages.len() < BATCH_SIZE {
+        match reader.next_message()? {
+            Some((start, raw)) => messages.push(FetchedMessage {
+                location: location_key("mbox", &container, start),
+                raw: RawMessage::Bytes(raw),
+            }),
+            None => break,
+        }
+    }
+    let position = reader.position();
+    Ok(SourceBatch {
+        messages,
+        cursor: Some(position.to_string()),
+        more: position < len,
+    })
+}
+
+#[async_trait]
+impl MailSource for MboxSource {
+    fn name(&self) -> String {
+        format!("mbox:{}", self.path.display())
+    }
+
+    async fn fetch(&mut self, cursor: Option<&str>) -> Result<SourceBatch, SourceError> {
+        let path = self.path.clone();
+        let cursor = cursor.map(str::to_string);
+        Ok(tokio::task::spawn_blocking(move || read_batch(&path, cursor.as_deref())).await??)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const MBOX: &[u8] = b"From alice@example.org Mon Jan  1 00:00:00 2001\n\
+Message-ID: <a@x>\n\
+\n\
+coi\n\
+>From here on\n\
+\n\
+From bob@example.org Tue Jan  2 00:00:00 2001\n\
+Message-ID: <b@x>\n\
+\n\
+mi'e bob\n\
+From inside a paragraph\n";
+
+    #[test]
+    fn splits_messages_and_unescapes_from_lines() {
+        let mut reader = MboxReader::new(Cursor::new(MBOX), 0);
+        let (start, first) = reader.next_message().unwrap().unwrap();
+        assert_eq!(start, 0);
+        assert_eq!(first, b"Message-ID: <a@x>\n\ncoi\nFrom here on\n");
+
+        let (second_start, second) = reader.next_message().unwrap().unwrap();
+        assert_eq!(&MBOX[second_start as usize..][..9], b"From bob@");
+        assert_eq!(
+            second,
+            b"Message-ID: <b@x>\n\nmi'e bob\nFrom inside a paragraph\n"
+        );
+        assert!(reader.next_message().unwrap().is_none());
+        assert_eq!(reader.position(), MBOX.len() as u64);
+    }
+
+    #[test]
+    fn resumes_from_a_cursor() {
+        let mut reader = MboxReader::new(Cursor::new(MBOX), 0);
+        reader.next_message().unwrap();
+        let cursor = reader.position();
+
+        let mut resumed = MboxReader::new(Cursor::new(&MBOX[cursor as usize..]), cursor);
+        let (start, message) = resumed.next_message().unwrap().unwrap();
+        assert_eq!(start, cursor);
+        assert!(message.starts_with(b"Message-ID: <b@x>"));
+    }
+}
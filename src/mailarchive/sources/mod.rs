@@ -0,0 +1,212 @@
+//! Pluggable ingestion sources for the mail archive.
+//!
+//! Every source hands raw RFC 822 messages to [`service::store_email`], the same pipeline the
+//! initial Maildir import uses. Each message gets a location key that is unique within the
+//! archive (stored in `messages.file_path`), and each source keeps its own progress cursor in
+//! `mail_source_cursors`, so a restart resumes where it stopped.
+//!
+//! - [`maildir::MaildirSource`]: files under `MAILDIR_PATH`, polled every few minutes
+//! - [`mbox::MboxSource`]: historical archives listed in `MAIL_MBOX_PATHS`
+//! - [`imap::ImapSource`]: the live list over IMAP, woken by IDLE (`IMAP_HOST` and friends)
+
+pub mod imap;
+pub mod maildir;
+pub mod mbox;
+
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+use log::{error, info, warn};
+use std::collections::HashSet;
+use std::env;
+use std::path::PathBuf;
+use tokio::time::{sleep, Duration};
+
+use super::service;
+
+pub type SourceError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How often sources without push notifications look for new mail.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const BATCH_DELAY: Duration = Duration::from_millis(100);
+
+pub enum RawMessage {
+    Bytes(Vec<u8>),
+    /// Read only if the location is not archived yet.
+    File(PathBuf),
+}
+
+pub struct FetchedMessage {
+    /// Unique key stored in `messages.file_path`.
+    pub location: String,
+    pub raw: RawMessage,
+}
+
+pub struct SourceBatch {
+    pub messages: Vec<FetchedMessage>,
+    /// Cursor to store once the batch is archived.
+    pub cursor: Option<String>,
+    /// Whether another fetch right away would return more messages.
+    pub more: bool,
+}
+
+#[async_trait]
+pub trait MailSource: Send {
+    /// Key of this source's row in `mail_source_cursors`.
+    fn name(&self) -> String;
+
+    /// Skip messages whose Message-ID is already archived (e.g. an mbox overlapping the Maildir).
+    fn dedup_by_message_id(&self) -> bool {
+        true
+    }
+
+    /// Messages after `cursor`, oldest first.
+    async fn fetch(&mut self, cursor: Option<&str>) -> Result<SourceBatch, SourceError>;
+
+    /// Returns when new mail may be available.
+    async fn wait_for_mail(&mut self) {
+        sleep(poll_interval()).await;
+    }
+}
+
+pub(crate) fn poll_interval() -> Duration {
+    env::var("MAIL_POLL_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_POLL_INTERVAL)
+}
+
+/// Sources configured through the environment, besides the Maildir.
+pub fn configured_sources() -> Vec<Box<dyn MailSource>> {
+    let mut sources: Vec<Box<dyn MailSource>> = Vec::new();
+    if let Ok(paths) = env::var("MAIL_MBOX_PATHS") {
+        for path in mbox::expand_paths(&paths) {
+            sources.push(Box::new(mbox::MboxSource::new(path)));
+        }
+    }
+    if let Some(config) = imap::ImapConfig::from_env() {
+        sources.push(Box::new(imap::ImapSource::new(config)));
+    }
+    sources
+}
+
+async fn load_cursor(
+    client: &deadpool_postgres::Client,
+    name: &str,
+) -> Result<Option<String>, SourceError> {
+    Ok(client
+        .query_opt(
+            "SELECT cursor FROM mail_source_cursors WHERE source = $1",
+            &[&name],
+        )
+        .await?
+        .map(|row| row.get("cursor")))
+}
+
+async fn save_cursor(
+    client: &deadpool_postgres::Client,
+    name: &str,
+    cursor: &str,
+) -> Result<(), SourceError> {
+    client
+        .execute(
+            "INSERT INTO mail_source_cursors (source, cursor) VALUES ($1, $2)
+             ON CONFLICT (source) DO UPDATE SET cursor = EXCLUDED.cursor, updated_at = NOW()",
+            &[&name, &cursor],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Archives everything the source has after its stored cursor. Returns the number of new messages.
+pub async fn sync_source(pool: &Pool, source: &mut dyn MailSource) -> Result<usize, SourceError> {
+    let client = pool.get().await?;
+    let name = source.name();
+    let mut cursor = load_cursor(&client, &name).await?;
+    let mut stored = 0;
+
+    loop {
+        let batch = source.fetch(cursor.as_deref()).await?;
+
+        let locations: Vec<&str> = batch.messages.iter().map(|m| m.location.as_str()).collect();
+        let existing: HashSet<String> = client
+            .query(
+                "SELECT file_path FROM messages WHERE file_path = ANY($1::text[])",
+                &[&locations],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get("file_path"))
+            .collect();
+
+        for message in &batch.messages {
+            if existing.contains(&message.location) {
+                continue;
+            }
+            let raw = match &message.raw {
+                RawMessage::Bytes(bytes) => bytes.clone(),
+                RawMessage::File(path) => match tokio::fs::read(path).await {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        warn!("Failed to read {}: {}", path.display(), e);
+                        continue;
+                    }
+                },
+            };
+            match service::store_email(
+                &client,
+                &raw,
+                &message.location,
+                source.dedup_by_message_id(),
+            )
+            .await
+            {
+                Ok(true) => stored += 1,
+                Ok(false) => {}
+                Err(e) => warn!(
+                    "Error processing email {} from {}: {}",
+                    message.location, name, e
+                ),
+            }
+        }
+
+        // A batch that does not move the cursor would be fetched again forever.
+        let progressed = batch.cursor.is_some() && batch.cursor != cursor;
+        if let Some(next) = &batch.cursor {
+            save_cursor(&client, &name, next).await?;
+        }
+        cursor = batch.cursor.or(cursor);
+        if !batch.more || !progressed {
+            break;
+        }
+        sleep(BATCH_DELAY).await;
+    }
+
+    if stored > 0 {
+        if let Err(e) = service::thread_new_messages(pool).await {
+            error!("Failed to thread new mail from {}: {}", name, e);
+        }
+    }
+    Ok(stored)
+}
+
+/// Keeps one source in sync for the lifetime of the process.
+pub async fn run_source(pool: Pool, mut source: Box<dyn MailSource>) {
+    loop {
+        match sync_source(&pool, source.as_mut()).await {
+            Ok(0) => {}
+            Ok(stored) => info!("Archived {} new emails from {}", stored, source.name()),
+            Err(e) => error!("Failed to sync mail source {}: {}", source.name(), e),
+        }
+        source.wait_for_mail().await;
+    }
+}
+
+/// Location key for a message that has no file of its own.
+pub(crate) fn location_key(
+    kind: &str,
+    container: &str,
+    position: impl std::fmt::Display,
+) -> String {
+    format!("{}:{}#{}", kind, container, position)
+}
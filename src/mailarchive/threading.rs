@@ -0,0 +1,331 @@
+#![allow(clippy::expect_used)] // compile-time-fixed pattern
+
+//! Reply trees for the mail archive, following Jamie Zawinski's threading algorithm
+//! (<https://www.jwz.org/doc/threading.html>).
+//!
+//! Messages are linked through `Message-ID`, `In-Reply-To` and `References`. Referenced messages
+//! that are not in the archive become placeholders: their replies still share a thread, with the
+//! earliest archived reply as the root. Subject matching is only a fallback for roots that carry
+//! no reference headers at all but whose subject says they are a reply ("Re: ..."); those are
+//! attached to the most recent earlier thread with the same cleaned subject.
+
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+static MESSAGE_ID_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<([^<>\s]+)>").expect("Invalid message id regex pattern"));
+
+/// How far back a headerless "Re:" may be attached to a thread with the same subject.
+pub const SUBJECT_FALLBACK_WINDOW_DAYS: i64 = 60;
+
+pub struct ThreadInput {
+    pub id: i32,
+    pub message_id: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    pub subject: Option<String>,
+    pub cleaned_subject: Option<String>,
+    pub sent_at: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ThreadPlacement {
+    pub id: i32,
+    /// Nearest archived ancestor, if any.
+    pub parent_id: Option<i32>,
+    /// Root message of the tree this message belongs to (itself for roots).
+    pub thread_id: i32,
+}
+
+/// Extracts `<id>` tokens from a `Message-ID`, `In-Reply-To` or `References` header, without the
+/// angle brackets. A bare id without brackets is accepted when it is the whole header.
+pub fn parse_message_ids(header: &str) -> Vec<String> {
+    let ids: Vec<String> = MESSAGE_ID_REGEX
+        .captures_iter(header)
+        .filter_map(|c| c.get(1).map(|m| m.as_str().to_string()))
+        .collect();
+    if ids.is_empty() {
+        let bare = header.trim();
+        if !bare.is_empty() && !bare.contains(char::is_whitespace) && bare.contains('@') {
+            return vec![bare.to_string()];
+        }
+    }
+    ids
+}
+
+fn is_reply_subject(subject: &str) -> bool {
+    let mut s = subject.trim();
+    // Mailing list tags may precede the prefix: "[lojban] Re: ..."
+    while s.starts_with('[') {
+        match s.find(']') {
+            Some(end) => s = s[end + 1..].trim_start(),
+            None => break,
+        }
+    }
+    s.get(..3).is_some_and(|p| p.eq_ignore_ascii_case("re:"))
+}
+
+struct Container {
+    message: Option<usize>,
+    parent: Option<usize>,
+}
+
+/// True when making `parent` the parent of `child` would create a cycle.
+fn would_loop(containers: &[Container], child: usize, parent: usize) -> bool {
+    let mut current = Some(parent);
+    while let Some(c) = current {
+        if c == child {
+            return true;
+        }
+        current = containers[c].parent;
+    }
+    false
+}
+
+pub fn build_threads(messages: &[ThreadInput]) -> Vec<ThreadPlacement> {
+    let mut order: Vec<usize> = (0..messages.len()).collect();
+    order.sort_by_key(|&i| (messages[i].sent_at, messages[i].id));
+
+    let mut containers: Vec<Container> = Vec::new();
+    let mut by_message_id: HashMap<&str, usize> = HashMap::new();
+    let mut own_container = vec![0usize; messages.len()];
+
+    fn container_for<'a>(
+        containers: &mut Vec<Container>,
+        by_message_id: &mut HashMap<&'a str, usize>,
+        message_id: &'a str,
+    ) -> usize {
+        *by_message_id.entry(message_id).or_insert_with(|| {
+            containers.push(Container {
+                message: None,
+                parent: None,
+            });
+            containers.len() - 1
+        })
+    }
+
+    for &i in &order {
+        let m = &messages[i];
+        let own_id = m.message_id.as_deref().filter(|s| !s.is_empty());
+
+        // Messages without an id, or duplicates of an id already taken, get their own container.
+        let own = match own_id {
+            Some(id) => {
+                let c = container_for(&mut containers, &mut by_message_id, id);
+                if containers[c].message.is_none() {
+                    c
+                } else {
+                    containers.push(Container {
+                        message: None,
+                        parent: None,
+                    });
+                    containers.len() - 1
+                }
+            }
+            None => {
+                containers.push(Container {
+                    message: None,
+                    parent: None,
+                });
+                containers.len() - 1
+            }
+        };
+        containers[own].message = Some(i);
+        own_container[i] = own;
+
+        let mut refs: Vec<&str> = m.references.iter().map(String::as_str).collect();
+        if let Some(in_reply_to) = m.in_reply_to.as_deref() {
+            if refs.last() != Some(&in_reply_to) {
+                refs.push(in_reply_to);
+            }
+        }
+        refs.retain(|r| !r.is_empty() && Some(*r) != own_id);
+
+        // Link the References chain, keeping links that earlier messages already established.
+        let mut previous: Option<usize> = None;
+        for r in refs {
+            let c = container_for(&mut containers, &mut by_message_id, r);
+            if let Some(p) = previous {
+                if c != p && containers[c].parent.is_none() && !would_loop(&containers, c, p) {
+                    containers[c].parent = Some(p);
+                }
+            }
+            previous = Some(c);
+        }
+
+        // The message's own parent is always the last reference, replacing any guessed link.
+        containers[own].parent = match previous {
+            Some(p) if p != own && !would_loop(&containers, own, p) => Some(p),
+            _ => None,
+        };
+    }
+
+    let mut parent_of = vec![None; messages.len()];
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &i in &order {
+        let mut current = containers[own_container[i]].parent;
+        let mut top = own_container[i];
+        while let Some(c) = current {
+            if parent_of[i].is_none() {
+                parent_of[i] = containers[c].message;
+            }
+            top = c;
+            current = containers[c].parent;
+        }
+        groups.entry(top).or_default().push(i);
+    }
+
+    // A placeholder top (the original was never archived) gets its earliest reply as root.
+    // `order` is chronological, so group members are too.
+    let mut threads: Vec<(usize, Vec<usize>)> = groups
+        .into_iter()
+        .map(|(top, members)| (containers[top].message.unwrap_or(members[0]), members))
+        .collect();
+    threads.sort_by_key(|(root, _)| (messages[*root].sent_at, messages[*root].id));
+
+    let mut thread_of = vec![0usize; messages.len()];
+    // cleaned subject -> (thread root, last activity) of the latest thread with that subject
+    let mut latest_by_subject: HashMap<String, (usize, DateTime<Utc>)> = HashMap::new();
+    let window = Duration::days(SUBJECT_FALLBACK_WINDOW_DAYS);
+
+    for (root, members) in threads {
+        let root_msg = &messages[root];
+        let last_activity = members
+            .iter()
+            .map(|&i| messages[i].sent_at)
+            .max()
+            .unwrap_or(root_msg.sent_at);
+        let key = root_msg
+            .cleaned_subject
+            .as_deref()
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty());
+
+        let headerless_reply = root_msg.in_reply_to.is_none()
+            && root_msg.references.is_empty()
+            && root_msg.subject.as_deref().is_some_and(is_reply_subject);
+        let fallback = key
+            .as_ref()
+            .filter(|_| headerless_reply)
+            .and_then(|k| latest_by_subject.get(k))
+            .filter(|(_, last)| *last >= root_msg.sent_at - window)
+            .map(|(target, _)| *target);
+
+        let thread_root = match fallback {
+            Some(target) => {
+                parent_of[root] = Some(target);
+                target
+            }
+            None => root,
+        };
+        for &i in &members {
+            thread_of[i] = thread_root;
+        }
+        if let Some(k) = key {
+            let entry = latest_by_subject
+                .entry(k)
+                .or_insert((thread_root, last_activity));
+            if entry.0 == thread_root {
+                entry.1 = entry.1.max(last_activity);
+            } else {
+                *entry = (thread_root, last_activity);
+            }
+        }
+    }
+
+    order
+        .into_iter()
+        .map(|i| ThreadPlacement {
+            id: messages[i].id,
+            parent_id: parent_of[i].map(|p| messages[p].id),
+            thread_id: messages[thread_of[i]].id,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn msg(
+        id: i32,
+        message_id: &str,
+        in_reply_to: Option<&str>,
+        references: &[&str],
+        subject: &str,
+        day: u32,
+    ) -> ThreadInput {
+        ThreadInput {
+            id,
+            message_id: Some(message_id.to_string()),
+            in_reply_to: in_reply_to.map(str::to_string),
+            references: references.iter().map(|r| r.to_string()).collect(),
+            subject: Some(subject.to_string()),
+            cleaned_subject: Some(
+                subject
+                    .trim_start_matches("Re: ")
+                    .trim_start_matches("[lojban] ")
+                    .to_string(),
+            ),
+            sent_at: Utc
+                .with_ymd_and_hms(2001, 1, day, 12, 0, 0)
+                .single()
+                .expect("valid date"),
+        }
+    }
+
+    fn placement(placements: &[ThreadPlacement], id: i32) -> (Option<i32>, i32) {
+        let p = placements.iter().find(|p| p.id == id).expect("placement");
+        (p.parent_id, p.thread_id)
+    }
+
+    #[test]
+    fn parses_message_id_headers() {
+        assert_eq!(
+            parse_message_ids("<a@x> <b@y>\r\n\t<c@z>"),
+            vec!["a@x", "b@y", "c@z"]
+        );
+        assert_eq!(parse_message_ids(" a@x "), vec!["a@x"]);
+        assert!(parse_message_ids("").is_empty());
+    }
+
+    #[test]
+    fn replies_follow_references_not_subjects() {
+        let placements = build_threads(&[
+            msg(1, "a@x", None, &[], "question", 1),
+            msg(2, "b@x", Some("a@x"), &["a@x"], "Re: question", 2),
+            msg(3, "c@x", None, &[], "question", 3),
+            msg(4, "d@x", Some("b@x"), &["a@x", "b@x"], "changed subject", 4),
+        ]);
+        assert_eq!(placement(&placements, 1), (None, 1));
+        assert_eq!(placement(&placements, 2), (Some(1), 1));
+        // Same subject but a new message: its own thread.
+        assert_eq!(placement(&placements, 3), (None, 3));
+        // Drifted subject stays in the tree.
+        assert_eq!(placement(&placements, 4), (Some(2), 1));
+    }
+
+    #[test]
+    fn missing_parent_keeps_siblings_together() {
+        let placements = build_threads(&[
+            msg(5, "b@x", Some("gone@x"), &["gone@x"], "Re: lost", 2),
+            msg(6, "c@x", None, &["gone@x"], "Re: lost", 3),
+        ]);
+        assert_eq!(placement(&placements, 5), (None, 5));
+        assert_eq!(placement(&placements, 6), (None, 5));
+    }
+
+    #[test]
+    fn headerless_reply_falls_back_to_subject() {
+        let placements = build_threads(&[
+            msg(1, "a@x", None, &[], "[lojban] gismu list", 1),
+            msg(2, "b@x", None, &[], "Re: [lojban] gismu list", 2),
+            msg(3, "c@x", None, &[], "[lojban] gismu list", 3),
+        ]);
+        assert_eq!(placement(&placements, 2), (Some(1), 1));
+        assert_eq!(placement(&placements, 3), (None, 3));
+    }
+}
@@ -11,6 +11,7 @@ pub mod error;
 pub use error::{AppError, AppResult};
 pub mod api_docs;
 mod assistant;
+mod audit;
 mod auth;
 pub mod auth_utils;
 mod background;
@@ -28,13 +29,16 @@ mod middleware;
 mod notifications;
 mod openapi;
 mod payments;
+mod reports;
 mod server;
 pub mod sessions;
 mod subscriptions;
+mod trash;
 mod users;
 mod utils;
 mod versions;
 mod waves;
+mod webhooks;
 mod wiki;
 
 #[actix_web::main]
@@ -1,7 +1,7 @@
 use actix_web::{delete, get, post, put, web, HttpResponse, Result};
 use validator::Validate;
 
-use super::{dto::*, service::MessagingService};
+use super::{dto::*, encryption::EncryptionService, service::MessagingService};
 use crate::{auth::Claims, AppError};
 
 #[utoipa::path(
@@ -220,6 +220,47 @@ pub async fn get_thread_messages(
     Ok(HttpResponse::Ok().json(result))
 }
 
+#[utoipa::path(
+    get,
+    path = "/messaging/search",
+    tag = "messaging",
+    summary = "Search your messages",
+    description = "Full-text search over the messages and thread names of the threads you take part in. \
+                  Deleted messages and users you blocked are left out; end-to-end encrypted threads only \
+                  match by name. Each message hit has a cursor for loading the thread at that message.",
+    params(
+        ("q" = String, Query, description = "Search text; quoted phrases, -excluded words and OR are supported"),
+        ("thread_id" = Option<i64>, Query, description = "Only search this thread"),
+        ("page" = i64, Query, description = "Page number"),
+        ("per_page" = i64, Query, description = "Items per page (max 100)")
+    ),
+    responses(
+        (status = 200, description = "Matches, best first", body = MessageSearchResponse),
+        (status = 400, description = "Missing or too long search text"),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+#[get("/search")]
+pub async fn search_messages(
+    claims: Claims,
+    query: web::Query<SearchMessagesQuery>,
+    service: web::Data<MessagingService>,
+) -> Result<HttpResponse, AppError> {
+    query
+        .validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let result = service
+        .search_messages(claims.sub, query.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
 #[utoipa::path(
     post,
     path = "/messaging/messages",
@@ -305,18 +346,23 @@ pub async fn get_message(
         ("bearer_auth" = [])
     )
 )]
-#[allow(dead_code)]
+#[put("/messages/{message_id}")]
 pub async fn update_message(
-    _claims: Claims,
+    claims: Claims,
     path: web::Path<i64>,
-    _request: web::Json<UpdateMessageRequest>,
-    _service: web::Data<MessagingService>,
+    request: web::Json<UpdateMessageRequest>,
+    service: web::Data<MessagingService>,
 ) -> Result<HttpResponse, AppError> {
-    let _message_id = path.into_inner();
-    // TODO: Implement message update functionality
-    Err(AppError::Internal(
-        "Message update not yet implemented".to_string(),
-    ))
+    let message_id = path.into_inner();
+    request
+        .validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let result = service
+        .update_message(message_id, claims.sub, request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(result))
 }
 
 #[utoipa::path(
@@ -338,17 +384,47 @@ pub async fn update_message(
         ("bearer_auth" = [])
     )
 )]
-#[allow(dead_code)]
+#[delete("/messages/{message_id}")]
 pub async fn delete_message(
-    _claims: Claims,
+    claims: Claims,
     path: web::Path<i64>,
-    _service: web::Data<MessagingService>,
+    service: web::Data<MessagingService>,
 ) -> Result<HttpResponse, AppError> {
-    let _message_id = path.into_inner();
-    // TODO: Implement message delete functionality
-    Err(AppError::Internal(
-        "Message delete not yet implemented".to_string(),
-    ))
+    let message_id = path.into_inner();
+    service.delete_message(message_id, claims.sub).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/messaging/messages/{message_id}/history",
+    tag = "messaging",
+    summary = "Get the edit history of a message",
+    params(
+        ("message_id" = i64, Path, description = "Message ID")
+    ),
+    responses(
+        (status = 200, description = "Previous versions of the message, oldest first", body = Vec<MessageAuditEntryResponse>),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden - not the sender or admin"),
+        (status = 404, description = "Message not found or access denied"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+#[get("/messages/{message_id}/history")]
+pub async fn get_message_history(
+    claims: Claims,
+    path: web::Path<i64>,
+    service: web::Data<MessagingService>,
+) -> Result<HttpResponse, AppError> {
+    let message_id = path.into_inner();
+    let result = service.get_message_history(message_id, claims.sub).await?;
+
+    Ok(HttpResponse::Ok().json(result))
 }
 
 #[utoipa::path(
@@ -361,29 +437,34 @@ pub async fn delete_message(
     ),
     request_body = AddParticipantRequest,
     responses(
-        (status = 201, description = "Participant added successfully"),
+        (status = 201, description = "Participant added successfully", body = ThreadParticipantResponse),
         (status = 400, description = "Invalid request data"),
         (status = 401, description = "Unauthorized"),
-        (status = 403, description = "Forbidden - not an admin"),
-        (status = 404, description = "Thread not found"),
+        (status = 403, description = "Forbidden - not an admin, or blocked by the user"),
+        (status = 404, description = "Thread or user not found"),
         (status = 500, description = "Internal server error")
     ),
     security(
         ("bearer_auth" = [])
     )
 )]
-#[allow(dead_code)]
+#[post("/threads/{thread_id}/participants")]
 pub async fn add_participant(
-    _claims: Claims,
+    claims: Claims,
     path: web::Path<i64>,
-    _request: web::Json<AddParticipantRequest>,
-    _service: web::Data<MessagingService>,
+    request: web::Json<AddParticipantRequest>,
+    service: web::Data<MessagingService>,
 ) -> Result<HttpResponse, AppError> {
-    let _thread_id = path.into_inner();
-    // TODO: Implement add participant functionality
-    Err(AppError::Internal(
-        "Add participant not yet implemented".to_string(),
-    ))
+    let thread_id = path.into_inner();
+    request
+        .validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let result = service
+        .add_participant(thread_id, claims.sub, request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Created().json(result))
 }
 
 #[utoipa::path(
@@ -397,6 +478,7 @@ pub async fn add_participant(
     ),
     responses(
         (status = 204, description = "Participant removed successfully"),
+        (status = 400, description = "Direct thread, or the last admin would leave other participants behind"),
         (status = 401, description = "Unauthorized"),
         (status = 403, description = "Forbidden - not an admin"),
         (status = 404, description = "Thread or participant not found"),
@@ -406,17 +488,18 @@ pub async fn add_participant(
         ("bearer_auth" = [])
     )
 )]
-#[allow(dead_code)]
+#[delete("/threads/{thread_id}/participants/{user_id}")]
 pub async fn remove_participant(
-    _claims: Claims,
+    claims: Claims,
     path: web::Path<(i64, i32)>,
-    _service: web::Data<MessagingService>,
+    service: web::Data<MessagingService>,
 ) -> Result<HttpResponse, AppError> {
-    let (_thread_id, _user_id) = path.into_inner();
-    // TODO: Implement remove participant functionality
-    Err(AppError::Internal(
-        "Remove participant not yet implemented".to_string(),
-    ))
+    let (thread_id, user_id) = path.into_inner();
+    service
+        .remove_participant(thread_id, claims.sub, user_id)
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
 }
 
 #[utoipa::path(
@@ -430,7 +513,7 @@ pub async fn remove_participant(
     ),
     request_body = UpdateParticipantRoleRequest,
     responses(
-        (status = 200, description = "Participant role updated successfully"),
+        (status = 200, description = "Participant role updated successfully", body = ThreadParticipantResponse),
         (status = 400, description = "Invalid request data"),
         (status = 401, description = "Unauthorized"),
         (status = 403, description = "Forbidden - not an admin"),
@@ -441,18 +524,23 @@ pub async fn remove_participant(
         ("bearer_auth" = [])
     )
 )]
-#[allow(dead_code)]
+#[put("/threads/{thread_id}/participants/{user_id}/role")]
 pub async fn update_participant_role(
-    _claims: Claims,
+    claims: Claims,
     path: web::Path<(i64, i32)>,
-    _request: web::Json<UpdateParticipantRoleRequest>,
-    _service: web::Data<MessagingService>,
+    request: web::Json<UpdateParticipantRoleRequest>,
+    service: web::Data<MessagingService>,
 ) -> Result<HttpResponse, AppError> {
-    let (_thread_id, _user_id) = path.into_inner();
-    // TODO: Implement update participant role functionality
-    Err(AppError::Internal(
-        "Update participant role not yet implemented".to_string(),
-    ))
+    let (thread_id, user_id) = path.into_inner();
+    request
+        .validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let result = service
+        .update_participant_role(thread_id, claims.sub, user_id, request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(result))
 }
 
 #[utoipa::path(
@@ -462,25 +550,29 @@ pub async fn update_participant_role(
     summary = "Block a user",
     request_body = BlockUserRequest,
     responses(
-        (status = 201, description = "User blocked successfully"),
+        (status = 201, description = "User blocked successfully", body = BlockedUserResponse),
         (status = 400, description = "Invalid request data"),
         (status = 401, description = "Unauthorized"),
+        (status = 404, description = "User not found"),
         (status = 500, description = "Internal server error")
     ),
     security(
         ("bearer_auth" = [])
     )
 )]
-#[allow(dead_code)]
+#[post("/blocks")]
 pub async fn block_user(
-    _claims: Claims,
-    _request: web::Json<BlockUserRequest>,
-    _service: web::Data<MessagingService>,
+    claims: Claims,
+    request: web::Json<BlockUserRequest>,
+    service: web::Data<MessagingService>,
 ) -> Result<HttpResponse, AppError> {
-    // TODO: Implement block user functionality
-    Err(AppError::Internal(
-        "Block user not yet implemented".to_string(),
-    ))
+    request
+        .validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let result = service.block_user(claims.sub, request.into_inner()).await?;
+
+    Ok(HttpResponse::Created().json(result))
 }
 
 #[utoipa::path(
@@ -501,17 +593,16 @@ pub async fn block_user(
         ("bearer_auth" = [])
     )
 )]
-#[allow(dead_code)]
+#[delete("/blocks/{user_id}")]
 pub async fn unblock_user(
-    _claims: Claims,
+    claims: Claims,
     path: web::Path<i32>,
-    _service: web::Data<MessagingService>,
+    service: web::Data<MessagingService>,
 ) -> Result<HttpResponse, AppError> {
-    let _user_id = path.into_inner();
-    // TODO: Implement unblock user functionality
-    Err(AppError::Internal(
-        "Unblock user not yet implemented".to_string(),
-    ))
+    let user_id = path.into_inner();
+    service.unblock_user(claims.sub, user_id).await?;
+
+    Ok(HttpResponse::NoContent().finish())
 }
 
 #[utoipa::path(
@@ -528,15 +619,179 @@ pub async fn unblock_user(
         ("bearer_auth" = [])
     )
 )]
-#[allow(dead_code)]
+#[get("/blocks")]
 pub async fn get_blocked_users(
+    claims: Claims,
+    service: web::Data<MessagingService>,
+) -> Result<HttpResponse, AppError> {
+    let result = service.get_blocked_users(claims.sub).await?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+#[utoipa::path(
+    put,
+    path = "/messaging/keys/identity",
+    tag = "messaging",
+    summary = "Register your public identity key",
+    request_body = RegisterIdentityKeyRequest,
+    responses(
+        (status = 200, description = "Identity key registered; prekeys signed with a replaced key are discarded", body = KeyBundleResponse),
+        (status = 400, description = "Invalid key encoding"),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+#[put("/keys/identity")]
+pub async fn register_identity_key(
+    claims: Claims,
+    request: web::Json<RegisterIdentityKeyRequest>,
+    service: web::Data<EncryptionService>,
+) -> Result<HttpResponse, AppError> {
+    request
+        .validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let result = service
+        .register_identity_key(claims.sub, request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+#[utoipa::path(
+    post,
+    path = "/messaging/keys/prekeys",
+    tag = "messaging",
+    summary = "Upload a signed prekey",
+    request_body = UploadSignedPrekeyRequest,
+    responses(
+        (status = 201, description = "Prekey stored; the newest one is handed out", body = SignedPrekeyResponse),
+        (status = 400, description = "Invalid key encoding or no identity key registered"),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+#[post("/keys/prekeys")]
+pub async fn upload_signed_prekey(
+    claims: Claims,
+    request: web::Json<UploadSignedPrekeyRequest>,
+    service: web::Data<EncryptionService>,
+) -> Result<HttpResponse, AppError> {
+    request
+        .validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let result = service
+        .upload_signed_prekey(claims.sub, request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Created().json(result))
+}
+
+#[utoipa::path(
+    get,
+    path = "/messaging/keys/{user_id}",
+    tag = "messaging",
+    summary = "Get a user's public key bundle",
+    params(
+        ("user_id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "Identity key and newest signed prekey", body = KeyBundleResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "No identity key registered for this user"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+#[get("/keys/{user_id}")]
+pub async fn get_key_bundle(
     _claims: Claims,
-    _service: web::Data<MessagingService>,
+    path: web::Path<i32>,
+    service: web::Data<EncryptionService>,
 ) -> Result<HttpResponse, AppError> {
-    // TODO: Implement get blocked users functionality
-    Err(AppError::Internal(
-        "Get blocked users not yet implemented".to_string(),
-    ))
+    let user_id = path.into_inner();
+    let result = service.get_key_bundle(user_id).await?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+#[utoipa::path(
+    post,
+    path = "/messaging/threads/{thread_id}/keys",
+    tag = "messaging",
+    summary = "Share the thread key with the participants",
+    params(
+        ("thread_id" = i64, Path, description = "Thread ID")
+    ),
+    request_body = DistributeThreadKeysRequest,
+    responses(
+        (status = 201, description = "Wrapped keys stored", body = Vec<EncryptionKeyResponse>),
+        (status = 400, description = "Thread not encrypted, stale key version, or version shared by someone else"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Thread not found or access denied"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+#[post("/threads/{thread_id}/keys")]
+pub async fn distribute_thread_keys(
+    claims: Claims,
+    path: web::Path<i64>,
+    request: web::Json<DistributeThreadKeysRequest>,
+    service: web::Data<EncryptionService>,
+) -> Result<HttpResponse, AppError> {
+    let thread_id = path.into_inner();
+    request
+        .validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let result = service
+        .distribute_thread_keys(thread_id, claims.sub, request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Created().json(result))
+}
+
+#[utoipa::path(
+    get,
+    path = "/messaging/threads/{thread_id}/keys",
+    tag = "messaging",
+    summary = "Get your wrapped thread keys",
+    params(
+        ("thread_id" = i64, Path, description = "Thread ID")
+    ),
+    responses(
+        (status = 200, description = "Thread keys wrapped for you, oldest version first", body = Vec<EncryptionKeyResponse>),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Thread not found or access denied"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+#[get("/threads/{thread_id}/keys")]
+pub async fn get_thread_keys(
+    claims: Claims,
+    path: web::Path<i64>,
+    service: web::Data<EncryptionService>,
+) -> Result<HttpResponse, AppError> {
+    let thread_id = path.into_inner();
+    let result = service.get_thread_keys(thread_id, claims.sub).await?;
+
+    Ok(HttpResponse::Ok().json(result))
 }
 
 #[utoipa::path(
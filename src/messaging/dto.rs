@@ -14,6 +14,9 @@ pub struct CreateThreadRequest {
     pub participant_ids: Option<Vec<i32>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_participants: Option<i32>,
+    /// End-to-end encrypt the thread. Cannot be changed later.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_encrypted: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema, Validate)]
@@ -36,6 +39,9 @@ pub struct SendMessageRequest {
     pub sender_key_signature: Option<String>, // Base64 encoded
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reply_to_message_id: Option<i64>,
+    /// Thread key version the content is encrypted with; required in encrypted threads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_version: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema, Validate)]
@@ -44,6 +50,9 @@ pub struct UpdateMessageRequest {
     pub content_nonce: String, // Base64 encoded
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sender_key_signature: Option<String>, // Base64 encoded
+    /// Thread key version the content is encrypted with; required in encrypted threads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_version: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema, Validate)]
@@ -65,6 +74,36 @@ pub struct BlockUserRequest {
     pub reason: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, ToSchema, Validate)]
+pub struct RegisterIdentityKeyRequest {
+    pub identity_key: String, // Base64 encoded public key
+    #[validate(length(min = 1, max = 50))]
+    pub key_algorithm: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Validate)]
+pub struct UploadSignedPrekeyRequest {
+    pub prekey_id: i32,
+    pub public_key: String, // Base64 encoded
+    pub signature: String,  // Base64 encoded, made with the identity key
+}
+
+/// A thread key encrypted by a client for one participant.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Validate)]
+pub struct WrappedThreadKey {
+    pub user_id: i32,
+    pub encrypted_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Validate)]
+pub struct DistributeThreadKeysRequest {
+    pub key_version: i32,
+    #[validate(length(min = 1, max = 50))]
+    pub key_algorithm: String,
+    #[validate(length(min = 1))]
+    pub keys: Vec<WrappedThreadKey>,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema, Validate)]
 pub struct WebRTCSignalRequest {
     pub to_user_id: i32,
@@ -88,6 +127,9 @@ pub struct ThreadResponse {
     pub unread_count: i64,
     pub participant_count: i64,
     pub is_admin: bool,
+    pub is_encrypted: bool,
+    /// Current thread key version; 0 for threads that are not encrypted
+    pub key_version: i32,
     pub participants: Vec<ThreadParticipantResponse>,
 }
 
@@ -118,6 +160,7 @@ pub struct MessageResponse {
     pub is_deleted: bool,
     pub edit_count: i32,
     pub last_edited_at: Option<DateTime<Utc>>,
+    pub key_version: Option<i32>,
     pub is_from_sender: bool,
 }
 
@@ -130,6 +173,41 @@ pub struct MessageListResponse {
     pub has_more: bool,
 }
 
+/// Where to jump to show a search hit in its thread.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MessageCursor {
+    pub thread_id: i64,
+    /// Pass as `before_message_id` to the thread's messages to get the page that starts with
+    /// the hit and continues with the messages before it
+    pub before_message_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MessageSearchHit {
+    pub thread_id: i64,
+    pub thread_name: Option<String>,
+    pub thread_type: ThreadType,
+    /// `None` when the thread name matched rather than a message
+    pub message_id: Option<i64>,
+    pub sender_id: Option<i32>,
+    pub sender_username: Option<String>,
+    /// When the message was sent, or the thread's last activity for thread name matches
+    pub created_at: DateTime<Utc>,
+    /// HTML-escaped excerpt with matched terms in `<mark>` tags
+    pub snippet: String,
+    pub rank: f32,
+    pub cursor: Option<MessageCursor>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MessageSearchResponse {
+    pub results: Vec<MessageSearchHit>,
+    pub total_count: i64,
+    pub page: i64,
+    pub per_page: i64,
+    pub has_more: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ThreadListResponse {
     pub threads: Vec<ThreadResponse>,
@@ -172,6 +250,21 @@ pub struct BlockedUserResponse {
     pub reason: Option<String>,
 }
 
+/// A previous version of an edited or deleted message.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MessageAuditEntryResponse {
+    pub audit_id: i64,
+    pub message_id: i64,
+    pub actor_id: Option<i32>,
+    pub actor_username: Option<String>,
+    /// `edit` or `delete`
+    pub action: String,
+    pub previous_content: String,
+    pub previous_nonce: String,             // Base64 encoded
+    pub previous_signature: Option<String>, // Base64 encoded
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct WebRTCSignalResponse {
     pub signal_id: i64,
@@ -189,9 +282,27 @@ pub struct EncryptionKeyResponse {
     pub encrypted_key: String,
     pub key_algorithm: String,
     pub key_version: i32,
+    pub wrapped_by: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SignedPrekeyResponse {
+    pub prekey_id: i32,
+    pub public_key: String, // Base64 encoded
+    pub signature: String,  // Base64 encoded
     pub created_at: DateTime<Utc>,
 }
 
+/// What a client needs to wrap a thread key for a user.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct KeyBundleResponse {
+    pub user_id: i32,
+    pub identity_key: String, // Base64 encoded
+    pub key_algorithm: String,
+    pub signed_prekey: Option<SignedPrekeyResponse>,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ThreadStatsResponse {
     pub thread_id: i64,
@@ -250,6 +361,8 @@ pub enum WebSocketMessage {
         new_role: ParticipantRole,
         updated_by: i32,
     },
+    #[serde(rename = "thread_key_rotated")]
+    ThreadKeyRotated { thread_id: i64, key_version: i32 },
     #[serde(rename = "typing")]
     Typing {
         thread_id: i64,
@@ -302,6 +415,19 @@ pub struct GetMessagesQuery {
     pub message_type: Option<MessageType>,
 }
 
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct SearchMessagesQuery {
+    /// Search text; quoted phrases, `-excluded` words and `OR` are supported
+    #[validate(length(min = 1, max = 500))]
+    pub q: String,
+    #[serde(default)]
+    pub thread_id: Option<i64>,
+    #[serde(default = "default_page")]
+    pub page: i64,
+    #[serde(default = "default_per_page")]
+    pub per_page: i64,
+}
+
 #[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct GetNotificationsQuery {
     #[serde(default = "default_page")]
@@ -1,77 +1,548 @@
-// Post-quantum encryption service for end-to-end encrypted messaging
-// This module will handle Kyber-1024 for key exchange and Dilithium3 for signatures
+// Key directory for end-to-end encrypted threads. Clients do all of the cryptography: they
+// register public identity keys and signed prekeys, wrap each thread key for every participant
+// and send only ciphertext. The server never sees a private key, a thread key or plaintext.
 
 use crate::{AppError, AppResult};
-use deadpool_postgres::Pool;
+use base64::{engine::general_purpose, Engine as _};
+use deadpool_postgres::{Pool, Transaction};
+use tokio_postgres::Row;
+
+use super::dto::{
+    DistributeThreadKeysRequest, EncryptionKeyResponse, KeyBundleResponse,
+    RegisterIdentityKeyRequest, SignedPrekeyResponse, UploadSignedPrekeyRequest,
+};
+
+fn decode_key(value: &str, field: &str) -> AppResult<Vec<u8>> {
+    general_purpose::STANDARD
+        .decode(value)
+        .ok()
+        .filter(|bytes| !bytes.is_empty())
+        .ok_or_else(|| AppError::Validation(format!("Invalid {} encoding", field)))
+}
+
+fn prekey_from_row(row: &Row) -> SignedPrekeyResponse {
+    let public_key: Vec<u8> = row.get("public_key");
+    let signature: Vec<u8> = row.get("signature");
+    SignedPrekeyResponse {
+        prekey_id: row.get("prekey_id"),
+        public_key: general_purpose::STANDARD.encode(&public_key),
+        signature: general_purpose::STANDARD.encode(&signature),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn thread_key_from_row(row: &Row) -> EncryptionKeyResponse {
+    EncryptionKeyResponse {
+        key_id: row.get("key_id"),
+        thread_id: row.get("thread_id"),
+        encrypted_key: row.get("encrypted_key"),
+        key_algorithm: row.get("key_algorithm"),
+        key_version: row.get("key_version"),
+        wrapped_by: row.get("wrapped_by"),
+        created_at: row.get("created_at"),
+    }
+}
+
+/// Moves an encrypted thread to a new key version, so that clients share a fresh thread key
+/// with the current participants. Returns the new version, or `None` for other threads.
+pub async fn rotate_thread_key(
+    transaction: &Transaction<'_>,
+    thread_id: i64,
+) -> AppResult<Option<i32>> {
+    Ok(transaction
+        .query_opt(
+            "UPDATE message_threads SET key_version = key_version + 1
+             WHERE thread_id = $1 AND is_encrypted = TRUE
+             RETURNING key_version",
+            &[&thread_id],
+        )
+        .await?
+        .map(|row| row.get("key_version")))
+}
+
+/// Checks content sent to a thread and returns the key version to store with it. Encrypted
+/// threads only take base64 ciphertext and a nonce under the current key version, once that
+/// version's key has been shared with every participant; other threads store no version.
+pub async fn content_key_version(
+    transaction: &Transaction<'_>,
+    thread_id: i64,
+    key_version: Option<i32>,
+    encrypted_content: &str,
+    content_nonce: &[u8],
+) -> AppResult<Option<i32>> {
+    let row = transaction
+        .query_one(
+            "SELECT is_encrypted, key_version FROM message_threads WHERE thread_id = $1",
+            &[&thread_id],
+        )
+        .await?;
+    if !row.get::<_, bool>("is_encrypted") {
+        return Ok(None);
+    }
+
+    let current_version: i32 = row.get("key_version");
+    if key_version != Some(current_version) {
+        return Err(AppError::BadRequest(format!(
+            "Encrypt with the current thread key (version {})",
+            current_version
+        )));
+    }
+    if content_nonce.is_empty() {
+        return Err(AppError::Validation(
+            "Encrypted threads need a content nonce".to_string(),
+        ));
+    }
+    decode_key(encrypted_content, "encrypted content")?;
+
+    let missing_keys: bool = transaction
+        .query_one(
+            "SELECT EXISTS(
+                SELECT 1 FROM thread_participants tp
+                WHERE tp.thread_id = $1 AND tp.is_active = TRUE
+                AND NOT EXISTS (
+                    SELECT 1 FROM message_encryption_keys k
+                    WHERE k.thread_id = tp.thread_id AND k.user_id = tp.user_id
+                    AND k.key_version = $2 AND k.is_active = TRUE
+                )
+             )",
+            &[&thread_id, &current_version],
+        )
+        .await?
+        .get(0);
+    if missing_keys {
+        return Err(AppError::BadRequest(format!(
+            "Share thread key version {} with every participant first",
+            current_version
+        )));
+    }
+
+    Ok(Some(current_version))
+}
 
-#[allow(dead_code)]
 pub struct EncryptionService {
     pool: Pool,
 }
 
 impl EncryptionService {
-    #[allow(dead_code)]
     pub fn new(pool: Pool) -> Self {
         Self { pool }
     }
 
-    // TODO: Implement post-quantum cryptography
-    // - Kyber-1024 for key exchange
-    // - Dilithium3 for digital signatures
-    // - AES-256-GCM for message encryption
-    // - Key management and rotation
+    /// Registers or replaces the user's public identity key. Replacing it discards the prekeys
+    /// signed with the old one.
+    pub async fn register_identity_key(
+        &self,
+        user_id: i32,
+        request: RegisterIdentityKeyRequest,
+    ) -> AppResult<KeyBundleResponse> {
+        let identity_key = decode_key(&request.identity_key, "identity key")?;
+
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+
+        transaction
+            .execute(
+                "DELETE FROM user_signed_prekeys p
+                 USING user_identity_keys k
+                 WHERE p.user_id = $1 AND k.user_id = $1 AND k.identity_key != $2",
+                &[&user_id, &identity_key],
+            )
+            .await?;
 
-    #[allow(dead_code)]
-    pub async fn generate_thread_key_pair(&self, _thread_id: i64) -> AppResult<(String, String)> {
-        // TODO: Generate post-quantum key pair for thread
-        Err(AppError::Internal(
-            "Thread key generation not yet implemented".to_string(),
-        ))
+        transaction
+            .execute(
+                "INSERT INTO user_identity_keys (user_id, identity_key, key_algorithm)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (user_id) DO UPDATE
+                 SET identity_key = EXCLUDED.identity_key,
+                     key_algorithm = EXCLUDED.key_algorithm,
+                     updated_at = CURRENT_TIMESTAMP",
+                &[&user_id, &identity_key, &request.key_algorithm],
+            )
+            .await?;
+
+        transaction.commit().await?;
+
+        self.get_key_bundle(user_id).await
     }
 
-    #[allow(dead_code)]
-    pub async fn encrypt_message(
+    pub async fn upload_signed_prekey(
         &self,
-        _content: &str,
-        _thread_key: &str,
-    ) -> AppResult<(String, String)> {
-        // TODO: Encrypt message with thread public key and return (encrypted_content, nonce)
-        Err(AppError::Internal(
-            "Message encryption not yet implemented".to_string(),
-        ))
+        user_id: i32,
+        request: UploadSignedPrekeyRequest,
+    ) -> AppResult<SignedPrekeyResponse> {
+        let public_key = decode_key(&request.public_key, "public key")?;
+        let signature = decode_key(&request.signature, "signature")?;
+
+        let client = self.pool.get().await?;
+
+        let row = client
+            .query_opt(
+                "INSERT INTO user_signed_prekeys (user_id, prekey_id, public_key, signature)
+                 SELECT k.user_id, $2, $3, $4 FROM user_identity_keys k WHERE k.user_id = $1
+                 ON CONFLICT (user_id, prekey_id) DO UPDATE
+                 SET public_key = EXCLUDED.public_key,
+                     signature = EXCLUDED.signature,
+                     created_at = CURRENT_TIMESTAMP
+                 RETURNING *",
+                &[&user_id, &request.prekey_id, &public_key, &signature],
+            )
+            .await?
+            .ok_or_else(|| AppError::BadRequest("Register an identity key first".to_string()))?;
+
+        Ok(prekey_from_row(&row))
+    }
+
+    /// The user's identity key and newest signed prekey.
+    pub async fn get_key_bundle(&self, user_id: i32) -> AppResult<KeyBundleResponse> {
+        let client = self.pool.get().await?;
+
+        let identity_row = client
+            .query_opt(
+                "SELECT identity_key, key_algorithm FROM user_identity_keys WHERE user_id = $1",
+                &[&user_id],
+            )
+            .await?
+            .ok_or_else(|| {
+                AppError::NotFound("No identity key registered for this user".to_string())
+            })?;
+
+        let prekey_row = client
+            .query_opt(
+                "SELECT * FROM user_signed_prekeys
+                 WHERE user_id = $1
+                 ORDER BY created_at DESC, prekey_id DESC
+                 LIMIT 1",
+                &[&user_id],
+            )
+            .await?;
+
+        let identity_key: Vec<u8> = identity_row.get("identity_key");
+        Ok(KeyBundleResponse {
+            user_id,
+            identity_key: general_purpose::STANDARD.encode(&identity_key),
+            key_algorithm: identity_row.get("key_algorithm"),
+            signed_prekey: prekey_row.as_ref().map(prekey_from_row),
+        })
     }
 
-    #[allow(dead_code)]
-    pub async fn decrypt_message(
+    /// Stores the current thread key wrapped for each participant. The first participant to
+    /// share a version owns it; only they can upload keys for that version again, e.g. for a
+    /// participant they missed.
+    pub async fn distribute_thread_keys(
         &self,
-        _encrypted_content: &str,
-        _nonce: &str,
-        _thread_key: &str,
-    ) -> AppResult<String> {
-        // TODO: Decrypt message with thread private key
-        Err(AppError::Internal(
-            "Message decryption not yet implemented".to_string(),
-        ))
-    }
-
-    #[allow(dead_code)]
-    pub async fn sign_message(&self, _message: &str, _private_key: &str) -> AppResult<String> {
-        // TODO: Sign message with Dilithium3 private key
-        Err(AppError::Internal(
-            "Message signing not yet implemented".to_string(),
-        ))
-    }
-
-    #[allow(dead_code)]
-    pub async fn verify_signature(
+        thread_id: i64,
+        user_id: i32,
+        request: DistributeThreadKeysRequest,
+    ) -> AppResult<Vec<EncryptionKeyResponse>> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+
+        let thread_row = transaction
+            .query_opt(
+                "SELECT mt.is_encrypted, mt.key_version FROM message_threads mt
+                 JOIN thread_participants tp ON tp.thread_id = mt.thread_id
+                 WHERE mt.thread_id = $1 AND tp.user_id = $2
+                 AND tp.is_active = TRUE AND mt.is_active = TRUE
+                 FOR UPDATE OF mt",
+                &[&thread_id, &user_id],
+            )
+            .await?
+            .ok_or_else(|| AppError::NotFound("Thread not found or access denied".to_string()))?;
+
+        if !thread_row.get::<_, bool>("is_encrypted") {
+            return Err(AppError::BadRequest(
+                "Thread is not end-to-end encrypted".to_string(),
+            ));
+        }
+        let current_version: i32 = thread_row.get("key_version");
+        if request.key_version != current_version {
+            return Err(AppError::BadRequest(format!(
+                "Keys must be for the current key version ({})",
+                current_version
+            )));
+        }
+
+        let shared_by_other = transaction
+            .query_opt(
+                "SELECT 1 FROM message_encryption_keys
+                 WHERE thread_id = $1 AND key_version = $2
+                 AND wrapped_by IS DISTINCT FROM $3
+                 LIMIT 1",
+                &[&thread_id, &current_version, &user_id],
+            )
+            .await?
+            .is_some();
+        if shared_by_other {
+            return Err(AppError::BadRequest(
+                "Another participant already shared this key version".to_string(),
+            ));
+        }
+
+        let recipient_ids: Vec<i32> = request.keys.iter().map(|key| key.user_id).collect();
+        let recipients: i64 = transaction
+            .query_one(
+                "SELECT COUNT(*) FROM thread_participants
+                 WHERE thread_id = $1 AND is_active = TRUE AND user_id = ANY($2)",
+                &[&thread_id, &recipient_ids],
+            )
+            .await?
+            .get(0);
+        let mut unique_ids = recipient_ids.clone();
+        unique_ids.sort_unstable();
+        unique_ids.dedup();
+        if unique_ids.len() != recipient_ids.len() || recipients != recipient_ids.len() as i64 {
+            return Err(AppError::Validation(
+                "Keys must be for distinct active participants".to_string(),
+            ));
+        }
+
+        let mut keys = Vec::with_capacity(request.keys.len());
+        for key in &request.keys {
+            let row = transaction
+                .query_one(
+                    "INSERT INTO message_encryption_keys
+                         (thread_id, user_id, encrypted_key, key_algorithm, key_version, wrapped_by)
+                     VALUES ($1, $2, $3, $4, $5, $6)
+                     ON CONFLICT (thread_id, user_id, key_version) DO UPDATE
+                     SET encrypted_key = EXCLUDED.encrypted_key,
+                         key_algorithm = EXCLUDED.key_algorithm,
+                         is_active = TRUE,
+                         created_at = CURRENT_TIMESTAMP
+                     RETURNING *",
+                    &[
+                        &thread_id,
+                        &key.user_id,
+                        &key.encrypted_key,
+                        &request.key_algorithm,
+                        &current_version,
+                        &user_id,
+                    ],
+                )
+                .await?;
+            keys.push(thread_key_from_row(&row));
+        }
+
+        transaction.commit().await?;
+
+        Ok(keys)
+    }
+
+    /// Thread keys wrapped for the user, one per key version, oldest first.
+    pub async fn get_thread_keys(
         &self,
-        _message: &str,
-        _signature: &str,
-        _public_key: &str,
-    ) -> AppResult<bool> {
-        // TODO: Verify message signature with Dilithium3 public key
-        Err(AppError::Internal(
-            "Signature verification not yet implemented".to_string(),
-        ))
+        thread_id: i64,
+        user_id: i32,
+    ) -> AppResult<Vec<EncryptionKeyResponse>> {
+        let client = self.pool.get().await?;
+
+        let is_participant = client
+            .query_opt(
+                "SELECT 1 FROM thread_participants
+                 WHERE thread_id = $1 AND user_id = $2 AND is_active = TRUE",
+                &[&thread_id, &user_id],
+            )
+            .await?
+            .is_some();
+
+        if !is_participant {
+            return Err(AppError::NotFound(
+                "Thread not found or access denied".to_string(),
+            ));
+        }
+
+        let rows = client
+            .query(
+                "SELECT * FROM message_encryption_keys
+                 WHERE thread_id = $1 AND user_id = $2 AND is_active = TRUE
+                 ORDER BY key_version",
+                &[&thread_id, &user_id],
+            )
+            .await?;
+
+        Ok(rows.iter().map(thread_key_from_row).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deadpool_postgres::{Config, Runtime};
+    use std::env;
+    use uuid::Uuid;
+
+    fn test_pool() -> Pool {
+        dotenvy::dotenv().ok();
+        let mut cfg = Config::new();
+        cfg.host = Some(env::var("DB_HOST").unwrap_or_else(|_| "localhost".into()));
+        cfg.port = Some(
+            env::var("DB_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(5432),
+        );
+        cfg.user = Some(env::var("DB_USER").expect("DB_USER"));
+        cfg.password = Some(env::var("DB_PASSWORD").expect("DB_PASSWORD"));
+        cfg.dbname = Some(env::var("DB_NAME").expect("DB_NAME"));
+        cfg.create_pool(Some(Runtime::Tokio1), tokio_postgres::NoTls)
+            .unwrap()
+    }
+
+    async fn create_user(transaction: &Transaction<'_>) -> i32 {
+        let username = format!("e2ee-{}", &Uuid::new_v4().simple().to_string()[..8]);
+        let email = format!("{}@example.org", username);
+        transaction
+            .query_one(
+                "INSERT INTO users (username, email, password, created_at, role, email_confirmed, votesize)
+                 VALUES ($1, $2, 'x', NOW(), 'user', true, 1.0)
+                 RETURNING userid",
+                &[&username, &email],
+            )
+            .await
+            .unwrap()
+            .get("userid")
+    }
+
+    /// A group thread with two participants, returned as (thread, participants).
+    async fn thread(transaction: &Transaction<'_>, is_encrypted: bool) -> (i64, [i32; 2]) {
+        let participants = [
+            create_user(transaction).await,
+            create_user(transaction).await,
+        ];
+        let thread_id: i64 = transaction
+            .query_one(
+                "INSERT INTO message_threads (thread_type, created_by, is_encrypted, key_version)
+                 VALUES ('group', $1, $2, CASE WHEN $2 THEN 1 ELSE 0 END)
+                 RETURNING thread_id",
+                &[&participants[0], &is_encrypted],
+            )
+            .await
+            .unwrap()
+            .get("thread_id");
+        transaction
+            .execute(
+                "INSERT INTO thread_participants (thread_id, user_id, role)
+                 VALUES ($1, $2, 'admin'), ($1, $3, 'member')",
+                &[&thread_id, &participants[0], &participants[1]],
+            )
+            .await
+            .unwrap();
+        (thread_id, participants)
+    }
+
+    async fn share_key(transaction: &Transaction<'_>, thread_id: i64, user_id: i32, version: i32) {
+        transaction
+            .execute(
+                "INSERT INTO message_encryption_keys (thread_id, user_id, encrypted_key, key_version)
+                 VALUES ($1, $2, 'd3JhcHBlZA==', $3)",
+                &[&thread_id, &user_id, &version],
+            )
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn keys_must_be_non_empty_base64() {
+        assert_eq!(decode_key("AQID", "key").unwrap(), vec![1, 2, 3]);
+        assert!(matches!(
+            decode_key("", "key"),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            decode_key("not base64!", "key"),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn only_encrypted_threads_rotate_their_key() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        let (encrypted, _) = thread(&transaction, true).await;
+        let (plain, _) = thread(&transaction, false).await;
+
+        assert_eq!(
+            rotate_thread_key(&transaction, encrypted).await.unwrap(),
+            Some(2)
+        );
+        assert_eq!(
+            rotate_thread_key(&transaction, encrypted).await.unwrap(),
+            Some(3)
+        );
+        assert_eq!(rotate_thread_key(&transaction, plain).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn encrypted_content_needs_the_current_key_shared_with_everyone() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        let (thread_id, [first, second]) = thread(&transaction, true).await;
+        let nonce = [7u8; 12];
+
+        // Nothing is accepted until every participant has the key
+        share_key(&transaction, thread_id, first, 1).await;
+        assert!(matches!(
+            content_key_version(&transaction, thread_id, Some(1), "AQID", &nonce).await,
+            Err(AppError::BadRequest(_))
+        ));
+        share_key(&transaction, thread_id, second, 1).await;
+        assert_eq!(
+            content_key_version(&transaction, thread_id, Some(1), "AQID", &nonce)
+                .await
+                .unwrap(),
+            Some(1)
+        );
+
+        assert!(matches!(
+            content_key_version(&transaction, thread_id, Some(1), "AQID", &[]).await,
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            content_key_version(&transaction, thread_id, Some(1), "plain text", &nonce).await,
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            content_key_version(&transaction, thread_id, None, "AQID", &nonce).await,
+            Err(AppError::BadRequest(_))
+        ));
+
+        // After a rotation the old version is refused, and the new one waits for its keys
+        rotate_thread_key(&transaction, thread_id).await.unwrap();
+        assert!(matches!(
+            content_key_version(&transaction, thread_id, Some(1), "AQID", &nonce).await,
+            Err(AppError::BadRequest(_))
+        ));
+        share_key(&transaction, thread_id, first, 2).await;
+        assert!(matches!(
+            content_key_version(&transaction, thread_id, Some(2), "AQID", &nonce).await,
+            Err(AppError::BadRequest(_))
+        ));
+        share_key(&transaction, thread_id, second, 2).await;
+        assert_eq!(
+            content_key_version(&transaction, thread_id, Some(2), "AQID", &nonce)
+                .await
+                .unwrap(),
+            Some(2)
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn plain_threads_store_no_key_version() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        let (thread_id, _) = thread(&transaction, false).await;
+
+        assert_eq!(
+            content_key_version(&transaction, thread_id, None, "hello", &[])
+                .await
+                .unwrap(),
+            None
+        );
     }
 }
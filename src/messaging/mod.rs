@@ -19,8 +19,26 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .service(controller::update_thread)
             .service(controller::delete_thread)
             .service(controller::get_thread_messages)
+            .service(controller::search_messages)
             .service(controller::send_message)
             .service(controller::get_message)
+            .service(controller::update_message)
+            .service(controller::delete_message)
+            .service(controller::get_message_history)
+            // Participants
+            .service(controller::add_participant)
+            .service(controller::remove_participant)
+            .service(controller::update_participant_role)
+            // Blocks
+            .service(controller::block_user)
+            .service(controller::unblock_user)
+            .service(controller::get_blocked_users)
+            // End-to-end encryption keys
+            .service(controller::register_identity_key)
+            .service(controller::upload_signed_prekey)
+            .service(controller::get_key_bundle)
+            .service(controller::distribute_thread_keys)
+            .service(controller::get_thread_keys)
             // Read receipts
             .service(controller::mark_thread_read)
             // WebSocket routes
@@ -1,13 +1,16 @@
+use crate::mailarchive::query::{render_snippet, HIGHLIGHT_START, HIGHLIGHT_STOP};
 use crate::{AppError, AppResult};
 use actix::Addr;
 use base64::{engine::general_purpose, Engine as _};
 use chrono::Utc;
-use deadpool_postgres::Pool;
+use deadpool_postgres::{Pool, Transaction};
+use tokio_postgres::Row;
 
 use super::dto::WebSocketMessage;
 use super::dto::*;
+use super::encryption::{content_key_version, rotate_thread_key};
 use super::models::*;
-use super::websocket::{BroadcastToUsers, ChatServer};
+use super::websocket::{BroadcastToUsers, ChatServer, LeaveThread};
 
 // Helper function to build dynamic message query
 fn build_message_query(
@@ -56,6 +59,171 @@ fn build_message_query(
     (query_str, params)
 }
 
+/// Decodes the base64 nonce and optional signature sent with message content.
+fn decode_message_keys(
+    content_nonce: &str,
+    sender_key_signature: &Option<String>,
+) -> AppResult<(Vec<u8>, Option<Vec<u8>>)> {
+    let content_nonce = general_purpose::STANDARD
+        .decode(content_nonce)
+        .map_err(|_| AppError::BadRequest("Invalid content nonce format".to_string()))?;
+
+    let sender_key_signature = sender_key_signature
+        .as_ref()
+        .and_then(|sig| general_purpose::STANDARD.decode(sig).ok());
+
+    Ok((content_nonce, sender_key_signature))
+}
+
+/// Locks a live message for an active participant of its active thread, with the
+/// participant's role and the content to keep in the message's history.
+async fn lock_message(
+    transaction: &Transaction<'_>,
+    message_id: i64,
+    user_id: i32,
+) -> AppResult<Option<Row>> {
+    Ok(transaction
+        .query_opt(
+            "SELECT m.thread_id, m.sender_id, m.encrypted_content, m.content_nonce,
+                    m.sender_key_signature, tp.role
+             FROM private_messages m
+             JOIN message_threads mt ON m.thread_id = mt.thread_id AND mt.is_active = TRUE
+             JOIN thread_participants tp
+               ON tp.thread_id = m.thread_id AND tp.user_id = $2 AND tp.is_active = TRUE
+             WHERE m.message_id = $1 AND m.is_deleted = FALSE
+             FOR UPDATE OF m",
+            &[&message_id, &user_id],
+        )
+        .await?)
+}
+
+async fn record_message_audit(
+    transaction: &Transaction<'_>,
+    message: &Row,
+    message_id: i64,
+    actor_id: i32,
+    action: &str,
+) -> AppResult<()> {
+    let previous_content: String = message.get("encrypted_content");
+    let previous_nonce: Vec<u8> = message.get("content_nonce");
+    let previous_signature: Option<Vec<u8>> = message.get("sender_key_signature");
+
+    transaction
+        .execute(
+            "INSERT INTO private_message_audit
+                 (message_id, actor_id, action, previous_content, previous_nonce, previous_signature)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+            &[
+                &message_id,
+                &actor_id,
+                &action,
+                &previous_content,
+                &previous_nonce,
+                &previous_signature,
+            ],
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Refuses to leave a thread without an admin while other participants remain in it.
+async fn ensure_other_admin(
+    transaction: &Transaction<'_>,
+    thread_id: i64,
+    user_id: i32,
+    leaving: bool,
+) -> AppResult<()> {
+    let row = transaction
+        .query_one(
+            "SELECT COUNT(*) FILTER (WHERE role = 'admin') as admins,
+                    COUNT(*) as others
+             FROM thread_participants
+             WHERE thread_id = $1 AND user_id != $2 AND is_active = TRUE",
+            &[&thread_id, &user_id],
+        )
+        .await?;
+    let admins: i64 = row.get("admins");
+    let others: i64 = row.get("others");
+
+    if admins == 0 && (others > 0 || !leaving) {
+        return Err(AppError::BadRequest(
+            "Make another participant an admin first".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Locks the thread, so that participant changes to it run one at a time, and returns the role
+/// of `user_id` in it with the thread's type. The participant row stays locked until commit, so
+/// the role checked is the one the change is made under.
+async fn lock_membership(
+    transaction: &Transaction<'_>,
+    thread_id: i64,
+    user_id: i32,
+) -> AppResult<Option<(ParticipantRole, ThreadType)>> {
+    let Some(thread) = transaction
+        .query_opt(
+            "SELECT thread_type FROM message_threads
+             WHERE thread_id = $1 AND is_active = TRUE
+             FOR UPDATE",
+            &[&thread_id],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let row = transaction
+        .query_opt(
+            "SELECT role FROM thread_participants
+             WHERE thread_id = $1 AND user_id = $2 AND is_active = TRUE
+             FOR SHARE",
+            &[&thread_id, &user_id],
+        )
+        .await?;
+
+    Ok(row.map(|row| (row.get("role"), thread.get("thread_type"))))
+}
+
+fn check_group_admin(membership: Option<(ParticipantRole, ThreadType)>) -> AppResult<()> {
+    match membership {
+        None => Err(AppError::NotFound(
+            "Thread not found or access denied".to_string(),
+        )),
+        Some((_, ThreadType::Direct)) => Err(AppError::BadRequest(
+            "Direct threads have fixed participants".to_string(),
+        )),
+        Some((ParticipantRole::Member, ThreadType::Group)) => Err(AppError::Auth(
+            "Only admins can manage participants".to_string(),
+        )),
+        Some((ParticipantRole::Admin, ThreadType::Group)) => Ok(()),
+    }
+}
+
+fn check_can_leave(membership: Option<(ParticipantRole, ThreadType)>) -> AppResult<()> {
+    match membership {
+        None => Err(AppError::NotFound(
+            "Thread not found or access denied".to_string(),
+        )),
+        Some((_, ThreadType::Direct)) => Err(AppError::BadRequest(
+            "Direct threads have fixed participants".to_string(),
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+fn blocked_user_from_row(row: &Row) -> BlockedUserResponse {
+    BlockedUserResponse {
+        block_id: row.get("block_id"),
+        blocked_id: row.get("blocked_id"),
+        blocked_username: row.get("blocked_username"),
+        blocked_at: row.get("blocked_at"),
+        reason: row.get("reason"),
+    }
+}
+
 pub struct MessagingService {
     pool: Pool,
     chat_server: Option<Addr<ChatServer>>,
@@ -112,6 +280,8 @@ impl MessagingService {
                 unread_count: row.get("unread_count"),
                 participant_count: row.get("participant_count"),
                 is_admin: row.get("is_admin"),
+                is_encrypted: row.get("is_encrypted"),
+                key_version: row.get("key_version"),
                 participants,
             });
         }
@@ -154,18 +324,20 @@ impl MessagingService {
             }
         }
 
-        // Create thread
+        // Create thread; encrypted threads start at key version 1
         let max_participants = request.max_participants.unwrap_or(100);
+        let is_encrypted = request.is_encrypted.unwrap_or(false);
         let thread_row = transaction
             .query_one(
-                "INSERT INTO message_threads (thread_name, thread_type, created_by, max_participants)
-                 VALUES ($1, $2, $3, $4)
+                "INSERT INTO message_threads (thread_name, thread_type, created_by, max_participants, is_encrypted, key_version)
+                 VALUES ($1, $2, $3, $4, $5, CASE WHEN $5 THEN 1 ELSE 0 END)
                  RETURNING *",
                 &[
                     &request.thread_name,
                     &request.thread_type,
                     &user_id,
                     &max_participants,
+                    &is_encrypted,
                 ],
             )
             .await?;
@@ -248,6 +420,8 @@ impl MessagingService {
             unread_count: thread_row.get("unread_count"),
             participant_count: thread_row.get("participant_count"),
             is_admin: thread_row.get::<_, ParticipantRole>("user_role") == ParticipantRole::Admin,
+            is_encrypted: thread_row.get("is_encrypted"),
+            key_version: thread_row.get("key_version"),
             participants,
         })
     }
@@ -400,6 +574,204 @@ impl MessagingService {
         Ok(())
     }
 
+    // Participant operations
+    pub async fn add_participant(
+        &self,
+        thread_id: i64,
+        actor_id: i32,
+        request: AddParticipantRequest,
+    ) -> AppResult<ThreadParticipantResponse> {
+        if self.is_user_blocked(request.user_id, actor_id).await? {
+            return Err(AppError::Auth("User is blocked by participant".to_string()));
+        }
+
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        check_group_admin(lock_membership(&transaction, thread_id, actor_id).await?)?;
+
+        let user_exists = transaction
+            .query_opt("SELECT 1 FROM users WHERE userid = $1", &[&request.user_id])
+            .await?
+            .is_some();
+
+        if !user_exists {
+            return Err(AppError::NotFound("User not found".to_string()));
+        }
+
+        // Lock the thread so that concurrent additions can't go past the limit
+        let capacity_row = transaction
+            .query_one(
+                "SELECT max_participants,
+                        (SELECT COUNT(*) FROM thread_participants
+                         WHERE thread_id = $1 AND is_active = TRUE) as participant_count
+                 FROM message_threads
+                 WHERE thread_id = $1
+                 FOR UPDATE",
+                &[&thread_id],
+            )
+            .await?;
+        let max_participants: i32 = capacity_row.get("max_participants");
+        let participant_count: i64 = capacity_row.get("participant_count");
+
+        if participant_count >= i64::from(max_participants) {
+            return Err(AppError::BadRequest("Thread is full".to_string()));
+        }
+
+        // Users who left earlier rejoin with a fresh unread count
+        let role = request.role.unwrap_or(ParticipantRole::Member);
+        let added = transaction
+            .execute(
+                "INSERT INTO thread_participants (thread_id, user_id, role)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (thread_id, user_id) DO UPDATE
+                 SET role = EXCLUDED.role, is_active = TRUE, left_at = NULL,
+                     joined_at = CURRENT_TIMESTAMP, unread_count = 0
+                 WHERE NOT thread_participants.is_active",
+                &[&thread_id, &request.user_id, &role],
+            )
+            .await?;
+
+        if added == 0 {
+            return Err(AppError::BadRequest(
+                "User is already a participant".to_string(),
+            ));
+        }
+
+        let key_version = rotate_thread_key(&transaction, thread_id).await?;
+
+        transaction.commit().await?;
+
+        let participant = self.get_participant(thread_id, request.user_id).await?;
+        self.broadcast_to_thread(
+            thread_id,
+            &WebSocketMessage::ParticipantAdded {
+                thread_id,
+                participant: participant.clone(),
+            },
+            None,
+        )
+        .await;
+        self.announce_key_rotation(thread_id, key_version).await;
+
+        Ok(participant)
+    }
+
+    /// Removes a participant, or lets a participant leave when `user_id` is the actor.
+    pub async fn remove_participant(
+        &self,
+        thread_id: i64,
+        actor_id: i32,
+        user_id: i32,
+    ) -> AppResult<()> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        let membership = lock_membership(&transaction, thread_id, actor_id).await?;
+        if user_id == actor_id {
+            check_can_leave(membership)?;
+        } else {
+            check_group_admin(membership)?;
+        }
+
+        let role: ParticipantRole = transaction
+            .query_opt(
+                "SELECT role FROM thread_participants
+                 WHERE thread_id = $1 AND user_id = $2 AND is_active = TRUE
+                 FOR UPDATE",
+                &[&thread_id, &user_id],
+            )
+            .await?
+            .ok_or_else(|| AppError::NotFound("Participant not found".to_string()))?
+            .get("role");
+
+        if role == ParticipantRole::Admin {
+            ensure_other_admin(&transaction, thread_id, user_id, true).await?;
+        }
+
+        transaction
+            .execute(
+                "UPDATE thread_participants
+                 SET is_active = FALSE, left_at = CURRENT_TIMESTAMP
+                 WHERE thread_id = $1 AND user_id = $2",
+                &[&thread_id, &user_id],
+            )
+            .await?;
+
+        let key_version = rotate_thread_key(&transaction, thread_id).await?;
+
+        transaction.commit().await?;
+
+        // The removed user hears about it too, then stops receiving the thread's events
+        let mut user_ids = self.get_thread_participant_user_ids(thread_id).await?;
+        user_ids.push(user_id);
+        self.broadcast(
+            user_ids,
+            &WebSocketMessage::ParticipantRemoved {
+                thread_id,
+                user_id,
+                removed_by: actor_id,
+            },
+            None,
+        );
+        if let Some(chat_server) = &self.chat_server {
+            chat_server.do_send(LeaveThread { user_id, thread_id });
+        }
+        self.announce_key_rotation(thread_id, key_version).await;
+
+        Ok(())
+    }
+
+    pub async fn update_participant_role(
+        &self,
+        thread_id: i64,
+        actor_id: i32,
+        user_id: i32,
+        request: UpdateParticipantRoleRequest,
+    ) -> AppResult<ThreadParticipantResponse> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        check_group_admin(lock_membership(&transaction, thread_id, actor_id).await?)?;
+
+        let current_role: ParticipantRole = transaction
+            .query_opt(
+                "SELECT role FROM thread_participants
+                 WHERE thread_id = $1 AND user_id = $2 AND is_active = TRUE
+                 FOR UPDATE",
+                &[&thread_id, &user_id],
+            )
+            .await?
+            .ok_or_else(|| AppError::NotFound("Participant not found".to_string()))?
+            .get("role");
+
+        if current_role == ParticipantRole::Admin && request.role == ParticipantRole::Member {
+            ensure_other_admin(&transaction, thread_id, user_id, false).await?;
+        }
+
+        transaction
+            .execute(
+                "UPDATE thread_participants SET role = $3
+                 WHERE thread_id = $1 AND user_id = $2",
+                &[&thread_id, &user_id, &request.role],
+            )
+            .await?;
+
+        transaction.commit().await?;
+
+        let participant = self.get_participant(thread_id, user_id).await?;
+        self.broadcast_to_thread(
+            thread_id,
+            &WebSocketMessage::ParticipantRoleUpdated {
+                thread_id,
+                user_id,
+                new_role: request.role,
+                updated_by: actor_id,
+            },
+            None,
+        )
+        .await;
+
+        Ok(participant)
+    }
+
     // Message operations
     pub async fn get_thread_messages(
         &self,
@@ -457,6 +829,7 @@ impl MessagingService {
                 is_deleted: row.get("is_deleted"),
                 edit_count: row.get("edit_count"),
                 last_edited_at: row.get("last_edited_at"),
+                key_version: row.get("key_version"),
                 is_from_sender: row.get::<_, i32>("sender_id") == user_id,
             });
         }
@@ -507,21 +880,40 @@ impl MessagingService {
             ));
         }
 
-        // Decode base64 nonce
-        let content_nonce = general_purpose::STANDARD
-            .decode(&request.content_nonce)
-            .map_err(|_| AppError::BadRequest("Invalid content nonce format".to_string()))?;
+        // In direct threads a block stops the conversation; group threads stay open
+        let blocked: bool = transaction
+            .query_one(
+                "SELECT EXISTS(
+                    SELECT 1 FROM thread_participants tp
+                    JOIN message_threads mt ON tp.thread_id = mt.thread_id
+                    WHERE tp.thread_id = $1 AND tp.user_id != $2 AND tp.is_active = TRUE
+                    AND mt.thread_type = 'direct' AND is_user_blocked(tp.user_id, $2)
+                 )",
+                &[&request.thread_id, &user_id],
+            )
+            .await?
+            .get(0);
+
+        if blocked {
+            return Err(AppError::Auth("User is blocked by participant".to_string()));
+        }
 
-        let sender_key_signature = request
-            .sender_key_signature
-            .as_ref()
-            .and_then(|sig| general_purpose::STANDARD.decode(sig).ok());
+        let (content_nonce, sender_key_signature) =
+            decode_message_keys(&request.content_nonce, &request.sender_key_signature)?;
+        let key_version = content_key_version(
+            &transaction,
+            request.thread_id,
+            request.key_version,
+            &request.encrypted_content,
+            &content_nonce,
+        )
+        .await?;
 
         // Insert message
         let message_row = transaction
             .query_one(
-                "INSERT INTO private_messages (thread_id, sender_id, message_type, encrypted_content, content_nonce, sender_key_signature, reply_to_message_id)
-                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "INSERT INTO private_messages (thread_id, sender_id, message_type, encrypted_content, content_nonce, sender_key_signature, reply_to_message_id, key_version)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
                  RETURNING *",
                 &[
                     &request.thread_id,
@@ -531,6 +923,7 @@ impl MessagingService {
                     &content_nonce,
                     &sender_key_signature,
                     &request.reply_to_message_id,
+                    &key_version,
                 ],
             )
             .await?;
@@ -554,26 +947,17 @@ impl MessagingService {
         let message = self.get_message(message_id, user_id).await?;
 
         // Broadcast to thread participants over WebSocket
-        if let Some(chat_server) = &self.chat_server {
-            if let Ok(user_ids) = self
-                .get_thread_participant_user_ids(request.thread_id)
-                .await
-            {
-                let mut broadcast_message = message.clone();
-                broadcast_message.is_from_sender = false;
-                let ws_message = WebSocketMessage::MessageSent {
-                    message: broadcast_message,
-                    thread_id: request.thread_id,
-                };
-                if let Ok(json) = serde_json::to_string(&ws_message) {
-                    chat_server.do_send(BroadcastToUsers {
-                        user_ids,
-                        message_json: json,
-                        exclude_user: Some(user_id),
-                    });
-                }
-            }
-        }
+        let mut broadcast_message = message.clone();
+        broadcast_message.is_from_sender = false;
+        self.broadcast_to_thread(
+            request.thread_id,
+            &WebSocketMessage::MessageSent {
+                message: broadcast_message,
+                thread_id: request.thread_id,
+            },
+            Some(user_id),
+        )
+        .await;
 
         Ok(message)
     }
@@ -586,7 +970,7 @@ impl MessagingService {
                 "SELECT m.*, u.username 
                  FROM private_messages m
                  JOIN users u ON m.sender_id = u.userid
-                 WHERE m.message_id = $1",
+                 WHERE m.message_id = $1 AND m.is_deleted = FALSE",
                 &[&message_id],
             )
             .await?;
@@ -627,6 +1011,7 @@ impl MessagingService {
                 is_deleted: row.get("is_deleted"),
                 edit_count: row.get("edit_count"),
                 last_edited_at: row.get("last_edited_at"),
+                key_version: row.get("key_version"),
                 is_from_sender: row.get::<_, i32>("sender_id") == user_id,
             })
         } else {
@@ -634,6 +1019,304 @@ impl MessagingService {
         }
     }
 
+    /// Replaces the content of a message, keeping the previous version in its history.
+    pub async fn update_message(
+        &self,
+        message_id: i64,
+        user_id: i32,
+        request: UpdateMessageRequest,
+    ) -> AppResult<MessageResponse> {
+        let (content_nonce, sender_key_signature) =
+            decode_message_keys(&request.content_nonce, &request.sender_key_signature)?;
+
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+
+        let row = lock_message(&transaction, message_id, user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?;
+        let thread_id: i64 = row.get("thread_id");
+
+        if row.get::<_, i32>("sender_id") != user_id {
+            return Err(AppError::Auth(
+                "Only the sender can edit a message".to_string(),
+            ));
+        }
+
+        let key_version = content_key_version(
+            &transaction,
+            thread_id,
+            request.key_version,
+            &request.encrypted_content,
+            &content_nonce,
+        )
+        .await?;
+
+        record_message_audit(&transaction, &row, message_id, user_id, "edit").await?;
+
+        transaction
+            .execute(
+                "UPDATE private_messages
+                 SET encrypted_content = $2, content_nonce = $3, sender_key_signature = $4,
+                     key_version = $5, edit_count = edit_count + 1,
+                     last_edited_at = CURRENT_TIMESTAMP
+                 WHERE message_id = $1",
+                &[
+                    &message_id,
+                    &request.encrypted_content,
+                    &content_nonce,
+                    &sender_key_signature,
+                    &key_version,
+                ],
+            )
+            .await?;
+
+        transaction.commit().await?;
+
+        let message = self.get_message(message_id, user_id).await?;
+
+        let mut broadcast_message = message.clone();
+        broadcast_message.is_from_sender = false;
+        self.broadcast_to_thread(
+            thread_id,
+            &WebSocketMessage::MessageUpdated {
+                message: broadcast_message,
+                thread_id,
+            },
+            Some(user_id),
+        )
+        .await;
+
+        Ok(message)
+    }
+
+    /// Hides a message. The sender and thread admins can delete; the content stays in the
+    /// message's history.
+    pub async fn delete_message(&self, message_id: i64, user_id: i32) -> AppResult<()> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+
+        let row = lock_message(&transaction, message_id, user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?;
+        let thread_id: i64 = row.get("thread_id");
+
+        if row.get::<_, i32>("sender_id") != user_id
+            && row.get::<_, ParticipantRole>("role") != ParticipantRole::Admin
+        {
+            return Err(AppError::Auth(
+                "Only the sender or a thread admin can delete a message".to_string(),
+            ));
+        }
+
+        record_message_audit(&transaction, &row, message_id, user_id, "delete").await?;
+
+        transaction
+            .execute(
+                "UPDATE private_messages
+                 SET is_deleted = TRUE, deleted_at = CURRENT_TIMESTAMP, deleted_by = $2
+                 WHERE message_id = $1",
+                &[&message_id, &user_id],
+            )
+            .await?;
+
+        transaction.commit().await?;
+
+        self.broadcast_to_thread(
+            thread_id,
+            &WebSocketMessage::MessageDeleted {
+                message_id,
+                thread_id,
+                deleted_by: user_id,
+            },
+            Some(user_id),
+        )
+        .await;
+
+        Ok(())
+    }
+
+    /// Earlier versions of an edited or deleted message, oldest first.
+    pub async fn get_message_history(
+        &self,
+        message_id: i64,
+        user_id: i32,
+    ) -> AppResult<Vec<MessageAuditEntryResponse>> {
+        let client = self.pool.get().await?;
+
+        let row = client
+            .query_opt(
+                "SELECT m.sender_id, tp.role
+                 FROM private_messages m
+                 JOIN thread_participants tp
+                   ON tp.thread_id = m.thread_id AND tp.user_id = $2 AND tp.is_active = TRUE
+                 WHERE m.message_id = $1",
+                &[&message_id, &user_id],
+            )
+            .await?
+            .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?;
+
+        if row.get::<_, i32>("sender_id") != user_id
+            && row.get::<_, ParticipantRole>("role") != ParticipantRole::Admin
+        {
+            return Err(AppError::Auth(
+                "Only the sender or a thread admin can see the history of a message".to_string(),
+            ));
+        }
+
+        let rows = client
+            .query(
+                "SELECT a.*, u.username as actor_username
+                 FROM private_message_audit a
+                 LEFT JOIN users u ON a.actor_id = u.userid
+                 WHERE a.message_id = $1
+                 ORDER BY a.created_at, a.audit_id",
+                &[&message_id],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let previous_nonce: Vec<u8> = row.get("previous_nonce");
+                let previous_signature: Option<Vec<u8>> = row.get("previous_signature");
+                MessageAuditEntryResponse {
+                    audit_id: row.get("audit_id"),
+                    message_id: row.get("message_id"),
+                    actor_id: row.get("actor_id"),
+                    actor_username: row.get("actor_username"),
+                    action: row.get("action"),
+                    previous_content: row.get("previous_content"),
+                    previous_nonce: general_purpose::STANDARD.encode(&previous_nonce),
+                    previous_signature: previous_signature
+                        .map(|sig| general_purpose::STANDARD.encode(&sig)),
+                    created_at: row.get("created_at"),
+                }
+            })
+            .collect())
+    }
+
+    /// Searches message content and thread names in the user's threads, best matches first.
+    /// Deleted messages, messages from users the searcher blocked and direct threads with them
+    /// are left out; encrypted threads only match by name.
+    pub async fn search_messages(
+        &self,
+        user_id: i32,
+        query: SearchMessagesQuery,
+    ) -> AppResult<MessageSearchResponse> {
+        let page = query.page.max(1);
+        let per_page = query.per_page.clamp(1, 100);
+        let offset = (page - 1) * per_page;
+        let headline_options = format!(
+            "StartSel=\"{}\", StopSel=\"{}\", MaxFragments=2, MaxWords=25, MinWords=10, FragmentDelimiter=\" … \"",
+            HIGHLIGHT_START, HIGHLIGHT_STOP
+        );
+
+        let client = self.pool.get().await?;
+
+        // $1 user, $2 search text, $3 thread filter
+        let hits_cte = "q AS (SELECT websearch_to_tsquery('simple', $2::text) AS query),
+            threads AS (
+                SELECT mt.thread_id, mt.thread_name, mt.thread_type,
+                       COALESCE(mt.last_message_at, mt.created_at) AS active_at
+                FROM message_threads mt
+                JOIN thread_participants tp ON tp.thread_id = mt.thread_id
+                WHERE tp.user_id = $1 AND tp.is_active = TRUE AND mt.is_active = TRUE
+                  AND ($3::bigint IS NULL OR mt.thread_id = $3)
+                  AND NOT (mt.thread_type = 'direct' AND EXISTS (
+                      SELECT 1 FROM thread_participants other
+                      WHERE other.thread_id = mt.thread_id AND other.user_id != $1
+                        AND is_user_blocked($1, other.user_id)))
+            ),
+            hits AS (
+                SELECT m.thread_id, m.message_id, m.sender_id, m.created_at,
+                       ts_rank_cd(m.search_vector, q.query, 32) AS rank
+                FROM threads t
+                JOIN private_messages m ON m.thread_id = t.thread_id, q
+                WHERE m.search_vector @@ q.query AND m.is_deleted = FALSE
+                  AND NOT is_user_blocked($1, m.sender_id)
+                UNION ALL
+                SELECT t.thread_id, NULL, NULL, t.active_at,
+                       ts_rank_cd(to_tsvector('simple', t.thread_name), q.query, 32) AS rank
+                FROM threads t, q
+                WHERE to_tsvector('simple', COALESCE(t.thread_name, '')) @@ q.query
+            )";
+
+        // Headlines are only computed for the rows on the requested page
+        let rows = client
+            .query(
+                &format!(
+                    "WITH {hits_cte},
+                    page AS (
+                        SELECT * FROM hits
+                        ORDER BY rank DESC, created_at DESC, message_id DESC NULLS FIRST
+                        LIMIT $4 OFFSET $5
+                    )
+                    SELECT p.*, t.thread_name, t.thread_type, u.username AS sender_username,
+                           ts_headline('simple',
+                               CASE WHEN p.message_id IS NULL THEN t.thread_name
+                                    ELSE LEFT(private_message_text(m.encrypted_content), 500000)
+                               END,
+                               q.query, $6) AS snippet
+                    FROM page p
+                    JOIN threads t ON t.thread_id = p.thread_id
+                    LEFT JOIN private_messages m ON m.message_id = p.message_id
+                    LEFT JOIN users u ON u.userid = p.sender_id, q
+                    ORDER BY p.rank DESC, p.created_at DESC, p.message_id DESC NULLS FIRST"
+                ),
+                &[
+                    &user_id,
+                    &query.q,
+                    &query.thread_id,
+                    &per_page,
+                    &offset,
+                    &headline_options,
+                ],
+            )
+            .await?;
+
+        let total_count: i64 = client
+            .query_one(
+                &format!("WITH {hits_cte} SELECT COUNT(*) FROM hits"),
+                &[&user_id, &query.q, &query.thread_id],
+            )
+            .await?
+            .get(0);
+
+        let results: Vec<MessageSearchHit> = rows
+            .iter()
+            .map(|row| {
+                let thread_id: i64 = row.get("thread_id");
+                let message_id: Option<i64> = row.get("message_id");
+                MessageSearchHit {
+                    thread_id,
+                    thread_name: row.get("thread_name"),
+                    thread_type: row.get("thread_type"),
+                    message_id,
+                    sender_id: row.get("sender_id"),
+                    sender_username: row.get("sender_username"),
+                    created_at: row.get("created_at"),
+                    snippet: render_snippet(&row.get::<_, String>("snippet")),
+                    rank: row.get("rank"),
+                    cursor: message_id.map(|message_id| MessageCursor {
+                        thread_id,
+                        before_message_id: message_id + 1,
+                    }),
+                }
+            })
+            .collect();
+
+        let has_more = (offset + results.len() as i64) < total_count;
+
+        Ok(MessageSearchResponse {
+            results,
+            total_count,
+            page,
+            per_page,
+            has_more,
+        })
+    }
+
     // Helper methods
     async fn get_thread_participants(
         &self,
@@ -682,6 +1365,61 @@ impl MessagingService {
         Ok(participants)
     }
 
+    async fn get_participant(
+        &self,
+        thread_id: i64,
+        user_id: i32,
+    ) -> AppResult<ThreadParticipantResponse> {
+        self.get_thread_participants(thread_id, None)
+            .await?
+            .into_iter()
+            .find(|participant| participant.user_id == user_id)
+            .ok_or_else(|| AppError::NotFound("Participant not found".to_string()))
+    }
+
+    fn broadcast(&self, user_ids: Vec<i32>, message: &WebSocketMessage, exclude_user: Option<i32>) {
+        if let Some(chat_server) = &self.chat_server {
+            if let Ok(json) = serde_json::to_string(message) {
+                chat_server.do_send(BroadcastToUsers {
+                    user_ids,
+                    message_json: json,
+                    exclude_user,
+                });
+            }
+        }
+    }
+
+    /// Asks the participants of an encrypted thread to share a key for its new version.
+    async fn announce_key_rotation(&self, thread_id: i64, key_version: Option<i32>) {
+        if let Some(key_version) = key_version {
+            self.broadcast_to_thread(
+                thread_id,
+                &WebSocketMessage::ThreadKeyRotated {
+                    thread_id,
+                    key_version,
+                },
+                None,
+            )
+            .await;
+        }
+    }
+
+    /// Sends an event to the active participants of a thread over WebSocket.
+    async fn broadcast_to_thread(
+        &self,
+        thread_id: i64,
+        message: &WebSocketMessage,
+        exclude_user: Option<i32>,
+    ) {
+        if self.chat_server.is_none() {
+            return;
+        }
+        match self.get_thread_participant_user_ids(thread_id).await {
+            Ok(user_ids) => self.broadcast(user_ids, message, exclude_user),
+            Err(e) => log::error!("Failed to load participants of thread {}: {}", thread_id, e),
+        }
+    }
+
     pub async fn get_thread_participant_user_ids(&self, thread_id: i64) -> AppResult<Vec<i32>> {
         let client = self.pool.get().await?;
 
@@ -739,6 +1477,72 @@ impl MessagingService {
         Ok(row.get(0))
     }
 
+    pub async fn block_user(
+        &self,
+        blocker_id: i32,
+        request: BlockUserRequest,
+    ) -> AppResult<BlockedUserResponse> {
+        if request.user_id == blocker_id {
+            return Err(AppError::BadRequest(
+                "You cannot block yourself".to_string(),
+            ));
+        }
+
+        let client = self.pool.get().await?;
+
+        // Blocking again reactivates an earlier block with the new reason
+        let row = client
+            .query_opt(
+                "INSERT INTO user_message_blocks (blocker_id, blocked_id, reason)
+                 SELECT $1, u.userid, $3 FROM users u WHERE u.userid = $2
+                 ON CONFLICT (blocker_id, blocked_id) DO UPDATE
+                 SET is_active = TRUE, reason = EXCLUDED.reason, blocked_at = CURRENT_TIMESTAMP
+                 RETURNING block_id, blocked_id, blocked_at, reason,
+                           (SELECT username FROM users WHERE userid = $2) as blocked_username",
+                &[&blocker_id, &request.user_id, &request.reason],
+            )
+            .await?
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+        Ok(blocked_user_from_row(&row))
+    }
+
+    pub async fn unblock_user(&self, blocker_id: i32, blocked_id: i32) -> AppResult<()> {
+        let client = self.pool.get().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE user_message_blocks SET is_active = FALSE
+                 WHERE blocker_id = $1 AND blocked_id = $2 AND is_active = TRUE",
+                &[&blocker_id, &blocked_id],
+            )
+            .await?;
+
+        if updated == 0 {
+            return Err(AppError::NotFound("Block not found".to_string()));
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_blocked_users(&self, blocker_id: i32) -> AppResult<Vec<BlockedUserResponse>> {
+        let client = self.pool.get().await?;
+
+        let rows = client
+            .query(
+                "SELECT b.block_id, b.blocked_id, b.blocked_at, b.reason,
+                        u.username as blocked_username
+                 FROM user_message_blocks b
+                 JOIN users u ON b.blocked_id = u.userid
+                 WHERE b.blocker_id = $1 AND b.is_active = TRUE
+                 ORDER BY b.blocked_at DESC",
+                &[&blocker_id],
+            )
+            .await?;
+
+        Ok(rows.iter().map(blocked_user_from_row).collect())
+    }
+
     // WebRTC signaling methods
     pub async fn send_webrtc_signal(
         &self,
@@ -864,3 +1668,365 @@ impl MessagingService {
         Ok(active_calls)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deadpool_postgres::{Config, Runtime};
+    use std::env;
+    use uuid::Uuid;
+
+    fn test_pool() -> Pool {
+        dotenvy::dotenv().ok();
+        let mut cfg = Config::new();
+        cfg.host = Some(env::var("DB_HOST").unwrap_or_else(|_| "localhost".into()));
+        cfg.port = Some(
+            env::var("DB_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(5432),
+        );
+        cfg.user = Some(env::var("DB_USER").expect("DB_USER"));
+        cfg.password = Some(env::var("DB_PASSWORD").expect("DB_PASSWORD"));
+        cfg.dbname = Some(env::var("DB_NAME").expect("DB_NAME"));
+        cfg.create_pool(Some(Runtime::Tokio1), tokio_postgres::NoTls)
+            .unwrap()
+    }
+
+    async fn create_user(transaction: &Transaction<'_>) -> i32 {
+        let username = format!("messaging-{}", &Uuid::new_v4().simple().to_string()[..8]);
+        let email = format!("{}@example.org", username);
+        transaction
+            .query_one(
+                "INSERT INTO users (username, email, password, created_at, role, email_confirmed, votesize)
+                 VALUES ($1, $2, 'x', NOW(), 'user', true, 1.0)
+                 RETURNING userid",
+                &[&username, &email],
+            )
+            .await
+            .unwrap()
+            .get("userid")
+    }
+
+    /// A group thread with an admin and a member, returned as (thread, admin, member).
+    async fn group_thread(transaction: &Transaction<'_>) -> (i64, i32, i32) {
+        let admin = create_user(transaction).await;
+        let member = create_user(transaction).await;
+        let thread_id: i64 = transaction
+            .query_one(
+                "INSERT INTO message_threads (thread_type, created_by) VALUES ('group', $1)
+                 RETURNING thread_id",
+                &[&admin],
+            )
+            .await
+            .unwrap()
+            .get("thread_id");
+        transaction
+            .execute(
+                "INSERT INTO thread_participants (thread_id, user_id, role)
+                 VALUES ($1, $2, 'admin'), ($1, $3, 'member')",
+                &[&thread_id, &admin, &member],
+            )
+            .await
+            .unwrap();
+        (thread_id, admin, member)
+    }
+
+    #[test]
+    fn only_group_admins_manage_participants() {
+        assert!(matches!(
+            check_group_admin(None),
+            Err(AppError::NotFound(_))
+        ));
+        assert!(matches!(
+            check_group_admin(Some((ParticipantRole::Admin, ThreadType::Direct))),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            check_group_admin(Some((ParticipantRole::Member, ThreadType::Group))),
+            Err(AppError::Auth(_))
+        ));
+        assert!(check_group_admin(Some((ParticipantRole::Admin, ThreadType::Group))).is_ok());
+    }
+
+    #[test]
+    fn any_group_participant_can_leave() {
+        assert!(matches!(check_can_leave(None), Err(AppError::NotFound(_))));
+        assert!(matches!(
+            check_can_leave(Some((ParticipantRole::Member, ThreadType::Direct))),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(check_can_leave(Some((ParticipantRole::Member, ThreadType::Group))).is_ok());
+        assert!(check_can_leave(Some((ParticipantRole::Admin, ThreadType::Group))).is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn membership_is_read_from_active_threads_and_participants() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        let (thread_id, admin, member) = group_thread(&transaction).await;
+        let stranger = create_user(&transaction).await;
+
+        assert_eq!(
+            lock_membership(&transaction, thread_id, admin)
+                .await
+                .unwrap(),
+            Some((ParticipantRole::Admin, ThreadType::Group))
+        );
+        assert_eq!(
+            lock_membership(&transaction, thread_id, member)
+                .await
+                .unwrap(),
+            Some((ParticipantRole::Member, ThreadType::Group))
+        );
+        assert_eq!(
+            lock_membership(&transaction, thread_id, stranger)
+                .await
+                .unwrap(),
+            None
+        );
+
+        transaction
+            .execute(
+                "UPDATE thread_participants SET is_active = FALSE
+                 WHERE thread_id = $1 AND user_id = $2",
+                &[&thread_id, &member],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            lock_membership(&transaction, thread_id, member)
+                .await
+                .unwrap(),
+            None
+        );
+
+        transaction
+            .execute(
+                "UPDATE message_threads SET is_active = FALSE WHERE thread_id = $1",
+                &[&thread_id],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            lock_membership(&transaction, thread_id, admin)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn the_last_admin_stays_while_others_remain() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        let (thread_id, admin, member) = group_thread(&transaction).await;
+
+        // The only admin can neither leave nor step down while the member is still there
+        assert!(matches!(
+            ensure_other_admin(&transaction, thread_id, admin, true).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            ensure_other_admin(&transaction, thread_id, admin, false).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(ensure_other_admin(&transaction, thread_id, member, true)
+            .await
+            .is_ok());
+
+        // Alone in the thread, the admin may leave it but still not step down
+        transaction
+            .execute(
+                "UPDATE thread_participants SET is_active = FALSE
+                 WHERE thread_id = $1 AND user_id = $2",
+                &[&thread_id, &member],
+            )
+            .await
+            .unwrap();
+        assert!(ensure_other_admin(&transaction, thread_id, admin, true)
+            .await
+            .is_ok());
+        assert!(matches!(
+            ensure_other_admin(&transaction, thread_id, admin, false).await,
+            Err(AppError::BadRequest(_))
+        ));
+
+        // With a second admin, either may step down
+        transaction
+            .execute(
+                "UPDATE thread_participants SET is_active = TRUE, role = 'admin'
+                 WHERE thread_id = $1 AND user_id = $2",
+                &[&thread_id, &member],
+            )
+            .await
+            .unwrap();
+        assert!(ensure_other_admin(&transaction, thread_id, admin, false)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn search_leaves_out_blocked_users_and_encrypted_content() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let word = format!("zq{}", &Uuid::new_v4().simple().to_string()[..8]);
+        let encode = |text: &str| general_purpose::STANDARD.encode(text);
+
+        // search runs on its own connection, so the fixture is committed and removed at the end
+        let transaction = client.transaction().await.unwrap();
+        let (group, searcher, friend) = group_thread(&transaction).await;
+        let blocked = create_user(&transaction).await;
+        let mut thread_ids = vec![group];
+        for (thread_type, name, is_encrypted, other) in [
+            ("direct", None, false, blocked),
+            ("group", Some(format!("{} vault", word)), true, friend),
+        ] {
+            let thread_id: i64 = transaction
+                .query_one(
+                    "INSERT INTO message_threads (thread_type, thread_name, created_by, is_encrypted, key_version)
+                     VALUES ($1, $2, $3, $4, CASE WHEN $4 THEN 1 ELSE 0 END)
+                     RETURNING thread_id",
+                    &[&thread_type, &name, &searcher, &is_encrypted],
+                )
+                .await
+                .unwrap()
+                .get("thread_id");
+            transaction
+                .execute(
+                    "INSERT INTO thread_participants (thread_id, user_id, role)
+                     VALUES ($1, $2, 'admin'), ($1, $3, 'member')",
+                    &[&thread_id, &searcher, &other],
+                )
+                .await
+                .unwrap();
+            thread_ids.push(thread_id);
+        }
+        let [_, direct, encrypted] = thread_ids[..] else {
+            unreachable!()
+        };
+        transaction
+            .execute(
+                "INSERT INTO thread_participants (thread_id, user_id) VALUES ($1, $2)",
+                &[&group, &blocked],
+            )
+            .await
+            .unwrap();
+        transaction
+            .execute(
+                "INSERT INTO user_message_blocks (blocker_id, blocked_id) VALUES ($1, $2)",
+                &[&searcher, &blocked],
+            )
+            .await
+            .unwrap();
+
+        let nonce = vec![0u8; 12];
+        let messages = [
+            (
+                group,
+                friend,
+                encode(&format!("{} from a friend", word)),
+                None,
+                false,
+            ),
+            (
+                group,
+                friend,
+                encode(&format!("{} deleted", word)),
+                None,
+                true,
+            ),
+            (
+                group,
+                blocked,
+                encode(&format!("{} from a blocked user", word)),
+                None,
+                false,
+            ),
+            (
+                direct,
+                searcher,
+                encode(&format!("{} to a blocked user", word)),
+                None,
+                false,
+            ),
+            (encrypted, friend, encode(&word), Some(1), false),
+        ];
+        let mut message_ids = Vec::new();
+        for (thread_id, sender_id, content, key_version, is_deleted) in &messages {
+            let message_id: i64 = transaction
+                .query_one(
+                    "INSERT INTO private_messages
+                         (thread_id, sender_id, encrypted_content, content_nonce, key_version, is_deleted)
+                     VALUES ($1, $2, $3, $4, $5, $6)
+                     RETURNING message_id",
+                    &[thread_id, sender_id, content, &nonce, key_version, is_deleted],
+                )
+                .await
+                .unwrap()
+                .get("message_id");
+            message_ids.push(message_id);
+        }
+        transaction.commit().await.unwrap();
+
+        let service = MessagingService::new(pool.clone(), None);
+        let search = |user_id| {
+            service.search_messages(
+                user_id,
+                SearchMessagesQuery {
+                    q: word.clone(),
+                    thread_id: None,
+                    page: 1,
+                    per_page: 20,
+                },
+            )
+        };
+        let searcher_hits = search(searcher).await;
+        let friend_hits = search(friend).await;
+
+        client
+            .execute(
+                "DELETE FROM message_threads WHERE thread_id = ANY($1)",
+                &[&thread_ids],
+            )
+            .await
+            .unwrap();
+        // New users get a balance row from a trigger
+        let user_ids = vec![searcher, friend, blocked];
+        for cleanup in [
+            "DELETE FROM user_balances WHERE user_id = ANY($1)",
+            "DELETE FROM users WHERE userid = ANY($1)",
+        ] {
+            client.execute(cleanup, &[&user_ids]).await.unwrap();
+        }
+
+        let hits = |response: MessageSearchResponse| {
+            let mut hits: Vec<(i64, Option<i64>)> = response
+                .results
+                .iter()
+                .map(|hit| (hit.thread_id, hit.message_id))
+                .collect();
+            hits.sort();
+            hits
+        };
+        // The encrypted thread only matches by name, and nothing from the blocked user shows up
+        assert_eq!(
+            hits(searcher_hits.unwrap()),
+            vec![(group, Some(message_ids[0])), (encrypted, None)]
+        );
+        // Blocks only hide messages from the user who made them
+        assert_eq!(
+            hits(friend_hits.unwrap()),
+            vec![
+                (group, Some(message_ids[0])),
+                (group, Some(message_ids[2])),
+                (encrypted, None),
+            ]
+        );
+    }
+}
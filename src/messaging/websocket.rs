@@ -1,21 +1,32 @@
 // WebSocket service for real-time messaging
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 
 use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, Message, Running, StreamHandler};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use futures::StreamExt;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio::sync::mpsc;
 
 use super::dto::WebSocketMessage;
-use crate::auth::decode_token;
+use crate::auth::service::authenticate_session_token;
 
 static SESSION_ID: AtomicUsize = AtomicUsize::new(0);
 
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// Redis channel on which instances share chat events with each other's sockets.
+const CHAT_CHANNEL: &str = "lensisku:chat:events";
+const BUS_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How often each instance publishes everyone connected to it.
+const PRESENCE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Users of an instance that has not been heard from for this long are considered offline.
+const PRESENCE_TTL: Duration = Duration::from_secs(45);
 
 #[derive(Message, Clone)]
 #[rtype(result = "()")]
@@ -66,6 +77,56 @@ pub struct BroadcastToUsers {
     pub exclude_user: Option<i32>,
 }
 
+/// A chat event published by one instance for the sockets connected to the others.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum BusEvent {
+    Users {
+        user_ids: Vec<i32>,
+        message_json: String,
+        exclude_user: Option<i32>,
+    },
+    Thread {
+        thread_id: i64,
+        message_json: String,
+        exclude_user: Option<i32>,
+    },
+    Presence {
+        user_id: i32,
+        username: String,
+        online: bool,
+    },
+    /// Everyone with a session on the origin instance; replaces what was known about it.
+    PresenceSnapshot { users: Vec<PresentUser> },
+    /// Asks the other instances to publish their snapshots.
+    PresenceSyncRequest,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PresentUser {
+    user_id: i32,
+    username: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BusEnvelope {
+    origin: String,
+    event: BusEvent,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct RemoteEvent {
+    origin: String,
+    event: BusEvent,
+}
+
+/// Sent when the bus subscription (re)starts so other instances learn who is online here, and
+/// answer with who is online there.
+#[derive(Message)]
+#[rtype(result = "()")]
+struct AnnouncePresence;
+
 pub struct WsSession {
     pub id: usize,
     pub user_id: i32,
@@ -212,18 +273,37 @@ struct SessionInfo {
     addr: Addr<WsSession>,
 }
 
+/// Routes chat events to the sockets connected to this instance. Every event is also published
+/// on a Redis channel, and events published by other instances are delivered to the local sockets
+/// they concern, so replicas can serve the same threads.
 pub struct ChatServer {
     sessions: HashMap<usize, SessionInfo>,
     user_sessions: HashMap<i32, Vec<usize>>,
     thread_participants: HashMap<i64, Vec<i32>>,
+    /// Users with a session on each other instance. Instances publish a snapshot every
+    /// [`PRESENCE_HEARTBEAT_INTERVAL`]; one that stays silent for [`PRESENCE_TTL`] is dropped
+    /// with its users.
+    remote_presence: HashMap<String, RemoteInstance>,
+    instance_id: String,
+    redis: redis::Client,
+    bus: Option<mpsc::UnboundedSender<String>>,
+}
+
+struct RemoteInstance {
+    last_seen: Instant,
+    users: HashMap<i32, String>,
 }
 
 impl ChatServer {
-    pub fn new() -> Self {
+    pub fn new(redis: redis::Client) -> Self {
         Self {
             sessions: HashMap::new(),
             user_sessions: HashMap::new(),
             thread_participants: HashMap::new(),
+            remote_presence: HashMap::new(),
+            instance_id: uuid::Uuid::new_v4().to_string(),
+            redis,
+            bus: None,
         }
     }
 
@@ -241,16 +321,210 @@ impl ChatServer {
             }
         }
     }
+
+    fn is_online_elsewhere(&self, user_id: i32) -> bool {
+        self.remote_presence
+            .values()
+            .any(|instance| instance.users.contains_key(&user_id))
+    }
+
+    fn remote_users(&self) -> HashMap<i32, String> {
+        self.remote_presence
+            .values()
+            .flat_map(|instance| instance.users.iter())
+            .map(|(&user_id, username)| (user_id, username.clone()))
+            .collect()
+    }
+
+    /// Applies `change` to what is known about other instances. Local sockets only hear about
+    /// users that came online or went offline everywhere as a result.
+    fn update_remote_presence(
+        &mut self,
+        change: impl FnOnce(&mut HashMap<String, RemoteInstance>),
+    ) {
+        let before = self.remote_users();
+        change(&mut self.remote_presence);
+        let after = self.remote_users();
+        for (user_id, username) in &after {
+            if !before.contains_key(user_id) && !self.user_sessions.contains_key(user_id) {
+                self.broadcast_user_status(*user_id, username, "online");
+            }
+        }
+        for (user_id, username) in &before {
+            if !after.contains_key(user_id) && !self.user_sessions.contains_key(user_id) {
+                self.broadcast_user_status(*user_id, username, "offline");
+            }
+        }
+    }
+
+    /// Updates the users known for `origin` and marks it as heard from.
+    fn refresh_remote_instance(
+        &mut self,
+        origin: String,
+        change: impl FnOnce(&mut HashMap<i32, String>),
+    ) {
+        self.update_remote_presence(|instances| {
+            let instance = instances.entry(origin).or_insert_with(|| RemoteInstance {
+                last_seen: Instant::now(),
+                users: HashMap::new(),
+            });
+            instance.last_seen = Instant::now();
+            change(&mut instance.users);
+        });
+    }
+
+    fn presence_snapshot(&self) -> BusEvent {
+        let mut announced = HashSet::new();
+        let users = self
+            .sessions
+            .values()
+            .filter(|info| announced.insert(info.user_id))
+            .map(|info| PresentUser {
+                user_id: info.user_id,
+                username: info.username.clone(),
+            })
+            .collect();
+        BusEvent::PresenceSnapshot { users }
+    }
+
+    fn send_to_users(&self, user_ids: &[i32], message_json: &str, exclude_user: Option<i32>) {
+        for &user_id in user_ids {
+            if Some(user_id) == exclude_user {
+                continue;
+            }
+            if let Some(ids) = self.user_sessions.get(&user_id) {
+                for id in ids {
+                    if let Some(info) = self.sessions.get(id) {
+                        info.addr.do_send(WsMessage(message_json.to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    fn send_to_thread(&self, thread_id: i64, message_json: &str, exclude_user: Option<i32>) {
+        if let Some(participants) = self.thread_participants.get(&thread_id) {
+            self.send_to_users(participants, message_json, exclude_user);
+        }
+    }
+
+    /// Hands an event to the publisher task. Events are dropped, with an error logged, while
+    /// Redis is unreachable; sockets on this instance still get them.
+    fn publish(&self, event: BusEvent) {
+        let Some(bus) = &self.bus else {
+            return;
+        };
+        let envelope = BusEnvelope {
+            origin: self.instance_id.clone(),
+            event,
+        };
+        match serde_json::to_string(&envelope) {
+            Ok(payload) => {
+                if bus.send(payload).is_err() {
+                    error!("Chat event publisher has stopped");
+                }
+            }
+            Err(e) => error!("Failed to serialize chat event: {}", e),
+        }
+    }
+
+    fn start_publisher(&mut self) {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+        self.bus = Some(sender);
+        let client = self.redis.clone();
+
+        tokio::spawn(async move {
+            let mut connection = None;
+            while let Some(payload) = receiver.recv().await {
+                if connection.is_none() {
+                    match client.get_multiplexed_async_connection().await {
+                        Ok(conn) => connection = Some(conn),
+                        Err(e) => error!("Failed to connect to publish chat events: {}", e),
+                    }
+                }
+                if let Some(conn) = connection.as_mut() {
+                    let published: redis::RedisResult<i64> =
+                        redis::AsyncCommands::publish(conn, CHAT_CHANNEL, payload).await;
+                    if let Err(e) = published {
+                        error!("Failed to publish chat event: {}", e);
+                        connection = None;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Delivers events from other instances until this actor stops, resubscribing after the
+    /// connection drops.
+    fn start_subscriber(&self, addr: Addr<Self>) {
+        let client = self.redis.clone();
+        let instance_id = self.instance_id.clone();
+
+        tokio::spawn(async move {
+            while addr.connected() {
+                match client.get_async_pubsub().await {
+                    Ok(mut pubsub) => match pubsub.subscribe(CHAT_CHANNEL).await {
+                        Ok(()) => {
+                            info!("Listening for chat events on {}", CHAT_CHANNEL);
+                            addr.do_send(AnnouncePresence);
+                            let mut messages = pubsub.on_message();
+                            while let Some(message) = messages.next().await {
+                                let envelope = message
+                                    .get_payload::<String>()
+                                    .map_err(|e| e.to_string())
+                                    .and_then(|payload| {
+                                        serde_json::from_str::<BusEnvelope>(&payload)
+                                            .map_err(|e| e.to_string())
+                                    });
+                                match envelope {
+                                    Ok(envelope) if envelope.origin != instance_id => {
+                                        addr.do_send(RemoteEvent {
+                                            origin: envelope.origin,
+                                            event: envelope.event,
+                                        })
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => error!("Ignoring malformed chat event: {}", e),
+                                }
+                            }
+                            error!("Chat event subscription closed");
+                        }
+                        Err(e) => error!("Failed to subscribe to chat events: {}", e),
+                    },
+                    Err(e) => error!("Failed to connect for chat events: {}", e),
+                }
+                tokio::time::sleep(BUS_RECONNECT_DELAY).await;
+            }
+        });
+    }
 }
 
 impl Actor for ChatServer {
     type Context = actix::Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.start_publisher();
+        self.start_subscriber(ctx.address());
+        ctx.run_interval(PRESENCE_HEARTBEAT_INTERVAL, |act, _| {
+            act.publish(act.presence_snapshot());
+            act.update_remote_presence(|instances| {
+                instances.retain(|_, instance| instance.last_seen.elapsed() < PRESENCE_TTL)
+            });
+        });
+    }
 }
 
 impl Handler<Connect> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: Connect, _: &mut Self::Context) {
+        if !self.user_sessions.contains_key(&msg.user_id) {
+            self.publish(BusEvent::Presence {
+                user_id: msg.user_id,
+                username: msg.username.clone(),
+                online: true,
+            });
+        }
         self.user_sessions
             .entry(msg.user_id)
             .or_default()
@@ -280,7 +554,14 @@ impl Handler<Disconnect> for ChatServer {
                     for participants in self.thread_participants.values_mut() {
                         participants.retain(|&uid| uid != info.user_id);
                     }
-                    self.broadcast_user_status(info.user_id, &info.username, "offline");
+                    self.publish(BusEvent::Presence {
+                        user_id: info.user_id,
+                        username: info.username.clone(),
+                        online: false,
+                    });
+                    if !self.is_online_elsewhere(info.user_id) {
+                        self.broadcast_user_status(info.user_id, &info.username, "offline");
+                    }
                 }
             }
         }
@@ -312,21 +593,13 @@ impl Handler<BroadcastToThread> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: BroadcastToThread, _: &mut Self::Context) {
-        if let Some(participants) = self.thread_participants.get(&msg.thread_id) {
-            if let Ok(json) = serde_json::to_string(&msg.message) {
-                for &user_id in participants {
-                    if Some(user_id) == msg.exclude_user {
-                        continue;
-                    }
-                    if let Some(ids) = self.user_sessions.get(&user_id) {
-                        for id in ids {
-                            if let Some(info) = self.sessions.get(id) {
-                                info.addr.do_send(WsMessage(json.clone()));
-                            }
-                        }
-                    }
-                }
-            }
+        if let Ok(json) = serde_json::to_string(&msg.message) {
+            self.send_to_thread(msg.thread_id, &json, msg.exclude_user);
+            self.publish(BusEvent::Thread {
+                thread_id: msg.thread_id,
+                message_json: json,
+                exclude_user: msg.exclude_user,
+            });
         }
     }
 }
@@ -335,21 +608,63 @@ impl Handler<BroadcastToUsers> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: BroadcastToUsers, _: &mut Self::Context) {
-        for &user_id in &msg.user_ids {
-            if Some(user_id) == msg.exclude_user {
-                continue;
-            }
-            if let Some(ids) = self.user_sessions.get(&user_id) {
-                for id in ids {
-                    if let Some(info) = self.sessions.get(id) {
-                        info.addr.do_send(WsMessage(msg.message_json.clone()));
-                    }
+        self.send_to_users(&msg.user_ids, &msg.message_json, msg.exclude_user);
+        self.publish(BusEvent::Users {
+            user_ids: msg.user_ids,
+            message_json: msg.message_json,
+            exclude_user: msg.exclude_user,
+        });
+    }
+}
+
+impl Handler<RemoteEvent> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RemoteEvent, _: &mut Self::Context) {
+        match msg.event {
+            BusEvent::Users {
+                user_ids,
+                message_json,
+                exclude_user,
+            } => self.send_to_users(&user_ids, &message_json, exclude_user),
+            BusEvent::Thread {
+                thread_id,
+                message_json,
+                exclude_user,
+            } => self.send_to_thread(thread_id, &message_json, exclude_user),
+            BusEvent::Presence {
+                user_id,
+                username,
+                online,
+            } => self.refresh_remote_instance(msg.origin, |users| {
+                if online {
+                    users.insert(user_id, username);
+                } else {
+                    users.remove(&user_id);
                 }
+            }),
+            BusEvent::PresenceSnapshot { users } => {
+                self.refresh_remote_instance(msg.origin, |known| {
+                    *known = users
+                        .into_iter()
+                        .map(|user| (user.user_id, user.username))
+                        .collect();
+                })
             }
+            BusEvent::PresenceSyncRequest => self.publish(self.presence_snapshot()),
         }
     }
 }
 
+impl Handler<AnnouncePresence> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, _: AnnouncePresence, _: &mut Self::Context) {
+        self.publish(self.presence_snapshot());
+        self.publish(BusEvent::PresenceSyncRequest);
+    }
+}
+
 /// Start a WebSocket session for a specific thread (legacy; automatically joins the thread).
 pub async fn websocket_handler(
     req: HttpRequest,
@@ -377,7 +692,7 @@ async fn start_session(
 ) -> Result<HttpResponse, Error> {
     let token = extract_token(&req);
     let claims = match token {
-        Some(t) => match decode_token(&t) {
+        Some(t) => match authenticate_session_token(&req, &t).await {
             Ok(c) => c,
             Err(_) => return Ok(HttpResponse::Unauthorized().finish()),
         },
@@ -51,6 +51,55 @@ impl KittenTtsLimiter {
     }
 }
 
+/// Per-user rate limit for mbox exports of the mail archive (`GET /mail/export`).
+/// Keys: `mail_export:user:{sub}`. Configure via `MAIL_EXPORT_RATE_LIMIT_MAX` and
+/// `MAIL_EXPORT_RATE_LIMIT_WINDOW_SECS`.
+pub struct MailExportLimiter {
+    client: Client,
+    window_secs: i64,
+    max_requests: i64,
+}
+
+impl MailExportLimiter {
+    pub fn new(redis_url: &str) -> Result<Self, redis::RedisError> {
+        let max_requests = env::var("MAIL_EXPORT_RATE_LIMIT_MAX")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(10);
+        let window_secs = env::var("MAIL_EXPORT_RATE_LIMIT_WINDOW_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(60 * 60);
+        Ok(Self {
+            client: Client::open(redis_url)?,
+            window_secs,
+            max_requests,
+        })
+    }
+
+    pub fn retry_after_secs(&self) -> i64 {
+        self.window_secs
+    }
+
+    /// Returns `true` if the request is allowed (counter incremented), `false` if over limit.
+    pub async fn check_and_record(&self, user_sub: i32) -> Result<bool, redis::RedisError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let key = format!("mail_export:user:{user_sub}");
+        // One MULTI/EXEC, so the counter can never be left without an expiry.
+        let (count,): (i64,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(self.window_secs)
+            .arg("NX")
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(count <= self.max_requests)
+    }
+}
+
 /// Rate limiter for login attempts by IP (any attempt counts).
 /// 20 attempts per 15 minutes per IP.
 pub struct LoginLimiter {
@@ -28,6 +28,10 @@ use utoipauto::utoipauto;
         (name = "flashcards", description = "Flashcard learning system endpoints"),
         (name = "payments", description = "Payments and balance handling endpoints"),
         (name = "messaging", description = "Private messaging system endpoints"),
+        (name = "webhooks", description = "Outgoing webhooks for dictionary and discussion events"),
+        (name = "reports", description = "Content reports and the moderation queue"),
+        (name = "audit", description = "Admin audit log of privileged actions"),
+        (name = "trash", description = "Deleted content that can still be restored"),
         (name = "Sessions", description = "User session management endpoints"),
     ),
     modifiers(&ApiModifier),
@@ -41,6 +45,14 @@ use utoipauto::utoipauto;
         crate::collections::dto::KittenTtsGenerateRequest,
         crate::collections::dto::MediaBulkManifestEntry,
         crate::collections::dto::MediaBulkImportResponse,
+        crate::collections::models::CollectionRole,
+        crate::collections::dto::InviteCollectionMemberRequest,
+        crate::collections::dto::UpdateCollectionMemberRequest,
+        crate::collections::dto::CollectionMemberResponse,
+        crate::collections::dto::CollectionMemberListResponse,
+        crate::collections::dto::CollectionInvitationResponse,
+        crate::collections::dto::CollectionItemHistoryEntry,
+        crate::collections::dto::CollectionItemHistoryResponse,
         crate::mailarchive::dto::SpamVoteResponse,
         crate::sessions::dto::PaginatedUserSessionsResponse,
         crate::waves::dto::WavesSearchResponse,
@@ -55,6 +67,10 @@ use utoipauto::utoipauto;
         crate::messaging::dto::UpdateParticipantRoleRequest,
         crate::messaging::dto::BlockUserRequest,
         crate::messaging::dto::WebRTCSignalRequest,
+        crate::messaging::dto::RegisterIdentityKeyRequest,
+        crate::messaging::dto::UploadSignedPrekeyRequest,
+        crate::messaging::dto::WrappedThreadKey,
+        crate::messaging::dto::DistributeThreadKeysRequest,
         crate::messaging::dto::ThreadResponse,
         crate::messaging::dto::ThreadParticipantResponse,
         crate::messaging::dto::MessageResponse,
@@ -63,9 +79,17 @@ use utoipauto::utoipauto;
         crate::messaging::dto::NotificationResponse,
         crate::messaging::dto::NotificationListResponse,
         crate::messaging::dto::BlockedUserResponse,
+        crate::messaging::dto::MessageAuditEntryResponse,
+        crate::messaging::dto::EncryptionKeyResponse,
+        crate::messaging::dto::SignedPrekeyResponse,
+        crate::messaging::dto::KeyBundleResponse,
         crate::messaging::dto::WebRTCSignalResponse,
         crate::messaging::dto::GetThreadsQuery,
         crate::messaging::dto::GetMessagesQuery,
+        crate::messaging::dto::SearchMessagesQuery,
+        crate::messaging::dto::MessageCursor,
+        crate::messaging::dto::MessageSearchHit,
+        crate::messaging::dto::MessageSearchResponse,
         crate::messaging::dto::GetNotificationsQuery,
     ))
 )]
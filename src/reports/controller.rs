@@ -0,0 +1,203 @@
+use actix_web::{get, post, web, HttpResponse};
+use deadpool_postgres::Pool;
+
+use super::{dto::*, service};
+use crate::audit::AuditContext;
+use crate::middleware::cache::RedisCache;
+use crate::{auth::permissions::PermissionCache, auth::Claims, AppError};
+
+async fn is_moderator(claims: &Claims, perm_cache: &PermissionCache) -> bool {
+    perm_cache
+        .claims_have_permission(claims, "moderate_reports")
+        .await
+}
+
+async fn require_moderator(claims: &Claims, perm_cache: &PermissionCache) -> Result<(), AppError> {
+    if is_moderator(claims, perm_cache).await {
+        Ok(())
+    } else {
+        Err(AppError::Auth(
+            "The report queue needs the moderate_reports permission".to_string(),
+        ))
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/reports",
+    tag = "reports",
+    request_body = CreateReportRequest,
+    responses(
+        (status = 201, description = "Report filed", body = ReportResponse),
+        (status = 400, description = "Already reported, too many reports or details too long"),
+        (status = 404, description = "Reported content not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Report content",
+    description = "Flags a comment, definition, collection or wiki page (`wiki_page`, by `wiki_articles` id) for \
+                  moderators, with a reason (spam, abuse, vandalism, copyright, other) and optional details. \
+                  A user can have one pending report per item."
+)]
+#[post("")]
+pub async fn create_report(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    request: web::Json<CreateReportRequest>,
+) -> Result<HttpResponse, AppError> {
+    let report = service::create_report(&pool, claims.sub, &request).await?;
+    Ok(HttpResponse::Created().json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/reports",
+    tag = "reports",
+    params(ReportQueueQuery),
+    responses(
+        (status = 200, description = "Report queue, oldest first", body = ReportQueueResponse),
+        (status = 400, description = "Unknown status or target type"),
+        (status = 403, description = "Missing the moderate_reports permission")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Moderation queue",
+    description = "Lists reports for moderators, by default the open and claimed ones. Each report carries a \
+                  preview and the author of the reported content and how many reports about it are pending."
+)]
+#[get("")]
+pub async fn list_queue(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    query: web::Query<ReportQueueQuery>,
+) -> Result<HttpResponse, AppError> {
+    require_moderator(&claims, &perm_cache).await?;
+    let queue = service::list_queue(&pool, claims.sub, &query).await?;
+    Ok(HttpResponse::Ok().json(queue))
+}
+
+#[utoipa::path(
+    get,
+    path = "/reports/{id}",
+    tag = "reports",
+    params(("id" = i32, Path, description = "Report ID")),
+    responses(
+        (status = 200, description = "Report and its history", body = ReportDetailResponse),
+        (status = 404, description = "Report not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Get a report",
+    description = "Moderators see any report with its history of claims and resolutions. Reporters can check \
+                  the status of their own reports."
+)]
+#[get("/{id}")]
+pub async fn get_report(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let moderator = is_moderator(&claims, &perm_cache).await;
+    let report = service::get_report(&pool, claims.sub, moderator, id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[utoipa::path(
+    post,
+    path = "/reports/{id}/claim",
+    tag = "reports",
+    params(("id" = i32, Path, description = "Report ID")),
+    responses(
+        (status = 200, description = "Report claimed", body = ReportResponse),
+        (status = 400, description = "Claimed by another moderator or already closed"),
+        (status = 403, description = "Missing the moderate_reports permission"),
+        (status = 404, description = "Report not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Claim a report",
+    description = "Takes an open report so that other moderators leave it alone."
+)]
+#[post("/{id}/claim")]
+pub async fn claim_report(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    require_moderator(&claims, &perm_cache).await?;
+    let report = service::claim_report(&pool, claims.sub, id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[utoipa::path(
+    post,
+    path = "/reports/{id}/resolve",
+    tag = "reports",
+    params(("id" = i32, Path, description = "Report ID")),
+    request_body = ResolveReportRequest,
+    responses(
+        (status = 200, description = "Report resolved", body = ReportResponse),
+        (status = 400, description = "Action does not fit the report, or it is claimed by another moderator or closed"),
+        (status = 403, description = "Missing the permission for the action, or a recent second factor"),
+        (status = 404, description = "Report or version not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Resolve a report",
+    description = "Closes a report and records what was done: `none`, `hide_comment` (needs `moderate_comments`), \
+                  `revert_version` with a `version_id` of the reported definition (needs `revert_entry_version`) or \
+                  `block_user` for the author of the reported content. The result, such as the new definition \
+                  version, is linked in `resolution_ref`. Any action other than `none` also resolves the other \
+                  pending reports about the same content."
+)]
+#[post("/{id}/resolve")]
+pub async fn resolve_report(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    redis_cache: web::Data<RedisCache>,
+    claims: Claims,
+    audit: AuditContext,
+    id: web::Path<i32>,
+    request: web::Json<ResolveReportRequest>,
+) -> Result<HttpResponse, AppError> {
+    require_moderator(&claims, &perm_cache).await?;
+    let report = service::resolve_report(
+        &pool,
+        &perm_cache,
+        &redis_cache,
+        &claims,
+        &audit,
+        id.into_inner(),
+        &request,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[utoipa::path(
+    post,
+    path = "/reports/{id}/dismiss",
+    tag = "reports",
+    params(("id" = i32, Path, description = "Report ID")),
+    request_body = DismissReportRequest,
+    responses(
+        (status = 200, description = "Report dismissed", body = ReportResponse),
+        (status = 400, description = "Claimed by another moderator or already closed"),
+        (status = 403, description = "Missing the moderate_reports permission"),
+        (status = 404, description = "Report not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Dismiss a report",
+    description = "Closes a report that needs no action, with an optional note."
+)]
+#[post("/{id}/dismiss")]
+pub async fn dismiss_report(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    audit: AuditContext,
+    id: web::Path<i32>,
+    request: web::Json<DismissReportRequest>,
+) -> Result<HttpResponse, AppError> {
+    require_moderator(&claims, &perm_cache).await?;
+    let report = service::dismiss_report(&pool, &audit, id.into_inner(), &request).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
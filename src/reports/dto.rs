@@ -0,0 +1,160 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Kinds of content that can be reported. `wiki_page` ids are `wiki_articles.id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportTargetType {
+    Comment,
+    Definition,
+    Collection,
+    WikiPage,
+}
+
+impl ReportTargetType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportTargetType::Comment => "comment",
+            ReportTargetType::Definition => "definition",
+            ReportTargetType::Collection => "collection",
+            ReportTargetType::WikiPage => "wiki_page",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    Spam,
+    Abuse,
+    Vandalism,
+    Copyright,
+    Other,
+}
+
+impl ReportReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportReason::Spam => "spam",
+            ReportReason::Abuse => "abuse",
+            ReportReason::Vandalism => "vandalism",
+            ReportReason::Copyright => "copyright",
+            ReportReason::Other => "other",
+        }
+    }
+}
+
+/// What a moderator does about a report when resolving it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolutionAction {
+    /// Acknowledge the report without changing anything
+    None,
+    /// Hide the reported comment
+    HideComment,
+    /// Revert the reported definition to `version_id`
+    RevertVersion,
+    /// Block the author of the reported content
+    BlockUser,
+}
+
+impl ResolutionAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResolutionAction::None => "none",
+            ResolutionAction::HideComment => "hide_comment",
+            ResolutionAction::RevertVersion => "revert_version",
+            ResolutionAction::BlockUser => "block_user",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateReportRequest {
+    pub target_type: ReportTargetType,
+    pub target_id: i32,
+    pub reason: ReportReason,
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResolveReportRequest {
+    pub action: ResolutionAction,
+    /// Version to restore, for `revert_version`
+    pub version_id: Option<i32>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DismissReportRequest {
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ReportQueueQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    /// open, claimed, resolved or dismissed; open and claimed reports when omitted
+    pub status: Option<String>,
+    /// comment, definition, collection or wiki_page
+    pub target_type: Option<String>,
+    /// Only reports claimed by the current moderator
+    #[serde(default)]
+    pub mine: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReportResponse {
+    pub id: i32,
+    pub reporter_id: i32,
+    pub reporter_username: String,
+    pub target_type: String,
+    pub target_id: i32,
+    /// Short excerpt of the reported content, if it still exists
+    pub target_preview: Option<String>,
+    /// Author or owner of the reported content
+    pub target_user_id: Option<i32>,
+    pub reason: String,
+    pub details: Option<String>,
+    pub status: String,
+    pub claimed_by: Option<i32>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub resolved_by: Option<i32>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolution_action: Option<String>,
+    pub resolution_ref: Option<serde_json::Value>,
+    pub resolution_note: Option<String>,
+    /// Pending reports about the same target, including this one
+    pub pending_reports_on_target: i64,
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReportEventResponse {
+    pub id: i64,
+    pub actor_id: Option<i32>,
+    pub actor_username: Option<String>,
+    pub event: String,
+    pub note: Option<String>,
+    pub data: Option<serde_json::Value>,
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReportDetailResponse {
+    pub report: ReportResponse,
+    pub events: Vec<ReportEventResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReportQueueResponse {
+    pub reports: Vec<ReportResponse>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+}
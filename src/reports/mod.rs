@@ -0,0 +1,19 @@
+pub mod controller;
+pub mod dto;
+pub mod service;
+
+use actix_web::web;
+use actix_web_httpauth::middleware::HttpAuthentication;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/reports")
+            .wrap(HttpAuthentication::bearer(crate::auth::validator))
+            .service(controller::create_report)
+            .service(controller::list_queue)
+            .service(controller::get_report)
+            .service(controller::claim_report)
+            .service(controller::resolve_report)
+            .service(controller::dismiss_report),
+    );
+}
@@ -0,0 +1,649 @@
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use serde_json::json;
+use tokio_postgres::Row;
+
+use super::dto::{
+    CreateReportRequest, DismissReportRequest, ReportDetailResponse, ReportEventResponse,
+    ReportQueueQuery, ReportQueueResponse, ReportResponse, ReportTargetType, ResolutionAction,
+    ResolveReportRequest,
+};
+use crate::audit::{self, AuditAction, AuditContext};
+use crate::auth::{self, permissions::PermissionCache, two_factor, Claims};
+use crate::error::{AppError, AppResult};
+use crate::middleware::cache::RedisCache;
+use crate::versions;
+
+const REPORT_STATUSES: [&str; 4] = ["open", "claimed", "resolved", "dismissed"];
+const TARGET_TYPES: [&str; 4] = ["comment", "definition", "collection", "wiki_page"];
+const MAX_DETAILS_LEN: usize = 2000;
+const MAX_REPORTS_PER_DAY: i64 = 50;
+
+/// Reports joined with their reporter and a preview and owner of the reported content.
+const REPORT_SELECT: &str = "SELECT r.id, r.reporter_id, ru.username AS reporter_username,
+        r.target_type, r.target_id, r.reason, r.details, r.status, r.claimed_by, r.claimed_at,
+        r.resolved_by, r.resolved_at, r.resolution_action, r.resolution_ref, r.resolution_note,
+        r.created_at,
+        (SELECT COUNT(*) FROM content_reports o
+         WHERE o.target_type = r.target_type AND o.target_id = r.target_id
+           AND o.status IN ('open', 'claimed')) AS pending_reports_on_target,
+        CASE r.target_type
+            WHEN 'comment' THEN (SELECT LEFT(COALESCE(NULLIF(c.plain_content, ''), c.subject), 280)
+                                 FROM comments c WHERE c.commentid = r.target_id)
+            WHEN 'definition' THEN (SELECT v.word || ': ' || LEFT(d.definition, 280)
+                                    FROM definitions d JOIN valsi v ON v.valsiid = d.valsiid
+                                    WHERE d.definitionid = r.target_id)
+            WHEN 'collection' THEN (SELECT cl.name FROM collections cl
+                                    WHERE cl.collection_id = r.target_id)
+            WHEN 'wiki_page' THEN (SELECT w.title FROM wiki_articles w WHERE w.id = r.target_id)
+        END AS target_preview,
+        CASE r.target_type
+            WHEN 'comment' THEN (SELECT c.userid FROM comments c WHERE c.commentid = r.target_id)
+            WHEN 'definition' THEN (SELECT d.userid FROM definitions d
+                                    WHERE d.definitionid = r.target_id)
+            WHEN 'collection' THEN (SELECT cl.user_id FROM collections cl
+                                    WHERE cl.collection_id = r.target_id)
+        END AS target_user_id
+    FROM content_reports r
+    JOIN users ru ON ru.userid = r.reporter_id";
+
+fn report_from_row(row: &Row) -> ReportResponse {
+    ReportResponse {
+        id: row.get("id"),
+        reporter_id: row.get("reporter_id"),
+        reporter_username: row.get("reporter_username"),
+        target_type: row.get("target_type"),
+        target_id: row.get("target_id"),
+        target_preview: row.get("target_preview"),
+        target_user_id: row.get("target_user_id"),
+        reason: row.get("reason"),
+        details: row.get("details"),
+        status: row.get("status"),
+        claimed_by: row.get("claimed_by"),
+        claimed_at: row.get("claimed_at"),
+        resolved_by: row.get("resolved_by"),
+        resolved_at: row.get("resolved_at"),
+        resolution_action: row.get("resolution_action"),
+        resolution_ref: row.get("resolution_ref"),
+        resolution_note: row.get("resolution_note"),
+        pending_reports_on_target: row.get("pending_reports_on_target"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn clean_text(text: Option<&str>, what: &str) -> AppResult<Option<String>> {
+    let text = text.map(str::trim).filter(|t| !t.is_empty());
+    if text.is_some_and(|t| t.chars().count() > MAX_DETAILS_LEN) {
+        return Err(AppError::Validation(format!(
+            "{} must be at most {} characters",
+            what, MAX_DETAILS_LEN
+        )));
+    }
+    Ok(text.map(str::to_string))
+}
+
+async fn load_report(client: &impl GenericClient, report_id: i32) -> AppResult<ReportResponse> {
+    client
+        .query_opt(&format!("{} WHERE r.id = $1", REPORT_SELECT), &[&report_id])
+        .await?
+        .map(|row| report_from_row(&row))
+        .ok_or_else(|| AppError::NotFound("Report not found".to_string()))
+}
+
+/// Loads a report and locks its row until the transaction ends, so that moderators acting on
+/// the same report at once are served one after the other.
+async fn lock_report(client: &impl GenericClient, report_id: i32) -> AppResult<ReportResponse> {
+    client
+        .query_opt(
+            "SELECT 1 FROM content_reports WHERE id = $1 FOR UPDATE",
+            &[&report_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound("Report not found".to_string()))?;
+    load_report(client, report_id).await
+}
+
+/// A moderator can act on open reports and on reports they claimed.
+fn ensure_actionable(report: &ReportResponse, moderator_id: i32) -> AppResult<()> {
+    match report.status.as_str() {
+        "open" => Ok(()),
+        "claimed" if report.claimed_by == Some(moderator_id) => Ok(()),
+        "claimed" => Err(AppError::BadRequest(
+            "Report is claimed by another moderator".to_string(),
+        )),
+        _ => Err(AppError::BadRequest("Report is already closed".to_string())),
+    }
+}
+
+async fn target_exists(
+    client: &impl GenericClient,
+    target_type: ReportTargetType,
+    target_id: i32,
+) -> AppResult<bool> {
+    let sql = match target_type {
+        ReportTargetType::Comment => "SELECT 1 FROM comments WHERE commentid = $1",
+        ReportTargetType::Definition => "SELECT 1 FROM definitions WHERE definitionid = $1",
+        ReportTargetType::Collection => "SELECT 1 FROM collections WHERE collection_id = $1",
+        ReportTargetType::WikiPage => "SELECT 1 FROM wiki_articles WHERE id = $1",
+    };
+    Ok(client.query_opt(sql, &[&target_id]).await?.is_some())
+}
+
+pub async fn create_report(
+    pool: &Pool,
+    reporter_id: i32,
+    request: &CreateReportRequest,
+) -> AppResult<ReportResponse> {
+    let details = clean_text(request.details.as_deref(), "Details")?;
+    let mut client = pool.get().await?;
+
+    if !target_exists(&client, request.target_type, request.target_id).await? {
+        return Err(AppError::NotFound(format!(
+            "No {} with id {}",
+            request.target_type.as_str().replace('_', " "),
+            request.target_id
+        )));
+    }
+    let recent: i64 = client
+        .query_one(
+            "SELECT COUNT(*) FROM content_reports
+             WHERE reporter_id = $1 AND created_at > NOW() - INTERVAL '1 day'",
+            &[&reporter_id],
+        )
+        .await?
+        .get(0);
+    if recent >= MAX_REPORTS_PER_DAY {
+        return Err(AppError::BadRequest(
+            "Too many reports in the last day, please try again later".to_string(),
+        ));
+    }
+
+    let transaction = client.transaction().await?;
+    let report_id: i32 = transaction
+        .query_opt(
+            "INSERT INTO content_reports (reporter_id, target_type, target_id, reason, details)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (reporter_id, target_type, target_id)
+                 WHERE status IN ('open', 'claimed') DO NOTHING
+             RETURNING id",
+            &[
+                &reporter_id,
+                &request.target_type.as_str(),
+                &request.target_id,
+                &request.reason.as_str(),
+                &details,
+            ],
+        )
+        .await?
+        .ok_or_else(|| {
+            AppError::BadRequest("You have already reported this and it is pending".to_string())
+        })?
+        .get("id");
+    transaction
+        .execute(
+            "INSERT INTO content_report_events (report_id, actor_id, event, note)
+             VALUES ($1, $2, 'reported', $3)",
+            &[&report_id, &reporter_id, &details],
+        )
+        .await?;
+    let report = load_report(&transaction, report_id).await?;
+    transaction.commit().await?;
+    Ok(report)
+}
+
+pub async fn list_queue(
+    pool: &Pool,
+    moderator_id: i32,
+    query: &ReportQueueQuery,
+) -> AppResult<ReportQueueResponse> {
+    if let Some(status) = query.status.as_deref() {
+        if !REPORT_STATUSES.contains(&status) {
+            return Err(AppError::Validation(format!(
+                "Unknown report status: {}",
+                status
+            )));
+        }
+    }
+    if let Some(target_type) = query.target_type.as_deref() {
+        if !TARGET_TYPES.contains(&target_type) {
+            return Err(AppError::Validation(format!(
+                "Unknown target type: {}",
+                target_type
+            )));
+        }
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query.per_page.unwrap_or(20).clamp(1, 100);
+    let offset = (page - 1) * per_page;
+    let claimed_by = query.mine.then_some(moderator_id);
+
+    let filter = "WHERE (($1::text IS NULL AND r.status IN ('open', 'claimed')) OR r.status = $1)
+                    AND ($2::text IS NULL OR r.target_type = $2)
+                    AND ($3::int IS NULL OR r.claimed_by = $3)";
+    let client = pool.get().await?;
+    // Oldest first, so that nothing waits forever at the bottom of the queue
+    let rows = client
+        .query(
+            &format!(
+                "{} {} ORDER BY r.created_at ASC, r.id ASC LIMIT $4 OFFSET $5",
+                REPORT_SELECT, filter
+            ),
+            &[
+                &query.status,
+                &query.target_type,
+                &claimed_by,
+                &per_page,
+                &offset,
+            ],
+        )
+        .await?;
+    let total: i64 = client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM content_reports r {}", filter),
+            &[&query.status, &query.target_type, &claimed_by],
+        )
+        .await?
+        .get(0);
+
+    Ok(ReportQueueResponse {
+        reports: rows.iter().map(report_from_row).collect(),
+        total,
+        page,
+        per_page,
+    })
+}
+
+/// A report and its event trail. Reporters can see their own reports, without the trail.
+pub async fn get_report(
+    pool: &Pool,
+    user_id: i32,
+    is_moderator: bool,
+    report_id: i32,
+) -> AppResult<ReportDetailResponse> {
+    let client = pool.get().await?;
+    let report = load_report(&client, report_id).await?;
+    if !is_moderator {
+        if report.reporter_id != user_id {
+            return Err(AppError::NotFound("Report not found".to_string()));
+        }
+        return Ok(ReportDetailResponse {
+            report,
+            events: Vec::new(),
+        });
+    }
+
+    let events = client
+        .query(
+            "SELECT e.id, e.actor_id, u.username AS actor_username, e.event, e.note, e.data,
+                    e.created_at
+             FROM content_report_events e
+             LEFT JOIN users u ON u.userid = e.actor_id
+             WHERE e.report_id = $1
+             ORDER BY e.id",
+            &[&report_id],
+        )
+        .await?
+        .iter()
+        .map(|row| ReportEventResponse {
+            id: row.get("id"),
+            actor_id: row.get("actor_id"),
+            actor_username: row.get("actor_username"),
+            event: row.get("event"),
+            note: row.get("note"),
+            data: row.get("data"),
+            created_at: row.get("created_at"),
+        })
+        .collect();
+    Ok(ReportDetailResponse { report, events })
+}
+
+pub async fn claim_report(
+    pool: &Pool,
+    moderator_id: i32,
+    report_id: i32,
+) -> AppResult<ReportResponse> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let report = lock_report(&transaction, report_id).await?;
+    ensure_actionable(&report, moderator_id)?;
+    if report.status == "claimed" {
+        return Ok(report);
+    }
+
+    let updated = transaction
+        .execute(
+            "UPDATE content_reports SET status = 'claimed', claimed_by = $2, claimed_at = NOW()
+             WHERE id = $1 AND status = 'open'",
+            &[&report_id, &moderator_id],
+        )
+        .await?;
+    if updated == 0 {
+        return Err(AppError::BadRequest(
+            "Report was claimed by another moderator".to_string(),
+        ));
+    }
+    transaction
+        .execute(
+            "INSERT INTO content_report_events (report_id, actor_id, event)
+             VALUES ($1, $2, 'claimed')",
+            &[&report_id, &moderator_id],
+        )
+        .await?;
+    let report = load_report(&transaction, report_id).await?;
+    transaction.commit().await?;
+    Ok(report)
+}
+
+/// Reverts the reported definition to one of its earlier versions.
+async fn revert_definition(
+    transaction: &Transaction<'_>,
+    perm_cache: &PermissionCache,
+    claims: &Claims,
+    ctx: &AuditContext,
+    report: &ReportResponse,
+    version_id: Option<i32>,
+) -> AppResult<serde_json::Value> {
+    if report.target_type != "definition" {
+        return Err(AppError::Validation(
+            "Only reported definitions can be reverted".to_string(),
+        ));
+    }
+    let version_id = version_id.ok_or_else(|| {
+        AppError::Validation("version_id is required to revert a definition".to_string())
+    })?;
+    if !perm_cache
+        .claims_have_permission(claims, "revert_entry_version")
+        .await
+    {
+        return Err(AppError::Auth(
+            "Reverting needs the revert_entry_version permission".to_string(),
+        ));
+    }
+
+    transaction
+        .query_opt(
+            "SELECT 1 FROM definition_versions WHERE version_id = $1 AND definition_id = $2",
+            &[&version_id, &report.target_id],
+        )
+        .await?
+        .ok_or_else(|| {
+            AppError::NotFound("Version not found for the reported definition".to_string())
+        })?;
+
+    let new_version = versions::service::revert_to_version_with_transaction(
+        transaction,
+        version_id,
+        claims,
+        perm_cache,
+        ctx,
+    )
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("in the trash") {
+            AppError::Validation(e.to_string())
+        } else {
+            AppError::Internal(format!("Failed to revert: {}", e))
+        }
+    })?;
+    Ok(json!({
+        "definition_id": report.target_id,
+        "reverted_to_version_id": version_id,
+        "new_version_id": new_version.version_id,
+    }))
+}
+
+/// Blocks the author of the reported content and signs them out of every session.
+async fn block_author(
+    transaction: &Transaction<'_>,
+    redis_cache: &RedisCache,
+    claims: &Claims,
+    ctx: &AuditContext,
+    report: &ReportResponse,
+) -> AppResult<serde_json::Value> {
+    let user_id = report.target_user_id.ok_or_else(|| {
+        AppError::Validation("The reported content has no author to block".to_string())
+    })?;
+    if user_id == claims.sub {
+        return Err(AppError::Validation(
+            "You cannot block yourself".to_string(),
+        ));
+    }
+
+    auth::service::block_user_with_transaction(transaction, redis_cache, ctx, user_id, true)
+        .await?;
+    Ok(json!({ "user_id": user_id }))
+}
+
+/// Hides the reported comment.
+async fn hide_comment(
+    transaction: &Transaction<'_>,
+    perm_cache: &PermissionCache,
+    claims: &Claims,
+    report: &ReportResponse,
+) -> AppResult<serde_json::Value> {
+    if report.target_type != "comment" {
+        return Err(AppError::Validation(
+            "Only reported comments can be hidden".to_string(),
+        ));
+    }
+    if !perm_cache
+        .claims_have_permission(claims, "moderate_comments")
+        .await
+    {
+        return Err(AppError::Auth(
+            "Hiding comments needs the moderate_comments permission".to_string(),
+        ));
+    }
+    transaction
+        .execute(
+            "UPDATE comments SET hidden_at = NOW(), hidden_by = $2
+             WHERE commentid = $1 AND hidden_at IS NULL",
+            &[&report.target_id, &claims.sub],
+        )
+        .await?;
+    Ok(json!({ "comment_id": report.target_id }))
+}
+
+/// Closes a report with `request.action`. The report row is locked and the action (hiding the
+/// comment, reverting the definition or blocking the author) runs in the same transaction as the
+/// status change, so either both happen or neither does. Acting on the content also resolves the
+/// other pending reports about it.
+pub async fn resolve_report(
+    pool: &Pool,
+    perm_cache: &PermissionCache,
+    redis_cache: &RedisCache,
+    claims: &Claims,
+    ctx: &AuditContext,
+    report_id: i32,
+    request: &ResolveReportRequest,
+) -> AppResult<ReportResponse> {
+    let note = clean_text(request.note.as_deref(), "Note")?;
+    if matches!(
+        request.action,
+        ResolutionAction::RevertVersion | ResolutionAction::BlockUser
+    ) {
+        two_factor::require_step_up(pool, perm_cache, claims).await?;
+    }
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let report = lock_report(&transaction, report_id).await?;
+    ensure_actionable(&report, claims.sub)?;
+
+    let resolution_ref = match request.action {
+        ResolutionAction::None => None,
+        ResolutionAction::HideComment => {
+            Some(hide_comment(&transaction, perm_cache, claims, &report).await?)
+        }
+        ResolutionAction::RevertVersion => Some(
+            revert_definition(
+                &transaction,
+                perm_cache,
+                claims,
+                ctx,
+                &report,
+                request.version_id,
+            )
+            .await?,
+        ),
+        ResolutionAction::BlockUser => {
+            Some(block_author(&transaction, redis_cache, claims, ctx, &report).await?)
+        }
+    };
+
+    let include_others = request.action != ResolutionAction::None;
+    let resolved: Vec<i32> = transaction
+        .query(
+            "UPDATE content_reports
+             SET status = 'resolved', claimed_by = COALESCE(claimed_by, $2),
+                 claimed_at = COALESCE(claimed_at, NOW()), resolved_by = $2, resolved_at = NOW(),
+                 resolution_action = $3, resolution_ref = $4, resolution_note = $5
+             WHERE status IN ('open', 'claimed')
+               AND (id = $1 OR ($6 AND target_type = $7 AND target_id = $8))
+             RETURNING id",
+            &[
+                &report_id,
+                &claims.sub,
+                &request.action.as_str(),
+                &resolution_ref,
+                &note,
+                &include_others,
+                &report.target_type,
+                &report.target_id,
+            ],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get("id"))
+        .collect();
+    transaction
+        .execute(
+            "INSERT INTO content_report_events (report_id, actor_id, event, note, data)
+             SELECT id, $2, 'resolved', $3, $4 FROM UNNEST($1::int[]) AS id",
+            &[
+                &resolved,
+                &claims.sub,
+                &note,
+                &json!({
+                    "action": request.action.as_str(),
+                    "ref": resolution_ref,
+                    "report_id": report_id,
+                }),
+            ],
+        )
+        .await?;
+    let resolved_report = load_report(&transaction, report_id).await?;
+    audit::service::record(
+        &transaction,
+        ctx,
+        AuditAction::ResolveReport,
+        &report_id.to_string(),
+        Some(serde_json::to_value(&report)?),
+        Some(serde_json::to_value(&resolved_report)?),
+    )
+    .await?;
+    transaction.commit().await?;
+
+    if request.action == ResolutionAction::BlockUser {
+        if let Err(e) = perm_cache.invalidate().await {
+            log::error!("Failed to reload permissions after blocking a user: {}", e);
+        }
+    }
+    Ok(resolved_report)
+}
+
+pub async fn dismiss_report(
+    pool: &Pool,
+    ctx: &AuditContext,
+    report_id: i32,
+    request: &DismissReportRequest,
+) -> AppResult<ReportResponse> {
+    let moderator_id = ctx.actor_id;
+    let note = clean_text(request.note.as_deref(), "Note")?;
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let report = lock_report(&transaction, report_id).await?;
+    ensure_actionable(&report, moderator_id)?;
+
+    let updated = transaction
+        .execute(
+            "UPDATE content_reports
+             SET status = 'dismissed', claimed_by = COALESCE(claimed_by, $2),
+                 claimed_at = COALESCE(claimed_at, NOW()), resolved_by = $2, resolved_at = NOW(),
+                 resolution_note = $3
+             WHERE id = $1 AND status IN ('open', 'claimed')",
+            &[&report_id, &moderator_id, &note],
+        )
+        .await?;
+    if updated == 0 {
+        return Err(AppError::BadRequest(
+            "Report was closed in the meantime".to_string(),
+        ));
+    }
+    transaction
+        .execute(
+            "INSERT INTO content_report_events (report_id, actor_id, event, note)
+             VALUES ($1, $2, 'dismissed', $3)",
+            &[&report_id, &moderator_id, &note],
+        )
+        .await?;
+    let dismissed_report = load_report(&transaction, report_id).await?;
+    audit::service::record(
+        &transaction,
+        ctx,
+        AuditAction::DismissReport,
+        &report_id.to_string(),
+        Some(serde_json::to_value(&report)?),
+        Some(serde_json::to_value(&dismissed_report)?),
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(dismissed_report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn report(status: &str, claimed_by: Option<i32>) -> ReportResponse {
+        ReportResponse {
+            id: 1,
+            reporter_id: 2,
+            reporter_username: "reporter".to_string(),
+            target_type: "comment".to_string(),
+            target_id: 3,
+            target_preview: None,
+            target_user_id: Some(4),
+            reason: "spam".to_string(),
+            details: None,
+            status: status.to_string(),
+            claimed_by,
+            claimed_at: None,
+            resolved_by: None,
+            resolved_at: None,
+            resolution_action: None,
+            resolution_ref: None,
+            resolution_note: None,
+            pending_reports_on_target: 1,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn only_open_or_own_claimed_reports_are_actionable() {
+        assert!(ensure_actionable(&report("open", None), 7).is_ok());
+        assert!(ensure_actionable(&report("claimed", Some(7)), 7).is_ok());
+        assert!(ensure_actionable(&report("claimed", Some(8)), 7).is_err());
+        assert!(ensure_actionable(&report("resolved", Some(7)), 7).is_err());
+        assert!(ensure_actionable(&report("dismissed", None), 7).is_err());
+    }
+
+    #[test]
+    fn notes_are_trimmed_and_bounded() {
+        assert_eq!(clean_text(Some("  "), "Note").ok(), Some(None));
+        assert_eq!(
+            clean_text(Some(" spam link "), "Note").ok(),
+            Some(Some("spam link".to_string()))
+        );
+        assert!(clean_text(Some(&"x".repeat(MAX_DETAILS_LEN + 1)), "Note").is_err());
+    }
+}
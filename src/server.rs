@@ -2,7 +2,7 @@ use crate::auth::permissions::PermissionCache;
 use crate::flashcards;
 use crate::middleware::limiter::EmailConfirmationLimiter;
 use crate::{
-    assistant, auth, collections, comments,
+    assistant, audit, auth, collections, comments,
     config::AppConfig,
     error::{AppError, AppResult},
     export, jbovlaste, language,
@@ -11,12 +11,12 @@ use crate::{
     middleware::{
         self,
         cache::RedisCache,
-        limiter::{KittenTtsLimiter, LoginLimiter, PasswordResetLimiter},
+        limiter::{KittenTtsLimiter, LoginLimiter, MailExportLimiter, PasswordResetLimiter},
         panic_handler::CatchPanicWithMessage,
     },
-    sessions, subscriptions, users,
+    reports, sessions, subscriptions, trash, users,
     versions::{self},
-    waves, wiki,
+    waves, webhooks, wiki,
 };
 use actix::Actor;
 use actix_cors::Cors;
@@ -70,20 +70,35 @@ pub async fn start_server(
         ))
     })?);
 
+    let mail_export_limiter = web::Data::new(MailExportLimiter::new(&redis_url).map_err(|e| {
+        AppError::ExternalService(format!(
+            "Failed to initialize mail export rate limiter: {}",
+            e
+        ))
+    })?);
+
     let redis_cache_data = web::Data::from(redis_cache);
 
     // Initialize messaging service and WebSocket broadcast server
-    let chat_server = messaging::websocket::ChatServer::new().start();
+    let chat_server =
+        messaging::websocket::ChatServer::new(redis_cache_data.client.clone()).start();
     let messaging_service = web::Data::new(messaging::MessagingService::new(
         pool.clone(),
         Some(chat_server.clone()),
     ));
+    let encryption_service =
+        web::Data::new(messaging::encryption::EncryptionService::new(pool.clone()));
 
-    let perm_cache = web::Data::from(PermissionCache::new(pool.clone()));
+    let perm_cache = web::Data::from(PermissionCache::new(
+        pool.clone(),
+        redis_cache_data.client.clone(),
+    ));
     perm_cache
         .load_permissions()
         .await
         .map_err(|e| AppError::Auth(format!("Failed to load permissions: {}", e)))?;
+    // Reload when another instance changes roles or user roles
+    perm_cache.clone().into_inner().listen_for_invalidations();
 
     HttpServer::new(move || {
         // Create parsers for this specific worker thread
@@ -146,8 +161,10 @@ pub async fn start_server(
             .app_data(email_confirmation_limiter.clone())
             .app_data(login_limiter.clone())
             .app_data(kitten_tts_limiter.clone())
+            .app_data(mail_export_limiter.clone())
             .app_data(redis_cache_data.clone())
             .app_data(messaging_service.clone())
+            .app_data(encryption_service.clone())
             .app_data(web::Data::new(chat_server.clone()))
             .configure(auth::configure)
             .configure(users::configure)
@@ -160,6 +177,10 @@ pub async fn start_server(
             .configure(versions::configure)
             .configure(export::configure)
             .configure(subscriptions::configure)
+            .configure(webhooks::configure)
+            .configure(reports::configure)
+            .configure(audit::configure)
+            .configure(trash::configure)
             .configure(collections::configure)
             .configure(flashcards::configure)
             .configure(crate::openapi::configure)
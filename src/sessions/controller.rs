@@ -1,10 +1,15 @@
 use actix_web::{web, HttpResponse, Responder};
-use actix_web_grants::protect;
+use actix_web_grants::{protect, GrantsMiddleware};
+use actix_web_httpauth::middleware::HttpAuthentication;
 use deadpool_postgres::Pool;
 
+use crate::auth::extractor::extract_authorities;
 use crate::auth::models::Claims;
-use crate::error::AppResult;
-use crate::sessions::dto::{PaginatedUserSessionsResponse, PaginationParams};
+use crate::error::{AppError, AppResult};
+use crate::middleware::cache::RedisCache;
+use crate::sessions::dto::{
+    PaginatedUserSessionsResponse, PaginationParams, RevokeSessionsResponse,
+};
 
 /// Get current authenticated user's sessions with pagination.
 #[utoipa::path(
@@ -78,10 +83,77 @@ pub async fn get_user_sessions_admin(
     Ok(HttpResponse::Ok().json(sessions_response))
 }
 
+/// Revoke one of the current user's sessions, e.g. on a lost or stolen device.
+#[utoipa::path(
+    delete,
+    path = "/api/sessions/{session_id}",
+    tag = "Sessions",
+    params(
+        ("session_id" = i64, Path, description = "ID of the session to revoke")
+    ),
+    responses(
+        (status = 204, description = "Session revoked; its tokens are no longer accepted"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Session not found"),
+        (status = 500, description = "Internal Server Error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn revoke_my_session(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    claims: Claims,
+    path: web::Path<i64>,
+) -> AppResult<impl Responder> {
+    let session_id = path.into_inner();
+
+    if !super::service::revoke_session(pool.get_ref(), &redis_cache, claims.sub, session_id).await?
+    {
+        return Err(AppError::NotFound("Session not found".to_string()));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Revoke every session of the current user except the one making the request.
+#[utoipa::path(
+    post,
+    path = "/api/sessions/revoke-others",
+    tag = "Sessions",
+    responses(
+        (status = 200, description = "Other sessions revoked", body = RevokeSessionsResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal Server Error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn revoke_my_other_sessions(
+    pool: web::Data<Pool>,
+    redis_cache: web::Data<RedisCache>,
+    claims: Claims,
+) -> AppResult<impl Responder> {
+    let revoked =
+        super::service::revoke_other_sessions(pool.get_ref(), &redis_cache, claims.sub, claims.sid)
+            .await?;
+    Ok(HttpResponse::Ok().json(RevokeSessionsResponse { revoked }))
+}
+
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api")
+            .wrap(GrantsMiddleware::with_extractor(extract_authorities))
+            .wrap(HttpAuthentication::bearer(crate::auth::validator))
             .service(web::resource("/sessions/my").route(web::get().to(get_my_sessions)))
+            .service(
+                web::resource("/sessions/revoke-others")
+                    .route(web::post().to(revoke_my_other_sessions)),
+            )
+            .service(
+                web::resource("/sessions/{session_id}").route(web::delete().to(revoke_my_session)),
+            )
             .service(
                 web::resource("/users/{user_id}/sessions")
                     .route(web::get().to(get_user_sessions_admin)),
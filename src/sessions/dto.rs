@@ -65,3 +65,9 @@ impl From<&super::models::UserSession> for UserSessionDto {
         }
     }
 }
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RevokeSessionsResponse {
+    /// Number of sessions that were revoked.
+    pub revoked: u64,
+}
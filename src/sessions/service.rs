@@ -1,11 +1,16 @@
 use crate::error::{AppError, AppResult};
+use crate::middleware::cache::RedisCache;
 use crate::sessions::dto::{PaginatedUserSessionsResponse, UserSessionDto};
 use crate::sessions::models::UserSession;
 use chrono::{DateTime, Utc};
-use deadpool_postgres::Pool;
+use deadpool_postgres::{GenericClient, Pool};
+use redis::AsyncCommands;
 use std::net::IpAddr;
 use uuid::Uuid;
 
+/// Revoked sessions stay listed in Redis for as long as their refresh token could be valid.
+const REVOKED_SESSION_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+
 /// Starts a new user session.
 pub async fn start_session(
     pool: &Pool,
@@ -303,3 +308,253 @@ pub async fn get_session_id_from_uuid(pool: &Pool, session_uuid: Uuid) -> AppRes
         Ok(None)
     }
 }
+
+fn revoked_session_key(session_uuid: Uuid) -> String {
+    format!("revoked_session:{}", session_uuid)
+}
+
+/// Whether the session a token was issued for has been revoked. Checked by
+/// `auth::service::authenticate_session_token` on every authenticated request.
+pub async fn is_session_revoked(
+    redis_cache: &RedisCache,
+    session_uuid: Uuid,
+) -> Result<bool, redis::RedisError> {
+    let mut conn = redis_cache
+        .client
+        .get_multiplexed_async_connection()
+        .await?;
+    conn.exists(revoked_session_key(session_uuid)).await
+}
+
+/// Durable counterpart of [`is_session_revoked`], used when refreshing tokens.
+pub async fn was_session_revoked(pool: &Pool, session_uuid: Uuid) -> AppResult<bool> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let row_opt = client
+        .query_opt(
+            "SELECT revoked_at IS NOT NULL AS revoked FROM user_sessions WHERE session_uuid = $1",
+            &[&session_uuid],
+        )
+        .await
+        .map_err(|e| AppError::Database(format!("Failed to check session revocation: {}", e)))?;
+    Ok(row_opt.is_some_and(|row| row.get("revoked")))
+}
+
+async fn mark_sessions_revoked(redis_cache: &RedisCache, session_uuids: &[Uuid]) -> AppResult<()> {
+    if session_uuids.is_empty() {
+        return Ok(());
+    }
+    let mut conn = redis_cache
+        .client
+        .get_multiplexed_async_connection()
+        .await?;
+    let mut pipe = redis::pipe();
+    for session_uuid in session_uuids {
+        pipe.set_ex(
+            revoked_session_key(*session_uuid),
+            1,
+            REVOKED_SESSION_TTL_SECS,
+        )
+        .ignore();
+    }
+    let _: () = pipe.query_async(&mut conn).await?;
+    Ok(())
+}
+
+/// Ends and revokes one of the user's sessions, so that its access and refresh tokens stop
+/// working. Returns `false` if the user has no such session.
+pub async fn revoke_session(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    user_id: i32,
+    session_id: i64,
+) -> AppResult<bool> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    let row_opt = client
+        .query_opt(
+            r#"
+        UPDATE user_sessions
+        SET ended_at = COALESCE(ended_at, NOW()), revoked_at = COALESCE(revoked_at, NOW())
+        WHERE user_id = $1 AND id = $2
+        RETURNING session_uuid
+        "#,
+            &[&user_id, &session_id],
+        )
+        .await
+        .map_err(|e| AppError::Database(format!("Failed to revoke session: {}", e)))?;
+
+    match row_opt {
+        Some(row) => {
+            mark_sessions_revoked(redis_cache, &[row.get("session_uuid")]).await?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Ends and revokes every session of the user except `keep`, or all of them if `keep` is
+/// `None`. Sessions inactive for longer than a refresh token lives are left alone, since their
+/// tokens have expired anyway. Returns the number of sessions revoked.
+pub async fn revoke_other_sessions(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    user_id: i32,
+    keep: Option<Uuid>,
+) -> AppResult<u64> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    revoke_other_sessions_with_client(&client, redis_cache, user_id, keep).await
+}
+
+/// Like [`revoke_other_sessions`], but on the caller's client or transaction. The sessions are
+/// marked revoked in Redis before the caller commits, so if that fails the caller's change is
+/// rolled back rather than leaving the old sessions signed in.
+pub async fn revoke_other_sessions_with_client(
+    client: &impl GenericClient,
+    redis_cache: &RedisCache,
+    user_id: i32,
+    keep: Option<Uuid>,
+) -> AppResult<u64> {
+    let rows = client
+        .query(
+            r#"
+        UPDATE user_sessions
+        SET ended_at = COALESCE(ended_at, NOW()), revoked_at = NOW()
+        WHERE user_id = $1
+          AND revoked_at IS NULL
+          AND ($2::uuid IS NULL OR session_uuid <> $2)
+          AND last_active_at > NOW() - make_interval(secs => $3)
+        RETURNING session_uuid
+        "#,
+            &[&user_id, &keep, &(REVOKED_SESSION_TTL_SECS as f64)],
+        )
+        .await
+        .map_err(|e| AppError::Database(format!("Failed to revoke sessions: {}", e)))?;
+
+    let session_uuids: Vec<Uuid> = rows.iter().map(|row| row.get("session_uuid")).collect();
+    mark_sessions_revoked(redis_cache, &session_uuids).await?;
+    Ok(session_uuids.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deadpool_postgres::{Config, Runtime, Transaction};
+    use std::env;
+    use std::time::Duration;
+
+    fn test_pool() -> Pool {
+        dotenvy::dotenv().ok();
+        let mut cfg = Config::new();
+        cfg.host = Some(env::var("DB_HOST").unwrap_or_else(|_| "localhost".into()));
+        cfg.port = Some(
+            env::var("DB_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(5432),
+        );
+        cfg.user = Some(env::var("DB_USER").expect("DB_USER"));
+        cfg.password = Some(env::var("DB_PASSWORD").expect("DB_PASSWORD"));
+        cfg.dbname = Some(env::var("DB_NAME").expect("DB_NAME"));
+        cfg.create_pool(Some(Runtime::Tokio1), tokio_postgres::NoTls)
+            .unwrap()
+    }
+
+    /// A user with a current session, another recent one and one idle for longer than a
+    /// refresh token lives, in that order.
+    async fn user_with_sessions(transaction: &Transaction<'_>) -> (i32, [Uuid; 3]) {
+        let username = format!("sessions-{}", &Uuid::new_v4().simple().to_string()[..8]);
+        let email = format!("{}@example.org", username);
+        let user_id: i32 = transaction
+            .query_one(
+                "INSERT INTO users (username, email, password, created_at, role, email_confirmed, votesize)
+                 VALUES ($1, $2, 'x', NOW(), 'user', true, 1.0)
+                 RETURNING userid",
+                &[&username, &email],
+            )
+            .await
+            .unwrap()
+            .get("userid");
+
+        let mut sessions = [Uuid::nil(); 3];
+        for (session, idle_secs) in
+            sessions
+                .iter_mut()
+                .zip([0.0, 60.0, REVOKED_SESSION_TTL_SECS as f64 + 60.0])
+        {
+            *session = transaction
+                .query_one(
+                    "INSERT INTO user_sessions (user_id, session_uuid, last_active_at)
+                     VALUES ($1, gen_random_uuid(), NOW() - make_interval(secs => $2))
+                     RETURNING session_uuid",
+                    &[&user_id, &idle_secs],
+                )
+                .await
+                .unwrap()
+                .get("session_uuid");
+        }
+        (user_id, sessions)
+    }
+
+    async fn revoked(transaction: &Transaction<'_>, session_uuid: Uuid) -> bool {
+        transaction
+            .query_one(
+                "SELECT revoked_at IS NOT NULL AS revoked FROM user_sessions WHERE session_uuid = $1",
+                &[&session_uuid],
+            )
+            .await
+            .unwrap()
+            .get("revoked")
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema"]
+    async fn revocation_fails_and_rolls_back_when_redis_is_unreachable() {
+        let pool = test_pool();
+        let mut client = pool.get().await.unwrap();
+        let mut transaction = client.transaction().await.unwrap();
+        let (user_id, [current, other, _]) = user_with_sessions(&transaction).await;
+        let unreachable = RedisCache::new("redis://127.0.0.1:1/", Duration::from_secs(60)).unwrap();
+
+        let savepoint = transaction.transaction().await.unwrap();
+        let result =
+            revoke_other_sessions_with_client(&savepoint, &unreachable, user_id, Some(current))
+                .await;
+        assert!(matches!(result, Err(AppError::Redis(_))));
+        drop(savepoint);
+
+        // Dropping the failed change leaves the other session signed in, for the caller to retry
+        assert!(!revoked(&transaction, other).await);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires local Postgres with the lensisku schema and Redis"]
+    async fn revoking_other_sessions_keeps_the_current_and_idle_ones() {
+        let pool = test_pool();
+        let redis_url =
+            env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let redis_cache = RedisCache::new(&redis_url, Duration::from_secs(60)).unwrap();
+        let mut client = pool.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        let (user_id, [current, other, idle]) = user_with_sessions(&transaction).await;
+
+        let count =
+            revoke_other_sessions_with_client(&transaction, &redis_cache, user_id, Some(current))
+                .await
+                .unwrap();
+
+        assert_eq!(count, 1);
+        assert!(revoked(&transaction, other).await);
+        assert!(!revoked(&transaction, current).await);
+        assert!(!revoked(&transaction, idle).await);
+        assert!(is_session_revoked(&redis_cache, other).await.unwrap());
+        assert!(!is_session_revoked(&redis_cache, current).await.unwrap());
+    }
+}
@@ -0,0 +1,84 @@
+use actix_web::{get, post, web, HttpResponse};
+use deadpool_postgres::Pool;
+
+use super::{dto::*, service};
+use crate::middleware::cache::RedisCache;
+use crate::{auth::permissions::PermissionCache, auth::Claims, AppError};
+
+#[utoipa::path(
+    get,
+    path = "/trash",
+    tag = "trash",
+    params(TrashQuery),
+    responses(
+        (status = 200, description = "Deleted items that can still be restored, newest first", body = TrashResponse),
+        (status = 400, description = "Unknown item type"),
+        (status = 403, description = "Listing all users' items needs the manage_trash permission")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "List the trash",
+    description = "Lists the user's deleted definitions, comments and collections with the time each one is \
+                  purged for good. Moderators can pass `all=true` to see everyone's."
+)]
+#[get("")]
+pub async fn list_trash(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    query: web::Query<TrashQuery>,
+) -> Result<HttpResponse, AppError> {
+    if query.all
+        && !perm_cache
+            .claims_have_permission(&claims, "manage_trash")
+            .await
+    {
+        return Err(AppError::Auth(
+            "Listing the whole trash needs the manage_trash permission".to_string(),
+        ));
+    }
+    let trash = service::list_trash(&pool, claims.sub, query.all, &query).await?;
+    Ok(HttpResponse::Ok().json(trash))
+}
+
+#[utoipa::path(
+    post,
+    path = "/trash/{item_type}/{id}/restore",
+    tag = "trash",
+    params(
+        ("item_type" = TrashItemType, Path, description = "definition, comment or collection"),
+        ("id" = i32, Path, description = "Item ID")
+    ),
+    responses(
+        (status = 200, description = "Item restored", body = RestoreResponse),
+        (status = 400, description = "The comment replies to a comment that is still in the trash"),
+        (status = 403, description = "Not the owner and missing the manage_trash permission"),
+        (status = 404, description = "Item not in the trash or past the retention period")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Restore from the trash",
+    description = "Brings back a deleted item as it was. A definition comes back with the flashcards, review \
+                  progress and collection items that use it; a collection with its items and levels."
+)]
+#[post("/{item_type}/{id}/restore")]
+pub async fn restore_item(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    redis_cache: web::Data<RedisCache>,
+    claims: Claims,
+    path: web::Path<(TrashItemType, i32)>,
+) -> Result<HttpResponse, AppError> {
+    let (item_type, item_id) = path.into_inner();
+    let can_manage = perm_cache
+        .claims_have_permission(&claims, "manage_trash")
+        .await;
+    let restored = service::restore(
+        &pool,
+        &redis_cache,
+        claims.sub,
+        can_manage,
+        item_type,
+        item_id,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(restored))
+}
@@ -0,0 +1,64 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Kinds of content that go to the trash when deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashItemType {
+    Definition,
+    Comment,
+    Collection,
+}
+
+impl TrashItemType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrashItemType::Definition => "definition",
+            TrashItemType::Comment => "comment",
+            TrashItemType::Collection => "collection",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TrashQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    /// definition, comment or collection
+    pub item_type: Option<String>,
+    /// Include content owned by other users; needs the manage_trash permission
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrashItem {
+    pub item_type: String,
+    pub item_id: i32,
+    /// Word and definition, comment excerpt or collection name
+    pub preview: Option<String>,
+    pub owner_id: Option<i32>,
+    pub owner_username: Option<String>,
+    #[schema(value_type = String, format = DateTime)]
+    pub deleted_at: DateTime<Utc>,
+    pub deleted_by: Option<i32>,
+    /// When the purge job removes the item for good
+    #[schema(value_type = String, format = DateTime)]
+    pub purge_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrashResponse {
+    pub items: Vec<TrashItem>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+    pub retention_days: i32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RestoreResponse {
+    pub item_type: String,
+    pub item_id: i32,
+}
@@ -0,0 +1,15 @@
+pub mod controller;
+pub mod dto;
+pub mod service;
+
+use actix_web::web;
+use actix_web_httpauth::middleware::HttpAuthentication;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/trash")
+            .wrap(HttpAuthentication::bearer(crate::auth::validator))
+            .service(controller::list_trash)
+            .service(controller::restore_item),
+    );
+}
@@ -0,0 +1,239 @@
+use std::env;
+
+use deadpool_postgres::{Pool, Transaction};
+use log::{error, info};
+use tokio_postgres::Row;
+
+use super::dto::{RestoreResponse, TrashItem, TrashItemType, TrashQuery, TrashResponse};
+use crate::error::{AppError, AppResult};
+use crate::middleware::cache::RedisCache;
+use crate::{collections, comments, jbovlaste};
+
+const DEFAULT_RETENTION_DAYS: i32 = 30;
+
+/// Trashed definitions, comments and collections with their owner, as one relation.
+const TRASH_ITEMS: &str = "(
+        SELECT 'definition' AS item_type, d.definitionid AS item_id,
+               v.word || ': ' || LEFT(d.definition, 280) AS preview,
+               d.userid AS owner_id, d.deleted_at, d.deleted_by
+        FROM definitions d JOIN valsi v ON v.valsiid = d.valsiid
+        WHERE d.deleted_at IS NOT NULL
+        UNION ALL
+        SELECT 'comment', c.commentid, LEFT(COALESCE(NULLIF(c.plain_content, ''), c.subject), 280),
+               c.userid, c.deleted_at, c.deleted_by
+        FROM comments c
+        WHERE c.deleted_at IS NOT NULL
+        UNION ALL
+        SELECT 'collection', cl.collection_id, cl.name, cl.user_id, cl.deleted_at, cl.deleted_by
+        FROM collections cl
+        WHERE cl.deleted_at IS NOT NULL
+    ) t";
+
+/// Days a deleted item stays restorable before the purge job removes it, from
+/// `TRASH_RETENTION_DAYS`.
+pub fn retention_days() -> i32 {
+    env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+fn item_from_row(row: &Row) -> TrashItem {
+    TrashItem {
+        item_type: row.get("item_type"),
+        item_id: row.get("item_id"),
+        preview: row.get("preview"),
+        owner_id: row.get("owner_id"),
+        owner_username: row.get("owner_username"),
+        deleted_at: row.get("deleted_at"),
+        deleted_by: row.get("deleted_by"),
+        purge_at: row.get("purge_at"),
+    }
+}
+
+/// Lists restorable items, newest deletions first. Without `show_all` only the user's own
+/// content is listed.
+pub async fn list_trash(
+    pool: &Pool,
+    user_id: i32,
+    show_all: bool,
+    query: &TrashQuery,
+) -> AppResult<TrashResponse> {
+    if let Some(item_type) = query.item_type.as_deref() {
+        if !matches!(item_type, "definition" | "comment" | "collection") {
+            return Err(AppError::Validation(format!(
+                "Unknown item type: {}",
+                item_type
+            )));
+        }
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 200);
+    let offset = (page - 1) * per_page;
+    let owner_id = (!show_all).then_some(user_id);
+    let retention = retention_days();
+
+    let filter = "WHERE ($1::int IS NULL OR t.owner_id = $1)
+          AND ($2::text IS NULL OR t.item_type = $2)
+          AND t.deleted_at > NOW() - make_interval(days => $3)";
+
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            &format!(
+                "SELECT t.*, u.username AS owner_username,
+                        t.deleted_at + make_interval(days => $3) AS purge_at
+                 FROM {}
+                 LEFT JOIN users u ON u.userid = t.owner_id
+                 {}
+                 ORDER BY t.deleted_at DESC, t.item_type, t.item_id DESC
+                 LIMIT $4 OFFSET $5",
+                TRASH_ITEMS, filter
+            ),
+            &[&owner_id, &query.item_type, &retention, &per_page, &offset],
+        )
+        .await?;
+    let total: i64 = client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {} {}", TRASH_ITEMS, filter),
+            &[&owner_id, &query.item_type, &retention],
+        )
+        .await?
+        .get(0);
+
+    Ok(TrashResponse {
+        items: rows.iter().map(item_from_row).collect(),
+        total,
+        page,
+        per_page,
+        retention_days: retention,
+    })
+}
+
+/// Takes an item out of the trash. Users restore their own content; `can_manage` allows
+/// restoring anyone's.
+pub async fn restore(
+    pool: &Pool,
+    redis_cache: &RedisCache,
+    user_id: i32,
+    can_manage: bool,
+    item_type: TrashItemType,
+    item_id: i32,
+) -> AppResult<RestoreResponse> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let owner_id: Option<i32> = transaction
+        .query_opt(
+            &format!(
+                "SELECT t.owner_id FROM {}
+                 WHERE t.item_type = $1 AND t.item_id = $2
+                   AND t.deleted_at > NOW() - make_interval(days => $3)",
+                TRASH_ITEMS
+            ),
+            &[&item_type.as_str(), &item_id, &retention_days()],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound("Item is not in the trash".to_string()))?
+        .get("owner_id");
+    if owner_id != Some(user_id) && !can_manage {
+        return Err(AppError::Auth(
+            "Only the owner or a moderator can restore this item".to_string(),
+        ));
+    }
+
+    match item_type {
+        TrashItemType::Definition => {
+            jbovlaste::service::restore_definition(&transaction, item_id, user_id)
+                .await
+                .map_err(|e| AppError::Validation(e.to_string()))?;
+        }
+        TrashItemType::Comment => {
+            comments::service::restore_comment(&transaction, item_id)
+                .await
+                .map_err(|e| AppError::Validation(e.to_string()))?;
+        }
+        TrashItemType::Collection => {
+            collections::service::restore_collection(&transaction, redis_cache, item_id).await?;
+        }
+    }
+    transaction.commit().await?;
+
+    if item_type == TrashItemType::Definition {
+        if let Err(e) = redis_cache.invalidate_definition_search_caches().await {
+            error!(
+                "Failed to invalidate definition search caches after restore: {}",
+                e
+            );
+        }
+    }
+    if item_type != TrashItemType::Collection {
+        if let Err(e) = redis_cache.invalidate_recent_changes().await {
+            error!(
+                "Failed to invalidate recent changes cache after restore: {}",
+                e
+            );
+        }
+    }
+
+    Ok(RestoreResponse {
+        item_type: item_type.as_str().to_string(),
+        item_id,
+    })
+}
+
+async fn purge_item(transaction: &Transaction<'_>, item_type: &str, item_id: i32) -> AppResult<()> {
+    match item_type {
+        "definition" => jbovlaste::service::purge_definition(transaction, item_id)
+            .await
+            .map_err(|e| AppError::Database(e.to_string())),
+        "comment" => comments::service::purge_comment(transaction, item_id)
+            .await
+            .map_err(|e| AppError::Database(e.to_string())),
+        "collection" => collections::service::purge_collection(transaction, item_id).await,
+        other => Err(AppError::Internal(format!(
+            "Unknown trash item type: {}",
+            other
+        ))),
+    }
+}
+
+/// Removes items that have been in the trash longer than the retention period, oldest first so
+/// that replies go before the comments they answer. Each item is purged in its own transaction.
+pub async fn purge_expired(pool: &Pool) -> AppResult<u64> {
+    let mut client = pool.get().await?;
+    let expired: Vec<(String, i32)> = client
+        .query(
+            &format!(
+                "SELECT t.item_type, t.item_id FROM {}
+                 WHERE t.deleted_at <= NOW() - make_interval(days => $1)
+                 ORDER BY t.deleted_at, t.item_id",
+                TRASH_ITEMS
+            ),
+            &[&retention_days()],
+        )
+        .await?
+        .iter()
+        .map(|row| (row.get("item_type"), row.get("item_id")))
+        .collect();
+
+    let mut purged = 0;
+    for (item_type, item_id) in expired {
+        let transaction = client.transaction().await?;
+        match purge_item(&transaction, &item_type, item_id).await {
+            Ok(()) => {
+                transaction.commit().await?;
+                purged += 1;
+            }
+            Err(e) => error!(
+                "Failed to purge {} {} from the trash: {}",
+                item_type, item_id, e
+            ),
+        }
+    }
+    if purged > 0 {
+        info!("Purged {} expired items from the trash", purged);
+    }
+    Ok(purged)
+}
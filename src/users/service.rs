@@ -160,7 +160,7 @@ pub async fn get_public_profile(
     // Get definition count
     let definition_count: i64 = transaction
         .query_one(
-            "SELECT COUNT(*) FROM definitions WHERE userid = $1",
+            "SELECT COUNT(*) FROM definitions WHERE userid = $1 AND deleted_at IS NULL",
             &[&user_id],
         )
         .await?
@@ -169,7 +169,7 @@ pub async fn get_public_profile(
     // Get comment count
     let comment_count: i64 = transaction
         .query_one(
-            "SELECT COUNT(*) FROM comments WHERE userid = $1",
+            "SELECT COUNT(*) FROM comments WHERE userid = $1 AND deleted_at IS NULL",
             &[&user_id],
         )
         .await?
@@ -223,7 +223,7 @@ pub async fn get_user_votes(
              JOIN definitions d ON dv.definitionid = d.definitionid
              JOIN valsi v ON d.valsiid = v.valsiid
              JOIN languages l ON d.langid = l.langid
-             WHERE dv.userid = $1
+             WHERE dv.userid = $1 AND d.deleted_at IS NULL
              ORDER BY dv.time DESC
              LIMIT $2 OFFSET $3",
             &[&user_id, &per_page, &offset],
@@ -232,7 +232,9 @@ pub async fn get_user_votes(
 
     let total: i64 = transaction
         .query_one(
-            "SELECT COUNT(*) FROM definitionvotes WHERE userid = $1",
+            "SELECT COUNT(*) FROM definitionvotes dv
+             JOIN definitions d ON dv.definitionid = d.definitionid
+             WHERE dv.userid = $1 AND d.deleted_at IS NULL",
             &[&user_id],
         )
         .await?
@@ -293,7 +295,8 @@ pub async fn get_user_definitions(
             FROM definition_versions v
             WHERE v.user_id = $1
               AND EXISTS (
-                  SELECT 1 FROM definitions d WHERE d.definitionid = v.definition_id
+                  SELECT 1 FROM definitions d
+                  WHERE d.definitionid = v.definition_id AND d.deleted_at IS NULL
               )
 
             UNION ALL
@@ -305,6 +308,7 @@ pub async fn get_user_definitions(
                 d.definitionid AS definitionid
             FROM definitions d
             WHERE d.userid = $1
+              AND d.deleted_at IS NULL
               AND NOT EXISTS (
                   SELECT 1
                   FROM definition_versions dv
@@ -367,7 +371,8 @@ pub async fn get_user_definitions(
                 FROM definition_versions v
                 WHERE v.user_id = $1
                   AND EXISTS (
-                      SELECT 1 FROM definitions d WHERE d.definitionid = v.definition_id
+                      SELECT 1 FROM definitions d
+                      WHERE d.definitionid = v.definition_id AND d.deleted_at IS NULL
                   )
             )
             +
@@ -375,6 +380,7 @@ pub async fn get_user_definitions(
                 SELECT COUNT(*)::bigint
                 FROM definitions d
                 WHERE d.userid = $1
+                  AND d.deleted_at IS NULL
                   AND NOT EXISTS (
                       SELECT 1
                       FROM definition_versions dv
@@ -478,7 +484,7 @@ pub async fn get_user_comments(
              JOIN users u ON c.userid = u.userid
              LEFT JOIN comment_counters cc ON c.commentid = cc.comment_id
              LEFT JOIN comment_bookmarks cb ON c.commentid = cb.comment_id AND cb.user_id = $1
-             WHERE c.userid = $1
+             WHERE c.userid = $1 AND c.deleted_at IS NULL
              ORDER BY c.time DESC
              LIMIT $2 OFFSET $3",
             &[&user_id, &per_page, &offset],
@@ -487,7 +493,7 @@ pub async fn get_user_comments(
 
     let total: i64 = transaction
         .query_one(
-            "SELECT COUNT(*) FROM comments WHERE userid = $1",
+            "SELECT COUNT(*) FROM comments WHERE userid = $1 AND deleted_at IS NULL",
             &[&user_id],
         )
         .await?
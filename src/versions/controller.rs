@@ -1,8 +1,10 @@
-use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web::{get, post, web, HttpResponse, Responder, ResponseError};
 // use actix_web_grants::protect;
 use deadpool_postgres::Pool;
 
+use crate::audit::AuditContext;
 use crate::auth::permissions::PermissionCache;
+use crate::auth::two_factor;
 
 use super::{
     dto::{GetDiffQuery, GetVersionsQuery, VersionHistoryResponse},
@@ -93,6 +95,7 @@ pub async fn get_version(pool: web::Data<Pool>, version_id: web::Path<i32>) -> i
     ),
     responses(
         (status = 200, description = "Definition reverted successfully", body = Version),
+        (status = 400, description = "The definition is in the trash"),
         (status = 404, description = "Version not found"),
         (status = 403, description = "User is not the author and lacks revert permissions"),
         (status = 500, description = "Internal server error")
@@ -110,14 +113,18 @@ pub async fn revert_to_version(
     pool: web::Data<Pool>,
     version_id: web::Path<i32>,
     user: crate::auth::Claims,
+    audit: AuditContext,
     perm_cache: web::Data<PermissionCache>,
 ) -> impl Responder {
+    if let Err(e) = two_factor::require_step_up(&pool, &perm_cache, &user).await {
+        return e.error_response();
+    }
     match service::revert_to_version(
         &pool,
         version_id.into_inner(),
-        user.sub,
-        &user.role.to_string(),
+        &user,
         perm_cache.get_ref(),
+        &audit,
     )
     .await
     {
@@ -126,6 +133,9 @@ pub async fn revert_to_version(
             Some(db_error) if db_error.code().is_some_and(|c| c.code() == "P0002") => {
                 HttpResponse::NotFound().finish()
             }
+            _ if e.to_string().contains("in the trash") => {
+                HttpResponse::BadRequest().body(e.to_string())
+            }
             _ => HttpResponse::InternalServerError().body(format!("Failed to revert: {}", e)),
         },
     }
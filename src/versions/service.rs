@@ -2,7 +2,12 @@ use super::{
     models::{Change, ChangeType, Version, VersionContent, VersionDiff},
     VersionHistoryResponse,
 };
-use crate::{auth::permissions::PermissionCache, jbovlaste::KeywordMapping};
+use crate::{
+    audit::{self, AuditAction, AuditContext},
+    auth::{permissions::PermissionCache, Claims},
+    jbovlaste::KeywordMapping,
+    webhooks::{self, WebhookEvent},
+};
 use deadpool_postgres::Pool;
 
 const VERSION_SELECT_SQL: &str = "SELECT v.*, u.username,
@@ -232,28 +237,51 @@ pub async fn create_version(
 pub async fn revert_to_version(
     pool: &Pool,
     version_id: i32,
-    user_id: i32,
-    user_role: &str,
+    user: &Claims,
     perm_cache: &PermissionCache,
+    ctx: &AuditContext,
 ) -> Result<Version, Box<dyn std::error::Error>> {
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
-    let old_version: Version = get_version_with_transaction(&transaction, version_id).await?;
+    let new_version =
+        revert_to_version_with_transaction(&transaction, version_id, user, perm_cache, ctx).await?;
+
+    transaction.commit().await?;
+
+    Ok(new_version)
+}
+
+pub async fn revert_to_version_with_transaction(
+    transaction: &deadpool_postgres::Transaction<'_>,
+    version_id: i32,
+    user: &Claims,
+    perm_cache: &PermissionCache,
+    ctx: &AuditContext,
+) -> Result<Version, Box<dyn std::error::Error>> {
+    let user_id = user.sub;
+    let old_version: Version = get_version_with_transaction(transaction, version_id).await?;
+
+    // Lock the definition so it cannot be trashed while the revert runs
+    let definition = transaction
+        .query_one(
+            "SELECT userid AS user_id, deleted_at IS NOT NULL AS in_trash
+             FROM definitions WHERE definitionid = $1
+             FOR UPDATE",
+            &[&old_version.definition_id],
+        )
+        .await?;
+    if definition.get::<_, bool>("in_trash") {
+        return Err("Definition is in the trash; restore it first".into());
+    }
 
     // Check permissions
     let has_permission = perm_cache
-        .has_permission(user_role.to_string(), "revert_entry_version")
+        .claims_have_permission(user, "revert_entry_version")
         .await;
     if !has_permission {
         // Check if user is the original author of the definition
-        let definition_author: i32 = transaction
-            .query_one(
-                "SELECT user_id FROM definitions WHERE definitionid = $1",
-                &[&old_version.definition_id],
-            )
-            .await?
-            .get("user_id");
+        let definition_author: i32 = definition.get("user_id");
 
         if definition_author != user_id {
             return Err(Box::new(actix_web::error::ErrorForbidden(
@@ -264,7 +292,7 @@ pub async fn revert_to_version(
 
     // Create a new version with the old content
     let new_version = create_version(
-        &transaction,
+        transaction,
         old_version.definition_id,
         user_id,
         &old_version.content,
@@ -272,6 +300,56 @@ pub async fn revert_to_version(
     )
     .await?;
 
+    let before =
+        audit::service::definition_snapshot(transaction, old_version.definition_id).await?;
+    restore_version_content(transaction, &old_version).await?;
+    let after = audit::service::definition_snapshot(transaction, old_version.definition_id).await?;
+    audit::service::record(
+        transaction,
+        ctx,
+        AuditAction::RevertVersion,
+        &old_version.definition_id.to_string(),
+        before,
+        after.map(|mut after| {
+            if let Some(fields) = after.as_object_mut() {
+                fields.insert("reverted_to_version_id".to_string(), version_id.into());
+                fields.insert("new_version_id".to_string(), new_version.version_id.into());
+            }
+            after
+        }),
+    )
+    .await?;
+
+    let valsi = transaction
+        .query_one(
+            "SELECT v.valsiid, v.word FROM definitions d
+             JOIN valsi v ON v.valsiid = d.valsiid
+             WHERE d.definitionid = $1",
+            &[&old_version.definition_id],
+        )
+        .await?;
+    webhooks::service::queue_event(
+        transaction,
+        WebhookEvent::VersionReverted,
+        Some(valsi.get("valsiid")),
+        user_id,
+        serde_json::json!({
+            "definition_id": old_version.definition_id,
+            "word": valsi.get::<_, String>("word"),
+            "reverted_to_version_id": version_id,
+            "new_version_id": new_version.version_id,
+        }),
+    )
+    .await?;
+
+    Ok(new_version)
+}
+
+/// Writes a version's content (fields, target language and keywords) back onto its definition.
+pub async fn restore_version_content(
+    transaction: &tokio_postgres::Transaction<'_>,
+    old_version: &Version,
+) -> Result<(), Box<dyn std::error::Error>> {
     let current_def = transaction
         .query_one(
             "SELECT valsiid, langid, definitionnum FROM definitions WHERE definitionid = $1",
@@ -285,7 +363,7 @@ pub async fn revert_to_version(
     let definitionnum = if restored_langid == current_langid {
         current_definitionnum
     } else {
-        next_definitionnum_for_language(&transaction, valsi_id, restored_langid).await?
+        next_definitionnum_for_language(transaction, valsi_id, restored_langid).await?
     };
 
     // Update the definition with the old content, including target language.
@@ -309,8 +387,7 @@ pub async fn revert_to_version(
         .await?;
 
     if restored_langid != current_langid {
-        retarget_definition_votes(&transaction, old_version.definition_id, restored_langid)
-            .await?;
+        retarget_definition_votes(transaction, old_version.definition_id, restored_langid).await?;
     }
 
     // Update keywords if they exist
@@ -359,9 +436,7 @@ pub async fn revert_to_version(
         }
     }
 
-    transaction.commit().await?;
-
-    Ok(new_version)
+    Ok(())
 }
 
 pub async fn get_diff_with_transaction(
@@ -78,6 +78,8 @@ pub enum WaveThreadSummary {
         comment_num: i32,
     },
     Mail {
+        /// Root message id; pass as `thread_id` to `/mail/thread`.
+        thread_id: i32,
         cleaned_subject: String,
         subject: Option<String>,
         from_address: Option<String>,
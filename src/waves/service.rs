@@ -442,6 +442,7 @@ pub async fn list_wave_threads(
             .map(|t: chrono::DateTime<chrono::Utc>| t.timestamp())
             .unwrap_or(0);
         items.push(WaveThreadSummary::Mail {
+            thread_id: m.thread_id,
             cleaned_subject: m.cleaned_subject,
             subject: m.subject,
             from_address: m.from_address,
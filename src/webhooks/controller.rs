@@ -0,0 +1,194 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use deadpool_postgres::Pool;
+
+use super::{dto::*, service};
+use crate::{auth::permissions::PermissionCache, auth::Claims, AppError};
+
+async fn can_manage_webhooks(claims: &Claims, perm_cache: &PermissionCache) -> bool {
+    perm_cache
+        .claims_have_permission(claims, "manage_webhooks")
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, description = "Webhooks of the current user", body = Vec<WebhookResponse>),
+        (status = 401, description = "Unauthorized")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "List webhooks",
+    description = "Lists the webhook endpoints registered by the authenticated user. Secrets are not included."
+)]
+#[get("")]
+pub async fn list_webhooks(
+    pool: web::Data<Pool>,
+    claims: Claims,
+) -> Result<HttpResponse, AppError> {
+    let webhooks = service::list_webhooks(&pool, claims.sub).await?;
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 201, description = "Webhook created; the secret is only returned here", body = WebhookSecretResponse),
+        (status = 400, description = "Invalid URL or no events"),
+        (status = 403, description = "all_valsi requested without the manage_webhooks permission")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Register a webhook",
+    description = "Registers an endpoint that receives the chosen events (definition.created, definition.updated, \
+                  definition.deleted, vote.changed, comment.added, version.reverted) as signed JSON POST requests. \
+                  Events are sent for the valsi the user is subscribed to, or for every valsi with `all_valsi`. \
+                  Requests carry `X-Lensisku-Event`, `X-Lensisku-Delivery` and `X-Lensisku-Signature: t=<unix time>,v1=<hex>`, \
+                  where v1 is the HMAC-SHA256 of `<unix time>.<body>` keyed with the webhook secret."
+)]
+#[post("")]
+pub async fn create_webhook(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    request: web::Json<CreateWebhookRequest>,
+) -> Result<HttpResponse, AppError> {
+    let can_manage = can_manage_webhooks(&claims, &perm_cache).await;
+    let created = service::create_webhook(&pool, claims.sub, can_manage, &request).await?;
+    Ok(HttpResponse::Created().json(created))
+}
+
+#[utoipa::path(
+    put,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook ID")),
+    request_body = UpdateWebhookRequest,
+    responses(
+        (status = 200, description = "Webhook updated", body = WebhookResponse),
+        (status = 400, description = "Invalid URL or no events"),
+        (status = 403, description = "all_valsi requested without the manage_webhooks permission"),
+        (status = 404, description = "Webhook not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Update a webhook",
+    description = "Changes the URL, events, scope or description of a webhook, or pauses it with `active: false`. \
+                  Deliveries queued while a webhook is paused are sent once it is active again."
+)]
+#[put("/{id}")]
+pub async fn update_webhook(
+    pool: web::Data<Pool>,
+    perm_cache: web::Data<PermissionCache>,
+    claims: Claims,
+    id: web::Path<i32>,
+    request: web::Json<UpdateWebhookRequest>,
+) -> Result<HttpResponse, AppError> {
+    let can_manage = can_manage_webhooks(&claims, &perm_cache).await;
+    let webhook =
+        service::update_webhook(&pool, claims.sub, can_manage, id.into_inner(), &request).await?;
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook ID")),
+    responses(
+        (status = 204, description = "Webhook and its delivery log deleted"),
+        (status = 404, description = "Webhook not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Delete a webhook"
+)]
+#[delete("/{id}")]
+pub async fn delete_webhook(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    service::delete_webhook(&pool, claims.sub, id.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/{id}/rotate-secret",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "New secret", body = WebhookSecretResponse),
+        (status = 404, description = "Webhook not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Rotate a webhook secret",
+    description = "Replaces the signing secret. Deliveries sent from now on are signed with the new secret."
+)]
+#[post("/{id}/rotate-secret")]
+pub async fn rotate_secret(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let rotated = service::rotate_secret(&pool, claims.sub, id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(rotated))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(
+        ("id" = i32, Path, description = "Webhook ID"),
+        DeliveryLogQuery
+    ),
+    responses(
+        (status = 200, description = "Delivery log, newest first", body = DeliveryLogResponse),
+        (status = 400, description = "Unknown status filter"),
+        (status = 404, description = "Webhook not found")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Webhook delivery log",
+    description = "Lists the deliveries of a webhook with their payload, status (pending, running, delivered, failed), \
+                  number of attempts, last response status and error, and when the next retry is due."
+)]
+#[get("/{id}/deliveries")]
+pub async fn list_deliveries(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    id: web::Path<i32>,
+    query: web::Query<DeliveryLogQuery>,
+) -> Result<HttpResponse, AppError> {
+    let log = service::list_deliveries(&pool, claims.sub, id.into_inner(), &query).await?;
+    Ok(HttpResponse::Ok().json(log))
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/{id}/deliveries/{delivery_id}/redeliver",
+    tag = "webhooks",
+    params(
+        ("id" = i32, Path, description = "Webhook ID"),
+        ("delivery_id" = i64, Path, description = "Delivery ID")
+    ),
+    responses(
+        (status = 200, description = "Delivery queued again", body = WebhookDeliveryResponse),
+        (status = 404, description = "Webhook or delivery not found, or the delivery is being sent")
+    ),
+    security(("bearer_auth" = [])),
+    summary = "Redeliver a webhook event",
+    description = "Queues a delivery again with a fresh set of retries, e.g. after it failed while the endpoint was down."
+)]
+#[post("/{id}/deliveries/{delivery_id}/redeliver")]
+pub async fn redeliver(
+    pool: web::Data<Pool>,
+    claims: Claims,
+    path: web::Path<(i32, i64)>,
+) -> Result<HttpResponse, AppError> {
+    let (id, delivery_id) = path.into_inner();
+    let delivery = service::redeliver(&pool, claims.sub, id, delivery_id).await?;
+    Ok(HttpResponse::Ok().json(delivery))
+}
@@ -0,0 +1,326 @@
+//! Sends queued webhook deliveries.
+//!
+//! [`super::service::queue_event`] adds a `pending` row to `webhook_deliveries` for every webhook
+//! an event concerns; the worker started by [`spawn_webhook_deliveries`] posts them as JSON.
+//! Each request carries `X-Lensisku-Signature: t=<unix time>,v1=<hex HMAC-SHA256>` computed
+//! with the webhook secret over `<unix time>.<body>`. Failed deliveries are retried with
+//! exponential backoff and given up as `failed` after [`MAX_ATTEMPTS`]. A claimed delivery is
+//! leased to its worker for [`LEASE`]; once that runs out it is sent again, by any replica.
+//! Delivered and failed rows are kept for `WEBHOOK_DELIVERY_RETENTION_DAYS` and then removed by
+//! [`purge_finished_deliveries`].
+
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use futures::future::join_all;
+use hmac::{Hmac, KeyInit, Mac};
+use log::{error, info, warn};
+use sha2::Sha256;
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+
+use super::target::{self, PublicResolver};
+
+const MAX_ATTEMPTS: i32 = 8;
+const BATCH_SIZE: i64 = 20;
+const BASE_RETRY_DELAY_SECS: i64 = 30;
+const MAX_RETRY_DELAY_SECS: i64 = 6 * 60 * 60;
+/// How often an idle worker looks for due deliveries.
+const IDLE_POLL: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a claimed delivery stays reserved for its worker; well above [`REQUEST_TIMEOUT`].
+const LEASE: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_RETENTION_DAYS: i32 = 30;
+
+struct PendingDelivery {
+    id: i64,
+    event_type: String,
+    payload: serde_json::Value,
+    attempts: i32,
+    url: String,
+    secret: String,
+}
+
+/// Delay before the next attempt once `attempts` attempts have failed: 30s, 1m, 2m, ... up to 6h.
+fn retry_delay_secs(attempts: i32) -> i64 {
+    let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+    BASE_RETRY_DELAY_SECS
+        .saturating_mul(1i64 << exponent)
+        .min(MAX_RETRY_DELAY_SECS)
+}
+
+/// Value of the `X-Lensisku-Signature` header for `body` sent at `timestamp`.
+fn signature_header(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(secret.as_bytes())
+        .unwrap_or_else(|_| unreachable!("HMAC accepts keys of any length"));
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("t={},v1={}", timestamp, digest)
+}
+
+/// Running deliveries whose lease ran out belong to a worker that stopped; send them again.
+async fn requeue_interrupted(pool: &Pool) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    Ok(pool
+        .get()
+        .await?
+        .execute(
+            "UPDATE webhook_deliveries
+             SET status = 'pending', next_attempt_at = NOW(), locked_until = NULL
+             WHERE status = 'running' AND (locked_until IS NULL OR locked_until < NOW())",
+            &[],
+        )
+        .await?)
+}
+
+async fn claim_batch(pool: &Pool) -> Result<Vec<PendingDelivery>, Box<dyn Error + Send + Sync>> {
+    let rows = pool
+        .get()
+        .await?
+        .query(
+            "UPDATE webhook_deliveries d
+             SET status = 'running', last_attempt_at = NOW(),
+                 locked_until = NOW() + make_interval(secs => $2)
+             FROM (
+                SELECT d2.id
+                FROM webhook_deliveries d2
+                JOIN webhooks w2 ON w2.id = d2.webhook_id
+                WHERE d2.status = 'pending' AND d2.next_attempt_at <= NOW() AND w2.active
+                ORDER BY d2.next_attempt_at
+                LIMIT $1
+                FOR UPDATE OF d2 SKIP LOCKED
+             ) next, webhooks w
+             WHERE d.id = next.id AND w.id = d.webhook_id
+             RETURNING d.id, d.event_type, d.payload, d.attempts, w.url, w.secret",
+            &[&BATCH_SIZE, &LEASE.as_secs_f64()],
+        )
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| PendingDelivery {
+            id: row.get("id"),
+            event_type: row.get("event_type"),
+            payload: row.get("payload"),
+            attempts: row.get("attempts"),
+            url: row.get("url"),
+            secret: row.get("secret"),
+        })
+        .collect())
+}
+
+/// `error` and its sources, so that a refused address or redirect shows up in `last_error`.
+fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+/// Posts the delivery. Returns the response status, plus an error for anything but 2xx.
+async fn send(http: &reqwest::Client, delivery: &PendingDelivery) -> (Option<i32>, Option<String>) {
+    let body = match serde_json::to_vec(&delivery.payload) {
+        Ok(body) => body,
+        Err(e) => return (None, Some(format!("Failed to serialize payload: {}", e))),
+    };
+    // Names are checked by the client's resolver; IP literals never reach it.
+    match reqwest::Url::parse(&delivery.url) {
+        Ok(url) => {
+            if let Err(e) = target::check_url_host(&url) {
+                return (None, Some(format!("Refused to send: {}", e)));
+            }
+        }
+        Err(e) => return (None, Some(format!("Invalid webhook URL: {}", e))),
+    }
+    let signature = signature_header(&delivery.secret, Utc::now().timestamp(), &body);
+    let response = http
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Lensisku-Event", &delivery.event_type)
+        .header("X-Lensisku-Delivery", delivery.id.to_string())
+        .header("X-Lensisku-Signature", signature)
+        .body(body)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) => {
+            let status = response.status();
+            if status.is_success() {
+                (Some(status.as_u16() as i32), None)
+            } else {
+                // The body is not kept: it is whatever the receiving server chose to return.
+                (
+                    Some(status.as_u16() as i32),
+                    Some(format!("HTTP {}", status)),
+                )
+            }
+        }
+        Err(e) => (None, Some(error_chain(&e))),
+    }
+}
+
+async fn record_attempt(
+    pool: &Pool,
+    delivery: &PendingDelivery,
+    response_status: Option<i32>,
+    failure: Option<String>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let client = pool.get().await?;
+    let attempts = delivery.attempts + 1;
+    match failure {
+        None => {
+            client
+                .execute(
+                    "UPDATE webhook_deliveries
+                     SET status = 'delivered', attempts = $2, response_status = $3,
+                         last_error = NULL, delivered_at = NOW(), locked_until = NULL
+                     WHERE id = $1",
+                    &[&delivery.id, &attempts, &response_status],
+                )
+                .await?;
+        }
+        Some(error) => {
+            let status = if attempts >= MAX_ATTEMPTS {
+                warn!(
+                    "Giving up webhook delivery {} after {} attempts: {}",
+                    delivery.id, attempts, error
+                );
+                "failed"
+            } else {
+                "pending"
+            };
+            client
+                .execute(
+                    "UPDATE webhook_deliveries
+                     SET status = $2, attempts = $3, response_status = $4, last_error = $5,
+                         next_attempt_at = NOW() + make_interval(secs => $6), locked_until = NULL
+                     WHERE id = $1",
+                    &[
+                        &delivery.id,
+                        &status,
+                        &attempts,
+                        &response_status,
+                        &error,
+                        &(retry_delay_secs(attempts) as f64),
+                    ],
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn process_batch(
+    pool: &Pool,
+    http: &reqwest::Client,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let batch = claim_batch(pool).await?;
+    let results = join_all(batch.iter().map(|delivery| send(http, delivery))).await;
+    for (delivery, (response_status, failure)) in batch.iter().zip(results) {
+        record_attempt(pool, delivery, response_status, failure).await?;
+    }
+    Ok(batch.len())
+}
+
+async fn run_webhook_deliveries(pool: Pool) {
+    let http = match reqwest::Client::builder()
+        .user_agent("lensisku-webhooks")
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(target::redirect_policy())
+        .dns_resolver(Arc::new(PublicResolver))
+        .no_proxy()
+        .build()
+    {
+        Ok(http) => http,
+        Err(e) => {
+            error!("Failed to build webhook HTTP client: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        match requeue_interrupted(&pool).await {
+            Ok(0) => {}
+            Ok(n) => info!("Requeued {} interrupted webhook deliveries", n),
+            Err(e) => error!("Failed to requeue interrupted webhook deliveries: {}", e),
+        }
+        match process_batch(&pool, &http).await {
+            Ok(0) => sleep(IDLE_POLL).await,
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to send webhook deliveries: {}", e);
+                sleep(IDLE_POLL).await;
+            }
+        }
+    }
+}
+
+pub fn spawn_webhook_deliveries(pool: Pool) {
+    tokio::spawn(run_webhook_deliveries(pool));
+}
+
+/// Days a delivered or failed delivery stays in the log, from `WEBHOOK_DELIVERY_RETENTION_DAYS`.
+fn retention_days() -> i32 {
+    env::var("WEBHOOK_DELIVERY_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// Removes delivered and failed deliveries older than the retention period. Pending and running
+/// ones are left to the worker.
+pub async fn purge_finished_deliveries(pool: &Pool) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let purged = pool
+        .get()
+        .await?
+        .execute(
+            "DELETE FROM webhook_deliveries
+             WHERE status IN ('delivered', 'failed')
+               AND created_at <= NOW() - make_interval(days => $1)",
+            &[&retention_days()],
+        )
+        .await?;
+    if purged > 0 {
+        info!("Purged {} old webhook deliveries", purged);
+    }
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        assert_eq!(retry_delay_secs(1), 30);
+        assert_eq!(retry_delay_secs(2), 60);
+        assert_eq!(retry_delay_secs(5), 480);
+        assert_eq!(retry_delay_secs(MAX_ATTEMPTS), 3840);
+        assert_eq!(retry_delay_secs(40), MAX_RETRY_DELAY_SECS);
+    }
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let header = signature_header("secret", 1700000000, br#"{"event":"vote.changed"}"#);
+        assert_eq!(
+            header,
+            "t=1700000000,v1=a71edf440f3b7bf65ece20cd8f43b3c8a9fb062f762c745960d72a090af9faad"
+        );
+        assert_ne!(
+            header,
+            signature_header("secret", 1700000001, br#"{"event":"vote.changed"}"#)
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Events a webhook can subscribe to. The serialized name is sent in the payload and in the
+/// `X-Lensisku-Event` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum WebhookEvent {
+    #[serde(rename = "definition.created")]
+    DefinitionCreated,
+    #[serde(rename = "definition.updated")]
+    DefinitionUpdated,
+    #[serde(rename = "definition.deleted")]
+    DefinitionDeleted,
+    #[serde(rename = "vote.changed")]
+    VoteChanged,
+    #[serde(rename = "comment.added")]
+    CommentAdded,
+    #[serde(rename = "version.reverted")]
+    VersionReverted,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::DefinitionCreated => "definition.created",
+            WebhookEvent::DefinitionUpdated => "definition.updated",
+            WebhookEvent::DefinitionDeleted => "definition.deleted",
+            WebhookEvent::VoteChanged => "vote.changed",
+            WebhookEvent::CommentAdded => "comment.added",
+            WebhookEvent::VersionReverted => "version.reverted",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookResponse {
+    pub id: i32,
+    pub url: String,
+    pub events: Vec<String>,
+    pub all_valsi: bool,
+    pub active: bool,
+    pub description: Option<String>,
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = String, format = DateTime)]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Returned when a webhook is created or its secret rotated; the secret is not shown again.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookSecretResponse {
+    pub webhook: WebhookResponse,
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    /// Receive events for every valsi instead of the subscribed ones. Needs `manage_webhooks`.
+    #[serde(default)]
+    pub all_valsi: bool,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateWebhookRequest {
+    pub url: Option<String>,
+    pub events: Option<Vec<WebhookEvent>>,
+    pub all_valsi: Option<bool>,
+    pub active: Option<bool>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DeliveryLogQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    /// pending, running, delivered or failed
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookDeliveryResponse {
+    pub id: i64,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    #[schema(value_type = String, format = DateTime)]
+    pub next_attempt_at: DateTime<Utc>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeliveryLogResponse {
+    pub deliveries: Vec<WebhookDeliveryResponse>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+}
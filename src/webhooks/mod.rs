@@ -0,0 +1,25 @@
+pub mod controller;
+pub mod delivery;
+pub mod dto;
+pub mod service;
+pub mod target;
+
+use actix_web::web;
+use actix_web_httpauth::middleware::HttpAuthentication;
+
+pub use delivery::{purge_finished_deliveries, spawn_webhook_deliveries};
+pub use dto::WebhookEvent;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/webhooks")
+            .wrap(HttpAuthentication::bearer(crate::auth::validator))
+            .service(controller::list_webhooks)
+            .service(controller::create_webhook)
+            .service(controller::update_webhook)
+            .service(controller::delete_webhook)
+            .service(controller::rotate_secret)
+            .service(controller::list_deliveries)
+            .service(controller::redeliver),
+    );
+}
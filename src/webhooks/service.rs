@@ -0,0 +1,363 @@
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use rand::distr::Alphanumeric;
+use rand::{rng, RngExt};
+use serde_json::json;
+use tokio_postgres::{Row, Transaction};
+
+use super::dto::{
+    CreateWebhookRequest, DeliveryLogQuery, DeliveryLogResponse, UpdateWebhookRequest,
+    WebhookDeliveryResponse, WebhookEvent, WebhookResponse, WebhookSecretResponse,
+};
+use super::target;
+use crate::error::{AppError, AppResult};
+
+const WEBHOOK_COLUMNS: &str =
+    "id, url, events, all_valsi, active, description, created_at, updated_at";
+const DELIVERY_STATUSES: [&str; 4] = ["pending", "running", "delivered", "failed"];
+
+/// Queues `event` for every active webhook that listens to it and either covers all valsi or
+/// belongs to a user subscribed to `valsi_id`. Webhooks of disabled or blocked owners get
+/// nothing, and `all_valsi` webhooks only while their owner still has `manage_webhooks`. Call it
+/// in the transaction that makes the change, next to `notify_valsi_subscribers`, so that
+/// deliveries exist only for committed changes.
+pub async fn queue_event(
+    transaction: &Transaction<'_>,
+    event: WebhookEvent,
+    valsi_id: Option<i32>,
+    actor_id: i32,
+    data: serde_json::Value,
+) -> Result<u64, tokio_postgres::Error> {
+    let payload = json!({
+        "event": event.as_str(),
+        "occurred_at": Utc::now().to_rfc3339(),
+        "actor_id": actor_id,
+        "valsi_id": valsi_id,
+        "data": data,
+    });
+    transaction
+        .execute(
+            "INSERT INTO webhook_deliveries (webhook_id, event_type, payload)
+             SELECT w.id, $1, $2
+             FROM webhooks w
+             JOIN users u ON u.userid = w.user_id
+             WHERE w.active
+               AND $1 = ANY(w.events)
+               AND NOT u.disabled
+               AND LOWER(u.role::text) <> 'blocked'
+               AND CASE
+                   WHEN w.all_valsi THEN EXISTS (
+                       SELECT 1 FROM role_permissions rp
+                       JOIN permissions p ON p.id = rp.permission_id
+                       WHERE LOWER(rp.role::text) = LOWER(u.role::text)
+                         AND p.name = 'manage_webhooks'
+                   )
+                   ELSE EXISTS (
+                       SELECT 1 FROM valsi_subscriptions s
+                       WHERE s.valsi_id = $3 AND s.user_id = w.user_id AND NOT s.unsubscribed
+                   )
+               END",
+            &[&event.as_str(), &payload, &valsi_id],
+        )
+        .await
+}
+
+fn webhook_from_row(row: &Row) -> WebhookResponse {
+    WebhookResponse {
+        id: row.get("id"),
+        url: row.get("url"),
+        events: row.get("events"),
+        all_valsi: row.get("all_valsi"),
+        active: row.get("active"),
+        description: row.get("description"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+fn delivery_from_row(row: &Row) -> WebhookDeliveryResponse {
+    WebhookDeliveryResponse {
+        id: row.get("id"),
+        event_type: row.get("event_type"),
+        payload: row.get("payload"),
+        status: row.get("status"),
+        attempts: row.get("attempts"),
+        next_attempt_at: row.get("next_attempt_at"),
+        last_attempt_at: row.get("last_attempt_at"),
+        response_status: row.get("response_status"),
+        last_error: row.get("last_error"),
+        created_at: row.get("created_at"),
+        delivered_at: row.get("delivered_at"),
+    }
+}
+
+fn generate_secret() -> String {
+    rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect()
+}
+
+/// Parses `url` and rejects it unless it points at the public internet; see [`super::target`].
+async fn validate_url(url: &str) -> AppResult<String> {
+    let parsed = reqwest::Url::parse(url.trim())
+        .map_err(|e| AppError::Validation(format!("Invalid webhook URL: {}", e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(AppError::Validation(
+            "Webhook URL must be an http or https URL".to_string(),
+        ));
+    }
+    target::check_url(&parsed)
+        .await
+        .map_err(|e| AppError::Validation(format!("Webhook URL is not allowed: {}", e)))?;
+    Ok(parsed.to_string())
+}
+
+fn event_names(events: &[WebhookEvent]) -> AppResult<Vec<String>> {
+    let mut names: Vec<String> = Vec::new();
+    for event in events {
+        let name = event.as_str().to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if names.is_empty() {
+        return Err(AppError::Validation(
+            "A webhook needs at least one event".to_string(),
+        ));
+    }
+    Ok(names)
+}
+
+fn check_all_valsi(all_valsi: bool, can_manage_webhooks: bool) -> AppResult<()> {
+    if all_valsi && !can_manage_webhooks {
+        return Err(AppError::Auth(
+            "Webhooks for all valsi need the manage_webhooks permission".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn ensure_owned(
+    client: &deadpool_postgres::Client,
+    user_id: i32,
+    webhook_id: i32,
+) -> AppResult<()> {
+    client
+        .query_opt(
+            "SELECT 1 FROM webhooks WHERE id = $1 AND user_id = $2",
+            &[&webhook_id, &user_id],
+        )
+        .await?
+        .map(|_| ())
+        .ok_or_else(|| AppError::NotFound("Webhook not found".to_string()))
+}
+
+pub async fn list_webhooks(pool: &Pool, user_id: i32) -> AppResult<Vec<WebhookResponse>> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {} FROM webhooks WHERE user_id = $1 ORDER BY created_at",
+                WEBHOOK_COLUMNS
+            ),
+            &[&user_id],
+        )
+        .await?;
+    Ok(rows.iter().map(webhook_from_row).collect())
+}
+
+pub async fn create_webhook(
+    pool: &Pool,
+    user_id: i32,
+    can_manage_webhooks: bool,
+    request: &CreateWebhookRequest,
+) -> AppResult<WebhookSecretResponse> {
+    let url = validate_url(&request.url).await?;
+    let events = event_names(&request.events)?;
+    check_all_valsi(request.all_valsi, can_manage_webhooks)?;
+    let secret = generate_secret();
+
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            &format!(
+                "INSERT INTO webhooks (user_id, url, secret, events, all_valsi, description)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 RETURNING {}",
+                WEBHOOK_COLUMNS
+            ),
+            &[
+                &user_id,
+                &url,
+                &secret,
+                &events,
+                &request.all_valsi,
+                &request.description,
+            ],
+        )
+        .await?;
+
+    Ok(WebhookSecretResponse {
+        webhook: webhook_from_row(&row),
+        secret,
+    })
+}
+
+pub async fn update_webhook(
+    pool: &Pool,
+    user_id: i32,
+    can_manage_webhooks: bool,
+    webhook_id: i32,
+    request: &UpdateWebhookRequest,
+) -> AppResult<WebhookResponse> {
+    let url = match request.url.as_deref() {
+        Some(url) => Some(validate_url(url).await?),
+        None => None,
+    };
+    let events = request.events.as_deref().map(event_names).transpose()?;
+    check_all_valsi(request.all_valsi.unwrap_or(false), can_manage_webhooks)?;
+
+    let client = pool.get().await?;
+    client
+        .query_opt(
+            &format!(
+                "UPDATE webhooks SET
+                    url = COALESCE($3, url),
+                    events = COALESCE($4, events),
+                    all_valsi = COALESCE($5, all_valsi),
+                    active = COALESCE($6, active),
+                    description = COALESCE($7, description),
+                    updated_at = NOW()
+                 WHERE id = $1 AND user_id = $2
+                 RETURNING {}",
+                WEBHOOK_COLUMNS
+            ),
+            &[
+                &webhook_id,
+                &user_id,
+                &url,
+                &events,
+                &request.all_valsi,
+                &request.active,
+                &request.description,
+            ],
+        )
+        .await?
+        .map(|row| webhook_from_row(&row))
+        .ok_or_else(|| AppError::NotFound("Webhook not found".to_string()))
+}
+
+pub async fn delete_webhook(pool: &Pool, user_id: i32, webhook_id: i32) -> AppResult<()> {
+    let client = pool.get().await?;
+    let deleted = client
+        .execute(
+            "DELETE FROM webhooks WHERE id = $1 AND user_id = $2",
+            &[&webhook_id, &user_id],
+        )
+        .await?;
+    if deleted == 0 {
+        return Err(AppError::NotFound("Webhook not found".to_string()));
+    }
+    Ok(())
+}
+
+pub async fn rotate_secret(
+    pool: &Pool,
+    user_id: i32,
+    webhook_id: i32,
+) -> AppResult<WebhookSecretResponse> {
+    let secret = generate_secret();
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            &format!(
+                "UPDATE webhooks SET secret = $3, updated_at = NOW()
+                 WHERE id = $1 AND user_id = $2
+                 RETURNING {}",
+                WEBHOOK_COLUMNS
+            ),
+            &[&webhook_id, &user_id, &secret],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound("Webhook not found".to_string()))?;
+
+    Ok(WebhookSecretResponse {
+        webhook: webhook_from_row(&row),
+        secret,
+    })
+}
+
+pub async fn list_deliveries(
+    pool: &Pool,
+    user_id: i32,
+    webhook_id: i32,
+    query: &DeliveryLogQuery,
+) -> AppResult<DeliveryLogResponse> {
+    if let Some(status) = query.status.as_deref() {
+        if !DELIVERY_STATUSES.contains(&status) {
+            return Err(AppError::Validation(format!(
+                "Unknown delivery status: {}",
+                status
+            )));
+        }
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query.per_page.unwrap_or(20).clamp(1, 100);
+    let offset = (page - 1) * per_page;
+
+    let client = pool.get().await?;
+    ensure_owned(&client, user_id, webhook_id).await?;
+
+    let rows = client
+        .query(
+            "SELECT id, event_type, payload, status, attempts, next_attempt_at, last_attempt_at,
+                    response_status, last_error, created_at, delivered_at
+             FROM webhook_deliveries
+             WHERE webhook_id = $1 AND ($2::text IS NULL OR status = $2)
+             ORDER BY created_at DESC, id DESC
+             LIMIT $3 OFFSET $4",
+            &[&webhook_id, &query.status, &per_page, &offset],
+        )
+        .await?;
+    let total: i64 = client
+        .query_one(
+            "SELECT COUNT(*) FROM webhook_deliveries
+             WHERE webhook_id = $1 AND ($2::text IS NULL OR status = $2)",
+            &[&webhook_id, &query.status],
+        )
+        .await?
+        .get(0);
+
+    Ok(DeliveryLogResponse {
+        deliveries: rows.iter().map(delivery_from_row).collect(),
+        total,
+        page,
+        per_page,
+    })
+}
+
+/// Puts a delivery back in the queue with a fresh set of attempts.
+pub async fn redeliver(
+    pool: &Pool,
+    user_id: i32,
+    webhook_id: i32,
+    delivery_id: i64,
+) -> AppResult<WebhookDeliveryResponse> {
+    let client = pool.get().await?;
+    ensure_owned(&client, user_id, webhook_id).await?;
+
+    client
+        .query_opt(
+            "UPDATE webhook_deliveries
+             SET status = 'pending', attempts = 0, next_attempt_at = NOW()
+             WHERE id = $1 AND webhook_id = $2 AND status <> 'running'
+             RETURNING id, event_type, payload, status, attempts, next_attempt_at,
+                       last_attempt_at, response_status, last_error, created_at, delivered_at",
+            &[&delivery_id, &webhook_id],
+        )
+        .await?
+        .map(|row| delivery_from_row(&row))
+        .ok_or_else(|| AppError::NotFound("Delivery not found or being sent".to_string()))
+}
@@ -0,0 +1,202 @@
+//! Keeps webhooks pointed at the public internet.
+//!
+//! A webhook URL is chosen by its owner, so without these checks the delivery worker could be
+//! used to reach services that are only exposed inside our network: the loopback interface,
+//! RFC 1918 and unique-local ranges, link-local addresses (which include the cloud metadata
+//! endpoint at `169.254.169.254`) and the carrier-grade NAT range. URLs are checked when a
+//! webhook is saved, and again on every request: names are resolved by [`PublicResolver`], so
+//! the address actually connected to is the one that was checked, and [`redirect_policy`]
+//! applies the same rules to every redirect.
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::lookup_host;
+
+/// Redirects followed before a delivery is given up.
+const MAX_REDIRECTS: usize = 3;
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8 "this network"
+        || a == 0
+        // 100.64.0.0/10 carrier-grade NAT
+        || (a == 100 && (b & 0xc0) == 64)
+        // 192.0.0.0/24 IETF protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // 198.18.0.0/15 benchmarking
+        || (a == 198 && (b & 0xfe) == 18)
+        // 240.0.0.0/4 reserved
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(v4);
+    }
+    let segments = ip.segments();
+    // 64:ff9b::/96 NAT64 reaches the embedded IPv4 address
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_ipv4(Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fc00::/7 unique local
+        || (segments[0] & 0xfe00) == 0xfc00
+        // fe80::/10 link-local, fec0::/10 deprecated site-local
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] & 0xffc0) == 0xfec0
+        // 2001:db8::/32 documentation
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+/// Whether `ip` is an address on the public internet that webhooks may be sent to.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+/// Checks the parts of `url` that need no lookup: the scheme and, when the host is an IP
+/// literal, the address itself. Host names are left to [`PublicResolver`].
+pub fn check_url_host(url: &Url) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("only http and https URLs are allowed".to_string());
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| "the URL has no host".to_string())?;
+    if let Ok(ip) = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        if !is_public_ip(ip) {
+            return Err(format!("{} is not a public address", ip));
+        }
+    }
+    Ok(())
+}
+
+/// Resolves `host` and fails unless every address it resolves to is public.
+async fn resolve_public(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = lookup_host((host, port))
+        .await
+        .map_err(|e| format!("failed to resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} does not resolve to any address", host));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+        return Err(format!(
+            "{} resolves to {}, which is not public",
+            host,
+            addr.ip()
+        ));
+    }
+    Ok(addrs)
+}
+
+/// Checks `url` as a whole, resolving its host if it is a name.
+pub async fn check_url(url: &Url) -> Result<(), String> {
+    check_url_host(url)?;
+    let host = url.host_str().unwrap_or_default();
+    if host.starts_with('[') || host.parse::<IpAddr>().is_ok() {
+        return Ok(());
+    }
+    resolve_public(host, url.port_or_known_default().unwrap_or(80))
+        .await
+        .map(|_| ())
+}
+
+/// DNS resolver for the delivery client that refuses names resolving to non-public addresses.
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = resolve_public(&host, 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn follow_redirect(attempt: Attempt) -> reqwest::redirect::Action {
+    if attempt.previous().len() > MAX_REDIRECTS {
+        return attempt.error("too many redirects");
+    }
+    match check_url_host(attempt.url()) {
+        Ok(()) => attempt.follow(),
+        Err(e) => attempt.error(format!("redirect refused: {}", e)),
+    }
+}
+
+/// Follows up to [`MAX_REDIRECTS`] redirects, each to a URL that passes [`check_url_host`].
+pub fn redirect_policy() -> Policy {
+    Policy::custom(follow_redirect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public_ip(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn internal_addresses_are_rejected() {
+        for ip in [
+            "127.0.0.1",
+            "0.0.0.0",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "::1",
+            "::",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn public_addresses_are_allowed() {
+        for ip in [
+            "93.184.215.14",
+            "1.1.1.1",
+            "2606:4700:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(public(ip), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn ip_literal_hosts_are_checked_without_lookup() {
+        let check = |url: &str| check_url_host(&Url::parse(url).unwrap());
+        assert!(check("http://127.0.0.1:8080/hook").is_err());
+        assert!(check("http://[::1]/hook").is_err());
+        assert!(check("ftp://example.com/hook").is_err());
+        assert!(check("https://1.1.1.1/hook").is_ok());
+        assert!(check("https://example.com/hook").is_ok());
+    }
+}
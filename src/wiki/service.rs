@@ -142,6 +142,7 @@ pub async fn search_wiki(
                  FROM definitions d
                  JOIN valsi v ON d.valsiid = v.valsiid
                  WHERE v.typeid = 16
+                   AND d.deleted_at IS NULL
                    AND COALESCE(d.metadata->>'is_redirect', 'false') <> 'true'",
                 &[],
             )
@@ -154,6 +155,7 @@ pub async fn search_wiki(
              FROM definitions d
              JOIN valsi v ON d.valsiid = v.valsiid
              WHERE v.typeid = 16
+               AND d.deleted_at IS NULL
                AND {NATIVE_WIKI_NOT_REDIRECT}
              ORDER BY d.created_at {order_dir} NULLS LAST
              LIMIT $1 OFFSET $2"
@@ -170,6 +172,7 @@ pub async fn search_wiki(
                  FROM definitions d
                  JOIN valsi v ON d.valsiid = v.valsiid
                  WHERE v.typeid = 16
+                   AND d.deleted_at IS NULL
                    AND COALESCE(d.metadata->>'is_redirect', 'false') <> 'true'
                    AND (v.word ILIKE $1 ESCAPE '\\' OR d.definition ILIKE $1 ESCAPE '\\')",
                 &[&pattern],
@@ -184,6 +187,7 @@ pub async fn search_wiki(
                  FROM definitions d
                  JOIN valsi v ON d.valsiid = v.valsiid
                  WHERE v.typeid = 16
+                   AND d.deleted_at IS NULL
                    AND {NATIVE_WIKI_NOT_REDIRECT}
                    AND (v.word ILIKE $1 ESCAPE '\\' OR d.definition ILIKE $1 ESCAPE '\\')
                  ORDER BY d.created_at {order_dir} NULLS LAST
@@ -198,6 +202,7 @@ pub async fn search_wiki(
                  FROM definitions d
                  JOIN valsi v ON d.valsiid = v.valsiid
                  WHERE v.typeid = 16
+                   AND d.deleted_at IS NULL
                    AND {NATIVE_WIKI_NOT_REDIRECT}
                    AND (v.word ILIKE $1 ESCAPE '\\' OR d.definition ILIKE $1 ESCAPE '\\')
                  ORDER BY (CASE
@@ -311,6 +316,7 @@ pub async fn list_wiki_threads(
              FROM definitions d
              JOIN valsi v ON d.valsiid = v.valsiid
              WHERE v.typeid = 16
+               AND d.deleted_at IS NULL
                AND COALESCE(d.metadata->>'is_redirect', 'false') <> 'true'",
             &[],
         )
@@ -323,6 +329,7 @@ pub async fn list_wiki_threads(
          FROM definitions d
          JOIN valsi v ON d.valsiid = v.valsiid
          WHERE v.typeid = 16
+           AND d.deleted_at IS NULL
            AND {NATIVE_WIKI_NOT_REDIRECT}
          ORDER BY d.created_at {order_dir} NULLS LAST
          LIMIT $1 OFFSET $2"